@@ -1,6 +1,9 @@
+use std::time::Duration;
+
 use num_rational::Ratio;
 
 use buffer::RgbaImage;
+use color::{Blend as BlendPixel, Rgba};
 
 /// Holds the frames of the animated image
 pub struct Frames {
@@ -16,17 +19,156 @@ impl Frames {
             current_frame: 0
         }
     }
+
+    /// Renders the remaining frames onto a canvas of ```width``` x ```height``` pixels
+    ///
+    /// Each returned frame is a full canvas showing the animation at that frame, with the frame
+    /// placed at its offset and combined with the content below it according to its blending.
+    /// The disposal of each frame is applied before the next one is drawn. The canvas starts out
+    /// fully transparent, the returned frames keep their delays.
+    pub fn composite(self, width: u32, height: u32) -> Frames {
+        let mut canvas = RgbaImage::new(width, height);
+        let mut frames = Vec::new();
+
+        for frame in self {
+            let previous = match frame.disposal {
+                Disposal::Previous => Some(canvas.clone()),
+                _ => None,
+            };
+
+            // The part of the frame inside the canvas
+            let right = width.min(frame.left.saturating_add(frame.buffer.width()));
+            let bottom = height.min(frame.top.saturating_add(frame.buffer.height()));
+
+            for y in frame.top..bottom {
+                for x in frame.left..right {
+                    let source = *frame.buffer.get_pixel(x - frame.left, y - frame.top);
+                    let target = canvas.get_pixel_mut(x, y);
+                    match frame.blend {
+                        Blend::Source => *target = source,
+                        Blend::Over => over(target, &source),
+                    }
+                }
+            }
+
+            frames.push(Frame::from_parts(canvas.clone(), 0, 0, frame.delay));
+
+            match frame.disposal {
+                Disposal::None => (),
+                Disposal::Background => for y in frame.top..bottom {
+                    for x in frame.left..right {
+                        canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                    }
+                },
+                Disposal::Previous => canvas = previous.unwrap(),
+            }
+        }
+
+        Frames::new(frames)
+    }
+}
+
+/// Composites ```source``` over ```target```
+fn over(target: &mut Rgba<u8>, source: &Rgba<u8>) {
+    match (target.data[3], source.data[3]) {
+        (_, 0) => (),
+        (0, _) | (_, 255) => *target = *source,
+        _ => target.blend(source),
+    }
+}
+
+/// The time a frame is shown before the next one
+///
+/// Delays are exact fractions of milliseconds, so the hundredths of a second of GIF, the
+/// fractions of a second of APNG and the milliseconds of WebP are all kept without rounding.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct Delay {
+    ratio: Ratio<u32>,
+}
+
+impl Delay {
+    /// Creates a delay of ```numer``` / ```denom``` milliseconds
+    ///
+    /// # Panics
+    ///
+    /// If ```denom``` is zero.
+    pub fn from_numer_denom_ms(numer: u32, denom: u32) -> Delay {
+        Delay {
+            ratio: Ratio::new(numer, denom),
+        }
+    }
+
+    /// Creates a delay of ```duration```, rounded to microseconds
+    ///
+    /// Delays too long to be stored in microseconds are rounded to milliseconds, saturating at
+    /// `u32::MAX` milliseconds.
+    pub fn from_saturating_duration(duration: Duration) -> Delay {
+        let micros = duration.as_secs().saturating_mul(1_000_000)
+            .saturating_add((duration.subsec_nanos() as u64 + 500) / 1000);
+        if micros <= u32::max_value() as u64 {
+            Delay::from_numer_denom_ms(micros as u32, 1000)
+        } else {
+            let millis = micros.saturating_add(500) / 1000;
+            Delay::from_numer_denom_ms(millis.min(u32::max_value() as u64) as u32, 1)
+        }
+    }
+
+    /// Returns the delay as a numerator and denominator of milliseconds, in lowest terms
+    pub fn numer_denom_ms(&self) -> (u32, u32) {
+        (*self.ratio.numer(), *self.ratio.denom())
+    }
+}
+
+impl From<Delay> for Duration {
+    fn from(delay: Delay) -> Duration {
+        let (numer, denom) = delay.numer_denom_ms();
+        let nanos = numer as u64 * 1_000_000 / denom as u64;
+        Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+    }
+}
+
+/// How often an animation is played
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoopCount {
+    /// The animation is repeated forever
+    Infinite,
+    /// The animation is played the given number of times, at least once
+    Finite(u32),
+}
+
+/// What happens to the area of a frame before the next frame is rendered
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Disposal {
+    /// The frame is left as it is
+    None,
+    /// The area of the frame is cleared to fully transparent black
+    Background,
+    /// The area of the frame is restored to its content before the frame was rendered
+    Previous,
+}
+
+/// How a frame is combined with the content already rendered
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Blend {
+    /// The frame replaces the content of its area, including the alpha channel
+    Source,
+    /// The frame is composited over the content of its area based on its alpha channel
+    Over,
 }
 
 /// A single animation frame
 #[derive(Clone)]
 pub struct Frame {
-    /// Delay between the frames in s
-    delay: Ratio<u16>,
+    /// Time the frame is shown before the next one
+    delay: Delay,
     /// x offset
     left: u32,
     /// y offset
     top: u32,
+    /// Treatment of the frame area after the delay
+    disposal: Disposal,
+    /// Treatment of the content below the frame
+    blend: Blend,
     buffer: RgbaImage,
 }
 
@@ -34,25 +176,39 @@ impl Frame {
     /// Contructs a new frame
     pub fn new(buffer: RgbaImage) -> Frame {
         Frame {
-            delay: Ratio::from_integer(0),
+            delay: Delay::from_numer_denom_ms(0, 1),
             left: 0,
             top: 0,
+            disposal: Disposal::None,
+            blend: Blend::Source,
             buffer: buffer
         }
     }
 
     /// Contructs a new frame
-    pub fn from_parts(buffer: RgbaImage, left: u32, top: u32, delay: Ratio<u16>) -> Frame {
+    pub fn from_parts(buffer: RgbaImage, left: u32, top: u32, delay: Delay) -> Frame {
         Frame {
             delay: delay,
             left: left,
             top: top,
+            disposal: Disposal::None,
+            blend: Blend::Source,
             buffer: buffer
         }
     }
 
-    /// Delay of this frame
-    pub fn delay(&self) -> Ratio<u16> {
+    /// Sets how the frame area is treated before the next frame is rendered
+    pub fn set_disposal(&mut self, disposal: Disposal) {
+        self.disposal = disposal
+    }
+
+    /// Sets how the frame is combined with the content below it
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend
+    }
+
+    /// Returns the time the frame is shown before the next one
+    pub fn delay(&self) -> Delay {
         self.delay
     }
 
@@ -75,6 +231,16 @@ impl Frame {
     pub fn top(&self) -> u32 {
         self.top
     }
+
+    /// Returns how the frame area is treated before the next frame is rendered
+    pub fn disposal(&self) -> Disposal {
+        self.disposal
+    }
+
+    /// Returns how the frame is combined with the content below it
+    pub fn blend(&self) -> Blend {
+        self.blend
+    }
 }
 
 impl<'a> Iterator for Frames {
@@ -85,3 +251,25 @@ impl<'a> Iterator for Frames {
         self.frames.get(frame).cloned()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Delay;
+
+    #[test]
+    fn delay() {
+        // Hundredths of a second and fractions of a second are kept exactly
+        assert_eq!(Delay::from_numer_denom_ms(10 * 7, 1), Delay::from_numer_denom_ms(7000, 100));
+        assert_eq!(Delay::from_numer_denom_ms(1000, 3).numer_denom_ms(), (1000, 3));
+        assert_eq!(Duration::from(Delay::from_numer_denom_ms(1000, 3)), Duration::new(0, 333_333_333));
+        assert!(Delay::from_numer_denom_ms(1, 3) < Delay::from_numer_denom_ms(1, 2));
+
+        let delay = Delay::from_saturating_duration(Duration::new(2, 500_000));
+        assert_eq!(delay.numer_denom_ms(), (4001, 2));
+        assert_eq!(Duration::from(delay), Duration::new(2, 500_000));
+        let longest = Delay::from_saturating_duration(Duration::new(u64::max_value(), 0));
+        assert_eq!(longest.numer_denom_ms(), (u32::max_value(), 1));
+    }
+}
@@ -0,0 +1,297 @@
+//! Decoding images read from asynchronous streams
+//!
+//! The futures returned here read an image from an `AsyncRead` without blocking the task that
+//! polls them, so images can be loaded in services built on an asynchronous runtime without
+//! moving the reading to a blocking thread. `dimensions` only reads as much of the image as its
+//! header needs.
+//!
+//! `AsyncRead` is implemented by wrapping the stream type of the runtime, for example by
+//! forwarding to `tokio::io::AsyncRead::poll_read` with a `ReadBuf` over ```buf```. Decoding
+//! itself happens when the image has been read, on the task polling the future.
+
+use std::future::Future;
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use dynimage::{self, DynamicImage};
+use image::{ImageError, ImageFormat, ImageResult, Limits};
+use registry::{self, Codec};
+
+/// The number of bytes read at once
+const CHUNK_LEN: usize = 8192;
+
+/// A stream of bytes that is read without blocking
+pub trait AsyncRead {
+    /// Reads bytes into ```buf```, returning how many were read and 0 at the end of the stream
+    ///
+    /// If no bytes are available yet, this returns `Poll::Pending` and wakes the task of
+    /// ```cx``` once they are.
+    fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>>;
+}
+
+impl<'a, R: AsyncRead + ?Sized> AsyncRead for &'a mut R {
+    fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        (**self).poll_read(cx, buf)
+    }
+}
+
+impl<'a> AsyncRead for &'a [u8] {
+    fn poll_read(&mut self, _: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(io::Read::read(self, buf))
+    }
+}
+
+/// The bytes of an image read so far
+struct Buffer<R> {
+    reader: R,
+    data: Vec<u8>,
+    at_end: bool,
+}
+
+impl<R: AsyncRead> Buffer<R> {
+    fn new(reader: R) -> Buffer<R> {
+        Buffer {
+            reader: reader,
+            data: Vec::new(),
+            at_end: false,
+        }
+    }
+
+    /// Reads the next bytes of the stream, returning `Poll::Ready` once some were read or the
+    /// stream has ended
+    fn poll_fill(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        let start = self.data.len();
+        self.data.resize(start + CHUNK_LEN, 0);
+        let result = self.reader.poll_read(cx, &mut self.data[start..]);
+        let read = match result {
+            Poll::Ready(Ok(read)) => read,
+            _ => 0,
+        };
+        self.data.truncate(start + read);
+        match result {
+            Poll::Ready(Ok(0)) => {
+                self.at_end = true;
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Ok(_)) => Poll::Ready(Ok(())),
+            Poll::Ready(Err(ref err)) if err.kind() == io::ErrorKind::Interrupted => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// The format of an image, either supported by this crate or by a registered codec
+enum Format {
+    Builtin(ImageFormat),
+    Registered(Arc<Codec>),
+}
+
+/// Returns ```format```, or guesses the format of the image starting with ```data```
+fn format_of(format: Option<ImageFormat>, data: &[u8]) -> ImageResult<Format> {
+    if let Some(format) = format {
+        return Ok(Format::Builtin(format))
+    }
+    match dynimage::guess_format(data) {
+        Ok(format) => Ok(Format::Builtin(format)),
+        Err(err) => registry::find_by_content(data).map(Format::Registered).ok_or(err),
+    }
+}
+
+/// A future decoding an image read from an `AsyncRead`
+///
+/// Returned by `load`, `load_with_limits` and `load_guessed`.
+pub struct Load<R> {
+    buffer: Buffer<R>,
+    format: Option<ImageFormat>,
+    limits: Limits,
+}
+
+impl<R: AsyncRead + Unpin> Future for Load<R> {
+    type Output = ImageResult<DynamicImage>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<ImageResult<DynamicImage>> {
+        let this = self.get_mut();
+        while !this.buffer.at_end {
+            match this.buffer.poll_fill(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(ImageError::IoError(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let data = &this.buffer.data;
+        Poll::Ready(format_of(this.format, data).and_then(|format| match format {
+            Format::Builtin(format) => dynimage::load_with_limits(Cursor::new(data), format, &this.limits),
+            Format::Registered(codec) => codec.decode(&mut Cursor::new(data), &this.limits),
+        }))
+    }
+}
+
+/// Decodes the image of format ```format``` read from ```reader```
+///
+/// The image is checked against the default `Limits`.
+pub fn load<R: AsyncRead + Unpin>(reader: R, format: ImageFormat) -> Load<R> {
+    load_with_limits(reader, format, &Limits::default())
+}
+
+/// Decodes the image of format ```format``` read from ```reader```, failing with
+/// `ImageError::Limits` if it exceeds ```limits```
+pub fn load_with_limits<R: AsyncRead + Unpin>(reader: R, format: ImageFormat, limits: &Limits) -> Load<R> {
+    Load {
+        buffer: Buffer::new(reader),
+        format: Some(format),
+        limits: *limits,
+    }
+}
+
+/// Decodes the image read from ```reader```, whose format is guessed with `guess_format`
+///
+/// Images of formats this crate does not support are decoded by the codecs of the `registry`.
+///
+/// The image is checked against the default `Limits`.
+pub fn load_guessed<R: AsyncRead + Unpin>(reader: R) -> Load<R> {
+    Load {
+        buffer: Buffer::new(reader),
+        format: None,
+        limits: Limits::default(),
+    }
+}
+
+/// A future reading the dimensions of an image from an `AsyncRead`
+///
+/// Returned by `dimensions`.
+pub struct Dimensions<R> {
+    buffer: Buffer<R>,
+    format: Option<ImageFormat>,
+    /// The length the data has to reach before the header is read again
+    next_attempt: usize,
+}
+
+impl<R: AsyncRead + Unpin> Future for Dimensions<R> {
+    type Output = ImageResult<(u32, u32)>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<ImageResult<(u32, u32)>> {
+        let this = self.get_mut();
+        loop {
+            match this.buffer.poll_fill(cx) {
+                Poll::Ready(Ok(())) => (),
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(ImageError::IoError(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+            // Headers are read again from the start as the data doubles, until one is complete
+            let data = &this.buffer.data;
+            if data.len() < this.next_attempt && !this.buffer.at_end {
+                continue
+            }
+            this.next_attempt = data.len() * 2;
+            let result = format_of(this.format, data).and_then(|format| match format {
+                Format::Builtin(format) => dynimage::load_dimensions(Cursor::new(data), format),
+                Format::Registered(codec) => codec.dimensions(&mut Cursor::new(data)),
+            });
+            if result.is_ok() || this.buffer.at_end {
+                return Poll::Ready(result)
+            }
+        }
+    }
+}
+
+/// Reads the dimensions of the image read from ```reader```, without decoding it
+///
+/// Only the start of the image holding its header is read. The format is guessed with
+/// `guess_format`, or by the codecs of the `registry`, if ```format``` is `None`.
+pub fn dimensions<R: AsyncRead + Unpin>(reader: R, format: Option<ImageFormat>) -> Dimensions<R> {
+    Dimensions {
+        buffer: Buffer::new(reader),
+        format: format,
+        next_attempt: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::future::Future;
+    use std::io::{self, Read};
+    use std::pin::Pin;
+    use std::ptr;
+    use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+    use super::{dimensions, load, load_guessed, AsyncRead};
+    use image::{GenericImageView, ImageError, ImageFormat};
+
+    /// Hands out ```chunk``` bytes at a time, being pending before each read
+    struct Chunked<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        ready: bool,
+        read: usize,
+    }
+
+    impl<'a> AsyncRead for Chunked<'a> {
+        fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending
+            }
+            let len = buf.len().min(self.chunk).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            self.read += len;
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    fn block_on<F: Future + Unpin>(mut future: F) -> F::Output {
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+
+        let waker = unsafe { Waker::from_raw(clone(ptr::null())) };
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = Pin::new(&mut future).poll(&mut cx) {
+                return output
+            }
+        }
+    }
+
+    fn chunked(data: &[u8]) -> Chunked {
+        Chunked { data: data, chunk: 100, ready: false, read: 0 }
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn read_png() {
+        let mut data = Vec::new();
+        File::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap()
+            .read_to_end(&mut data).unwrap();
+
+        let mut reader = chunked(&data);
+        assert_eq!(block_on(dimensions(&mut reader, None)).unwrap(), (62, 57));
+        assert!(reader.read < data.len() / 10);
+
+        let mut reader = chunked(&data);
+        let image = block_on(load(&mut reader, ImageFormat::PNG)).unwrap();
+        assert_eq!(image.dimensions(), (62, 57));
+        assert_eq!(reader.read, data.len());
+        assert_eq!(block_on(load_guessed(&data[..])).unwrap().dimensions(), (62, 57));
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn truncated_header() {
+        match block_on(dimensions(chunked(b"P6 4 "), Some(ImageFormat::PNM))) {
+            Err(ImageError::IoError(_)) | Err(ImageError::Decoding { .. }) => (),
+            r => panic!("Expected the header to be truncated, got {:?}", r),
+        }
+        assert_eq!(block_on(dimensions(chunked(b"P6 4 3 255\n"), None)).unwrap(), (4, 3));
+    }
+}
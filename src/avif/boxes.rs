@@ -0,0 +1,460 @@
+//! Parsing of the boxes of the HEIF container that AVIF images are stored in
+
+use std::io::{Cursor, Read};
+use byteorder::{ReadBytesExt, BigEndian, ByteOrder};
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+/// The auxiliary types of alpha planes
+const ALPHA_URNS: [&'static str; 2] = [
+    "urn:mpeg:mpegB:cicp:systems:auxiliary:alpha",
+    "urn:mpeg:hevc:2015:auxid:1",
+];
+
+/// The color properties of a ```colr``` box
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nclx {
+    pub matrix_coefficients: u16,
+    pub full_range: bool,
+}
+
+/// The properties of an AV1 bitstream given by its ```av1C``` box
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Av1Config {
+    pub bit_depth: u8,
+    pub monochrome: bool,
+}
+
+/// An item property
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Property {
+    /// The dimensions of an image
+    Spatial(u32, u32),
+    Nclx(Nclx),
+    Av1Config(Av1Config),
+    Icc(Vec<u8>),
+    /// The type of an auxiliary image
+    Auxiliary(String),
+    Other,
+}
+
+/// A part of the data of an item
+#[derive(Clone, Copy, Debug)]
+struct Extent {
+    offset: u64,
+    /// The length, or zero for the rest of the data
+    length: u64,
+}
+
+#[derive(Clone, Debug)]
+struct Item {
+    id: u32,
+    item_type: [u8; 4],
+    /// Whether the extents are in the ```idat``` box rather than the file
+    in_idat: bool,
+    extents: Vec<Extent>,
+    /// The indices of the properties of the item
+    properties: Vec<usize>,
+}
+
+/// The structure of a HEIF file
+#[derive(Debug)]
+pub struct Container {
+    primary: u32,
+    items: Vec<Item>,
+    properties: Vec<Property>,
+    /// The references by type, from item and to items
+    references: Vec<([u8; 4], u32, Vec<u32>)>,
+    /// The range of the ```idat``` box in the file
+    idat: (usize, usize),
+}
+
+fn truncated() -> ImageError {
+    ImageError::truncated(ImageFormat::AVIF)
+}
+
+/// Splits ```data``` into boxes, returning their types, the offsets of their contents and
+/// their contents
+fn read_boxes(data: &[u8], base: usize) -> ImageResult<Vec<([u8; 4], usize, &[u8])>> {
+    let mut boxes = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let rest = &data[pos..];
+        if rest.len() < 8 {
+            return Err(truncated())
+        }
+        let (header, size) = match BigEndian::read_u32(rest) {
+            0 => (8, rest.len() as u64),
+            1 if rest.len() >= 16 => (16, BigEndian::read_u64(&rest[8..])),
+            1 => return Err(truncated()),
+            size => (8, size as u64),
+        };
+        if size < header as u64 || size > rest.len() as u64 {
+            return Err(truncated())
+        }
+        let mut box_type = [0; 4];
+        box_type.copy_from_slice(&rest[4..8]);
+        boxes.push((box_type, base + pos + header, &rest[header..size as usize]));
+        pos += size as usize;
+    }
+    Ok(boxes)
+}
+
+/// Reads an integer of ```size``` bytes, which may be zero
+fn read_sized(r: &mut Cursor<&[u8]>, size: u8) -> ImageResult<u64> {
+    Ok(match size {
+        0 => 0,
+        4 => try!(r.read_u32::<BigEndian>()) as u64,
+        8 => try!(r.read_u64::<BigEndian>()),
+        _ => return Err(ImageError::malformed(ImageFormat::AVIF,
+            format!("Invalid AVIF field size {}", size))),
+    })
+}
+
+/// Reads a 16-bit item id in version zero of a box, and a 32-bit one otherwise
+fn read_item_id(r: &mut Cursor<&[u8]>, wide: bool) -> ImageResult<u32> {
+    Ok(if wide {
+        try!(r.read_u32::<BigEndian>())
+    } else {
+        try!(r.read_u16::<BigEndian>()) as u32
+    })
+}
+
+fn read_four_cc(r: &mut Cursor<&[u8]>) -> ImageResult<[u8; 4]> {
+    let mut four_cc = [0; 4];
+    try!(r.read_exact(&mut four_cc));
+    Ok(four_cc)
+}
+
+/// Returns the version of a box with a version and flags, and a reader for the rest
+fn full_box(data: &[u8]) -> ImageResult<(u8, u32, Cursor<&[u8]>)> {
+    if data.len() < 4 {
+        return Err(truncated())
+    }
+    let mut r = Cursor::new(data);
+    r.set_position(4);
+    Ok((data[0], BigEndian::read_u32(data) & 0xff_ffff, r))
+}
+
+fn parse_property(box_type: &[u8; 4], data: &[u8]) -> ImageResult<Property> {
+    Ok(match box_type {
+        b"ispe" => {
+            let (_, _, mut r) = try!(full_box(data));
+            let width = try!(r.read_u32::<BigEndian>());
+            let height = try!(r.read_u32::<BigEndian>());
+            Property::Spatial(width, height)
+        }
+        b"colr" => {
+            let mut r = Cursor::new(data);
+            match &try!(read_four_cc(&mut r)) {
+                b"nclx" => {
+                    let _primaries = try!(r.read_u16::<BigEndian>());
+                    let _transfer = try!(r.read_u16::<BigEndian>());
+                    let matrix_coefficients = try!(r.read_u16::<BigEndian>());
+                    let full_range = try!(r.read_u8()) & 0x80 != 0;
+                    Property::Nclx(Nclx {
+                        matrix_coefficients: matrix_coefficients,
+                        full_range: full_range,
+                    })
+                }
+                b"prof" | b"rICC" => Property::Icc(data[4..].to_vec()),
+                _ => Property::Other,
+            }
+        }
+        b"av1C" => {
+            if data.len() < 4 {
+                return Err(truncated())
+            }
+            // The high bit depth, twelve bit and monochrome flags follow the profile and level
+            let bit_depth = match (data[2] & 0x40 != 0, data[2] & 0x20 != 0) {
+                (true, true) => 12,
+                (true, false) => 10,
+                _ => 8,
+            };
+            Property::Av1Config(Av1Config { bit_depth: bit_depth, monochrome: data[2] & 0x10 != 0 })
+        }
+        b"auxC" => {
+            let (_, _, r) = try!(full_box(data));
+            let name = &data[r.position() as usize..];
+            let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            Property::Auxiliary(String::from_utf8_lossy(&name[..end]).into_owned())
+        }
+        _ => Property::Other,
+    })
+}
+
+impl Container {
+    /// Parses the boxes of the file ```data```
+    pub fn parse(data: &[u8]) -> ImageResult<Container> {
+        let boxes = try!(read_boxes(data, 0));
+        match boxes.first() {
+            Some(&(ref box_type, _, ftyp)) if box_type == b"ftyp" && ftyp.len() >= 8 => {
+                // The major brand, the minor version and the compatible brands
+                let is_avif = ftyp.chunks(4).enumerate()
+                    .any(|(i, brand)| i != 1 && brand == b"avif");
+                if !is_avif {
+                    return Err(ImageError::malformed(ImageFormat::AVIF, "Not an AVIF image".to_string()))
+                }
+            }
+            _ => return Err(ImageError::malformed(ImageFormat::AVIF, "Not an AVIF image".to_string())),
+        }
+        let (meta_offset, meta) = match boxes.iter().find(|b| &b.0 == b"meta") {
+            Some(&(_, offset, meta)) if meta.len() >= 4 => (offset + 4, &meta[4..]),
+            _ => return Err(ImageError::malformed(ImageFormat::AVIF,
+                "AVIF image without meta box".to_string())),
+        };
+
+        let mut container = Container {
+            primary: 0,
+            items: Vec::new(),
+            properties: Vec::new(),
+            references: Vec::new(),
+            idat: (0, 0),
+        };
+        let mut associations = Vec::new();
+        for (box_type, offset, data) in try!(read_boxes(meta, meta_offset)) {
+            match &box_type {
+                b"pitm" => {
+                    let (version, _, mut r) = try!(full_box(data));
+                    container.primary = try!(read_item_id(&mut r, version > 0));
+                }
+                b"iinf" => try!(container.parse_item_infos(data, offset)),
+                b"iloc" => try!(container.parse_item_locations(data)),
+                b"iref" => {
+                    let (version, _, r) = try!(full_box(data));
+                    let start = r.position() as usize;
+                    for (reference_type, _, data) in try!(read_boxes(&data[start..], 0)) {
+                        let mut r = Cursor::new(data);
+                        let from = try!(read_item_id(&mut r, version > 0));
+                        let mut to = Vec::new();
+                        for _ in 0..try!(r.read_u16::<BigEndian>()) {
+                            to.push(try!(read_item_id(&mut r, version > 0)));
+                        }
+                        container.references.push((reference_type, from, to));
+                    }
+                }
+                b"iprp" => {
+                    for (box_type, _, data) in try!(read_boxes(data, 0)) {
+                        match &box_type {
+                            b"ipco" => for (box_type, _, data) in try!(read_boxes(data, 0)) {
+                                container.properties.push(try!(parse_property(&box_type, data)));
+                            },
+                            b"ipma" => try!(parse_associations(data, &mut associations)),
+                            _ => (),
+                        }
+                    }
+                }
+                b"idat" => container.idat = (offset, data.len()),
+                _ => (),
+            }
+        }
+
+        for (id, index) in associations {
+            // Index zero associates no property
+            if index == 0 {
+                continue
+            }
+            if index > container.properties.len() {
+                return Err(ImageError::malformed(ImageFormat::AVIF,
+                    format!("Invalid AVIF property index {}", index)))
+            }
+            if let Some(item) = container.items.iter_mut().find(|item| item.id == id) {
+                item.properties.push(index - 1);
+            }
+        }
+        Ok(container)
+    }
+
+    fn parse_item_infos(&mut self, data: &[u8], offset: usize) -> ImageResult<()> {
+        let (version, _, r) = try!(full_box(data));
+        let start = r.position() as usize + if version == 0 { 2 } else { 4 };
+        if data.len() < start {
+            return Err(truncated())
+        }
+        for (box_type, _, data) in try!(read_boxes(&data[start..], offset + start)) {
+            if &box_type != b"infe" {
+                continue
+            }
+            let (version, _, mut r) = try!(full_box(data));
+            if version < 2 {
+                return Err(ImageError::unsupported(ImageFormat::AVIF,
+                    format!("Unsupported item info version {}", version)))
+            }
+            let id = try!(read_item_id(&mut r, version > 2));
+            let _protection_index = try!(r.read_u16::<BigEndian>());
+            let item_type = try!(read_four_cc(&mut r));
+            match self.items.iter_mut().find(|item| item.id == id) {
+                Some(item) => item.item_type = item_type,
+                None => self.items.push(Item {
+                    id: id,
+                    item_type: item_type,
+                    in_idat: false,
+                    extents: Vec::new(),
+                    properties: Vec::new(),
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_item_locations(&mut self, data: &[u8]) -> ImageResult<()> {
+        let (version, _, mut r) = try!(full_box(data));
+        let sizes = try!(r.read_u16::<BigEndian>());
+        let (offset_size, length_size) = ((sizes >> 12) as u8, (sizes >> 8) as u8 & 0xf);
+        let base_offset_size = (sizes >> 4) as u8 & 0xf;
+        let index_size = if version > 0 { sizes as u8 & 0xf } else { 0 };
+        let count = try!(read_item_id(&mut r, version > 1));
+        for _ in 0..count {
+            let id = try!(read_item_id(&mut r, version > 1));
+            let construction_method = if version > 0 { try!(r.read_u16::<BigEndian>()) & 0xf } else { 0 };
+            if construction_method > 1 {
+                return Err(ImageError::unsupported(ImageFormat::AVIF,
+                    format!("Unsupported item construction method {}", construction_method)))
+            }
+            let _data_reference_index = try!(r.read_u16::<BigEndian>());
+            let base_offset = try!(read_sized(&mut r, base_offset_size));
+            let mut extents = Vec::new();
+            for _ in 0..try!(r.read_u16::<BigEndian>()) {
+                let _index = try!(read_sized(&mut r, index_size));
+                let offset = try!(read_sized(&mut r, offset_size));
+                let length = try!(read_sized(&mut r, length_size));
+                extents.push(Extent {
+                    offset: try!(base_offset.checked_add(offset).ok_or_else(truncated)),
+                    length: length,
+                });
+            }
+
+            let item = Item {
+                id: id,
+                item_type: [0; 4],
+                in_idat: construction_method == 1,
+                extents: extents,
+                properties: Vec::new(),
+            };
+            match self.items.iter_mut().find(|item| item.id == id) {
+                Some(existing) => {
+                    existing.in_idat = item.in_idat;
+                    existing.extents = item.extents;
+                }
+                None => self.items.push(item),
+            }
+        }
+        Ok(())
+    }
+
+    fn item(&self, id: u32) -> ImageResult<&Item> {
+        self.items.iter().find(|item| item.id == id)
+            .ok_or_else(|| ImageError::malformed(ImageFormat::AVIF, format!("Missing AVIF item {}", id)))
+    }
+
+    /// Returns the first value that ```f``` returns for the properties of the item ```id```
+    fn find_property<T, F>(&self, id: u32, f: F) -> ImageResult<Option<T>>
+        where F: Fn(&Property) -> Option<T> {
+        let item = try!(self.item(id));
+        Ok(item.properties.iter().filter_map(|&i| f(&self.properties[i])).next())
+    }
+
+    /// Returns the id of the primary image, which has to be AV1 coded
+    pub fn primary_item(&self) -> ImageResult<u32> {
+        let item = try!(self.item(self.primary));
+        match &item.item_type {
+            b"av01" => Ok(item.id),
+            b"grid" => Err(ImageError::unsupported(ImageFormat::AVIF,
+                "AVIF grid images are not supported".to_string())),
+            item_type => Err(ImageError::unsupported(ImageFormat::AVIF,
+                format!("Unsupported AVIF item type {:?}", String::from_utf8_lossy(item_type)))),
+        }
+    }
+
+    /// Returns the id of the alpha plane of the primary image, if it has one
+    pub fn alpha_item(&self) -> Option<u32> {
+        self.references.iter()
+            .filter(|&&(ref reference_type, _, ref to)| reference_type == b"auxl" && to.contains(&self.primary))
+            .map(|&(_, from, _)| from)
+            .find(|&id| {
+                let is_av1 = self.item(id).map(|item| &item.item_type == b"av01").unwrap_or(false);
+                let is_alpha = self.find_property(id, |p| match *p {
+                    Property::Auxiliary(ref urn) if ALPHA_URNS.contains(&&urn[..]) => Some(()),
+                    _ => None,
+                });
+                is_av1 && is_alpha.ok().and_then(|alpha| alpha).is_some()
+            })
+    }
+
+    /// Returns the dimensions of the item ```id```
+    pub fn dimensions(&self, id: u32) -> ImageResult<(u32, u32)> {
+        let dimensions = try!(self.find_property(id, |p| match *p {
+            Property::Spatial(width, height) => Some((width, height)),
+            _ => None,
+        }));
+        dimensions.ok_or_else(|| ImageError::malformed(ImageFormat::AVIF,
+            "AVIF image without dimensions".to_string()))
+    }
+
+    /// Returns the color properties of the item ```id```, if they are stored in the container
+    pub fn nclx(&self, id: u32) -> ImageResult<Option<Nclx>> {
+        self.find_property(id, |p| match *p {
+            Property::Nclx(nclx) => Some(nclx),
+            _ => None,
+        })
+    }
+
+    /// Returns the properties of the AV1 bitstream of the item ```id```, if they are stored in
+    /// the container
+    pub fn av1_config(&self, id: u32) -> ImageResult<Option<Av1Config>> {
+        self.find_property(id, |p| match *p {
+            Property::Av1Config(config) => Some(config),
+            _ => None,
+        })
+    }
+
+    /// Returns the ICC profile of the item ```id```, if it has one
+    pub fn icc_profile(&self, id: u32) -> ImageResult<Option<Vec<u8>>> {
+        self.find_property(id, |p| match *p {
+            Property::Icc(ref profile) => Some(profile.clone()),
+            _ => None,
+        })
+    }
+
+    /// Collects the data of the item ```id``` from the file ```data```
+    pub fn item_data(&self, data: &[u8], id: u32) -> ImageResult<Vec<u8>> {
+        let item = try!(self.item(id));
+        let source = if item.in_idat {
+            &data[self.idat.0..self.idat.0 + self.idat.1]
+        } else {
+            data
+        };
+
+        let mut out = Vec::new();
+        for extent in &item.extents {
+            let start = extent.offset;
+            let end = if extent.length == 0 { source.len() as u64 } else {
+                try!(start.checked_add(extent.length).ok_or_else(truncated))
+            };
+            if start > end || end > source.len() as u64 {
+                return Err(ImageError::malformed(ImageFormat::AVIF,
+                    "AVIF item data out of bounds".to_string()))
+            }
+            out.extend_from_slice(&source[start as usize..end as usize]);
+        }
+        Ok(out)
+    }
+}
+
+/// Reads the item ids and the one-based property indices of an ```ipma``` box
+fn parse_associations(data: &[u8], associations: &mut Vec<(u32, usize)>) -> ImageResult<()> {
+    let (version, flags, mut r) = try!(full_box(data));
+    for _ in 0..try!(r.read_u32::<BigEndian>()) {
+        let id = try!(read_item_id(&mut r, version > 0));
+        for _ in 0..try!(r.read_u8()) {
+            // The highest bit marks essential properties
+            let index = if flags & 1 != 0 {
+                try!(r.read_u16::<BigEndian>()) as usize & 0x7fff
+            } else {
+                try!(r.read_u8()) as usize & 0x7f
+            };
+            associations.push((id, index));
+        }
+    }
+    Ok(())
+}
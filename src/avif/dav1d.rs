@@ -0,0 +1,213 @@
+//! Bindings to the AV1 decoder of the system's dav1d library
+//!
+//! Only the leading fields of the library's structures are declared. They are followed by
+//! enough padding for the library to write the rest. The layout is the one of dav1d 1.0 and
+//! later, whose shared library has the ABI version 6.
+
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use image::{ImageError, ImageFormat, ImageResult, LimitErrorKind};
+
+/// dav1d returns negated ```errno``` values
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd",
+          target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly"))]
+const EAGAIN: c_int = -35;
+#[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd",
+              target_os = "openbsd", target_os = "netbsd", target_os = "dragonfly")))]
+const EAGAIN: c_int = -11;
+
+#[repr(C)]
+struct Settings {
+    n_threads: c_int,
+    max_frame_delay: c_int,
+    rest: [u64; 64],
+}
+
+#[repr(C)]
+struct Data {
+    data: *const u8,
+    sz: usize,
+    rest: [u64; 16],
+}
+
+#[repr(C)]
+struct SequenceHeader {
+    profile: u8,
+    max_width: c_int,
+    max_height: c_int,
+    layout: c_int,
+    pri: c_int,
+    trc: c_int,
+    mtrx: c_int,
+    chr: c_int,
+    hbd: c_int,
+    color_range: c_int,
+}
+
+#[repr(C)]
+struct Picture {
+    seq_hdr: *const SequenceHeader,
+    frame_hdr: *const c_void,
+    data: [*const u8; 3],
+    stride: [isize; 2],
+    w: c_int,
+    h: c_int,
+    layout: c_int,
+    bpc: c_int,
+    rest: [u64; 64],
+}
+
+#[link(name = "dav1d")]
+extern "C" {
+    fn dav1d_default_settings(s: *mut Settings);
+    fn dav1d_open(c_out: *mut *mut c_void, s: *const Settings) -> c_int;
+    fn dav1d_close(c_out: *mut *mut c_void);
+    fn dav1d_data_create(data: *mut Data, sz: usize) -> *mut u8;
+    fn dav1d_data_unref(data: *mut Data);
+    fn dav1d_send_data(c: *mut c_void, data: *mut Data) -> c_int;
+    fn dav1d_get_picture(c: *mut c_void, out: *mut Picture) -> c_int;
+    fn dav1d_picture_unref(p: *mut Picture);
+}
+
+/// The subsampling of the chroma planes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Only a luma plane
+    I400,
+    I420,
+    I422,
+    I444,
+}
+
+/// A decoded frame
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub layout: Layout,
+    /// The matrix coefficients of the sequence header
+    pub matrix_coefficients: u16,
+    pub full_range: bool,
+    /// The luma and the two chroma planes, without padding
+    pub planes: [Vec<u16>; 3],
+}
+
+impl Frame {
+    /// Returns the dimensions of the plane ```plane```
+    pub fn plane_dimensions(&self, plane: usize) -> (u32, u32) {
+        match (plane, self.layout) {
+            (0, _) | (_, Layout::I444) => (self.width, self.height),
+            (_, Layout::I400) => (0, 0),
+            (_, Layout::I420) => ((self.width + 1) / 2, (self.height + 1) / 2),
+            (_, Layout::I422) => ((self.width + 1) / 2, self.height),
+        }
+    }
+}
+
+/// Closes the decoder context when it goes out of scope
+struct Context(*mut c_void);
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe { dav1d_close(&mut self.0) }
+    }
+}
+
+fn error(code: c_int) -> ImageError {
+    ImageError::malformed(ImageFormat::AVIF, format!("AV1 decoding failed with error {}", code))
+}
+
+/// Decodes the first frame of the AV1 open bitstream units in ```obus```
+pub fn decode(obus: &[u8]) -> ImageResult<Frame> {
+    if obus.is_empty() {
+        return Err(ImageError::truncated(ImageFormat::AVIF))
+    }
+
+    unsafe {
+        let mut settings: Settings = ::std::mem::zeroed();
+        dav1d_default_settings(&mut settings);
+        // Frames are returned as soon as they are decoded
+        settings.n_threads = 1;
+        settings.max_frame_delay = 1;
+        let mut context = Context(ptr::null_mut());
+        let result = dav1d_open(&mut context.0, &settings);
+        if result < 0 {
+            return Err(error(result))
+        }
+
+        let mut data: Data = ::std::mem::zeroed();
+        let buffer = dav1d_data_create(&mut data, obus.len());
+        if buffer.is_null() {
+            return Err(ImageError::Limits(LimitErrorKind::Memory))
+        }
+        ptr::copy_nonoverlapping(obus.as_ptr(), buffer, obus.len());
+
+        let mut picture: Picture = ::std::mem::zeroed();
+        let result = loop {
+            if data.sz > 0 {
+                let result = dav1d_send_data(context.0, &mut data);
+                if result < 0 && result != EAGAIN {
+                    break result
+                }
+            }
+            let result = dav1d_get_picture(context.0, &mut picture);
+            // Without data left and no frame, the bitstream does not contain one
+            if result != EAGAIN || data.sz == 0 {
+                break result
+            }
+        };
+        if data.sz > 0 {
+            dav1d_data_unref(&mut data);
+        }
+        if result < 0 {
+            return Err(error(result))
+        }
+
+        let frame = copy_frame(&picture);
+        dav1d_picture_unref(&mut picture);
+        frame
+    }
+}
+
+unsafe fn copy_frame(picture: &Picture) -> ImageResult<Frame> {
+    let layout = match picture.layout {
+        0 => Layout::I400,
+        1 => Layout::I420,
+        2 => Layout::I422,
+        3 => Layout::I444,
+        layout => return Err(ImageError::malformed(ImageFormat::AVIF,
+            format!("Invalid AV1 pixel layout {}", layout))),
+    };
+    if picture.w <= 0 || picture.h <= 0 || picture.seq_hdr.is_null() {
+        return Err(ImageError::malformed(ImageFormat::AVIF, "Invalid image dimensions".to_string()))
+    }
+    let seq_hdr = &*picture.seq_hdr;
+    let mut frame = Frame {
+        width: picture.w as u32,
+        height: picture.h as u32,
+        bit_depth: picture.bpc as u8,
+        layout: layout,
+        matrix_coefficients: seq_hdr.mtrx as u16,
+        full_range: seq_hdr.color_range != 0,
+        planes: [Vec::new(), Vec::new(), Vec::new()],
+    };
+
+    let planes = if layout == Layout::I400 { 1 } else { 3 };
+    for plane in 0..planes {
+        let (width, height) = frame.plane_dimensions(plane);
+        let stride = picture.stride[if plane == 0 { 0 } else { 1 }];
+        let out = &mut frame.planes[plane];
+        out.reserve(width as usize * height as usize);
+        for y in 0..height as isize {
+            let row = picture.data[plane].offset(y * stride);
+            if picture.bpc > 8 {
+                out.extend_from_slice(slice::from_raw_parts(row as *const u16, width as usize));
+            } else {
+                out.extend(slice::from_raw_parts(row, width as usize).iter().map(|&v| v as u16));
+            }
+        }
+    }
+    Ok(frame)
+}
@@ -0,0 +1,307 @@
+use std::io::Read;
+use byteorder::{BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+use super::boxes::Container;
+use super::dav1d::{self, Frame, Layout};
+
+/// The converted samples of an image
+struct Samples {
+    color: ColorType,
+    /// The interleaved channels, with 8 or 16 significant bits
+    data: Vec<u16>,
+}
+
+/// The representation of an AVIF decoder
+///
+/// The whole file is read when the decoder is created, and the image is decoded on the first
+/// request for its samples.
+pub struct AVIFDecoder {
+    data: Vec<u8>,
+    container: Container,
+    primary: u32,
+    eight_bit: bool,
+    samples: Option<Samples>,
+    /// The next row to be returned
+    row: u32,
+}
+
+impl AVIFDecoder {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new<R: Read>(mut r: R) -> ImageResult<AVIFDecoder> {
+        let mut data = Vec::new();
+        try!(r.read_to_end(&mut data));
+        let container = try!(Container::parse(&data));
+        let primary = try!(container.primary_item());
+        Ok(AVIFDecoder {
+            data: data,
+            container: container,
+            primary: primary,
+            eight_bit: false,
+            samples: None,
+            row: 0,
+        })
+    }
+
+    /// Sets whether images with more than 8 bits per channel are decoded to 8-bit samples
+    ///
+    /// By default they are scaled to 16 bits.
+    pub fn set_8bit_output(&mut self, eight_bit: bool) {
+        self.eight_bit = eight_bit;
+    }
+
+    fn decode(&mut self) -> ImageResult<&Samples> {
+        if self.samples.is_none() {
+            let (width, height) = try!(self.dimensions());
+            let frame = try!(dav1d::decode(&try!(self.container.item_data(&self.data, self.primary))));
+            if (frame.width, frame.height) != (width, height) {
+                return Err(ImageError::malformed(ImageFormat::AVIF,
+                    "AVIF image dimensions do not match".to_string()))
+            }
+            // The color type is taken from the configuration before the image is decoded
+            if let Some(config) = try!(self.container.av1_config(self.primary)) {
+                if (config.bit_depth, config.monochrome) != (frame.bit_depth, frame.layout == Layout::I400) {
+                    return Err(ImageError::malformed(ImageFormat::AVIF,
+                        "AVIF configuration does not match the image".to_string()))
+                }
+            }
+            let alpha = match self.container.alpha_item() {
+                Some(id) => {
+                    let alpha = try!(dav1d::decode(&try!(self.container.item_data(&self.data, id))));
+                    if (alpha.width, alpha.height) != (width, height) {
+                        return Err(ImageError::malformed(ImageFormat::AVIF,
+                            "AVIF alpha dimensions do not match".to_string()))
+                    }
+                    Some(alpha)
+                }
+                None => None,
+            };
+            // The properties of the container take precedence over those of the bitstream
+            let (matrix, full_range) = match try!(self.container.nclx(self.primary)) {
+                Some(nclx) => (nclx.matrix_coefficients, nclx.full_range),
+                None => (frame.matrix_coefficients, frame.full_range),
+            };
+            let bits = if frame.bit_depth > 8 && !self.eight_bit { 16 } else { 8 };
+            self.samples = Some(convert(&frame, matrix, full_range, alpha.as_ref(), bits));
+        }
+        Ok(self.samples.as_ref().unwrap())
+    }
+}
+
+/// Normalizes a luma or chroma sample
+///
+/// Chroma samples are centered around zero.
+fn normalize(v: u16, depth: u8, full_range: bool, chroma: bool) -> f32 {
+    let scale = (1u32 << (depth - 8)) as f32;
+    match (full_range, chroma) {
+        (true, false) => v as f32 / ((1u32 << depth) - 1) as f32,
+        (true, true) => (v as f32 - 128.0 * scale) / ((1u32 << depth) - 1) as f32,
+        (false, false) => (v as f32 - 16.0 * scale) / (219.0 * scale),
+        (false, true) => (v as f32 - 128.0 * scale) / (224.0 * scale),
+    }
+}
+
+/// Converts the planes of ```frame``` to interleaved samples of ```bits``` bits
+fn convert(frame: &Frame, matrix: u16, full_range: bool, alpha: Option<&Frame>, bits: u8) -> Samples {
+    let max = ((1u32 << bits) - 1) as f32;
+    let quantize = |v: f32| (v.max(0.0).min(1.0) * max + 0.5) as u16;
+    // The luma coefficients of red and blue
+    let (kr, kb) = match matrix {
+        1 => (0.2126, 0.0722),
+        9 | 10 => (0.2627, 0.0593),
+        _ => (0.299, 0.114),
+    };
+    let (chroma_width, _) = frame.plane_dimensions(1);
+    let (x_shift, y_shift) = match frame.layout {
+        Layout::I420 => (1, 1),
+        Layout::I422 => (1, 0),
+        Layout::I400 | Layout::I444 => (0, 0),
+    };
+
+    let mut data = Vec::new();
+    for y in 0..frame.height as usize {
+        for x in 0..frame.width as usize {
+            let i = y * frame.width as usize + x;
+            let luma = frame.planes[0][i];
+            if frame.layout == Layout::I400 {
+                data.push(quantize(normalize(luma, frame.bit_depth, full_range, false)));
+            } else {
+                let c = (y >> y_shift) * chroma_width as usize + (x >> x_shift);
+                let (u, v) = (frame.planes[1][c], frame.planes[2][c]);
+                let rgb = if matrix == 0 {
+                    // The planes hold green, blue and red
+                    [v, luma, u].iter().map(|&s| normalize(s, frame.bit_depth, full_range, false)).collect()
+                } else {
+                    let luma = normalize(luma, frame.bit_depth, full_range, false);
+                    let cb = normalize(u, frame.bit_depth, full_range, true);
+                    let cr = normalize(v, frame.bit_depth, full_range, true);
+                    let r = luma + 2.0 * (1.0 - kr) * cr;
+                    let b = luma + 2.0 * (1.0 - kb) * cb;
+                    let g = (luma - kr * r - kb * b) / (1.0 - kr - kb);
+                    vec![r, g, b]
+                };
+                data.extend(rgb.into_iter().map(&quantize));
+            }
+            if let Some(alpha) = alpha {
+                data.push(quantize(normalize(alpha.planes[0][i], alpha.bit_depth, alpha.full_range, false)));
+            }
+        }
+    }
+
+    let color = match (frame.layout == Layout::I400, alpha.is_some()) {
+        (true, false) => ColorType::Gray(bits),
+        (true, true) => ColorType::GrayA(bits),
+        (false, false) => ColorType::RGB(bits),
+        (false, true) => ColorType::RGBA(bits),
+    };
+    Samples { color: color, data: data }
+}
+
+impl ImageDecoder for AVIFDecoder {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        self.container.dimensions(self.primary)
+    }
+
+    /// Returns the color type given by the container, only decoding images without AV1
+    /// configuration
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        let config = match try!(self.container.av1_config(self.primary)) {
+            Some(config) => config,
+            None => return Ok(try!(self.decode()).color),
+        };
+        let bits = if config.bit_depth > 8 && !self.eight_bit { 16 } else { 8 };
+        Ok(match (config.monochrome, self.container.alpha_item().is_some()) {
+            (true, false) => ColorType::Gray(bits),
+            (true, true) => ColorType::GrayA(bits),
+            (false, false) => ColorType::RGB(bits),
+            (false, true) => ColorType::RGBA(bits),
+        })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let (width, _) = try!(self.dimensions());
+        let bits = ::color::bits_per_pixel(try!(self.colortype()));
+        Ok(width as usize * bits / 8)
+    }
+
+    /// Reads the next row into ```buf```, with 16-bit samples in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let (_, height) = try!(self.dimensions());
+        let len = try!(self.row_len());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let row = self.row as usize;
+        let samples = try!(self.decode());
+        let channels = ::color::num_components(samples.color);
+        let bits = ::color::bits_per_pixel(samples.color) / channels;
+        let row_samples = len * 8 / bits;
+        let source = &samples.data[row * row_samples..(row + 1) * row_samples];
+        if bits == 8 {
+            for (out, &v) in buf.iter_mut().zip(source) {
+                *out = v as u8;
+            }
+        } else {
+            for (out, &v) in buf.chunks_mut(2).zip(source) {
+                BigEndian::write_u16(out, v);
+            }
+        }
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let row = self.row as usize;
+        let len = try!(self.row_len());
+        let samples = try!(self.decode());
+        let bits = ::color::bits_per_pixel(samples.color) / ::color::num_components(samples.color);
+        let data = &samples.data[row * len * 8 / bits..];
+        let result = if bits == 8 {
+            DecodingResult::U8(data.iter().map(|&v| v as u8).collect())
+        } else {
+            DecodingResult::U16(data.to_vec())
+        };
+        self.row = try!(self.dimensions()).1;
+        Ok(result)
+    }
+
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        self.container.icc_profile(self.primary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::AVIFDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn open(name: &str) -> AVIFDecoder {
+        AVIFDecoder::new(File::open(format!("tests/images/avif/{}.avif", name)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rgb() {
+        for &(name, first) in &[("rgb8", [0, 2, 121]), ("rgb8_lossy", [1, 4, 121])] {
+            let mut decoder = open(name);
+            assert_eq!(decoder.dimensions().unwrap(), (20, 14));
+            assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+            // The header is enough for the color type
+            assert!(decoder.samples.is_none());
+            assert_eq!(decoder.icc_profile().unwrap(), None);
+            let mut row = [0; 60];
+            assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+            assert_eq!(&row[..3], &first);
+            match decoder.read_image().unwrap() {
+                DecodingResult::U8(data) => assert_eq!(data.len(), 13 * 60),
+                _ => panic!("Image did not decode as 8-bit"),
+            }
+        }
+    }
+
+    #[test]
+    fn alpha() {
+        let mut decoder = open("rgba8");
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => for (i, pixel) in data.chunks(4).enumerate() {
+                assert_eq!(pixel[3], if i % 20 < 10 { 255 } else { 60 });
+            },
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn high_bit_depth() {
+        let mut decoder = open("yuv12");
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(16));
+        let mut row = [0; 120];
+        decoder.read_scanline(&mut row).unwrap();
+        assert_eq!(&row[..6], &[0, 0, 0x65, 0xc9, 0, 0]);
+
+        let mut decoder = open("yuv12");
+        decoder.set_8bit_output(true);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(&data[..3], &[0, 101, 0]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let mut data = Vec::new();
+        ::std::io::Read::read_to_end(&mut File::open("tests/images/avif/rgb8.avif").unwrap(), &mut data).unwrap();
+        assert!(AVIFDecoder::new(&data[..40]).is_err());
+        data[4..8].copy_from_slice(b"free");
+        assert!(AVIFDecoder::new(&data[..]).is_err());
+    }
+}
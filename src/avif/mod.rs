@@ -0,0 +1,18 @@
+//! Decoding of AVIF Images
+//!
+//! AVIF stores AV1 coded frames in a HEIF container. The frames are decoded by the system's
+//! dav1d library, which has to be installed to use the ```avif``` feature.
+//!
+//! Only still images coded as a single item are supported, with an optional alpha plane.
+//! Rotation and mirroring properties are not applied.
+//!
+//! # Related Links
+//! * <https://aomediacodec.github.io/av1-avif/> - The AVIF specification
+//! * <https://aomediacodec.github.io/av1-spec/> - The AV1 bitstream specification
+//!
+
+pub use self::decoder::AVIFDecoder;
+
+mod boxes;
+mod dav1d;
+mod decoder;
@@ -7,12 +7,17 @@ use std::slice::ChunksMut;
 use byteorder::{ReadBytesExt, LittleEndian};
 
 use image::{
+    decoder_metadata,
     DecodingResult,
     ImageResult,
     ImageDecoder,
-    ImageError
+    ImageError,
+    ImageFormat,
+    ParameterErrorKind,
+    Metadata
 };
 use color::ColorType;
+use monitor::Monitor;
 
 const BITMAPCOREHEADER_SIZE: u32 = 12;
 const BITMAPINFOHEADER_SIZE: u32 = 40;
@@ -102,7 +107,8 @@ impl<'a> Iterator for RowIterator<'a> {
 /// Convenience function to check if the combination of width, length and number of
 /// channels would result in a buffer that would overflow.
 fn check_for_overflow(width: i32, length: i32, channels: usize) -> ImageResult<()> {
-    num_bytes(width, length, channels).map(|_| ()).ok_or_else(|| ImageError::FormatError("Image would require a buffer that is too large to be represented!".to_owned()))
+    num_bytes(width, length, channels).map(|_| ()).ok_or_else(|| ImageError::malformed(ImageFormat::BMP,
+        "Image would require a buffer that is too large to be represented!".to_owned()))
 }
 
 /// Calculate how many many bytes a buffer holding a decoded image with these properties would
@@ -198,12 +204,21 @@ fn extend_buffer(buffer: &mut Vec<u8>, full_size: usize, blank: bool) -> &mut [u
 
 /// Call the provided function on each row of the provided buffer, returning Err if the provided
 /// function returns an error, extends the buffer if it's not large enough.
-fn with_rows<F>(buffer: &mut Vec<u8>, width: i32, height: i32, channels: usize, top_down: bool, mut func: F)
-                -> io::Result<()> where F: FnMut(&mut [u8]) -> io::Result<()>  {
+fn with_rows<F>(buffer: &mut Vec<u8>, width: i32, height: i32, channels: usize, top_down: bool,
+                monitor: &mut Monitor, mut func: F) -> ImageResult<()>
+                where F: FnMut(&mut [u8]) -> io::Result<()>  {
     // An overflow should already have been checked for when this is called,
     // though we check anyhow, as it somehow seems to increase performance slightly.
     let row_width = channels.checked_mul(width as usize).unwrap();
     let full_image_size = row_width.checked_mul(height as usize).unwrap();
+    // Each row read is reported to the monitor
+    try!(monitor.update(0, height as u64));
+    let mut rows = 0;
+    let mut func = |row: &mut [u8]| -> ImageResult<()> {
+        try!(func(row));
+        rows += 1;
+        monitor.update(rows, height as u64)
+    };
 
     if !top_down {
         for row in buffer.chunks_mut(row_width).rev() {
@@ -315,10 +330,10 @@ impl Bitfield {
         let mut shift = mask.trailing_zeros();
         let mut len = (!(mask >> shift)).trailing_zeros();
         if len != mask.count_ones() {
-            return Err(ImageError::FormatError("Non-contiguous bitfield mask".to_string()))
+            return Err(ImageError::malformed(ImageFormat::BMP, "Non-contiguous bitfield mask".to_string()))
         }
         if len + shift > max_len {
-            return Err(ImageError::FormatError("Invalid bitfield mask".to_string()))
+            return Err(ImageError::malformed(ImageFormat::BMP, "Invalid bitfield mask".to_string()))
         }
         if len > 8 {
             shift += len - 8;
@@ -330,6 +345,8 @@ impl Bitfield {
     fn read(&self, data: u32) -> u8 {
         let data = data >> self.shift;
         match self.len {
+            // A missing alpha mask means the pixels are opaque.
+            0 => 0xff,
             1 => ((data & 0b1) * 0xff) as u8,
             2 => ((data & 0b11) * 0x55) as u8,
             3 => LOOKUP_TABLE_3_BIT_TO_8_BIT[(data & 0b111) as usize],
@@ -360,7 +377,7 @@ impl Bitfields {
             a: try!(Bitfield::from_mask(a_mask, max_len)),
         };
         if bitfields.r.len == 0 || bitfields.g.len == 0 || bitfields.b.len == 0 {
-            return Err(ImageError::FormatError("Missing bitfield mask".to_string()))
+            return Err(ImageError::malformed(ImageFormat::BMP, "Missing bitfield mask".to_string()))
         }
         Ok(bitfields)
     }
@@ -385,6 +402,13 @@ pub struct BMPDecoder<R> {
     colors_used: u32,
     palette: Option<Vec<(u8, u8, u8)>>,
     bitfields: Option<Bitfields>,
+    /// The horizontal and vertical printing resolutions in pixels per meter, zero if unknown
+    pixels_per_meter: (i32, i32),
+
+    /// The decoded image, while its rows are read by `read_scanline`
+    rows: Option<Vec<u8>>,
+    /// The number of rows read by `read_scanline`
+    row: u32,
 }
 
 enum RLEInsn {
@@ -477,6 +501,10 @@ impl<R: Read + Seek> BMPDecoder<R> {
             colors_used: 0,
             palette: None,
             bitfields: None,
+            pixels_per_meter: (0, 0),
+
+            rows: None,
+            row: 0,
         }
     }
 
@@ -494,7 +522,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
         try!(self.r.read_exact(&mut signature));
 
         if signature != b"BM"[..] {
-            return Err(ImageError::FormatError("BMP signature not found".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "BMP signature not found".to_string()));
         }
 
         // The next 8 bytes represent file size, followed the 4 reserved bytes
@@ -520,14 +548,14 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         // Number of planes (format specifies that this should be 1).
         if try!(self.r.read_u16::<LittleEndian>()) != 1 {
-            return Err(ImageError::FormatError("Invalid number of planes.".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "Invalid number of planes.".to_string()));
         }
 
         self.bit_count = try!(self.r.read_u16::<LittleEndian>());
         self.image_type = match self.bit_count {
             1 | 4 | 8 => ImageType::Palette,
             24 => ImageType::RGB24,
-            _ => return Err(ImageError::FormatError("Invalid bit count".to_string())),
+            _ => return Err(ImageError::malformed(ImageFormat::BMP, "Invalid bit count".to_string())),
         };
 
         Ok(())
@@ -543,15 +571,15 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         // Width can not be negative
         if self.width < 0 {
-            return Err(ImageError::FormatError("Negative width".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "Negative width".to_string()));
         } else if self.width > MAX_WIDTH_HEIGHT || self.height > MAX_WIDTH_HEIGHT {
             // Limit very large image sizes to avoid OOM issues. Images with these sizes are
             // unlikely to be valid anyhow.
-            return Err(ImageError::FormatError("Image too large".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "Image too large".to_string()));
         }
 
         if self.height == i32::min_value() {
-            return Err(ImageError::FormatError("Invalid height".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "Invalid height".to_string()));
         }
 
         // A negative height indicates a top-down DIB.
@@ -564,15 +592,15 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         // Number of planes (format specifies that this should be 1).
         if try!(self.r.read_u16::<LittleEndian>()) != 1 {
-            return Err(ImageError::FormatError("Invalid number of planes.".to_string()));
+            return Err(ImageError::malformed(ImageFormat::BMP, "Invalid number of planes.".to_string()));
         }
 
         self.bit_count = try!(self.r.read_u16::<LittleEndian>());
         let image_type_u32 = try!(self.r.read_u32::<LittleEndian>());
 
         // Top-down dibs can not be compressed.
-        if self.top_down && image_type_u32 != 0 && image_type_u32 != 3 {
-            return Err(ImageError::FormatError("Invalid image type for top-down image."
+        if self.top_down && image_type_u32 != 0 && image_type_u32 != 3 && image_type_u32 != 6 {
+            return Err(ImageError::malformed(ImageFormat::BMP, "Invalid image type for top-down image."
                                                .to_string()));
         }
         self.image_type = match image_type_u32 {
@@ -581,32 +609,36 @@ impl<R: Read + Seek> BMPDecoder<R> {
                 16 => ImageType::RGB16,
                 24 => ImageType::RGB24,
                 32 => if self.add_alpha_channel { ImageType::RGBA32 } else { ImageType::RGB32 },
-                _ => return Err(ImageError::FormatError("Invalid RGB bit count".to_string())),
+                _ => return Err(ImageError::malformed(ImageFormat::BMP, "Invalid RGB bit count".to_string())),
             },
             1 => match self.bit_count {
                 8 => ImageType::RLE8,
-                _ => return Err(ImageError::FormatError("Invalid RLE8 bit count".to_string())),
+                _ => return Err(ImageError::malformed(ImageFormat::BMP,
+                    "Invalid RLE8 bit count".to_string())),
             },
             2 => match self.bit_count {
                 4 => ImageType::RLE4,
-                _ => return Err(ImageError::FormatError("Invalid RLE4 bit count".to_string())),
+                _ => return Err(ImageError::malformed(ImageFormat::BMP,
+                    "Invalid RLE4 bit count".to_string())),
             },
-            3 => match self.bit_count {
+            // BI_BITFIELDS, or BI_ALPHABITFIELDS which adds an alpha mask to the info header.
+            3 | 6 => match self.bit_count {
                 16 => ImageType::Bitfields16,
                 32 => ImageType::Bitfields32,
-                _ => return Err(ImageError::FormatError("Invalid bitfields bit count".to_string())),
+                _ => return Err(ImageError::malformed(ImageFormat::BMP,
+                    "Invalid bitfields bit count".to_string())),
             },
             // PNG and JPEG not implemented yet.
-            _  => return Err(ImageError::UnsupportedError("Unsupported image type".to_string())),
+            _  => return Err(ImageError::unsupported(ImageFormat::BMP, "Unsupported image type".to_string())),
         };
 
         // The next 12 bytes represent data array size in bytes,
         // followed the horizontal and vertical printing resolutions
         // We will calculate the pixel array size using width & height of image
-        // We're not interesting the horz or vert printing resolutions
-        try!(self.r.read_u32::<LittleEndian>());
-        try!(self.r.read_u32::<LittleEndian>());
         try!(self.r.read_u32::<LittleEndian>());
+        let x_pixels_per_meter = try!(self.r.read_i32::<LittleEndian>());
+        let y_pixels_per_meter = try!(self.r.read_i32::<LittleEndian>());
+        self.pixels_per_meter = (x_pixels_per_meter, y_pixels_per_meter);
 
         self.colors_used = try!(self.r.read_u32::<LittleEndian>());
 
@@ -614,11 +646,20 @@ impl<R: Read + Seek> BMPDecoder<R> {
         // We're not interested in this value, so we'll skip it
         try!(self.r.read_u32::<LittleEndian>());
 
+        // The masks follow a BITMAPINFOHEADER, while later versions of the header hold them
+        // in the fields that come next.
+        match self.image_type {
+            ImageType::Bitfields16 | ImageType::Bitfields32 => try!(self.read_bitmasks(image_type_u32 == 6)),
+            _ => { }
+        };
+
         Ok(())
     }
 
 
-    fn read_bitmasks(&mut self) -> ImageResult<()> {
+    /// Read the channel masks of bitfield images, ```alpha``` tells whether an alpha mask is
+    /// present even though the header is a BITMAPINFOHEADER.
+    fn read_bitmasks(&mut self, alpha: bool) -> ImageResult<()> {
         let r_mask = try!(self.r.read_u32::<LittleEndian>());
         let g_mask = try!(self.r.read_u32::<LittleEndian>());
         let b_mask = try!(self.r.read_u32::<LittleEndian>());
@@ -627,6 +668,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
             BMPHeaderType::V3Header | BMPHeaderType::V4Header | BMPHeaderType::V5Header => {
                 try!(self.r.read_u32::<LittleEndian>())
             },
+            _ if alpha => try!(self.r.read_u32::<LittleEndian>()),
             _ => 0
         };
 
@@ -657,7 +699,8 @@ impl<R: Read + Seek> BMPDecoder<R> {
                 BITMAPV3HEADER_SIZE => BMPHeaderType::V3Header,
                 BITMAPV4HEADER_SIZE => BMPHeaderType::V4Header,
                 BITMAPV5HEADER_SIZE => BMPHeaderType::V5Header,
-                _ => return Err(ImageError::UnsupportedError("Unsupported Bitmap Header".to_string()))
+                _ => return Err(ImageError::unsupported(ImageFormat::BMP,
+                    "Unsupported Bitmap Header".to_string()))
             };
 
             match self.bmp_header_type {
@@ -669,12 +712,9 @@ impl<R: Read + Seek> BMPDecoder<R> {
                 }
             };
 
-            match self.image_type {
-                ImageType::Bitfields16 | ImageType::Bitfields32 => try!(self.read_bitmasks()),
-                _ => { }
-            };
-
-            try!(self.r.seek(SeekFrom::Start(bmp_header_end)));
+            // Masks following a BITMAPINFOHEADER have been read past the end of the header.
+            let header_end = cmp::max(bmp_header_end, try!(self.r.seek(SeekFrom::Current(0))));
+            try!(self.r.seek(SeekFrom::Start(header_end)));
 
             match self.image_type {
                 ImageType::Palette | ImageType::RLE4 | ImageType::RLE8 => try!(self.read_palette()),
@@ -709,7 +749,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
             0 => Ok(1 << self.bit_count),
             _ => {
                 if self.colors_used > 1 << self.bit_count {
-                    return Err(ImageError::FormatError(format!(
+                    return Err(ImageError::malformed(ImageFormat::BMP, format!(
                         "Palette size {} exceeds maximum size for BMP with bit count of {}",
                         self.colors_used, self.bit_count
                     )))
@@ -792,7 +832,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
         }
     }
 
-    fn read_palettized_pixel_data(&mut self) -> ImageResult<Vec<u8>> {
+    fn read_palettized_pixel_data(&mut self, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
         let mut pixel_data = self.create_pixel_data();
         let num_channels = self.num_channels();
         let row_byte_length = ((self.bit_count as u32 * self.width as u32 + 31) / 32 * 4) as usize;
@@ -804,7 +844,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         try!(reader.seek(SeekFrom::Start(self.data_offset)));
 
-        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, |row| {
+        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, monitor, |row| {
             try!(reader.read_exact(&mut indices));
             let mut pixel_iter = row.chunks_mut(num_channels);
             match bit_count {
@@ -819,7 +859,8 @@ impl<R: Read + Seek> BMPDecoder<R> {
         Ok(pixel_data)
     }
 
-    fn read_16_bit_pixel_data(&mut self, bitfields: Option<&Bitfields>) -> ImageResult<Vec<u8>> {
+    fn read_16_bit_pixel_data(&mut self, bitfields: Option<&Bitfields>, monitor: &mut Monitor)
+                              -> ImageResult<Vec<u8>> {
         let mut pixel_data = self.create_pixel_data();
         let num_channels = self.num_channels();
         let row_padding_len = self.width as usize % 2 * 2;
@@ -832,7 +873,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         try!(reader.seek(SeekFrom::Start(self.data_offset)));
 
-        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, |row| {
+        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, monitor, |row| {
             for pixel in row.chunks_mut(num_channels) {
                 let data = try!(reader.read_u16::<LittleEndian>()) as u32;
 
@@ -850,7 +891,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
     }
 
     /// Read image data from a reader in 32-bit formats that use bitfields.
-    fn read_32_bit_pixel_data(&mut self) -> ImageResult<Vec<u8>> {
+    fn read_32_bit_pixel_data(&mut self, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
         let mut pixel_data = self.create_pixel_data();
         let num_channels = self.num_channels();
 
@@ -859,7 +900,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
         let reader = &mut self.r;
         try!(reader.seek(SeekFrom::Start(self.data_offset)));
 
-        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, |row| {
+        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, monitor, |row| {
             for pixel in row.chunks_mut(num_channels) {
                 let data = try!(reader.read_u32::<LittleEndian>());
 
@@ -877,7 +918,8 @@ impl<R: Read + Seek> BMPDecoder<R> {
     }
 
     /// Read image data from a reader where the colours are stored as 8-bit values (24 or 32-bit).
-    fn read_full_byte_pixel_data(&mut self, format: FormatFullBytes) -> ImageResult<Vec<u8>> {
+    fn read_full_byte_pixel_data(&mut self, format: FormatFullBytes, monitor: &mut Monitor)
+                                 -> ImageResult<Vec<u8>> {
         let mut pixel_data = self.create_pixel_data();
         let num_channels = self.num_channels();
         let row_padding_len = match format {
@@ -890,7 +932,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
 
         let reader = &mut self.r;
 
-        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, |row| {
+        try!(with_rows(&mut pixel_data, self.width, self.height, num_channels, self.top_down, monitor, |row| {
             for pixel in row.chunks_mut(num_channels) {
 
                 if format == FormatFullBytes::Format888 {
@@ -918,13 +960,17 @@ impl<R: Read + Seek> BMPDecoder<R> {
         Ok(pixel_data)
     }
 
-    fn read_rle_data(&mut self, image_type: ImageType) -> ImageResult<Vec<u8>> {
+    /// Reads run length encoded image data, whose rows are only reported to ```monitor``` once
+    /// the image has been read as deltas may skip them
+    fn read_rle_data(&mut self, image_type: ImageType, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
+        try!(monitor.update(0, self.height as u64));
         // Seek to the start of the actual image data.
         try!(self.r.seek(SeekFrom::Start(self.data_offset)));
 
         let full_image_size = try!(
             num_bytes(self.width, self.height, self.num_channels())
-                .ok_or_else(|| ImageError::FormatError("Image buffer would be too large!".to_owned()))
+                .ok_or_else(|| ImageError::malformed(ImageFormat::BMP,
+                    "Image buffer would be too large!".to_owned()))
         );
         let mut pixel_data = self.create_pixel_data();
         let (skip_pixels, skip_rows, eof_hit) = try!(self.read_rle_data_step(&mut pixel_data, image_type, 0, 0));
@@ -937,11 +983,13 @@ impl<R: Read + Seek> BMPDecoder<R> {
             let new = extend_buffer(&mut pixel_data, full_image_size, true);
             try!(self.read_rle_data_step(new, image_type, skip_pixels, skip_rows));
         }
+        try!(monitor.update(self.height as u64, self.height as u64));
         Ok(pixel_data)
     }
 
-    fn read_rle_data_step(&mut self, mut pixel_data: &mut [u8], image_type: ImageType, skip_pixels: u8, skip_rows: u8) -> ImageResult<(u8, u8, bool)> {
+    fn read_rle_data_step(&mut self, mut pixel_data: &mut [u8], image_type: ImageType, skip_pixels: usize, skip_rows: usize) -> ImageResult<(usize, usize, bool)> {
         let num_channels = self.num_channels();
+        let width = self.width as usize;
 
         let mut delta_rows_left = 0;
         let mut delta_pixels_left = skip_pixels;
@@ -956,14 +1004,14 @@ impl<R: Read + Seek> BMPDecoder<R> {
             let mut row_iter = self.rows(&mut pixel_data);
             // If we have previously hit a delta value,
             // blank the rows that are to be skipped.
-            blank_bytes((&mut row_iter).take(skip_rows.into()));
+            blank_bytes((&mut row_iter).take(skip_rows));
             let mut insns_iter = RLEInsnIterator{ r: &mut self.r, image_type: image_type };
             let p = self.palette.as_ref().unwrap();
 
             'row_loop: while let Some(row) = row_iter.next() {
                 let mut pixel_iter = row.chunks_mut(num_channels);
                 // Blank delta skipped pixels if any.
-                blank_bytes((&mut pixel_iter).take(delta_pixels_left.into()));
+                blank_bytes((&mut pixel_iter).take(delta_pixels_left));
                 delta_pixels_left = 0;
 
                 'rle_loop: loop {
@@ -980,35 +1028,41 @@ impl<R: Read + Seek> BMPDecoder<R> {
                                 break 'rle_loop;
                             },
                             RLEInsn::Delta(x_delta, y_delta) => {
+                                // The msdn site on bitmap compression doesn't specify
+                                // what happens to the values skipped when encountering
+                                // a delta code, however IE and the windows image
+                                // preview seems to replace them with black pixels,
+                                // so we stick to that.
                                 if y_delta > 0 {
-                                    for n in 1..y_delta {
+                                    // The delta moves down from the current column, so the
+                                    // horizontal position carries over to the new row.
+                                    let x = width - pixel_iter.len() + x_delta as usize;
+                                    blank_bytes(pixel_iter);
+                                    for n in 1..y_delta as usize {
                                         if let Some(row) = row_iter.next() {
-                                            // The msdn site on bitmap compression doesn't specify
-                                            // what happens to the values skipped when encountering
-                                            // a delta code, however IE and the windows image
-                                            // preview seems to replace them with black pixels,
-                                            // so we stick to that.
                                             for b in row {
                                                 *b = 0;
                                             }
                                         } else {
-                                            delta_pixels_left = x_delta;
                                             // We've reached the end of the buffer.
-                                            delta_rows_left = y_delta - n;
+                                            delta_pixels_left = x;
+                                            delta_rows_left = y_delta as usize - n;
                                             break 'row_loop;
                                         }
                                     }
-                                }
-
-                                for _ in 0..x_delta {
-                                    if let Some(pixel) = pixel_iter.next() {
-                                        for b in pixel {
-                                            *b = 0;
+                                    match row_iter.next() {
+                                        Some(row) => {
+                                            pixel_iter = row.chunks_mut(num_channels);
+                                            blank_bytes((&mut pixel_iter).take(x));
+                                        },
+                                        None => {
+                                            delta_pixels_left = x;
+                                            break 'row_loop;
                                         }
-                                    } else {
-                                        // We can't go any further in this row.
-                                        break;
                                     }
+                                } else {
+                                    // We can't go any further than the end of this row.
+                                    blank_bytes((&mut pixel_iter).take(x_delta as usize));
                                 }
                             },
                             RLEInsn::Absolute(length, indices) => {
@@ -1062,7 +1116,7 @@ impl<R: Read + Seek> BMPDecoder<R> {
                         }
                     } else {
                         // We ran out of data while we still had rows to fill in.
-                        return Err(ImageError::FormatError("Not enough RLE data".to_string()))
+                        return Err(ImageError::malformed(ImageFormat::BMP, "Not enough RLE data".to_string()))
                     }
                 }
             }
@@ -1071,28 +1125,31 @@ impl<R: Read + Seek> BMPDecoder<R> {
         Ok((delta_pixels_left, delta_rows_left, eof_hit))
     }
 
-    fn read_image_data(&mut self) -> ImageResult<Vec<u8>> {
+    /// Reads the image data, reporting the rows read to ```monitor```
+    fn read_image_data(&mut self, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
         match self.image_type {
-            ImageType::Palette => self.read_palettized_pixel_data(),
-            ImageType::RGB16 => self.read_16_bit_pixel_data(Some(&R5_G5_B5_COLOR_MASK)),
-            ImageType::RGB24 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGB24),
-            ImageType::RGB32 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGB32),
-            ImageType::RGBA32 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGBA32),
-            ImageType::RLE8 => self.read_rle_data(ImageType::RLE8),
-            ImageType::RLE4 => self.read_rle_data(ImageType::RLE4),
+            ImageType::Palette => self.read_palettized_pixel_data(monitor),
+            ImageType::RGB16 => self.read_16_bit_pixel_data(Some(&R5_G5_B5_COLOR_MASK), monitor),
+            ImageType::RGB24 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGB24, monitor),
+            ImageType::RGB32 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGB32, monitor),
+            ImageType::RGBA32 => self.read_full_byte_pixel_data(FormatFullBytes::FormatRGBA32, monitor),
+            ImageType::RLE8 => self.read_rle_data(ImageType::RLE8, monitor),
+            ImageType::RLE4 => self.read_rle_data(ImageType::RLE4, monitor),
             ImageType::Bitfields16 => {
                 match self.bitfields {
-                    Some(_) => self.read_16_bit_pixel_data(None),
-                    None => Err(ImageError::FormatError("Missing 16-bit bitfield masks".to_string()))
+                    Some(_) => self.read_16_bit_pixel_data(None, monitor),
+                    None => Err(ImageError::malformed(ImageFormat::BMP,
+                        "Missing 16-bit bitfield masks".to_string()))
                 }
             },
             ImageType::Bitfields32 => {
                 match self.bitfields {
                     Some(R8_G8_B8_COLOR_MASK) => {
-                        self.read_full_byte_pixel_data(FormatFullBytes::Format888)
+                        self.read_full_byte_pixel_data(FormatFullBytes::Format888, monitor)
                     },
-                    Some(_) => self.read_32_bit_pixel_data(),
-                    None => Err(ImageError::FormatError("Missing 32-bit bitfield masks".to_string()))
+                    Some(_) => self.read_32_bit_pixel_data(monitor),
+                    None => Err(ImageError::malformed(ImageFormat::BMP,
+                        "Missing 32-bit bitfield masks".to_string()))
                 }
             },
         }
@@ -1116,22 +1173,164 @@ impl<R: Read + Seek> ImageDecoder for BMPDecoder<R> {
 
     fn row_len(&mut self) -> ImageResult<usize> {
         try!(self.read_metadata());
-        Ok(3 * self.width as usize)
+        Ok(self.num_channels() * self.width as usize)
+    }
+
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.dpi = match self.pixels_per_meter {
+            (x, y) if x > 0 && y > 0 => Some((x as f32 * 0.0254, y as f32 * 0.0254)),
+            _ => None,
+        };
+        Ok(metadata)
     }
 
-    fn read_scanline(&mut self, _buf: &mut [u8]) -> ImageResult<u32> {
-        unimplemented!();
+    /// Reads the next row of the image into ```buf```.
+    ///
+    /// Bitmaps are usually stored bottom up, so the whole image is decoded when the first row
+    /// is read.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        try!(self.read_metadata());
+        if self.row == self.height as u32 {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        if self.rows.is_none() {
+            self.rows = Some(try!(self.read_image_data(&mut Monitor::new())));
+        }
+        let start = self.row as usize * len;
+        buf[..len].copy_from_slice(&self.rows.as_ref().unwrap()[start..start + len]);
+        self.row += 1;
+        Ok(self.row - 1)
     }
 
     fn read_image(&mut self) -> ImageResult<DecodingResult> {
         try!(self.read_metadata());
-        self.read_image_data().map(DecodingResult::U8)
+        self.read_image_data(&mut Monitor::new()).map(DecodingResult::U8)
+    }
+
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        try!(self.read_metadata());
+        self.read_image_data(monitor).map(DecodingResult::U8)
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::Bitfield;
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+    use byteorder::{WriteBytesExt, LittleEndian};
+
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+    use super::{Bitfield, BMPDecoder};
+
+    /// Builds a run-length encoded bmp with a palette of 16 grays, where index ```i``` is
+    /// ```i * 16 + 8``` so that pixels skipped by deltas can be told apart
+    fn rle_bmp(bit_count: u16, width: i32, height: i32, data: &[u8]) -> Vec<u8> {
+        let mut bmp = b"BM".to_vec();
+        bmp.write_u32::<LittleEndian>(118 + data.len() as u32).unwrap();
+        bmp.write_u32::<LittleEndian>(0).unwrap();
+        bmp.write_u32::<LittleEndian>(118).unwrap();
+        bmp.write_u32::<LittleEndian>(40).unwrap();
+        bmp.write_i32::<LittleEndian>(width).unwrap();
+        bmp.write_i32::<LittleEndian>(height).unwrap();
+        bmp.write_u16::<LittleEndian>(1).unwrap();
+        bmp.write_u16::<LittleEndian>(bit_count).unwrap();
+        bmp.write_u32::<LittleEndian>(if bit_count == 8 { 1 } else { 2 }).unwrap();
+        bmp.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+        bmp.write_u32::<LittleEndian>(2835).unwrap();
+        bmp.write_u32::<LittleEndian>(2835).unwrap();
+        bmp.write_u32::<LittleEndian>(16).unwrap();
+        bmp.write_u32::<LittleEndian>(0).unwrap();
+        for i in 0..16 {
+            let gray = i * 16 + 8;
+            bmp.extend_from_slice(&[gray, gray, gray, 0]);
+        }
+        bmp.extend_from_slice(data);
+        bmp
+    }
+
+    /// Decodes ```bmp``` and returns the red channel of its pixels
+    fn decode_red(bmp: Vec<u8>) -> Vec<u8> {
+        match BMPDecoder::new(Cursor::new(bmp)).read_image().unwrap() {
+            DecodingResult::U8(data) => data.chunks(3).map(|p| p[0]).collect(),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    /// Builds a single row bitfields bmp with a BITMAPINFOHEADER followed by ```masks```
+    fn bitfields_bmp(compression: u32, masks: &[u32], pixels: &[u16]) -> Vec<u8> {
+        let data_offset = 54 + 4 * masks.len() as u32;
+        let mut bmp = b"BM".to_vec();
+        bmp.write_u32::<LittleEndian>(data_offset + 4).unwrap();
+        bmp.write_u32::<LittleEndian>(0).unwrap();
+        bmp.write_u32::<LittleEndian>(data_offset).unwrap();
+        for &field in &[40, pixels.len() as u32, 1, 1 | 16 << 16, compression, 4, 2835, 2835, 0, 0] {
+            bmp.write_u32::<LittleEndian>(field).unwrap();
+        }
+        for &mask in masks {
+            bmp.write_u32::<LittleEndian>(mask).unwrap();
+        }
+        for &pixel in pixels {
+            bmp.write_u16::<LittleEndian>(pixel).unwrap();
+        }
+        bmp
+    }
+
+    #[test]
+    fn test_bitfields() {
+        let mut decoder = BMPDecoder::new(Cursor::new(
+            bitfields_bmp(3, &[0xf800, 0x07e0, 0x001f], &[0xf800, 0x07ff])));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![255, 0, 0, 0, 255, 255]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+
+        // BI_ALPHABITFIELDS adds an alpha mask after the color masks
+        let mut decoder = BMPDecoder::new(Cursor::new(
+            bitfields_bmp(6, &[0x7c00, 0x03e0, 0x001f, 0x8000], &[0xfc00, 0x03e0])));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![255, 0, 0, 255, 0, 255, 0, 0]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn test_rle8() {
+        let data = [
+            // A single pixel and an absolute run padded to a whole word, then the end of the row
+            1, 1, 0, 3, 1, 2, 3, 0, 0, 0,
+            // One pixel, then a delta one pixel right and one row up from the second column
+            1, 4, 0, 2, 1, 1,
+            2, 5, 0, 1,
+        ];
+        assert_eq!(decode_red(rle_bmp(8, 4, 3, &data)), vec![
+            0, 0, 88, 88,
+            72, 0, 0, 0,
+            24, 24, 40, 56,
+        ]);
+    }
+
+    #[test]
+    fn test_rle4() {
+        let data = [
+            // An odd run alternating between two indices
+            5, 0x12, 0, 0,
+            // An odd absolute run padded to a whole word, then a delta and the end of the file
+            0, 3, 0x34, 0x50, 0, 2, 1, 0, 1, 0x66, 0, 1,
+        ];
+        assert_eq!(decode_red(rle_bmp(4, 5, 2, &data)), vec![
+            56, 72, 88, 0, 104,
+            24, 40, 24, 40, 24,
+        ]);
+    }
 
     #[test]
     fn test_bitfield_len() {
@@ -1147,4 +1346,27 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn test_scanlines() {
+        // Stored bottom up and top down
+        for name in &["Core_8_Bit", "Info_1_Bit_Top_Down", "rgba32"] {
+            let mut bmp = Vec::new();
+            File::open(format!("tests/images/bmp/images/{}.bmp", name)).unwrap().read_to_end(&mut bmp).unwrap();
+            let image = match BMPDecoder::new(Cursor::new(&bmp)).read_image().unwrap() {
+                DecodingResult::U8(data) => data,
+                _ => panic!("Image did not decode as 8-bit"),
+            };
+
+            let mut decoder = BMPDecoder::new(Cursor::new(&bmp));
+            let (_, height) = decoder.dimensions().unwrap();
+            let mut row = vec![0; decoder.row_len().unwrap()];
+            assert_eq!(image.len(), row.len() * height as usize);
+            for (y, expected) in image.chunks(row.len()).enumerate() {
+                assert_eq!(decoder.read_scanline(&mut row).unwrap(), y as u32);
+                assert_eq!(&row[..], expected);
+            }
+            assert!(decoder.read_scanline(&mut row).is_err());
+        }
+    }
 }
@@ -2,6 +2,7 @@ use std::io::{self, Write};
 use byteorder::{WriteBytesExt, LittleEndian};
 
 use color;
+use image::{ImageEncoder, ImageResult};
 
 /// The representation of a BMP encoder.
 pub struct BMPEncoder<'a, W: 'a> {
@@ -131,6 +132,12 @@ impl<'a, W: Write + 'a> BMPEncoder<'a, W> {
     }
 }
 
+impl<'a, W: Write + 'a> ImageEncoder for BMPEncoder<'a, W> {
+    fn write_image(mut self, buf: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
 fn get_unsupported_error_message(c: &color::ColorType) -> String {
     format!("Unsupported color type {:?}.  Supported types: RGB(8), RGBA(8), Gray(8), GrayA(8).", c)
 }
@@ -1,14 +1,18 @@
+use std::iter::Take;
 use std::slice::{ Chunks, ChunksMut };
 use std::ops::{ Deref, DerefMut, Index, IndexMut };
 use std::marker::PhantomData;
+use std::cmp;
+use std::mem;
 use std::path::Path;
 use std::io;
-use num_traits::Zero;
+use num_traits::{ ToPrimitive, Zero };
 
 use traits::Primitive;
 use color::{ Rgb, Rgba, Luma, LumaA, FromColor, ColorType };
-use image::GenericImage;
+use image::{GenericImage, GenericImageView, ImageError, ImageResult, ParameterErrorKind};
 use dynimage::save_buffer;
+use flat::{FlatSamples, SampleLayout};
 use utils::expand_packed;
 
 /// A generalized pixel.
@@ -105,7 +109,13 @@ pub trait Pixel: Copy + Clone {
 
 /// Iterate over pixel refs.
 pub struct Pixels<'a, P: Pixel + 'a> where P::Subpixel: 'a {
-    chunks: Chunks<'a, P::Subpixel>
+    /// The rows not reached yet from either end, including the padding after them
+    rows: Take<Chunks<'a, P::Subpixel>>,
+    /// The pixels left of the rows reached from the front and from the back
+    front: Chunks<'a, P::Subpixel>,
+    back: Chunks<'a, P::Subpixel>,
+    /// The number of samples of the pixels of a row
+    row_len: usize,
 }
 
 impl<'a, P: Pixel + 'a> Iterator for Pixels<'a, P> where P::Subpixel: 'a {
@@ -113,9 +123,15 @@ impl<'a, P: Pixel + 'a> Iterator for Pixels<'a, P> where P::Subpixel: 'a {
 
     #[inline(always)]
     fn next(&mut self) -> Option<&'a P> {
-        self.chunks.next().map(|v|
-            <P as Pixel>::from_slice(v)
-        )
+        loop {
+            if let Some(v) = self.front.next() {
+                return Some(<P as Pixel>::from_slice(v))
+            }
+            match self.rows.next() {
+                Some(row) => self.front = row[..self.row_len].chunks(<P as Pixel>::channel_count() as usize),
+                None => return self.back.next().map(|v| <P as Pixel>::from_slice(v)),
+            }
+        }
     }
 }
 
@@ -123,15 +139,27 @@ impl<'a, P: Pixel + 'a> DoubleEndedIterator for Pixels<'a, P> where P::Subpixel:
 
     #[inline(always)]
     fn next_back(&mut self) -> Option<&'a P> {
-        self.chunks.next_back().map(|v|
-            <P as Pixel>::from_slice(v)
-        )
+        loop {
+            if let Some(v) = self.back.next_back() {
+                return Some(<P as Pixel>::from_slice(v))
+            }
+            match self.rows.next_back() {
+                Some(row) => self.back = row[..self.row_len].chunks(<P as Pixel>::channel_count() as usize),
+                None => return self.front.next_back().map(|v| <P as Pixel>::from_slice(v)),
+            }
+        }
     }
 }
 
 /// Iterate over mutable pixel refs.
 pub struct PixelsMut<'a, P: Pixel + 'a> where P::Subpixel: 'a {
-    chunks: ChunksMut<'a, P::Subpixel>
+    /// The rows not reached yet from either end, including the padding after them
+    rows: Take<ChunksMut<'a, P::Subpixel>>,
+    /// The pixels left of the rows reached from the front and from the back
+    front: ChunksMut<'a, P::Subpixel>,
+    back: ChunksMut<'a, P::Subpixel>,
+    /// The number of samples of the pixels of a row
+    row_len: usize,
 }
 
 impl<'a, P: Pixel + 'a> Iterator for PixelsMut<'a, P> where P::Subpixel: 'a {
@@ -139,18 +167,36 @@ impl<'a, P: Pixel + 'a> Iterator for PixelsMut<'a, P> where P::Subpixel: 'a {
 
     #[inline(always)]
     fn next(&mut self) -> Option<&'a mut P> {
-        self.chunks.next().map(|v|
-            <P as Pixel>::from_slice_mut(v)
-        )
+        loop {
+            if let Some(v) = self.front.next() {
+                return Some(<P as Pixel>::from_slice_mut(v))
+            }
+            match self.rows.next() {
+                Some(row) => {
+                    let (pixels, _) = row.split_at_mut(self.row_len);
+                    self.front = pixels.chunks_mut(<P as Pixel>::channel_count() as usize);
+                }
+                None => return self.back.next().map(|v| <P as Pixel>::from_slice_mut(v)),
+            }
+        }
     }
 }
 
 impl<'a, P: Pixel + 'a> DoubleEndedIterator for PixelsMut<'a, P> where P::Subpixel: 'a {
     #[inline(always)]
     fn next_back(&mut self) -> Option<&'a mut P> {
-        self.chunks.next_back().map(|v|
-            <P as Pixel>::from_slice_mut(v)
-        )
+        loop {
+            if let Some(v) = self.back.next_back() {
+                return Some(<P as Pixel>::from_slice_mut(v))
+            }
+            match self.rows.next_back() {
+                Some(row) => {
+                    let (pixels, _) = row.split_at_mut(self.row_len);
+                    self.back = pixels.chunks_mut(<P as Pixel>::channel_count() as usize);
+                }
+                None => return self.front.next_back().map(|v| <P as Pixel>::from_slice_mut(v)),
+            }
+        }
     }
 }
 
@@ -206,11 +252,183 @@ impl<'a, P: Pixel + 'a> Iterator for EnumeratePixelsMut<'a, P> where P::Subpixel
     }
 }
 
+/// Iterate over the rows of an image, each of them an iterator over its pixel refs.
+pub struct Rows<'a, P: Pixel + 'a> where P::Subpixel: 'a {
+    /// The rows not reached yet from either end, including the padding after them
+    rows: Take<Chunks<'a, P::Subpixel>>,
+    /// The number of samples of the pixels of a row
+    row_len: usize,
+}
+
+impl<'a, P: Pixel + 'a> Rows<'a, P> where P::Subpixel: 'a {
+    fn pixels(&self, row: &'a [P::Subpixel]) -> Pixels<'a, P> {
+        Pixels {
+            rows: [].chunks(1).take(0),
+            front: row[..self.row_len].chunks(<P as Pixel>::channel_count() as usize),
+            back: [].chunks(1),
+            row_len: self.row_len,
+        }
+    }
+}
+
+impl<'a, P: Pixel + 'a> Iterator for Rows<'a, P> where P::Subpixel: 'a {
+    type Item = Pixels<'a, P>;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<Pixels<'a, P>> {
+        self.rows.next().map(|row| self.pixels(row))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl<'a, P: Pixel + 'a> DoubleEndedIterator for Rows<'a, P> where P::Subpixel: 'a {
+    #[inline(always)]
+    fn next_back(&mut self) -> Option<Pixels<'a, P>> {
+        self.rows.next_back().map(|row| self.pixels(row))
+    }
+}
+
+impl<'a, P: Pixel + 'a> ExactSizeIterator for Rows<'a, P> where P::Subpixel: 'a {}
+
+/// Iterate over the rows of an image, each of them an iterator over its mutable pixel refs.
+pub struct RowsMut<'a, P: Pixel + 'a> where P::Subpixel: 'a {
+    /// The rows not reached yet from either end, including the padding after them
+    rows: Take<ChunksMut<'a, P::Subpixel>>,
+    /// The number of samples of the pixels of a row
+    row_len: usize,
+}
+
+impl<'a, P: Pixel + 'a> RowsMut<'a, P> where P::Subpixel: 'a {
+    fn pixels(&self, row: &'a mut [P::Subpixel]) -> PixelsMut<'a, P> {
+        let (pixels, _) = row.split_at_mut(self.row_len);
+        PixelsMut {
+            rows: [].chunks_mut(1).take(0),
+            front: pixels.chunks_mut(<P as Pixel>::channel_count() as usize),
+            back: [].chunks_mut(1),
+            row_len: self.row_len,
+        }
+    }
+}
+
+impl<'a, P: Pixel + 'a> Iterator for RowsMut<'a, P> where P::Subpixel: 'a {
+    type Item = PixelsMut<'a, P>;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<PixelsMut<'a, P>> {
+        match self.rows.next() {
+            Some(row) => Some(self.pixels(row)),
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+impl<'a, P: Pixel + 'a> DoubleEndedIterator for RowsMut<'a, P> where P::Subpixel: 'a {
+    #[inline(always)]
+    fn next_back(&mut self) -> Option<PixelsMut<'a, P>> {
+        match self.rows.next_back() {
+            Some(row) => Some(self.pixels(row)),
+            None => None,
+        }
+    }
+}
+
+impl<'a, P: Pixel + 'a> ExactSizeIterator for RowsMut<'a, P> where P::Subpixel: 'a {}
+
+/// Enumerate the rows of an image.
+pub struct EnumerateRows<'a, P: Pixel + 'a> where <P as Pixel>::Subpixel: 'a {
+    rows:  Rows<'a, P>,
+    y:     u32,
+    width: u32
+}
+
+impl<'a, P: Pixel + 'a> Iterator for EnumerateRows<'a, P> where P::Subpixel: 'a {
+    type Item = (u32, EnumeratePixels<'a, P>);
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<(u32, EnumeratePixels<'a, P>)> {
+        let y = self.y;
+        self.y += 1;
+        self.rows.next().map(|pixels| (y, EnumeratePixels { pixels: pixels, x: 0, y: y, width: self.width }))
+    }
+}
+
+/// Enumerate the rows of an image.
+pub struct EnumerateRowsMut<'a, P: Pixel + 'a> where <P as Pixel>::Subpixel: 'a {
+    rows:  RowsMut<'a, P>,
+    y:     u32,
+    width: u32
+}
+
+impl<'a, P: Pixel + 'a> Iterator for EnumerateRowsMut<'a, P> where P::Subpixel: 'a {
+    type Item = (u32, EnumeratePixelsMut<'a, P>);
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<(u32, EnumeratePixelsMut<'a, P>)> {
+        let y = self.y;
+        self.y += 1;
+        let width = self.width;
+        self.rows.next().map(|pixels| (y, EnumeratePixelsMut { pixels: pixels, x: 0, y: y, width: width }))
+    }
+}
+
+/// Iterate over the tiles of an image, left to right and top to bottom.
+///
+/// Each tile is a view into the buffer of the image. The tiles at the right and bottom edges are
+/// cut to the image.
+pub struct Tiles<'a, P: Pixel + 'a> where P::Subpixel: 'a {
+    data: &'a [P::Subpixel],
+    width: u32,
+    height: u32,
+    stride: usize,
+    tile_width: u32,
+    tile_height: u32,
+    x: u32,
+    y: u32,
+}
+
+impl<'a, P: Pixel + 'a> Iterator for Tiles<'a, P> where P::Subpixel: 'a {
+    type Item = (u32, u32, ImageBuffer<P, &'a [P::Subpixel]>);
+
+    fn next(&mut self) -> Option<(u32, u32, ImageBuffer<P, &'a [P::Subpixel]>)> {
+        if self.x >= self.width || self.y >= self.height {
+            return None
+        }
+        let (x, y) = (self.x, self.y);
+        let start = y as usize * self.stride + x as usize * <P as Pixel>::channel_count() as usize;
+        let tile = ImageBuffer {
+            data: &self.data[start..],
+            width: cmp::min(self.tile_width, self.width - x),
+            height: cmp::min(self.tile_height, self.height - y),
+            stride: self.stride,
+            _phantom: PhantomData,
+        };
+        self.x = self.x.saturating_add(self.tile_width);
+        if self.x >= self.width {
+            self.x = 0;
+            self.y = self.y.saturating_add(self.tile_height);
+        }
+        Some((x, y, tile))
+    }
+}
+
 /// Generic image buffer
+///
+/// The rows of the image follow each other in the container, `stride` samples apart. Rows are
+/// usually packed, but buffers constructed with `from_raw_with_stride` can wrap memory whose rows
+/// are padded, like the pitch of a framebuffer or of a GPU texture.
 #[derive(Debug)]
 pub struct ImageBuffer<P: Pixel, Container> {
     width: u32,
     height: u32,
+    /// The number of samples from the start of a row to the start of the next one
+    stride: usize,
     _phantom: PhantomData<P>,
     data: Container,
 }
@@ -227,18 +445,56 @@ where P: Pixel + 'static,
     /// Returns None if the container is not big enough
     pub fn from_raw(width: u32, height: u32, buf: Container)
                     -> Option<ImageBuffer<P, Container>> {
-        if width as usize
-           * height as usize
-           * <P as Pixel>::channel_count() as usize
-           <= buf.len() {
-            Some(ImageBuffer {
+        ImageBuffer::try_from_raw(width, height, buf).ok()
+    }
+
+    /// Contructs a buffer from a generic container, like `from_raw`
+    ///
+    /// Fails with an error stating the number of samples needed if the container is not big enough.
+    pub fn try_from_raw(width: u32, height: u32, buf: Container)
+                        -> ImageResult<ImageBuffer<P, Container>> {
+        let stride = width as usize * <P as Pixel>::channel_count() as usize;
+        ImageBuffer::try_from_raw_with_stride(width, height, stride, buf)
+    }
+
+    /// Contructs a buffer from a generic container whose rows start ```stride``` samples apart
+    ///
+    /// The samples between the end of a row and the start of the next one are padding, which is
+    /// neither read nor written by the methods of the buffer. The last row needs no padding.
+    ///
+    /// Returns None if ```stride``` is shorter than a row or the container is not big enough
+    pub fn from_raw_with_stride(width: u32, height: u32, stride: usize, buf: Container)
+                                -> Option<ImageBuffer<P, Container>> {
+        ImageBuffer::try_from_raw_with_stride(width, height, stride, buf).ok()
+    }
+
+    /// Contructs a buffer from a generic container whose rows start ```stride``` samples apart,
+    /// like `from_raw_with_stride`
+    ///
+    /// Fails with an error describing the mismatch if ```stride``` is shorter than a row or the
+    /// container is not big enough.
+    pub fn try_from_raw_with_stride(width: u32, height: u32, stride: usize, buf: Container)
+                                    -> ImageResult<ImageBuffer<P, Container>> {
+        let row_len = width as usize * <P as Pixel>::channel_count() as usize;
+        if stride < row_len {
+            return Err(ImageError::Parameter(ParameterErrorKind::Generic(format!(
+                "a stride of {} samples is shorter than the {} samples of a row", stride, row_len))))
+        }
+        let len = match height {
+            0 => Some(0),
+            _ => stride.checked_mul(height as usize - 1).and_then(|len| len.checked_add(row_len)),
+        };
+        match len {
+            Some(len) if len <= buf.len() => Ok(ImageBuffer {
                 data: buf,
                 width: width,
                 height: height,
+                stride: stride,
                 _phantom: PhantomData,
-            })
-        } else {
-            None
+            }),
+            Some(len) => Err(ImageError::Parameter(ParameterErrorKind::Generic(format!(
+                "{}x{} pixels need {} samples, the buffer holds {}", width, height, len, buf.len())))),
+            None => Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch)),
         }
     }
 
@@ -262,15 +518,104 @@ where P: Pixel + 'static,
         self.height
     }
 
+    /// The number of samples from the start of a row to the start of the next one
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
     /// Returns an iterator over the pixels of this image.
     pub fn pixels(&self) -> Pixels<P> {
         Pixels {
-            chunks: self.data.chunks(
-                <P as Pixel>::channel_count() as usize
-            )
+            rows: self.data.chunks(cmp::max(self.stride, 1)).take(self.height as usize),
+            front: [].chunks(1),
+            back: [].chunks(1),
+            row_len: self.row_len(),
         }
     }
 
+    /// Returns an iterator over the rows of this image, top to bottom.
+    ///
+    /// Each row is an iterator over its pixels, left to right.
+    pub fn rows(&self) -> Rows<P> {
+        Rows {
+            rows: self.data.chunks(cmp::max(self.stride, 1)).take(self.height as usize),
+            row_len: self.row_len(),
+        }
+    }
+
+    /// Enumerates over the rows of the image.
+    /// The iterator yields the y coordinate of each row along with
+    /// an enumeration of its pixels.
+    pub fn enumerate_rows(&self) -> EnumerateRows<P> {
+        EnumerateRows {
+            rows: self.rows(),
+            y: 0,
+            width: self.width
+        }
+    }
+
+    /// Returns an iterator over the tiles of ```tile_width``` by ```tile_height``` pixels of this
+    /// image, along with the coordinates of their top left pixel.
+    ///
+    /// The tiles are views into this image, which borrow its buffer. The tiles at the right and
+    /// bottom edges are smaller if the dimensions of the image are not multiples of those of the
+    /// tiles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if ```tile_width``` or ```tile_height``` is zero.
+    pub fn tiles(&self, tile_width: u32, tile_height: u32) -> Tiles<P> {
+        assert!(tile_width > 0 && tile_height > 0, "Tiles must not be empty");
+        Tiles {
+            data: &self.data,
+            width: self.width,
+            height: self.height,
+            stride: self.stride,
+            tile_width: tile_width,
+            tile_height: tile_height,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// The number of samples of the pixels of a row, without padding
+    fn row_len(&self) -> usize {
+        self.width as usize * <P as Pixel>::channel_count() as usize
+    }
+
+    /// Returns a view of the samples of this image and their layout, borrowing its container
+    pub fn as_flat_samples(&self) -> FlatSamples<&[P::Subpixel]> {
+        FlatSamples {
+            samples: &*self.data,
+            layout: self.sample_layout(),
+            color_hint: Some(<P as Pixel>::color_type()),
+        }
+    }
+
+    /// Returns the container of this image together with the layout of its samples
+    pub fn into_flat_samples(self) -> FlatSamples<Container> {
+        let layout = self.sample_layout();
+        FlatSamples {
+            samples: self.data,
+            layout: layout,
+            color_hint: Some(<P as Pixel>::color_type()),
+        }
+    }
+
+    /// Returns the layout of the samples of this image in its container
+    pub fn sample_layout(&self) -> SampleLayout {
+        SampleLayout {
+            height_stride: self.stride,
+            ..SampleLayout::row_major_packed(<P as Pixel>::channel_count(), self.width, self.height)
+        }
+    }
+
+    /// The index of the first sample of the pixel at location `(x, y)`
+    #[inline(always)]
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.stride + x as usize * <P as Pixel>::channel_count() as usize
+    }
+
     /// Enumerates over the pixels of the image.
     /// The iterator yields the coordinates of each pixel
     /// along with a reference to them.
@@ -289,12 +634,22 @@ where P: Pixel + 'static,
     ///
     /// Panics if `(x, y)` is out of the bounds `(width, height)`.
     pub fn get_pixel(&self, x: u32, y: u32) -> &P {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds", x, y);
         let no_channels = <P as Pixel>::channel_count() as usize;
-        let index  = no_channels * (y * self.width + x) as usize;
+        let index = self.index(x, y);
         <P as Pixel>::from_slice(
             &self.data[index .. index + no_channels]
         )
     }
+
+    /// Gets a reference to the pixel at location `(x, y)`, or None if it is out of bounds
+    pub fn get_pixel_checked(&self, x: u32, y: u32) -> Option<&P> {
+        if x < self.width && y < self.height {
+            Some(self.get_pixel(x, y))
+        } else {
+            None
+        }
+    }
 }
 
 impl<P, Container> ImageBuffer<P, Container>
@@ -304,10 +659,35 @@ where P: Pixel + 'static,
 
     /// Returns an iterator over the mutable pixels of this image.
     pub fn pixels_mut(&mut self) -> PixelsMut<P> {
+        let row_len = self.row_len();
         PixelsMut {
-            chunks: self.data.chunks_mut(
-                <P as Pixel>::channel_count() as usize
-            )
+            rows: self.data.chunks_mut(cmp::max(self.stride, 1)).take(self.height as usize),
+            front: [].chunks_mut(1),
+            back: [].chunks_mut(1),
+            row_len: row_len,
+        }
+    }
+
+    /// Returns an iterator over the rows of this image, top to bottom.
+    ///
+    /// Each row is an iterator over its mutable pixels, left to right.
+    pub fn rows_mut(&mut self) -> RowsMut<P> {
+        let row_len = self.row_len();
+        RowsMut {
+            rows: self.data.chunks_mut(cmp::max(self.stride, 1)).take(self.height as usize),
+            row_len: row_len,
+        }
+    }
+
+    /// Enumerates over the rows of the image.
+    /// The iterator yields the y coordinate of each row along with
+    /// an enumeration of its mutable pixels.
+    pub fn enumerate_rows_mut(&mut self) -> EnumerateRowsMut<P> {
+        let width = self.width;
+        EnumerateRowsMut {
+            rows: self.rows_mut(),
+            y: 0,
+            width: width
         }
     }
 
@@ -330,13 +710,23 @@ where P: Pixel + 'static,
     ///
     /// Panics if `(x, y)` is out of the bounds `(width, height)`.
     pub fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut P {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds", x, y);
         let no_channels = <P as Pixel>::channel_count() as usize;
-        let index  = no_channels * (y * self.width + x) as usize;
+        let index = self.index(x, y);
         <P as Pixel>::from_slice_mut(
             &mut self.data[index .. index + no_channels]
         )
     }
 
+    /// Gets a reference to the mutable pixel at location `(x, y)`, or None if it is out of bounds
+    pub fn get_pixel_mut_checked(&mut self, x: u32, y: u32) -> Option<&mut P> {
+        if x < self.width && y < self.height {
+            Some(self.get_pixel_mut(x, y))
+        } else {
+            None
+        }
+    }
+
     /// Puts a pixel at location `(x, y)`
     ///
     /// # Panics
@@ -348,19 +738,32 @@ where P: Pixel + 'static,
 }
 
 impl<P, Container> ImageBuffer<P, Container>
-where P: Pixel<Subpixel=u8> + 'static,
-      Container: Deref<Target=[u8]> {
+where P: Pixel + 'static,
+      P::Subpixel: 'static,
+      Container: Deref<Target=[P::Subpixel]> {
     /// Saves the buffer to a file at the path specified.
     ///
     /// The image format is derived from the file extension.
     /// Currently only jpeg and png files are supported.
+    ///
+    /// Buffers of `u16` subpixels keep their full 16 bits, so only formats supporting them such
+    /// as png can be used.
     pub fn save<Q>(&self, path: Q) -> io::Result<()> where Q: AsRef<Path> {
-        // This is valid as the subpixel is u8.
+        let color = <P as Pixel>::color_type();
+        // Encoders expect 16-bit samples in big endian order
+        let samples = self.pixels().flat_map(|pixel| pixel.channels().iter());
+        let bytes: Vec<u8> = match mem::size_of::<P::Subpixel>() {
+            1 => samples.map(|&sample| sample.to_u8().unwrap()).collect(),
+            2 => samples.map(|&sample| sample.to_u16().unwrap())
+                     .flat_map(|sample| vec![(sample >> 8) as u8, sample as u8]).collect(),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Can not save images of color type {:?}", color))),
+        };
         save_buffer(path,
-                    self,
+                    &bytes,
                     self.width(),
                     self.height(),
-                    <P as Pixel>::color_type())
+                    color)
     }
 }
 
@@ -370,6 +773,8 @@ where P: Pixel + 'static,
       Container: Deref<Target=[P::Subpixel]> {
     type Target = [P::Subpixel];
 
+    /// Returns the samples of the container, including the padding of the rows if the buffer
+    /// has a stride longer than its rows
     fn deref(&self) -> &<Self as Deref>::Target {
         &*self.data
     }
@@ -414,14 +819,15 @@ where P: Pixel,
             data: self.data.clone(),
             width: self.width,
             height: self.height,
+            stride: self.stride,
             _phantom: PhantomData,
         }
     }
 }
 
-impl<P, Container> GenericImage for ImageBuffer<P, Container>
+impl<P, Container> GenericImageView for ImageBuffer<P, Container>
 where P: Pixel + 'static,
-      Container: Deref<Target=[P::Subpixel]> + DerefMut,
+      Container: Deref<Target=[P::Subpixel]>,
       P::Subpixel: 'static {
 
     type Pixel = P;
@@ -438,30 +844,42 @@ where P: Pixel + 'static,
         *self.get_pixel(x, y)
     }
 
-    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut P {
-        self.get_pixel_mut(x, y)
-    }
-
     /// Returns the pixel located at (x, y), ignoring bounds checking.
+    ///
+    /// Out of bounds coordinates are only caught by an assertion in debug builds.
     #[inline(always)]
     unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> P {
+        debug_assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds", x, y);
         let no_channels = <P as Pixel>::channel_count() as usize;
-        let index  = no_channels as isize * (y * self.width + x) as isize;
+        let index = self.index(x, y) as isize;
         *<P as Pixel>::from_slice(
             ::std::slice::from_raw_parts(self.data.as_ptr().offset(index),
                                          no_channels)
         )
     }
+}
+
+impl<P, Container> GenericImage for ImageBuffer<P, Container>
+where P: Pixel + 'static,
+      Container: Deref<Target=[P::Subpixel]> + DerefMut,
+      P::Subpixel: 'static {
+
+    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut P {
+        self.get_pixel_mut(x, y)
+    }
 
     fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
         *self.get_pixel_mut(x, y) = pixel
     }
 
     /// Puts a pixel at location (x, y), ignoring bounds checking.
+    ///
+    /// Out of bounds coordinates are only caught by an assertion in debug builds.
     #[inline(always)]
     unsafe fn unsafe_put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        debug_assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds", x, y);
         let no_channels = <P as Pixel>::channel_count() as usize;
-        let index  = no_channels as isize * (y * self.width + x) as isize;
+        let index = self.index(x, y) as isize;
         let p = <P as Pixel>::from_slice_mut(
             ::std::slice::from_raw_parts_mut(self.data.as_mut_ptr().offset(index),
                                              no_channels)
@@ -496,6 +914,7 @@ where P::Subpixel: 'static {
                       ) as usize],
             width: width,
             height: height,
+            stride: width as usize * <P as Pixel>::channel_count() as usize,
             _phantom: PhantomData,
         }
     }
@@ -554,7 +973,11 @@ impl GrayImage {
                           palette: &[(u8, u8, u8)],
                           transparent_idx: Option<u8>) -> RgbaImage {
         let (width, height) = self.dimensions();
-        let mut data = self.into_raw();
+        let mut data = if self.stride == width as usize {
+            self.into_raw()
+        } else {
+            self.pixels().map(|pixel| pixel[0]).collect()
+        };
         let entries = data.len();
         data.reserve_exact(entries.checked_mul(3).unwrap()); // 3 additional channels
         // set_len is save since type is u8 an the data never read
@@ -609,12 +1032,26 @@ pub type RgbaImage = ImageBuffer<Rgba<u8>, Vec<u8>>;
 pub type GrayImage = ImageBuffer<Luma<u8>, Vec<u8>>;
 /// Sendable grayscale + alpha channel image buffer
 pub type GrayAlphaImage = ImageBuffer<LumaA<u8>, Vec<u8>>;
+/// Sendable Rgb image buffer with 16-bit samples
+pub type Rgb16Image = ImageBuffer<Rgb<u16>, Vec<u16>>;
+/// Sendable Rgb + alpha channel image buffer with 16-bit samples
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+/// Sendable grayscale image buffer with 16-bit samples
+pub type Gray16Image = ImageBuffer<Luma<u16>, Vec<u16>>;
+/// Sendable grayscale + alpha channel image buffer with 16-bit samples
+pub type GrayAlpha16Image = ImageBuffer<LumaA<u16>, Vec<u16>>;
+/// Sendable Rgb image buffer with floating point samples
+pub type Rgb32FImage = ImageBuffer<Rgb<f32>, Vec<f32>>;
+/// Sendable Rgb + alpha channel image buffer with floating point samples
+pub type Rgba32FImage = ImageBuffer<Rgba<f32>, Vec<f32>>;
 
 #[cfg(test)]
 mod test {
 
     use super::{ImageBuffer, RgbImage};
     use color;
+    use dynimage::DynamicImage;
+    use image::GenericImageView;
     #[cfg(feature = "benchmarks")]
     use test;
 
@@ -647,6 +1084,154 @@ mod test {
         assert_eq!(a.data[0], 42)
     }
 
+    #[test]
+    fn test_stride() {
+        // Rows of 2 gray pixels padded to 3 samples, the last row without padding
+        let mut data = [1, 2, 0, 3, 4, 0, 5, 6];
+        {
+            let buf: ImageBuffer<color::Luma<u8>, _> =
+                ImageBuffer::from_raw_with_stride(2, 3, 3, &data[..]).unwrap();
+            assert_eq!(buf.stride(), 3);
+            assert_eq!(buf.get_pixel(1, 2)[0], 6);
+            assert_eq!(buf.pixels().map(|p| p[0]).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6]);
+            assert_eq!(buf.pixels().rev().map(|p| p[0]).collect::<Vec<_>>(), vec![6, 5, 4, 3, 2, 1]);
+            let mut pixels = buf.pixels();
+            assert_eq!((pixels.next().unwrap()[0], pixels.next_back().unwrap()[0]), (1, 6));
+            assert_eq!(pixels.map(|p| p[0]).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+            assert_eq!(buf.enumerate_pixels().map(|(x, y, p)| (x, y, p[0])).nth(3), Some((1, 1, 4)));
+        }
+        {
+            let mut buf: ImageBuffer<color::Luma<u8>, _> =
+                ImageBuffer::from_raw_with_stride(2, 3, 3, &mut data[..]).unwrap();
+            for p in buf.pixels_mut() {
+                p[0] *= 10;
+            }
+            buf.put_pixel(0, 2, color::Luma([7]));
+            assert_eq!(unsafe { GenericImageView::unsafe_get_pixel(&buf, 0, 1) }[0], 30);
+        }
+        // The padding is left unchanged
+        assert_eq!(data, [10, 20, 0, 30, 40, 0, 7, 60]);
+
+        assert!(ImageBuffer::<color::Luma<u8>, _>::from_raw_with_stride(2, 3, 1, &data[..]).is_none());
+        assert!(ImageBuffer::<color::Luma<u8>, _>::from_raw_with_stride(2, 3, 4, &data[..]).is_none());
+        assert!(ImageBuffer::<color::Luma<u8>, _>::from_raw_with_stride(2, 0, 4, &data[..0]).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_stride_out_of_bounds() {
+        let data = [1, 2, 0, 3, 4, 0];
+        let buf: ImageBuffer<color::Luma<u8>, _> =
+            ImageBuffer::from_raw_with_stride(2, 2, 3, &data[..]).unwrap();
+        buf.get_pixel(2, 0);
+    }
+
+    #[test]
+    fn test_stride_raw_pixels() {
+        let data = vec![1, 2, 3, 0, 4, 5, 6, 0];
+        let buf: RgbImage = ImageBuffer::from_raw_with_stride(1, 2, 4, data).unwrap();
+        let image = DynamicImage::ImageRgb8(buf);
+        assert_eq!(image.raw_pixels(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(image.to_rgba().into_raw(), vec![1, 2, 3, 255, 4, 5, 6, 255]);
+    }
+
+    #[test]
+    fn test_rows() {
+        let data = vec![1u8, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let mut buf: RgbImage = ImageBuffer::from_raw_with_stride(2, 2, 8, data).unwrap();
+        assert_eq!(buf.rows().len(), 2);
+        let rows: Vec<Vec<u8>> = buf.rows().map(|row| row.map(|p| p[0]).collect()).collect();
+        assert_eq!(rows, vec![vec![1, 4], vec![7, 10]]);
+        assert_eq!(buf.rows().next_back().unwrap().next().unwrap(), &color::Rgb([7, 8, 9]));
+
+        for (y, row) in buf.enumerate_rows_mut() {
+            for (x, py, pixel) in row {
+                assert_eq!(py, y);
+                pixel[0] = (x + 2 * y) as u8;
+            }
+        }
+        for row in buf.rows_mut().rev().take(1) {
+            for pixel in row {
+                pixel[1] = 0;
+            }
+        }
+        let coords: Vec<(u32, u32, u8)> = buf.enumerate_rows()
+            .flat_map(|(_, row)| row.map(|(x, y, p)| (x, y, p[0]))).collect();
+        assert_eq!(coords, vec![(0, 0, 0), (1, 0, 1), (0, 1, 2), (1, 1, 3)]);
+        assert_eq!(buf.into_raw(), vec![0, 2, 3, 1, 5, 6, 0, 0, 2, 0, 9, 3, 0, 12]);
+    }
+
+    #[test]
+    fn test_tiles() {
+        let buf: ImageBuffer<color::Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(5, 3, |x, y| {
+            color::Luma([(10 * y + x) as u8])
+        });
+        let tiles: Vec<(u32, u32, (u32, u32), Vec<u8>)> = buf.tiles(2, 2)
+            .map(|(x, y, tile)| (x, y, tile.dimensions(), tile.pixels().map(|p| p[0]).collect()))
+            .collect();
+        assert_eq!(tiles, vec![
+            (0, 0, (2, 2), vec![0, 1, 10, 11]),
+            (2, 0, (2, 2), vec![2, 3, 12, 13]),
+            (4, 0, (1, 2), vec![4, 14]),
+            (0, 2, (2, 1), vec![20, 21]),
+            (2, 2, (2, 1), vec![22, 23]),
+            (4, 2, (1, 1), vec![24]),
+        ]);
+        assert_eq!(buf.tiles(8, 8).count(), 1);
+        let empty: RgbImage = ImageBuffer::new(0, 4);
+        assert_eq!(empty.tiles(1, 1).count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_unsafe_out_of_bounds() {
+        let buf: RgbImage = ImageBuffer::from_raw(2, 2, vec![0; 12]).unwrap();
+        unsafe { GenericImageView::unsafe_get_pixel(&buf, 2, 0) };
+    }
+
+    #[test]
+    fn test_checked() {
+        let mut buf: RgbImage = ImageBuffer::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(buf.get_pixel_checked(1, 0), Some(&color::Rgb([4, 5, 6])));
+        assert_eq!(buf.get_pixel_checked(2, 0), None);
+        assert_eq!(buf.get_pixel_checked(0, 1), None);
+        buf.get_pixel_mut_checked(0, 0).unwrap().data[0] = 7;
+        assert!(buf.get_pixel_mut_checked(0, 3).is_none());
+        assert_eq!(buf.into_raw()[0], 7);
+
+        let short: Result<RgbImage, _> = ImageBuffer::try_from_raw(2, 2, vec![0; 9]);
+        assert_eq!(short.err().unwrap().to_string(),
+                   "Invalid argument: 2x2 pixels need 12 samples, the buffer holds 9");
+        let narrow: Result<RgbImage, _> = ImageBuffer::try_from_raw_with_stride(2, 2, 5, vec![0; 12]);
+        assert_eq!(narrow.err().unwrap().to_string(),
+                   "Invalid argument: a stride of 5 samples is shorter than the 6 samples of a row");
+    }
+
+    #[test]
+    fn test_bgra() {
+        use buffer::{ConvertBuffer, Pixel, RgbaImage};
+        use color::{Bgr, Bgra, Rgba};
+        let data = vec![1u8, 2, 3, 4, 5, 6, 7, 8];
+        let bgra: ImageBuffer<Bgra<u8>, &[u8]> = ImageBuffer::from_raw(2, 1, &data[..]).unwrap();
+        assert_eq!(bgra.get_pixel(1, 0).to_rgba(), Rgba([7, 6, 5, 8]));
+        let rgba: RgbaImage = bgra.convert();
+        assert_eq!(rgba.into_raw(), vec![3, 2, 1, 4, 7, 6, 5, 8]);
+        let bgr: ImageBuffer<Bgr<u8>, Vec<u8>> = bgra.convert();
+        assert_eq!(bgr.into_raw(), vec![1, 2, 3, 5, 6, 7]);
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn test_save_bgr() {
+        use color::Bgr;
+        use dynimage::open;
+        let path = ::std::env::temp_dir().join("image-test-save-bgr.png");
+        let bgr: ImageBuffer<Bgr<u8>, Vec<u8>> = ImageBuffer::from_raw(1, 1, vec![1, 2, 3]).unwrap();
+        bgr.save(&path).unwrap();
+        assert_eq!(open(&path).unwrap().raw_pixels(), vec![3, 2, 1]);
+    }
+
     #[bench]
     #[cfg(feature = "benchmarks")]
     fn bench_conversion(b: &mut test::Bencher) {
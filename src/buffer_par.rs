@@ -0,0 +1,104 @@
+//! Parallel iteration over the pixels of image buffers, with rayon
+
+extern crate rayon;
+
+use std::cmp;
+use std::ops::{Deref, DerefMut};
+
+use self::rayon::prelude::*;
+
+use buffer::{ImageBuffer, Pixel};
+
+impl<P, Container> ImageBuffer<P, Container>
+where P: Pixel + Sync + 'static,
+      P::Subpixel: Sync + 'static,
+      Container: Deref<Target=[P::Subpixel]> {
+
+    /// Returns a parallel iterator over the pixels of this image.
+    ///
+    /// The pixels are split between the threads of the global rayon pool.
+    pub fn par_pixels<'a>(&'a self) -> impl ParallelIterator<Item=&'a P> + 'a {
+        let (width, height) = self.dimensions();
+        let row_len = width as usize * <P as Pixel>::channel_count() as usize;
+        let channels = <P as Pixel>::channel_count() as usize;
+        // Rows are padded up to the stride, the last row may end right after its pixels
+        self.par_chunks(cmp::max(self.stride(), 1))
+            .take(height as usize)
+            .flat_map(move |row| row[..row_len].par_chunks(channels).map(<P as Pixel>::from_slice))
+    }
+}
+
+impl<P, Container> ImageBuffer<P, Container>
+where P: Pixel + Send + Sync + 'static,
+      P::Subpixel: Send + Sync + 'static,
+      Container: Deref<Target=[P::Subpixel]> + DerefMut {
+
+    /// Returns a parallel iterator over the mutable pixels of this image.
+    ///
+    /// The pixels are split between the threads of the global rayon pool.
+    pub fn par_pixels_mut<'a>(&'a mut self) -> impl ParallelIterator<Item=&'a mut P> + 'a {
+        self.par_enumerate_pixels_mut().map(|(_, _, pixel)| pixel)
+    }
+
+    /// Enumerates over the pixels of the image in parallel.
+    /// The iterator yields the coordinates of each pixel
+    /// along with a mutable reference to them.
+    pub fn par_enumerate_pixels_mut<'a>(&'a mut self)
+                                        -> impl ParallelIterator<Item=(u32, u32, &'a mut P)> + 'a {
+        let (width, height) = self.dimensions();
+        let row_len = width as usize * <P as Pixel>::channel_count() as usize;
+        let channels = <P as Pixel>::channel_count() as usize;
+        let stride = cmp::max(self.stride(), 1);
+        self.par_chunks_mut(stride)
+            .take(height as usize)
+            .enumerate()
+            .flat_map(move |(y, row)| {
+                row[..row_len].par_chunks_mut(channels)
+                    .enumerate()
+                    .map(move |(x, pixel)| (x as u32, y as u32, <P as Pixel>::from_slice_mut(pixel)))
+            })
+    }
+}
+
+impl<P: Pixel + Send + Sync + 'static> ImageBuffer<P, Vec<P::Subpixel>>
+where P::Subpixel: Send + Sync + 'static {
+
+    /// Constructs a new ImageBuffer by application of the supplied function, in parallel.
+    /// The arguments to the function are the pixel's x and y coordinates.
+    pub fn from_par_fn<F>(width: u32, height: u32, f: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+                          where F: Fn(u32, u32) -> P + Sync {
+        let mut buf = ImageBuffer::new(width, height);
+        buf.par_enumerate_pixels_mut().for_each(|(x, y, p)| *p = f(x, y));
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rayon::prelude::*;
+
+    use buffer::{ImageBuffer, RgbImage};
+    use color::{Luma, Rgb};
+
+    #[test]
+    fn par_pixels() {
+        let data = vec![1u8, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let mut buf: RgbImage = ImageBuffer::from_raw_with_stride(2, 2, 8, data).unwrap();
+        let sum: u32 = buf.par_pixels().map(|p| p[0] as u32).sum();
+        assert_eq!(sum, 1 + 4 + 7 + 10);
+
+        buf.par_pixels_mut().for_each(|p| p[1] = 0);
+        buf.par_enumerate_pixels_mut().for_each(|(x, y, p)| p[2] = (x + 2 * y) as u8);
+        assert_eq!(buf.into_raw(), vec![1, 0, 0, 4, 0, 1, 0, 0, 7, 0, 2, 10, 0, 3]);
+    }
+
+    #[test]
+    fn from_par_fn() {
+        let buf = ImageBuffer::from_par_fn(40, 30, |x, y| Luma([(x + y) as u8]));
+        let expected = ImageBuffer::from_fn(40, 30, |x, y| Luma([(x + y) as u8]));
+        assert_eq!(buf.into_raw(), expected.into_raw());
+
+        let empty = ImageBuffer::from_par_fn(0, 3, |_, _| Rgb([0u8, 0, 0]));
+        assert_eq!(empty.dimensions(), (0, 3));
+    }
+}
@@ -0,0 +1,620 @@
+//! Parsing ICC color profiles and converting images between them
+//!
+//! Images embed an ICC profile to tell which colors their samples stand for. Images of cameras
+//! and phones are often stored in a wider gamut than sRGB, and look washed out when their samples
+//! are shown as sRGB. A `Transform` converts the pixels of a `DynamicImage` from the profile of
+//! the image into sRGB or another working space.
+//!
+//! Matrix/TRC profiles are supported: RGB profiles with a colorant and a tone reproduction curve
+//! per channel, and gray profiles with a single curve. Profiles that describe the conversion with
+//! lookup tables, or whose connection space is Lab, are not.
+
+use byteorder::{BigEndian, ByteOrder};
+
+use buffer::{ImageBuffer, Pixel};
+use dynimage::DynamicImage;
+use image::{DecodingErrorKind, ImageError, ImageFormatHint, ImageResult, UnsupportedFeature};
+
+/// The length of the header of a profile, which is followed by the tag table
+const HEADER_LEN: usize = 128;
+
+/// The number of entries of the tables of a `Transform` from linear light to samples
+const OUTPUT_LEN: usize = 16384;
+
+/// The white point of the profile connection space, D50
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// The curve of sRGB and Display P3, as the parameters of a parametric curve
+const SRGB_CURVE: [f64; 7] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045, 0.0, 0.0];
+
+/// A tone reproduction curve, mapping samples to linear light
+#[derive(Clone, Debug, PartialEq)]
+enum Curve {
+    /// `x^g`
+    Gamma(f64),
+    /// Values spaced evenly from 0 to 1, interpolated linearly
+    Table(Vec<f64>),
+    /// The parameters `g, a, b, c, d, e, f` of `(a x + b)^g + e` from `d` on and of `c x + f`
+    /// below `d`
+    Parametric([f64; 7]),
+}
+
+impl Curve {
+    /// Returns the linear light of the sample ```x```, both from 0 to 1
+    fn eval(&self, x: f64) -> f64 {
+        let x = clamp(x);
+        let y = match *self {
+            Curve::Gamma(g) => x.powf(g),
+            Curve::Table(ref table) => {
+                let position = x * (table.len() - 1) as f64;
+                let i = (position as usize).min(table.len() - 2);
+                let t = position - i as f64;
+                table[i] + (table[i + 1] - table[i]) * t
+            }
+            Curve::Parametric([g, a, b, c, d, e, f]) => {
+                if x >= d {
+                    (a * x + b).max(0.0).powf(g) + e
+                } else {
+                    c * x + f
+                }
+            }
+        };
+        clamp(y)
+    }
+
+    /// Returns the sample of the linear light ```y```, both from 0 to 1
+    fn invert(&self, y: f64) -> f64 {
+        let y = clamp(y);
+        match *self {
+            Curve::Gamma(g) if g > 0.0 => y.powf(1.0 / g),
+            Curve::Parametric([g, a, b, c, d, e, f]) if g > 0.0 && a > 0.0 => {
+                let x = if y >= (a * d + b).max(0.0).powf(g) + e {
+                    ((y - e).max(0.0).powf(1.0 / g) - b) / a
+                } else if c != 0.0 {
+                    (y - f) / c
+                } else {
+                    0.0
+                };
+                clamp(x)
+            }
+            _ => {
+                // Curves without an inverse in closed form are inverted by bisection, assuming
+                // that they increase
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..32 {
+                    let middle = (low + high) / 2.0;
+                    if self.eval(middle) < y {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                (low + high) / 2.0
+            }
+        }
+    }
+}
+
+fn clamp(x: f64) -> f64 {
+    if x > 1.0 {
+        1.0
+    } else if x >= 0.0 {
+        x
+    } else {
+        // Also maps NaN to 0
+        0.0
+    }
+}
+
+/// A matrix/TRC color profile
+///
+/// The colors of the image are converted into the XYZ connection space, relative to D50, by
+/// mapping each sample to linear light with the curve of its channel, then multiplying them with
+/// the colorants of the channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    /// The XYZ of the red, green and blue colorants as columns, or the white point on the
+    /// diagonal for gray profiles
+    matrix: [[f64; 3]; 3],
+    curves: [Curve; 3],
+}
+
+impl Profile {
+    /// Parses the ICC profile ```data```, as returned by `ImageDecoder::icc_profile`
+    ///
+    /// Fails with `ImageError::Unsupported` for profiles that are not matrix/TRC profiles.
+    pub fn parse(data: &[u8]) -> ImageResult<Profile> {
+        if data.len() < HEADER_LEN + 4 {
+            return Err(truncated())
+        }
+        if &data[36..40] != b"acsp" {
+            return Err(malformed("ICC profile signature is missing"))
+        }
+        let len = BigEndian::read_u32(&data[0..4]) as usize;
+        if len > data.len() {
+            return Err(truncated())
+        }
+        let data = &data[..len];
+        if &data[20..24] != b"XYZ " {
+            return Err(unsupported("profiles connecting through Lab"))
+        }
+        match &data[16..20] {
+            b"RGB " => {
+                let red = try!(parse_xyz(try!(required_tag(data, b"rXYZ"))));
+                let green = try!(parse_xyz(try!(required_tag(data, b"gXYZ"))));
+                let blue = try!(parse_xyz(try!(required_tag(data, b"bXYZ"))));
+                let red_curve = try!(parse_curve(try!(required_tag(data, b"rTRC"))));
+                let green_curve = try!(parse_curve(try!(required_tag(data, b"gTRC"))));
+                let blue_curve = try!(parse_curve(try!(required_tag(data, b"bTRC"))));
+                Ok(Profile::from_colorants(red, green, blue, [red_curve, green_curve, blue_curve]))
+            }
+            b"GRAY" => {
+                let curve = try!(parse_curve(try!(required_tag(data, b"kTRC"))));
+                Ok(Profile {
+                    matrix: [[D50[0], 0.0, 0.0], [0.0, D50[1], 0.0], [0.0, 0.0, D50[2]]],
+                    curves: [curve.clone(), curve.clone(), curve],
+                })
+            }
+            space => Err(unsupported(&format!("profiles of the color space {}",
+                                              String::from_utf8_lossy(space).trim_end()))),
+        }
+    }
+
+    /// Returns the sRGB profile, which images without a profile are usually meant to be in
+    pub fn srgb() -> Profile {
+        Profile::from_colorants([0.4360747, 0.2225045, 0.0139322],
+                                [0.3850649, 0.7168786, 0.0971045],
+                                [0.1430804, 0.0606169, 0.7141733],
+                                [Curve::Parametric(SRGB_CURVE), Curve::Parametric(SRGB_CURVE),
+                                 Curve::Parametric(SRGB_CURVE)])
+    }
+
+    /// Returns the Display P3 profile, which has the primaries of DCI-P3 and the curve of sRGB
+    ///
+    /// Photos of phones are often stored in Display P3.
+    pub fn display_p3() -> Profile {
+        Profile::from_colorants([0.5151024, 0.2411823, -0.0010494],
+                                [0.2919693, 0.6922360, 0.0418818],
+                                [0.1571484, 0.0665817, 0.7843787],
+                                [Curve::Parametric(SRGB_CURVE), Curve::Parametric(SRGB_CURVE),
+                                 Curve::Parametric(SRGB_CURVE)])
+    }
+
+    /// Returns the Adobe RGB (1998) profile
+    pub fn adobe_rgb() -> Profile {
+        let gamma = 563.0 / 256.0;
+        Profile::from_colorants([0.6097559, 0.3111242, 0.0194811],
+                                [0.2052401, 0.6256560, 0.0608902],
+                                [0.1492240, 0.0632197, 0.7448387],
+                                [Curve::Gamma(gamma), Curve::Gamma(gamma), Curve::Gamma(gamma)])
+    }
+
+    fn from_colorants(red: [f64; 3], green: [f64; 3], blue: [f64; 3], curves: [Curve; 3]) -> Profile {
+        Profile {
+            matrix: [[red[0], green[0], blue[0]],
+                     [red[1], green[1], blue[1]],
+                     [red[2], green[2], blue[2]]],
+            curves: curves,
+        }
+    }
+}
+
+/// Returns the data of the tag ```signature``` of the profile ```data```, if it has one
+fn find_tag<'a>(data: &'a [u8], signature: &[u8; 4]) -> ImageResult<Option<&'a [u8]>> {
+    let count = BigEndian::read_u32(&data[HEADER_LEN..]) as usize;
+    if count > (data.len() - HEADER_LEN - 4) / 12 {
+        return Err(truncated())
+    }
+    for entry in data[HEADER_LEN + 4..].chunks(12).take(count) {
+        if &entry[0..4] != signature {
+            continue
+        }
+        let offset = BigEndian::read_u32(&entry[4..8]) as usize;
+        let len = BigEndian::read_u32(&entry[8..12]) as usize;
+        if offset > data.len() || len > data.len() - offset {
+            return Err(truncated())
+        }
+        return Ok(Some(&data[offset..offset + len]))
+    }
+    Ok(None)
+}
+
+/// Returns the data of the tag ```signature```, which matrix/TRC profiles have
+fn required_tag<'a>(data: &'a [u8], signature: &[u8; 4]) -> ImageResult<&'a [u8]> {
+    match try!(find_tag(data, signature)) {
+        Some(tag) => Ok(tag),
+        None => Err(unsupported("profiles without colorants and curves")),
+    }
+}
+
+/// Parses an `XYZ ` tag holding a single color
+fn parse_xyz(tag: &[u8]) -> ImageResult<[f64; 3]> {
+    if tag.len() < 20 || &tag[0..4] != b"XYZ " {
+        return Err(malformed("ICC colorant is not an XYZ tag"))
+    }
+    Ok([s15_fixed16(&tag[8..]), s15_fixed16(&tag[12..]), s15_fixed16(&tag[16..])])
+}
+
+/// Parses a `curv` or `para` tag
+fn parse_curve(tag: &[u8]) -> ImageResult<Curve> {
+    if tag.len() < 12 {
+        return Err(truncated())
+    }
+    match &tag[0..4] {
+        b"curv" => {
+            let count = BigEndian::read_u32(&tag[8..12]) as usize;
+            if count > (tag.len() - 12) / 2 {
+                return Err(truncated())
+            }
+            let entries = &tag[12..12 + 2 * count];
+            Ok(match count {
+                0 => Curve::Gamma(1.0),
+                // A single entry is a gamma stored as u8Fixed8
+                1 => Curve::Gamma(BigEndian::read_u16(entries) as f64 / 256.0),
+                _ => Curve::Table(entries.chunks(2)
+                    .map(|entry| BigEndian::read_u16(entry) as f64 / 65535.0)
+                    .collect()),
+            })
+        }
+        b"para" => {
+            let kind = BigEndian::read_u16(&tag[8..10]);
+            let count = match kind {
+                0 => 1,
+                1 => 3,
+                2 => 4,
+                3 => 5,
+                4 => 7,
+                _ => return Err(malformed("ICC parametric curve is of an unknown type")),
+            };
+            if tag.len() < 12 + 4 * count {
+                return Err(truncated())
+            }
+            let p: Vec<f64> = (0..count).map(|i| s15_fixed16(&tag[12 + 4 * i..])).collect();
+            // All types are special cases of the last one
+            Ok(Curve::Parametric(match kind {
+                0 => [p[0], 1.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+                1 => [p[0], p[1], p[2], 0.0, -p[2] / p[1], 0.0, 0.0],
+                2 => [p[0], p[1], p[2], 0.0, -p[2] / p[1], p[3], p[3]],
+                3 => [p[0], p[1], p[2], p[3], p[4], 0.0, 0.0],
+                _ => [p[0], p[1], p[2], p[3], p[4], p[5], p[6]],
+            }))
+        }
+        _ => Err(malformed("ICC tone reproduction curve is not a curv or para tag")),
+    }
+}
+
+fn s15_fixed16(data: &[u8]) -> f64 {
+    BigEndian::read_i32(data) as f64 / 65536.0
+}
+
+/// A conversion of pixels from one `Profile` into another
+///
+/// ```no_run
+/// # fn main() { run().unwrap() }
+/// # fn run() -> image::ImageResult<()> {
+/// use image::color::icc::{Profile, Transform};
+///
+/// let mut image = try!(image::open("photo.png"));
+/// let transform = Transform::new(&Profile::display_p3(), &Profile::srgb());
+/// transform.apply(&mut image);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Transform {
+    input: [Curve; 3],
+    /// Maps linear light of the source profile to linear light of the target profile
+    matrix: [[f64; 3]; 3],
+    output: [Curve; 3],
+    /// The samples of `OUTPUT_LEN` linear light values spaced evenly from 0 to 1, per channel
+    output_tables: [Vec<f32>; 3],
+    identity: bool,
+}
+
+impl Transform {
+    /// Creates a conversion of pixels from the profile ```source``` into ```target```
+    ///
+    /// Colors outside of the gamut of ```target``` are clipped.
+    pub fn new(source: &Profile, target: &Profile) -> Transform {
+        let matrix = multiply(&invert(&target.matrix), &source.matrix);
+        let table = |curve: &Curve| {
+            (0..OUTPUT_LEN).map(|i| curve.invert(i as f64 / (OUTPUT_LEN - 1) as f64) as f32).collect()
+        };
+        Transform {
+            input: source.curves.clone(),
+            matrix: matrix,
+            output: target.curves.clone(),
+            output_tables: [table(&target.curves[0]), table(&target.curves[1]), table(&target.curves[2])],
+            identity: source == target,
+        }
+    }
+
+    /// Converts the pixels of ```image``` in place
+    ///
+    /// The alpha channel is left unchanged. Gray images stay gray, their samples are converted
+    /// like those of a gray pixel of an RGB image.
+    pub fn apply(&self, image: &mut DynamicImage) {
+        if self.identity {
+            return
+        }
+        match *image {
+            DynamicImage::ImageLuma8(ref mut buffer) => self.apply_u8(buffer),
+            DynamicImage::ImageLumaA8(ref mut buffer) => self.apply_u8(buffer),
+            DynamicImage::ImageRgb8(ref mut buffer) => self.apply_u8(buffer),
+            DynamicImage::ImageRgba8(ref mut buffer) => self.apply_u8(buffer),
+            DynamicImage::ImageLuma16(ref mut buffer) => self.apply_u16(buffer),
+            DynamicImage::ImageLumaA16(ref mut buffer) => self.apply_u16(buffer),
+            DynamicImage::ImageRgb16(ref mut buffer) => self.apply_u16(buffer),
+            DynamicImage::ImageRgba16(ref mut buffer) => self.apply_u16(buffer),
+            DynamicImage::ImageRgb32F(ref mut buffer) => self.apply_f32(buffer),
+            DynamicImage::ImageRgba32F(ref mut buffer) => self.apply_f32(buffer),
+        }
+    }
+
+    /// Converts the 8 bit pixels of ```image```
+    fn apply_u8<P: Pixel<Subpixel=u8> + 'static>(&self, image: &mut ImageBuffer<P, Vec<u8>>) {
+        let input: Vec<Vec<f64>> = self.input.iter()
+            .map(|curve| (0..256).map(|i| curve.eval(i as f64 / 255.0)).collect())
+            .collect();
+        let to_u8 = |value: f32| (value * 255.0 + 0.5) as u8;
+        for pixel in image.pixels_mut() {
+            let pixel = pixel.channels_mut();
+            if pixel.len() < 3 {
+                let linear = self.convert([input[1][pixel[0] as usize]; 3]);
+                pixel[0] = to_u8(self.encode(1, linear[1]));
+            } else {
+                let linear = self.convert([input[0][pixel[0] as usize], input[1][pixel[1] as usize],
+                                           input[2][pixel[2] as usize]]);
+                for c in 0..3 {
+                    pixel[c] = to_u8(self.encode(c, linear[c]));
+                }
+            }
+        }
+    }
+
+    /// Converts the 16 bit pixels of ```image```
+    fn apply_u16<P: Pixel<Subpixel=u16> + 'static>(&self, image: &mut ImageBuffer<P, Vec<u16>>) {
+        let input = |c: usize, sample: u16| self.input[c].eval(sample as f64 / 65535.0);
+        let to_u16 = |c: usize, linear: f64| (clamp(self.output[c].invert(linear)) * 65535.0 + 0.5) as u16;
+        for pixel in image.pixels_mut() {
+            let pixel = pixel.channels_mut();
+            if pixel.len() < 3 {
+                let linear = self.convert([input(1, pixel[0]); 3]);
+                pixel[0] = to_u16(1, linear[1]);
+            } else {
+                let linear = self.convert([input(0, pixel[0]), input(1, pixel[1]), input(2, pixel[2])]);
+                for c in 0..3 {
+                    pixel[c] = to_u16(c, linear[c]);
+                }
+            }
+        }
+    }
+
+    /// Converts the floating point pixels of ```image```
+    fn apply_f32<P: Pixel<Subpixel=f32> + 'static>(&self, image: &mut ImageBuffer<P, Vec<f32>>) {
+        for pixel in image.pixels_mut() {
+            let pixel = pixel.channels_mut();
+            let linear = self.convert([self.input[0].eval(pixel[0] as f64),
+                                       self.input[1].eval(pixel[1] as f64),
+                                       self.input[2].eval(pixel[2] as f64)]);
+            for c in 0..3 {
+                pixel[c] = self.output[c].invert(linear[c]) as f32;
+            }
+        }
+    }
+
+    fn convert(&self, linear: [f64; 3]) -> [f64; 3] {
+        let m = &self.matrix;
+        let mut result = [0.0; 3];
+        for (row, value) in m.iter().zip(result.iter_mut()) {
+            *value = row[0] * linear[0] + row[1] * linear[1] + row[2] * linear[2];
+        }
+        result
+    }
+
+    /// Returns the sample of the linear light ```linear``` of the channel ```channel```
+    fn encode(&self, channel: usize, linear: f64) -> f32 {
+        let table = &self.output_tables[channel];
+        let position = clamp(linear) as f32 * (OUTPUT_LEN - 1) as f32;
+        let i = (position as usize).min(OUTPUT_LEN - 2);
+        let t = position - i as f32;
+        table[i] + (table[i + 1] - table[i]) * t
+    }
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut result = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            result[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j] + a[i][2] * b[2][j];
+        }
+    }
+    result
+}
+
+/// Inverts ```m```, returning the identity for singular matrices
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    if determinant.abs() < 1e-12 {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+    }
+    let mut result = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            result[i][j] = cofactor(j, i) / determinant;
+        }
+    }
+    result
+}
+
+fn malformed(message: &str) -> ImageError {
+    ImageError::Decoding {
+        format: ImageFormatHint::Name("ICC".to_string()),
+        kind: DecodingErrorKind::Malformed(message.to_string()),
+    }
+}
+
+fn truncated() -> ImageError {
+    ImageError::Decoding {
+        format: ImageFormatHint::Name("ICC".to_string()),
+        kind: DecodingErrorKind::Truncated,
+    }
+}
+
+fn unsupported(feature: &str) -> ImageError {
+    ImageError::Unsupported {
+        format: ImageFormatHint::Name("ICC".to_string()),
+        feature: UnsupportedFeature::Other(feature.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    use super::{Curve, Profile, Transform, SRGB_CURVE};
+    use buffer::ImageBuffer;
+    use dynimage::DynamicImage;
+    use image::{ImageDecoder, ImageError};
+
+    fn fixed(value: f64) -> [u8; 4] {
+        ((value * 65536.0).round() as i32).to_be_bytes()
+    }
+
+    /// Returns an RGB profile of the colorants ```colorants``` and the parametric curve ```curve```
+    /// of type 3, or a gray profile if there are no colorants
+    fn profile(colorants: &[[f64; 3]], curve: &[f64; 5]) -> Vec<u8> {
+        let mut curv = b"para\0\0\0\0\0\x03\0\0".to_vec();
+        for &p in curve {
+            curv.extend_from_slice(&fixed(p));
+        }
+        let mut tags: Vec<(&[u8], Vec<u8>)> = Vec::new();
+        for (signature, xyz) in [&b"rXYZ"[..], b"gXYZ", b"bXYZ"].iter().zip(colorants) {
+            let mut tag = b"XYZ \0\0\0\0".to_vec();
+            for &v in xyz {
+                tag.extend_from_slice(&fixed(v));
+            }
+            tags.push((signature, tag));
+        }
+        let curves = if colorants.is_empty() {
+            &[&b"kTRC"[..]][..]
+        } else {
+            &[&b"rTRC"[..], b"gTRC", b"bTRC"][..]
+        };
+        for signature in curves {
+            tags.push((signature, curv.clone()));
+        }
+
+        let mut data = vec![0; 128];
+        data[16..20].copy_from_slice(if colorants.is_empty() { b"GRAY" } else { b"RGB " });
+        data[20..24].copy_from_slice(b"XYZ ");
+        data[36..40].copy_from_slice(b"acsp");
+        data.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+        let mut offset = 132 + 12 * tags.len();
+        for &(signature, ref tag) in &tags {
+            data.extend_from_slice(signature);
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+            data.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+            offset += tag.len();
+        }
+        for &(_, ref tag) in &tags {
+            data.extend_from_slice(tag);
+        }
+        let len = data.len() as u32;
+        data[0..4].copy_from_slice(&len.to_be_bytes());
+        data
+    }
+
+    fn rgb(pixels: &[u8]) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_raw(pixels.len() as u32 / 3, 1, pixels.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn parse() {
+        let srgb = [[0.4360747, 0.2225045, 0.0139322], [0.3850649, 0.7168786, 0.0971045],
+                    [0.1430804, 0.0606169, 0.7141733]];
+        let parsed = Profile::parse(&profile(&srgb, &[2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045]))
+            .unwrap();
+        for (row, expected) in parsed.matrix.iter().zip(Profile::srgb().matrix.iter()) {
+            for (value, expected) in row.iter().zip(expected) {
+                assert!((value - expected).abs() < 1e-4);
+            }
+        }
+        match parsed.curves[0] {
+            Curve::Parametric(p) => for (value, expected) in p.iter().zip(&SRGB_CURVE) {
+                assert!((value - expected).abs() < 1e-4);
+            },
+            ref curve => panic!("Expected a parametric curve, got {:?}", curve),
+        }
+
+        let gray = Profile::parse(&profile(&[], &[1.75, 1.0, 0.0, 0.0, 0.0])).unwrap();
+        assert_eq!(gray.curves[1], Curve::Parametric([1.75, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]));
+
+        let data = profile(&srgb, &[2.2, 1.0, 0.0, 0.0, 0.0]);
+        match Profile::parse(&data[..data.len() - 1]) {
+            Err(ImageError::Decoding { .. }) => (),
+            r => panic!("Expected the profile to be truncated, got {:?}", r),
+        }
+        let mut lab = data.clone();
+        lab[20..24].copy_from_slice(b"Lab ");
+        match Profile::parse(&lab) {
+            Err(ImageError::Unsupported { .. }) => (),
+            r => panic!("Expected Lab profiles to be unsupported, got {:?}", r),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn parse_embedded() {
+        let file = File::open("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let mut decoder = ::jpeg::JPEGDecoder::new(BufReader::new(file));
+        let data = decoder.icc_profile().unwrap().unwrap();
+        let profile = Profile::parse(&data).unwrap();
+        // The colorants of the channels add up to the white point
+        for (row, white) in profile.matrix.iter().zip(&super::D50) {
+            assert!((row.iter().sum::<f64>() - white).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn convert() {
+        let pixels = [0, 0, 0, 255, 255, 255, 200, 100, 50, 10, 128, 240];
+        let mut image = rgb(&pixels);
+        Transform::new(&Profile::srgb(), &Profile::srgb()).apply(&mut image);
+        assert_eq!(image.raw_pixels(), pixels);
+
+        // The colors of a wider gamut are more saturated in sRGB
+        let mut image = rgb(&pixels);
+        Transform::new(&Profile::display_p3(), &Profile::srgb()).apply(&mut image);
+        let converted = image.raw_pixels();
+        assert_eq!(&converted[..6], &pixels[..6]);
+        assert!(converted[6] > 200 && converted[7] < 100 && converted[8] < 50);
+
+        // The colors of sRGB are inside the wider gamuts and are converted back
+        let mut image = rgb(&pixels);
+        Transform::new(&Profile::srgb(), &Profile::display_p3()).apply(&mut image);
+        Transform::new(&Profile::display_p3(), &Profile::srgb()).apply(&mut image);
+        for (&value, &expected) in image.raw_pixels().iter().zip(&pixels) {
+            assert!((value as i32 - expected as i32).abs() <= 1, "{:?}", image.raw_pixels());
+        }
+
+        let mut image = rgb(&pixels);
+        Transform::new(&Profile::srgb(), &Profile::adobe_rgb()).apply(&mut image);
+        Transform::new(&Profile::adobe_rgb(), &Profile::srgb()).apply(&mut image);
+        for (&value, &expected) in image.raw_pixels().iter().zip(&pixels) {
+            assert!((value as i32 - expected as i32).abs() <= 1, "{:?}", image.raw_pixels());
+        }
+
+        // Gray images stay gray
+        let gray = Profile::parse(&profile(&[], &[1.0, 1.0, 0.0, 0.0, 0.0])).unwrap();
+        let mut image = DynamicImage::ImageLumaA8(ImageBuffer::from_raw(2, 1, vec![0, 7, 50, 9]).unwrap());
+        Transform::new(&gray, &Profile::srgb()).apply(&mut image);
+        assert_eq!(image.raw_pixels(), vec![0, 7, 122, 9]);
+    }
+}
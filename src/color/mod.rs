@@ -1,3 +1,9 @@
+//! Color types, the conversions between them and color management
+
+pub mod icc;
+pub mod space;
+pub mod transfer;
+
 use std::ops::{ Index, IndexMut };
 use num_traits::{ NumCast, Zero };
 use std::mem;
@@ -23,28 +29,51 @@ pub enum ColorType {
     /// Pixel is RGB with an alpha channel
     RGBA(u8),
 
+    /// Pixel is grayscale with floating point samples
+    GrayF(u8),
+
+    /// Pixel is grayscale with an alpha channel, with floating point samples
+    GrayAF(u8),
+
+    /// Pixel contains R, G and B channels with floating point samples
+    RGBF(u8),
+
+    /// Pixel is RGB with an alpha channel, with floating point samples
+    RGBAF(u8),
+
+    /// Pixel contains B, G and R channels, in this order
+    BGR(u8),
+
+    /// Pixel is BGR with an alpha channel
+    BGRA(u8),
 }
 
 /// Returns the number of bits contained in a pixel of ColorType ```c```
 pub fn bits_per_pixel(c: ColorType) -> usize {
     match c {
-        ColorType::Gray(n)    => n as usize,
-        ColorType::GrayA(n)   => 2 * n as usize,
-        ColorType::RGB(n) | ColorType::Palette(n) => 3 * n as usize,
-        ColorType::RGBA(n)    => 4 * n as usize,
+        ColorType::Gray(n) | ColorType::GrayF(n) => n as usize,
+        ColorType::GrayA(n) | ColorType::GrayAF(n) => 2 * n as usize,
+        ColorType::RGB(n) | ColorType::RGBF(n) | ColorType::BGR(n) | ColorType::Palette(n) => 3 * n as usize,
+        ColorType::RGBA(n) | ColorType::RGBAF(n) | ColorType::BGRA(n) => 4 * n as usize,
     }
 }
 
 /// Returns the number of color channels that make up this pixel
 pub fn num_components(c: ColorType) -> usize {
     match c {
-        ColorType::Gray(_)    => 1,
-        ColorType::GrayA(_)   => 2,
-        ColorType::RGB(_) | ColorType::Palette(_) => 3,
-        ColorType::RGBA(_)    => 4,
+        ColorType::Gray(_) | ColorType::GrayF(_) => 1,
+        ColorType::GrayA(_) | ColorType::GrayAF(_) => 2,
+        ColorType::RGB(_) | ColorType::RGBF(_) | ColorType::BGR(_) | ColorType::Palette(_) => 3,
+        ColorType::RGBA(_) | ColorType::RGBAF(_) | ColorType::BGRA(_) => 4,
     }
 }
 
+/// Returns true if ```T``` is a floating point type, as integer types truncate a half to zero
+fn is_float_primitive<T: Primitive>() -> bool {
+    let half: Option<T> = NumCast::from(0.5f32);
+    half.and_then(|h| h.to_f32()) == Some(0.5)
+}
+
 macro_rules! define_colors {
     {$(
         $ident:ident,
@@ -52,6 +81,7 @@ macro_rules! define_colors {
         $alphas: expr,
         $interpretation: expr,
         $color_type: ident,
+        $float_type: ident,
         #[$doc:meta];
     )*} => {
 
@@ -80,7 +110,12 @@ impl<T: Primitive + 'static> Pixel for $ident<T> {
         $interpretation
     }
     fn color_type() -> ColorType {
-        ColorType::$color_type(mem::size_of::<T>() as u8 * 8)
+        let bits = mem::size_of::<T>() as u8 * 8;
+        if is_float_primitive::<T>() {
+            ColorType::$float_type(bits)
+        } else {
+            ColorType::$color_type(bits)
+        }
     }
     #[inline(always)]
     fn channels(&self) -> &[T] {
@@ -93,7 +128,7 @@ impl<T: Primitive + 'static> Pixel for $ident<T> {
 
     #[allow(trivial_casts)]
     fn channels4(&self) -> (T, T, T, T) {
-        let mut channels = [T::max_value(); 4];
+        let mut channels = [T::default_max_value(); 4];
         channels[0..$channels].copy_from_slice(&self.data);
         (channels[0], channels[1], channels[2], channels[3])
     }
@@ -206,10 +241,12 @@ impl<T: Primitive> IndexMut<usize> for $ident<T> {
 }
 
 define_colors! {
-    Rgb, 3, 0, "RGB", RGB, #[doc = "RGB colors"];
-    Luma, 1, 0, "Y", Gray, #[doc = "Grayscale colors"];
-    Rgba, 4, 1, "RGBA", RGBA, #[doc = "RGB colors + alpha channel"];
-    LumaA, 2, 1, "YA", GrayA, #[doc = "Grayscale colors + alpha channel"];
+    Rgb, 3, 0, "RGB", RGB, RGBF, #[doc = "RGB colors"];
+    Luma, 1, 0, "Y", Gray, GrayF, #[doc = "Grayscale colors"];
+    Rgba, 4, 1, "RGBA", RGBA, RGBAF, #[doc = "RGB colors + alpha channel"];
+    LumaA, 2, 1, "YA", GrayA, GrayAF, #[doc = "Grayscale colors + alpha channel"];
+    Bgr, 3, 0, "BGR", BGR, BGR, #[doc = "BGR colors, RGB colors stored in reverse order"];
+    Bgra, 4, 1, "BGRA", BGRA, BGRA, #[doc = "BGR colors + alpha channel"];
 }
 
 
@@ -279,7 +316,7 @@ impl<T: Primitive + 'static> FromColor<Rgb<T>> for LumaA<T> {
                 0.7152f32 * rgb[1].to_f32().unwrap() +
                 0.0722f32 * rgb[2].to_f32().unwrap();
         gray_a[0] = NumCast::from(l).unwrap();
-        gray_a[1] = T::max_value();
+        gray_a[1] = T::default_max_value();
     }
 }
 
@@ -287,7 +324,7 @@ impl<T: Primitive + 'static> FromColor<Luma<T>> for LumaA<T> {
     fn from_color(&mut self, other: &Luma<T>) {
         let gray_a = self.channels_mut();
         gray_a[0] = other.channels()[0];
-        gray_a[1] = T::max_value();
+        gray_a[1] = T::default_max_value();
     }
 }
 
@@ -300,7 +337,7 @@ impl<T: Primitive + 'static> FromColor<Rgb<T>> for Rgba<T> {
         rgba[0] = rgb[0];
         rgba[1] = rgb[1];
         rgba[2] = rgb[2];
-        rgba[3] = T::max_value();
+        rgba[3] = T::default_max_value();
 
     }
 }
@@ -323,7 +360,7 @@ impl<T: Primitive + 'static> FromColor<Luma<T>> for Rgba<T> {
         rgba[0] = gray;
         rgba[1] = gray;
         rgba[2] = gray;
-        rgba[3] = T::max_value();
+        rgba[3] = T::default_max_value();
     }
 }
 
@@ -361,6 +398,126 @@ impl<T: Primitive + 'static> FromColor<Luma<T>> for Rgb<T> {
     }
 }
 
+/// FromColor for BGR and BGRA, which swap the red and blue channels of RGB and RGBA
+
+impl<T: Primitive + 'static> FromColor<Bgr<T>> for Rgb<T> {
+    fn from_color(&mut self, other: &Bgr<T>) {
+        let bgr = other.channels();
+        *self = Rgb([bgr[2], bgr[1], bgr[0]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgra<T>> for Rgb<T> {
+    fn from_color(&mut self, other: &Bgra<T>) {
+        let bgra = other.channels();
+        *self = Rgb([bgra[2], bgra[1], bgra[0]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgr<T>> for Rgba<T> {
+    fn from_color(&mut self, other: &Bgr<T>) {
+        let bgr = other.channels();
+        *self = Rgba([bgr[2], bgr[1], bgr[0], T::default_max_value()])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgra<T>> for Rgba<T> {
+    fn from_color(&mut self, other: &Bgra<T>) {
+        let bgra = other.channels();
+        *self = Rgba([bgra[2], bgra[1], bgra[0], bgra[3]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgr<T>> for Luma<T> {
+    fn from_color(&mut self, other: &Bgr<T>) {
+        self.from_color(&other.to_rgb())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgra<T>> for Luma<T> {
+    fn from_color(&mut self, other: &Bgra<T>) {
+        self.from_color(&other.to_rgba())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgr<T>> for LumaA<T> {
+    fn from_color(&mut self, other: &Bgr<T>) {
+        self.from_color(&other.to_rgb())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgra<T>> for LumaA<T> {
+    fn from_color(&mut self, other: &Bgra<T>) {
+        self.from_color(&other.to_rgba())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Rgb<T>> for Bgr<T> {
+    fn from_color(&mut self, other: &Rgb<T>) {
+        let rgb = other.channels();
+        *self = Bgr([rgb[2], rgb[1], rgb[0]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Rgba<T>> for Bgr<T> {
+    fn from_color(&mut self, other: &Rgba<T>) {
+        let rgba = other.channels();
+        *self = Bgr([rgba[2], rgba[1], rgba[0]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Luma<T>> for Bgr<T> {
+    fn from_color(&mut self, other: &Luma<T>) {
+        self.from_color(&other.to_rgb())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<LumaA<T>> for Bgr<T> {
+    fn from_color(&mut self, other: &LumaA<T>) {
+        self.from_color(&other.to_rgb())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgra<T>> for Bgr<T> {
+    fn from_color(&mut self, other: &Bgra<T>) {
+        let bgra = other.channels();
+        *self = Bgr([bgra[0], bgra[1], bgra[2]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Rgb<T>> for Bgra<T> {
+    fn from_color(&mut self, other: &Rgb<T>) {
+        let rgb = other.channels();
+        *self = Bgra([rgb[2], rgb[1], rgb[0], T::default_max_value()])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Rgba<T>> for Bgra<T> {
+    fn from_color(&mut self, other: &Rgba<T>) {
+        let rgba = other.channels();
+        *self = Bgra([rgba[2], rgba[1], rgba[0], rgba[3]])
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Luma<T>> for Bgra<T> {
+    fn from_color(&mut self, other: &Luma<T>) {
+        self.from_color(&other.to_rgba())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<LumaA<T>> for Bgra<T> {
+    fn from_color(&mut self, other: &LumaA<T>) {
+        self.from_color(&other.to_rgba())
+    }
+}
+
+impl<T: Primitive + 'static> FromColor<Bgr<T>> for Bgra<T> {
+    fn from_color(&mut self, other: &Bgr<T>) {
+        let bgr = other.channels();
+        *self = Bgra([bgr[0], bgr[1], bgr[2], T::default_max_value()])
+    }
+}
+
 /// Blends a color inter another one
 pub trait Blend {
     /// Blends a color in-place.
@@ -369,7 +526,7 @@ pub trait Blend {
 
 impl<T: Primitive> Blend for LumaA<T> {
     fn blend(&mut self, other: &LumaA<T>) {
-        let max_t = T::max_value();
+        let max_t = T::default_max_value();
         let max_t = max_t.to_f32().unwrap();
         let (bg_luma, bg_a) = (self.data[0], self.data[1]);
         let (fg_luma, fg_a) = (other.data[0], other.data[1]);
@@ -402,7 +559,7 @@ impl<T: Primitive> Blend for Rgba<T> {
         // http://stackoverflow.com/questions/7438263/alpha-compositing-algorithm-blend-modes#answer-11163848
 
         // First, as we don't know what type our pixel is, we have to convert to floats between 0.0 and 1.0
-        let max_t = T::max_value();
+        let max_t = T::default_max_value();
         let max_t = max_t.to_f32().unwrap();
         let (bg_r, bg_g, bg_b, bg_a) = (self.data[0], self.data[1], self.data[2], self.data[3]);
         let (fg_r, fg_g, fg_b, fg_a) = (other.data[0], other.data[1], other.data[2], other.data[3]);
@@ -438,6 +595,21 @@ impl<T: Primitive> Blend for Rgb<T> {
     }
 }
 
+impl<T: Primitive> Blend for Bgra<T> {
+    fn blend(&mut self, other: &Bgra<T>) {
+        // The channels are blended the same way whatever their order is
+        let mut rgba = Rgba(self.data);
+        Blend::blend(&mut rgba, &Rgba(other.data));
+        *self = Bgra(rgba.data)
+    }
+}
+
+impl<T: Primitive> Blend for Bgr<T> {
+    fn blend(&mut self, other: &Bgr<T>) {
+        *self = *other
+    }
+}
+
 /// Invert a color
 pub trait Invert {
     /// Inverts a color in-place.
@@ -447,7 +619,7 @@ pub trait Invert {
 impl<T: Primitive> Invert for LumaA<T> {
     fn invert(&mut self) {
         let l = self.data;
-        let max = T::max_value();
+        let max = T::default_max_value();
 
         *self = LumaA([max - l[0], l[1]])
 
@@ -458,7 +630,7 @@ impl<T: Primitive> Invert for Luma<T> {
     fn invert(&mut self) {
         let l = self.data;
 
-        let max = T::max_value();
+        let max = T::default_max_value();
         let l1 = max - l[0];
 
         *self = Luma {data: [l1]}
@@ -469,7 +641,7 @@ impl<T: Primitive> Invert for Rgba<T> {
     fn invert(&mut self) {
         let rgba = self.data;
 
-        let max = T::max_value();
+        let max = T::default_max_value();
 
         *self = Rgba([max - rgba[0], max - rgba[1], max - rgba[2], rgba[3]])
     }
@@ -479,7 +651,7 @@ impl<T: Primitive> Invert for Rgb<T> {
     fn invert(&mut self) {
         let rgb = self.data;
 
-        let max = T::max_value();
+        let max = T::default_max_value();
 
         let r1 = max - rgb[0];
         let g1 = max - rgb[1];
@@ -488,3 +660,124 @@ impl<T: Primitive> Invert for Rgb<T> {
         *self = Rgb([r1, g1, b1])
     }
 }
+
+impl<T: Primitive> Invert for Bgra<T> {
+    fn invert(&mut self) {
+        let bgra = self.data;
+
+        let max = T::default_max_value();
+
+        *self = Bgra([max - bgra[0], max - bgra[1], max - bgra[2], bgra[3]])
+    }
+}
+
+impl<T: Primitive> Invert for Bgr<T> {
+    fn invert(&mut self) {
+        let bgr = self.data;
+
+        let max = T::default_max_value();
+
+        *self = Bgr([max - bgr[0], max - bgr[1], max - bgr[2]])
+    }
+}
+
+/// Converts a color between straight and premultiplied alpha
+///
+/// With straight alpha, the color channels hold the color as if the pixel were opaque. With
+/// premultiplied alpha, they hold the color already scaled by the alpha, so that filters can
+/// average neighbouring pixels without transparent ones bleeding their color into the result.
+/// The pixel types do not record which representation they hold, converting twice scales twice.
+pub trait Premultiply {
+    /// Multiplies the color channels by the alpha channel, in-place.
+    fn premultiply(&mut self);
+
+    /// Divides the color channels by the alpha channel, in-place.
+    ///
+    /// Fully transparent pixels have no color left to recover, their color channels become zero.
+    fn unpremultiply(&mut self);
+}
+
+/// Scales ```colors``` by ```alpha```, rounding integer samples
+fn premultiply_channels<T: Primitive>(colors: &mut [T], alpha: T) {
+    let max = T::default_max_value().to_f32().unwrap();
+    let alpha = alpha.to_f32().unwrap() / max;
+    let float = is_float_primitive::<T>();
+    for c in colors {
+        let value = c.to_f32().unwrap() * alpha;
+        *c = NumCast::from(if float { value } else { value.round() }).unwrap();
+    }
+}
+
+/// Divides ```colors``` by ```alpha```, rounding and clamping integer samples
+fn unpremultiply_channels<T: Primitive>(colors: &mut [T], alpha: T) {
+    let max = T::default_max_value().to_f32().unwrap();
+    let alpha = alpha.to_f32().unwrap() / max;
+    let float = is_float_primitive::<T>();
+    for c in colors {
+        let value = if alpha > 0.0 { c.to_f32().unwrap() / alpha } else { 0.0 };
+        *c = NumCast::from(if float { value } else { value.round().min(max) }).unwrap();
+    }
+}
+
+impl<T: Primitive> Premultiply for LumaA<T> {
+    fn premultiply(&mut self) {
+        let alpha = self.data[1];
+        premultiply_channels(&mut self.data[..1], alpha)
+    }
+
+    fn unpremultiply(&mut self) {
+        let alpha = self.data[1];
+        unpremultiply_channels(&mut self.data[..1], alpha)
+    }
+}
+
+impl<T: Primitive> Premultiply for Rgba<T> {
+    fn premultiply(&mut self) {
+        let alpha = self.data[3];
+        premultiply_channels(&mut self.data[..3], alpha)
+    }
+
+    fn unpremultiply(&mut self) {
+        let alpha = self.data[3];
+        unpremultiply_channels(&mut self.data[..3], alpha)
+    }
+}
+
+impl<T: Primitive> Premultiply for Bgra<T> {
+    fn premultiply(&mut self) {
+        let alpha = self.data[3];
+        premultiply_channels(&mut self.data[..3], alpha)
+    }
+
+    fn unpremultiply(&mut self) {
+        let alpha = self.data[3];
+        unpremultiply_channels(&mut self.data[..3], alpha)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LumaA, Premultiply, Rgba};
+
+    #[test]
+    fn premultiply() {
+        let mut pixel = Rgba([255u8, 128, 0, 128]);
+        pixel.premultiply();
+        assert_eq!(pixel, Rgba([128, 64, 0, 128]));
+        // Integer samples lose the precision the alpha scaled away
+        pixel.unpremultiply();
+        assert_eq!(pixel, Rgba([255, 127, 0, 128]));
+
+        let mut transparent = LumaA([200u8, 0]);
+        transparent.premultiply();
+        assert_eq!(transparent, LumaA([0, 0]));
+        transparent.unpremultiply();
+        assert_eq!(transparent, LumaA([0, 0]));
+
+        let mut float = Rgba([1.0f32, 0.5, 0.25, 0.5]);
+        float.premultiply();
+        assert_eq!(float, Rgba([0.5, 0.25, 0.125, 0.5]));
+        float.unpremultiply();
+        assert_eq!(float, Rgba([1.0, 0.5, 0.25, 0.5]));
+    }
+}
@@ -0,0 +1,485 @@
+//! Colors in the HSV, HSL, CIE XYZ, CIE Lab and YCbCr color spaces
+//!
+//! The pixel types of this crate store sRGB samples. The colors of this module describe the same
+//! colors in other spaces, where hue and saturation can be adjusted directly or where distances
+//! between colors follow the perceived differences. They convert from and to `Rgb` pixels of any
+//! sample type, with `from_rgb` and `to_rgb` or with `FromColor`.
+//!
+//! The channels are ```f32``` values. Hues are in degrees from 0 to 360, lightness of Lab from 0
+//! to 100, the other channels from 0 to 1, or from -0.5 to 0.5 for the chroma of YCbCr. Colors
+//! outside the sRGB gamut are clamped when they are converted to `Rgb`.
+//!
+//! These colors deliberately do not implement `Pixel`, so they can not be stored in an
+//! `ImageBuffer`. A pixel type must name the `ColorType` of its samples, which encoders rely on
+//! to write images, and there is none for these spaces: an `ImageBuffer` of them would be saved
+//! as if its channels were RGB. The operations of `Pixel` and of `imageops`, such as `invert`,
+//! `blend` and the averaging of resizing, treat channels as independent intensities, which is
+//! wrong for circular hues and for the signed chroma of Lab and YCbCr. Images are converted
+//! pixel by pixel instead, and stored as `Rgb` again once they have been adjusted.
+
+use num_traits::NumCast;
+
+use color::{FromColor, Rgb};
+use traits::Primitive;
+use super::is_float_primitive;
+use super::transfer::{linear_to_srgb, srgb_to_linear};
+
+/// The white point of sRGB, D65, in CIE XYZ
+const D65: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+/// The matrix from linear sRGB to CIE XYZ
+const RGB_TO_XYZ: [[f32; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+
+/// The matrix from CIE XYZ to linear sRGB
+const XYZ_TO_RGB: [[f32; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+/// The lightness of Lab below which the linear segment of its curve applies, (6/29)^3
+const LAB_EPSILON: f32 = 216.0 / 24389.0;
+
+/// The slope of the linear segment of the curve of Lab, (29/3)^3
+const LAB_KAPPA: f32 = 24389.0 / 27.0;
+
+/// Returns the channels of ```rgb``` from 0 to 1
+fn normalize<T: Primitive>(rgb: &Rgb<T>) -> [f32; 3] {
+    let max = T::default_max_value().to_f32().unwrap();
+    let channel = |c: T| c.to_f32().unwrap() / max;
+    [channel(rgb.data[0]), channel(rgb.data[1]), channel(rgb.data[2])]
+}
+
+/// Returns the `Rgb` pixel of the ```channels``` from 0 to 1, clamped and rounded to integer
+/// samples
+fn denormalize<T: Primitive>(channels: [f32; 3]) -> Rgb<T> {
+    let max = T::default_max_value().to_f32().unwrap();
+    let float = is_float_primitive::<T>();
+    let sample = |c: f32| -> T {
+        let c = c.max(0.0).min(1.0) * max;
+        NumCast::from(if float { c } else { c.round() }).unwrap()
+    };
+    Rgb([sample(channels[0]), sample(channels[1]), sample(channels[2])])
+}
+
+fn transform(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    let row = |r: &[f32; 3]| r[0] * v[0] + r[1] * v[1] + r[2] * v[2];
+    [row(&m[0]), row(&m[1]), row(&m[2])]
+}
+
+/// Returns the hue in degrees, chroma and maximum of the channels of an RGB color
+fn hue_chroma_max(rgb: [f32; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = rgb;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let hue = if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / chroma)
+    } else if max == g {
+        60.0 * ((b - r) / chroma + 2.0)
+    } else {
+        60.0 * ((r - g) / chroma + 4.0)
+    };
+    (if hue < 0.0 { hue + 360.0 } else { hue }, chroma, max)
+}
+
+/// Returns the RGB channels of the ```hue``` in degrees and ```chroma```, to be raised by the
+/// smallest channel ```min```
+fn from_hue_chroma(hue: f32, chroma: f32, min: f32) -> [f32; 3] {
+    let h = (hue % 360.0 + 360.0) % 360.0 / 60.0;
+    let x = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    [r + min, g + min, b + min]
+}
+
+/// A color as its hue, saturation and value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsv {
+    /// The hue in degrees, from 0 to 360
+    pub h: f32,
+    /// The saturation, from 0 to 1
+    pub s: f32,
+    /// The value, the largest of the RGB channels, from 0 to 1
+    pub v: f32,
+}
+
+impl Hsv {
+    /// Returns the HSV color of ```rgb```
+    pub fn from_rgb<T: Primitive>(rgb: &Rgb<T>) -> Hsv {
+        let (h, chroma, v) = hue_chroma_max(normalize(rgb));
+        Hsv {
+            h: h,
+            s: if v == 0.0 { 0.0 } else { chroma / v },
+            v: v,
+        }
+    }
+
+    /// Returns the `Rgb` pixel of this color
+    pub fn to_rgb<T: Primitive>(&self) -> Rgb<T> {
+        let chroma = self.v * self.s;
+        denormalize(from_hue_chroma(self.h, chroma, self.v - chroma))
+    }
+}
+
+/// A color as its hue, saturation and lightness
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hsl {
+    /// The hue in degrees, from 0 to 360
+    pub h: f32,
+    /// The saturation, from 0 to 1
+    pub s: f32,
+    /// The lightness, the mean of the largest and smallest RGB channels, from 0 to 1
+    pub l: f32,
+}
+
+impl Hsl {
+    /// Returns the HSL color of ```rgb```
+    pub fn from_rgb<T: Primitive>(rgb: &Rgb<T>) -> Hsl {
+        let (h, chroma, max) = hue_chroma_max(normalize(rgb));
+        let l = max - chroma / 2.0;
+        Hsl {
+            h: h,
+            s: if l == 0.0 || l == 1.0 { 0.0 } else { chroma / (1.0 - (2.0 * l - 1.0).abs()) },
+            l: l,
+        }
+    }
+
+    /// Returns the `Rgb` pixel of this color
+    pub fn to_rgb<T: Primitive>(&self) -> Rgb<T> {
+        let chroma = (1.0 - (2.0 * self.l - 1.0).abs()) * self.s;
+        denormalize(from_hue_chroma(self.h, chroma, self.l - chroma / 2.0))
+    }
+}
+
+/// A color in CIE XYZ, relative to the D65 white point of sRGB
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Xyz {
+    /// The X tristimulus value
+    pub x: f32,
+    /// The luminance, from 0 to 1
+    pub y: f32,
+    /// The Z tristimulus value
+    pub z: f32,
+}
+
+impl Xyz {
+    /// Returns the XYZ color of ```rgb```, decoding its sRGB samples to linear light
+    pub fn from_rgb<T: Primitive>(rgb: &Rgb<T>) -> Xyz {
+        let rgb = normalize(rgb);
+        let linear = [srgb_to_linear(rgb[0]), srgb_to_linear(rgb[1]), srgb_to_linear(rgb[2])];
+        let [x, y, z] = transform(&RGB_TO_XYZ, linear);
+        Xyz { x: x, y: y, z: z }
+    }
+
+    /// Returns the `Rgb` pixel of this color
+    pub fn to_rgb<T: Primitive>(&self) -> Rgb<T> {
+        let linear = transform(&XYZ_TO_RGB, [self.x, self.y, self.z]);
+        let clamped = |c: f32| linear_to_srgb(c.max(0.0).min(1.0));
+        denormalize([clamped(linear[0]), clamped(linear[1]), clamped(linear[2])])
+    }
+}
+
+/// A color in CIE L\*a\*b\*, relative to the D65 white point of sRGB
+///
+/// Distances between Lab colors follow the perceived differences between them, see `delta_e`
+/// and `delta_e_2000`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lab {
+    /// The lightness, from 0 to 100
+    pub l: f32,
+    /// The position between green, negative, and red, positive
+    pub a: f32,
+    /// The position between blue, negative, and yellow, positive
+    pub b: f32,
+}
+
+impl Lab {
+    /// Returns the Lab color of ```xyz```
+    pub fn from_xyz(xyz: &Xyz) -> Lab {
+        let f = |t: f32| if t > LAB_EPSILON { t.cbrt() } else { (LAB_KAPPA * t + 16.0) / 116.0 };
+        let (fx, fy, fz) = (f(xyz.x / D65[0]), f(xyz.y / D65[1]), f(xyz.z / D65[2]));
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+
+    /// Returns the XYZ color of this color
+    pub fn to_xyz(&self) -> Xyz {
+        let fy = (self.l + 16.0) / 116.0;
+        let fx = fy + self.a / 500.0;
+        let fz = fy - self.b / 200.0;
+        let f = |t: f32| if t * t * t > LAB_EPSILON { t * t * t } else { (116.0 * t - 16.0) / LAB_KAPPA };
+        let y = if self.l > LAB_KAPPA * LAB_EPSILON { fy * fy * fy } else { self.l / LAB_KAPPA };
+        Xyz {
+            x: f(fx) * D65[0],
+            y: y * D65[1],
+            z: f(fz) * D65[2],
+        }
+    }
+
+    /// Returns the Lab color of ```rgb```
+    pub fn from_rgb<T: Primitive>(rgb: &Rgb<T>) -> Lab {
+        Lab::from_xyz(&Xyz::from_rgb(rgb))
+    }
+
+    /// Returns the `Rgb` pixel of this color
+    pub fn to_rgb<T: Primitive>(&self) -> Rgb<T> {
+        self.to_xyz().to_rgb()
+    }
+
+    /// Returns the CIE76 color difference to ```other```, their euclidean distance
+    ///
+    /// A difference of about 2.3 is just noticeable.
+    pub fn delta_e(&self, other: &Lab) -> f32 {
+        let (dl, da, db) = (self.l - other.l, self.a - other.a, self.b - other.b);
+        (dl * dl + da * da + db * db).sqrt()
+    }
+
+    /// Returns the CIEDE2000 color difference to ```other```
+    ///
+    /// It corrects the distance of `delta_e` for the lower sensitivity of the eye to differences
+    /// of saturated colors and for the blue hues, where the euclidean distance is least uniform.
+    pub fn delta_e_2000(&self, other: &Lab) -> f32 {
+        let (l1, a1, b1) = (self.l as f64, self.a as f64, self.b as f64);
+        let (l2, a2, b2) = (other.l as f64, other.a as f64, other.b as f64);
+        let pow7 = |x: f64| x.powi(7);
+        let hue = |b: f64, a: f64| if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            (b.atan2(a).to_degrees() + 360.0) % 360.0
+        };
+
+        let c_mean = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+        let g = 0.5 * (1.0 - (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt());
+        let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+        let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+        let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+        let dl = l2 - l1;
+        let dc = c2 - c1;
+        let dh = if c1 * c2 == 0.0 {
+            0.0
+        } else if (h2 - h1).abs() <= 180.0 {
+            h2 - h1
+        } else if h2 > h1 {
+            h2 - h1 - 360.0
+        } else {
+            h2 - h1 + 360.0
+        };
+        let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+        let l_mean = (l1 + l2) / 2.0;
+        let c_mean = (c1 + c2) / 2.0;
+        let h_mean = if c1 * c2 == 0.0 {
+            h1 + h2
+        } else if (h1 - h2).abs() <= 180.0 {
+            (h1 + h2) / 2.0
+        } else if h1 + h2 < 360.0 {
+            (h1 + h2 + 360.0) / 2.0
+        } else {
+            (h1 + h2 - 360.0) / 2.0
+        };
+
+        let cos = |degrees: f64| degrees.to_radians().cos();
+        let t = 1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean)
+            + 0.32 * cos(3.0 * h_mean + 6.0) - 0.20 * cos(4.0 * h_mean - 63.0);
+        let rotation = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+        let rc = 2.0 * (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt();
+        let sl = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+        let sc = 1.0 + 0.045 * c_mean;
+        let sh = 1.0 + 0.015 * c_mean * t;
+        let rt = -(2.0 * rotation).to_radians().sin() * rc;
+
+        let (dl, dc, dh) = (dl / sl, dc / sc, dh / sh);
+        (dl * dl + dc * dc + dh * dh + rt * dc * dh).sqrt() as f32
+    }
+}
+
+/// A color as its luma and blue and red chroma, with the full range BT.601 coefficients of JPEG
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct YCbCr {
+    /// The luma, from 0 to 1
+    pub y: f32,
+    /// The blue difference chroma, from -0.5 to 0.5
+    pub cb: f32,
+    /// The red difference chroma, from -0.5 to 0.5
+    pub cr: f32,
+}
+
+impl YCbCr {
+    /// Returns the YCbCr color of ```rgb```
+    pub fn from_rgb<T: Primitive>(rgb: &Rgb<T>) -> YCbCr {
+        let [r, g, b] = normalize(rgb);
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        YCbCr {
+            y: y,
+            cb: (b - y) / 1.772,
+            cr: (r - y) / 1.402,
+        }
+    }
+
+    /// Returns the `Rgb` pixel of this color
+    pub fn to_rgb<T: Primitive>(&self) -> Rgb<T> {
+        let r = self.y + 1.402 * self.cr;
+        let b = self.y + 1.772 * self.cb;
+        let g = (self.y - 0.299 * r - 0.114 * b) / 0.587;
+        denormalize([r, g, b])
+    }
+}
+
+macro_rules! rgb_conversions {
+    ($($space:ident),*) => {$(
+        impl<T: Primitive> FromColor<Rgb<T>> for $space {
+            fn from_color(&mut self, other: &Rgb<T>) {
+                *self = $space::from_rgb(other)
+            }
+        }
+
+        impl<T: Primitive> FromColor<$space> for Rgb<T> {
+            fn from_color(&mut self, other: &$space) {
+                *self = other.to_rgb()
+            }
+        }
+    )*}
+}
+
+rgb_conversions!(Hsv, Hsl, Xyz, Lab, YCbCr);
+
+#[cfg(test)]
+mod tests {
+    use super::{Hsl, Hsv, Lab, Xyz, YCbCr};
+    use color::{FromColor, Rgb};
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() <= tolerance, "{:?} is not {:?}", actual, expected);
+        }
+    }
+
+    fn colors() -> Vec<Rgb<u8>> {
+        let steps = [0u8, 1, 37, 100, 128, 200, 254, 255];
+        let mut colors = Vec::new();
+        for &r in &steps {
+            for &g in &steps {
+                for &b in &steps {
+                    colors.push(Rgb([r, g, b]));
+                }
+            }
+        }
+        colors
+    }
+
+    #[test]
+    fn hsv() {
+        let hsv = Hsv::from_rgb(&Rgb([255u8, 0, 0]));
+        assert_close([hsv.h, hsv.s, hsv.v], [0.0, 1.0, 1.0], 1e-6);
+        let hsv = Hsv::from_rgb(&Rgb([0.25f32, 0.5, 0.5]));
+        assert_close([hsv.h, hsv.s, hsv.v], [180.0, 0.5, 0.5], 1e-6);
+        let hsv = Hsv::from_rgb(&Rgb([128u8, 0, 255]));
+        assert_close([hsv.h, hsv.s, hsv.v], [270.1, 1.0, 1.0], 0.1);
+        assert_eq!(Hsv { h: 120.0, s: 1.0, v: 0.5 }.to_rgb(), Rgb([0u8, 128, 0]));
+        assert_eq!(Hsv { h: 480.0, s: 1.0, v: 0.5 }.to_rgb(), Rgb([0u8, 128, 0]));
+        for rgb in colors() {
+            assert_eq!(Hsv::from_rgb(&rgb).to_rgb::<u8>(), rgb);
+        }
+    }
+
+    #[test]
+    fn hsl() {
+        let hsl = Hsl::from_rgb(&Rgb([255u8, 0, 0]));
+        assert_close([hsl.h, hsl.s, hsl.l], [0.0, 1.0, 0.5], 1e-6);
+        let hsl = Hsl::from_rgb(&Rgb([0.25f32, 0.75, 0.75]));
+        assert_close([hsl.h, hsl.s, hsl.l], [180.0, 0.5, 0.5], 1e-6);
+        let hsl = Hsl::from_rgb(&Rgb([255u8, 255, 255]));
+        assert_close([hsl.h, hsl.s, hsl.l], [0.0, 0.0, 1.0], 1e-6);
+        assert_eq!(Hsl { h: 240.0, s: 1.0, l: 0.25 }.to_rgb(), Rgb([0u8, 0, 128]));
+        for rgb in colors() {
+            assert_eq!(Hsl::from_rgb(&rgb).to_rgb::<u8>(), rgb);
+        }
+    }
+
+    #[test]
+    fn xyz() {
+        let white = Xyz::from_rgb(&Rgb([255u8, 255, 255]));
+        assert_close([white.x, white.y, white.z], [0.95047, 1.0, 1.08883], 1e-4);
+        let red = Xyz::from_rgb(&Rgb([255u8, 0, 0]));
+        assert_close([red.x, red.y, red.z], [0.41246, 0.21267, 0.01933], 1e-4);
+        let gray = Xyz::from_rgb(&Rgb([119u8, 119, 119]));
+        assert_close([gray.y, 0.0, 0.0], [0.18447, 0.0, 0.0], 1e-4);
+        for rgb in colors() {
+            assert_eq!(Xyz::from_rgb(&rgb).to_rgb::<u8>(), rgb);
+        }
+    }
+
+    #[test]
+    fn lab() {
+        let white = Lab::from_rgb(&Rgb([255u8, 255, 255]));
+        assert_close([white.l, white.a, white.b], [100.0, 0.0, 0.0], 1e-2);
+        let red = Lab::from_rgb(&Rgb([255u8, 0, 0]));
+        assert_close([red.l, red.a, red.b], [53.2408, 80.0925, 67.2032], 1e-2);
+        let blue = Lab::from_rgb(&Rgb([0u8, 0, 255]));
+        assert_close([blue.l, blue.a, blue.b], [32.2970, 79.1875, -107.8602], 1e-2);
+        let dark = Lab::from_rgb(&Rgb([1u8, 1, 1]));
+        assert_close([dark.l, dark.a, dark.b], [0.2742, 0.0, 0.0], 1e-3);
+        for rgb in colors() {
+            assert_eq!(Lab::from_rgb(&rgb).to_rgb::<u8>(), rgb);
+        }
+    }
+
+    #[test]
+    fn delta_e() {
+        let lab = |l, a, b| Lab { l: l, a: a, b: b };
+        assert_eq!(lab(50.0, 0.0, 0.0).delta_e(&lab(53.0, 4.0, 0.0)), 5.0);
+
+        // Test data of Sharma, Wu and Dalal, "The CIEDE2000 Color-Difference Formula"
+        let pairs = [
+            (lab(50.0, 2.6772, -79.7751), lab(50.0, 0.0, -82.7485), 2.0425),
+            (lab(50.0, -1.3802, -84.2814), lab(50.0, 0.0, -82.7485), 1.0),
+            (lab(50.0, 0.0, 0.0), lab(50.0, -1.0, 2.0), 2.3669),
+            (lab(50.0, 2.49, -0.001), lab(50.0, -2.49, 0.0011), 7.2195),
+            (lab(50.0, 2.5, 0.0), lab(73.0, 25.0, -18.0), 27.1492),
+            (lab(60.2574, -34.0099, 36.2677), lab(60.4626, -34.1751, 39.4387), 1.2644),
+            (lab(22.7233, 20.0904, -46.694), lab(23.0331, 14.973, -42.5619), 2.0373),
+        ];
+        for &(ref first, ref second, expected) in &pairs {
+            assert!((first.delta_e_2000(second) - expected).abs() < 1e-4);
+            assert!((second.delta_e_2000(first) - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn ycbcr() {
+        let red = YCbCr::from_rgb(&Rgb([255u8, 0, 0]));
+        assert_close([red.y, red.cb, red.cr], [0.299, -0.168736, 0.5], 1e-5);
+        let gray = YCbCr::from_rgb(&Rgb([0.5f32, 0.5, 0.5]));
+        assert_close([gray.y, gray.cb, gray.cr], [0.5, 0.0, 0.0], 1e-6);
+        for rgb in colors() {
+            assert_eq!(YCbCr::from_rgb(&rgb).to_rgb::<u8>(), rgb);
+        }
+    }
+
+    #[test]
+    fn from_color() {
+        let mut hsv = Hsv { h: 0.0, s: 0.0, v: 0.0 };
+        hsv.from_color(&Rgb([0u8, 0, 255]));
+        assert_eq!(hsv, Hsv { h: 240.0, s: 1.0, v: 1.0 });
+        let mut rgb = Rgb([0u16, 0, 0]);
+        rgb.from_color(&hsv);
+        assert_eq!(rgb, Rgb([0, 0, 65535]));
+    }
+}
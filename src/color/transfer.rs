@@ -0,0 +1,254 @@
+//! Transfer functions between encoded samples and linear light
+//!
+//! The samples of most images are not proportional to the light they stand for: they are encoded
+//! with a transfer function that spends more of the sample values on dark tones, where the eye is
+//! more sensitive. Averaging encoded samples, as filters, resizing and blending do, darkens edges
+//! and mixes colors wrongly. Decoding the samples to linear light with `to_linear` first, and
+//! encoding the result with `from_linear`, gives the physically correct result.
+//!
+//! The conversions of 8-bit images use tables of the 256 sample values, they are as exact as
+//! calling the transfer function on each sample.
+
+use std::cmp::Ordering;
+
+use buffer::{ImageBuffer, Pixel};
+use color::ColorType;
+use image::GenericImageView;
+use math::utils::unit_to_sample;
+
+/// A transfer function, mapping encoded samples to linear light and back
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransferFunction {
+    /// Samples are linear already
+    Linear,
+    /// The piecewise curve of sRGB, IEC 61966-2-1, a linear segment followed by a power of 2.4
+    Srgb,
+    /// The curve of ITU-R BT.709 and BT.2020 video, a linear segment followed by a power of 0.45
+    Rec709,
+    /// A plain power function, the linear light is the sample raised to this gamma, such as 2.2
+    Gamma(f32),
+}
+
+impl TransferFunction {
+    /// Returns the linear light of the ```sample```, both from 0 to 1
+    ///
+    /// Negative samples are mirrored, samples above 1 follow the curve.
+    pub fn to_linear(&self, sample: f32) -> f32 {
+        if sample < 0.0 {
+            return -self.to_linear(-sample)
+        }
+        match *self {
+            TransferFunction::Linear => sample,
+            TransferFunction::Srgb => srgb_to_linear(sample),
+            TransferFunction::Rec709 => if sample < 4.5 * REC709_BETA {
+                sample / 4.5
+            } else {
+                ((sample + REC709_ALPHA - 1.0) / REC709_ALPHA).powf(1.0 / 0.45)
+            },
+            TransferFunction::Gamma(gamma) => sample.powf(gamma),
+        }
+    }
+
+    /// Returns the sample encoding the ```linear``` light, both from 0 to 1
+    ///
+    /// Negative values are mirrored, values above 1 follow the curve.
+    pub fn from_linear(&self, linear: f32) -> f32 {
+        if linear < 0.0 {
+            return -self.from_linear(-linear)
+        }
+        match *self {
+            TransferFunction::Linear => linear,
+            TransferFunction::Srgb => linear_to_srgb(linear),
+            TransferFunction::Rec709 => if linear < REC709_BETA {
+                4.5 * linear
+            } else {
+                REC709_ALPHA * linear.powf(0.45) - (REC709_ALPHA - 1.0)
+            },
+            TransferFunction::Gamma(gamma) => linear.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// The scale of the power segment of the BT.709 curve, 1.099 rounded in the standard
+///
+/// The precise values of BT.2020 join both segments without a step, which keeps the curve
+/// invertible.
+const REC709_ALPHA: f32 = 1.0992968;
+
+/// The linear light where the BT.709 curve changes from the linear segment to the power segment,
+/// 0.018 rounded in the standard
+const REC709_BETA: f32 = 0.018053968;
+
+/// Returns the linear light of the sRGB ```sample```, both from 0 to 1
+pub fn srgb_to_linear(sample: f32) -> f32 {
+    if sample <= 0.04045 {
+        sample / 12.92
+    } else {
+        ((sample + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Returns the sRGB sample encoding the ```linear``` light, both from 0 to 1
+pub fn linear_to_srgb(linear: f32) -> f32 {
+    if linear <= 0.0031308 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Returns the index of the alpha channel of pixels of type ```P```, if they have one
+fn alpha_index<P: Pixel>() -> Option<usize> {
+    match P::color_type() {
+        ColorType::GrayA(_) | ColorType::RGBA(_) | ColorType::BGRA(_) |
+        ColorType::GrayAF(_) | ColorType::RGBAF(_) => Some(P::channel_count() as usize - 1),
+        _ => None,
+    }
+}
+
+/// Decodes the 8-bit ```image``` with ```function``` to an ```f32``` image of linear light of
+/// the same color model
+///
+/// Alpha channels are scaled to the range 0 to 1 but are not decoded.
+///
+/// # Panics
+///
+/// Panics if the output pixel type has a different number of channels than the input.
+pub fn to_linear<I, P>(image: &I, function: TransferFunction) -> ImageBuffer<P, Vec<f32>>
+    where I: GenericImageView,
+          I::Pixel: Pixel<Subpixel=u8>,
+          P: Pixel<Subpixel=f32> + 'static {
+
+    assert_eq!(I::Pixel::channel_count(), P::channel_count());
+    let alpha = alpha_index::<I::Pixel>();
+    let mut table = [0.0; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = function.to_linear(i as f32 / 255.0);
+    }
+
+    let (width, height) = image.dimensions();
+    let mut out: ImageBuffer<P, Vec<f32>> = ImageBuffer::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let p = image.get_pixel(x, y);
+        for (i, (&c, o)) in p.channels().iter().zip(pixel.channels_mut()).enumerate() {
+            *o = if Some(i) == alpha { c as f32 / 255.0 } else { table[c as usize] };
+        }
+    }
+
+    out
+}
+
+/// Encodes the ```f32``` ```image``` of linear light with ```function``` to an 8-bit image of
+/// the same color model
+///
+/// Each sample is the 8-bit value nearest to the encoded light, values outside of the range 0
+/// to 1 are clamped. Alpha channels are scaled to the range 0 to 255 but are not encoded.
+///
+/// # Panics
+///
+/// Panics if the output pixel type has a different number of channels than the input.
+pub fn from_linear<I, P>(image: &I, function: TransferFunction) -> ImageBuffer<P, Vec<u8>>
+    where I: GenericImageView,
+          I::Pixel: Pixel<Subpixel=f32>,
+          P: Pixel<Subpixel=u8> + 'static {
+
+    assert_eq!(I::Pixel::channel_count(), P::channel_count());
+    let alpha = alpha_index::<I::Pixel>();
+    // The linear light halfway between each pair of neighbouring samples, the encoded value of a
+    // light is the number of halfway points at or below it
+    let mut thresholds = [0.0; 255];
+    for (i, threshold) in thresholds.iter_mut().enumerate() {
+        *threshold = function.to_linear((i as f32 + 0.5) / 255.0);
+    }
+
+    let (width, height) = image.dimensions();
+    let mut out: ImageBuffer<P, Vec<u8>> = ImageBuffer::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let p = image.get_pixel(x, y);
+        for (i, (&c, o)) in p.channels().iter().zip(pixel.channels_mut()).enumerate() {
+            *o = if Some(i) == alpha {
+                unit_to_sample(c)
+            } else {
+                // NaN is below every threshold and maps to zero
+                let order = |t: &f32| t.partial_cmp(&c).unwrap_or(Ordering::Greater);
+                match thresholds.binary_search_by(order) {
+                    Ok(i) => i as u8 + 1,
+                    Err(i) => i as u8,
+                }
+            };
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_linear, to_linear, TransferFunction};
+    use buffer::{ImageBuffer, RgbaImage};
+    use color::{Luma, Rgba};
+
+    const FUNCTIONS: [TransferFunction; 4] = [
+        TransferFunction::Linear,
+        TransferFunction::Srgb,
+        TransferFunction::Rec709,
+        TransferFunction::Gamma(2.2),
+    ];
+
+    #[test]
+    fn round_trip() {
+        for &function in &FUNCTIONS {
+            for i in 0..1001 {
+                let v = i as f32 / 1000.0;
+                let linear = function.to_linear(v);
+                assert!((function.from_linear(linear) - v).abs() < 1e-5, "{:?} {}", function, v);
+                assert!((function.to_linear(-v) + linear).abs() < 1e-6);
+            }
+            assert_eq!(function.to_linear(0.0), 0.0);
+            assert!((function.to_linear(1.0) - 1.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn reference_values() {
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-5;
+        assert!(close(TransferFunction::Srgb.to_linear(0.5), 0.214041));
+        assert!(close(TransferFunction::Srgb.to_linear(0.04), 0.04 / 12.92));
+        assert!(close(TransferFunction::Srgb.from_linear(0.18), 0.461356));
+        assert!(close(TransferFunction::Rec709.from_linear(0.18), 0.408848));
+        assert!(close(TransferFunction::Rec709.from_linear(0.01), 0.045));
+        assert!(close(TransferFunction::Gamma(2.0).to_linear(0.5), 0.25));
+    }
+
+    #[test]
+    fn images() {
+        let image: RgbaImage = ImageBuffer::from_fn(16, 16, |x, y| {
+            let v = (16 * y + x) as u8;
+            Rgba([v, 255 - v, v / 2, v])
+        });
+        for &function in &FUNCTIONS {
+            let linear: ImageBuffer<Rgba<f32>, Vec<f32>> = to_linear(&image, function);
+            let pixel = linear.get_pixel(3, 2);
+            assert_eq!(pixel[0], function.to_linear(35.0 / 255.0));
+            assert_eq!(pixel[3], 35.0 / 255.0);
+
+            let encoded: RgbaImage = from_linear(&linear, function);
+            assert_eq!(encoded.into_raw(), image.clone().into_raw());
+        }
+    }
+
+    #[test]
+    fn nearest_sample() {
+        let values = [-1.0, 0.0, 0.001, 0.0031308, 0.1, 0.5, 0.999, 1.0, 2.0, ::std::f32::NAN];
+        let image = ImageBuffer::from_fn(values.len() as u32, 1, |x, _| Luma([values[x as usize]]));
+        let encoded: ImageBuffer<Luma<u8>, Vec<u8>> = from_linear(&image, TransferFunction::Srgb);
+        for (&v, &sample) in values.iter().zip(encoded.into_raw().iter()) {
+            let expected = if v > 0.0 {
+                (TransferFunction::Srgb.from_linear(v.min(1.0)) * 255.0).round() as u8
+            } else {
+                0
+            };
+            assert_eq!(sample, expected, "{}", v);
+        }
+    }
+}
@@ -0,0 +1,529 @@
+//! Decoding of DDS images
+//!
+//! DDS is the container of DirectX textures. Besides the image it stores its mip levels and
+//! the faces of cube maps or the layers of texture arrays, one after another.
+//!
+//! Block compressed textures in the DXT1, DXT3, DXT5, BC4, BC5 and BC7 formats and uncompressed
+//! textures with up to 32 bits per pixel are supported, with both the legacy header and the DX10
+//! extension. Signed BC4 and BC5 textures are not supported.
+//! Volume textures are not supported.
+//!
+//! # Related Links
+//! * <https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide> - Description of the DDS format
+
+use std::io::{Read, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, LittleEndian};
+
+use color::{ColorType, num_components};
+use dxt::DXTVariant;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+/// The header flag of a valid pitch for uncompressed textures
+const DDSD_PITCH: u32 = 0x8;
+/// The header flag of volume textures
+const DDSD_DEPTH: u32 = 0x80_0000;
+
+/// The pixel format flags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+/// The flags of cube maps and each of their faces, and of volume textures in ```caps2```
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_FACES: u32 = 0xfc00;
+const DDSCAPS2_VOLUME: u32 = 0x20_0000;
+
+/// The DX10 flag of cube maps, which store six faces for every array layer
+const DDS_RESOURCE_MISC_TEXTURECUBE: u32 = 0x4;
+/// The DX10 resource dimension of volume textures
+const DDS_DIMENSION_TEXTURE3D: u32 = 4;
+
+/// The largest number of mip levels of images with 32-bit dimensions
+const MAX_MIP_LEVELS: u32 = 32;
+
+/// The layout of the pixels of a surface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Blocks of 4x4 pixels
+    Dxt(DXTVariant),
+    /// Little endian pixels of ```bytes``` bytes, with a bit mask for each channel of ```color```
+    Masked {
+        bytes: usize,
+        masks: [u32; 4],
+        color: ColorType,
+    },
+}
+
+impl Format {
+    /// Reads a legacy pixel format
+    fn from_pixel_format<R: Read>(r: &mut R) -> ImageResult<Option<Format>> {
+        let size = try!(r.read_u32::<LittleEndian>());
+        let flags = try!(r.read_u32::<LittleEndian>());
+        let mut four_cc = [0; 4];
+        try!(r.read_exact(&mut four_cc));
+        let bit_count = try!(r.read_u32::<LittleEndian>());
+        let mut masks = [0; 4];
+        for mask in &mut masks {
+            *mask = try!(r.read_u32::<LittleEndian>());
+        }
+        if size != 32 {
+            return Err(ImageError::malformed(ImageFormat::DDS, format!("Invalid pixel format size {}", size)))
+        }
+
+        if flags & DDPF_FOURCC != 0 {
+            let variant = match &four_cc {
+                b"DX10" => return Ok(None),
+                b"DXT1" => DXTVariant::DXT1,
+                // The premultiplied variants are decoded the same way
+                b"DXT2" | b"DXT3" => DXTVariant::DXT3,
+                b"DXT4" | b"DXT5" => DXTVariant::DXT5,
+                b"ATI1" | b"BC4U" => DXTVariant::BC4,
+                b"ATI2" | b"BC5U" => DXTVariant::BC5,
+                _ => return Err(ImageError::unsupported(ImageFormat::DDS,
+                    format!("Unsupported DDS format {:?}", String::from_utf8_lossy(&four_cc)))),
+            };
+            return Ok(Some(Format::Dxt(variant)))
+        }
+
+        let alpha = flags & DDPF_ALPHAPIXELS != 0;
+        let (color, masks) = match (flags & (DDPF_RGB | DDPF_LUMINANCE), alpha) {
+            (DDPF_RGB, false) => (ColorType::RGB(8), [masks[0], masks[1], masks[2], 0]),
+            (DDPF_RGB, true) => (ColorType::RGBA(8), masks),
+            (DDPF_LUMINANCE, false) => (ColorType::Gray(8), [masks[0], 0, 0, 0]),
+            (DDPF_LUMINANCE, true) => (ColorType::GrayA(8), [masks[0], masks[3], 0, 0]),
+            _ => return Err(ImageError::unsupported(ImageFormat::DDS,
+                format!("Unsupported DDS pixel format flags {:#x}", flags))),
+        };
+        match bit_count {
+            8 | 16 | 24 | 32 => (),
+            _ => return Err(ImageError::unsupported(ImageFormat::DDS,
+                format!("Unsupported DDS bit count {}", bit_count))),
+        }
+
+        Ok(Some(Format::Masked {
+            bytes: bit_count as usize / 8,
+            masks: masks,
+            color: color,
+        }))
+    }
+
+    /// Maps a DXGI format of the DX10 extension
+    fn from_dxgi_format(format: u32) -> ImageResult<Format> {
+        let masked = |bytes, masks, color| Format::Masked { bytes: bytes, masks: masks, color: color };
+        Ok(match format {
+            // The typeless, unorm and unorm srgb formats
+            70 | 71 | 72 => Format::Dxt(DXTVariant::DXT1),
+            73 | 74 | 75 => Format::Dxt(DXTVariant::DXT3),
+            76 | 77 | 78 => Format::Dxt(DXTVariant::DXT5),
+            79 | 80 => Format::Dxt(DXTVariant::BC4),
+            82 | 83 => Format::Dxt(DXTVariant::BC5),
+            97 | 98 | 99 => Format::Dxt(DXTVariant::BC7),
+            27 | 28 | 29 => masked(4, [0xff, 0xff00, 0xff_0000, 0xff00_0000], ColorType::RGBA(8)),
+            87 | 90 | 91 => masked(4, [0xff_0000, 0xff00, 0xff, 0xff00_0000], ColorType::RGBA(8)),
+            88 | 92 | 93 => masked(4, [0xff_0000, 0xff00, 0xff, 0], ColorType::RGB(8)),
+            60 | 61 => masked(1, [0xff, 0, 0, 0], ColorType::Gray(8)),
+            _ => return Err(ImageError::unsupported(ImageFormat::DDS,
+                format!("Unsupported DXGI format {}", format))),
+        })
+    }
+
+    /// Returns the number of bytes of a row of pixels, or of blocks for block compressed formats
+    fn row_bytes(&self, width: u32) -> usize {
+        match *self {
+            Format::Dxt(variant) => (width as usize + 3) / 4 * variant.encoded_bytes_per_block(),
+            Format::Masked { bytes, .. } => width as usize * bytes,
+        }
+    }
+
+    /// Returns the number of pixel rows in a row of ```row_bytes```
+    fn rows_per_row(&self) -> u32 {
+        match *self {
+            Format::Dxt(_) => 4,
+            Format::Masked { .. } => 1,
+        }
+    }
+
+    fn color(&self) -> ColorType {
+        match *self {
+            Format::Dxt(variant) => variant.color_type(),
+            Format::Masked { color, .. } => color,
+        }
+    }
+
+    /// Decodes a row of ```encoded``` into ```out```, which are ```width``` pixels wide
+    fn decode_row(&self, encoded: &[u8], width: u32, out: &mut [u8]) {
+        match *self {
+            Format::Dxt(variant) => variant.decode_row(encoded, width, out),
+            Format::Masked { bytes, masks, color } => {
+                let channels = num_components(color);
+                for (pixel, target) in encoded.chunks(bytes).zip(out.chunks_mut(channels)) {
+                    let value = pixel.iter().rev().fold(0, |v, &b| v << 8 | b as u32);
+                    for (&mask, t) in masks.iter().zip(target.iter_mut()) {
+                        *t = scale_masked(value, mask);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Extracts the channel ```mask``` of ```value``` and scales it to 8 bits
+fn scale_masked(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0
+    }
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    let v = ((value & mask) >> mask.trailing_zeros()) as u64;
+    ((v * 255 + max / 2) / max) as u8
+}
+
+/// The representation of a DDS decoder
+///
+/// The decoder reads the first mip level of the first face as the image. The other
+/// surfaces are read with ```read_surface```.
+pub struct DDSDecoder<R> {
+    r: R,
+    width: u32,
+    height: u32,
+    /// The stride of the rows of the first mip level
+    pitch: usize,
+    mip_levels: u32,
+    faces: u32,
+    format: Format,
+    /// The length of the header, which is followed by the surfaces
+    header_len: u64,
+    /// The decoded rows of the current row of the surface
+    rows: Vec<u8>,
+    /// The next row to be read
+    row: u32,
+}
+
+impl<R: Read> DDSDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> ImageResult<DDSDecoder<R>> {
+        let mut r = r;
+        let mut magic = [0; 4];
+        try!(r.read_exact(&mut magic));
+        if &magic != b"DDS " {
+            return Err(ImageError::malformed(ImageFormat::DDS, "Not a DDS image".to_string()))
+        }
+
+        let size = try!(r.read_u32::<LittleEndian>());
+        if size != 124 {
+            return Err(ImageError::malformed(ImageFormat::DDS, format!("Invalid DDS header size {}", size)))
+        }
+        let flags = try!(r.read_u32::<LittleEndian>());
+        let height = try!(r.read_u32::<LittleEndian>());
+        let width = try!(r.read_u32::<LittleEndian>());
+        let pitch = try!(r.read_u32::<LittleEndian>());
+        let depth = try!(r.read_u32::<LittleEndian>());
+        let mip_levels = try!(r.read_u32::<LittleEndian>());
+        try!(r.read_exact(&mut [0; 44]));
+        let format = try!(Format::from_pixel_format(&mut r));
+        let _caps = try!(r.read_u32::<LittleEndian>());
+        let caps2 = try!(r.read_u32::<LittleEndian>());
+        try!(r.read_exact(&mut [0; 12]));
+
+        if caps2 & DDSCAPS2_VOLUME != 0 || (flags & DDSD_DEPTH != 0 && depth > 1) {
+            return Err(ImageError::unsupported(ImageFormat::DDS,
+                "DDS volume textures are not supported".to_string()))
+        }
+        let mut faces = if caps2 & DDSCAPS2_CUBEMAP != 0 {
+            (caps2 & DDSCAPS2_CUBEMAP_FACES).count_ones()
+        } else {
+            1
+        };
+        let mut header_len = 128;
+
+        let format = match format {
+            Some(format) => format,
+            None => {
+                let dxgi_format = try!(r.read_u32::<LittleEndian>());
+                let dimension = try!(r.read_u32::<LittleEndian>());
+                let misc_flag = try!(r.read_u32::<LittleEndian>());
+                let array_size = try!(r.read_u32::<LittleEndian>());
+                let _misc_flags2 = try!(r.read_u32::<LittleEndian>());
+                header_len += 20;
+
+                if dimension == DDS_DIMENSION_TEXTURE3D {
+                    return Err(ImageError::unsupported(ImageFormat::DDS,
+                        "DDS volume textures are not supported".to_string()))
+                }
+                let layers = if misc_flag & DDS_RESOURCE_MISC_TEXTURECUBE != 0 { 6 } else { 1 };
+                faces = try!(array_size.max(1).checked_mul(layers).ok_or(ImageError::malformed(ImageFormat::DDS,
+                    "Invalid image dimensions".to_string())));
+                try!(Format::from_dxgi_format(dxgi_format))
+            }
+        };
+
+        if width == 0 || height == 0 || faces == 0 {
+            return Err(ImageError::malformed(ImageFormat::DDS, "Invalid image dimensions".to_string()))
+        }
+        // Many writers leave the mip level count at zero for a single level
+        let mip_levels = mip_levels.max(1);
+        if mip_levels > MAX_MIP_LEVELS {
+            return Err(ImageError::malformed(ImageFormat::DDS,
+                format!("Invalid number of mip levels {}", mip_levels)))
+        }
+        if (width as u64 * height as u64).checked_mul(4).map_or(true, |n| n > usize::max_value() as u64) {
+            return Err(ImageError::malformed(ImageFormat::DDS, "Invalid image dimensions".to_string()))
+        }
+
+        let min_pitch = format.row_bytes(width);
+        let pitch = match format {
+            Format::Masked { .. } if flags & DDSD_PITCH != 0 && pitch as usize >= min_pitch => pitch as usize,
+            _ => min_pitch,
+        };
+
+        Ok(DDSDecoder {
+            r: r,
+            width: width,
+            height: height,
+            pitch: pitch,
+            mip_levels: mip_levels,
+            faces: faces,
+            format: format,
+            header_len: header_len,
+            rows: Vec::new(),
+            row: 0,
+        })
+    }
+
+    /// Returns the number of mip levels of each face, including the full size image
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Returns the number of faces, which are the six faces of a cube map in the order +x, -x,
+    /// +y, -y, +z, -z or the layers of a texture array
+    ///
+    /// Cube maps of the legacy format may store only some of the faces.
+    pub fn faces(&self) -> u32 {
+        self.faces
+    }
+
+    /// Returns the dimensions of the mip level ```level```
+    pub fn level_dimensions(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Returns the stride and the number of rows of the mip level ```level```
+    fn level_layout(&self, level: u32) -> (usize, u32) {
+        let (width, height) = self.level_dimensions(level);
+        let pitch = if level == 0 { self.pitch } else { self.format.row_bytes(width) };
+        let rows_per_row = self.format.rows_per_row();
+        (pitch, (height + rows_per_row - 1) / rows_per_row)
+    }
+
+    /// Returns the number of bytes of the mip level ```level```
+    fn level_len(&self, level: u32) -> u64 {
+        let (pitch, rows) = self.level_layout(level);
+        pitch as u64 * rows as u64
+    }
+
+    /// Decodes the surface of ```width``` by ```height``` pixels in ```encoded```
+    fn decode_surface(&self, encoded: &[u8], width: u32, height: u32, pitch: usize) -> Vec<u8> {
+        let row_len = width as usize * num_components(self.format.color());
+        let rows_per_row = self.format.rows_per_row() as usize;
+        let mut rows = vec![0; row_len * rows_per_row];
+        let mut data = Vec::with_capacity(row_len * height as usize);
+        for (i, row) in encoded.chunks(pitch).enumerate() {
+            self.format.decode_row(row, width, &mut rows);
+            let remaining = height as usize - i * rows_per_row;
+            data.extend_from_slice(&rows[..row_len * remaining.min(rows_per_row)]);
+        }
+        data
+    }
+}
+
+impl<R: Read + Seek> DDSDecoder<R> {
+    /// Reads the mip level ```level``` of the face ```face```
+    ///
+    /// The pixels have the color type of the image and the dimensions returned by
+    /// ```level_dimensions```.
+    pub fn read_surface(&mut self, face: u32, level: u32) -> ImageResult<DecodingResult> {
+        if face >= self.faces || level >= self.mip_levels {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+
+        let face_len: u64 = (0..self.mip_levels).map(|l| self.level_len(l)).sum();
+        let offset = (0..level).map(|l| self.level_len(l)).sum::<u64>() + face as u64 * face_len;
+        try!(self.r.seek(SeekFrom::Start(self.header_len + offset)));
+
+        let (width, height) = self.level_dimensions(level);
+        let (pitch, _) = self.level_layout(level);
+        let len = self.level_len(level);
+        let mut encoded = Vec::new();
+        try!(self.r.by_ref().take(len).read_to_end(&mut encoded));
+        if (encoded.len() as u64) < len {
+            return Err(ImageError::truncated(ImageFormat::DDS))
+        }
+
+        // Return to where the image is read by rows
+        let rows_per_row = self.format.rows_per_row();
+        let next_row = ((self.row + rows_per_row - 1) / rows_per_row) as u64;
+        try!(self.r.seek(SeekFrom::Start(self.header_len + next_row * self.pitch as u64)));
+        Ok(DecodingResult::U8(self.decode_surface(&encoded, width, height, pitch)))
+    }
+}
+
+impl<R: Read> ImageDecoder for DDSDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        Ok(self.format.color())
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize * num_components(self.format.color()))
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let row_len = try!(self.row_len());
+        if buf.len() < row_len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let rows_per_row = self.format.rows_per_row();
+        if self.row % rows_per_row == 0 {
+            let mut encoded = vec![0; self.pitch];
+            try!(self.r.read_exact(&mut encoded));
+            self.rows.resize(row_len * rows_per_row as usize, 0);
+            self.format.decode_row(&encoded, self.width, &mut self.rows);
+        }
+
+        let start = (self.row % rows_per_row) as usize * row_len;
+        buf[..row_len].copy_from_slice(&self.rows[start..start + row_len]);
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let row_len = try!(self.row_len());
+        let mut row = vec![0; row_len];
+        let mut data = Vec::new();
+        while self.row < self.height {
+            try!(self.read_scanline(&mut row));
+            data.extend_from_slice(&row);
+        }
+        Ok(DecodingResult::U8(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use byteorder::{WriteBytesExt, LittleEndian};
+
+    use super::DDSDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    /// Writes a header with the given pixel format, which is followed by ```caps2```
+    fn header(width: u32, height: u32, flags: u32, pitch: u32, mip_levels: u32,
+              pixel_format: &[u32; 8], caps2: u32) -> Vec<u8> {
+        let mut data = b"DDS ".to_vec();
+        for &v in &[124, 0x1007 | flags, height, width, pitch, 0, mip_levels] {
+            data.write_u32::<LittleEndian>(v).unwrap();
+        }
+        data.extend_from_slice(&[0; 44]);
+        for &v in pixel_format {
+            data.write_u32::<LittleEndian>(v).unwrap();
+        }
+        data.write_u32::<LittleEndian>(0x1000).unwrap();
+        data.write_u32::<LittleEndian>(caps2).unwrap();
+        data.extend_from_slice(&[0; 12]);
+        data
+    }
+
+    fn u8_data(result: DecodingResult) -> Vec<u8> {
+        match result {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn uncompressed_with_pitch() {
+        // 16-bit RGB565 pixels in rows padded to four bytes
+        let format = [32, 0x40, 0, 16, 0xf800, 0x7e0, 0x1f, 0];
+        let mut data = header(1, 2, 0x8, 4, 0, &format, 0);
+        data.extend_from_slice(&[0x00, 0xf8, 0xaa, 0xaa, 0x1f, 0x00, 0xaa, 0xaa]);
+        let mut decoder = DDSDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(decoder.mip_levels(), 1);
+        assert_eq!(u8_data(decoder.read_image().unwrap()), vec![255, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn mip_levels_and_cube_faces() {
+        // Luminance and alpha with two mip levels and two cube faces
+        let format = [32, 0x20001, 0, 16, 0xff, 0, 0, 0xff00];
+        let mut data = header(2, 1, 0x20000, 0, 2, &format, 0x200 | 0x400 | 0x1000);
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let mut decoder = DDSDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!((decoder.faces(), decoder.mip_levels()), (2, 2));
+        assert_eq!(decoder.level_dimensions(1), (1, 1));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::GrayA(8));
+        assert_eq!(u8_data(decoder.read_surface(1, 0).unwrap()), vec![7, 8, 9, 10]);
+        assert_eq!(u8_data(decoder.read_surface(1, 1).unwrap()), vec![11, 12]);
+        assert_eq!(u8_data(decoder.read_surface(0, 1).unwrap()), vec![5, 6]);
+        assert!(decoder.read_surface(2, 0).is_err());
+        assert_eq!(u8_data(decoder.read_image().unwrap()), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn dx10_compressed() {
+        // A texture array of two layers of 5x5 BC1 pixels
+        let format = [32, 0x4, 0x30315844, 0, 0, 0, 0, 0];
+        let mut data = header(5, 5, 0, 0, 1, &format, 0);
+        for &v in &[71, 3, 0, 2, 0] {
+            data.write_u32::<LittleEndian>(v).unwrap();
+        }
+        // Four blocks per layer, red for the first layer and blue for the second
+        for _ in 0..4 {
+            data.extend_from_slice(&[0x00, 0xf8, 0x00, 0xf8, 0, 0, 0, 0]);
+        }
+        for _ in 0..4 {
+            data.extend_from_slice(&[0x1f, 0x00, 0x1f, 0x00, 0, 0, 0, 0]);
+        }
+        let mut decoder = DDSDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.faces(), 2);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        let second = u8_data(decoder.read_surface(1, 0).unwrap());
+        assert_eq!(second.len(), 5 * 5 * 4);
+        assert!(second.chunks(4).all(|p| p == [0, 0, 255, 255]));
+        let first = u8_data(decoder.read_image().unwrap());
+        assert_eq!(first.len(), 5 * 5 * 4);
+        assert!(first.chunks(4).all(|p| p == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn two_channel() {
+        let format = [32, 0x4, 0x32495441, 0, 0, 0, 0, 0];
+        let mut data = header(4, 1, 0, 0, 1, &format, 0);
+        data.extend_from_slice(&[10, 10, 0, 0, 0, 0, 0, 0, 20, 20, 0, 0, 0, 0, 0, 0]);
+        let mut decoder = DDSDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(u8_data(decoder.read_image().unwrap()), [10, 20, 0].iter().cycle().take(12).cloned().collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn invalid() {
+        let format = [32, 0x4, 0x31545844, 0, 0, 0, 0, 0];
+        let mut data = header(4, 4, 0, 0, 0, &format, 0);
+        data[0] = b'X';
+        assert!(DDSDecoder::new(Cursor::new(data)).is_err());
+        // A volume texture
+        let data = header(4, 4, 0, 0, 0, &format, 0x200000);
+        assert!(DDSDecoder::new(Cursor::new(data)).is_err());
+        let data = header(0, 4, 0, 0, 0, &format, 0);
+        assert!(DDSDecoder::new(Cursor::new(data)).is_err());
+    }
+}
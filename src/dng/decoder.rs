@@ -0,0 +1,565 @@
+use std::io::Read;
+
+use color::ColorType;
+use image::{decoder_metadata, DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult,
+            Metadata, ParameterErrorKind};
+use imageops::BayerPattern;
+
+use super::ifd::{self, Ifd, Reader};
+use super::ljpeg;
+
+/// The photometric interpretations of raw images
+const PHOTOMETRIC_CFA: u32 = 32803;
+const PHOTOMETRIC_LINEAR_RAW: u32 = 34892;
+
+/// The compressions of raw images
+const UNCOMPRESSED: u32 = 1;
+const LOSSLESS_JPEG: u32 = 7;
+
+/// The arrangement of the color filters over a sensor
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CFAPattern {
+    /// The width of the repeated pattern
+    pub width: u32,
+    /// The height of the repeated pattern
+    pub height: u32,
+    /// The colors of the pattern row by row, with 0 for red, 1 for green and 2 for blue
+    pub colors: Vec<u8>,
+}
+
+/// The layout of the raw image, as stored in its directory
+struct Layout {
+    width: u32,
+    height: u32,
+    samples: usize,
+    bits: u32,
+    compression: u32,
+    tile_width: u32,
+    tile_height: u32,
+    offsets: Vec<u32>,
+    byte_counts: Vec<u32>,
+}
+
+/// The representation of a DNG decoder
+///
+/// Creating the decoder reads the file and its directories, the raw image is decoded when its
+/// rows are read. Its samples are scaled from the black level to the white level of the sensor,
+/// and cropped to the active area.
+///
+/// Mosaic images of color filter arrays are returned as they are, as gray images with one
+/// sample per pixel, whose colors are given by ```cfa_pattern```. Linear raw images, whose
+/// pixels have all colors, are returned as rgb images.
+pub struct DNGDecoder {
+    width: u32,
+    height: u32,
+    /// The left and top of the active area
+    origin: (u32, u32),
+    samples_per_pixel: usize,
+    cfa_pattern: Option<CFAPattern>,
+    orientation: Option<u16>,
+    data: Vec<u8>,
+    /// The directory of the raw image
+    ifd: Ifd,
+    layout: Layout,
+    samples: Option<Vec<u16>>,
+    /// The next row to be returned
+    row: u32,
+}
+
+fn format_error(message: &str) -> ImageError {
+    ImageError::malformed(ImageFormat::DNG, message.to_string())
+}
+
+/// Unpacks the uncompressed samples of ```bits``` bits of a tile at ```offset``` with ```rows```
+/// rows of ```row_samples``` samples
+///
+/// Samples of 16 bits are stored in the byte order of the file, other samples are packed with the
+/// highest bit first and each row starts at a byte.
+fn unpack(reader: &Reader, offset: usize, len: usize, bits: u32, row_samples: usize, rows: usize)
+          -> ImageResult<Vec<u16>> {
+    let row_bytes = (row_samples * bits as usize + 7) / 8;
+    if len < row_bytes * rows {
+        return Err(ImageError::truncated(ImageFormat::DNG))
+    }
+    let mut samples = Vec::with_capacity(row_samples * rows);
+    for y in 0..rows {
+        let start = offset + y * row_bytes;
+        let row = &reader.data[start..start + row_bytes];
+        match bits {
+            8 => samples.extend(row.iter().map(|&v| v as u16)),
+            16 => for i in 0..row_samples {
+                samples.push(try!(reader.u16_at(start + i * 2)));
+            },
+            _ => {
+                let (mut acc, mut count) = (0u32, 0);
+                let mut bytes = row.iter();
+                for _ in 0..row_samples {
+                    while count < bits {
+                        acc = acc << 8 | *bytes.next().unwrap_or(&0) as u32;
+                        count += 8;
+                    }
+                    samples.push((acc >> (count - bits)) as u16 & ((1u32 << bits) - 1) as u16);
+                    count -= bits;
+                }
+            }
+        }
+    }
+    Ok(samples)
+}
+
+impl DNGDecoder {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new<R: Read>(mut r: R) -> ImageResult<DNGDecoder> {
+        let mut data = Vec::new();
+        try!(r.read_to_end(&mut data));
+        let reader = try!(Reader::new(&data));
+        let ifds = try!(reader.read_ifds());
+        let first = try!(ifds.first().ok_or_else(|| format_error("DNG image without directory")));
+        if try!(reader.values(first, ifd::DNG_VERSION)).is_none() {
+            return Err(format_error("Not a DNG image"))
+        }
+        let orientation = try!(reader.values(first, ifd::ORIENTATION)).and_then(|v| v.first().map(|&o| o as u16));
+
+        // The raw image is the main image with a raw photometric interpretation
+        let mut raw = None;
+        for ifd in &ifds {
+            let photometric = try!(reader.value(ifd, ifd::PHOTOMETRIC_INTERPRETATION, 0));
+            let is_raw = photometric == PHOTOMETRIC_CFA || photometric == PHOTOMETRIC_LINEAR_RAW;
+            if is_raw && try!(reader.value(ifd, ifd::NEW_SUBFILE_TYPE, 0)) == 0 {
+                raw = Some((ifd, photometric == PHOTOMETRIC_CFA));
+                break
+            }
+        }
+        let (ifd, is_cfa) = try!(raw.ok_or_else(|| {
+            ImageError::unsupported(ImageFormat::DNG, "DNG image without supported raw image".to_string())
+        }));
+
+        let layout = try!(DNGDecoder::read_layout(&reader, ifd));
+        if is_cfa && layout.samples != 1 {
+            return Err(ImageError::unsupported(ImageFormat::DNG,
+                "Unsupported DNG color filter array".to_string()))
+        }
+        if !is_cfa && layout.samples != 1 && layout.samples != 3 {
+            return Err(ImageError::unsupported(ImageFormat::DNG,
+                format!("Unsupported number of DNG samples {}", layout.samples)))
+        }
+
+        // The active area as top, left, bottom and right
+        let (mut top, mut left, mut bottom, mut right) = (0, 0, layout.height, layout.width);
+        if let Some(area) = try!(reader.values(ifd, ifd::ACTIVE_AREA)) {
+            if area.len() == 4 && area[0] < area[2] && area[1] < area[3]
+                && area[2] <= layout.height && area[3] <= layout.width {
+                top = area[0];
+                left = area[1];
+                bottom = area[2];
+                right = area[3];
+            }
+        }
+
+        let cfa_pattern = if is_cfa {
+            let dim = try!(reader.values(ifd, ifd::CFA_REPEAT_PATTERN_DIM)).unwrap_or_else(|| vec![2, 2]);
+            let colors = try!(reader.values(ifd, ifd::CFA_PATTERN)).unwrap_or_else(Vec::new);
+            if dim.len() != 2 || colors.len() as u64 != dim[0] as u64 * dim[1] as u64 || colors.is_empty() {
+                return Err(format_error("Invalid DNG color filter pattern"))
+            }
+            Some(CFAPattern {
+                width: dim[1],
+                height: dim[0],
+                colors: colors.into_iter().map(|c| c as u8).collect(),
+            })
+        } else {
+            None
+        };
+
+        Ok(DNGDecoder {
+            width: right - left,
+            height: bottom - top,
+            origin: (left, top),
+            samples_per_pixel: layout.samples,
+            cfa_pattern: cfa_pattern,
+            orientation: orientation,
+            data: data,
+            ifd: ifd.clone(),
+            layout: layout,
+            samples: None,
+            row: 0,
+        })
+    }
+
+    /// Decodes the raw image, scaled to the levels of the sensor and cropped to the active area
+    fn decode(&mut self) -> ImageResult<&[u16]> {
+        if self.samples.is_none() {
+            let reader = try!(Reader::new(&self.data));
+            let (ifd, layout) = (&self.ifd, &self.layout);
+            let mut samples = try!(DNGDecoder::read_samples(&reader, layout));
+
+            if let Some(table) = try!(reader.values(ifd, ifd::LINEARIZATION_TABLE)) {
+                if !table.is_empty() {
+                    for sample in &mut samples {
+                        *sample = table[(*sample as usize).min(table.len() - 1)] as u16;
+                    }
+                }
+            }
+
+            let (width, height) = (self.width, self.height);
+            let (left, top) = self.origin;
+            let channels = layout.samples;
+            let black_dim = try!(reader.values(ifd, ifd::BLACK_LEVEL_REPEAT_DIM))
+                .and_then(|dim| if dim.len() == 2 && dim[0] > 0 && dim[1] > 0 { Some((dim[0], dim[1])) } else { None })
+                .unwrap_or((1, 1));
+            let black = try!(reader.float_values(ifd, ifd::BLACK_LEVEL)).unwrap_or_else(|| vec![0.0]);
+            let white = try!(reader.float_values(ifd, ifd::WHITE_LEVEL))
+                .unwrap_or_else(|| vec![((1u64 << layout.bits) - 1) as f64]);
+            if black.is_empty() || white.is_empty() {
+                return Err(format_error("Invalid DNG levels"))
+            }
+
+            let mut scaled = Vec::with_capacity(width as usize * height as usize * channels);
+            for y in 0..height {
+                let source = ((top + y) * layout.width + left) as usize * channels;
+                for (i, &sample) in samples[source..source + width as usize * channels].iter().enumerate() {
+                    let (x, c) = ((i / channels) as u32, i % channels);
+                    // The black level repeats from the top left of the active area
+                    let index = ((y % black_dim.0 * black_dim.1 + x % black_dim.1) as usize * channels + c) % black.len();
+                    let white = white[c % white.len()];
+                    let v = (sample as f64 - black[index]) / (white - black[index]);
+                    scaled.push(if v > 0.0 { (v.min(1.0) * 65535.0 + 0.5) as u16 } else { 0 });
+                }
+            }
+            self.samples = Some(scaled);
+        }
+        Ok(self.samples.as_ref().unwrap())
+    }
+
+    fn read_layout(reader: &Reader, ifd: &Ifd) -> ImageResult<Layout> {
+        let width = try!(reader.value(ifd, ifd::IMAGE_WIDTH, 0));
+        let height = try!(reader.value(ifd, ifd::IMAGE_LENGTH, 0));
+        if width == 0 || height == 0 || width > 0x10000 || height > 0x10000 {
+            return Err(ImageError::malformed(ImageFormat::DNG, "Invalid image dimensions".to_string()))
+        }
+        let samples = try!(reader.value(ifd, ifd::SAMPLES_PER_PIXEL, 1)) as usize;
+        let bits = try!(reader.value(ifd, ifd::BITS_PER_SAMPLE, 1));
+        if bits == 0 || bits > 16 {
+            return Err(ImageError::unsupported(ImageFormat::DNG,
+                format!("Unsupported DNG sample size {}", bits)))
+        }
+        let compression = try!(reader.value(ifd, ifd::COMPRESSION, UNCOMPRESSED));
+        if compression != UNCOMPRESSED && compression != LOSSLESS_JPEG {
+            return Err(ImageError::unsupported(ImageFormat::DNG,
+                format!("Unsupported DNG compression {}", compression)))
+        }
+
+        // Strips are treated as tiles that span the width of the image
+        let (tile_width, tile_height, offsets, byte_counts) = match try!(reader.values(ifd, ifd::TILE_OFFSETS)) {
+            Some(offsets) => (
+                try!(reader.value(ifd, ifd::TILE_WIDTH, 0)),
+                try!(reader.value(ifd, ifd::TILE_LENGTH, 0)),
+                offsets,
+                try!(reader.values(ifd, ifd::TILE_BYTE_COUNTS)).unwrap_or_else(Vec::new),
+            ),
+            None => (
+                width,
+                try!(reader.value(ifd, ifd::ROWS_PER_STRIP, height)).min(height),
+                try!(reader.values(ifd, ifd::STRIP_OFFSETS)).unwrap_or_else(Vec::new),
+                try!(reader.values(ifd, ifd::STRIP_BYTE_COUNTS)).unwrap_or_else(Vec::new),
+            ),
+        };
+        if tile_width == 0 || tile_height == 0 {
+            return Err(format_error("Invalid DNG tile dimensions"))
+        }
+        let tiles = ((width + tile_width - 1) / tile_width) as usize * ((height + tile_height - 1) / tile_height) as usize;
+        if offsets.len() < tiles || byte_counts.len() < tiles {
+            return Err(format_error("Missing DNG tiles"))
+        }
+        for (&offset, &len) in offsets.iter().zip(&byte_counts).take(tiles) {
+            if offset as u64 + len as u64 > reader.data.len() as u64 {
+                return Err(format_error("DNG tile data out of bounds"))
+            }
+            // Uncompressed tiles are complete rows of packed samples
+            let row_bytes = (tile_width as u64 * samples as u64 * bits as u64 + 7) / 8;
+            if compression == UNCOMPRESSED && (len as u64) < row_bytes * tile_height as u64 {
+                return Err(ImageError::truncated(ImageFormat::DNG))
+            }
+        }
+
+        Ok(Layout {
+            width: width,
+            height: height,
+            samples: samples,
+            bits: bits,
+            compression: compression,
+            tile_width: tile_width,
+            tile_height: tile_height,
+            offsets: offsets,
+            byte_counts: byte_counts,
+        })
+    }
+
+    /// Decodes the samples of all tiles or strips of the raw image
+    fn read_samples(reader: &Reader, layout: &Layout) -> ImageResult<Vec<u16>> {
+        let channels = layout.samples;
+        let (tile_width, tile_height) = (layout.tile_width as usize, layout.tile_height as usize);
+        let across = (layout.width as usize + tile_width - 1) / tile_width;
+        let down = (layout.height as usize + tile_height - 1) / tile_height;
+        let row_len = layout.width as usize * channels;
+        let mut samples = vec![0u16; row_len * layout.height as usize];
+        for i in 0..across * down {
+            let (offset, len) = (layout.offsets[i] as usize, layout.byte_counts[i] as usize);
+            if offset as u64 + len as u64 > reader.data.len() as u64 {
+                return Err(format_error("DNG tile data out of bounds"))
+            }
+            let tile = match layout.compression {
+                UNCOMPRESSED => try!(unpack(reader, offset, len, layout.bits, tile_width * channels, tile_height)),
+                _ => {
+                    let image = try!(ljpeg::decode(&reader.data[offset..offset + len]));
+                    // Mosaics are often compressed with several samples of a row in each
+                    // pixel, so only the samples of a row have to match
+                    if image.width as usize * image.components != tile_width * channels
+                        || (image.height as usize) < tile_height {
+                        return Err(format_error("DNG tile does not match its image"))
+                    }
+                    image.samples
+                }
+            };
+
+            // Tiles at the right and bottom edges may extend beyond the image
+            let (x, y) = (i % across * tile_width, i / across * tile_height);
+            let width = tile_width.min(layout.width as usize - x) * channels;
+            for row in 0..tile_height.min(layout.height as usize - y) {
+                let source = row * tile_width * channels;
+                let target = (y + row) * row_len + x * channels;
+                samples[target..target + width].copy_from_slice(&tile[source..source + width]);
+            }
+        }
+        Ok(samples)
+    }
+
+    /// Returns the arrangement of the color filters of mosaic images, relative to the top left
+    /// pixel, or `None` for images that have all colors in each pixel
+    pub fn cfa_pattern(&self) -> Option<&CFAPattern> {
+        self.cfa_pattern.as_ref()
+    }
+
+    /// Returns the Bayer pattern of mosaics with a 2x2 pattern of red, green and blue, which can
+    /// be demosaiced with `imageops::demosaic`
+    pub fn bayer_pattern(&self) -> Option<BayerPattern> {
+        let cfa = match self.cfa_pattern {
+            Some(ref cfa) if cfa.width == 2 && cfa.height == 2 => cfa,
+            _ => return None,
+        };
+        match &cfa.colors[..] {
+            [0, 1, 1, 2] => Some(BayerPattern::RGGB),
+            [2, 1, 1, 0] => Some(BayerPattern::BGGR),
+            [1, 0, 2, 1] => Some(BayerPattern::GRBG),
+            [1, 2, 0, 1] => Some(BayerPattern::GBRG),
+            _ => None,
+        }
+    }
+
+    /// Returns the orientation of the image.
+    ///
+    /// The values are those of the EXIF orientation tag, as in `JPEGDecoder::orientation`.
+    pub fn orientation(&self) -> Option<u16> {
+        self.orientation
+    }
+}
+
+impl ImageDecoder for DNGDecoder {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        Ok(if self.samples_per_pixel == 3 { ColorType::RGB(16) } else { ColorType::Gray(16) })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize * self.samples_per_pixel * 2)
+    }
+
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.orientation = self.orientation;
+        Ok(metadata)
+    }
+
+    /// Reads the next row into ```buf```, with the samples in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let start = self.row as usize * len / 2;
+        let samples = try!(self.decode());
+        for (out, &v) in buf.chunks_mut(2).zip(&samples[start..start + len / 2]) {
+            out[0] = (v >> 8) as u8;
+            out[1] = v as u8;
+        }
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let start = self.row as usize * try!(self.row_len()) / 2;
+        let samples = try!(self.decode())[start..].to_vec();
+        self.row = self.height;
+        Ok(DecodingResult::U16(samples))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::ifd;
+    use super::super::ljpeg;
+
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+
+    /// Writes a little endian DNG file with a single directory of ```entries```, whose raw data
+    /// is ```data``` in a single strip or tile
+    fn dng(entries: &[(u16, u16, &[u32])], data: &[u8]) -> Vec<u8> {
+        let tiled = entries.iter().any(|e| e.0 == ifd::TILE_WIDTH);
+        let len = [data.len() as u32];
+        let mut entries = entries.to_vec();
+        entries.push((ifd::DNG_VERSION, 1, &[1, 4, 0, 0]));
+        entries.push((if tiled { ifd::TILE_OFFSETS } else { ifd::STRIP_OFFSETS }, LONG, &[8]));
+        entries.push((if tiled { ifd::TILE_BYTE_COUNTS } else { ifd::STRIP_BYTE_COUNTS }, LONG, &len));
+        entries.sort_by_key(|e| e.0);
+
+        let mut file = b"II*\0".to_vec();
+        let ifd_offset = 8 + data.len() as u32;
+        file.extend((0..4).map(|i| (ifd_offset >> (i * 8)) as u8));
+        file.extend_from_slice(data);
+
+        let mut values = Vec::new();
+        let mut values_offset = ifd_offset as usize + 2 + entries.len() * 12 + 4;
+        file.extend_from_slice(&[entries.len() as u8, 0]);
+        for &(tag, field_type, entry_values) in &entries {
+            let mut bytes = Vec::new();
+            for &v in entry_values {
+                let size = match field_type { SHORT => 2, LONG => 4, _ => 1 };
+                bytes.extend((0..size).map(|i| (v >> (i * 8)) as u8));
+            }
+            file.extend_from_slice(&[tag as u8, (tag >> 8) as u8, field_type as u8, 0]);
+            file.extend((0..4).map(|i| (entry_values.len() >> (i * 8)) as u8));
+            if bytes.len() <= 4 {
+                bytes.resize(4, 0);
+                file.extend_from_slice(&bytes);
+            } else {
+                file.extend((0..4).map(|i| (values_offset >> (i * 8)) as u8));
+                values_offset += bytes.len();
+                values.extend_from_slice(&bytes);
+            }
+        }
+        file.extend_from_slice(&[0, 0, 0, 0]);
+        file.extend_from_slice(&values);
+        file
+    }
+
+    fn decode(file: &[u8]) -> (DNGDecoder, Vec<u16>) {
+        let mut decoder = DNGDecoder::new(file).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(samples) => (decoder, samples),
+            _ => panic!("Expected 16-bit samples"),
+        }
+    }
+
+    #[test]
+    fn test_uncompressed_cfa() {
+        // A 4x3 mosaic of 16 bits, whose first row and column are masked
+        let raw: Vec<u16> = vec![
+            0, 0, 0, 0,
+            0, 100, 1100, 600,
+            0, 2100, 100, 50,
+        ];
+        let data: Vec<u8> = raw.iter().flat_map(|&v| vec![v as u8, (v >> 8) as u8]).collect();
+        let file = dng(&[
+            (ifd::IMAGE_WIDTH, SHORT, &[4]),
+            (ifd::IMAGE_LENGTH, SHORT, &[3]),
+            (ifd::BITS_PER_SAMPLE, SHORT, &[16]),
+            (ifd::PHOTOMETRIC_INTERPRETATION, SHORT, &[PHOTOMETRIC_CFA]),
+            (ifd::ORIENTATION, SHORT, &[6]),
+            (ifd::CFA_REPEAT_PATTERN_DIM, SHORT, &[2, 2]),
+            (ifd::CFA_PATTERN, 1, &[0, 1, 1, 2]),
+            (ifd::BLACK_LEVEL, SHORT, &[100]),
+            (ifd::WHITE_LEVEL, SHORT, &[2100]),
+            (ifd::ACTIVE_AREA, SHORT, &[1, 1, 3, 4]),
+        ], &data);
+
+        let (mut decoder, samples) = decode(&file);
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(16));
+        assert_eq!(decoder.orientation(), Some(6));
+        assert_eq!(decoder.cfa_pattern(), Some(&CFAPattern { width: 2, height: 2, colors: vec![0, 1, 1, 2] }));
+        assert_eq!(decoder.bayer_pattern(), Some(BayerPattern::RGGB));
+        assert_eq!(samples, vec![0, 32768, 16384, 65535, 0, 0]);
+    }
+
+    #[test]
+    fn test_packed_linear_raw() {
+        // A 2x1 rgb image of 12 bits, with two samples in every three bytes
+        let file = dng(&[
+            (ifd::IMAGE_WIDTH, SHORT, &[2]),
+            (ifd::IMAGE_LENGTH, SHORT, &[1]),
+            (ifd::BITS_PER_SAMPLE, SHORT, &[12, 12, 12]),
+            (ifd::SAMPLES_PER_PIXEL, SHORT, &[3]),
+            (ifd::PHOTOMETRIC_INTERPRETATION, SHORT, &[PHOTOMETRIC_LINEAR_RAW]),
+        ], &[0xff, 0xf0, 0x00, 0x80, 0x00, 0x00, 0x00, 0x0f, 0xff]);
+
+        let (mut decoder, samples) = decode(&file);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(16));
+        assert_eq!(decoder.cfa_pattern(), None);
+        assert_eq!(samples, vec![65535, 0, 32776, 0, 0, 65535]);
+    }
+
+    #[test]
+    fn test_lossless_jpeg_tile() {
+        // A 4x2 mosaic compressed as a 2x2 image of two components, with a linearization table
+        let raw = [0u16, 1, 2, 3, 3, 2, 1, 0];
+        let data = ljpeg::tests::encode(&raw, 2, 2, 2, 1);
+        let file = dng(&[
+            (ifd::IMAGE_WIDTH, SHORT, &[4]),
+            (ifd::IMAGE_LENGTH, SHORT, &[2]),
+            (ifd::BITS_PER_SAMPLE, SHORT, &[16]),
+            (ifd::COMPRESSION, SHORT, &[LOSSLESS_JPEG]),
+            (ifd::PHOTOMETRIC_INTERPRETATION, SHORT, &[PHOTOMETRIC_CFA]),
+            (ifd::TILE_WIDTH, SHORT, &[4]),
+            (ifd::TILE_LENGTH, SHORT, &[2]),
+            (ifd::CFA_PATTERN, 1, &[1, 0, 2, 1]),
+            (ifd::LINEARIZATION_TABLE, SHORT, &[0, 21845, 43690, 65535]),
+        ], &data);
+
+        let (decoder, samples) = decode(&file);
+        assert_eq!(decoder.bayer_pattern(), Some(BayerPattern::GRBG));
+        assert_eq!(samples, vec![0, 21845, 43690, 65535, 65535, 43690, 21845, 0]);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let data = [0u8; 4];
+        let entries: &[(u16, u16, &[u32])] = &[
+            (ifd::IMAGE_WIDTH, SHORT, &[2]),
+            (ifd::IMAGE_LENGTH, SHORT, &[2]),
+            (ifd::BITS_PER_SAMPLE, SHORT, &[8]),
+            (ifd::PHOTOMETRIC_INTERPRETATION, SHORT, &[PHOTOMETRIC_CFA]),
+            (ifd::CFA_PATTERN, 1, &[0, 1, 1, 2]),
+        ];
+        assert!(DNGDecoder::new(&dng(entries, &data)[..]).is_ok());
+
+        // A plain TIFF file without a DNG version
+        let mut file = dng(entries, &data);
+        let pos = file.windows(2).position(|w| w == [0x12, 0xc6]).unwrap();
+        file[pos] = 0x13;
+        assert!(DNGDecoder::new(&file[..]).is_err());
+
+        // Missing raw data and directories
+        let file = dng(entries, &data[..3]);
+        assert!(DNGDecoder::new(&file[..]).is_err());
+        assert!(DNGDecoder::new(&b"II*\0\0\0\0\0"[..]).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+//! Reading of the image file directories of a DNG file held in memory
+
+use std::collections::HashMap;
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+/// The tags that are used to find and decode the raw image
+pub const NEW_SUBFILE_TYPE: u16 = 254;
+pub const IMAGE_WIDTH: u16 = 256;
+pub const IMAGE_LENGTH: u16 = 257;
+pub const BITS_PER_SAMPLE: u16 = 258;
+pub const COMPRESSION: u16 = 259;
+pub const PHOTOMETRIC_INTERPRETATION: u16 = 262;
+pub const STRIP_OFFSETS: u16 = 273;
+pub const ORIENTATION: u16 = 274;
+pub const SAMPLES_PER_PIXEL: u16 = 277;
+pub const ROWS_PER_STRIP: u16 = 278;
+pub const STRIP_BYTE_COUNTS: u16 = 279;
+pub const TILE_WIDTH: u16 = 322;
+pub const TILE_LENGTH: u16 = 323;
+pub const TILE_OFFSETS: u16 = 324;
+pub const TILE_BYTE_COUNTS: u16 = 325;
+pub const SUB_IFDS: u16 = 330;
+pub const CFA_REPEAT_PATTERN_DIM: u16 = 33421;
+pub const CFA_PATTERN: u16 = 33422;
+pub const DNG_VERSION: u16 = 50706;
+pub const LINEARIZATION_TABLE: u16 = 50712;
+pub const BLACK_LEVEL_REPEAT_DIM: u16 = 50713;
+pub const BLACK_LEVEL: u16 = 50714;
+pub const WHITE_LEVEL: u16 = 50717;
+pub const ACTIVE_AREA: u16 = 50829;
+
+/// The most directories that are read, which also breaks cycles
+const MAX_IFDS: usize = 64;
+
+#[derive(Clone, Copy, Debug)]
+struct Entry {
+    field_type: u16,
+    count: u32,
+    /// The offset of the values in the file
+    offset: usize,
+}
+
+/// An image file directory
+#[derive(Clone, Debug)]
+pub struct Ifd {
+    entries: HashMap<u16, Entry>,
+}
+
+/// The byte order of a file and the file itself
+#[derive(Clone, Copy)]
+pub struct Reader<'a> {
+    pub data: &'a [u8],
+    big_endian: bool,
+}
+
+fn truncated() -> ImageError {
+    ImageError::malformed(ImageFormat::DNG, "Truncated DNG directory".to_string())
+}
+
+impl<'a> Reader<'a> {
+    /// Checks the header of the TIFF file ```data```
+    pub fn new(data: &'a [u8]) -> ImageResult<Reader<'a>> {
+        let big_endian = match data.get(..4) {
+            Some(b"MM\0*") => true,
+            Some(b"II*\0") => false,
+            _ => return Err(ImageError::malformed(ImageFormat::DNG, "Not a DNG image".to_string())),
+        };
+        Ok(Reader { data: data, big_endian: big_endian })
+    }
+
+    pub fn u16_at(&self, pos: usize) -> ImageResult<u16> {
+        let b = try!(self.data.get(pos..pos + 2).ok_or_else(truncated));
+        Ok(if self.big_endian {
+            (b[0] as u16) << 8 | b[1] as u16
+        } else {
+            (b[1] as u16) << 8 | b[0] as u16
+        })
+    }
+
+    pub fn u32_at(&self, pos: usize) -> ImageResult<u32> {
+        let (a, b) = (try!(self.u16_at(pos)) as u32, try!(self.u16_at(pos + 2)) as u32);
+        Ok(if self.big_endian { a << 16 | b } else { b << 16 | a })
+    }
+
+    fn read_ifd(&self, offset: usize) -> ImageResult<(Ifd, usize)> {
+        let count = try!(self.u16_at(offset)) as usize;
+        let mut entries = HashMap::new();
+        for i in 0..count {
+            let pos = offset + 2 + i * 12;
+            let field_type = try!(self.u16_at(pos + 2));
+            let count = try!(self.u32_at(pos + 4));
+            let size = match field_type {
+                1 | 2 | 6 | 7 => 1,
+                3 | 8 => 2,
+                4 | 9 | 11 | 13 => 4,
+                5 | 10 | 12 => 8,
+                // Unknown types are skipped
+                _ => continue,
+            };
+            // Values of up to four bytes are stored in the entry itself
+            let offset = if count as u64 * size <= 4 { pos + 8 } else { try!(self.u32_at(pos + 8)) as usize };
+            if offset as u64 + count as u64 * size > self.data.len() as u64 {
+                return Err(truncated())
+            }
+            entries.insert(try!(self.u16_at(pos)), Entry { field_type: field_type, count: count, offset: offset });
+        }
+        let next = try!(self.u32_at(offset + 2 + count * 12)) as usize;
+        Ok((Ifd { entries: entries }, next))
+    }
+
+    /// Reads the chain of directories from the header, and the directories referenced by
+    /// their ```SubIFDs``` tags, in the order they are found
+    pub fn read_ifds(&self) -> ImageResult<Vec<Ifd>> {
+        let mut ifds = Vec::new();
+        let mut pending = vec![try!(self.u32_at(4)) as usize];
+        while let Some(offset) = pending.pop() {
+            if offset == 0 {
+                continue
+            }
+            if ifds.len() == MAX_IFDS {
+                return Err(ImageError::malformed(ImageFormat::DNG, "Too many DNG directories".to_string()))
+            }
+            let (ifd, next) = try!(self.read_ifd(offset));
+            pending.push(next);
+            if let Some(sub_ifds) = try!(self.values(&ifd, SUB_IFDS)) {
+                pending.extend(sub_ifds.iter().rev().map(|&offset| offset as usize));
+            }
+            ifds.push(ifd);
+        }
+        Ok(ifds)
+    }
+
+    /// Returns the values of ```tag``` in ```ifd``` as unsigned integers
+    pub fn values(&self, ifd: &Ifd, tag: u16) -> ImageResult<Option<Vec<u32>>> {
+        let entry = match ifd.entries.get(&tag) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+        let mut values = Vec::with_capacity(entry.count as usize);
+        for i in 0..entry.count as usize {
+            values.push(match entry.field_type {
+                1 | 2 | 6 | 7 => self.data[entry.offset + i] as u32,
+                3 | 8 => try!(self.u16_at(entry.offset + i * 2)) as u32,
+                4 | 9 | 13 => try!(self.u32_at(entry.offset + i * 4)),
+                _ => return Err(ImageError::malformed(ImageFormat::DNG,
+                    format!("Invalid DNG type for tag {}", tag))),
+            });
+        }
+        Ok(Some(values))
+    }
+
+    /// Returns the values of ```tag``` in ```ifd``` as floating point numbers
+    pub fn float_values(&self, ifd: &Ifd, tag: u16) -> ImageResult<Option<Vec<f64>>> {
+        let entry = match ifd.entries.get(&tag) {
+            Some(entry) => *entry,
+            None => return Ok(None),
+        };
+        let mut values = Vec::with_capacity(entry.count as usize);
+        for i in 0..entry.count as usize {
+            values.push(match entry.field_type {
+                5 => {
+                    let pos = entry.offset + i * 8;
+                    try!(self.u32_at(pos)) as f64 / try!(self.u32_at(pos + 4)).max(1) as f64
+                }
+                10 => {
+                    let pos = entry.offset + i * 8;
+                    try!(self.u32_at(pos)) as i32 as f64 / (try!(self.u32_at(pos + 4)) as i32 as f64)
+                }
+                11 => f32::from_bits(try!(self.u32_at(entry.offset + i * 4))) as f64,
+                12 => {
+                    let pos = entry.offset + i * 8;
+                    let (a, b) = (try!(self.u32_at(pos)) as u64, try!(self.u32_at(pos + 4)) as u64);
+                    f64::from_bits(if self.big_endian { a << 32 | b } else { b << 32 | a })
+                }
+                _ => return self.values(ifd, tag).map(|v| v.map(|v| v.into_iter().map(|v| v as f64).collect())),
+            });
+        }
+        Ok(Some(values))
+    }
+
+    /// Returns the first value of ```tag```, or ```default``` if ```ifd``` does not have it
+    pub fn value(&self, ifd: &Ifd, tag: u16, default: u32) -> ImageResult<u32> {
+        Ok(try!(self.values(ifd, tag)).and_then(|v| v.first().cloned()).unwrap_or(default))
+    }
+}
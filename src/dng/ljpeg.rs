@@ -0,0 +1,358 @@
+//! Decoding of lossless JPEG, as used for the compressed raw data of DNG files
+//!
+//! Only the lossless process with Huffman coding is supported, which codes the difference of
+//! each sample to a prediction from its neighbours.
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+/// A Huffman table, decoded with the code boundaries of each length
+struct HuffmanTable {
+    /// The largest code of each length, or -1 if there is none
+    max_code: [i32; 17],
+    /// The index in ```values``` of the first code of each length, minus that code
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(counts: &[u8], values: &[u8]) -> HuffmanTable {
+        let mut table = HuffmanTable {
+            max_code: [-1; 17],
+            offset: [0; 17],
+            values: values.to_vec(),
+        };
+        let (mut code, mut index) = (0i32, 0i32);
+        for length in 1..17 {
+            let count = counts[length - 1] as i32;
+            table.offset[length] = index - code;
+            code += count;
+            index += count;
+            if count > 0 {
+                table.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        table
+    }
+}
+
+/// Reads bits from the entropy coded data, removing the stuffed zero bytes
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u64,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn fill(&mut self) {
+        while self.count <= 56 {
+            let byte = match self.data.get(self.pos) {
+                // A marker ends the data, which is padded with zeros
+                Some(&0xff) if self.data.get(self.pos + 1) != Some(&0) => 0,
+                Some(&0xff) => {
+                    self.pos += 2;
+                    0xff
+                }
+                Some(&byte) => {
+                    self.pos += 1;
+                    byte
+                }
+                None => 0,
+            };
+            self.bits |= (byte as u64) << (56 - self.count);
+            self.count += 8;
+        }
+    }
+
+    fn read(&mut self, count: u8) -> u32 {
+        if count == 0 {
+            return 0
+        }
+        if self.count < count {
+            self.fill();
+        }
+        let value = (self.bits >> (64 - count)) as u32;
+        self.bits <<= count;
+        self.count -= count;
+        value
+    }
+
+    fn decode(&mut self, table: &HuffmanTable) -> ImageResult<u8> {
+        let mut code = 0i32;
+        for length in 1..17 {
+            code = code << 1 | self.read(1) as i32;
+            if code <= table.max_code[length] {
+                let index = (table.offset[length] + code) as usize;
+                return table.values.get(index).cloned()
+                    .ok_or_else(|| ImageError::malformed(ImageFormat::DNG,
+                        "Invalid lossless JPEG code".to_string()))
+            }
+        }
+        Err(ImageError::malformed(ImageFormat::DNG, "Invalid lossless JPEG code".to_string()))
+    }
+
+    /// Skips to the restart marker that follows the current position
+    fn restart(&mut self) -> ImageResult<()> {
+        self.bits = 0;
+        self.count = 0;
+        while self.pos + 1 < self.data.len() {
+            if self.data[self.pos] == 0xff && self.data[self.pos + 1] & 0xf8 == 0xd0 {
+                self.pos += 2;
+                return Ok(())
+            }
+            self.pos += 1;
+        }
+        Err(ImageError::malformed(ImageFormat::DNG, "Missing lossless JPEG restart marker".to_string()))
+    }
+}
+
+/// A decoded lossless JPEG image
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub components: usize,
+    /// The interleaved samples
+    pub samples: Vec<u16>,
+}
+
+fn format_error(message: &str) -> ImageError {
+    ImageError::malformed(ImageFormat::DNG, message.to_string())
+}
+
+/// Decodes the lossless JPEG image ```data```
+pub fn decode(data: &[u8]) -> ImageResult<Image> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return Err(format_error("Not a lossless JPEG image"))
+    }
+    let mut tables: [Option<HuffmanTable>; 4] = [None, None, None, None];
+    let mut frame = None;
+    let mut restart_interval = 0;
+    let mut pos = 2;
+    loop {
+        if pos + 4 > data.len() || data[pos] != 0xff {
+            return Err(format_error("Truncated lossless JPEG image"))
+        }
+        let marker = data[pos + 1];
+        let len = (data[pos + 2] as usize) << 8 | data[pos + 3] as usize;
+        if len < 2 || pos + 2 + len > data.len() {
+            return Err(format_error("Truncated lossless JPEG image"))
+        }
+        let segment = &data[pos + 4..pos + 2 + len];
+        pos += 2 + len;
+        match marker {
+            0xc4 => {
+                let mut rest = segment;
+                while rest.len() >= 17 {
+                    let count: usize = rest[1..17].iter().map(|&c| c as usize).sum();
+                    if rest.len() < 17 + count {
+                        return Err(format_error("Truncated lossless JPEG Huffman table"))
+                    }
+                    tables[(rest[0] & 3) as usize] = Some(HuffmanTable::new(&rest[1..17], &rest[17..17 + count]));
+                    rest = &rest[17 + count..];
+                }
+            }
+            0xc3 => {
+                if segment.len() < 6 || segment.len() < 6 + segment[5] as usize * 3 {
+                    return Err(format_error("Truncated lossless JPEG frame"))
+                }
+                let precision = segment[0];
+                let height = (segment[1] as u32) << 8 | segment[2] as u32;
+                let width = (segment[3] as u32) << 8 | segment[4] as u32;
+                let ids: Vec<u8> = segment[6..6 + segment[5] as usize * 3].chunks(3).map(|c| c[0]).collect();
+                if precision < 2 || precision > 16 || ids.is_empty() || width == 0 || height == 0 {
+                    return Err(format_error("Invalid lossless JPEG frame"))
+                }
+                frame = Some((precision, width, height, ids));
+            }
+            0xc0...0xc2 | 0xc5...0xc7 | 0xc9...0xcb | 0xcd...0xcf => {
+                return Err(ImageError::unsupported(ImageFormat::DNG,
+                    "Only lossless JPEG is supported".to_string()))
+            }
+            0xdd if segment.len() >= 2 => restart_interval = (segment[0] as usize) << 8 | segment[1] as usize,
+            0xda => {
+                let (precision, width, height, ids) = try!(frame.ok_or_else(|| format_error("Missing lossless JPEG frame")));
+                let count = segment.first().cloned().unwrap_or(0) as usize;
+                if segment.len() < 4 + count * 2 || count != ids.len() {
+                    return Err(ImageError::unsupported(ImageFormat::DNG,
+                        "Only interleaved lossless JPEG is supported".to_string()))
+                }
+                let mut component_tables = Vec::with_capacity(count);
+                for c in segment[1..1 + count * 2].chunks(2) {
+                    let table = try!(tables[(c[1] >> 4 & 3) as usize].as_ref()
+                        .ok_or_else(|| format_error("Missing lossless JPEG Huffman table")));
+                    component_tables.push(table);
+                }
+                let predictor = segment[1 + count * 2];
+                let point_transform = segment[3 + count * 2] & 0xf;
+                if predictor < 1 || predictor > 7 || point_transform >= precision {
+                    return Err(format_error("Invalid lossless JPEG scan"))
+                }
+                // Every sample takes at least one bit
+                if width as u64 * height as u64 * count as u64 > (data.len() - pos) as u64 * 8 {
+                    return Err(format_error("Truncated lossless JPEG scan"))
+                }
+                let mut reader = BitReader { data: &data[pos..], pos: 0, bits: 0, count: 0 };
+                let samples = try!(decode_scan(&mut reader, &component_tables, width as usize, height as usize,
+                                               precision, predictor, point_transform, restart_interval));
+                return Ok(Image {
+                    width: width,
+                    height: height,
+                    components: count,
+                    samples: samples,
+                })
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Decodes the differences of all samples and undoes their prediction
+fn decode_scan(reader: &mut BitReader, tables: &[&HuffmanTable], width: usize, height: usize,
+               precision: u8, predictor: u8, point_transform: u8, restart_interval: usize) -> ImageResult<Vec<u16>> {
+    let components = tables.len();
+    let row_len = width * components;
+    let initial = 1i32 << (precision - point_transform - 1);
+    let mask = (1i32 << (precision - point_transform)) - 1;
+    let mut samples = vec![0u16; row_len * height];
+    let mut restart_row = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let mcu = y * width + x;
+            if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+                try!(reader.restart());
+                restart_row = y;
+            }
+            for c in 0..components {
+                let category = try!(reader.decode(tables[c]));
+                let diff = match category {
+                    0 => 0,
+                    16 => 32768,
+                    1...15 => {
+                        let bits = reader.read(category) as i32;
+                        // Values with a cleared highest bit are negative
+                        if bits < 1 << (category - 1) { bits - (1 << category) + 1 } else { bits }
+                    }
+                    _ => return Err(format_error("Invalid lossless JPEG difference")),
+                };
+
+                let i = y * row_len + x * components + c;
+                let left = || samples[i - components] as i32;
+                let above = || samples[i - row_len] as i32;
+                // Predictions restart at the first row after a restart marker
+                let prediction = if y == restart_row && x == 0 {
+                    initial
+                } else if y == restart_row {
+                    left()
+                } else if x == 0 {
+                    above()
+                } else {
+                    let corner = samples[i - row_len - components] as i32;
+                    match predictor {
+                        1 => left(),
+                        2 => above(),
+                        3 => corner,
+                        4 => left() + above() - corner,
+                        5 => left() + ((above() - corner) >> 1),
+                        6 => above() + ((left() - corner) >> 1),
+                        _ => (left() + above()) >> 1,
+                    }
+                };
+                samples[i] = ((prediction + diff) & mask) as u16;
+            }
+        }
+    }
+    if point_transform > 0 {
+        for sample in &mut samples {
+            *sample <<= point_transform;
+        }
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::decode;
+
+    /// Writes ```value``` with ```count``` bits
+    fn put(bits: &mut Vec<bool>, value: u32, count: u8) {
+        for i in (0..count).rev() {
+            bits.push(value >> i & 1 == 1);
+        }
+    }
+
+    /// Encodes ```samples``` as lossless JPEG with the predictor ```predictor```, using a table
+    /// that codes each category with five bits
+    pub fn encode(samples: &[u16], width: usize, height: usize, components: usize, predictor: u8) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8, 0xff, 0xc4, 0, 2 + 17 + 17, 0];
+        data.extend_from_slice(&[0, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        data.extend((0..17).map(|v| v as u8));
+        data.extend_from_slice(&[0xff, 0xc3, 0, 8 + 3 * components as u8, 16]);
+        data.extend_from_slice(&[(height >> 8) as u8, height as u8, (width >> 8) as u8, width as u8]);
+        data.push(components as u8);
+        for c in 0..components {
+            data.extend_from_slice(&[c as u8 + 1, 0x11, 0]);
+        }
+        data.extend_from_slice(&[0xff, 0xda, 0, 6 + 2 * components as u8, components as u8]);
+        for c in 0..components {
+            data.extend_from_slice(&[c as u8 + 1, 0]);
+        }
+        data.extend_from_slice(&[predictor, 0, 0]);
+
+        let row_len = width * components;
+        let mut bits = Vec::new();
+        for (i, &sample) in samples.iter().enumerate() {
+            let (x, y) = (i % row_len / components, i / row_len);
+            let s = |i: usize| samples[i] as i32;
+            let prediction = match (x, y) {
+                (0, 0) => 1 << 15,
+                (_, 0) => s(i - components),
+                (0, _) => s(i - row_len),
+                _ => match predictor {
+                    1 => s(i - components),
+                    2 => s(i - row_len),
+                    _ => (s(i - components) + s(i - row_len)) >> 1,
+                },
+            };
+            let diff = ((sample as i32 - prediction) as i16) as i32;
+            let category = (32 - diff.abs().leading_zeros()) as u8;
+            put(&mut bits, category as u32, 5);
+            if category == 16 {
+                continue
+            }
+            let value = if diff < 0 { diff - 1 + (1 << category) } else { diff };
+            put(&mut bits, value as u32, category);
+        }
+        while bits.len() % 8 != 0 {
+            bits.push(true);
+        }
+        for byte in bits.chunks(8) {
+            let byte = byte.iter().fold(0u8, |b, &bit| b << 1 | bit as u8);
+            data.push(byte);
+            if byte == 0xff {
+                data.push(0);
+            }
+        }
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    }
+
+    #[test]
+    fn predictors() {
+        let samples: Vec<u16> = (0..6 * 4 * 2).map(|i| (i * 2731 % 65536) as u16).collect();
+        for &predictor in &[1, 2, 7] {
+            let image = decode(&encode(&samples, 6, 4, 2, predictor)).unwrap();
+            assert_eq!((image.width, image.height, image.components), (6, 4, 2));
+            assert_eq!(image.samples, samples);
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(decode(&[0xff, 0xd8, 0xff, 0xda, 0, 8, 1, 1, 0, 1, 0, 0]).is_err());
+        // Baseline JPEG
+        assert!(decode(&[0xff, 0xd8, 0xff, 0xc0, 0, 11, 8, 0, 1, 0, 1, 1, 1, 0x11, 0]).is_err());
+        let data = encode(&[1, 2, 3, 4], 2, 2, 1, 1);
+        assert!(decode(&data[..30]).is_err());
+    }
+}
@@ -0,0 +1,19 @@
+//! Decoding of DNG Images
+//!
+//! Digital Negatives store the raw sensor data of cameras in a TIFF/EP structure. The raw image
+//! is usually a mosaic of a color filter array, either uncompressed or compressed with lossless
+//! JPEG.
+//!
+//! Only the raw samples are decoded. Color calibration, white balance and lens corrections
+//! are left to the application, as is the default crop.
+//!
+//! # Related Links
+//! * <https://helpx.adobe.com/camera-raw/digital-negative.html> - The DNG specification
+//! * <https://www.w3.org/Graphics/JPEG/itu-t81.pdf> - The JPEG specification, with the lossless process
+//!
+
+pub use self::decoder::{DNGDecoder, CFAPattern};
+
+mod decoder;
+mod ifd;
+mod ljpeg;
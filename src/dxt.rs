@@ -0,0 +1,588 @@
+//! Decoding of DXT (S3TC) and BC compressed blocks
+//!
+//! Block compression stores blocks of 4x4 pixels in 8 or 16 bytes. The color variants decode to
+//! 8-bit RGBA, BC4 to 8-bit gray and BC5 to 8-bit RGB with the two channels in red and green.
+//!
+//! # Related Links
+//! * <https://www.khronos.org/registry/OpenGL/extensions/EXT/EXT_texture_compression_s3tc.txt> - Description of the S3TC block formats
+//! * <https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_texture_compression_bptc.txt> - Description of the BC7 block format
+
+use std::io::Read;
+use byteorder::{ByteOrder, LittleEndian};
+
+use color::{ColorType, num_components};
+use image::{DecodingResult, ImageDecoder, ImageError, ImageResult, ParameterErrorKind};
+
+/// The block compression variants
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DXTVariant {
+    /// Four colors per block, or three colors and transparent black (BC1)
+    DXT1,
+    /// Four colors per block and 4-bit alpha values (BC2)
+    DXT3,
+    /// Four colors per block and interpolated alpha values (BC3)
+    DXT5,
+    /// A single channel of interpolated values
+    BC4,
+    /// Two channels of interpolated values, as used for normal maps
+    BC5,
+    /// Up to three sets of endpoints per block in one of eight modes, with or without alpha
+    BC7,
+}
+
+impl DXTVariant {
+    /// Returns the number of bytes of an encoded block of 4x4 pixels
+    pub fn encoded_bytes_per_block(&self) -> usize {
+        match *self {
+            DXTVariant::DXT1 | DXTVariant::BC4 => 8,
+            DXTVariant::DXT3 | DXTVariant::DXT5 | DXTVariant::BC5 | DXTVariant::BC7 => 16,
+        }
+    }
+
+    /// Returns the color type of the decoded pixels
+    pub fn color_type(&self) -> ColorType {
+        match *self {
+            DXTVariant::BC4 => ColorType::Gray(8),
+            DXTVariant::BC5 => ColorType::RGB(8),
+            _ => ColorType::RGBA(8),
+        }
+    }
+
+    /// Decodes a row of blocks in ```encoded``` into four rows of pixels of the color type
+    /// returned by ```color_type``` in ```out```
+    ///
+    /// The rows are ```width``` pixels wide, so that the pixels of the last block that are beyond
+    /// ```width``` are dropped. ```encoded``` has to contain at least ```(width + 3) / 4```
+    /// blocks and ```out``` has to hold at least ```4 * width``` pixels.
+    pub fn decode_row(&self, encoded: &[u8], width: u32, out: &mut [u8]) {
+        let width = width as usize;
+        let channels = num_components(self.color_type());
+        let block_len = self.encoded_bytes_per_block();
+        let mut pixels = [[0; 4]; 16];
+        for (bx, block) in encoded.chunks(block_len).take((width + 3) / 4).enumerate() {
+            match *self {
+                DXTVariant::DXT1 => decode_color_block(block, &mut pixels, false),
+                DXTVariant::DXT3 => {
+                    decode_color_block(&block[8..], &mut pixels, true);
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        pixel[3] = ((block[i / 2] >> (i % 2 * 4)) & 0xf) * 17;
+                    }
+                }
+                DXTVariant::DXT5 => {
+                    decode_color_block(&block[8..], &mut pixels, true);
+                    let mut alpha = [0; 16];
+                    decode_alpha_block(&block[..8], &mut alpha);
+                    for (pixel, &a) in pixels.iter_mut().zip(alpha.iter()) {
+                        pixel[3] = a;
+                    }
+                }
+                DXTVariant::BC4 => {
+                    let mut values = [0; 16];
+                    decode_alpha_block(block, &mut values);
+                    for (pixel, &v) in pixels.iter_mut().zip(values.iter()) {
+                        pixel[0] = v;
+                    }
+                }
+                DXTVariant::BC5 => {
+                    let (mut red, mut green) = ([0; 16], [0; 16]);
+                    decode_alpha_block(&block[..8], &mut red);
+                    decode_alpha_block(&block[8..], &mut green);
+                    for (i, pixel) in pixels.iter_mut().enumerate() {
+                        *pixel = [red[i], green[i], 0, 255];
+                    }
+                }
+                DXTVariant::BC7 => decode_bc7_block(block, &mut pixels),
+            }
+
+            for (i, pixel) in pixels.iter().enumerate() {
+                let x = bx * 4 + i % 4;
+                if x < width {
+                    let start = (i / 4 * width + x) * channels;
+                    out[start..start + channels].copy_from_slice(&pixel[..channels]);
+                }
+            }
+        }
+    }
+}
+
+/// Expands a RGB565 color to 8 bits per channel
+fn rgb565(color: u16) -> [u8; 4] {
+    let (r, g, b) = ((color >> 11) as u8, (color >> 5) as u8 & 0x3f, color as u8 & 0x1f);
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+/// Decodes the 8 byte color part of a block into ```pixels```
+///
+/// The block interpolates two colors if ```four_colors``` is set or the first color is larger.
+/// Otherwise the third color is halfway between them and the fourth color is transparent black.
+fn decode_color_block(block: &[u8], pixels: &mut [[u8; 4]; 16], four_colors: bool) {
+    let c0 = LittleEndian::read_u16(&block[0..2]);
+    let c1 = LittleEndian::read_u16(&block[2..4]);
+    let (p0, p1) = (rgb565(c0), rgb565(c1));
+    let mut palette = [p0, p1, [0; 4], [0; 4]];
+    for i in 0..3 {
+        let (a, b) = (p0[i] as u16, p1[i] as u16);
+        if four_colors || c0 > c1 {
+            palette[2][i] = ((2 * a + b) / 3) as u8;
+            palette[3][i] = ((a + 2 * b) / 3) as u8;
+        } else {
+            palette[2][i] = ((a + b) / 2) as u8;
+        }
+    }
+    palette[2][3] = 255;
+    if four_colors || c0 > c1 {
+        palette[3][3] = 255;
+    }
+
+    let indices = LittleEndian::read_u32(&block[4..8]);
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        *pixel = palette[(indices >> (i * 2)) as usize & 3];
+    }
+}
+
+/// Decodes an 8 byte block of interpolated 8-bit values, as used for the DXT5 alpha channel
+/// and the channels of BC4 and BC5
+pub fn decode_alpha_block(block: &[u8], values: &mut [u8; 16]) {
+    let (a0, a1) = (block[0] as u16, block[1] as u16);
+    let mut palette = [a0 as u8, a1 as u8, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for i in 1..7 {
+            palette[i + 1] = (((7 - i as u16) * a0 + i as u16 * a1) / 7) as u8;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (((5 - i as u16) * a0 + i as u16 * a1) / 5) as u8;
+        }
+    }
+
+    // Sixteen 3-bit indices in six bytes
+    let indices = LittleEndian::read_uint(&block[2..8], 6);
+    for (i, value) in values.iter_mut().enumerate() {
+        *value = palette[(indices >> (i * 3)) as usize & 7];
+    }
+}
+
+/// The layout of a BC7 mode
+struct BC7Mode {
+    subsets: usize,
+    partition_bits: usize,
+    rotation_bits: usize,
+    index_selection_bits: usize,
+    color_bits: usize,
+    alpha_bits: usize,
+    /// A p-bit, the shared lowest bit of all channels, for each endpoint
+    endpoint_pbits: bool,
+    /// A p-bit for both endpoints of each subset
+    shared_pbits: bool,
+    index_bits: usize,
+    /// The bits of the second set of indices, which is used for either the colors or alpha
+    index2_bits: usize,
+}
+
+macro_rules! bc7_mode {
+    ($ns:expr, $pb:expr, $rb:expr, $isb:expr, $cb:expr, $ab:expr, $epb:expr, $spb:expr, $ib:expr, $ib2:expr) => {
+        BC7Mode {
+            subsets: $ns, partition_bits: $pb, rotation_bits: $rb, index_selection_bits: $isb,
+            color_bits: $cb, alpha_bits: $ab, endpoint_pbits: $epb, shared_pbits: $spb,
+            index_bits: $ib, index2_bits: $ib2,
+        }
+    }
+}
+
+static BC7_MODES: [BC7Mode; 8] = [
+    bc7_mode!(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    bc7_mode!(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    bc7_mode!(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    bc7_mode!(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    bc7_mode!(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    bc7_mode!(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    bc7_mode!(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    bc7_mode!(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+/// The subset of each pixel of the partitions into two subsets, one bit per pixel
+static BC7_PARTITIONS_2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+/// The subset of each pixel of the partitions into three subsets, two bits per pixel
+static BC7_PARTITIONS_3: [u32; 64] = [
+    0xaa685050, 0x6a5a5040, 0x5a5a4200, 0x5450a0a8, 0xa5a50000, 0xa0a05050, 0x5555a0a0, 0x5a5a5050,
+    0xaa550000, 0xaa555500, 0xaaaa5500, 0x90909090, 0x94949494, 0xa4a4a4a4, 0xa9a59450, 0x2a0a4250,
+    0xa5945040, 0x0a425054, 0xa5a5a500, 0x55a0a0a0, 0xa8a85454, 0x6a6a4040, 0xa4a45000, 0x1a1a0500,
+    0x0050a4a4, 0xaaa59090, 0x14696914, 0x69691400, 0xa08585a0, 0xaa821414, 0x50a4a450, 0x6a5a0200,
+    0xa9a58000, 0x5090a0a8, 0xa8a09050, 0x24242424, 0x00aa5500, 0x24924924, 0x24499224, 0x50a50a50,
+    0x500aa550, 0xaaaa4444, 0x66660000, 0xa5a0a5a0, 0x50a050a0, 0x69286928, 0x44aaaa44, 0x66666600,
+    0xaa444444, 0x54a854a8, 0x95809580, 0x96969600, 0xa85454a8, 0x80959580, 0xaa141414, 0x96960000,
+    0xaaaa1414, 0xa05050a0, 0xa0a5a5a0, 0x96000000, 0x40804080, 0xa9a8a9a8, 0xaaaaaa44, 0x2a4a5254,
+];
+
+/// The pixels of the second subset of the two subset partitions whose indices lack the highest bit
+static BC7_ANCHORS_2: [u8; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15,
+    15, 2, 8, 2, 2, 8, 8, 15, 2, 8, 2, 2, 8, 8, 2, 2,
+    15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6,
+    6, 2, 6, 8, 15, 15, 2, 2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// The anchor pixels of the second subset of the three subset partitions
+static BC7_ANCHORS_3_SECOND: [u8; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3,
+    3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5, 15, 15,
+    8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15,
+    3, 15, 5, 5, 5, 8, 5, 10, 5, 10, 8, 13, 15, 12, 3, 3,
+];
+
+/// The anchor pixels of the third subset of the three subset partitions
+static BC7_ANCHORS_3_THIRD: [u8; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8,
+    15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6, 10, 15, 15, 10, 8,
+    15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8,
+    15, 3, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+/// The interpolation weights in 64ths for indices of two, three and four bits
+static BC7_WEIGHTS_2: [u16; 4] = [0, 21, 43, 64];
+static BC7_WEIGHTS_3: [u16; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+static BC7_WEIGHTS_4: [u16; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+/// Reads fields of up to 8 bits from a block, starting at the lowest bit of the first byte
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, bits: usize) -> u8 {
+        let mut value = 0;
+        for i in 0..bits {
+            let bit = (self.data[self.pos / 8] >> (self.pos % 8)) & 1;
+            value |= bit << i;
+            self.pos += 1;
+        }
+        value
+    }
+}
+
+/// Expands a value of ```bits``` bits to 8 bits by repeating its highest bits
+fn expand_bits(v: u8, bits: usize) -> u8 {
+    if bits >= 8 {
+        return v
+    }
+    let v = v << (8 - bits);
+    v | v >> bits
+}
+
+fn bc7_interpolate(e0: u8, e1: u8, index: u8, bits: usize) -> u8 {
+    let weight = match bits {
+        2 => BC7_WEIGHTS_2[index as usize],
+        3 => BC7_WEIGHTS_3[index as usize],
+        _ => BC7_WEIGHTS_4[index as usize],
+    };
+    (((64 - weight) * e0 as u16 + weight * e1 as u16 + 32) >> 6) as u8
+}
+
+/// Decodes a 16 byte BC7 block into ```pixels```
+///
+/// Blocks with an invalid mode decode to transparent black.
+fn decode_bc7_block(block: &[u8], pixels: &mut [[u8; 4]; 16]) {
+    // The mode is the position of the lowest set bit
+    let mode = match (0..8).find(|&m| block[0] & (1 << m) != 0) {
+        Some(mode) => mode,
+        None => {
+            *pixels = [[0; 4]; 16];
+            return
+        }
+    };
+    let m = &BC7_MODES[mode];
+    let mut bits = BitReader { data: block, pos: mode + 1 };
+    let partition = bits.read(m.partition_bits) as usize;
+    let rotation = bits.read(m.rotation_bits);
+    let index_selection = bits.read(m.index_selection_bits);
+
+    let endpoint_count = m.subsets * 2;
+    let mut endpoints = [[0u8; 4]; 6];
+    for c in 0..3 {
+        for endpoint in &mut endpoints[..endpoint_count] {
+            endpoint[c] = bits.read(m.color_bits);
+        }
+    }
+    for endpoint in &mut endpoints[..endpoint_count] {
+        endpoint[3] = bits.read(m.alpha_bits);
+    }
+
+    let has_pbits = m.endpoint_pbits || m.shared_pbits;
+    if has_pbits {
+        for e in 0..endpoint_count {
+            // Shared p-bits are read for the first endpoint of each subset
+            let pbit = if m.endpoint_pbits || e % 2 == 0 { bits.read(1) } else { endpoints[e - 1][0] & 1 };
+            for v in &mut endpoints[e] {
+                *v = *v << 1 | pbit;
+            }
+        }
+    }
+    let (color_bits, alpha_bits) = (m.color_bits + has_pbits as usize, m.alpha_bits + has_pbits as usize);
+    for endpoint in &mut endpoints[..endpoint_count] {
+        for v in &mut endpoint[..3] {
+            *v = expand_bits(*v, color_bits);
+        }
+        endpoint[3] = if m.alpha_bits == 0 { 255 } else { expand_bits(endpoint[3], alpha_bits) };
+    }
+
+    let subset = |i: usize| match m.subsets {
+        1 => 0,
+        2 => (BC7_PARTITIONS_2[partition] >> i) as usize & 1,
+        _ => (BC7_PARTITIONS_3[partition] >> (i * 2)) as usize & 3,
+    };
+    // The highest bit of the index of the first pixel of each subset is implied to be zero
+    let is_anchor = |i: usize| i == 0 || match m.subsets {
+        2 => i == BC7_ANCHORS_2[partition] as usize,
+        3 => i == BC7_ANCHORS_3_SECOND[partition] as usize || i == BC7_ANCHORS_3_THIRD[partition] as usize,
+        _ => false,
+    };
+    let mut indices = [0; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        *index = bits.read(m.index_bits - is_anchor(i) as usize);
+    }
+    let mut indices2 = [0; 16];
+    if m.index2_bits > 0 {
+        for (i, index) in indices2.iter_mut().enumerate() {
+            *index = bits.read(m.index2_bits - (i == 0) as usize);
+        }
+    }
+
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let s = subset(i);
+        let (e0, e1) = (endpoints[s * 2], endpoints[s * 2 + 1]);
+        let ((color_index, color_index_bits), (alpha_index, alpha_index_bits)) = if m.index2_bits == 0 {
+            ((indices[i], m.index_bits), (indices[i], m.index_bits))
+        } else if index_selection == 0 {
+            ((indices[i], m.index_bits), (indices2[i], m.index2_bits))
+        } else {
+            ((indices2[i], m.index2_bits), (indices[i], m.index_bits))
+        };
+        for c in 0..3 {
+            pixel[c] = bc7_interpolate(e0[c], e1[c], color_index, color_index_bits);
+        }
+        pixel[3] = bc7_interpolate(e0[3], e1[3], alpha_index, alpha_index_bits);
+        if rotation > 0 {
+            pixel.swap(rotation as usize - 1, 3);
+        }
+    }
+}
+
+/// The representation of a decoder for raw DXT compressed data
+///
+/// The blocks are expected row by row without any header, as the dimensions and the
+/// variant are stored by the container.
+pub struct DxtDecoder<R> {
+    r: R,
+    width: u32,
+    height: u32,
+    variant: DXTVariant,
+    /// The decoded rows of the current row of blocks
+    rows: Vec<u8>,
+    /// The next row to be read
+    row: u32,
+}
+
+impl<R: Read> DxtDecoder<R> {
+    /// Create a new decoder that decodes the blocks of an image of ```width``` by ```height```
+    /// pixels from the stream ```r```
+    pub fn new(r: R, width: u32, height: u32, variant: DXTVariant) -> ImageResult<DxtDecoder<R>> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+
+        Ok(DxtDecoder {
+            r: r,
+            width: width,
+            height: height,
+            variant: variant,
+            rows: Vec::new(),
+            row: 0,
+        })
+    }
+}
+
+impl<R: Read> ImageDecoder for DxtDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        Ok(self.variant.color_type())
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize * num_components(self.variant.color_type()))
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let row_len = try!(self.row_len());
+        if buf.len() < row_len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        if self.row % 4 == 0 {
+            let mut encoded = vec![0; (self.width as usize + 3) / 4 * self.variant.encoded_bytes_per_block()];
+            try!(self.r.read_exact(&mut encoded));
+            self.rows.resize(row_len * 4, 0);
+            self.variant.decode_row(&encoded, self.width, &mut self.rows);
+        }
+
+        let start = (self.row % 4) as usize * row_len;
+        buf[..row_len].copy_from_slice(&self.rows[start..start + row_len]);
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let mut row = vec![0; try!(self.row_len())];
+        let mut data = Vec::new();
+        while self.row < self.height {
+            try!(self.read_scanline(&mut row));
+            data.extend_from_slice(&row);
+        }
+        Ok(DecodingResult::U8(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{decode_alpha_block, DXTVariant, DxtDecoder};
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn decode(data: &[u8], width: u32, height: u32, variant: DXTVariant) -> Vec<u8> {
+        let mut decoder = DxtDecoder::new(Cursor::new(data.to_vec()), width, height, variant).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn dxt1() {
+        // Red and blue with the interpolated colors, and black and white with gray and
+        // transparent black, of which only the first column is in the image
+        let data = [
+            0x00, 0xf8, 0x1f, 0x00, 0b11_10_01_00, 0, 0, 0,
+            0x00, 0x00, 0xff, 0xff, 0b01_00_11_10, 0xff, 0xff, 0xff,
+        ];
+        let image = decode(&data, 5, 2, DXTVariant::DXT1);
+        assert_eq!(&image[..20], &[
+            255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255, 85, 0, 170, 255, 127, 127, 127, 255,
+        ]);
+        assert_eq!(&image[20..], &[
+            255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn dxt3_and_dxt5() {
+        let color = [0x00, 0x00, 0xff, 0xff, 0, 0, 0, 0];
+        let mut dxt3 = vec![0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe];
+        dxt3.extend_from_slice(&color);
+        let image = decode(&dxt3, 4, 4, DXTVariant::DXT3);
+        let alpha: Vec<u8> = image.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(alpha, (0..16).map(|a| a * 17).collect::<Vec<u8>>());
+        assert!(image.chunks(4).all(|p| p[..3] == [0, 0, 0]));
+
+        let mut dxt5 = vec![255, 0, 0b10_001_000, 0b1_100_011_0, 0b111_110_10, 0, 0, 0];
+        dxt5.extend_from_slice(&color);
+        let image = decode(&dxt5, 4, 4, DXTVariant::DXT5);
+        let alpha: Vec<u8> = image.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(&alpha[..8], &[255, 0, 218, 182, 145, 109, 72, 36]);
+    }
+
+    #[test]
+    fn alpha_with_six_values() {
+        // Indices zero to seven
+        let mut values = [0; 16];
+        decode_alpha_block(&[50, 100, 0b10_001_000, 0b1_100_011_0, 0b111_110_10, 0, 0, 0], &mut values);
+        assert_eq!(&values[..8], &[50, 100, 60, 70, 80, 90, 0, 255]);
+    }
+
+    #[test]
+    fn bc4_and_bc5() {
+        let red = [255, 0, 0b10_001_000, 0b1_100_011_0, 0b111_110_10, 0, 0, 0];
+        let green = [50, 100, 0b10_001_000, 0b1_100_011_0, 0b111_110_10, 0, 0, 0];
+        let mut decoder = DxtDecoder::new(Cursor::new(red.to_vec()), 3, 1, DXTVariant::BC4).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+        assert_eq!(decode(&red, 3, 1, DXTVariant::BC4), vec![255, 0, 218]);
+
+        let mut bc5 = red.to_vec();
+        bc5.extend_from_slice(&green);
+        assert_eq!(decode(&bc5, 3, 1, DXTVariant::BC5), vec![255, 50, 0, 0, 100, 0, 218, 60, 0]);
+    }
+
+    /// Packs fields of values and their number of bits into a BC7 block
+    fn bc7_block(fields: &[(u32, usize)]) -> Vec<u8> {
+        let mut block = vec![0; 16];
+        let mut pos = 0;
+        for &(value, bits) in fields {
+            for i in 0..bits {
+                block[pos / 8] |= (((value >> i) & 1) as u8) << (pos % 8);
+                pos += 1;
+            }
+        }
+        assert_eq!(pos, 128);
+        block
+    }
+
+    #[test]
+    fn bc7_single_subset() {
+        // Mode 6 with 7-bit endpoints and p-bits, and the index of each pixel being its position
+        let mut fields = vec![(1 << 6, 7), (127, 7), (0, 7), (64, 7), (0, 7), (0, 7), (0, 7),
+                              (127, 7), (0, 7), (1, 1), (0, 1), (0, 3)];
+        fields.extend((1..16).map(|i| (i, 4)));
+        let image = decode(&bc7_block(&fields), 4, 4, DXTVariant::BC7);
+        assert_eq!(&image[..4], &[255, 129, 1, 255]);
+        assert_eq!(&image[32..36], &[120, 60, 0, 120]);
+        assert_eq!(&image[60..], &[0, 0, 0, 0]);
+
+        // Mode 5 with separate alpha indices, and alpha swapped with red
+        let fields = [(1 << 5, 6), (1, 2), (127, 7), (0, 7), (0, 7), (0, 7), (0, 7), (0, 7),
+                      (64, 8), (64, 8), (0, 31), (0, 31)];
+        let image = decode(&bc7_block(&fields), 4, 4, DXTVariant::BC7);
+        assert!(image.chunks(4).all(|p| p == [64, 0, 0, 255]));
+
+        // No mode bit is set
+        assert_eq!(decode(&[0; 16], 4, 4, DXTVariant::BC7), vec![0; 64]);
+    }
+
+    #[test]
+    fn bc7_partitions() {
+        // Mode 1 with two subsets in the first partition, red on the left and blue on the right
+        let fields = [(0b10, 2), (0, 6),
+                      (63, 6), (63, 6), (0, 6), (0, 6), (0, 6), (0, 6), (0, 6), (0, 6),
+                      (0, 6), (0, 6), (63, 6), (63, 6), (1, 1), (1, 1), (0, 23), (0, 23)];
+        let image = decode(&bc7_block(&fields), 4, 4, DXTVariant::BC7);
+        for (i, pixel) in image.chunks(4).enumerate() {
+            let expected = if i % 4 < 2 { [255, 2, 2, 255] } else { [2, 2, 255, 255] };
+            assert_eq!(pixel, expected);
+        }
+
+        // The first pixel and the anchors are in the subsets whose indices they start
+        for p in 0..64 {
+            assert_eq!(super::BC7_PARTITIONS_2[p] & 1, 0);
+            assert_eq!((super::BC7_PARTITIONS_2[p] >> super::BC7_ANCHORS_2[p]) & 1, 1);
+            assert_eq!(super::BC7_PARTITIONS_3[p] & 3, 0);
+            assert_eq!((super::BC7_PARTITIONS_3[p] >> (super::BC7_ANCHORS_3_SECOND[p] * 2)) & 3, 1);
+            assert_eq!((super::BC7_PARTITIONS_3[p] >> (super::BC7_ANCHORS_3_THIRD[p] * 2)) & 3, 2);
+        }
+    }
+}
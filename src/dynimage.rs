@@ -1,13 +1,15 @@
 use std::io;
 use std::io::{Write, Seek, BufRead, BufReader, BufWriter};
+use std::ffi::OsStr;
 use std::path::Path;
 use std::fs::File;
 use std::iter;
 use std::ascii::AsciiExt;
 use num_iter;
+use byteorder::{BigEndian, ByteOrder};
 
-#[cfg(feature = "ppm")]
-use ppm;
+#[cfg(feature = "pnm")]
+use pnm;
 #[cfg(feature = "gif_codec")]
 use gif;
 #[cfg(feature = "webp")]
@@ -26,19 +28,54 @@ use bmp;
 use ico;
 #[cfg(feature = "hdr")]
 use hdr;
+#[cfg(feature = "exr")]
+use exr;
+#[cfg(feature = "qoi")]
+use qoi;
+#[cfg(feature = "farbfeld")]
+use farbfeld;
+#[cfg(feature = "dds")]
+use dds;
+#[cfg(feature = "avif")]
+use avif;
+#[cfg(feature = "heif")]
+use heif;
+#[cfg(feature = "sgi")]
+use sgi;
+#[cfg(feature = "xbm")]
+use xbm;
+#[cfg(feature = "xpm")]
+use xpm;
+#[cfg(feature = "dng")]
+use dng;
 
 use color;
-use buffer::{ImageBuffer, ConvertBuffer, Pixel, GrayImage, GrayAlphaImage, RgbImage, RgbaImage};
+use color::FromColor;
+use color::icc::{Profile, Transform};
+use buffer::{ImageBuffer, ConvertBuffer, Pixel, GrayImage, GrayAlphaImage, RgbImage, RgbaImage,
+             Gray16Image, GrayAlpha16Image, Rgb16Image, Rgba16Image, Rgb32FImage, Rgba32FImage};
 use imageops;
+use math::utils::{sample_to_unit, unit_to_sample};
+use traits::Primitive;
+use registry;
 use image;
 use image:: {
     GenericImage,
+    GenericImageView,
     ImageDecoder,
+    ImageEncoder,
     ImageResult,
     ImageFormat,
+    Limits,
+    DecodingResult,
+    ImageFormatHint,
+    ParameterErrorKind,
+    UnsupportedFeature,
 };
 
-use image::DecodingResult::{U8};
+use image::DecodingResult::{U8, U16, F32};
+use animation::{Frame, Frames, LoopCount};
+use monitor::Monitor;
 
 /// A Dynamic Image
 #[derive(Clone)]
@@ -54,6 +91,24 @@ pub enum DynamicImage {
 
     /// Each pixel in this image is 8-bit Rgb with alpha
     ImageRgba8(RgbaImage),
+
+    /// Each pixel in this image is 16-bit Luma
+    ImageLuma16(Gray16Image),
+
+    /// Each pixel in this image is 16-bit Luma with alpha
+    ImageLumaA16(GrayAlpha16Image),
+
+    /// Each pixel in this image is 16-bit Rgb
+    ImageRgb16(Rgb16Image),
+
+    /// Each pixel in this image is 16-bit Rgb with alpha
+    ImageRgba16(Rgba16Image),
+
+    /// Each pixel in this image is 32-bit floating point Rgb
+    ImageRgb32F(Rgb32FImage),
+
+    /// Each pixel in this image is 32-bit floating point Rgb with alpha
+    ImageRgba32F(Rgba32FImage),
 }
 
 macro_rules! dynamic_map(
@@ -63,6 +118,12 @@ macro_rules! dynamic_map(
                         DynamicImage::ImageLumaA8(ref $image) => DynamicImage::ImageLumaA8($action),
                         DynamicImage::ImageRgb8(ref $image) => DynamicImage::ImageRgb8($action),
                         DynamicImage::ImageRgba8(ref $image) => DynamicImage::ImageRgba8($action),
+                        DynamicImage::ImageLuma16(ref $image) => DynamicImage::ImageLuma16($action),
+                        DynamicImage::ImageLumaA16(ref $image) => DynamicImage::ImageLumaA16($action),
+                        DynamicImage::ImageRgb16(ref $image) => DynamicImage::ImageRgb16($action),
+                        DynamicImage::ImageRgba16(ref $image) => DynamicImage::ImageRgba16($action),
+                        DynamicImage::ImageRgb32F(ref $image) => DynamicImage::ImageRgb32F($action),
+                        DynamicImage::ImageRgba32F(ref $image) => DynamicImage::ImageRgba32F($action),
                 }
         );
 
@@ -72,6 +133,12 @@ macro_rules! dynamic_map(
                         DynamicImage::ImageLumaA8(ref mut $image) => DynamicImage::ImageLumaA8($action),
                         DynamicImage::ImageRgb8(ref mut $image) => DynamicImage::ImageRgb8($action),
                         DynamicImage::ImageRgba8(ref mut $image) => DynamicImage::ImageRgba8($action),
+                        DynamicImage::ImageLuma16(ref mut $image) => DynamicImage::ImageLuma16($action),
+                        DynamicImage::ImageLumaA16(ref mut $image) => DynamicImage::ImageLumaA16($action),
+                        DynamicImage::ImageRgb16(ref mut $image) => DynamicImage::ImageRgb16($action),
+                        DynamicImage::ImageRgba16(ref mut $image) => DynamicImage::ImageRgba16($action),
+                        DynamicImage::ImageRgb32F(ref mut $image) => DynamicImage::ImageRgb32F($action),
+                        DynamicImage::ImageRgba32F(ref mut $image) => DynamicImage::ImageRgba32F($action),
                 }
         );
 
@@ -81,6 +148,12 @@ macro_rules! dynamic_map(
                         DynamicImage::ImageLumaA8(ref $image) => $action,
                         DynamicImage::ImageRgb8(ref $image) => $action,
                         DynamicImage::ImageRgba8(ref $image) => $action,
+                        DynamicImage::ImageLuma16(ref $image) => $action,
+                        DynamicImage::ImageLumaA16(ref $image) => $action,
+                        DynamicImage::ImageRgb16(ref $image) => $action,
+                        DynamicImage::ImageRgba16(ref $image) => $action,
+                        DynamicImage::ImageRgb32F(ref $image) => $action,
+                        DynamicImage::ImageRgba32F(ref $image) => $action,
                 }
         );
 
@@ -90,6 +163,12 @@ macro_rules! dynamic_map(
                         DynamicImage::ImageLumaA8(ref mut $image) => $action,
                         DynamicImage::ImageRgb8(ref mut $image) => $action,
                         DynamicImage::ImageRgba8(ref mut $image) => $action,
+                        DynamicImage::ImageLuma16(ref mut $image) => $action,
+                        DynamicImage::ImageLumaA16(ref mut $image) => $action,
+                        DynamicImage::ImageRgb16(ref mut $image) => $action,
+                        DynamicImage::ImageRgba16(ref mut $image) => $action,
+                        DynamicImage::ImageRgb32F(ref mut $image) => $action,
+                        DynamicImage::ImageRgba32F(ref mut $image) => $action,
                 }
         );
 );
@@ -116,32 +195,207 @@ impl DynamicImage {
         DynamicImage::ImageRgba8(ImageBuffer::new(w, h))
     }
 
+    /// Creates a dynamic image backed by a buffer of 16-bit grey pixels.
+    pub fn new_luma16(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageLuma16(ImageBuffer::new(w, h))
+    }
+
+    /// Creates a dynamic image backed by a buffer of 16-bit grey
+    /// pixels with transparency.
+    pub fn new_luma_a16(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageLumaA16(ImageBuffer::new(w, h))
+    }
+
+    /// Creates a dynamic image backed by a buffer of 16-bit RGB pixels.
+    pub fn new_rgb16(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageRgb16(ImageBuffer::new(w, h))
+    }
+
+    /// Creates a dynamic image backed by a buffer of 16-bit RGBA pixels.
+    pub fn new_rgba16(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageRgba16(ImageBuffer::new(w, h))
+    }
+
+    /// Creates a dynamic image backed by a buffer of floating point RGB pixels.
+    pub fn new_rgb32f(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageRgb32F(ImageBuffer::new(w, h))
+    }
+
+    /// Creates a dynamic image backed by a buffer of floating point RGBA pixels.
+    pub fn new_rgba32f(w: u32, h: u32) -> DynamicImage {
+        DynamicImage::ImageRgba32F(ImageBuffer::new(w, h))
+    }
+
     /// Returns a copy of this image as an RGB image.
     pub fn to_rgb(&self) -> RgbImage {
-        dynamic_map!(*self, ref p -> {
-            p.convert()
-        })
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.convert(),
+            DynamicImage::ImageLumaA8(ref p) => p.convert(),
+            DynamicImage::ImageRgb8(ref p) => p.convert(),
+            DynamicImage::ImageRgba8(ref p) => p.convert(),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
     }
 
     /// Returns a copy of this image as an RGBA image.
     pub fn to_rgba(&self) -> RgbaImage {
-        dynamic_map!(*self, ref p -> {
-            p.convert()
-        })
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.convert(),
+            DynamicImage::ImageLumaA8(ref p) => p.convert(),
+            DynamicImage::ImageRgb8(ref p) => p.convert(),
+            DynamicImage::ImageRgba8(ref p) => p.convert(),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
     }
 
     /// Returns a copy of this image as a Luma image.
     pub fn to_luma(&self) -> GrayImage {
-        dynamic_map!(*self, ref p -> {
-            p.convert()
-        })
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.convert(),
+            DynamicImage::ImageLumaA8(ref p) => p.convert(),
+            DynamicImage::ImageRgb8(ref p) => p.convert(),
+            DynamicImage::ImageRgba8(ref p) => p.convert(),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
     }
 
     /// Returns a copy of this image as a LumaA image.
     pub fn to_luma_alpha(&self) -> GrayAlphaImage {
-        dynamic_map!(*self, ref p -> {
-            p.convert()
-        })
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.convert(),
+            DynamicImage::ImageLumaA8(ref p) => p.convert(),
+            DynamicImage::ImageRgb8(ref p) => p.convert(),
+            DynamicImage::ImageRgba8(ref p) => p.convert(),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
+    }
+
+    /// Returns a copy of this image as a 16-bit RGB image.
+    ///
+    /// 8-bit samples are scaled to the full 16-bit range.
+    pub fn to_rgb16(&self) -> Rgb16Image {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLuma16(ref p) => p.convert(),
+            DynamicImage::ImageLumaA16(ref p) => p.convert(),
+            DynamicImage::ImageRgb16(ref p) => p.convert(),
+            DynamicImage::ImageRgba16(ref p) => p.convert(),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
+    }
+
+    /// Returns a copy of this image as a 16-bit RGBA image.
+    ///
+    /// 8-bit samples are scaled to the full 16-bit range.
+    pub fn to_rgba16(&self) -> Rgba16Image {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLuma16(ref p) => p.convert(),
+            DynamicImage::ImageLumaA16(ref p) => p.convert(),
+            DynamicImage::ImageRgb16(ref p) => p.convert(),
+            DynamicImage::ImageRgba16(ref p) => p.convert(),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
+    }
+
+    /// Returns a copy of this image as a 16-bit Luma image.
+    ///
+    /// 8-bit samples are scaled to the full 16-bit range.
+    pub fn to_luma16(&self) -> Gray16Image {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLuma16(ref p) => p.convert(),
+            DynamicImage::ImageLumaA16(ref p) => p.convert(),
+            DynamicImage::ImageRgb16(ref p) => p.convert(),
+            DynamicImage::ImageRgba16(ref p) => p.convert(),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
+    }
+
+    /// Returns a copy of this image as a 16-bit LumaA image.
+    ///
+    /// 8-bit samples are scaled to the full 16-bit range.
+    pub fn to_luma_alpha16(&self) -> GrayAlpha16Image {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, u8_to_u16),
+            DynamicImage::ImageLuma16(ref p) => p.convert(),
+            DynamicImage::ImageLumaA16(ref p) => p.convert(),
+            DynamicImage::ImageRgb16(ref p) => p.convert(),
+            DynamicImage::ImageRgba16(ref p) => p.convert(),
+            DynamicImage::ImageRgb32F(ref p) => convert_samples(p, unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => convert_samples(p, unit_to_sample),
+        }
+    }
+
+    /// Returns a copy of this image as a floating point RGB image.
+    ///
+    /// 8 and 16-bit samples are scaled to the range 0 to 1.
+    pub fn to_rgb32f(&self) -> Rgb32FImage {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb32F(ref p) => p.clone(),
+            DynamicImage::ImageRgba32F(ref p) => p.convert(),
+        }
+    }
+
+    /// Returns a copy of this image as a floating point RGBA image.
+    ///
+    /// 8 and 16-bit samples are scaled to the range 0 to 1.
+    pub fn to_rgba32f(&self) -> Rgba32FImage {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLumaA8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgba8(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLuma16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageLumaA16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgba16(ref p) => convert_samples(p, sample_to_unit),
+            DynamicImage::ImageRgb32F(ref p) => p.convert(),
+            DynamicImage::ImageRgba32F(ref p) => p.clone(),
+        }
     }
 
     /// Return a cut out of this image delimited by the bounding rectangle.
@@ -218,7 +472,105 @@ impl DynamicImage {
         }
     }
 
+    /// Return a reference to a 16-bit RGB image
+    pub fn as_rgb16(&self) -> Option<&Rgb16Image> {
+        match *self {
+            DynamicImage::ImageRgb16(ref p) => Some(p),
+            _                               => None
+        }
+    }
+
+    /// Return a mutable reference to a 16-bit RGB image
+    pub fn as_mut_rgb16(&mut self) -> Option<&mut Rgb16Image> {
+        match *self {
+            DynamicImage::ImageRgb16(ref mut p) => Some(p),
+            _                                   => None
+        }
+    }
+
+    /// Return a reference to a 16-bit RGBA image
+    pub fn as_rgba16(&self) -> Option<&Rgba16Image> {
+        match *self {
+            DynamicImage::ImageRgba16(ref p) => Some(p),
+            _                                => None
+        }
+    }
+
+    /// Return a mutable reference to a 16-bit RGBA image
+    pub fn as_mut_rgba16(&mut self) -> Option<&mut Rgba16Image> {
+        match *self {
+            DynamicImage::ImageRgba16(ref mut p) => Some(p),
+            _                                    => None
+        }
+    }
+
+    /// Return a reference to a 16-bit Grayscale image
+    pub fn as_luma16(&self) -> Option<&Gray16Image> {
+        match *self {
+            DynamicImage::ImageLuma16(ref p) => Some(p),
+            _                                => None
+        }
+    }
+
+    /// Return a mutable reference to a 16-bit Grayscale image
+    pub fn as_mut_luma16(&mut self) -> Option<&mut Gray16Image> {
+        match *self {
+            DynamicImage::ImageLuma16(ref mut p) => Some(p),
+            _                                    => None
+        }
+    }
+
+    /// Return a reference to a 16-bit Grayscale image with an alpha channel
+    pub fn as_luma_alpha16(&self) -> Option<&GrayAlpha16Image> {
+        match *self {
+            DynamicImage::ImageLumaA16(ref p) => Some(p),
+            _                                 => None
+        }
+    }
+
+    /// Return a mutable reference to a 16-bit Grayscale image with an alpha channel
+    pub fn as_mut_luma_alpha16(&mut self) -> Option<&mut GrayAlpha16Image> {
+        match *self {
+            DynamicImage::ImageLumaA16(ref mut p) => Some(p),
+            _                                     => None
+        }
+    }
+
+    /// Return a reference to a floating point RGB image
+    pub fn as_rgb32f(&self) -> Option<&Rgb32FImage> {
+        match *self {
+            DynamicImage::ImageRgb32F(ref p) => Some(p),
+            _                                => None
+        }
+    }
+
+    /// Return a mutable reference to a floating point RGB image
+    pub fn as_mut_rgb32f(&mut self) -> Option<&mut Rgb32FImage> {
+        match *self {
+            DynamicImage::ImageRgb32F(ref mut p) => Some(p),
+            _                                    => None
+        }
+    }
+
+    /// Return a reference to a floating point RGBA image
+    pub fn as_rgba32f(&self) -> Option<&Rgba32FImage> {
+        match *self {
+            DynamicImage::ImageRgba32F(ref p) => Some(p),
+            _                                 => None
+        }
+    }
+
+    /// Return a mutable reference to a floating point RGBA image
+    pub fn as_mut_rgba32f(&mut self) -> Option<&mut Rgba32FImage> {
+        match *self {
+            DynamicImage::ImageRgba32F(ref mut p) => Some(p),
+            _                                     => None
+        }
+    }
+
     /// Return this image's pixels as a byte vector.
+    ///
+    /// 16-bit and floating point samples are stored in big endian order.
     pub fn raw_pixels(&self) -> Vec<u8> {
         image_to_bytes(self)
     }
@@ -230,16 +582,30 @@ impl DynamicImage {
             DynamicImage::ImageLumaA8(_) => color::ColorType::GrayA(8),
             DynamicImage::ImageRgb8(_) => color::ColorType::RGB(8),
             DynamicImage::ImageRgba8(_) => color::ColorType::RGBA(8),
+            DynamicImage::ImageLuma16(_) => color::ColorType::Gray(16),
+            DynamicImage::ImageLumaA16(_) => color::ColorType::GrayA(16),
+            DynamicImage::ImageRgb16(_) => color::ColorType::RGB(16),
+            DynamicImage::ImageRgba16(_) => color::ColorType::RGBA(16),
+            DynamicImage::ImageRgb32F(_) => color::ColorType::RGBF(32),
+            DynamicImage::ImageRgba32F(_) => color::ColorType::RGBAF(32),
         }
     }
 
     /// Return a grayscale version of this image.
+    ///
+    /// Floating point images stay floating point, with the gray value in all three channels.
     pub fn grayscale(&self) -> DynamicImage {
         match *self {
             DynamicImage::ImageLuma8(ref p) => DynamicImage::ImageLuma8(p.clone()),
             DynamicImage::ImageLumaA8(ref p) => DynamicImage::ImageLuma8(imageops::grayscale(p)),
             DynamicImage::ImageRgb8(ref p) => DynamicImage::ImageLuma8(imageops::grayscale(p)),
             DynamicImage::ImageRgba8(ref p) => DynamicImage::ImageLuma8(imageops::grayscale(p)),
+            DynamicImage::ImageLuma16(ref p) => DynamicImage::ImageLuma16(p.clone()),
+            DynamicImage::ImageLumaA16(ref p) => DynamicImage::ImageLuma16(imageops::grayscale(p)),
+            DynamicImage::ImageRgb16(ref p) => DynamicImage::ImageLuma16(imageops::grayscale(p)),
+            DynamicImage::ImageRgba16(ref p) => DynamicImage::ImageLuma16(imageops::grayscale(p)),
+            DynamicImage::ImageRgb32F(ref p) => DynamicImage::ImageRgb32F(imageops::grayscale(p).convert()),
+            DynamicImage::ImageRgba32F(ref p) => DynamicImage::ImageRgb32F(imageops::grayscale(p).convert()),
         }
     }
 
@@ -296,9 +662,23 @@ impl DynamicImage {
     /// ```sigma``` is the amount to blur the image by.
     /// ```threshold``` is a control of how much to sharpen.
     ///
+    /// For 16-bit and floating point images ```threshold``` is in 255ths of full intensity.
+    ///
     /// See <https://en.wikipedia.org/wiki/Unsharp_masking#Digital_unsharp_masking>
     pub fn unsharpen(&self, sigma: f32, threshold: i32) -> DynamicImage {
-        dynamic_map!(*self, ref p => imageops::unsharpen(p, sigma, threshold))
+        let threshold16 = threshold.saturating_mul(257);
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => DynamicImage::ImageLuma8(imageops::unsharpen(p, sigma, threshold)),
+            DynamicImage::ImageLumaA8(ref p) => DynamicImage::ImageLumaA8(imageops::unsharpen(p, sigma, threshold)),
+            DynamicImage::ImageRgb8(ref p) => DynamicImage::ImageRgb8(imageops::unsharpen(p, sigma, threshold)),
+            DynamicImage::ImageRgba8(ref p) => DynamicImage::ImageRgba8(imageops::unsharpen(p, sigma, threshold)),
+            DynamicImage::ImageLuma16(ref p) => DynamicImage::ImageLuma16(imageops::unsharpen(p, sigma, threshold16)),
+            DynamicImage::ImageLumaA16(ref p) => DynamicImage::ImageLumaA16(imageops::unsharpen(p, sigma, threshold16)),
+            DynamicImage::ImageRgb16(ref p) => DynamicImage::ImageRgb16(imageops::unsharpen(p, sigma, threshold16)),
+            DynamicImage::ImageRgba16(ref p) => DynamicImage::ImageRgba16(imageops::unsharpen(p, sigma, threshold16)),
+            DynamicImage::ImageRgb32F(ref p) => DynamicImage::ImageRgb32F(unsharpen_float(p, sigma, threshold)),
+            DynamicImage::ImageRgba32F(ref p) => DynamicImage::ImageRgba32F(unsharpen_float(p, sigma, threshold)),
+        }
     }
 
     /// Filters this image with the specified 3x3 kernel.
@@ -320,8 +700,22 @@ impl DynamicImage {
     /// Brighten the pixels of this image.
     /// ```value``` is the amount to brighten each pixel by.
     /// Negative values decrease the brightness and positive values increase it.
+    /// For 16-bit images ```value``` is in 255ths of full intensity. For floating point images
+    /// it is too, and samples are not limited to 1.
     pub fn brighten(&self, value: i32) -> DynamicImage {
-        dynamic_map!(*self, ref p => imageops::brighten(p, value))
+        let value16 = value.saturating_mul(257);
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => DynamicImage::ImageLuma8(imageops::brighten(p, value)),
+            DynamicImage::ImageLumaA8(ref p) => DynamicImage::ImageLumaA8(imageops::brighten(p, value)),
+            DynamicImage::ImageRgb8(ref p) => DynamicImage::ImageRgb8(imageops::brighten(p, value)),
+            DynamicImage::ImageRgba8(ref p) => DynamicImage::ImageRgba8(imageops::brighten(p, value)),
+            DynamicImage::ImageLuma16(ref p) => DynamicImage::ImageLuma16(imageops::brighten(p, value16)),
+            DynamicImage::ImageLumaA16(ref p) => DynamicImage::ImageLumaA16(imageops::brighten(p, value16)),
+            DynamicImage::ImageRgb16(ref p) => DynamicImage::ImageRgb16(imageops::brighten(p, value16)),
+            DynamicImage::ImageRgba16(ref p) => DynamicImage::ImageRgba16(imageops::brighten(p, value16)),
+            DynamicImage::ImageRgb32F(ref p) => DynamicImage::ImageRgb32F(brighten_float(p, value)),
+            DynamicImage::ImageRgba32F(ref p) => DynamicImage::ImageRgba32F(brighten_float(p, value)),
+        }
     }
 
     /// Hue rotate the supplied image.
@@ -358,71 +752,245 @@ impl DynamicImage {
     }
 
     /// Encode this image and write it to ```w```
+    ///
+    /// Floating point images are stored as they are in HDR and TIFF files, with 16 bits in
+    /// farbfeld files, and converted to 8 bits for all other formats. 16-bit images are stored
+    /// as they are in PNG, PNM, TIFF, farbfeld and SGI files, and converted to 8 bits for all
+    /// other formats. The pixels are then written by the `ImageEncoder` of ```format```.
     pub fn save<W: Write>(&self, w: &mut W, format: ImageFormat) -> ImageResult<()> {
+        self.save_with_progress(w, format, &mut Monitor::new())
+    }
+
+    /// Encode this image and write it to ```w``` like `save`, reporting the rows encoded to
+    /// ```monitor```
+    ///
+    /// Encoding fails with `ImageError::Cancelled` once the `CancelToken` of ```monitor``` is
+    /// cancelled. Only PNG images are reported row by row, images of all other formats are
+    /// reported once they have been written.
+    pub fn save_with_progress<W: Write>(&self, w: &mut W, format: ImageFormat, monitor: &mut Monitor)
+                                        -> ImageResult<()> {
+        self.write_encoded(w, format, None, monitor)
+    }
+
+    /// Encode this image and write it to the file at ```path``` in the format ```format```,
+    /// whatever the extension of the file is
+    pub fn save_with_format<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> ImageResult<()> {
+        let mut file = BufWriter::new(try!(File::create(path)));
+        try!(self.save(&mut file, format));
+        try!(file.flush());
+        Ok(())
+    }
+
+    /// Encode this image and write it to ```w``` like `save`, in the format of ```options```
+    /// with the settings of ```options``` instead of the defaults of the encoder
+    ///
+    /// ```no_run
+    /// # fn main() { run().unwrap() }
+    /// # fn run() -> image::ImageResult<()> {
+    /// use image::EncoderOptions;
+    /// use image::jpeg::JPEGEncoderOptions;
+    ///
+    /// let image = try!(image::open("photo.png"));
+    /// let mut jpeg = Vec::new();
+    /// let options = JPEGEncoderOptions { quality: 90, ..JPEGEncoderOptions::default() };
+    /// try!(image.write_with_options(&mut jpeg, EncoderOptions::JPEG(options)));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn write_with_options<W: Write>(&self, w: &mut W, options: EncoderOptions) -> ImageResult<()> {
+        let format = options.format();
+        self.write_encoded(w, format, Some(options), &mut Monitor::new())
+    }
+
+    /// Encode this image in the format ```format```, with the settings of ```options``` if they
+    /// are given
+    fn write_encoded<W: Write>(&self, w: &mut W, format: ImageFormat, options: Option<EncoderOptions>,
+                               monitor: &mut Monitor) -> ImageResult<()> {
+        let keeps_float = match format {
+            image::ImageFormat::HDR | image::ImageFormat::TIFF | image::ImageFormat::Farbfeld => true,
+            _ => false,
+        };
+        // HDR files are written from the floating point samples of any image
+        let keeps_16_bits = match format {
+            image::ImageFormat::PNG | image::ImageFormat::PNM | image::ImageFormat::TIFF |
+            image::ImageFormat::Farbfeld | image::ImageFormat::SGI | image::ImageFormat::HDR => true,
+            #[allow(deprecated)]
+            image::ImageFormat::PPM => true,
+            _ => false,
+        };
+        let narrowed = match *self {
+            DynamicImage::ImageLuma16(_) if !keeps_16_bits => DynamicImage::ImageLuma8(self.to_luma()),
+            DynamicImage::ImageLumaA16(_) if !keeps_16_bits => DynamicImage::ImageLumaA8(self.to_luma_alpha()),
+            DynamicImage::ImageRgb16(_) if !keeps_16_bits => DynamicImage::ImageRgb8(self.to_rgb()),
+            DynamicImage::ImageRgba16(_) if !keeps_16_bits => DynamicImage::ImageRgba8(self.to_rgba()),
+            DynamicImage::ImageRgb32F(_) if !keeps_float => DynamicImage::ImageRgb8(self.to_rgb()),
+            DynamicImage::ImageRgba32F(_) if !keeps_float => DynamicImage::ImageRgba8(self.to_rgba()),
+            _ => return self.write_pixels(w, format, options, monitor),
+        };
+        narrowed.write_pixels(w, format, options, monitor)
+    }
+
+    /// Writes the pixels of this image as they are in the format ```format```
+    fn write_pixels<W: Write>(&self, w: &mut W, format: ImageFormat, options: Option<EncoderOptions>,
+                              monitor: &mut Monitor) -> ImageResult<()> {
+
         let bytes = self.raw_pixels();
         let (width, height) = self.dimensions();
         let color = self.color();
 
         match format {
             #[cfg(feature = "png_codec")]
-            image::ImageFormat::PNG  => {
-                let p = png::PNGEncoder::new(w);
-
-                try!(p.encode(&bytes, width, height, color));
-                Ok(())
+            image::ImageFormat::PNG => {
+                let options = match options {
+                    Some(EncoderOptions::PNG(options)) => options,
+                    _ => png::PNGEncoderOptions::default(),
+                };
+                png::PNGEncoder::new_with_options(w, options)
+                    .write_image_with_progress(&bytes, width, height, color, monitor)
             }
-            #[cfg(feature = "ppm")]
-            image::ImageFormat::PPM  => {
-                let mut p = ppm::PPMEncoder::new(w);
-
-                try!(p.encode(&bytes, width, height, color));
-                Ok(())
+            #[cfg(feature = "pnm")]
+            #[allow(deprecated)]
+            image::ImageFormat::PNM | image::ImageFormat::PPM => {
+                let options = match options {
+                    Some(EncoderOptions::PNM(options)) => options,
+                    _ => pnm::PNMEncoderOptions::default(),
+                };
+                pnm::PNMEncoder::new_with_options(w, options)
+                    .write_image_with_progress(&bytes, width, height, color, monitor)
             }
-
             #[cfg(feature = "jpeg")]
             image::ImageFormat::JPEG => {
-                let mut j = jpeg::JPEGEncoder::new(w);
-
-                try!(j.encode(&bytes, width, height, color));
-                Ok(())
+                let options = match options {
+                    Some(EncoderOptions::JPEG(options)) => options,
+                    _ => jpeg::JPEGEncoderOptions::default(),
+                };
+                jpeg::JPEGEncoder::new_with_options(w, options)
+                    .write_image_with_progress(&bytes, width, height, color, monitor)
             }
-
             #[cfg(feature = "gif_codec")]
             image::ImageFormat::GIF => {
-                let g = gif::Encoder::new(w);
-
-                try!(g.encode(gif::Frame::from_rgba(
-                    width as u16,
-                    height as u16,
-                    &mut *self.to_rgba().iter().cloned().collect::<Vec<u8>>()
-                )));
-                Ok(())
+                let options = match options {
+                    Some(EncoderOptions::GIF(options)) => options,
+                    _ => gif::EncoderOptions::default(),
+                };
+                let rgba = self.to_rgba();
+                gif::Encoder::new_with_options(w, options)
+                    .write_image_with_progress(&rgba, width, height, color::ColorType::RGBA(8), monitor)
             }
-
             #[cfg(feature = "ico")]
-            image::ImageFormat::ICO => {
-                let i = ico::ICOEncoder::new(w);
-
-                try!(i.encode(&bytes, width, height, color));
-                Ok(())
-            }
-
+            image::ImageFormat::ICO => ico::ICOEncoder::new(w).write_image_with_progress(&bytes, width, height, color, monitor),
             #[cfg(feature = "bmp")]
-            image::ImageFormat::BMP => {
-                let mut b = bmp::BMPEncoder::new(w);
-                try!(b.encode(&bytes, width, height, color));
-                Ok(())
+            image::ImageFormat::BMP => bmp::BMPEncoder::new(w).write_image_with_progress(&bytes, width, height, color, monitor),
+            #[cfg(feature = "webp")]
+            image::ImageFormat::WEBP => {
+                let quality = match options {
+                    Some(EncoderOptions::WEBP(quality)) => quality,
+                    _ => webp::WebpQuality::Lossless,
+                };
+                webp::WebpEncoder::new_with_quality(w, quality)
+                    .write_image_with_progress(&bytes, width, height, color, monitor)
+            }
+            #[cfg(feature = "tiff")]
+            image::ImageFormat::TIFF => {
+                let compression = match options {
+                    Some(EncoderOptions::TIFF(compression)) => compression,
+                    _ => tiff::TIFFCompression::None,
+                };
+                tiff::TIFFEncoder::new_with_compression(w, compression)
+                    .write_image_with_progress(&bytes, width, height, color, monitor)
+            }
+            #[cfg(feature = "tga")]
+            image::ImageFormat::TGA => {
+                let mut encoder = tga::TGAEncoder::new(w);
+                if let Some(EncoderOptions::TGA(rle)) = options {
+                    encoder.set_rle(rle);
+                }
+                encoder.write_image_with_progress(&bytes, width, height, color, monitor)
+            }
+            #[cfg(feature = "qoi")]
+            image::ImageFormat::QOI => qoi::QOIEncoder::new(w).write_image_with_progress(&bytes, width, height, color, monitor),
+            #[cfg(feature = "farbfeld")]
+            image::ImageFormat::Farbfeld => {
+                farbfeld::FarbfeldEncoder::new(w).write_image_with_progress(&bytes, width, height, color, monitor)
+            }
+            #[cfg(feature = "sgi")]
+            image::ImageFormat::SGI => {
+                let mut encoder = sgi::SGIEncoder::new(w);
+                if let Some(EncoderOptions::SGI(rle)) = options {
+                    encoder.set_rle(rle);
+                }
+                encoder.write_image_with_progress(&bytes, width, height, color, monitor)
             }
+            #[cfg(feature = "hdr")]
+            image::ImageFormat::HDR => {
+                let rgb = float_bytes(&self.to_rgb32f());
+                hdr::HDREncoder::new(w)
+                    .write_image_with_progress(&rgb, width, height, color::ColorType::RGBF(32), monitor)
+            }
+            _ => Err(image::ImageError::unsupported_format(ImageFormatHint::Exact(format))),
+        }
+    }
+}
+
+/// The settings of the encoder of a format, chosen by its variant
+///
+/// Formats are written with the defaults of their encoder by `DynamicImage::save`,
+/// `DynamicImage::write_with_options` uses these settings instead.
+// The gif settings hold a palette, without them all settings could be `Copy`
+#[allow(missing_copy_implementations)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum EncoderOptions {
+    /// The quality, chroma subsampling and progressive mode of JPEG images
+    #[cfg(feature = "jpeg")]
+    JPEG(jpeg::JPEGEncoderOptions),
+    /// The compression level and filtering of PNG images
+    #[cfg(feature = "png_codec")]
+    PNG(png::PNGEncoderOptions),
+    /// The quantizer, dithering and palette of GIF images
+    #[cfg(feature = "gif_codec")]
+    GIF(gif::EncoderOptions),
+    /// Whether WebP images are compressed losslessly, or lossily with a quality
+    #[cfg(feature = "webp")]
+    WEBP(webp::WebpQuality),
+    /// The compression of the strips of TIFF images
+    #[cfg(feature = "tiff")]
+    TIFF(tiff::TIFFCompression),
+    /// The sample encoding of PNM images
+    #[cfg(feature = "pnm")]
+    PNM(pnm::PNMEncoderOptions),
+    /// Whether the pixels of TGA images are run length encoded
+    #[cfg(feature = "tga")]
+    TGA(bool),
+    /// Whether the rows of SGI images are run length encoded
+    #[cfg(feature = "sgi")]
+    SGI(bool),
+}
 
-            _ => Err(image::ImageError::UnsupportedError(
-                     format!("An encoder for {:?} is not available.", format))
-                 ),
+impl EncoderOptions {
+    /// Returns the format these settings apply to
+    pub fn format(&self) -> ImageFormat {
+        match *self {
+            #[cfg(feature = "jpeg")]
+            EncoderOptions::JPEG(_) => ImageFormat::JPEG,
+            #[cfg(feature = "png_codec")]
+            EncoderOptions::PNG(_) => ImageFormat::PNG,
+            #[cfg(feature = "gif_codec")]
+            EncoderOptions::GIF(_) => ImageFormat::GIF,
+            #[cfg(feature = "webp")]
+            EncoderOptions::WEBP(_) => ImageFormat::WEBP,
+            #[cfg(feature = "tiff")]
+            EncoderOptions::TIFF(_) => ImageFormat::TIFF,
+            #[cfg(feature = "pnm")]
+            EncoderOptions::PNM(_) => ImageFormat::PNM,
+            #[cfg(feature = "tga")]
+            EncoderOptions::TGA(_) => ImageFormat::TGA,
+            #[cfg(feature = "sgi")]
+            EncoderOptions::SGI(_) => ImageFormat::SGI,
         }
     }
 }
 
-#[allow(deprecated)]
-impl GenericImage for DynamicImage {
+impl GenericImageView for DynamicImage {
     type Pixel = color::Rgba<u8>;
 
     fn dimensions(&self) -> (u32, u32) {
@@ -434,15 +1002,66 @@ impl GenericImage for DynamicImage {
     }
 
     fn get_pixel(&self, x: u32, y: u32) -> color::Rgba<u8> {
-        dynamic_map!(*self, ref p -> p.get_pixel(x, y).to_rgba())
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageLumaA8(ref p) => p.get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageRgb8(ref p) => p.get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageRgba8(ref p) => *p.get_pixel(x, y),
+            DynamicImage::ImageLuma16(ref p) => map_rgba(p.get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => map_rgba(p.get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => map_rgba(p.get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => map_rgba(*p.get_pixel(x, y), u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => map_rgba(p.get_pixel(x, y).to_rgba(), unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => map_rgba(*p.get_pixel(x, y), unit_to_sample),
+        }
     }
 
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> color::Rgba<u8> {
+        match *self {
+            DynamicImage::ImageLuma8(ref p) => p.unsafe_get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageLumaA8(ref p) => p.unsafe_get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageRgb8(ref p) => p.unsafe_get_pixel(x, y).to_rgba(),
+            DynamicImage::ImageRgba8(ref p) => p.unsafe_get_pixel(x, y),
+            DynamicImage::ImageLuma16(ref p) => map_rgba(p.unsafe_get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageLumaA16(ref p) => map_rgba(p.unsafe_get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageRgb16(ref p) => map_rgba(p.unsafe_get_pixel(x, y).to_rgba(), u16_to_u8),
+            DynamicImage::ImageRgba16(ref p) => map_rgba(p.unsafe_get_pixel(x, y), u16_to_u8),
+            DynamicImage::ImageRgb32F(ref p) => map_rgba(p.unsafe_get_pixel(x, y).to_rgba(), unit_to_sample),
+            DynamicImage::ImageRgba32F(ref p) => map_rgba(p.unsafe_get_pixel(x, y), unit_to_sample),
+        }
+    }
+
+}
+
+#[allow(deprecated)]
+impl GenericImage for DynamicImage {
     fn put_pixel(&mut self, x: u32, y: u32, pixel: color::Rgba<u8>) {
         match *self {
             DynamicImage::ImageLuma8(ref mut p) => p.put_pixel(x, y, pixel.to_luma()),
             DynamicImage::ImageLumaA8(ref mut p) => p.put_pixel(x, y, pixel.to_luma_alpha()),
             DynamicImage::ImageRgb8(ref mut p) => p.put_pixel(x, y, pixel.to_rgb()),
             DynamicImage::ImageRgba8(ref mut p) => p.put_pixel(x, y, pixel),
+            DynamicImage::ImageLuma16(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma()),
+            DynamicImage::ImageLumaA16(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma_alpha()),
+            DynamicImage::ImageRgb16(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_rgb()),
+            DynamicImage::ImageRgba16(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, u8_to_u16)),
+            DynamicImage::ImageRgb32F(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, sample_to_unit).to_rgb()),
+            DynamicImage::ImageRgba32F(ref mut p) => p.put_pixel(x, y, map_rgba(pixel, sample_to_unit)),
+        }
+    }
+
+    unsafe fn unsafe_put_pixel(&mut self, x: u32, y: u32, pixel: color::Rgba<u8>) {
+        match *self {
+            DynamicImage::ImageLuma8(ref mut p) => p.unsafe_put_pixel(x, y, pixel.to_luma()),
+            DynamicImage::ImageLumaA8(ref mut p) => p.unsafe_put_pixel(x, y, pixel.to_luma_alpha()),
+            DynamicImage::ImageRgb8(ref mut p) => p.unsafe_put_pixel(x, y, pixel.to_rgb()),
+            DynamicImage::ImageRgba8(ref mut p) => p.unsafe_put_pixel(x, y, pixel),
+            DynamicImage::ImageLuma16(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma()),
+            DynamicImage::ImageLumaA16(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma_alpha()),
+            DynamicImage::ImageRgb16(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, u8_to_u16).to_rgb()),
+            DynamicImage::ImageRgba16(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, u8_to_u16)),
+            DynamicImage::ImageRgb32F(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, sample_to_unit).to_rgb()),
+            DynamicImage::ImageRgba32F(ref mut p) => p.unsafe_put_pixel(x, y, map_rgba(pixel, sample_to_unit)),
         }
     }
     /// DEPRECATED: Use iterator `pixels_mut` to blend the pixels directly.
@@ -452,6 +1071,12 @@ impl GenericImage for DynamicImage {
             DynamicImage::ImageLumaA8(ref mut p) => p.blend_pixel(x, y, pixel.to_luma_alpha()),
             DynamicImage::ImageRgb8(ref mut p) => p.blend_pixel(x, y, pixel.to_rgb()),
             DynamicImage::ImageRgba8(ref mut p) => p.blend_pixel(x, y, pixel),
+            DynamicImage::ImageLuma16(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma()),
+            DynamicImage::ImageLumaA16(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, u8_to_u16).to_luma_alpha()),
+            DynamicImage::ImageRgb16(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, u8_to_u16).to_rgb()),
+            DynamicImage::ImageRgba16(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, u8_to_u16)),
+            DynamicImage::ImageRgb32F(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, sample_to_unit).to_rgb()),
+            DynamicImage::ImageRgba32F(ref mut p) => p.blend_pixel(x, y, map_rgba(pixel, sample_to_unit)),
         }
     }
 
@@ -462,12 +1087,45 @@ impl GenericImage for DynamicImage {
 }
 
 
+/// Decodes an image after checking its dimensions against ```limits```
+fn decoder_to_image_with_limits<I: ImageDecoder>(codec: I, limits: &Limits) -> ImageResult<DynamicImage> {
+    decoder_to_image_with_progress(codec, limits, &mut Monitor::new(), None)
+}
+
+/// Decodes an image after checking its dimensions against ```limits```, reporting the rows
+/// decoded to ```monitor```
+///
+/// If ```target``` is given, the pixels are converted from the ICC profile of the image into it.
+fn decoder_to_image_with_progress<I: ImageDecoder>(codec: I, limits: &Limits, monitor: &mut Monitor,
+                                                   target: Option<&Profile>) -> ImageResult<DynamicImage> {
+    let mut codec = codec;
+    try!(codec.check_limits(limits));
+    // Profiles that can not be read or converted from leave the pixels unchanged
+    let source = match target {
+        Some(_) => codec.icc_profile().ok().and_then(|data| data)
+            .and_then(|data| Profile::parse(&data).ok()),
+        None => None,
+    };
+    let buf = try!(codec.read_image_with_progress(monitor));
+    let mut image = try!(image_from_decoded(codec, buf));
+    if let (Some(source), Some(target)) = (source, target) {
+        Transform::new(&source, target).apply(&mut image);
+    }
+    Ok(image)
+}
+
 /// Decodes an image and stores it into a dynamic image
 pub fn decoder_to_image<I: ImageDecoder>(codec: I) -> ImageResult<DynamicImage> {
     let mut codec = codec;
+    let buf = try!(codec.read_image());
+    image_from_decoded(codec, buf)
+}
+
+/// Stores the image ```buf``` decoded by ```codec``` into a dynamic image
+fn image_from_decoded<I: ImageDecoder>(codec: I, buf: DecodingResult) -> ImageResult<DynamicImage> {
+    let mut codec = codec;
 
     let color  = try!(codec.colortype());
-    let buf    = try!(codec.read_image());
     let (w, h) = try!(codec.dimensions());
 
     let image = match (color, buf) {
@@ -486,6 +1144,42 @@ pub fn decoder_to_image<I: ImageDecoder>(codec: I) -> ImageResult<DynamicImage>
         (color::ColorType::GrayA(8), U8(buf)) => {
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLumaA8)
         }
+
+        (color::ColorType::RGBF(32), F32(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgb32F)
+        }
+
+        (color::ColorType::RGBAF(32), F32(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgba32F)
+        }
+
+        // There are no floating point gray images, the gray value is repeated in each channel
+        (color::ColorType::GrayF(32), F32(buf)) => {
+            let rgb = buf.iter().flat_map(|&v| iter::repeat(v).take(3)).collect();
+            ImageBuffer::from_raw(w, h, rgb).map(DynamicImage::ImageRgb32F)
+        }
+
+        (color::ColorType::GrayAF(32), F32(buf)) => {
+            let rgba = buf.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect();
+            ImageBuffer::from_raw(w, h, rgba).map(DynamicImage::ImageRgba32F)
+        }
+
+        (color::ColorType::RGB(16), U16(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgb16)
+        }
+
+        (color::ColorType::RGBA(16), U16(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgba16)
+        }
+
+        (color::ColorType::Gray(16), U16(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLuma16)
+        }
+
+        (color::ColorType::GrayA(16), U16(buf)) => {
+            ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLumaA16)
+        }
+
         (color::ColorType::Gray(bit_depth), U8(ref buf)) if bit_depth == 1 || bit_depth == 2 || bit_depth == 4 => {
             // Note: this conversion assumes that the scanlines begin on byte boundaries
             let mask = (1u8 << bit_depth as usize) - 1;
@@ -508,79 +1202,249 @@ pub fn decoder_to_image<I: ImageDecoder>(codec: I) -> ImageResult<DynamicImage>
                        .collect();
             ImageBuffer::from_raw(w, h, p).map(DynamicImage::ImageLuma8)
         },
-        _ => return Err(image::ImageError::UnsupportedColor(color))
+        _ => return Err(image::ImageError::Unsupported {
+            format: ImageFormatHint::Unknown,
+            feature: UnsupportedFeature::Color(color),
+        })
     };
     match image {
         Some(image) => Ok(image),
-        None => Err(image::ImageError::DimensionError)
+        None => Err(image::ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
     }
 }
 
 #[allow(deprecated)]
 fn image_to_bytes(image: &DynamicImage) -> Vec<u8> {
     match *image {
-        // TODO: consider transmuting
-        DynamicImage::ImageLuma8(ref a) => {
-            a.iter().cloned().collect()
-        }
+        DynamicImage::ImageLuma8(ref a) => packed_samples(a),
+        DynamicImage::ImageLumaA8(ref a) => packed_samples(a),
+        DynamicImage::ImageRgb8(ref a) => packed_samples(a),
+        DynamicImage::ImageRgba8(ref a) => packed_samples(a),
+        DynamicImage::ImageLuma16(ref a) => u16_bytes(&packed_samples(a)),
+        DynamicImage::ImageLumaA16(ref a) => u16_bytes(&packed_samples(a)),
+        DynamicImage::ImageRgb16(ref a) => u16_bytes(&packed_samples(a)),
+        DynamicImage::ImageRgba16(ref a) => u16_bytes(&packed_samples(a)),
+        DynamicImage::ImageRgb32F(ref a) => float_bytes(&packed_samples(a)),
+        DynamicImage::ImageRgba32F(ref a) => float_bytes(&packed_samples(a)),
+    }
+}
 
-        DynamicImage::ImageLumaA8(ref a) => {
-            a.iter().cloned().collect()
-        }
+/// Returns the samples of the pixels of ```image```, leaving out the padding of its rows
+fn packed_samples<P>(image: &ImageBuffer<P, Vec<P::Subpixel>>) -> Vec<P::Subpixel>
+    where P: Pixel + 'static, P::Subpixel: 'static {
+    if image.stride() == image.width() as usize * P::channel_count() as usize {
+        image.to_vec()
+    } else {
+        image.pixels().flat_map(|pixel| pixel.channels().iter().cloned()).collect()
+    }
+}
 
-        DynamicImage::ImageRgb8(ref a)  => {
-            a.iter().cloned().collect()
-        }
+fn float_bytes(samples: &[f32]) -> Vec<u8> {
+    let mut bytes = vec![0; samples.len() * 4];
+    for (&sample, chunk) in samples.iter().zip(bytes.chunks_mut(4)) {
+        BigEndian::write_f32(chunk, sample);
+    }
+    bytes
+}
 
-        DynamicImage::ImageRgba8(ref a) => {
-            a.iter().cloned().collect()
-        }
+fn u16_bytes(samples: &[u16]) -> Vec<u8> {
+    let mut bytes = vec![0; samples.len() * 2];
+    BigEndian::write_u16_into(samples, &mut bytes);
+    bytes
+}
+
+/// Scales a 16-bit sample to the nearest 8-bit sample
+fn u16_to_u8(v: u16) -> u8 {
+    ((v as u32 * 255 + 32767) / 65535) as u8
+}
+
+/// Scales an 8-bit sample to the full 16-bit range
+fn u8_to_u16(v: u8) -> u16 {
+    v as u16 * 257
+}
+
+/// Converts each sample of ```p``` with ```f```
+fn map_rgba<S, T, F>(p: color::Rgba<S>, f: F) -> color::Rgba<T>
+    where S: Primitive, T: Primitive, F: Fn(S) -> T {
+    color::Rgba([f(p[0]), f(p[1]), f(p[2]), f(p[3])])
+}
+
+/// Converts the samples of ```image``` with ```f```, and its pixels to the color model of ```Q```
+fn convert_samples<P, Q, F>(image: &ImageBuffer<P, Vec<P::Subpixel>>, f: F)
+    -> ImageBuffer<Q, Vec<Q::Subpixel>>
+    where P: Pixel + 'static,
+          Q: Pixel + FromColor<color::Rgba<Q::Subpixel>> + 'static,
+          P::Subpixel: 'static,
+          Q::Subpixel: 'static,
+          F: Fn(P::Subpixel) -> Q::Subpixel {
+    let (width, height) = image.dimensions();
+    let rgba: ImageBuffer<color::Rgba<Q::Subpixel>, _> = ImageBuffer::from_fn(width, height, |x, y| {
+        map_rgba(image.get_pixel(x, y).to_rgba(), &f)
+    });
+    rgba.convert()
+}
+
+/// Adds ```value``` 255ths of full intensity to the colors of a floating point image
+fn brighten_float<P>(image: &ImageBuffer<P, Vec<f32>>, value: i32) -> ImageBuffer<P, Vec<f32>>
+    where P: Pixel<Subpixel=f32> + 'static {
+    let offset = value as f32 / 255.0;
+    let mut out = image.clone();
+    for pixel in out.pixels_mut() {
+        pixel.apply_with_alpha(|c| (c + offset).max(0.0), |alpha| alpha);
+    }
+    out
+}
+
+/// Unsharpens a floating point image, with ```threshold``` in 255ths of full intensity
+fn unsharpen_float<P>(image: &ImageBuffer<P, Vec<f32>>, sigma: f32, threshold: i32)
+    -> ImageBuffer<P, Vec<f32>>
+    where P: Pixel<Subpixel=f32> + 'static {
+    let threshold = threshold as f32 / 255.0;
+    let mut out = imageops::blur(image, sigma);
+    for (sharp, original) in out.pixels_mut().zip(image.pixels()) {
+        *sharp = original.map2(sharp, |c, d| {
+            let diff = (c - d).abs();
+            if diff > threshold {
+                c + diff
+            } else {
+                c
+            }
+        });
     }
+    out
 }
 
 /// Open the image located at the path specified.
 /// The image's format is determined from the path's file extension.
+/// Extensions of formats this crate does not support are looked up in the `registry`.
+///
+/// The image is checked against the default `Limits`.
 pub fn open<P>(path: P) -> ImageResult<DynamicImage> where P: AsRef<Path> {
     // thin wrapper function to strip generics before calling open_impl
-    open_impl(path.as_ref())
+    open_impl(path.as_ref(), &Limits::default())
+}
+
+/// Open the image located at the path specified, failing with `ImageError::Limits` if
+/// it exceeds ```limits```
+pub fn open_with_limits<P>(path: P, limits: &Limits) -> ImageResult<DynamicImage> where P: AsRef<Path> {
+    open_impl(path.as_ref(), limits)
 }
 
-fn open_impl(path: &Path) -> ImageResult<DynamicImage> {
+fn open_impl(path: &Path, limits: &Limits) -> ImageResult<DynamicImage> {
     let fin = match File::open(path) {
         Ok(f)  => f,
         Err(err) => return Err(image::ImageError::IoError(err))
     };
-    let fin = BufReader::new(fin);
+    let mut fin = BufReader::new(fin);
 
-    let ext = path.extension().and_then(|s| s.to_str())
-                  .map_or("".to_string(), |s| s.to_ascii_lowercase());
+    match format_from_path(path) {
+        Ok(format) => load_with_limits(fin, format, limits),
+        Err(err) => match path.extension().and_then(registry::find_by_extension) {
+            Some(codec) => codec.decode(&mut fin, limits),
+            None => Err(err),
+        },
+    }
+}
 
-    let format = match &ext[..] {
-        "jpg" |
-        "jpeg" => image::ImageFormat::JPEG,
-        "png"  => image::ImageFormat::PNG,
-        "gif"  => image::ImageFormat::GIF,
-        "webp" => image::ImageFormat::WEBP,
-        "tif" |
-        "tiff" => image::ImageFormat::TIFF,
-        "tga" => image::ImageFormat::TGA,
-        "bmp" => image::ImageFormat::BMP,
-        "ico" => image::ImageFormat::ICO,
-        "hdr" => image::ImageFormat::HDR,
-        "ppm" => image::ImageFormat::PPM,
-        format => return Err(image::ImageError::UnsupportedError(format!(
-            "Image format image/{:?} is not supported.",
-            format
-        )))
-    };
+/// Read the dimensions of the image located at the path specified, without decoding it.
+/// The image's format is determined from the path's file extension.
+///
+/// Only the header of the image is read, so this is much faster than opening the image.
+pub fn image_dimensions<P>(path: P) -> ImageResult<(u32, u32)> where P: AsRef<Path> {
+    // thin wrapper function to strip generics before calling image_dimensions_impl
+    image_dimensions_impl(path.as_ref())
+}
+
+fn image_dimensions_impl(path: &Path) -> ImageResult<(u32, u32)> {
+    let mut fin = BufReader::new(try!(File::open(path)));
+    match format_from_path(path) {
+        Ok(format) => load_dimensions(fin, format),
+        Err(err) => match path.extension().and_then(registry::find_by_extension) {
+            Some(codec) => codec.dimensions(&mut fin),
+            None => Err(err),
+        },
+    }
+}
+
+/// Open the image located at the path specified, turning it upright.
+///
+/// Like `open`, but jpeg and DNG images are rotated and flipped according to the orientation stored in
+/// their EXIF data, as cameras store photos in the orientation of their sensor. HEIF images are
+/// always decoded upright.
+pub fn open_with_orientation<P>(path: P) -> ImageResult<DynamicImage> where P: AsRef<Path> {
+    open_with_orientation_impl(path.as_ref())
+}
+
+fn open_with_orientation_impl(path: &Path) -> ImageResult<DynamicImage> {
+    match try!(format_from_path(path)) {
+        #[cfg(feature = "jpeg")]
+        image::ImageFormat::JPEG => {
+            let fin = BufReader::new(try!(File::open(path)));
+            let mut decoder = jpeg::JPEGDecoder::new(fin);
+            let orientation = try!(decoder.orientation());
+            let image = try!(decoder_to_image_with_limits(decoder, &Limits::default()));
+            Ok(match orientation {
+                Some(orientation) => orient(&image, orientation),
+                None => image,
+            })
+        },
+        #[cfg(feature = "dng")]
+        image::ImageFormat::DNG => {
+            let decoder = try!(dng::DNGDecoder::new(BufReader::new(try!(File::open(path)))));
+            let orientation = decoder.orientation();
+            let image = try!(decoder_to_image_with_limits(decoder, &Limits::default()));
+            Ok(match orientation {
+                Some(orientation) => orient(&image, orientation),
+                None => image,
+            })
+        },
+        _ => open_impl(path, &Limits::default()),
+    }
+}
+
+/// Transforms ```image``` according to an EXIF ```orientation``` so it is shown upright
+fn orient(image: &DynamicImage, orientation: u16) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image.clone(),
+    }
+}
 
-    load(fin, format)
+/// The format of an image file, as indicated by the extension of its ```path```
+fn format_from_path(path: &Path) -> ImageResult<ImageFormat> {
+    let ext = path.extension().unwrap_or_default();
+    ImageFormat::from_extension(ext).ok_or_else(|| image::ImageError::unsupported_format(
+        ImageFormatHint::Name(ext.to_string_lossy().to_ascii_lowercase())
+    ))
+}
+
+/// Swaps the first and third sample of the pixels of ```channels``` samples of ```bits``` bits in ```buf```
+fn swap_red_blue(buf: &[u8], channels: usize, bits: u8) -> Vec<u8> {
+    let sample_len = bits as usize / 8;
+    let pixel_len = channels * sample_len;
+    let mut swapped = buf.to_vec();
+    for pixel in swapped.chunks_mut(pixel_len) {
+        if pixel.len() == pixel_len {
+            for i in 0..sample_len {
+                pixel.swap(i, 2 * sample_len + i);
+            }
+        }
+    }
+    swapped
 }
 
 /// Saves the supplied buffer to a file at the path specified.
 ///
-/// The image format is derived from the file extension. The buffer is assumed to have
-/// the correct format according to the specified color type.
+/// The image format is derived from the file extension, which is looked up in the `registry`
+/// for formats this crate does not support. The buffer is assumed to have
+/// the correct format according to the specified color type. Buffers of BGR or BGRA pixels are
+/// saved as RGB or RGBA images.
 
 /// This will lead to corrupted files if the buffer contains malformed data. Currently only
 /// jpeg and png files are supported.
@@ -592,76 +1456,337 @@ pub fn save_buffer<P>(path: P, buf: &[u8], width: u32, height: u32, color: color
 
 fn save_buffer_impl(path: &Path, buf: &[u8], width: u32, height: u32, color: color::ColorType)
                       -> io::Result<()> {
+    // No format stores its samples in BGR order, swap them into RGB order first
+    let (rgb, color) = match color {
+        color::ColorType::BGR(bits) => (swap_red_blue(buf, 3, bits), color::ColorType::RGB(bits)),
+        color::ColorType::BGRA(bits) => (swap_red_blue(buf, 4, bits), color::ColorType::RGBA(bits)),
+        _ => (Vec::new(), color),
+    };
+    let buf = if rgb.is_empty() { buf } else { &rgb[..] };
+
     let fout = &mut BufWriter::new(try!(File::create(path)));
     let ext = path.extension().and_then(|s| s.to_str())
                   .map_or("".to_string(), |s| s.to_ascii_lowercase());
 
-    match &*ext {
+    let result: ImageResult<()> = match &*ext {
+        #[cfg(feature = "ico")]
+        "ico" => ico::ICOEncoder::new(fout).write_image(buf, width, height, color),
         #[cfg(feature = "ico")]
-        "ico" => ico::ICOEncoder::new(fout).encode(buf, width, height, color),
+        "cur" => {
+            let mut encoder = ico::ICOEncoder::new(fout);
+            encoder.set_hotspot(0, 0);
+            encoder.write_image(buf, width, height, color)
+        },
         #[cfg(feature = "jpeg")]
         "jpg" |
-        "jpeg" => jpeg::JPEGEncoder::new(fout).encode(buf, width, height, color),
+        "jpeg" => jpeg::JPEGEncoder::new(fout).write_image(buf, width, height, color),
         #[cfg(feature = "png_codec")]
-        "png"  => png::PNGEncoder::new(fout).encode(buf, width, height, color),
-        #[cfg(feature = "ppm")]
-        "ppm"  => ppm::PPMEncoder::new(fout).encode(buf, width, height, color),
+        "png"  => png::PNGEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "pnm")]
+        "pbm" |
+        "pgm" |
+        "ppm"  => pnm::PNMEncoder::new(fout).write_image(buf, width, height, color),
         #[cfg(feature = "bmp")]
-        "bmp" => bmp::BMPEncoder::new(fout).encode(buf, width, height, color),
-        format => Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            &format!("Unsupported image format image/{:?}", format)[..],
-        ))
-    }
+        "bmp" => bmp::BMPEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "webp")]
+        "webp" => webp::WebpEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "tiff")]
+        "tif" |
+        "tiff" => tiff::TIFFEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "tga")]
+        "tga" => tga::TGAEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "qoi")]
+        "qoi" => qoi::QOIEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "farbfeld")]
+        "ff" |
+        "farbfeld" => farbfeld::FarbfeldEncoder::new(fout).write_image(buf, width, height, color),
+        #[cfg(feature = "sgi")]
+        "sgi" |
+        "rgb" |
+        "rgba" |
+        "bw" => sgi::SGIEncoder::new(fout).write_image(buf, width, height, color),
+        format => match registry::find_by_extension(OsStr::new(format)) {
+            Some(codec) => codec.encode(fout, buf, width, height, color),
+            None => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                &format!("Unsupported image format image/{:?}", format)[..],
+            )),
+        },
+    };
+    result.map_err(|err| match err {
+        image::ImageError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidInput, err),
+    })
 }
 
 /// Create a new image from a Reader
+///
+/// The image is checked against the default `Limits`.
 pub fn load<R: BufRead+Seek>(r: R, format: ImageFormat) -> ImageResult<DynamicImage> {
+    load_with_limits(r, format, &Limits::default())
+}
+
+/// Create a new image from a Reader, failing with `ImageError::Limits` if it exceeds
+/// ```limits```
+pub fn load_with_limits<R: BufRead+Seek>(r: R, format: ImageFormat, limits: &Limits) -> ImageResult<DynamicImage> {
+    load_with_progress(r, format, limits, &mut Monitor::new())
+}
+
+/// Create a new image from a Reader like `load_with_limits`, reporting the rows decoded to
+/// ```monitor```
+///
+/// Decoding fails with `ImageError::Cancelled` once the `CancelToken` of ```monitor``` is
+/// cancelled. PNG, PNM, TIFF, TGA and uncompressed BMP images are reported as their rows are
+/// decoded, images of all other formats once they have been decoded.
+pub fn load_with_progress<R: BufRead+Seek>(r: R, format: ImageFormat, limits: &Limits, monitor: &mut Monitor)
+                                           -> ImageResult<DynamicImage> {
+    load_decoded(r, format, limits, monitor, None)
+}
+
+/// Create a new image from a Reader like `load_with_limits`, converting its pixels from the ICC
+/// profile embedded in the image into ```target```
+///
+/// Images without a profile, or whose profile is not a matrix/TRC profile, are left unchanged.
+/// Converting photos stored in a wide gamut into `Profile::srgb` keeps them from looking washed
+/// out on displays that assume sRGB.
+pub fn load_with_profile<R: BufRead+Seek>(r: R, format: ImageFormat, limits: &Limits, target: &Profile)
+                                          -> ImageResult<DynamicImage> {
+    load_decoded(r, format, limits, &mut Monitor::new(), Some(target))
+}
+
+fn load_decoded<R: BufRead+Seek>(r: R, format: ImageFormat, limits: &Limits, monitor: &mut Monitor,
+                                 target: Option<&Profile>) -> ImageResult<DynamicImage> {
     match format {
         #[cfg(feature = "png_codec")]
-        image::ImageFormat::PNG  => decoder_to_image(png::PNGDecoder::new(r)),
+        image::ImageFormat::PNG  => decoder_to_image_with_progress(png::PNGDecoder::new(r), limits, monitor, target),
         #[cfg(feature = "gif_codec")]
-        image::ImageFormat::GIF  => decoder_to_image(gif::Decoder::new(r)),
+        image::ImageFormat::GIF  => decoder_to_image_with_progress(gif::Decoder::new(r), limits, monitor, target),
         #[cfg(feature = "jpeg")]
-        image::ImageFormat::JPEG => decoder_to_image(jpeg::JPEGDecoder::new(r)),
+        image::ImageFormat::JPEG => decoder_to_image_with_progress(jpeg::JPEGDecoder::new(r), limits, monitor, target),
         #[cfg(feature = "webp")]
-        image::ImageFormat::WEBP => decoder_to_image(webp::WebpDecoder::new(r)),
+        image::ImageFormat::WEBP => decoder_to_image_with_progress(webp::WebpDecoder::new(r), limits, monitor, target),
         #[cfg(feature = "tiff")]
-        image::ImageFormat::TIFF => decoder_to_image(try!(tiff::TIFFDecoder::new(r))),
+        image::ImageFormat::TIFF => {
+            let mut decoder = try!(tiff::TIFFDecoder::new(r));
+            decoder.set_limits(*limits);
+            decoder_to_image_with_progress(decoder, limits, monitor, target)
+        }
         #[cfg(feature = "tga")]
-        image::ImageFormat::TGA => decoder_to_image(tga::TGADecoder::new(r)),
+        image::ImageFormat::TGA => decoder_to_image_with_progress(tga::TGADecoder::new(r), limits, monitor, target),
         #[cfg(feature = "bmp")]
-        image::ImageFormat::BMP => decoder_to_image(bmp::BMPDecoder::new(r)),
+        image::ImageFormat::BMP => decoder_to_image_with_progress(bmp::BMPDecoder::new(r), limits, monitor, target),
         #[cfg(feature = "ico")]
-        image::ImageFormat::ICO => decoder_to_image(try!(ico::ICODecoder::new(r))),
+        image::ImageFormat::ICO => decoder_to_image_with_progress(try!(ico::ICODecoder::new(r)), limits, monitor, target),
         #[cfg(feature = "hdr")]
-        image::ImageFormat::HDR => decoder_to_image(try!(hdr::HDRAdapter::new(BufReader::new(r)))),
-        #[cfg(feature = "ppm")]
-        image::ImageFormat::PPM => decoder_to_image(try!(ppm::PPMDecoder::new(BufReader::new(r)))),
-        _ => Err(image::ImageError::UnsupportedError(format!("A decoder for {:?} is not available.", format))),
+        image::ImageFormat::HDR => {
+            let decoder = try!(hdr::HDRDecoder::new(BufReader::new(r)));
+            let meta = decoder.metadata();
+            try!(limits.check_image(meta.width, meta.height, color::ColorType::RGBF(32)));
+            try!(monitor.update(0, meta.height as u64));
+            let pixels = try!(decoder.read_image_hdr());
+            try!(monitor.update(meta.height as u64, meta.height as u64));
+            let samples = pixels.iter().flat_map(|p| p.data.iter().cloned()).collect();
+            ImageBuffer::from_raw(meta.width, meta.height, samples)
+                .map(DynamicImage::ImageRgb32F)
+                .ok_or(image::ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+        #[cfg(feature = "exr")]
+        image::ImageFormat::EXR => decoder_to_image_with_progress(exr::EXRDecoder::new(r), limits, monitor, target),
+        #[cfg(feature = "pnm")]
+        #[allow(deprecated)]
+        image::ImageFormat::PNM | image::ImageFormat::PPM => {
+            let options = pnm::PNMDecoderOptions { limits: *limits, ..pnm::PNMDecoderOptions::default() };
+            let decoder = try!(pnm::PNMDecoder::new_with_options(BufReader::new(r), options));
+            decoder_to_image_with_progress(decoder, limits, monitor, target)
+        }
+        #[cfg(feature = "qoi")]
+        image::ImageFormat::QOI => decoder_to_image_with_progress(qoi::QOIDecoder::new(r), limits, monitor, target),
+        #[cfg(feature = "farbfeld")]
+        image::ImageFormat::Farbfeld => decoder_to_image_with_progress(farbfeld::FarbfeldDecoder::new(r), limits, monitor, target),
+        #[cfg(feature = "dds")]
+        image::ImageFormat::DDS => decoder_to_image_with_progress(try!(dds::DDSDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "avif")]
+        image::ImageFormat::AVIF => decoder_to_image_with_progress(try!(avif::AVIFDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "heif")]
+        image::ImageFormat::HEIF => decoder_to_image_with_progress(try!(heif::HEIFDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "sgi")]
+        image::ImageFormat::SGI => decoder_to_image_with_progress(try!(sgi::SGIDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "xbm")]
+        image::ImageFormat::XBM => decoder_to_image_with_progress(try!(xbm::XBMDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "xpm")]
+        image::ImageFormat::XPM => decoder_to_image_with_progress(try!(xpm::XPMDecoder::new(r)), limits, monitor, target),
+        #[cfg(feature = "dng")]
+        image::ImageFormat::DNG => decoder_to_image_with_progress(try!(dng::DNGDecoder::new(r)), limits, monitor, target),
+        _ => Err(image::ImageError::unsupported_format(ImageFormatHint::Exact(format))),
     }
 }
 
-static MAGIC_BYTES: [(&'static [u8], ImageFormat); 11] = [
+/// Reads the animation of format ```format``` from a Reader, returning its frames and how often
+/// it is played
+///
+/// The frames are rendered onto canvases of the size of the animation, so that they can be
+/// written in any format. Images of formats other than GIF, APNG and WebP are a single frame
+/// played once. The animation is checked against the default `Limits`.
+pub fn load_animation<R: BufRead+Seek>(r: R, format: ImageFormat) -> ImageResult<(Frames, LoopCount)> {
+    let limits = Limits::default();
+    let (frames, loop_count, (width, height)) = match format {
+        #[cfg(feature = "gif_codec")]
+        ImageFormat::GIF => {
+            let mut decoder = gif::Decoder::new(r);
+            let loop_count = try!(decoder.loop_count());
+            let dimensions = try!(decoder.dimensions());
+            (try!(decoder.into_frames_with_limits(&limits)), loop_count, dimensions)
+        }
+        #[cfg(feature = "png_codec")]
+        ImageFormat::PNG => {
+            let mut decoder = png::PNGDecoder::new(r);
+            let loop_count = try!(decoder.loop_count());
+            let dimensions = try!(decoder.dimensions());
+            (try!(decoder.into_frames_with_limits(&limits)), loop_count, dimensions)
+        }
+        #[cfg(feature = "webp")]
+        ImageFormat::WEBP => {
+            let mut decoder = webp::WebpDecoder::new(r);
+            let loop_count = try!(decoder.loop_count());
+            let dimensions = try!(decoder.dimensions());
+            (try!(decoder.into_frames_with_limits(&limits)), loop_count, dimensions)
+        }
+        _ => {
+            let image = try!(load_with_limits(r, format, &limits));
+            let dimensions = image.dimensions();
+            (Frames::new(vec![Frame::new(image.to_rgba())]), LoopCount::Finite(1), dimensions)
+        }
+    };
+    Ok((frames.composite(width, height), loop_count))
+}
+
+/// Writes ```frames``` as an animation of format ```format``` to a Writer, played
+/// ```loop_count``` times
+///
+/// Only GIF, APNG and WebP images can be animated. GIF frames are cropped to the area that
+/// changed, WebP animations are stored losslessly.
+pub fn save_animation<W: Write, I>(w: W, frames: I, loop_count: LoopCount, format: ImageFormat) -> ImageResult<()>
+where I: IntoIterator<Item = Frame> {
+    match format {
+        #[cfg(feature = "gif_codec")]
+        ImageFormat::GIF => {
+            // Optimizing frames also clears pixels that become transparent again
+            let options = gif::EncoderOptions { optimize: true, ..gif::EncoderOptions::default() };
+            gif::Encoder::new_with_options(w, options).encode_frames(frames, loop_count)
+        }
+        #[cfg(feature = "png_codec")]
+        ImageFormat::PNG => png::PNGEncoder::new(w).encode_frames(frames, loop_count).map_err(From::from),
+        #[cfg(feature = "webp")]
+        ImageFormat::WEBP => webp::WebpEncoder::new(w).encode_frames(frames, loop_count).map_err(From::from),
+        _ => Err(image::ImageError::Unsupported {
+            format: ImageFormatHint::Exact(format),
+            feature: UnsupportedFeature::Other("animations".to_string()),
+        }),
+    }
+}
+
+/// Converts the animation of format ```from``` read from a Reader to format ```to```, keeping
+/// the delays of its frames and how often it is played
+///
+/// See `load_animation` and `save_animation`.
+pub fn transcode_animation<R: BufRead+Seek, W: Write>(r: R, from: ImageFormat, w: W, to: ImageFormat)
+                                                      -> ImageResult<()> {
+    let (frames, loop_count) = try!(load_animation(r, from));
+    save_animation(w, frames, loop_count, to)
+}
+
+/// Reads the dimensions of an image of format ```format``` from a Reader, without decoding it
+pub fn load_dimensions<R: BufRead+Seek>(r: R, format: ImageFormat) -> ImageResult<(u32, u32)> {
+    match format {
+        #[cfg(feature = "png_codec")]
+        ImageFormat::PNG  => png::PNGDecoder::new(r).dimensions(),
+        #[cfg(feature = "gif_codec")]
+        ImageFormat::GIF  => gif::Decoder::new(r).dimensions(),
+        #[cfg(feature = "jpeg")]
+        ImageFormat::JPEG => jpeg::JPEGDecoder::new(r).dimensions(),
+        #[cfg(feature = "webp")]
+        ImageFormat::WEBP => webp::WebpDecoder::new(r).dimensions(),
+        #[cfg(feature = "tiff")]
+        ImageFormat::TIFF => try!(tiff::TIFFDecoder::new(r)).dimensions(),
+        #[cfg(feature = "tga")]
+        ImageFormat::TGA => tga::TGADecoder::new(r).dimensions(),
+        #[cfg(feature = "bmp")]
+        ImageFormat::BMP => bmp::BMPDecoder::new(r).dimensions(),
+        #[cfg(feature = "ico")]
+        ImageFormat::ICO => try!(ico::ICODecoder::new(r)).dimensions(),
+        #[cfg(feature = "hdr")]
+        ImageFormat::HDR => {
+            let meta = try!(hdr::HDRDecoder::new(BufReader::new(r))).metadata();
+            Ok((meta.width, meta.height))
+        }
+        #[cfg(feature = "exr")]
+        ImageFormat::EXR => exr::EXRDecoder::new(r).dimensions(),
+        #[cfg(feature = "pnm")]
+        #[allow(deprecated)]
+        ImageFormat::PNM | ImageFormat::PPM => try!(pnm::PNMDecoder::new(BufReader::new(r))).dimensions(),
+        #[cfg(feature = "qoi")]
+        ImageFormat::QOI => qoi::QOIDecoder::new(r).dimensions(),
+        #[cfg(feature = "farbfeld")]
+        ImageFormat::Farbfeld => farbfeld::FarbfeldDecoder::new(r).dimensions(),
+        #[cfg(feature = "dds")]
+        ImageFormat::DDS => try!(dds::DDSDecoder::new(r)).dimensions(),
+        #[cfg(feature = "avif")]
+        ImageFormat::AVIF => try!(avif::AVIFDecoder::new(r)).dimensions(),
+        #[cfg(feature = "heif")]
+        ImageFormat::HEIF => try!(heif::HEIFDecoder::new(r)).dimensions(),
+        #[cfg(feature = "sgi")]
+        ImageFormat::SGI => try!(sgi::SGIDecoder::new(r)).dimensions(),
+        #[cfg(feature = "xbm")]
+        ImageFormat::XBM => try!(xbm::XBMDecoder::new(r)).dimensions(),
+        #[cfg(feature = "xpm")]
+        ImageFormat::XPM => try!(xpm::XPMDecoder::new(r)).dimensions(),
+        #[cfg(feature = "dng")]
+        ImageFormat::DNG => try!(dng::DNGDecoder::new(r)).dimensions(),
+        _ => Err(image::ImageError::unsupported_format(ImageFormatHint::Exact(format))),
+    }
+}
+
+static MAGIC_BYTES: [(&'static [u8], ImageFormat); 25] = [
     (b"\x89PNG\r\n\x1a\n", ImageFormat::PNG),
     (&[0xff, 0xd8, 0xff], ImageFormat::JPEG),
     (b"GIF89a", ImageFormat::GIF),
     (b"GIF87a", ImageFormat::GIF),
-    (b"WEBP", ImageFormat::WEBP),
-    (b"MM.*", ImageFormat::TIFF),
-    (b"II*.", ImageFormat::TIFF),
+    (b"MM\0*", ImageFormat::TIFF),
+    (b"II*\0", ImageFormat::TIFF),
     (b"BM", ImageFormat::BMP),
     (&[0, 0, 1, 0], ImageFormat::ICO),
+    (&[0, 0, 2, 0], ImageFormat::ICO),
     (b"#?RADIANCE", ImageFormat::HDR),
-    (b"P6", ImageFormat::PPM),
+    (b"#?RGBE", ImageFormat::HDR),
+    (&[0x76, 0x2f, 0x31, 0x01], ImageFormat::EXR),
+    (b"qoif", ImageFormat::QOI),
+    (b"farbfeld", ImageFormat::Farbfeld),
+    (b"DDS ", ImageFormat::DDS),
+    (&[0x01, 0xda], ImageFormat::SGI),
+    (b"/* XPM */", ImageFormat::XPM),
+    (b"#define", ImageFormat::XBM),
+    (b"P1", ImageFormat::PNM),
+    (b"P2", ImageFormat::PNM),
+    (b"P3", ImageFormat::PNM),
+    (b"P4", ImageFormat::PNM),
+    (b"P5", ImageFormat::PNM),
+    (b"P6", ImageFormat::PNM),
+    (b"P7", ImageFormat::PNM),
 ];
 
 /// Create a new image from a byte slice
 ///
-/// Makes an educated guess about the image format.
+/// Makes an educated guess about the image format, falling back to the codecs of the
+/// `registry` for formats this crate does not support.
 /// TGA is not supported by this function.
 pub fn load_from_memory(buffer: &[u8]) -> ImageResult<DynamicImage> {
-    load_from_memory_with_format(buffer, try!(guess_format(buffer)))
+    match guess_format(buffer) {
+        Ok(format) => load_from_memory_with_format(buffer, format),
+        Err(err) => match registry::find_by_content(buffer) {
+            Some(codec) => codec.decode(&mut io::Cursor::new(buffer), &Limits::default()),
+            None => Err(err),
+        },
+    }
 }
 
 
@@ -675,17 +1800,56 @@ pub fn load_from_memory_with_format(buf: &[u8], format: ImageFormat) -> ImageRes
 /// Guess image format from memory block
 ///
 /// Makes an educated guess about the image format based on the Magic Bytes at the beginning.
-/// TGA is not supported by this function.
+/// TGA is not supported by this function. DNG images are told apart from TIFF images by the
+/// DNGVersion tag of their first directory, which has to be part of ```buffer```.
 /// This is not to be trusted on the validity of the whole memory block
 pub fn guess_format(buffer: &[u8]) -> ImageResult<ImageFormat> {
+    if is_dng(buffer) {
+        return Ok(ImageFormat::DNG)
+    }
     for &(signature, format) in &MAGIC_BYTES {
         if buffer.starts_with(signature) {
             return Ok(format);
         }
     }
-    Err(image::ImageError::UnsupportedError(
-        "Unsupported image format".to_string())
-    )
+    if buffer.len() >= 12 && &buffer[..4] == b"RIFF" && &buffer[8..12] == b"WEBP" {
+        return Ok(ImageFormat::WEBP)
+    }
+    // AVIF and HEIF files start with a file type box of any size
+    if buffer.len() >= 12 && &buffer[4..8] == b"ftyp" {
+        match &buffer[8..12] {
+            b"avif" => return Ok(ImageFormat::AVIF),
+            b"heic" | b"heix" | b"mif1" => return Ok(ImageFormat::HEIF),
+            _ => (),
+        }
+    }
+    Err(image::ImageError::unsupported_format(ImageFormatHint::Unknown))
+}
+
+/// Whether ```buffer``` is a TIFF file whose first directory has the DNGVersion tag
+fn is_dng(buffer: &[u8]) -> bool {
+    let big_endian = match buffer.get(..4) {
+        Some(b"MM\0*") => true,
+        Some(b"II*\0") => false,
+        _ => return false,
+    };
+    let read = |pos: usize, len: usize| buffer.get(pos..pos + len).map(|bytes| {
+        let bytes = bytes.iter().map(|&b| b as usize);
+        if big_endian {
+            bytes.fold(0, |v, b| v << 8 | b)
+        } else {
+            bytes.rev().fold(0, |v, b| v << 8 | b)
+        }
+    });
+    let offset = match read(4, 4) {
+        Some(offset) => offset,
+        None => return false,
+    };
+    let count = match read(offset, 2) {
+        Some(count) => count,
+        None => return false,
+    };
+    (0..count).any(|i| read(offset + 2 + i * 12, 2) == Some(50706))
 }
 
 #[cfg(test)]
@@ -706,8 +1870,586 @@ mod bench {
 
 #[cfg(test)]
 mod test {
+    use image::{GenericImage, GenericImageView};
+
     #[test]
     fn test_empty_file() {
         assert!(super::load_from_memory(b"").is_err());
     }
+
+    #[test]
+    fn test_orient() {
+        // 1 2
+        // 3 4
+        let image = super::DynamicImage::ImageLuma8(::ImageBuffer::from_raw(2, 2, vec![1, 2, 3, 4]).unwrap());
+        let oriented = |orientation| super::orient(&image, orientation).raw_pixels();
+        assert_eq!(oriented(1), vec![1, 2, 3, 4]);
+        assert_eq!(oriented(2), vec![2, 1, 4, 3]);
+        assert_eq!(oriented(3), vec![4, 3, 2, 1]);
+        assert_eq!(oriented(4), vec![3, 4, 1, 2]);
+        assert_eq!(oriented(5), vec![1, 3, 2, 4]);
+        assert_eq!(oriented(6), vec![3, 1, 4, 2]);
+        assert_eq!(oriented(7), vec![4, 2, 3, 1]);
+        assert_eq!(oriented(8), vec![2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn test_float_conversions() {
+        let image = super::DynamicImage::ImageRgb32F(
+            ::ImageBuffer::from_raw(2, 1, vec![0.0, 0.5, 2.0, -1.0, 0.25, 1.0]).unwrap());
+        assert_eq!(image.color(), ::ColorType::RGBF(32));
+        assert_eq!(image.to_rgb().into_raw(), vec![0, 128, 255, 0, 64, 255]);
+        assert_eq!(image.to_rgba().into_raw(), vec![0, 128, 255, 255, 0, 64, 255, 255]);
+        assert_eq!(image.to_rgba32f().into_raw(), vec![0.0, 0.5, 2.0, 1.0, -1.0, 0.25, 1.0, 1.0]);
+        assert_eq!(&image.raw_pixels()[..8], &[0, 0, 0, 0, 0x3f, 0, 0, 0]);
+
+        let gray = super::DynamicImage::ImageLuma8(::ImageBuffer::from_raw(1, 1, vec![51]).unwrap());
+        assert_eq!(gray.to_rgba32f().into_raw(), vec![0.2, 0.2, 0.2, 1.0]);
+        assert_eq!(<::Rgba<f32> as ::Pixel>::color_type(), ::ColorType::RGBAF(32));
+    }
+
+    #[test]
+    fn test_16_bit_conversions() {
+        let image = super::DynamicImage::ImageRgb16(
+            ::ImageBuffer::from_raw(2, 1, vec![0, 0x8080, 0xffff, 0x7f7f, 0x00ff, 0x1234]).unwrap());
+        assert_eq!(image.color(), ::ColorType::RGB(16));
+        assert_eq!(image.to_rgb().into_raw(), vec![0, 128, 255, 127, 1, 18]);
+        assert_eq!(image.to_luma16().into_raw(), image.grayscale().as_luma16().unwrap().clone().into_raw());
+        assert_eq!(image.to_rgba32f().get_pixel(0, 0).data, [0.0, 0x8080 as f32 / 65535.0, 1.0, 1.0]);
+        assert_eq!(&image.raw_pixels()[..6], &[0, 0, 0x80, 0x80, 0xff, 0xff]);
+        assert_eq!(image.get_pixel(1, 0), ::Rgba([127, 1, 18, 255]));
+
+        let mut gray = super::DynamicImage::new_luma_a16(1, 1);
+        gray.put_pixel(0, 0, ::Rgba([51, 51, 51, 255]));
+        assert_eq!(gray.as_luma_alpha16().unwrap().get_pixel(0, 0).data, [0x3333, 0xffff]);
+        assert_eq!(gray.to_rgba16().into_raw(), vec![0x3333, 0x3333, 0x3333, 0xffff]);
+        assert_eq!(gray.brighten(1).as_luma_alpha16().unwrap().get_pixel(0, 0).data, [0x3434, 0xffff]);
+    }
+
+    #[test]
+    fn test_float_operations() {
+        let samples: Vec<f32> = (0..4 * 4 * 3).map(|i| i as f32 * 0.125).collect();
+        let mut image = super::DynamicImage::ImageRgb32F(::ImageBuffer::from_raw(4, 4, samples).unwrap());
+
+        // Values above 1 survive geometric operations
+        let cropped = image.crop(1, 1, 2, 2).fliph();
+        assert_eq!(cropped.as_rgb32f().unwrap().get_pixel(0, 0).data, [2.25, 2.375, 2.5]);
+        let resized = image.resize_exact(2, 2, ::FilterType::Nearest);
+        assert!(resized.as_rgb32f().unwrap().pixels().any(|p| p[2] > 3.0));
+
+        let brighter = image.brighten(51);
+        assert_eq!(brighter.as_rgb32f().unwrap().get_pixel(3, 3).data, [5.825, 5.95, 6.075]);
+        let mut inverted = super::DynamicImage::new_rgba32f(1, 1);
+        inverted.invert();
+        assert_eq!(inverted.as_rgba32f().unwrap().get_pixel(0, 0).data, [1.0, 1.0, 1.0, 0.0]);
+
+        image.put_pixel(0, 0, ::Rgba([255, 0, 51, 255]));
+        assert_eq!(image.as_rgb32f().unwrap().get_pixel(0, 0).data, [1.0, 0.0, 0.2]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "gif_codec", feature = "png_codec", feature = "webp"))]
+    fn test_transcode_animation() {
+        use std::io::Cursor;
+        use super::ImageFormat;
+        use animation::{Delay, Frame, LoopCount};
+
+        // A red square with a blue pixel that is cleared after it is shown
+        let mut blue = Frame::from_parts(::RgbaImage::from_pixel(1, 1, ::Rgba([0, 0, 255, 255])), 1, 1,
+                                         Delay::from_numer_denom_ms(250, 1));
+        blue.set_disposal(::Disposal::Background);
+        let frames = vec![
+            Frame::from_parts(::RgbaImage::from_pixel(2, 2, ::Rgba([255, 0, 0, 255])), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            blue,
+            Frame::from_parts(::RgbaImage::from_pixel(1, 1, ::Rgba([0, 255, 0, 255])), 0, 0, Delay::from_numer_denom_ms(1000, 1)),
+        ];
+        let mut gif = Vec::new();
+        ::gif::Encoder::new(&mut gif).encode_frames(frames, LoopCount::Finite(2)).unwrap();
+        let expected: Vec<_> = super::load_animation(Cursor::new(&gif), ImageFormat::GIF).unwrap().0.collect();
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[2].buffer().get_pixel(1, 1), &::Rgba([0, 0, 0, 0]));
+
+        let mut data = gif;
+        for &(from, to) in &[(ImageFormat::GIF, ImageFormat::PNG),
+                             (ImageFormat::PNG, ImageFormat::WEBP),
+                             (ImageFormat::WEBP, ImageFormat::GIF)] {
+            let mut transcoded = Vec::new();
+            super::transcode_animation(Cursor::new(&data), from, &mut transcoded, to).unwrap();
+            let (frames, loop_count) = super::load_animation(Cursor::new(&transcoded), to).unwrap();
+            assert_eq!(loop_count, LoopCount::Finite(2));
+            let frames: Vec<_> = frames.collect();
+            assert_eq!(frames.len(), expected.len());
+            for (frame, expected) in frames.iter().zip(&expected) {
+                assert_eq!(frame.delay(), expected.delay());
+                assert_eq!(frame.buffer().clone().into_raw(), expected.buffer().clone().into_raw());
+            }
+            data = transcoded;
+        }
+
+        // Formats without animations can not be written as such
+        let (frames, loop_count) = super::load_animation(Cursor::new(&data), ImageFormat::GIF).unwrap();
+        assert!(super::save_animation(Vec::new(), frames, loop_count, ImageFormat::BMP).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "tiff", feature = "hdr", feature = "png_codec"))]
+    fn test_save_float() {
+        let samples: Vec<f32> = (0..3 * 2 * 4).map(|i| i as f32 * 0.375).collect();
+        let image = super::DynamicImage::ImageRgba32F(::ImageBuffer::from_raw(3, 2, samples.clone()).unwrap());
+        let save = |format| {
+            let mut encoded = Vec::new();
+            image.save(&mut encoded, format).unwrap();
+            super::load_from_memory_with_format(&encoded, format).unwrap()
+        };
+
+        match save(super::ImageFormat::TIFF) {
+            super::DynamicImage::ImageRgba32F(decoded) => assert_eq!(decoded.into_raw(), samples),
+            _ => panic!("Expected a floating point RGBA image"),
+        }
+        match save(super::ImageFormat::HDR) {
+            super::DynamicImage::ImageRgb32F(decoded) => {
+                let rgb = samples.chunks(4).flat_map(|p| p[..3].to_vec());
+                assert!(decoded.iter().zip(rgb).all(|(&a, b)| (a - b).abs() <= b / 128.0));
+            }
+            _ => panic!("Expected a floating point RGB image"),
+        }
+        match save(super::ImageFormat::PNG) {
+            super::DynamicImage::ImageRgba8(decoded) => assert_eq!(&decoded.into_raw()[..8], &[0, 96, 191, 255, 255, 255, 255, 255]),
+            _ => panic!("Expected an 8-bit RGBA image"),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "png_codec", feature = "tiff", feature = "pnm", feature = "jpeg"))]
+    fn test_save_16_bits() {
+        use std::io::Cursor;
+        use image::{DecodingResult, ImageDecoder};
+        use pnm::PNMDecoder;
+        use tiff::TIFFDecoder;
+
+        let samples: Vec<u16> = (0..2 * 2 * 4).map(|i| i * 4111 + 0x0102).collect();
+        let images = [
+            super::DynamicImage::ImageLuma16(::ImageBuffer::from_raw(2, 2, samples[..4].to_vec()).unwrap()),
+            super::DynamicImage::ImageLumaA16(::ImageBuffer::from_raw(2, 2, samples[..8].to_vec()).unwrap()),
+            super::DynamicImage::ImageRgb16(::ImageBuffer::from_raw(2, 2, samples[..12].to_vec()).unwrap()),
+            super::DynamicImage::ImageRgba16(::ImageBuffer::from_raw(2, 2, samples.clone()).unwrap()),
+        ];
+        let save = |image: &super::DynamicImage, format| {
+            let mut encoded = Vec::new();
+            image.save(&mut encoded, format).unwrap();
+            encoded
+        };
+        let decode = |data: Vec<u8>, format| {
+            let decoded = match format {
+                super::ImageFormat::TIFF => TIFFDecoder::new(Cursor::new(data)).and_then(|mut d| d.read_image()),
+                _ => PNMDecoder::new(Cursor::new(data)).and_then(|mut d| d.read_image()),
+            };
+            match decoded.unwrap() {
+                DecodingResult::U16(samples) => samples,
+                _ => panic!("Expected 16-bit samples"),
+            }
+        };
+
+        for image in &images {
+            let raw = match *image {
+                super::DynamicImage::ImageLuma16(ref p) => p.clone().into_raw(),
+                super::DynamicImage::ImageLumaA16(ref p) => p.clone().into_raw(),
+                super::DynamicImage::ImageRgb16(ref p) => p.clone().into_raw(),
+                super::DynamicImage::ImageRgba16(ref p) => p.clone().into_raw(),
+                _ => unreachable!(),
+            };
+            // The bit depth of the IHDR chunk
+            assert_eq!(save(image, super::ImageFormat::PNG)[24], 16);
+            // TIFF images have no gray alpha and PNM images no alpha at all
+            if image.color() != ::ColorType::GrayA(16) {
+                assert_eq!(decode(save(image, super::ImageFormat::TIFF), super::ImageFormat::TIFF), raw);
+            }
+            if image.color() == ::ColorType::Gray(16) || image.color() == ::ColorType::RGB(16) {
+                assert_eq!(decode(save(image, super::ImageFormat::PNM), super::ImageFormat::PNM), raw);
+            }
+
+            // Formats without 16-bit samples get the image converted to 8 bits
+            let mut jpeg = Vec::new();
+            image.save(&mut jpeg, super::ImageFormat::JPEG).unwrap();
+            let decoded = super::load_from_memory_with_format(&jpeg, super::ImageFormat::JPEG).unwrap();
+            assert_eq!(decoded.dimensions(), (2, 2));
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "tiff", feature = "pnm"))]
+    fn test_load_16_bits() {
+        let samples: Vec<u16> = (0..3 * 2 * 4).map(|i| i * 2729 + 0x0102).collect();
+        let images = [
+            super::DynamicImage::ImageLuma16(::ImageBuffer::from_raw(3, 2, samples[..6].to_vec()).unwrap()),
+            super::DynamicImage::ImageRgb16(::ImageBuffer::from_raw(3, 2, samples[..18].to_vec()).unwrap()),
+            super::DynamicImage::ImageRgba16(::ImageBuffer::from_raw(3, 2, samples.clone()).unwrap()),
+        ];
+        for image in &images {
+            for &format in &[super::ImageFormat::TIFF, super::ImageFormat::PNM] {
+                if format == super::ImageFormat::PNM && image.color() == ::ColorType::RGBA(16) {
+                    continue
+                }
+                let mut encoded = Vec::new();
+                image.save(&mut encoded, format).unwrap();
+                let decoded = super::load_from_memory(&encoded).unwrap();
+                assert_eq!(decoded.color(), image.color());
+                assert_eq!(decoded.raw_pixels(), image.raw_pixels());
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "pnm", feature = "tiff", feature = "tga", feature = "bmp"))]
+    fn test_load_progress() {
+        use std::io::Cursor;
+        use image::{ImageError, ImageFormat, Limits};
+        use monitor::{CancelToken, Monitor};
+
+        let pixels = (0..4 * 6 * 3).map(|i| (i * 11) as u8).collect();
+        let image = super::DynamicImage::ImageRgb8(::ImageBuffer::from_raw(4, 6, pixels).unwrap());
+        for &format in &[ImageFormat::PNM, ImageFormat::TIFF, ImageFormat::TGA, ImageFormat::BMP] {
+            let mut encoded = Vec::new();
+            image.save(&mut encoded, format).unwrap();
+            let load = |monitor: &mut Monitor| {
+                super::load_with_progress(Cursor::new(&encoded), format, &Limits::default(), monitor)
+            };
+
+            let mut updates = Vec::new();
+            let decoded = load(&mut Monitor::new().with_callback(|done, total| updates.push((done, total))));
+            assert_eq!(decoded.unwrap().raw_pixels(), image.raw_pixels());
+            assert_eq!(updates.first(), Some(&(0, 6)));
+            assert_eq!(updates.last(), Some(&(6, 6)));
+            // The small TIFF image is stored in a single strip
+            if format == ImageFormat::TIFF {
+                continue
+            }
+            assert_eq!(updates.len(), 7, "{:?}", format);
+
+            // Decoding stops at the first row after the token has been cancelled
+            let token = CancelToken::new();
+            let mut monitor = Monitor::new()
+                .with_callback(|done, _| if done == 2 { token.cancel() })
+                .with_cancel_token(token.clone());
+            match load(&mut monitor) {
+                Err(ImageError::Cancelled) => (),
+                r => panic!("Expected decoding {:?} to be cancelled, got {:?}", format, r.is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "jpeg", feature = "pnm"))]
+    fn test_write_with_options() {
+        use jpeg::JPEGEncoderOptions;
+        use pnm::{PNMEncoderOptions, SampleEncoding};
+
+        let pixels = (0..32 * 32 * 3).map(|i| (i * 7 % 251) as u8).collect();
+        let image = super::DynamicImage::ImageRgb8(::ImageBuffer::from_raw(32, 32, pixels).unwrap());
+        let jpeg = |quality| {
+            let mut encoded = Vec::new();
+            let options = JPEGEncoderOptions { quality: quality, ..JPEGEncoderOptions::default() };
+            image.write_with_options(&mut encoded, super::EncoderOptions::JPEG(options)).unwrap();
+            encoded
+        };
+        assert!(jpeg(10).len() < jpeg(95).len());
+
+        let mut encoded = Vec::new();
+        let options = PNMEncoderOptions { encoding: SampleEncoding::Ascii, ..PNMEncoderOptions::default() };
+        image.write_with_options(&mut encoded, super::EncoderOptions::PNM(options)).unwrap();
+        assert_eq!(&encoded[..2], b"P3");
+        let decoded = super::load_from_memory_with_format(&encoded, super::ImageFormat::PNM).unwrap();
+        assert_eq!(decoded.raw_pixels(), image.raw_pixels());
+
+        // The format is chosen regardless of the extension
+        let path = ::std::env::temp_dir().join("image-save-with-format.png");
+        image.save_with_format(&path, super::ImageFormat::PNM).unwrap();
+        let saved = ::std::fs::read(&path).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(super::guess_format(&saved).unwrap(), super::ImageFormat::PNM);
+    }
+
+    #[test]
+    #[cfg(feature = "tiff")]
+    fn test_load_float_gray() {
+        let mut encoded = Vec::new();
+        ::tiff::TIFFEncoder::new(&mut encoded)
+            .encode(&[0x3f, 0x80, 0, 0, 0x40, 0, 0, 0], 2, 1, ::ColorType::GrayF(32)).unwrap();
+        match super::load_from_memory_with_format(&encoded, super::ImageFormat::TIFF).unwrap() {
+            super::DynamicImage::ImageRgb32F(image) => assert_eq!(image.into_raw(), vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0]),
+            _ => panic!("Expected a floating point RGB image"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "qoi")]
+    fn test_qoi_from_memory() {
+        let image = super::DynamicImage::ImageLumaA8(::ImageBuffer::from_raw(2, 1, vec![7, 255, 9, 0]).unwrap());
+        let mut encoded = Vec::new();
+        image.save(&mut encoded, super::ImageFormat::QOI).unwrap();
+        assert_eq!(super::guess_format(&encoded).unwrap(), super::ImageFormat::QOI);
+        match super::load_from_memory(&encoded).unwrap() {
+            super::DynamicImage::ImageRgba8(image) => assert_eq!(image.into_raw(), vec![7, 7, 7, 255, 9, 9, 9, 0]),
+            _ => panic!("Expected an RGBA image"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "farbfeld")]
+    fn test_farbfeld_from_memory() {
+        let mut encoded = b"farbfeld\0\0\0\x02\0\0\0\x01".to_vec();
+        encoded.extend_from_slice(&[0, 0, 0x80, 0, 0xff, 0xff, 0xff, 0xff, 0x12, 0x34, 0, 1, 0, 0, 0, 0]);
+        assert_eq!(super::guess_format(&encoded).unwrap(), super::ImageFormat::Farbfeld);
+        let image = super::load_from_memory(&encoded).unwrap();
+        match image {
+            super::DynamicImage::ImageRgba16(ref p) => assert_eq!(p.get_pixel(1, 0).data, [0x1234, 1, 0, 0]),
+            _ => panic!("Expected a 16-bit RGBA image"),
+        }
+        let mut saved = Vec::new();
+        image.save(&mut saved, super::ImageFormat::Farbfeld).unwrap();
+        assert_eq!(saved, encoded);
+    }
+
+    #[test]
+    fn test_guess_pnm_format() {
+        for magic in &[b"P1", b"P2", b"P3", b"P4", b"P5", b"P6", b"P7"] {
+            assert_eq!(super::guess_format(&magic[..]).unwrap(), super::ImageFormat::PNM);
+        }
+        assert!(super::guess_format(b"P8").is_err());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_ppm_format() {
+        use image::ImageFormat::{PNM, PPM};
+
+        let image = super::load_from_memory_with_format(b"P6 1 1 255 \x01\x02\x03", PPM).unwrap();
+        assert_eq!(image.to_rgb().into_raw(), vec![1, 2, 3]);
+        let mut encoded = Vec::new();
+        image.save(&mut encoded, PPM).unwrap();
+        assert_eq!(super::guess_format(&encoded).unwrap(), PNM);
+        assert_eq!(PPM.to_mime_type(), PNM.to_mime_type());
+    }
+
+    #[test]
+    fn test_guess_every_format() {
+        use image::ImageFormat::*;
+
+        // Matching every format makes new formats fail to compile until they are added here
+        let start = |format| -> Option<&'static [u8]> {
+            Some(match format {
+                PNG => b"\x89PNG\r\n\x1a\n",
+                JPEG => &[0xff, 0xd8, 0xff, 0xe0],
+                GIF => b"GIF89a",
+                WEBP => b"RIFF\0\0\0\0WEBPVP8 ",
+                PNM => b"P6\n",
+                // PPM files are guessed as PNM
+                #[allow(deprecated)]
+                PPM => return None,
+                TIFF => b"MM\0*",
+                // TGA files have no signature
+                TGA => return None,
+                BMP => b"BM",
+                ICO => &[0, 0, 1, 0],
+                HDR => b"#?RADIANCE\n",
+                EXR => &[0x76, 0x2f, 0x31, 0x01],
+                QOI => b"qoif",
+                Farbfeld => b"farbfeld",
+                DDS => b"DDS ",
+                AVIF => b"\0\0\0\x1cftypavif",
+                HEIF => b"\0\0\0\x18ftypheic",
+                SGI => &[0x01, 0xda],
+                XBM => b"#define",
+                XPM => b"/* XPM */",
+                DNG => b"II*\0\x08\0\0\0\x01\0\x12\xc6\x01\0\x04\0\0\0\x01\x04\0\0\0\0\0\0",
+            })
+        };
+        let formats = [PNG, JPEG, GIF, WEBP, PNM, TIFF, TGA, BMP, ICO, HDR, EXR, QOI, Farbfeld, DDS,
+                       AVIF, HEIF, SGI, XBM, XPM, DNG];
+        for &format in &formats {
+            if let Some(data) = start(format) {
+                assert_eq!(super::guess_format(data).ok(), Some(format), "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_guess_test_images() {
+        use std::fs::{self, File};
+        use std::io::Read;
+        use std::path::Path;
+
+        // The format of every test image is guessed from its contents as from its extension
+        let mut dirs = vec![Path::new("tests/images").to_path_buf()];
+        let mut checked = 0;
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue
+                }
+                let format = match path.extension().and_then(super::ImageFormat::from_extension) {
+                    Some(super::ImageFormat::TGA) | None => continue,
+                    Some(format) => format,
+                };
+                let mut data = Vec::new();
+                File::open(&path).unwrap().read_to_end(&mut data).unwrap();
+                assert_eq!(super::guess_format(&data).ok(), Some(format), "{}", path.display());
+                checked += 1;
+            }
+        }
+        assert!(checked > 50);
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn test_image_dimensions() {
+        use std::fs::{self, File};
+        use std::io::{Cursor, Read};
+        use std::path::Path;
+
+        // The dimensions read from the header of every test image match those of the decoded image
+        let mut dirs = vec![Path::new("tests/images").to_path_buf()];
+        let mut checked = 0;
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                    continue
+                }
+                if let Ok(image) = super::open(&path) {
+                    assert_eq!(super::image_dimensions(&path).unwrap(), image.dimensions(), "{}", path.display());
+                    checked += 1;
+                }
+            }
+        }
+        assert!(checked > 50);
+        assert!(super::image_dimensions("tests/images/missing.png").is_err());
+
+        // Only the header is read, so images cut off in their data still have dimensions
+        let mut data = Vec::new();
+        File::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap().read_to_end(&mut data).unwrap();
+        let end = data.windows(4).position(|w| w == b"IDAT").unwrap() + 16;
+        let dimensions = super::load_dimensions(Cursor::new(&data[..end]), super::ImageFormat::PNG).unwrap();
+        assert_eq!(dimensions, super::load_from_memory(&data).unwrap().dimensions());
+        assert!(super::load_from_memory(&data[..end]).is_err());
+    }
+
+    #[test]
+    fn test_guess_dng_format() {
+        // A little endian TIFF with a directory holding a single DNGVersion entry
+        let mut dng = b"II*\0\x08\0\0\0\x01\0\x12\xc6\x01\0\x04\0\0\0\x01\x04\0\0\0\0\0\0".to_vec();
+        assert_eq!(super::guess_format(&dng).unwrap(), super::ImageFormat::DNG);
+        // Other tags are plain TIFF images, as are directories beyond the buffer
+        dng[10] = 0x13;
+        assert_eq!(super::guess_format(&dng).unwrap(), super::ImageFormat::TIFF);
+        assert_eq!(super::guess_format(&dng[..9]).unwrap(), super::ImageFormat::TIFF);
+        assert!(super::guess_format(b"WEBP").is_err());
+    }
+
+    #[test]
+    fn test_format_names() {
+        use image::ImageFormat::*;
+
+        let formats = [PNG, JPEG, GIF, WEBP, PNM, TIFF, TGA, BMP, ICO, HDR, EXR, QOI, Farbfeld, DDS,
+                       AVIF, HEIF, SGI, XBM, XPM, DNG];
+        for &format in &formats {
+            assert_eq!(super::ImageFormat::from_mime_type(format.to_mime_type()), Some(format));
+        }
+        assert_eq!(super::ImageFormat::from_mime_type("Image/JPEG; q=0.9"), Some(JPEG));
+        assert_eq!(super::ImageFormat::from_mime_type("image/x-portable-graymap"), Some(PNM));
+        assert_eq!(super::ImageFormat::from_mime_type("text/plain"), None);
+
+        assert_eq!(super::ImageFormat::from_extension("JPG"), Some(JPEG));
+        assert_eq!(super::ImageFormat::from_extension("heic"), Some(HEIF));
+        assert_eq!(super::ImageFormat::from_extension("EXR"), Some(EXR));
+        assert_eq!(super::ImageFormat::from_extension("txt"), None);
+        assert_eq!(super::ImageFormat::from_extension(""), None);
+    }
+
+    #[test]
+    fn test_guess_file_type_box_format() {
+        assert_eq!(super::guess_format(b"\0\0\0\x1cftypavif").unwrap(), super::ImageFormat::AVIF);
+        assert_eq!(super::guess_format(b"\0\0\0\x18ftypheic").unwrap(), super::ImageFormat::HEIF);
+        assert_eq!(super::guess_format(b"\0\0\0\x18ftypmif1").unwrap(), super::ImageFormat::HEIF);
+        assert!(super::guess_format(b"\0\0\0\x18ftypisom").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_load_pnm_from_memory() {
+        match super::load_from_memory(b"P5 2 1 255 \x00\xff").unwrap() {
+            super::DynamicImage::ImageLuma8(image) => assert_eq!(image.into_raw(), vec![0, 255]),
+            _ => panic!("Expected a grayscale image"),
+        }
+        match super::load_from_memory(b"P1 2 1 0 1").unwrap() {
+            super::DynamicImage::ImageLuma8(image) => assert_eq!(image.into_raw(), vec![255, 0]),
+            _ => panic!("Expected a grayscale image"),
+        }
+        match super::load_from_memory(b"P3 1 1 255 1 2 3").unwrap() {
+            super::DynamicImage::ImageRgb8(image) => assert_eq!(image.into_raw(), vec![1, 2, 3]),
+            _ => panic!("Expected an RGB image"),
+        }
+        let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n\x01\x02\x03\x04";
+        match super::load_from_memory(pam).unwrap() {
+            super::DynamicImage::ImageRgba8(image) => assert_eq!(image.into_raw(), vec![1, 2, 3, 4]),
+            _ => panic!("Expected an RGBA image"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_limits() {
+        use std::io::Cursor;
+        use image::{ImageError, Limits};
+
+        let load = |data: &[u8], limits: &Limits| super::load_with_limits(Cursor::new(data), super::ImageFormat::PNM, limits);
+        let image = b"P5 3 2 255 \x00\x01\x02\x03\x04\x05";
+        assert!(load(image, &Limits { max_width: Some(3), max_height: Some(2), max_bytes: Some(6), max_frames: Some(1) }).is_ok());
+        for &limits in &[Limits { max_width: Some(2), ..Limits::no_limits() },
+                         Limits { max_height: Some(1), ..Limits::no_limits() },
+                         Limits { max_bytes: Some(5), ..Limits::no_limits() }] {
+            match load(image, &limits) {
+                Err(ImageError::Limits(_)) => {},
+                r => panic!("Expected {:?} to be exceeded, got {:?}", limits, r.map(|i| i.dimensions())),
+            }
+        }
+
+        // A header declaring a huge image is rejected before its samples are allocated
+        match super::load_from_memory(b"P5 100000 100000 255 \x00") {
+            Err(ImageError::Limits(_)) => {},
+            r => panic!("Expected the default limits to be exceeded, got {:?}", r.map(|i| i.dimensions())),
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "png_codec", feature = "bmp", feature = "qoi", feature = "gif_codec", feature = "hdr"))]
+    fn test_image_encoder() {
+        use image::ImageEncoder;
+
+        let pixels = [0, 0, 0, 255, 0, 0, 0, 255, 0, 255, 255, 255];
+        fn encode<E: ImageEncoder>(encoder: E, pixels: &[u8]) -> ::ImageResult<()> {
+            encoder.write_image(pixels, 2, 2, ::ColorType::RGB(8))
+        }
+
+        let (mut png, mut bmp, mut qoi, mut gif) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        encode(::png::PNGEncoder::new(&mut png), &pixels).unwrap();
+        encode(::bmp::BMPEncoder::new(&mut bmp), &pixels).unwrap();
+        encode(::qoi::QOIEncoder::new(&mut qoi), &pixels).unwrap();
+        encode(::gif::Encoder::new(&mut gif), &pixels).unwrap();
+        for encoded in &[png, bmp, qoi] {
+            let image = super::load_from_memory(encoded).unwrap();
+            assert_eq!(image.to_rgb().into_raw(), pixels.to_vec());
+        }
+        // Gif colors are quantized
+        assert_eq!(super::load_from_memory(&gif).unwrap().dimensions(), (2, 2));
+
+        assert!(encode(::gif::Encoder::new(Vec::new()), &pixels[..6]).is_err());
+        match encode(::hdr::HDREncoder::new(Vec::new()), &pixels) {
+            Err(::ImageError::Unsupported { .. }) => (),
+            _ => panic!("Expected an unsupported color error"),
+        }
+    }
 }
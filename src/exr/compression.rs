@@ -0,0 +1,367 @@
+//! The compression methods of the pixel data of OpenEXR images
+//!
+//! Each function decompresses a single block of scanlines and returns its bytes in the layout
+//! of uncompressed data: line after line, with the samples of one channel after the other
+//! in every line.
+
+extern crate inflate;
+
+use image::ImageResult;
+
+use super::{malformed, truncated};
+
+/// The size of the bitmap of the 16-bit values that occur in PIZ compressed data
+const BITMAP_SIZE: usize = 8192;
+
+/// The number of symbols in the huffman code of PIZ compressed data
+const HUF_ENCSIZE: usize = (1 << 16) + 1;
+
+/// Code lengths in the packed huffman table that stand for runs of unused symbols
+const SHORT_ZEROCODE_RUN: u64 = 59;
+const LONG_ZEROCODE_RUN: u64 = 63;
+const SHORTEST_LONG_RUN: u64 = 2 + LONG_ZEROCODE_RUN - SHORT_ZEROCODE_RUN;
+
+/// The longest code of the huffman code of PIZ compressed data
+const MAX_CODE_LENGTH: usize = 58;
+
+/// Decompresses run length encoded data of ```expected``` bytes
+pub fn rle(data: &[u8], expected: usize) -> ImageResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+    while i < data.len() {
+        let count = data[i] as i8;
+        i += 1;
+        if count < 0 {
+            let n = -(count as isize) as usize;
+            if i + n > data.len() {
+                return Err(truncated())
+            }
+            out.extend_from_slice(&data[i..i + n]);
+            i += n;
+        } else {
+            if i >= data.len() {
+                return Err(truncated())
+            }
+            let n = count as usize + 1;
+            for _ in 0..n {
+                out.push(data[i]);
+            }
+            i += 1;
+        }
+        if out.len() > expected {
+            return Err(malformed("Too much RLE compressed EXR data".to_string()))
+        }
+    }
+    reconstruct(out, expected)
+}
+
+/// Decompresses zlib compressed data of ```expected``` bytes
+pub fn zip(data: &[u8], expected: usize) -> ImageResult<Vec<u8>> {
+    let out = try!(inflate::inflate_bytes_zlib(data).map_err(malformed));
+    reconstruct(out, expected)
+}
+
+/// Undoes the delta encoding and byte reordering that RLE and ZIP compression apply
+/// before compressing
+fn reconstruct(mut data: Vec<u8>, expected: usize) -> ImageResult<Vec<u8>> {
+    if data.len() != expected {
+        return Err(malformed("Wrong size of decompressed EXR data".to_string()))
+    }
+
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+
+    // The first half of the bytes holds the even positions, the second half the odd ones
+    let half = (data.len() + 1) / 2;
+    let mut out = Vec::with_capacity(data.len());
+    for i in 0..half {
+        out.push(data[i]);
+        if half + i < data.len() {
+            out.push(data[half + i]);
+        }
+    }
+    Ok(out)
+}
+
+/// Decompresses PIZ compressed data of ```lines``` lines of ```width``` pixels,
+/// where ```sizes``` holds the number of 16-bit words of a sample of each channel
+pub fn piz(data: &[u8], width: usize, lines: usize, sizes: &[usize]) -> ImageResult<Vec<u8>> {
+    let total = width * lines * sizes.iter().sum::<usize>();
+    if data.len() < 4 {
+        return Err(truncated())
+    }
+
+    // The bitmap of the values that occur in the data
+    let min_non_zero = (data[0] as usize) | (data[1] as usize) << 8;
+    let max_non_zero = (data[2] as usize) | (data[3] as usize) << 8;
+    if max_non_zero >= BITMAP_SIZE {
+        return Err(malformed("Invalid PIZ bitmap size".to_string()))
+    }
+    let mut bitmap = [0u8; BITMAP_SIZE];
+    let mut pos = 4;
+    if min_non_zero <= max_non_zero {
+        let n = max_non_zero - min_non_zero + 1;
+        if pos + n > data.len() {
+            return Err(truncated())
+        }
+        bitmap[min_non_zero..max_non_zero + 1].copy_from_slice(&data[pos..pos + n]);
+        pos += n;
+    }
+
+    // The data holds indices into the sorted list of values that occur
+    let mut lut = vec![0u16; 1 << 16];
+    let mut k = 0;
+    for i in 0..1 << 16 {
+        if i == 0 || bitmap[i >> 3] & (1 << (i & 7)) != 0 {
+            lut[k] = i as u16;
+            k += 1;
+        }
+    }
+    let max_value = (k - 1) as u16;
+
+    if pos + 4 > data.len() {
+        return Err(truncated())
+    }
+    let length = (data[pos] as usize) | (data[pos + 1] as usize) << 8
+        | (data[pos + 2] as usize) << 16 | (data[pos + 3] as usize) << 24;
+    pos += 4;
+    if length > data.len() - pos {
+        return Err(truncated())
+    }
+
+    let mut words = vec![0u16; total];
+    try!(huffman_decode(&data[pos..pos + length], &mut words));
+
+    // Every channel is stored as a separate wavelet transformed plane
+    let mut start = 0;
+    for &size in sizes {
+        let n = width * lines * size;
+        for j in 0..size {
+            wavelet_decode(&mut words[start..start + n], j, width, size, lines, width * size, max_value);
+        }
+        start += n;
+    }
+
+    for word in &mut words {
+        *word = lut[*word as usize];
+    }
+
+    // Interleave the channel planes line by line
+    let mut starts = Vec::with_capacity(sizes.len());
+    let mut start = 0;
+    for &size in sizes {
+        starts.push(start);
+        start += width * lines * size;
+    }
+    let mut out = Vec::with_capacity(total * 2);
+    for _ in 0..lines {
+        for (start, &size) in starts.iter_mut().zip(sizes) {
+            let n = width * size;
+            for &word in &words[*start..*start + n] {
+                out.push(word as u8);
+                out.push((word >> 8) as u8);
+            }
+            *start += n;
+        }
+    }
+    Ok(out)
+}
+
+/// Reads the bits of a byte slice starting with the most significant bit
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn read(&mut self, n: usize) -> ImageResult<u64> {
+        if self.pos + n > self.data.len() * 8 {
+            return Err(truncated())
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let bit = (self.data[self.pos / 8] >> (7 - self.pos % 8)) & 1;
+            value = value << 1 | bit as u64;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+}
+
+/// Decodes the huffman coded words of PIZ compressed data into ```out```, which must be filled
+fn huffman_decode(data: &[u8], out: &mut [u16]) -> ImageResult<()> {
+    if data.len() < 20 {
+        return Err(truncated())
+    }
+    let read_u32 = |i: usize| {
+        (data[i] as usize) | (data[i + 1] as usize) << 8
+            | (data[i + 2] as usize) << 16 | (data[i + 3] as usize) << 24
+    };
+    let min_symbol = read_u32(0);
+    let max_symbol = read_u32(4);
+    let bit_count = read_u32(12);
+    if min_symbol >= HUF_ENCSIZE || max_symbol >= HUF_ENCSIZE || min_symbol > max_symbol {
+        return Err(malformed("Invalid huffman table size".to_string()))
+    }
+
+    // The code lengths, where runs of unused symbols are stored as a single length
+    let mut lengths = vec![0u8; HUF_ENCSIZE];
+    let mut table = BitReader { data: &data[20..], pos: 0 };
+    let mut symbol = min_symbol;
+    while symbol <= max_symbol {
+        let length = try!(table.read(6));
+        let run = if length == LONG_ZEROCODE_RUN {
+            try!(table.read(8)) + SHORTEST_LONG_RUN
+        } else if length >= SHORT_ZEROCODE_RUN {
+            length - SHORT_ZEROCODE_RUN + 2
+        } else {
+            lengths[symbol] = length as u8;
+            symbol += 1;
+            continue
+        };
+        symbol += run as usize;
+        if symbol > max_symbol + 1 {
+            return Err(malformed("Huffman table too long".to_string()))
+        }
+    }
+    let data = &data[20 + (table.pos + 7) / 8..];
+    if bit_count > data.len() * 8 {
+        return Err(truncated())
+    }
+
+    // The canonical code assigns consecutive codes to the symbols of each length,
+    // with the longest codes starting at zero
+    let mut symbols = vec![Vec::new(); MAX_CODE_LENGTH + 1];
+    for (symbol, &length) in lengths.iter().enumerate() {
+        if length > 0 {
+            symbols[length as usize].push(symbol);
+        }
+    }
+    let mut first = [0u64; MAX_CODE_LENGTH + 1];
+    let mut code = 0;
+    for length in (1..MAX_CODE_LENGTH + 1).rev() {
+        first[length] = code;
+        code = (code + symbols[length].len() as u64) >> 1;
+    }
+
+    // The largest symbol, which may be one past the largest word, is followed by the number
+    // of times the previous word repeats
+    let mut bits = BitReader { data: &data[..(bit_count + 7) / 8], pos: 0 };
+    let mut pos = 0;
+    while bits.pos < bit_count {
+        let mut code = 0;
+        let mut length = 0;
+        let symbol = loop {
+            code = code << 1 | try!(bits.read(1));
+            length += 1;
+            if length > MAX_CODE_LENGTH {
+                return Err(malformed("Invalid huffman code".to_string()))
+            }
+            if code >= first[length] && code - first[length] < symbols[length].len() as u64 {
+                break symbols[length][(code - first[length]) as usize]
+            }
+        };
+
+        if symbol == max_symbol {
+            let count = try!(bits.read(8)) as usize;
+            if pos == 0 || pos + count > out.len() {
+                return Err(malformed("Invalid huffman run".to_string()))
+            }
+            let previous = out[pos - 1];
+            for word in &mut out[pos..pos + count] {
+                *word = previous;
+            }
+            pos += count;
+        } else {
+            if pos >= out.len() {
+                return Err(malformed("Too much huffman coded data".to_string()))
+            }
+            out[pos] = symbol as u16;
+            pos += 1;
+        }
+    }
+
+    if pos != out.len() {
+        return Err(truncated())
+    }
+    Ok(())
+}
+
+/// Inverts the 14-bit haar wavelet step of low value ```l``` and high value ```h```
+fn wdec14(l: u16, h: u16) -> (u16, u16) {
+    let ls = l as i16 as i32;
+    let hs = h as i16 as i32;
+    let a = ls + (hs & 1) + (hs >> 1);
+    let b = a - hs;
+    (a as i16 as u16, b as i16 as u16)
+}
+
+/// Inverts the 16-bit haar wavelet step of low value ```l``` and high value ```h```
+fn wdec16(l: u16, h: u16) -> (u16, u16) {
+    let m = l as i32;
+    let d = h as i32;
+    let b = (m - (d >> 1)) & 0xffff;
+    let a = (d + b - 0x8000) & 0xffff;
+    (a as u16, b as u16)
+}
+
+/// Inverts the two dimensional wavelet transform of the ```nx``` by ```ny``` values of ```buf```
+/// that start at ```start``` and are ```ox``` apart horizontally and ```oy``` apart vertically
+fn wavelet_decode(buf: &mut [u16], start: usize, nx: usize, ox: usize, ny: usize, oy: usize, max_value: u16) {
+    let dec: fn(u16, u16) -> (u16, u16) = if max_value < 1 << 14 { wdec14 } else { wdec16 };
+    let n = nx.min(ny);
+    let mut p = 1;
+    while p <= n {
+        p <<= 1;
+    }
+    p >>= 1;
+    let mut p2 = p;
+    p >>= 1;
+
+    // From the coarsest level to the finest one
+    while p >= 1 {
+        let (ox1, ox2, oy1, oy2) = (ox * p, ox * p2, oy * p, oy * p2);
+        let ey = start + oy * (ny - p2);
+        let mut py = start;
+        while py <= ey {
+            let ex = py + ox * (nx - p2);
+            let mut px = py;
+            while px <= ex {
+                let (p01, p10) = (px + ox1, px + oy1);
+                let p11 = p10 + ox1;
+                let (i00, i10) = dec(buf[px], buf[p10]);
+                let (i01, i11) = dec(buf[p01], buf[p11]);
+                let (a, b) = dec(i00, i01);
+                buf[px] = a;
+                buf[p01] = b;
+                let (a, b) = dec(i10, i11);
+                buf[p10] = a;
+                buf[p11] = b;
+                px += ox2;
+            }
+            // An odd column
+            if nx & p != 0 {
+                let p10 = px + oy1;
+                let (a, b) = dec(buf[px], buf[p10]);
+                buf[px] = a;
+                buf[p10] = b;
+            }
+            py += oy2;
+        }
+        // An odd line
+        if ny & p != 0 {
+            let ex = py + ox * (nx - p2);
+            let mut px = py;
+            while px <= ex {
+                let p01 = px + ox1;
+                let (a, b) = dec(buf[px], buf[p01]);
+                buf[px] = a;
+                buf[p01] = b;
+                px += ox2;
+            }
+        }
+        p2 = p;
+        p >>= 1;
+    }
+}
@@ -0,0 +1,658 @@
+use std::io::{Read, Seek, SeekFrom};
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt, LittleEndian};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageResult, ParameterErrorKind};
+
+use super::{compression, malformed, truncated, unsupported};
+
+/// The magic number at the start of every OpenEXR file
+const MAGIC: u32 = 20000630;
+
+/// Version flags of the features that are not supported
+const TILED_FLAG: u32 = 0x200;
+const NON_IMAGE_FLAG: u32 = 0x800;
+const MULTIPART_FLAG: u32 = 0x1000;
+
+/// Version flag of names of up to 255 instead of 31 characters
+const LONG_NAMES_FLAG: u32 = 0x400;
+
+/// The largest attribute value that is read
+const MAX_ATTRIBUTE_SIZE: u32 = 1 << 24;
+
+/// The type of the samples of a channel
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn from_i32(value: i32) -> Option<PixelType> {
+        match value {
+            0 => Some(PixelType::Uint),
+            1 => Some(PixelType::Half),
+            2 => Some(PixelType::Float),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes of a sample
+    fn size(&self) -> usize {
+        match *self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+
+    /// Converts the little endian sample at the start of ```bytes```
+    fn read(&self, bytes: &[u8]) -> f32 {
+        match *self {
+            PixelType::Half => half_to_f32(bytes[0] as u16 | (bytes[1] as u16) << 8),
+            PixelType::Uint | PixelType::Float => {
+                let bits = bytes[0] as u32 | (bytes[1] as u32) << 8
+                    | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24;
+                if *self == PixelType::Uint { bits as f32 } else { f32::from_bits(bits) }
+            }
+        }
+    }
+}
+
+/// The compression of the pixel data
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Compression {
+    None,
+    RLE,
+    ZIPS,
+    ZIP,
+    PIZ,
+}
+
+impl Compression {
+    /// The number of scanlines that are compressed together
+    fn lines_per_block(&self) -> usize {
+        match *self {
+            Compression::None | Compression::RLE | Compression::ZIPS => 1,
+            Compression::ZIP => 16,
+            Compression::PIZ => 32,
+        }
+    }
+}
+
+/// A channel of the image
+#[derive(Clone, Debug)]
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+/// The attributes of the image that are needed for decoding
+#[derive(Clone, Debug)]
+struct Header {
+    channels: Vec<Channel>,
+    compression: Compression,
+    /// The coordinates of the top left and bottom right pixels
+    data_window: (i32, i32, i32, i32),
+}
+
+/// The representation of an OpenEXR decoder
+///
+/// Single part scanline images are supported, with uncompressed, RLE, ZIP or PIZ compressed
+/// data. Images with R, G and B channels decode as ```RGB``` and grayscale images with a Y
+/// channel as ```Gray```, both optionally with an A channel, and other images with a single
+/// channel as ```Gray```. All samples are converted to ```f32```.
+///
+/// The blocks of an image may be stored in any order, so reading the first scanline decodes the
+/// whole image, and the following scanlines are copied from it.
+pub struct EXRDecoder<R> {
+    r: R,
+    header: Option<Header>,
+    /// The header channel of each output channel
+    layout: Vec<usize>,
+    color: ColorType,
+    /// The samples of the image, once a scanline has been read
+    samples: Option<Vec<f32>>,
+    /// The next scanline to read
+    row: u32,
+}
+
+impl<R: Read + Seek> EXRDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> EXRDecoder<R> {
+        EXRDecoder {
+            r: r,
+            header: None,
+            layout: Vec::new(),
+            color: ColorType::GrayF(32),
+            samples: None,
+            row: 0,
+        }
+    }
+
+    /// Returns the names of all channels of the image, including those that are not decoded
+    pub fn channel_names(&mut self) -> ImageResult<Vec<String>> {
+        try!(self.read_metadata());
+        Ok(self.header.as_ref().unwrap().channels.iter().map(|c| c.name.clone()).collect())
+    }
+
+    fn read_metadata(&mut self) -> ImageResult<()> {
+        if self.header.is_some() {
+            return Ok(())
+        }
+
+        try!(self.r.seek(SeekFrom::Start(0)));
+        if try!(self.r.read_u32::<LittleEndian>()) != MAGIC {
+            return Err(malformed("Not an OpenEXR image".to_string()))
+        }
+        let version = try!(self.r.read_u32::<LittleEndian>());
+        if version & 0xff != 2 {
+            return Err(unsupported(format!("OpenEXR version {}", version & 0xff)))
+        }
+        if version & TILED_FLAG != 0 {
+            return Err(unsupported("Tiled OpenEXR images".to_string()))
+        }
+        if version & (NON_IMAGE_FLAG | MULTIPART_FLAG) != 0 {
+            return Err(unsupported("Deep or multipart OpenEXR images".to_string()))
+        }
+        let max_name = if version & LONG_NAMES_FLAG != 0 { 255 } else { 31 };
+
+        let mut channels = None;
+        let mut compression = None;
+        let mut data_window = None;
+        loop {
+            let name = try!(read_name(&mut self.r, max_name));
+            if name.is_empty() {
+                break
+            }
+            let kind = try!(read_name(&mut self.r, max_name));
+            let size = try!(self.r.read_u32::<LittleEndian>());
+            if size > MAX_ATTRIBUTE_SIZE {
+                return Err(malformed(format!("Attribute {} is too large", name)))
+            }
+            let mut value = vec![0; size as usize];
+            try!(self.r.read_exact(&mut value));
+
+            match (&*name, &*kind) {
+                ("channels", "chlist") => channels = Some(try!(parse_channels(&value))),
+                ("compression", "compression") => {
+                    compression = Some(match value.first() {
+                        Some(&0) => Compression::None,
+                        Some(&1) => Compression::RLE,
+                        Some(&2) => Compression::ZIPS,
+                        Some(&3) => Compression::ZIP,
+                        Some(&4) => Compression::PIZ,
+                        Some(&c) => return Err(unsupported(
+                            format!("OpenEXR compression {}", c))),
+                        None => return Err(malformed("Empty compression attribute".to_string())),
+                    })
+                }
+                ("dataWindow", "box2i") => {
+                    let mut r = &value[..];
+                    data_window = Some((try!(r.read_i32::<LittleEndian>()), try!(r.read_i32::<LittleEndian>()),
+                                        try!(r.read_i32::<LittleEndian>()), try!(r.read_i32::<LittleEndian>())));
+                }
+                _ => (),
+            }
+        }
+
+        let header = match (channels, compression, data_window) {
+            (Some(channels), Some(compression), Some(data_window)) => Header {
+                channels: channels,
+                compression: compression,
+                data_window: data_window,
+            },
+            _ => return Err(malformed("Missing required OpenEXR attribute".to_string())),
+        };
+        let (x_min, y_min, x_max, y_max) = header.data_window;
+        if x_max < x_min || y_max < y_min
+            || x_max as i64 - x_min as i64 >= 1 << 30 || y_max as i64 - y_min as i64 >= 1 << 30 {
+            return Err(malformed("Invalid image dimensions".to_string()))
+        }
+
+        let find = |name: &str| header.channels.iter().position(|c| c.name == name);
+        let alpha = find("A");
+        let (layout, color) = match (find("R"), find("G"), find("B"), find("Y"), alpha) {
+            (Some(r), Some(g), Some(b), _, Some(a)) => (vec![r, g, b, a], ColorType::RGBAF(32)),
+            (Some(r), Some(g), Some(b), _, None) => (vec![r, g, b], ColorType::RGBF(32)),
+            (_, _, _, Some(y), Some(a)) => (vec![y, a], ColorType::GrayAF(32)),
+            (_, _, _, Some(y), None) => (vec![y], ColorType::GrayF(32)),
+            _ if header.channels.len() == 1 => (vec![0], ColorType::GrayF(32)),
+            _ => return Err(unsupported(
+                "OpenEXR images without RGB or Y channels".to_string())),
+        };
+
+        self.layout = layout;
+        self.color = color;
+        self.header = Some(header);
+        Ok(())
+    }
+}
+
+/// Reads a null terminated name of at most ```max``` characters
+fn read_name<R: Read>(r: &mut R, max: usize) -> ImageResult<String> {
+    let mut name = Vec::new();
+    loop {
+        match try!(r.read_u8()) {
+            0 => break,
+            c => name.push(c),
+        }
+        if name.len() > max {
+            return Err(malformed("OpenEXR attribute name is too long".to_string()))
+        }
+    }
+    String::from_utf8(name).map_err(|_| malformed("Invalid OpenEXR attribute name".to_string()))
+}
+
+/// Parses the value of a ```chlist``` attribute
+fn parse_channels(mut value: &[u8]) -> ImageResult<Vec<Channel>> {
+    let mut channels = Vec::new();
+    loop {
+        let name = try!(read_name(&mut value, 255));
+        if name.is_empty() {
+            break
+        }
+        let pixel_type = try!(value.read_i32::<LittleEndian>());
+        // Linearity hint and reserved bytes
+        let mut skipped = [0; 4];
+        try!(value.read_exact(&mut skipped));
+        let x_sampling = try!(value.read_i32::<LittleEndian>());
+        let y_sampling = try!(value.read_i32::<LittleEndian>());
+        if x_sampling != 1 || y_sampling != 1 {
+            return Err(unsupported("Subsampled OpenEXR channels".to_string()))
+        }
+        let pixel_type = match PixelType::from_i32(pixel_type) {
+            Some(t) => t,
+            None => return Err(malformed(format!("Invalid pixel type {}", pixel_type))),
+        };
+        channels.push(Channel {
+            name: name,
+            pixel_type: pixel_type,
+        });
+    }
+    if channels.is_empty() {
+        return Err(malformed("OpenEXR image without channels".to_string()))
+    }
+    Ok(channels)
+}
+
+/// Converts a half precision float to single precision
+fn half_to_f32(half: u16) -> f32 {
+    let sign = (half as u32 & 0x8000) << 16;
+    let exponent = (half as u32 >> 10) & 0x1f;
+    let mantissa = half as u32 & 0x3ff;
+    let bits = match exponent {
+        0 if mantissa == 0 => sign,
+        0 => {
+            // Subnormal halves are normal single precision floats
+            let mut exponent = 127 - 15 + 1;
+            let mut mantissa = mantissa;
+            while mantissa & 0x400 == 0 {
+                mantissa <<= 1;
+                exponent -= 1;
+            }
+            sign | exponent << 23 | (mantissa & 0x3ff) << 13
+        }
+        0x1f => sign | 0x7f80_0000 | mantissa << 13,
+        _ => sign | (exponent + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+impl<R: Read + Seek> ImageDecoder for EXRDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        try!(self.read_metadata());
+        let (x_min, y_min, x_max, y_max) = self.header.as_ref().unwrap().data_window;
+        Ok(((x_max - x_min) as u32 + 1, (y_max - y_min) as u32 + 1))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        try!(self.read_metadata());
+        Ok(self.color)
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let (width, _) = try!(self.dimensions());
+        Ok(width as usize * self.layout.len() * 4)
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let (_, height) = try!(self.dimensions());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+        if self.samples.is_none() {
+            self.samples = Some(try!(self.decode()));
+        }
+
+        let samples = self.samples.as_ref().unwrap();
+        let row = &samples[self.row as usize * len / 4..(self.row as usize + 1) * len / 4];
+        BigEndian::write_f32_into(row, &mut buf[..len]);
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let (_, height) = try!(self.dimensions());
+        let start = self.row as usize * try!(self.row_len()) / 4;
+        let mut samples = match self.samples.take() {
+            Some(samples) => samples,
+            None => try!(self.decode()),
+        };
+        // The rows that were read as scanlines already are left out
+        self.row = height;
+        Ok(DecodingResult::F32(if start == 0 { samples } else { samples.split_off(start) }))
+    }
+}
+
+impl<R: Read + Seek> EXRDecoder<R> {
+    /// Decodes the samples of the whole image
+    fn decode(&mut self) -> ImageResult<Vec<f32>> {
+        let (width, height) = try!(self.dimensions());
+        let header = self.header.clone().unwrap();
+        let (width, height) = (width as usize, height as usize);
+        let lines_per_block = header.compression.lines_per_block();
+        let samples = self.layout.len();
+        let mut out = vec![0f32; try!(width.checked_mul(height)
+            .and_then(|n| n.checked_mul(samples))
+            .ok_or(malformed("Invalid image dimensions".to_string())))];
+
+        // The output channel of each header channel, if it is decoded
+        let targets: Vec<Option<usize>> = (0..header.channels.len())
+            .map(|c| self.layout.iter().position(|&l| l == c))
+            .collect();
+        let line_size: usize = header.channels.iter().map(|c| c.pixel_type.size() * width).sum();
+
+        // The offset table follows the header
+        let blocks = (height + lines_per_block - 1) / lines_per_block;
+        let mut offsets = Vec::with_capacity(blocks);
+        for _ in 0..blocks {
+            offsets.push(try!(self.r.read_u64::<LittleEndian>()));
+        }
+
+        for offset in offsets {
+            try!(self.r.seek(SeekFrom::Start(offset)));
+            let y = try!(self.r.read_i32::<LittleEndian>()) as i64 - header.data_window.1 as i64;
+            if y < 0 || y as usize >= height || y as usize % lines_per_block != 0 {
+                return Err(malformed(format!("Invalid block position {}", y)))
+            }
+            let y = y as usize;
+            let lines = lines_per_block.min(height - y);
+            let expected = line_size * lines;
+
+            let size = try!(self.r.read_i32::<LittleEndian>());
+            if size < 0 || size as usize > expected {
+                return Err(malformed(format!("Invalid block size {}", size)))
+            }
+            let mut data = vec![0; size as usize];
+            try!(self.r.read_exact(&mut data));
+
+            // Blocks that would not get smaller are stored uncompressed
+            let data = if data.len() == expected {
+                data
+            } else {
+                match header.compression {
+                    Compression::None => data,
+                    Compression::RLE => try!(compression::rle(&data, expected)),
+                    Compression::ZIPS | Compression::ZIP => try!(compression::zip(&data, expected)),
+                    Compression::PIZ => {
+                        let sizes: Vec<usize> = header.channels.iter().map(|c| c.pixel_type.size() / 2).collect();
+                        try!(compression::piz(&data, width, lines, &sizes))
+                    }
+                }
+            };
+            if data.len() < expected {
+                return Err(truncated())
+            }
+
+            // Every line holds the samples of one channel after the other
+            let mut pos = 0;
+            for line in y..y + lines {
+                for (channel, target) in header.channels.iter().zip(&targets) {
+                    let size = channel.pixel_type.size();
+                    if let Some(target) = *target {
+                        let row = &mut out[line * width * samples..(line + 1) * width * samples];
+                        for (x, bytes) in data[pos..pos + width * size].chunks(size).enumerate() {
+                            row[x * samples + target] = channel.pixel_type.read(bytes);
+                        }
+                    }
+                    pos += width * size;
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{EXRDecoder, half_to_f32};
+    use color::ColorType;
+    use dynimage::DynamicImage;
+    use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ParameterErrorKind};
+
+    /// Builds a file of ```height``` lines starting at ```y_min``` from blocks of stored data
+    fn exr(channels: &[(&str, u8)], compression: u8, width: i32, y_min: i32, height: i32,
+           blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+        let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+            file.extend_from_slice(name.as_bytes());
+            file.push(0);
+            file.extend_from_slice(kind.as_bytes());
+            file.push(0);
+            file.extend_from_slice(&[value.len() as u8, 0, 0, 0]);
+            file.extend_from_slice(value);
+        };
+        let mut list = Vec::new();
+        for &(name, pixel_type) in channels {
+            list.extend_from_slice(name.as_bytes());
+            list.extend_from_slice(&[0, pixel_type, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        }
+        list.push(0);
+        attribute("channels", "chlist", &list);
+        attribute("compression", "compression", &[compression]);
+        let mut window = Vec::new();
+        for &v in &[0, y_min, width - 1, y_min + height - 1] {
+            window.extend_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+        }
+        attribute("dataWindow", "box2i", &window);
+        attribute("lineOrder", "lineOrder", &[0]);
+        file.push(0);
+
+        let lines_per_block = match compression { 3 => 16, 4 => 32, _ => 1 };
+        let mut offset = file.len() + 8 * blocks.len();
+        for block in blocks {
+            file.extend_from_slice(&[offset as u8, (offset >> 8) as u8, 0, 0, 0, 0, 0, 0]);
+            offset += 8 + block.len();
+        }
+        for (i, block) in blocks.iter().enumerate() {
+            let y = y_min + (i * lines_per_block) as i32;
+            file.extend_from_slice(&[y as u8, (y >> 8) as u8, (y >> 16) as u8, (y >> 24) as u8]);
+            file.extend_from_slice(&[block.len() as u8, (block.len() >> 8) as u8, 0, 0]);
+            file.extend_from_slice(block);
+        }
+        file
+    }
+
+    fn decode(file: Vec<u8>) -> (ColorType, u32, u32, Vec<f32>) {
+        let mut decoder = EXRDecoder::new(Cursor::new(file));
+        let color = decoder.colortype().unwrap();
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::F32(data) => (color, width, height, data),
+            _ => panic!("Image did not decode as f32"),
+        }
+    }
+
+    /// Applies the byte reordering and delta encoding that precede RLE and ZIP compression
+    fn predict(raw: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = raw.iter().step_by(2).chain(raw.iter().skip(1).step_by(2)).cloned().collect();
+        for i in (1..data.len()).rev() {
+            data[i] = data[i].wrapping_sub(data[i - 1]).wrapping_add(128);
+        }
+        data
+    }
+
+    #[test]
+    fn scanlines() {
+        let file = exr(&[("Y", 1)], 0, 2, 0, 3, &[
+            vec![0x00, 0x3c, 0x00, 0x40], vec![0x00, 0x42, 0x00, 0x44], vec![0x00, 0x45, 0x00, 0x46],
+        ]);
+        let mut decoder = EXRDecoder::new(Cursor::new(file));
+        let mut row = vec![0; decoder.row_len().unwrap()];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, vec![0x3f, 0x80, 0, 0, 0x40, 0, 0, 0]);
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 1);
+        assert_eq!(row, vec![0x40, 0x40, 0, 0, 0x40, 0x80, 0, 0]);
+        match decoder.read_scanline(&mut row[..7]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => (),
+            r => panic!("Expected the buffer to be too short, got {:?}", r),
+        }
+        // The image holds the rows that have not been read yet
+        match decoder.read_image().unwrap() {
+            DecodingResult::F32(data) => assert_eq!(data, vec![5.0, 6.0]),
+            _ => panic!("Expected f32 samples"),
+        }
+        match decoder.read_scanline(&mut row) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => (),
+            r => panic!("Expected no more rows, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn load_from_memory() {
+        let file = exr(&[("Y", 1)], 0, 2, 0, 1, &[vec![0x00, 0x3c, 0x00, 0x40]]);
+        assert_eq!(::guess_format(&file).unwrap(), ImageFormat::EXR);
+        match ::load_from_memory(&file).unwrap() {
+            DynamicImage::ImageRgb32F(image) => {
+                assert_eq!(image.into_raw(), vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0])
+            }
+            _ => panic!("Expected an f32 image"),
+        }
+        assert!(::load_from_memory(&file[..40]).is_err());
+    }
+
+    #[test]
+    fn half_conversion() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 0.333251953125);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x03ff), 1023.0 * 2f32.powi(-24));
+        assert_eq!(half_to_f32(0xfc00), ::std::f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn channel_types() {
+        // Channels are sorted by name, and unknown ones are skipped
+        let channels = [("A", 1), ("B", 2), ("G", 0), ("R", 1), ("Z", 2)];
+        let lines = vec![
+            vec![0x00, 0x3c, 0x00, 0x38, 0, 0, 0x80, 0x3f, 0, 0, 0, 0, 7, 0, 0, 0, 0xff, 0, 0, 0,
+                 0x00, 0xc0, 0x00, 0x7c, 1, 2, 3, 4, 5, 6, 7, 8],
+            vec![0x00, 0x00, 0x00, 0x3c, 0, 0, 0x20, 0x41, 0, 0, 0x80, 0xbf, 0, 0, 1, 0, 3, 0, 0, 0,
+                 0x01, 0x00, 0x00, 0x3c, 1, 2, 3, 4, 5, 6, 7, 8],
+        ];
+        let file = exr(&channels, 0, 2, -5, 2, &lines);
+
+        let mut decoder = EXRDecoder::new(Cursor::new(file.clone()));
+        assert_eq!(decoder.channel_names().unwrap(), vec!["A", "B", "G", "R", "Z"]);
+        let (color, width, height, data) = decode(file);
+        assert_eq!((color, width, height), (ColorType::RGBAF(32), 2, 2));
+        assert_eq!(data, vec![
+            -2.0, 7.0, 1.0, 1.0, ::std::f32::INFINITY, 255.0, 0.0, 0.5,
+            2f32.powi(-24), 65536.0, 10.0, 0.0, 1.0, 3.0, -1.0, 1.0,
+        ]);
+
+        let gray = exr(&[("Y", 1)], 0, 3, 0, 1, &[vec![0x00, 0x3c, 0x00, 0x40, 0x00, 0x42]]);
+        assert_eq!(decode(gray), (ColorType::GrayF(32), 3, 1, vec![1.0, 2.0, 3.0]));
+        let depth = exr(&[("depth", 2)], 0, 1, 0, 1, &[vec![0, 0, 0x80, 0x3f]]);
+        assert_eq!(decode(depth), (ColorType::GrayF(32), 1, 1, vec![1.0]));
+        let unknown = exr(&[("U", 1), ("V", 1)], 0, 1, 0, 1, &[vec![0; 4]]);
+        match EXRDecoder::new(Cursor::new(unknown)).colortype() {
+            Err(ImageError::Unsupported { .. }) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rle_and_zip() {
+        // An alpha channel and a Y channel alternating between 2 and 4
+        let channels = [("A", 1), ("Y", 2)];
+        let raw: Vec<Vec<u8>> = (0..20).map(|y| {
+            let mut line = Vec::new();
+            for x in 0..4 {
+                line.extend_from_slice(&[0, if x == y % 4 { 0x3c } else { 0 }]);
+            }
+            for x in 0..4 {
+                line.extend_from_slice(&[0, 0, if (x + y) % 3 == 0 { 0x80 } else { 0 }, 0x40]);
+            }
+            line
+        }).collect();
+        let expected: Vec<f32> = (0..20).flat_map(|y| (0..4).flat_map(move |x| {
+            vec![if (x + y) % 3 == 0 { 4.0 } else { 2.0 }, if x == y % 4 { 1.0 } else { 0.0 }]
+        })).collect();
+
+        // Runs of repeated bytes, and the literal bytes between them
+        let rle: Vec<Vec<u8>> = raw.iter().map(|line| {
+            let data = predict(line);
+            let mut block = Vec::new();
+            let mut i = 0;
+            while i < data.len() {
+                let start = i;
+                while i < data.len() && !(i + 1 < data.len() && data[i + 1] == data[i]) {
+                    i += 1;
+                }
+                if i > start {
+                    block.push(-((i - start) as i8) as u8);
+                    block.extend_from_slice(&data[start..i]);
+                } else {
+                    while i < data.len() && data[i] == data[start] {
+                        i += 1;
+                    }
+                    block.extend_from_slice(&[(i - start - 1) as u8, data[start]]);
+                }
+            }
+            block
+        }).collect();
+        assert!(rle.iter().all(|block| block.len() < raw[0].len()));
+        let file = exr(&channels, 1, 4, 0, 20, &rle);
+        assert_eq!(decode(file), (ColorType::GrayAF(32), 4, 20, expected.clone()));
+
+        // A block of 16 lines that is deflated, and one of 4 lines that is stored as it is
+        let zip = vec![
+            vec![0x78, 0xda, 0x63, 0x68, 0x00, 0x02, 0x06, 0x06, 0x04, 0x89, 0x9f, 0x35, 0xe8, 0xd4,
+                 0xd8, 0xb8, 0x00, 0xc9, 0x03, 0x0e, 0x10, 0xb8, 0xc7, 0x05, 0xc1, 0x6e, 0x00, 0x72,
+                 0xe0, 0x6c, 0x20, 0x07, 0xca, 0xae, 0x19, 0x64, 0xea, 0x01, 0x33, 0x39, 0xa9, 0xc1],
+            raw[16..].concat(),
+        ];
+        let file = exr(&channels, 3, 4, 0, 20, &zip);
+        assert_eq!(decode(file), (ColorType::GrayAF(32), 4, 20, expected));
+    }
+
+    #[test]
+    fn piz() {
+        // A uint channel compressed as a single wavelet transformed and huffman coded block
+        let block = vec![
+            0x02, 0x00, 0x0b, 0x00, 0x44, 0x44, 0x00, 0x10, 0x11, 0x11, 0x00, 0x44, 0x44, 0x04,
+            0x25, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x09, 0x00,
+            0x00, 0x00, 0x3d, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x20, 0x05, 0x00,
+            0x40, 0x05, 0x14, 0x01, 0x44, 0x1d, 0x8a, 0xc3, 0x2e, 0x5c, 0xa0, 0xb6, 0xd8,
+        ];
+        let file = exr(&[("Y", 0)], 4, 5, 0, 3, &[block]);
+        assert_eq!(decode(file), (ColorType::GrayF(32), 5, 3, vec![
+            90.0, 86.0, 82.0, 78.0, 74.0,
+            60.0, 56.0, 52.0, 48.0, 44.0,
+            30.0, 26.0, 22.0, 18.0, 18.0,
+        ]));
+    }
+}
@@ -0,0 +1,36 @@
+//!  Decoding of OpenEXR Images
+//!
+//!  A decoder for single part scanline OpenEXR images, which decodes the samples as ```f32```.
+//!  OpenEXR images are loaded by `open` and `load_from_memory` as ```f32``` dynamic images.
+//!
+//!  # Related Links
+//!
+//!  * <http://www.openexr.com/openexrfilelayout.pdf>
+//!
+
+use image::{DecodingErrorKind, ImageError, ImageFormat, ImageFormatHint, UnsupportedFeature};
+
+mod compression;
+mod decoder;
+
+pub use self::decoder::EXRDecoder;
+
+/// The format of errors of OpenEXR images
+fn format() -> ImageFormatHint {
+    ImageFormatHint::Exact(ImageFormat::EXR)
+}
+
+/// The error of an image that does not follow the format, as described by ```message```
+fn malformed(message: String) -> ImageError {
+    ImageError::Decoding { format: format(), kind: DecodingErrorKind::Malformed(message) }
+}
+
+/// The error of an image whose data ends before it is complete
+fn truncated() -> ImageError {
+    ImageError::Decoding { format: format(), kind: DecodingErrorKind::Truncated }
+}
+
+/// The error of an image using a feature described by ```message``` that is not supported
+fn unsupported(message: String) -> ImageError {
+    ImageError::Unsupported { format: format(), feature: UnsupportedFeature::Other(message) }
+}
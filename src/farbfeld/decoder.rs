@@ -0,0 +1,128 @@
+use std::io::Read;
+use byteorder::{ReadBytesExt, BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+use super::MAGIC;
+
+/// The representation of a farbfeld decoder
+///
+/// Rows are read one at a time, so the decoder does not need to seek.
+pub struct FarbfeldDecoder<R> {
+    r: R,
+    dimensions: Option<(u32, u32)>,
+    /// The next row to be read
+    row: u32,
+}
+
+impl<R: Read> FarbfeldDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> FarbfeldDecoder<R> {
+        FarbfeldDecoder {
+            r: r,
+            dimensions: None,
+            row: 0,
+        }
+    }
+
+    fn read_header(&mut self) -> ImageResult<(u32, u32)> {
+        if let Some(dimensions) = self.dimensions {
+            return Ok(dimensions)
+        }
+
+        let mut magic = [0; 8];
+        try!(self.r.read_exact(&mut magic));
+        if magic != MAGIC {
+            return Err(ImageError::malformed(ImageFormat::Farbfeld, "Not a farbfeld image".to_string()))
+        }
+        let width = try!(self.r.read_u32::<BigEndian>());
+        let height = try!(self.r.read_u32::<BigEndian>());
+        let len = (width as usize).checked_mul(height as usize).and_then(|n| n.checked_mul(8));
+        if len.is_none() {
+            return Err(ImageError::malformed(ImageFormat::Farbfeld, "Invalid image dimensions".to_string()))
+        }
+
+        self.dimensions = Some((width, height));
+        Ok((width, height))
+    }
+}
+
+impl<R: Read> ImageDecoder for FarbfeldDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        self.read_header()
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        try!(self.read_header());
+        Ok(ColorType::RGBA(16))
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let (width, _) = try!(self.read_header());
+        Ok(width as usize * 8)
+    }
+
+    /// Reads the next row into ```buf```, with the samples in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let (_, height) = try!(self.read_header());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        try!(self.r.read_exact(&mut buf[..len]));
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let (_, height) = try!(self.read_header());
+        let mut row = vec![0; try!(self.row_len())];
+        // The image grows with the data that is actually there, whatever the header claims
+        let mut data = Vec::new();
+        while self.row < height {
+            try!(self.read_scanline(&mut row));
+            data.extend(row.chunks(2).map(BigEndian::read_u16));
+        }
+        Ok(DecodingResult::U16(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::FarbfeldDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError, ParameterErrorKind};
+
+    #[test]
+    fn decode() {
+        let mut data = b"farbfeld\0\0\0\x02\0\0\0\x01".to_vec();
+        data.extend_from_slice(&[0, 1, 0, 2, 0, 3, 0xff, 0xff, 0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0, 0]);
+        let mut decoder = FarbfeldDecoder::new(Cursor::new(data));
+        assert_eq!(decoder.dimensions().unwrap(), (2, 1));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(16));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(image) => assert_eq!(image, vec![1, 2, 3, 65535, 0x1234, 0x5678, 0x9abc, 0]),
+            _ => panic!("Image did not decode as 16-bit"),
+        }
+        match decoder.read_scanline(&mut [0; 16]) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => (),
+            _ => panic!("Expected the end of the image"),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let data = b"farbfelt\0\0\0\x01\0\0\0\x01\0\0\0\0\0\0\0\0".to_vec();
+        assert!(FarbfeldDecoder::new(Cursor::new(data)).dimensions().is_err());
+        // The header claims far more pixels than there are
+        let data = b"farbfeld\xff\xff\xff\xff\xff\xff\xff\xff\0\0\0\0\0\0\0\0".to_vec();
+        assert!(FarbfeldDecoder::new(Cursor::new(data)).read_image().is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{ImageEncoder, ImageResult};
+use math::utils::unit_to_sample;
+
+use super::MAGIC;
+
+/// The representation of a farbfeld encoder
+pub struct FarbfeldEncoder<W> {
+    w: W,
+}
+
+impl<W: Write> FarbfeldEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```
+    pub fn new(w: W) -> FarbfeldEncoder<W> {
+        FarbfeldEncoder {
+            w: w,
+        }
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// Gray, gray with alpha, rgb and rgba images are supported with 8 or 16 bits per sample,
+    /// or as 32-bit floats in the range 0 to 1. 16-bit and float samples are expected in big
+    /// endian order. All images are stored as 16-bit rgba, with opaque alpha if the image has none.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        let (channels, bytes) = match color {
+            ColorType::Gray(8) => (1, 1),
+            ColorType::GrayA(8) => (2, 1),
+            ColorType::RGB(8) => (3, 1),
+            ColorType::RGBA(8) => (4, 1),
+            ColorType::Gray(16) => (1, 2),
+            ColorType::GrayA(16) => (2, 2),
+            ColorType::RGB(16) => (3, 2),
+            ColorType::RGBA(16) => (4, 2),
+            ColorType::GrayF(32) => (1, 4),
+            ColorType::GrayAF(32) => (2, 4),
+            ColorType::RGBF(32) => (3, 4),
+            ColorType::RGBAF(32) => (4, 4),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Unsupported color type {:?}", color))),
+        };
+        let row_len = width as usize * channels * bytes;
+        if data.len() < row_len * height as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+
+        try!(self.w.write_all(MAGIC));
+        try!(self.w.write_u32::<BigEndian>(width));
+        try!(self.w.write_u32::<BigEndian>(height));
+
+        let mut row = Vec::with_capacity(width as usize * 8);
+        let mut samples = Vec::with_capacity(channels);
+        for source in data.chunks(row_len).take(height as usize) {
+            row.clear();
+            for pixel in source.chunks(channels * bytes) {
+                samples.clear();
+                samples.extend(pixel.chunks(bytes).map(|s| match bytes {
+                    1 => s[0] as u16 * 257,
+                    2 => BigEndian::read_u16(s),
+                    _ => unit_to_sample(BigEndian::read_f32(s)),
+                }));
+                let rgba = match channels {
+                    1 => [samples[0], samples[0], samples[0], 0xffff],
+                    2 => [samples[0], samples[0], samples[0], samples[1]],
+                    3 => [samples[0], samples[1], samples[2], 0xffff],
+                    _ => [samples[0], samples[1], samples[2], samples[3]],
+                };
+                for &v in &rgba {
+                    try!(row.write_u16::<BigEndian>(v));
+                }
+            }
+            try!(self.w.write_all(&row));
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for FarbfeldEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use byteorder::{BigEndian, ByteOrder};
+
+    use super::FarbfeldEncoder;
+    use super::super::FarbfeldDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn roundtrip(data: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u16> {
+        let mut encoded = Vec::new();
+        FarbfeldEncoder::new(&mut encoded).encode(data, width, height, color).unwrap();
+        assert_eq!(&encoded[..16], b"farbfeld\0\0\0\x02\0\0\0\x01");
+
+        let mut decoder = FarbfeldDecoder::new(Cursor::new(encoded));
+        assert_eq!(decoder.dimensions().unwrap(), (width, height));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(data) => data,
+            _ => panic!("Image did not decode as 16-bit"),
+        }
+    }
+
+    #[test]
+    fn color_types() {
+        assert_eq!(roundtrip(&[0, 255], 2, 1, ColorType::Gray(8)),
+                   vec![0, 0, 0, 65535, 65535, 65535, 65535, 65535]);
+        assert_eq!(roundtrip(&[1, 2, 3, 4, 5, 6, 7, 8], 2, 1, ColorType::RGBA(8)),
+                   vec![257, 514, 771, 1028, 1285, 1542, 1799, 2056]);
+        assert_eq!(roundtrip(&[0x12, 0x34, 0, 1, 0xff, 0xfe, 0, 0], 2, 1, ColorType::GrayA(16)),
+                   vec![0x1234, 0x1234, 0x1234, 1, 0xfffe, 0xfffe, 0xfffe, 0]);
+
+        let floats = [0.0, 0.5, 1.0, 2.0, -1.0, ::std::f32::NAN];
+        let mut data = [0; 24];
+        for (v, bytes) in floats.iter().zip(data.chunks_mut(4)) {
+            BigEndian::write_f32(bytes, *v);
+        }
+        assert_eq!(roundtrip(&data, 2, 1, ColorType::RGBF(32)),
+                   vec![0, 32768, 65535, 65535, 65535, 0, 0, 65535]);
+    }
+
+    #[test]
+    fn not_enough_data() {
+        let mut encoded = Vec::new();
+        assert!(FarbfeldEncoder::new(&mut encoded).encode(&[0; 5], 2, 1, ColorType::RGB(8)).is_err());
+        assert!(FarbfeldEncoder::new(&mut encoded).encode(&[0; 4], 1, 1, ColorType::RGB(16)).is_err());
+    }
+}
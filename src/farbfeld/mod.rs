@@ -0,0 +1,18 @@
+//! Decoding and Encoding of farbfeld Images
+//!
+//! farbfeld stores 16-bit RGBA pixels in big endian order after an 8 byte magic number and the
+//! dimensions. Both the decoder and the encoder stream the image row by row, so they work well
+//! in pipes.
+//!
+//! # Related Links
+//! * <https://tools.suckless.org/farbfeld/> - The farbfeld specification
+//!
+
+pub use self::decoder::FarbfeldDecoder;
+pub use self::encoder::FarbfeldEncoder;
+
+mod decoder;
+mod encoder;
+
+/// The magic bytes at the start of every farbfeld image
+const MAGIC: &'static [u8] = b"farbfeld";
@@ -0,0 +1,434 @@
+//! Describing the layout of samples in a flat buffer
+//!
+//! Memory shared with other libraries, graphics APIs or video decoders rarely stores its pixels
+//! the way an `ImageBuffer` does. `FlatSamples` pairs such a buffer with a `SampleLayout` giving
+//! the distance between the channels of a pixel, the pixels of a row and the rows of the image,
+//! so that interleaved, planar and column major samples can all be described without copying
+//! them.
+//!
+//! Buffers whose samples are interleaved and whose rows follow each other, possibly padded, are
+//! converted into an `ImageBuffer` in place with `FlatSamples::try_into_buffer`, others are copied
+//! into one with `FlatSamples::to_buffer`. `ImageBuffer::into_flat_samples` goes the other way.
+
+use std::cmp;
+use std::ops::{Deref, DerefMut};
+
+use buffer::{ImageBuffer, Pixel};
+use color::ColorType;
+use image::{ImageError, ImageResult, ParameterErrorKind};
+use traits::Primitive;
+
+/// The distances between the samples of an image in a flat buffer
+///
+/// The sample of channel `c` of the pixel at `(x, y)` is stored at the index
+/// `c * channel_stride + x * width_stride + y * height_stride`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SampleLayout {
+    /// The number of channels of each pixel
+    pub channels: u8,
+    /// The distance between the samples of two consecutive channels of a pixel
+    ///
+    /// This is 1 for interleaved samples, and the size of a plane for planar ones.
+    pub channel_stride: usize,
+    /// The number of pixels of each row
+    pub width: u32,
+    /// The distance between the first samples of two pixels next to each other in a row
+    pub width_stride: usize,
+    /// The number of rows
+    pub height: u32,
+    /// The distance between the first samples of two rows, including their padding
+    pub height_stride: usize,
+}
+
+impl SampleLayout {
+    /// Returns the layout of interleaved samples in rows following each other without padding,
+    /// as in an `ImageBuffer`
+    pub fn row_major_packed(channels: u8, width: u32, height: u32) -> SampleLayout {
+        SampleLayout {
+            channels: channels,
+            channel_stride: 1,
+            width: width,
+            width_stride: channels as usize,
+            height: height,
+            height_stride: channels as usize * width as usize,
+        }
+    }
+
+    /// Returns the layout of interleaved samples in columns following each other without
+    /// padding
+    pub fn column_major_packed(channels: u8, width: u32, height: u32) -> SampleLayout {
+        SampleLayout {
+            channels: channels,
+            channel_stride: 1,
+            width: width,
+            width_stride: channels as usize * height as usize,
+            height: height,
+            height_stride: channels as usize,
+        }
+    }
+
+    /// Returns the layout of samples stored in one plane per channel, each holding its rows
+    /// without padding
+    pub fn planar(channels: u8, width: u32, height: u32) -> SampleLayout {
+        SampleLayout {
+            channels: channels,
+            channel_stride: width as usize * height as usize,
+            width: width,
+            width_stride: 1,
+            height: height,
+            height_stride: width as usize,
+        }
+    }
+
+    /// Returns the channel count, width and height
+    pub fn extents(&self) -> (usize, usize, usize) {
+        (self.channels as usize, self.width as usize, self.height as usize)
+    }
+
+    /// Returns the channel, width and height strides
+    pub fn strides_cwh(&self) -> (usize, usize, usize) {
+        (self.channel_stride, self.width_stride, self.height_stride)
+    }
+
+    /// Returns the length a buffer needs to hold all samples, or `None` if it exceeds the
+    /// address space
+    pub fn min_length(&self) -> Option<usize> {
+        let (channels, width, height) = self.extents();
+        if channels == 0 || width == 0 || height == 0 {
+            return Some(0)
+        }
+        self.index_of(channels - 1, width - 1, height - 1).and_then(|last| last.checked_add(1))
+    }
+
+    /// Returns whether a buffer of ```len``` samples holds all samples
+    pub fn fits(&self, len: usize) -> bool {
+        self.min_length().map_or(false, |min| min <= len)
+    }
+
+    /// Returns the index of the sample of channel ```channel``` of the pixel at
+    /// ```(x, y)```, or `None` if it is out of bounds
+    pub fn index(&self, channel: u8, x: u32, y: u32) -> Option<usize> {
+        if channel >= self.channels || x >= self.width || y >= self.height {
+            return None
+        }
+        self.index_of(channel as usize, x as usize, y as usize)
+    }
+
+    fn index_of(&self, channel: usize, x: usize, y: usize) -> Option<usize> {
+        let channel = channel.checked_mul(self.channel_stride);
+        let x = x.checked_mul(self.width_stride);
+        let y = y.checked_mul(self.height_stride);
+        match (channel, x, y) {
+            (Some(channel), Some(x), Some(y)) => {
+                channel.checked_add(x).and_then(|index| index.checked_add(y))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether two different samples are stored at the same index
+    ///
+    /// Layouts with zero strides, for example, repeat a single row or pixel. Writing to such
+    /// samples changes all of their aliases.
+    pub fn has_aliased_samples(&self) -> bool {
+        let mut dimensions = [
+            (self.channel_stride, self.channels as usize),
+            (self.width_stride, self.width as usize),
+            (self.height_stride, self.height as usize),
+        ];
+        if dimensions.iter().any(|&(_, extent)| extent == 0) {
+            return false
+        }
+        // Without aliasing, each stride steps over all samples of the dimensions with smaller
+        // strides
+        dimensions.sort();
+        let mut reach = 0usize;
+        for &(stride, extent) in &dimensions {
+            if extent > 1 && stride <= reach {
+                return true
+            }
+            reach = match stride.checked_mul(extent - 1).and_then(|r| r.checked_add(reach)) {
+                Some(reach) => reach,
+                None => return false,
+            };
+        }
+        false
+    }
+
+    /// Returns whether the samples are interleaved and the rows follow each other, possibly
+    /// padded, as in an `ImageBuffer`
+    pub fn is_row_major_interleaved(&self) -> bool {
+        let row_len = self.channels as usize * self.width as usize;
+        (self.channel_stride == 1 || self.channels <= 1)
+            && (self.width_stride == self.channels as usize || self.width <= 1)
+            && (self.height_stride >= row_len || self.height <= 1)
+    }
+
+    /// Returns the layout of every ```x_step```-th pixel of every ```y_step```-th row,
+    /// starting with the first pixel
+    ///
+    /// Returns `None` if a step is zero or the strides would overflow.
+    pub fn subsample(&self, x_step: u32, y_step: u32) -> Option<SampleLayout> {
+        if x_step == 0 || y_step == 0 {
+            return None
+        }
+        let width_stride = self.width_stride.checked_mul(x_step as usize);
+        let height_stride = self.height_stride.checked_mul(y_step as usize);
+        match (width_stride, height_stride) {
+            (Some(width_stride), Some(height_stride)) => Some(SampleLayout {
+                width: self.width / x_step + (self.width % x_step != 0) as u32,
+                width_stride: width_stride,
+                height: self.height / y_step + (self.height % y_step != 0) as u32,
+                height_stride: height_stride,
+                ..*self
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns the layout of the first ```channels``` channels of the ```width``` by
+    /// ```height``` pixels at the top left, which never need more samples
+    ///
+    /// The extents are only reduced, larger ones are kept as they are.
+    pub fn shrink_to(&self, channels: u8, width: u32, height: u32) -> SampleLayout {
+        SampleLayout {
+            channels: cmp::min(self.channels, channels),
+            width: cmp::min(self.width, width),
+            height: cmp::min(self.height, height),
+            ..*self
+        }
+    }
+}
+
+/// A buffer of samples together with their layout
+///
+/// ```
+/// use image::{Rgb, RgbImage};
+/// use image::flat::{FlatSamples, SampleLayout};
+///
+/// // Two rows of two pixels, stored as a red, a green and a blue plane
+/// let planes = vec![1, 2, 3, 4, 10, 20, 30, 40, 100, 200, 255, 0];
+/// let flat = FlatSamples::new(planes, SampleLayout::planar(3, 2, 2));
+/// let image: RgbImage = flat.to_buffer().unwrap();
+/// assert_eq!(image.get_pixel(1, 0), &Rgb([2, 20, 200]));
+/// ```
+#[derive(Clone, Debug)]
+pub struct FlatSamples<Buffer> {
+    /// The samples, at the indices given by `layout`
+    pub samples: Buffer,
+    /// The positions of the samples in the buffer
+    pub layout: SampleLayout,
+    /// The color type of the pixels, if it is known
+    pub color_hint: Option<ColorType>,
+}
+
+impl<Buffer> FlatSamples<Buffer> {
+    /// Creates a view of ```samples``` laid out as ```layout```, of no particular color type
+    pub fn new(samples: Buffer, layout: SampleLayout) -> FlatSamples<Buffer> {
+        FlatSamples {
+            samples: samples,
+            layout: layout,
+            color_hint: None,
+        }
+    }
+
+    /// Returns the samples of every ```x_step```-th pixel of every ```y_step```-th row, starting
+    /// with the first pixel
+    ///
+    /// Fails if a step is zero.
+    pub fn subsample(self, x_step: u32, y_step: u32) -> ImageResult<FlatSamples<Buffer>> {
+        match self.layout.subsample(x_step, y_step) {
+            Some(layout) => Ok(FlatSamples { layout: layout, ..self }),
+            None => Err(ImageError::Parameter(ParameterErrorKind::Generic(
+                "Subsampling steps have to be at least 1".to_string()))),
+        }
+    }
+
+    /// Reduces the samples to the first ```channels``` channels of the ```width``` by
+    /// ```height``` pixels at the top left, like `SampleLayout::shrink_to`
+    pub fn shrink_to(&mut self, channels: u8, width: u32, height: u32) {
+        self.layout = self.layout.shrink_to(channels, width, height);
+    }
+}
+
+impl<T, Buffer> FlatSamples<Buffer> where Buffer: Deref<Target=[T]> {
+    /// Returns a view of the samples borrowing the buffer
+    pub fn as_slice(&self) -> FlatSamples<&[T]> {
+        FlatSamples {
+            samples: &*self.samples,
+            layout: self.layout,
+            color_hint: self.color_hint,
+        }
+    }
+
+    /// Returns whether the buffer holds all samples of the layout
+    pub fn is_valid(&self) -> bool {
+        self.layout.fits(self.samples.len())
+    }
+
+    /// Returns the sample of channel ```channel``` of the pixel at ```(x, y)```, or `None` if
+    /// it is out of bounds of the layout or the buffer
+    pub fn get_sample(&self, channel: u8, x: u32, y: u32) -> Option<&T> {
+        self.layout.index(channel, x, y).and_then(|index| self.samples.get(index))
+    }
+
+    /// Converts the samples into an `ImageBuffer` of pixels of type ```P```, without copying
+    /// them
+    ///
+    /// The samples have to be interleaved with as many channels as ```P``` and the rows have
+    /// to follow each other, as `SampleLayout::is_row_major_interleaved` checks. Otherwise, or
+    /// if the buffer is too short, the error is returned together with the samples.
+    pub fn try_into_buffer<P>(self) -> Result<ImageBuffer<P, Buffer>, (ImageError, FlatSamples<Buffer>)>
+        where P: Pixel<Subpixel=T> + 'static, T: Primitive + 'static {
+        let layout = self.layout;
+        if layout.channels != P::channel_count() {
+            return Err((ImageError::Parameter(ParameterErrorKind::DimensionMismatch), self))
+        }
+        if !layout.is_row_major_interleaved() {
+            return Err((ImageError::Parameter(ParameterErrorKind::Generic(
+                "Samples are not interleaved in rows".to_string())), self))
+        }
+        if !self.is_valid() {
+            return Err((ImageError::Parameter(ParameterErrorKind::BufferTooShort), self))
+        }
+        // A single row needs no row stride, ImageBuffer only checks that it covers the row
+        let row_len = layout.channels as usize * layout.width as usize;
+        let stride = if layout.height <= 1 { row_len } else { layout.height_stride };
+        match ImageBuffer::from_raw_with_stride(layout.width, layout.height, stride, self.samples) {
+            Some(buffer) => Ok(buffer),
+            None => unreachable!("The layout has been checked to fit the buffer"),
+        }
+    }
+
+    /// Copies the samples into a new `ImageBuffer` of pixels of type ```P```, whatever their
+    /// layout is
+    ///
+    /// Fails if the number of channels differs from ```P``` or the buffer is too short.
+    pub fn to_buffer<P>(&self) -> ImageResult<ImageBuffer<P, Vec<T>>>
+        where P: Pixel<Subpixel=T> + 'static, T: Primitive + 'static {
+        let layout = self.layout;
+        if layout.channels != P::channel_count() {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+        if !self.is_valid() {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+        let mut buffer: ImageBuffer<P, Vec<T>> = ImageBuffer::new(layout.width, layout.height);
+        for (x, y, pixel) in buffer.enumerate_pixels_mut() {
+            for (c, sample) in pixel.channels_mut().iter_mut().enumerate() {
+                *sample = self.samples[layout.index_of(c, x as usize, y as usize).unwrap()];
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+impl<T, Buffer> FlatSamples<Buffer> where Buffer: Deref<Target=[T]> + DerefMut {
+    /// Returns a view of the samples borrowing the buffer mutably
+    pub fn as_mut_slice(&mut self) -> FlatSamples<&mut [T]> {
+        FlatSamples {
+            samples: &mut *self.samples,
+            layout: self.layout,
+            color_hint: self.color_hint,
+        }
+    }
+
+    /// Returns the mutable sample of channel ```channel``` of the pixel at ```(x, y)```, or
+    /// `None` if it is out of bounds of the layout or the buffer
+    pub fn get_mut_sample(&mut self, channel: u8, x: u32, y: u32) -> Option<&mut T> {
+        match self.layout.index(channel, x, y) {
+            Some(index) => self.samples.get_mut(index),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FlatSamples, SampleLayout};
+    use buffer::{ImageBuffer, RgbImage};
+    use color::{ColorType, Luma, Rgb};
+    use image::{ImageError, ParameterErrorKind};
+
+    #[test]
+    fn layouts() {
+        let layout = SampleLayout::row_major_packed(3, 4, 2);
+        assert_eq!(layout.min_length(), Some(24));
+        assert_eq!(layout.index(2, 1, 1), Some(17));
+        assert_eq!(layout.index(3, 0, 0), None);
+        assert!(layout.is_row_major_interleaved());
+        assert!(!layout.has_aliased_samples());
+
+        let planar = SampleLayout::planar(3, 4, 2);
+        assert_eq!(planar.min_length(), Some(24));
+        assert_eq!(planar.index(2, 1, 1), Some(21));
+        assert!(!planar.is_row_major_interleaved());
+        assert!(!planar.has_aliased_samples());
+        assert!(!SampleLayout::column_major_packed(3, 4, 2).has_aliased_samples());
+
+        let repeated = SampleLayout { height_stride: 0, ..layout };
+        assert!(repeated.has_aliased_samples());
+        assert_eq!(repeated.min_length(), Some(12));
+        let huge = SampleLayout { height_stride: usize::max_value(), ..layout };
+        assert_eq!(huge.min_length(), None);
+        assert!(!huge.fits(usize::max_value()));
+
+        let half = layout.subsample(2, 2).unwrap();
+        assert_eq!((half.width, half.height, half.width_stride, half.height_stride), (2, 1, 6, 24));
+        assert_eq!(half.index(0, 1, 0), Some(6));
+        assert!(layout.subsample(0, 1).is_none());
+        assert_eq!(layout.shrink_to(4, 1, 1).extents(), (3, 1, 1));
+    }
+
+    #[test]
+    fn buffer_round_trip() {
+        let image: RgbImage = ImageBuffer::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 7]));
+        let flat = image.clone().into_flat_samples();
+        assert_eq!(flat.layout, SampleLayout::row_major_packed(3, 3, 2));
+        assert_eq!(flat.color_hint, Some(ColorType::RGB(8)));
+        assert_eq!(flat.get_sample(1, 2, 1), Some(&1));
+
+        let back: RgbImage = flat.try_into_buffer().unwrap();
+        assert_eq!(back.into_raw(), image.clone().into_raw());
+        let borrowed: ImageBuffer<Rgb<u8>, &[u8]> = image.as_flat_samples().try_into_buffer().unwrap();
+        assert_eq!(borrowed.get_pixel(2, 1), &Rgb([2, 1, 7]));
+
+        // Padded rows are kept in place
+        let padded = FlatSamples::new(vec![1, 2, 0, 3, 4, 0], SampleLayout {
+            height_stride: 3,
+            ..SampleLayout::row_major_packed(1, 2, 2)
+        });
+        let buffer: ImageBuffer<Luma<u8>, Vec<u8>> = padded.try_into_buffer().unwrap();
+        assert_eq!((buffer.stride(), buffer.get_pixel(1, 1)[0]), (3, 4));
+    }
+
+    #[test]
+    fn conversion_errors() {
+        let planar = FlatSamples::new(vec![0u8; 12], SampleLayout::planar(3, 2, 2));
+        let planar = match planar.try_into_buffer::<Rgb<u8>>() {
+            Err((ImageError::Parameter(ParameterErrorKind::Generic(_)), planar)) => planar,
+            r => panic!("Expected planar samples to be rejected, got {:?}", r.is_ok()),
+        };
+        assert!(planar.to_buffer::<Rgb<u8>>().is_ok());
+        match planar.to_buffer::<Luma<u8>>() {
+            Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch)) => (),
+            r => panic!("Expected the channel count to differ, got {:?}", r.is_ok()),
+        }
+        let short = FlatSamples::new(vec![0u8; 11], SampleLayout::row_major_packed(3, 2, 2));
+        match short.try_into_buffer::<Rgb<u8>>() {
+            Err((ImageError::Parameter(ParameterErrorKind::BufferTooShort), _)) => (),
+            r => panic!("Expected the buffer to be too short, got {:?}", r.is_ok()),
+        }
+    }
+
+    #[test]
+    fn subsample() {
+        let samples: Vec<u8> = (0..16).collect();
+        let mut flat = FlatSamples::new(samples, SampleLayout::row_major_packed(1, 4, 4));
+        *flat.get_mut_sample(0, 2, 2).unwrap() = 100;
+        let half = flat.subsample(2, 2).unwrap();
+        let image: ImageBuffer<Luma<u8>, Vec<u8>> = half.to_buffer().unwrap();
+        assert_eq!(image.into_raw(), vec![0, 2, 8, 100]);
+        assert!(half.subsample(1, 0).is_err());
+    }
+}
@@ -8,39 +8,79 @@
 
 extern crate gif;
 
-use std::io::{Read, Write};
+use std::collections::HashSet;
+use std::io::{self, Cursor, Read, Write};
+
+use byteorder::{ByteOrder, LittleEndian};
 
 pub use self::gif::Frame;
-use self::gif::{SetParameter, ColorOutput};
+use self::gif::{SetParameter, ColorOutput, DisposalMethod, Repeat};
 
-use image::{ImageError, ImageResult, DecodingResult, ImageDecoder};
-use color;
+use animation::{self, Blend, Delay, Disposal, Frames, LoopCount};
+use buffer::RgbaImage;
+use image::{decoder_metadata, ImageError, ImageFormat, ImageResult, DecodingResult, ImageDecoder,
+            ImageEncoder, Limits, Metadata, ParameterErrorKind};
+use color::{self, Rgba};
+use imageops;
+use math::{nq, quantize};
+use utils::Either;
 
-enum Either<T, U> {
-    Left(T),
-    Right(U)
-}
+/// The stream read by the gif reader, which starts with the blocks read by `scan_loop_count`
+type Source<R> = io::Chain<Cursor<Vec<u8>>, R>;
 
 /// GIF decoder
 pub struct Decoder<R: Read> {
-    inner: Option<Either<gif::Decoder<R>, gif::Reader<R>>>
+    inner: Option<Either<Source<R>, gif::Reader<Source<R>>>>,
+    loop_count: Option<LoopCount>,
 }
 
 impl<R: Read> Decoder<R> {
     /// Creates a new decoder that decodes the input steam ```r```
     pub fn new(r: R) -> Decoder<R> {
-        let mut decoder = gif::Decoder::new(r);
-        decoder.set(ColorOutput::RGBA);
         Decoder {
-            inner: Some(Either::Left(decoder))
+            inner: Some(Either::Left(Cursor::new(Vec::new()).chain(r))),
+            loop_count: None,
+        }
+    }
+
+    /// Returns how often the animation is played, which is once for still images
+    ///
+    /// It is given by the NETSCAPE2.0 application extension before the first frame.
+    pub fn loop_count(&mut self) -> ImageResult<LoopCount> {
+        if self.loop_count.is_none() {
+            let mut source = match self.inner.take().unwrap() {
+                Either::Left(source) => source,
+                Either::Right(_) => unreachable!(),
+            };
+            let mut recorded = Vec::new();
+            let loop_count = scan_loop_count(&mut source, &mut recorded);
+            let (prefix, rest) = source.into_inner();
+            let position = prefix.position() as usize;
+            recorded.extend_from_slice(&prefix.get_ref()[position..]);
+            self.inner = Some(Either::Left(Cursor::new(recorded).chain(rest)));
+            self.loop_count = Some(try!(loop_count));
         }
+        Ok(self.loop_count.unwrap())
+    }
+
+    /// Returns the frames of the animation rendered onto canvases of the size of the image.
+    ///
+    /// Unlike the frames returned by `into_frames`, which only cover the area they change, each
+    /// of these shows the whole image with the disposal of the frames before it applied.
+    pub fn into_composited_frames(mut self) -> ImageResult<Frames> {
+        let (width, height) = try!(self.dimensions());
+        Ok(try!(self.into_frames()).composite(width, height))
     }
 
     // Converts the inner decoder to a reader
-    fn get_reader(&mut self) -> Result<&mut gif::Reader<R>, gif::DecodingError> {
+    fn get_reader(&mut self) -> ImageResult<&mut gif::Reader<Source<R>>> {
+        // The reader skips the application extensions, so they are read beforehand
+        try!(self.loop_count());
         let inner = self.inner.take().unwrap();
         self.inner = Some(match inner {
-            Either::Left(decoder) => {
+            Either::Left(source) => {
+                let mut decoder = gif::Decoder::new(source);
+                decoder.set(ColorOutput::RGBA);
                 let reader = try!(decoder.read_info());
                 Either::Right(reader)
             },
@@ -64,6 +104,14 @@ impl<R: Read> ImageDecoder for Decoder<R> {
         Ok(color::ColorType::RGBA(8))
     }
 
+    /// Returns the metadata of the image, whose frame count is unknown as the frames are only
+    /// found by decoding those before them
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.frame_count = None;
+        Ok(metadata)
+    }
+
     fn row_len(&mut self) -> ImageResult<usize> {
         let reader = try!(self.get_reader());
         Ok(reader.line_length())
@@ -83,7 +131,126 @@ impl<R: Read> ImageDecoder for Decoder<R> {
             try!(reader.read_into_buffer(&mut buf));
             Ok(DecodingResult::U8(buf))
         } else {
-            Err(ImageError::ImageEnd)
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+    }
+
+    fn read_image_into(&mut self, buf: &mut [u8]) -> ImageResult<usize> {
+        let reader = try!(self.get_reader());
+        if try!(reader.next_frame_info()).is_none() {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = reader.buffer_size();
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+        try!(reader.read_into_buffer(&mut buf[..len]));
+        Ok(len)
+    }
+
+    /// Returns the frames of the animation, still images have a single frame.
+    ///
+    /// Each frame holds the pixels of its own area, colored by its local palette or the global
+    /// one, along with its offset, delay and disposal. Frames are drawn over the content below
+    /// them. Frames already decoded by `read_image` are not returned again.
+    fn into_frames(self) -> ImageResult<Frames> {
+        self.into_frames_with_limits(&Limits::no_limits())
+    }
+
+    fn into_frames_with_limits(mut self, limits: &Limits) -> ImageResult<Frames> {
+        let reader = try!(self.get_reader());
+        let mut frames = Vec::new();
+        let mut bytes = 0;
+        while let Some(frame) = try!(reader.next_frame_info()).cloned() {
+            let (width, height) = (frame.width as u32, frame.height as u32);
+            try!(limits.check_image(width, height, color::ColorType::RGBA(8)));
+            bytes += width as u64 * height as u64 * 4;
+            try!(limits.check_animation(frames.len() + 1, bytes));
+
+            let mut data = vec![0; reader.buffer_size()];
+            try!(reader.read_into_buffer(&mut data));
+            let buffer = match RgbaImage::from_raw(width, height, data) {
+                Some(buffer) => buffer,
+                None => return Err(ImageError::malformed(ImageFormat::GIF,
+                    "Frame data does not match its dimensions".into())),
+            };
+
+            // Gif delays are stored in hundredths of a second
+            let delay = Delay::from_numer_denom_ms(frame.delay as u32 * 10, 1);
+            let mut animation_frame = animation::Frame::from_parts(buffer, frame.left as u32, frame.top as u32, delay);
+            animation_frame.set_disposal(match frame.dispose {
+                DisposalMethod::Any | DisposalMethod::Keep => Disposal::None,
+                DisposalMethod::Background => Disposal::Background,
+                DisposalMethod::Previous => Disposal::Previous,
+            });
+            animation_frame.set_blend(Blend::Over);
+            frames.push(animation_frame);
+        }
+        Ok(Frames::new(frames))
+    }
+}
+
+/// How the colors of the frames of an animation are reduced to gif palettes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PaletteMode {
+    /// Each frame is quantized to a local palette of its own
+    Local,
+    /// All frames share a single global palette, which is smaller when their colors are similar
+    Global,
+}
+
+/// The algorithm reducing the colors of a frame to a gif palette of at most 256 colors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quantizer {
+    /// The NeuQuant neural network, which gives the best quality but is the slowest
+    NeuQuant,
+    /// Median cut, splitting the colors at the median of their widest channel
+    MedianCut,
+    /// Octree quantization, merging the least used colors that differ in the fewest bits
+    Octree,
+}
+
+/// How the error of reducing the colors of a frame is spread to hide banding
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dithering {
+    /// Each pixel gets the nearest color of the palette
+    None,
+    /// The pixels are offset by a Bayer threshold matrix, giving a regular pattern
+    Ordered,
+    /// The error of each pixel is diffused to its neighbours below and to the right
+    FloydSteinberg,
+}
+
+/// Settings controlling how a gif `Encoder` reduces the colors of its frames
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct EncoderOptions {
+    /// The algorithm building the palettes. Defaults to `Quantizer::NeuQuant`.
+    pub quantizer: Quantizer,
+
+    /// The dithering applied when mapping the pixels to a palette. Defaults to
+    /// `Dithering::None`.
+    pub dithering: Dithering,
+
+    /// A palette of up to 256 colors shared by all frames instead of quantized ones. One entry
+    /// has to be left for the transparent color of frames with transparent pixels. Defaults to
+    /// `None`.
+    pub palette: Option<Vec<[u8; 3]>>,
+
+    /// Store only the changes of each frame of an animation.
+    ///
+    /// Frames are cropped to the area that differs from the image shown before them, pixels
+    /// which did not change inside it are made transparent. This shrinks animations with
+    /// mostly static content, like screen recordings, considerably. Disabled by default.
+    pub optimize: bool,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> EncoderOptions {
+        EncoderOptions {
+            quantizer: Quantizer::NeuQuant,
+            dithering: Dithering::None,
+            palette: None,
+            optimize: false,
         }
     }
 }
@@ -91,15 +258,23 @@ impl<R: Read> ImageDecoder for Decoder<R> {
 /// GIF encoder.
 pub struct Encoder<W: Write> {
     w: W,
+    options: EncoderOptions,
 }
 
 impl<W: Write> Encoder<W> {
     /// Creates a new GIF encoder.
     pub fn new(w: W) -> Encoder<W> {
+        Encoder::new_with_options(w, EncoderOptions::default())
+    }
+
+    /// Creates a new GIF encoder reducing colors as chosen by ```options```.
+    pub fn new_with_options(w: W, options: EncoderOptions) -> Encoder<W> {
         Encoder {
-            w: w
+            w: w,
+            options: options,
         }
     }
+
     /// Encodes a frame.
     pub fn encode(self, frame: Frame) -> ImageResult<()> {
         let mut encoder = try!(
@@ -107,15 +282,685 @@ impl<W: Write> Encoder<W> {
         );
         encoder.write_frame(&frame).map_err(|err| err.into())
     }
+
+    /// Encodes ```frames``` as an animation, played ```loop_count``` times.
+    ///
+    /// The size of the animation is that of the first frame. Colors are quantized to a palette
+    /// of 256 colors per frame, pixels which are not fully transparent become opaque. Blending
+    /// is ignored as gif frames are always drawn over the previous content.
+    pub fn encode_frames<I>(self, frames: I, loop_count: LoopCount) -> ImageResult<()>
+    where I: IntoIterator<Item = animation::Frame> {
+        self.encode_frames_with_palette(frames, loop_count, PaletteMode::Local)
+    }
+
+    /// Encodes ```frames``` as an animation like `encode_frames`, choosing how the palettes are
+    /// built with ```palette```.
+    ///
+    /// A global palette holds at most 256 colors for all frames together, so it is exact only for
+    /// animations with few colors. The frames are collected to build it. A palette given by the
+    /// encoder options is always global.
+    pub fn encode_frames_with_palette<I>(self, frames: I, loop_count: LoopCount, palette: PaletteMode)
+        -> ImageResult<()>
+    where I: IntoIterator<Item = animation::Frame> {
+        let frames: Vec<_> = frames.into_iter().collect();
+        let (width, height) = match frames.first() {
+            Some(first) => first.buffer().dimensions(),
+            None => return Err(ImageError::malformed(ImageFormat::GIF,
+                "An animation needs at least one frame".into())),
+        };
+        let frames = if self.options.optimize {
+            optimize_frames(frames, width, height)
+        } else {
+            frames
+        };
+
+        let global = match (&self.options.palette, palette) {
+            (&Some(ref colors), _) => {
+                let transparency = frames.iter().any(|frame| has_transparency(frame.buffer()));
+                if colors.is_empty() || colors.len() + transparency as usize > 256 {
+                    return Err(ImageError::malformed(ImageFormat::GIF,
+                        "The palette needs 1 to 256 colors, including the transparent one".into()))
+                }
+                Some(with_transparency(colors.clone(), transparency))
+            }
+            (&None, PaletteMode::Global) => {
+                let buffers: Vec<_> = frames.iter().map(|frame| frame.buffer()).collect();
+                Some(build_palette(&buffers, self.options.quantizer))
+            }
+            (&None, PaletteMode::Local) => None,
+        };
+
+        let global_colors: Vec<u8> = match global {
+            Some((ref palette, _)) => palette.colors().iter().flat_map(|c| c.to_vec()).collect(),
+            None => Vec::new(),
+        };
+        let mut encoder = try!(gif::Encoder::new(self.w, try!(to_u16(width)), try!(to_u16(height)), &global_colors));
+
+        // Gif counts the repetitions after the first play
+        let repeat = match loop_count {
+            LoopCount::Infinite => Repeat::Infinite,
+            LoopCount::Finite(0) => return Err(ImageError::malformed(ImageFormat::GIF,
+                "An animation must be played at least once".into())),
+            LoopCount::Finite(n) => Repeat::Finite(try!(to_u16(n - 1))),
+        };
+        try!(encoder.set(repeat));
+
+        for frame in &frames {
+            let (frame_width, frame_height) = frame.buffer().dimensions();
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            // Gif delays are stored in hundredths of a second
+            let centiseconds = (numer as u64 + denom as u64 * 5) / (denom as u64 * 10);
+
+            let local = match global {
+                Some(_) => None,
+                None => Some(build_palette(&[frame.buffer()], self.options.quantizer)),
+            };
+            let (palette, transparent) = match (&global, &local) {
+                (&Some(ref global), _) => (&global.0, global.1),
+                (_, &Some(ref local)) => (&local.0, local.1),
+                _ => unreachable!(),
+            };
+
+            let mut gif_frame = Frame::default();
+            gif_frame.width = try!(to_u16(frame_width));
+            gif_frame.height = try!(to_u16(frame_height));
+            gif_frame.buffer = index_pixels(frame.buffer(), palette, transparent, self.options.dithering).into();
+            gif_frame.transparent = transparent;
+            if local.is_some() {
+                gif_frame.palette = Some(palette.colors().iter().flat_map(|c| c.to_vec()).collect());
+            }
+            gif_frame.delay = try!(to_u16(centiseconds as u32));
+            gif_frame.left = try!(to_u16(frame.left()));
+            gif_frame.top = try!(to_u16(frame.top()));
+            gif_frame.dispose = match frame.disposal() {
+                Disposal::None => DisposalMethod::Keep,
+                Disposal::Background => DisposalMethod::Background,
+                Disposal::Previous => DisposalMethod::Previous,
+            };
+            try!(encoder.write_frame(&gif_frame));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for Encoder<W> {
+    /// Encodes an 8-bit image as a single frame, see `Frame::from_rgba`.
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        if width > u16::max_value() as u32 || height > u16::max_value() as u32 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+        let channels = match color {
+            color::ColorType::Gray(8) => 1,
+            color::ColorType::GrayA(8) => 2,
+            color::ColorType::RGB(8) => 3,
+            color::ColorType::RGBA(8) => 4,
+            _ => return Err(ImageError::unsupported_color(ImageFormat::GIF, color)),
+        };
+        let len = width as usize * height as usize * channels;
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let mut rgba = Vec::with_capacity(len / channels * 4);
+        for p in buf[..len].chunks(channels) {
+            match channels {
+                1 => rgba.extend_from_slice(&[p[0], p[0], p[0], 255]),
+                2 => rgba.extend_from_slice(&[p[0], p[0], p[0], p[1]]),
+                3 => rgba.extend_from_slice(&[p[0], p[1], p[2], 255]),
+                _ => rgba.extend_from_slice(p),
+            }
+        }
+        self.encode(Frame::from_rgba(width as u16, height as u16, &mut rgba))
+    }
+}
+
+/// Builds a palette for the opaque colors of ```buffers``` with the ```quantizer```
+///
+/// Returns the palette and the index of the transparent color, which is added last if any
+/// pixel is transparent. Like gif itself, pixels which are not fully transparent are
+/// treated as opaque.
+fn build_palette(buffers: &[&RgbaImage], quantizer: Quantizer) -> (quantize::Palette, Option<u8>) {
+    let transparency = buffers.iter().any(|buffer| has_transparency(buffer));
+    let max_colors = if transparency { 255 } else { 256 };
+
+    let opaque: Vec<u8> = buffers.iter()
+        .flat_map(|buffer| buffer.pixels().filter(|p| p.data[3] != 0))
+        .flat_map(|p| vec![p.data[0], p.data[1], p.data[2], 255])
+        .collect();
+
+    let mut colors = match quantizer {
+        Quantizer::MedianCut => quantize::median_cut(&opaque, max_colors),
+        Quantizer::Octree => quantize::octree(&opaque, max_colors),
+        Quantizer::NeuQuant => {
+            let distinct: HashSet<_> = opaque.chunks(4).map(|p| [p[0], p[1], p[2]]).collect();
+            if distinct.len() <= max_colors {
+                // Few enough colors are kept exactly, which median cut does
+                quantize::median_cut(&opaque, max_colors)
+            } else {
+                let quantizer = nq::NeuQuant::new(10, max_colors, &opaque);
+                quantizer.color_map_rgba().chunks(4).map(|c| [c[0], c[1], c[2]]).collect()
+            }
+        }
+    };
+    if colors.is_empty() && !transparency {
+        colors.push([0, 0, 0]);
+    }
+
+    with_transparency(colors, transparency)
+}
+
+/// Replaces ```frames``` by the changes between the images they show on a canvas of
+/// ```width``` x ```height``` pixels
+///
+/// Each frame is cropped to the area that changed since the previous one, the unchanged pixels
+/// inside it become transparent so the content below shows through. Gif frames can not make
+/// pixels transparent again, so a frame which clears pixels is preceded by a frame covering the
+/// whole canvas, which is disposed to the background.
+fn optimize_frames(frames: Vec<animation::Frame>, width: u32, height: u32) -> Vec<animation::Frame> {
+    // Gif pixels are either transparent or opaque
+    let opaque = |p: &Rgba<u8>| p.data[3] != 0;
+    let same = |a: &Rgba<u8>, b: &Rgba<u8>| opaque(a) == opaque(b) && (!opaque(a) || a.data[..3] == b.data[..3]);
+
+    let canvases: Vec<_> = Frames::new(frames).composite(width, height).collect();
+    let clears: Vec<bool> = canvases.iter().enumerate().map(|(i, canvas)| i > 0 && canvas.buffer().pixels()
+        .zip(canvases[i - 1].buffer().pixels())
+        .any(|(p, previous)| !opaque(p) && opaque(previous))).collect();
+
+    let mut optimized = Vec::with_capacity(canvases.len());
+    for (i, canvas) in canvases.iter().enumerate() {
+        let canvas_buffer = canvas.buffer();
+        // The content the frame is drawn over, nothing for the first frame and after clearing
+        let base = if i == 0 || clears[i] { None } else { Some(canvases[i - 1].buffer()) };
+        let covers_canvas = clears.get(i + 1) == Some(&true);
+
+        let changed = |x: u32, y: u32| {
+            let pixel = canvas_buffer.get_pixel(x, y);
+            match base {
+                Some(base) => !same(pixel, base.get_pixel(x, y)),
+                None => opaque(pixel),
+            }
+        };
+
+        // The bounding box of the changed pixels
+        let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+        if covers_canvas {
+            left = 0; top = 0; right = width; bottom = height;
+        } else {
+            for y in 0..height {
+                for x in 0..width {
+                    if changed(x, y) {
+                        left = left.min(x);
+                        top = top.min(y);
+                        right = right.max(x + 1);
+                        bottom = bottom.max(y + 1);
+                    }
+                }
+            }
+        }
+        if left >= right || top >= bottom {
+            // Nothing changed, frames still need a pixel
+            left = 0; top = 0; right = 1; bottom = 1;
+        }
+
+        let buffer = RgbaImage::from_fn(right - left, bottom - top, |x, y| {
+            let (x, y) = (x + left, y + top);
+            if changed(x, y) { *canvas_buffer.get_pixel(x, y) } else { Rgba([0, 0, 0, 0]) }
+        });
+        let mut frame = animation::Frame::from_parts(buffer, left, top, canvas.delay());
+        frame.set_disposal(if covers_canvas { Disposal::Background } else { Disposal::None });
+        frame.set_blend(Blend::Over);
+        optimized.push(frame);
+    }
+    optimized
+}
+
+/// Whether any pixel of ```buffer``` is fully transparent
+fn has_transparency(buffer: &RgbaImage) -> bool {
+    buffer.pixels().any(|p| p.data[3] == 0)
+}
+
+/// Turns ```colors``` into a palette, adding a transparent color if ```transparency``` is needed
+fn with_transparency(mut colors: Vec<[u8; 3]>, transparency: bool) -> (quantize::Palette, Option<u8>) {
+    let transparent = if transparency {
+        colors.push([0, 0, 0]);
+        Some((colors.len() - 1) as u8)
+    } else {
+        None
+    };
+    (quantize::Palette::new(colors), transparent)
+}
+
+/// Maps the pixels of ```buffer``` to the indices of their colors in ```palette```
+///
+/// Transparent pixels get the index ```transparent```, which is excluded from the search for the
+/// other colors.
+fn index_pixels(buffer: &RgbaImage, palette: &quantize::Palette, transparent: Option<u8>, dithering: Dithering)
+    -> Vec<u8> {
+    // The transparent entry is not a color opaque pixels may take
+    let opaque = match transparent {
+        Some(t) => quantize::Palette::new(palette.colors()[..t as usize].to_vec()),
+        None => quantize::Palette::new(palette.colors().to_vec()),
+    };
+
+    let mut image = buffer.clone();
+    let (width, height) = image.dimensions();
+    match dithering {
+        Dithering::None => (),
+        // Dithering spreads the error of a pixel to its right and lower neighbours
+        Dithering::FloydSteinberg => if width > 1 && height > 1 {
+            imageops::dither(&mut image, &opaque);
+        },
+        Dithering::Ordered => {
+            // The distance between the levels of each channel if the colors formed a regular grid
+            let levels = (opaque.colors().len() as f32).cbrt().max(2.0);
+            let spread = 255.0 / (levels - 1.0);
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let threshold = BAYER[(y % 8) as usize][(x % 8) as usize] as f32 / 64.0 - 0.5;
+                for c in &mut pixel.data[..3] {
+                    *c = (*c as f32 + threshold * spread).max(0.0).min(255.0).round() as u8;
+                }
+            }
+        }
+    }
+
+    image.pixels().zip(buffer.pixels()).map(|(p, original)| match (original.data[3], transparent) {
+        (0, Some(t)) => t,
+        _ => opaque.index_of(&p.data) as u8,
+    }).collect()
+}
+
+/// The threshold matrix of ordered dithering
+static BAYER: [[u8; 8]; 8] = [
+    [ 0, 32,  8, 40,  2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44,  4, 36, 14, 46,  6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [ 3, 35, 11, 43,  1, 33,  9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47,  7, 39, 13, 45,  5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reads the blocks before the first image from ```r``` into ```recorded```, returning how often
+/// the animation is played
+fn scan_loop_count<R: Read>(r: &mut R, recorded: &mut Vec<u8>) -> ImageResult<LoopCount> {
+    // The header and logical screen descriptor, followed by the global color table
+    try!(read_recorded(r, recorded, 13));
+    let flags = recorded[10];
+    if flags & 0x80 != 0 {
+        try!(read_recorded(r, recorded, 3 << ((flags & 0x07) + 1)));
+    }
+
+    let mut loop_count = LoopCount::Finite(1);
+    loop {
+        try!(read_recorded(r, recorded, 1));
+        if recorded[recorded.len() - 1] != 0x21 {
+            // The first image, or the end of an image without any
+            return Ok(loop_count)
+        }
+        try!(read_recorded(r, recorded, 1));
+        let label = recorded[recorded.len() - 1];
+
+        // The sub-blocks of the extension, ended by an empty one
+        let mut looping = false;
+        for index in 0.. {
+            try!(read_recorded(r, recorded, 1));
+            let len = recorded[recorded.len() - 1] as usize;
+            if len == 0 {
+                break
+            }
+            try!(read_recorded(r, recorded, len));
+            let block = &recorded[recorded.len() - len..];
+            if label == 0xff && index == 0 {
+                looping = block == b"NETSCAPE2.0" || block == b"ANIMEXTS1.0";
+            } else if looping && block.len() == 3 && block[0] == 1 {
+                // Gif counts the repetitions after the first play
+                loop_count = match LittleEndian::read_u16(&block[1..]) {
+                    0 => LoopCount::Infinite,
+                    n => LoopCount::Finite(n as u32 + 1),
+                };
+            }
+        }
+    }
+}
+
+/// Appends ```len``` bytes read from ```r``` to ```recorded```
+fn read_recorded<R: Read>(r: &mut R, recorded: &mut Vec<u8>, len: usize) -> io::Result<()> {
+    let start = recorded.len();
+    recorded.resize(start + len, 0);
+    r.read_exact(&mut recorded[start..])
+}
+
+/// Converts a dimension, offset or delay to the 16 bits gif stores them in
+fn to_u16(value: u32) -> ImageResult<u16> {
+    if value > u16::max_value() as u32 {
+        Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+    } else {
+        Ok(value as u16)
+    }
 }
 
 impl From<gif::DecodingError> for ImageError {
     fn from(err: gif::DecodingError) -> ImageError {
         use self::gif::DecodingError::*;
         match err {
-            Format(desc) => ImageError::FormatError(desc.into()),
-            Internal(desc) => ImageError::FormatError(desc.into()),
+            Format(desc) => ImageError::malformed(ImageFormat::GIF, desc.into()),
+            Internal(desc) => ImageError::malformed(ImageFormat::GIF, desc.into()),
             Io(io_err) => ImageError::IoError(io_err),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use buffer::RgbaImage;
+    use color::Rgba;
+
+    #[test]
+    fn encode_frames() {
+        let mut second = animation::Frame::from_parts(
+            RgbaImage::from_pixel(1, 1, Rgba([0, 0, 255, 255])), 1, 1, Delay::from_numer_denom_ms(250, 1));
+        second.set_disposal(Disposal::Background);
+        let frames = vec![
+            animation::Frame::from_parts(RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255])), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            second,
+        ];
+
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames(frames, LoopCount::Infinite).unwrap();
+
+        let mut reader = gif::Decoder::new(&data[..]).read_info().unwrap();
+        assert_eq!((reader.width(), reader.height()), (2, 2));
+        let mut decoded = Vec::new();
+        while let Some(frame) = reader.read_next_frame().unwrap() {
+            decoded.push((frame.left, frame.top, frame.width, frame.height, frame.delay, frame.dispose));
+        }
+        assert_eq!(decoded, vec![
+            (0, 0, 2, 2, 10, DisposalMethod::Keep),
+            (1, 1, 1, 1, 25, DisposalMethod::Background),
+        ]);
+    }
+
+    #[test]
+    fn loop_count() {
+        let frame = || animation::Frame::new(RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 255])));
+        for &loop_count in &[LoopCount::Infinite, LoopCount::Finite(1), LoopCount::Finite(4)] {
+            let mut data = Vec::new();
+            Encoder::new(&mut data).encode_frames(vec![frame(), frame()], loop_count).unwrap();
+            let mut decoder = Decoder::new(&data[..]);
+            assert_eq!(decoder.loop_count().unwrap(), loop_count);
+            // The blocks read for the loop count are decoded again
+            assert_eq!(decoder.into_frames().unwrap().count(), 2);
+        }
+
+        // A global color table and other extensions before the application extension
+        let mut data = b"GIF89a\x01\0\x01\0\x80\0\0\0\0\0\xff\xff\xff".to_vec();
+        data.extend_from_slice(b"\x21\xfe\x03abc\0");
+        data.extend_from_slice(b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x02\0\0");
+        data.extend_from_slice(b"\x2c\0\0\0\0\x01\0\x01\0\0\x02\x02\x44\x01\0\x3b");
+        let mut decoder = Decoder::new(&data[..]);
+        assert_eq!(decoder.loop_count().unwrap(), LoopCount::Finite(3));
+        assert_eq!(decoder.dimensions().unwrap(), (1, 1));
+        assert!(decoder.read_image().is_ok());
+        assert!(Decoder::new(&data[..10]).loop_count().is_err());
+    }
+
+    #[test]
+    fn into_frames() {
+        let mut second = animation::Frame::from_parts(
+            RgbaImage::from_pixel(1, 2, Rgba([0, 0, 255, 255])), 2, 1, Delay::from_numer_denom_ms(500, 1));
+        second.set_disposal(Disposal::Previous);
+        let frames = vec![
+            animation::Frame::from_parts(RgbaImage::from_pixel(3, 3, Rgba([255, 0, 0, 255])), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            second,
+        ];
+
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames(frames, LoopCount::Finite(1)).unwrap();
+
+        let frames: Vec<_> = Decoder::new(&data[..]).into_frames().unwrap().collect();
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!((frames[0].left(), frames[0].top()), (0, 0));
+        assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(100, 1));
+        assert_eq!(frames[0].disposal(), Disposal::None);
+        assert_eq!(frames[0].blend(), Blend::Over);
+        assert_eq!(frames[0].buffer().dimensions(), (3, 3));
+        assert_eq!(*frames[0].buffer().get_pixel(1, 1), Rgba([255, 0, 0, 255]));
+
+        // The second frame has a palette of its own
+        assert_eq!((frames[1].left(), frames[1].top()), (2, 1));
+        assert_eq!(frames[1].delay(), Delay::from_numer_denom_ms(500, 1));
+        assert_eq!(frames[1].disposal(), Disposal::Previous);
+        assert_eq!(frames[1].buffer().dimensions(), (1, 2));
+        assert_eq!(*frames[1].buffer().get_pixel(0, 1), Rgba([0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn read_image_into() {
+        let mut data = Vec::new();
+        let image = RgbaImage::from_raw(2, 1, vec![255, 0, 0, 255, 0, 0, 255, 255]).unwrap();
+        let frames = vec![animation::Frame::new(image)];
+        Encoder::new(&mut data).encode_frames(frames, LoopCount::Finite(1)).unwrap();
+        let mut buf = [0; 9];
+        match Decoder::new(&data[..]).read_image_into(&mut buf[..7]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => (),
+            r => panic!("Expected the buffer to be too short, got {:?}", r),
+        }
+        assert_eq!(Decoder::new(&data[..]).read_image_into(&mut buf).unwrap(), 8);
+        assert_eq!(buf, [255, 0, 0, 255, 0, 0, 255, 255, 0]);
+    }
+
+    #[test]
+    fn frame_limits() {
+        let frames = vec![
+            animation::Frame::from_parts(RgbaImage::from_pixel(3, 3, Rgba([255, 0, 0, 255])), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            animation::Frame::from_parts(RgbaImage::from_pixel(1, 2, Rgba([0, 0, 255, 255])), 2, 1, Delay::from_numer_denom_ms(100, 1)),
+        ];
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames(frames, LoopCount::Finite(1)).unwrap();
+
+        // The frames hold 36 and 8 bytes
+        let frames = |limits: Limits| Decoder::new(&data[..]).into_frames_with_limits(&limits);
+        assert_eq!(frames(Limits { max_frames: Some(2), max_bytes: Some(44), ..Limits::no_limits() }).unwrap().count(), 2);
+        for &limits in &[Limits { max_frames: Some(1), ..Limits::no_limits() },
+                         Limits { max_bytes: Some(43), ..Limits::no_limits() },
+                         Limits { max_height: Some(2), ..Limits::no_limits() }] {
+            match frames(limits) {
+                Err(ImageError::Limits(_)) => {},
+                _ => panic!("Expected {:?} to be exceeded", limits),
+            }
+        }
+    }
+
+    #[test]
+    fn global_palette() {
+        let mut first = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        first.put_pixel(1, 2, Rgba([0, 0, 0, 0]));
+        let frames = vec![
+            animation::Frame::from_parts(first, 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            animation::Frame::from_parts(RgbaImage::from_pixel(2, 2, Rgba([0, 255, 0, 255])), 1, 1, Delay::from_numer_denom_ms(100, 1)),
+        ];
+
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames_with_palette(frames, LoopCount::Infinite, PaletteMode::Global).unwrap();
+
+        {
+            let mut reader = gif::Decoder::new(&data[..]).read_info().unwrap();
+            assert_eq!(reader.global_palette().map(|p| p[..9].to_vec()), Some(vec![255, 0, 0, 0, 255, 0, 0, 0, 0]));
+            while let Some(frame) = reader.read_next_frame().unwrap() {
+                assert!(frame.palette.is_none());
+                assert_eq!(frame.transparent, Some(2));
+            }
+        }
+
+        let frames: Vec<_> = Decoder::new(&data[..]).into_frames().unwrap().collect();
+        assert_eq!(*frames[0].buffer().get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[0].buffer().get_pixel(1, 2).data[3], 0);
+        assert_eq!(*frames[1].buffer().get_pixel(1, 1), Rgba([0, 255, 0, 255]));
+
+        // Animations with too many colors are quantized
+        let gradient = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 128, 255]));
+        let frames = vec![animation::Frame::new(gradient.clone())];
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames_with_palette(frames, LoopCount::Infinite, PaletteMode::Global).unwrap();
+
+        let frames: Vec<_> = Decoder::new(&data[..]).into_frames().unwrap().collect();
+        let error: i32 = frames[0].buffer().pixels().zip(gradient.pixels())
+            .map(|(a, b)| (0..3).map(|c| (a.data[c] as i32 - b.data[c] as i32).abs()).sum::<i32>())
+            .sum();
+        assert!(error / 1024 < 36);
+    }
+
+    #[test]
+    fn composited_frames() {
+        let red = Rgba([255, 0, 0, 255]);
+        let blue = Rgba([0, 0, 255, 255]);
+        let green = Rgba([0, 255, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let clear = Rgba([0, 0, 0, 0]);
+
+        let delay = Delay::from_numer_denom_ms(100, 1);
+        let mut second = animation::Frame::from_parts(RgbaImage::from_pixel(1, 1, blue), 1, 1, delay);
+        second.set_disposal(Disposal::Background);
+        let mut third = animation::Frame::from_parts(RgbaImage::from_pixel(1, 1, green), 0, 0, delay);
+        third.set_disposal(Disposal::Previous);
+        let frames = vec![
+            animation::Frame::from_parts(RgbaImage::from_pixel(3, 3, red), 0, 0, delay),
+            second,
+            third,
+            animation::Frame::from_parts(RgbaImage::from_pixel(1, 1, white), 2, 2, delay),
+        ];
+
+        let mut data = Vec::new();
+        Encoder::new(&mut data).encode_frames(frames, LoopCount::Infinite).unwrap();
+
+        let frames: Vec<_> = Decoder::new(&data[..]).into_composited_frames().unwrap().collect();
+        let pixels: Vec<_> = frames.iter().map(|frame| {
+            assert_eq!(frame.buffer().dimensions(), (3, 3));
+            assert_eq!((frame.left(), frame.top(), frame.delay()), (0, 0, delay));
+            [*frame.buffer().get_pixel(0, 0), *frame.buffer().get_pixel(1, 1), *frame.buffer().get_pixel(2, 2)]
+        }).collect();
+
+        assert_eq!(pixels, vec![
+            [red, red, red],
+            [red, blue, red],
+            // The blue pixel was cleared, the green one is removed again after the frame
+            [green, clear, red],
+            [red, clear, white],
+        ]);
+    }
+
+    fn decode_first_frame(data: &[u8]) -> RgbaImage {
+        Decoder::new(data).into_frames().unwrap().next().unwrap().into_buffer()
+    }
+
+    #[test]
+    fn quantizers() {
+        let gradient = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 128, 255]));
+
+        for &quantizer in &[Quantizer::NeuQuant, Quantizer::MedianCut, Quantizer::Octree] {
+            for &dithering in &[Dithering::None, Dithering::Ordered, Dithering::FloydSteinberg] {
+                let options = EncoderOptions { quantizer: quantizer, dithering: dithering, ..EncoderOptions::default() };
+                let mut data = Vec::new();
+                Encoder::new_with_options(&mut data, options)
+                    .encode_frames(vec![animation::Frame::new(gradient.clone())], LoopCount::Infinite)
+                    .unwrap();
+
+                let decoded = decode_first_frame(&data);
+                let error: i32 = decoded.pixels().zip(gradient.pixels())
+                    .map(|(a, b)| (0..3).map(|c| (a.data[c] as i32 - b.data[c] as i32).abs()).sum::<i32>())
+                    .sum();
+                // Dithering trades the error of single pixels for a better average
+                let bound = if dithering == Dithering::None { 36 } else { 60 };
+                assert!(error / 1024 < bound, "{:?} {:?}: {}", quantizer, dithering, error / 1024);
+                for c in 0..3 {
+                    let sum = |image: &RgbaImage| image.pixels().map(|p| p.data[c] as i32).sum::<i32>();
+                    assert!((sum(&decoded) - sum(&gradient)).abs() / 1024 < 6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn explicit_palette() {
+        let gray = RgbaImage::from_pixel(16, 16, Rgba([128, 128, 128, 255]));
+        let palette = vec![[0, 0, 0], [255, 255, 255]];
+
+        for &dithering in &[Dithering::None, Dithering::Ordered, Dithering::FloydSteinberg] {
+            let options = EncoderOptions { dithering: dithering, palette: Some(palette.clone()), ..EncoderOptions::default() };
+            let mut data = Vec::new();
+            Encoder::new_with_options(&mut data, options)
+                .encode_frames(vec![animation::Frame::new(gray.clone())], LoopCount::Infinite)
+                .unwrap();
+
+            {
+                let mut reader = gif::Decoder::new(&data[..]).read_info().unwrap();
+                assert_eq!(reader.global_palette().map(|p| p[..6].to_vec()), Some(vec![0, 0, 0, 255, 255, 255]));
+            }
+
+            // Without dithering the gray becomes a single color, dithering mixes both evenly
+            let white = decode_first_frame(&data).pixels().filter(|p| p.data[0] == 255).count();
+            match dithering {
+                Dithering::None => assert!(white == 0 || white == 256),
+                _ => assert!(white > 96 && white < 160, "{:?}: {}", dithering, white),
+            }
+        }
+
+        // There has to be room for the transparent color
+        let mut transparent = gray.clone();
+        transparent.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+        let options = EncoderOptions { palette: Some(vec![[0, 0, 0]; 256]), ..EncoderOptions::default() };
+        assert!(Encoder::new_with_options(Vec::new(), options)
+            .encode_frames(vec![animation::Frame::new(transparent)], LoopCount::Infinite)
+            .is_err());
+    }
+
+    fn encode_and_composite(frames: &[RgbaImage], optimize: bool) -> (usize, Vec<RgbaImage>) {
+        let options = EncoderOptions { optimize: optimize, ..EncoderOptions::default() };
+        let animation = frames.iter().map(|buffer| animation::Frame::new(buffer.clone()));
+        let mut data = Vec::new();
+        Encoder::new_with_options(&mut data, options).encode_frames(animation, LoopCount::Infinite).unwrap();
+
+        let composited = Decoder::new(&data[..]).into_composited_frames().unwrap()
+            .map(|frame| frame.into_buffer())
+            .collect();
+        (data.len(), composited)
+    }
+
+    #[test]
+    fn optimize_frames() {
+        // A small square moving over a striped background
+        let frames: Vec<_> = (0..8).map(|i| RgbaImage::from_fn(64, 48, |x, y| {
+            if x >= i * 4 && x < i * 4 + 4 && y >= 10 && y < 14 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([(x / 16 * 60) as u8, 100, (y / 12 * 60) as u8, 255])
+            }
+        })).collect();
+
+        let (plain_size, plain) = encode_and_composite(&frames, false);
+        let (optimized_size, optimized) = encode_and_composite(&frames, true);
+        assert!(optimized_size * 2 < plain_size);
+        for (decoded, frame) in plain.iter().chain(&optimized).zip(frames.iter().cycle()) {
+            assert_eq!(decoded.pixels().collect::<Vec<_>>(), frame.pixels().collect::<Vec<_>>());
+        }
+        assert_eq!(optimized.len(), 8);
+
+        // Pixels becoming transparent again need the canvas to be cleared
+        let opaque = RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255]));
+        let mut hole = opaque.clone();
+        hole.put_pixel(3, 3, Rgba([0, 0, 0, 0]));
+        let mut moved = opaque.clone();
+        moved.put_pixel(5, 5, Rgba([0, 255, 0, 255]));
+        let frames = vec![opaque.clone(), opaque, hole, moved];
+        let (_, optimized) = encode_and_composite(&frames, true);
+        assert_eq!(optimized.len(), 4);
+        for (decoded, frame) in optimized.iter().zip(&frames) {
+            for (a, b) in decoded.pixels().zip(frame.pixels()) {
+                assert!(a == b || (a.data[3] == 0 && b.data[3] == 0));
+            }
+        }
+    }
+}
@@ -13,6 +13,8 @@ use image::{
     DecodingResult,
     ImageDecoder,
     ImageError,
+    ImageFormat,
+    ParameterErrorKind,
     ImageResult,
 };
 
@@ -79,7 +81,7 @@ impl<R: BufRead> ImageDecoder for HDRAdapter<R> {
 
             },
             None => {
-                Err(ImageError::ImageEnd)
+                Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
             }
         }
     }
@@ -193,7 +195,8 @@ impl<R: BufRead> HDRDecoder<R> {
                 let mut signature = [0; SIGNATURE_LENGTH];
                 try!(r.read_exact(&mut signature));
                 if signature != SIGNATURE {
-                    return Err(ImageError::FormatError("Radiance HDR signature not found".to_string()));
+                    return Err(ImageError::malformed(ImageFormat::HDR,
+                        "Radiance HDR signature not found".to_string()));
                 } // no else
                 // skip signature line ending
                 try!(read_line_u8(r));
@@ -205,7 +208,7 @@ impl<R: BufRead> HDRDecoder<R> {
             loop {
                 match try!(read_line_u8(r)) {
                     None => { // EOF before end of header
-                        return Err(ImageError::FormatError("EOF in header".into()));
+                        return Err(ImageError::malformed(ImageFormat::HDR, "EOF in header".into()));
                     },
                     Some(line) => {
                         if line.is_empty() {
@@ -227,7 +230,7 @@ impl<R: BufRead> HDRDecoder<R> {
             match try!(read_line_u8(&mut reader)) {
                 None => {
                     // EOF instead of image dimensions
-                    return Err(ImageError::FormatError("EOF in dimensions line".into()));
+                    return Err(ImageError::malformed(ImageFormat::HDR, "EOF in dimensions line".into()));
                 },
                 Some(dimensions) => {
                     let dimensions = String::from_utf8_lossy(&dimensions[..]);
@@ -395,7 +398,7 @@ impl<R: BufRead> Iterator for HDRImageDecoderIterator<R> {
                 self.advance();
                 // Error was encountered. Keep producing errors.
                 // ImageError can't implement Clone, so just dump some error
-                return Some(Err(ImageError::ImageEnd));
+                return Some(Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)));
             } // no else
             if self.col == 0 {
                 // fill scanline buffer
@@ -468,7 +471,8 @@ fn decode_component<R: BufRead, S: FnMut(usize, u8)>(r: &mut R, width: usize, mu
             if rl <= 128 {
                 // sanity check
                 if pos + rl as usize > width {
-                    return Err(ImageError::FormatError("Wrong length of decoded scanline".into()));
+                    return Err(ImageError::malformed(ImageFormat::HDR,
+                        "Wrong length of decoded scanline".into()));
                 }
                 // read values
                 try!(r.read_exact(&mut buf[0..rl as usize]));
@@ -481,7 +485,8 @@ fn decode_component<R: BufRead, S: FnMut(usize, u8)>(r: &mut R, width: usize, mu
                 let rl = rl - 128;
                 // sanity check
                 if pos + rl as usize > width {
-                    return Err(ImageError::FormatError("Wrong length of decoded scanline".into()));
+                    return Err(ImageError::malformed(ImageFormat::HDR,
+                        "Wrong length of decoded scanline".into()));
                 }
                 // fill with same value
                 let value = try!(read_byte(r));
@@ -493,7 +498,7 @@ fn decode_component<R: BufRead, S: FnMut(usize, u8)>(r: &mut R, width: usize, mu
         };
     }
     if pos != width {
-        return Err(ImageError::FormatError("Wrong length of decoded scanline".into()));
+        return Err(ImageError::malformed(ImageFormat::HDR, "Wrong length of decoded scanline".into()));
     }
     Ok(())
 }
@@ -517,7 +522,8 @@ fn decode_old_rle<R: BufRead>(r: &mut R, fb: RGBE8Pixel, buf: &mut [RGBE8Pixel])
     // first pixel in scanline should not be run length marker
     // it is error if it is
     if rl_marker(fb).is_some() {
-        return Err(ImageError::FormatError("First pixel of a scanline shouldn't be run length marker".into()));
+        return Err(ImageError::malformed(ImageFormat::HDR,
+            "First pixel of a scanline shouldn't be run length marker".into()));
     }
     buf[0] = fb; // set first pixel of scanline
 
@@ -538,7 +544,8 @@ fn decode_old_rle<R: BufRead>(r: &mut R, fb: RGBE8Pixel, buf: &mut [RGBE8Pixel])
                         buf[x] = prev_pixel;
                     }
                 } else {
-                    return Err(ImageError::FormatError("Wrong length of decoded scanline".into()));
+                    return Err(ImageError::malformed(ImageFormat::HDR,
+                        "Wrong length of decoded scanline".into()));
                 };
                 rl // value to increase x_off by
             } else {
@@ -550,7 +557,7 @@ fn decode_old_rle<R: BufRead>(r: &mut R, fb: RGBE8Pixel, buf: &mut [RGBE8Pixel])
         };
     }
     if x_off != width {
-        return Err(ImageError::FormatError("Wrong length of decoded scanline".into()));
+        return Err(ImageError::malformed(ImageFormat::HDR, "Wrong length of decoded scanline".into()));
     }
     Ok(())
 }
@@ -621,7 +628,7 @@ impl HDRMetadata {
             Some(("FORMAT", val)) => {
                 if val.trim() != "32-bit_rle_rgbe" {
                     // XYZE isn't supported yet
-                    return Err(ImageError::UnsupportedError(limit_string_len(val, 20)));
+                    return Err(ImageError::unsupported(ImageFormat::HDR, limit_string_len(val, 20)));
                 }
             },
             Some(("EXPOSURE", val)) => {
@@ -631,7 +638,8 @@ impl HDRMetadata {
                     },
                     Err(parse_error) => {
                         if strict {
-                            return Err(ImageError::FormatError(format!("Cannot parse EXPOSURE value: {}", parse_error.description())));
+                            return Err(ImageError::malformed(ImageFormat::HDR,
+                                format!("Cannot parse EXPOSURE value: {}", parse_error.description())));
                         } // no else, skip this line in non-strict mode
                     },
                 };
@@ -643,7 +651,8 @@ impl HDRMetadata {
                     },
                     Err(parse_error) => {
                         if strict {
-                            return Err(ImageError::FormatError(format!("Cannot parse PIXASPECT value: {}", parse_error.description())));
+                            return Err(ImageError::malformed(ImageFormat::HDR,
+                                format!("Cannot parse PIXASPECT value: {}", parse_error.description())));
                         } // no else, skip this line in non-strict mode
                     },
                 };
@@ -653,7 +662,8 @@ impl HDRMetadata {
                 match parse_space_separated_f32(val, &mut rgbcorr, "COLORCORR") {
                     Ok(extra_numbers) => {
                         if strict && extra_numbers {
-                            return Err(ImageError::FormatError("Extra numbers in COLORCORR".into()));
+                            return Err(ImageError::malformed(ImageFormat::HDR,
+                                "Extra numbers in COLORCORR".into()));
                         } // no else, just ignore extra numbers
                         let (rc, gc, bc) = self.color_correction.unwrap_or((1., 1., 1.));
                         self.color_correction = Some((rc*rgbcorr[0], gc*rgbcorr[1], bc*rgbcorr[2]));
@@ -684,12 +694,13 @@ fn parse_space_separated_f32(line: &str, vals: &mut [f32], name: &str) -> ImageR
             match num.parse::<f32>() {
                 Ok(v) => *val = v,
                 Err(err) => {
-                    return Err(ImageError::FormatError(format!("f32 parse error in {}: {}", name, err.description())));
+                    return Err(ImageError::malformed(ImageFormat::HDR,
+                        format!("f32 parse error in {}: {}", name, err.description())));
                 }
             }
         } else {
             // not enough numbers in line
-            return Err(ImageError::FormatError(format!("Not enough numbers in {}", name)));
+            return Err(ImageError::malformed(ImageFormat::HDR, format!("Not enough numbers in {}", name)));
         }
     }
     Ok(nums.next().is_some())
@@ -700,14 +711,14 @@ fn parse_space_separated_f32(line: &str, vals: &mut [f32], name: &str) -> ImageR
 fn parse_dimensions_line<'a>(line: &Cow<'a, str>, strict: bool) -> ImageResult<(u32,u32)> {
     let mut dim_parts = line.split_whitespace();
     let err = "Malformed dimensions line";
-    let c1_tag = try!(dim_parts.next().ok_or(ImageError::FormatError(err.into())));
-    let c1_str = try!(dim_parts.next().ok_or(ImageError::FormatError(err.into())));
-    let c2_tag = try!(dim_parts.next().ok_or(ImageError::FormatError(err.into())));
-    let c2_str = try!(dim_parts.next().ok_or(ImageError::FormatError(err.into())));
+    let c1_tag = try!(dim_parts.next().ok_or(ImageError::malformed(ImageFormat::HDR, err.into())));
+    let c1_str = try!(dim_parts.next().ok_or(ImageError::malformed(ImageFormat::HDR, err.into())));
+    let c2_tag = try!(dim_parts.next().ok_or(ImageError::malformed(ImageFormat::HDR, err.into())));
+    let c2_str = try!(dim_parts.next().ok_or(ImageError::malformed(ImageFormat::HDR, err.into())));
     if strict {
         if dim_parts.next().is_some() {
             // extra data in dimensions line
-            return Err(ImageError::FormatError(err.into()));
+            return Err(ImageError::malformed(ImageFormat::HDR, err.into()));
         } // no else
     } // no else
     // dimensions line is in the form "-Y 10 +X 20"
@@ -721,7 +732,7 @@ fn parse_dimensions_line<'a>(line: &Cow<'a, str>, strict: bool) -> ImageResult<(
             Ok((width, height))
         },
         _ => {
-            Err(ImageError::FormatError(
+            Err(ImageError::malformed(ImageFormat::HDR,
                     format!("Unsupported orientation {} {}",
                         limit_string_len(c1_tag, 4),
                         limit_string_len(c2_tag, 4))))
@@ -735,13 +746,15 @@ trait IntoImageError<T> {
 
 impl<T> IntoImageError<T> for ::std::result::Result<T, ::std::num::ParseFloatError> {
     fn into_image_error(self, description: &str) -> ImageResult<T> {
-        self.map_err(|err| ImageError::FormatError(format!("{} {}", description, err.description())))
+        self.map_err(|err| ImageError::malformed(ImageFormat::HDR,
+            format!("{} {}", description, err.description())))
     }
 }
 
 impl<T> IntoImageError<T> for ::std::result::Result<T, ::std::num::ParseIntError> {
     fn into_image_error(self, description: &str) -> ImageResult<T> {
-        self.map_err(|err| ImageError::FormatError(format!("{} {}", description, err.description())))
+        self.map_err(|err| ImageError::malformed(ImageFormat::HDR,
+            format!("{} {}", description, err.description())))
     }
 }
 
@@ -1,5 +1,7 @@
-use std::io::{Write, Result};
-use color::{Rgb};
+use std::io::{self, Write, Result};
+use byteorder::{BigEndian, ByteOrder};
+use color::{ColorType, Rgb};
+use image::{ImageEncoder, ImageError, ImageFormat, ImageResult};
 use hdr::{SIGNATURE, RGBE8Pixel, rgbe8};
 
 /// Radiance HDR encoder
@@ -17,8 +19,14 @@ impl<W: Write> HDREncoder<W> {
 
     /// Encodes the image ```data```
     /// that has dimensions ```width``` and ```height```
+    ///
+    /// Scanlines between 8 and 32767 pixels wide are run length encoded per component, other
+    /// widths are stored flat.
     pub fn encode(mut self, data: &[Rgb<f32>], width: usize, height: usize) -> Result<()> {
-        assert!(data.len() >= width*height);
+        if data.len() < width * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+        let data = &data[..width * height];
         let w = &mut self.w;
         try!(w.write_all(SIGNATURE));
         try!(w.write_all(b"\n"));
@@ -26,7 +34,8 @@ impl<W: Write> HDREncoder<W> {
         try!(w.write_all(b"FORMAT=32-bit_rle_rgbe\n\n"));
         try!(w.write_all(format!("-Y {} +X {}\n", height, width).as_bytes()));
 
-        if width < 8 || width > 32768 {
+        // The width has to fit in 15 bits of the scanline marker
+        if width < 8 || width > 32767 {
             for &pix in data {
                 try!(write_rgbe8(w, to_rgbe8(pix)));
             }
@@ -73,6 +82,19 @@ impl<W: Write> HDREncoder<W> {
     }
 }
 
+impl<W: Write> ImageEncoder for HDREncoder<W> {
+    /// Encodes an image of color type ```RGBF(32)```.
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        if color != ColorType::RGBF(32) {
+            return Err(ImageError::unsupported_color(ImageFormat::HDR, color))
+        }
+        let pixels: Vec<Rgb<f32>> = buf.chunks(12).filter(|p| p.len() == 12).map(|p| {
+            Rgb([BigEndian::read_f32(&p[..4]), BigEndian::read_f32(&p[4..8]), BigEndian::read_f32(&p[8..])])
+        }).collect();
+        self.encode(&pixels, width as usize, height as usize).map_err(From::from)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 enum RunOrNot {
     Run(u8, usize),
@@ -223,7 +245,14 @@ pub fn to_rgbe8(pix: Rgb<f32>) -> RGBE8Pixel {
         RGBE8Pixel { c: [0, 0, 0], e: 0}
     } else {
         // let (frac, exp) = mx.frexp(); // unstable yet
-        let exp = mx.log2().floor() as i32 + 1;
+        let exp = (mx.log2().floor() as i32).saturating_add(1);
+        if exp < -127 {
+            // Too small for the exponent, which stands for zero if it is 0
+            return RGBE8Pixel { c: [0, 0, 0], e: 0 }
+        } else if exp > 127 {
+            // Too large for the exponent, saturate to the largest value
+            return RGBE8Pixel { c: [255, 255, 255], e: 255 }
+        }
         let mul = f32::powi(2., exp);
         let mut conv = [0u8; 3];
         for (cv, &sv) in conv.iter_mut().zip(pix.iter()) {
@@ -279,6 +308,38 @@ fn to_rgbe8_test() {
     }
 }
 
+#[test]
+fn to_rgbe8_range_test() {
+    use hdr::rgbe8;
+    assert_eq!(to_rgbe8(Rgb([1e-40, 0., 0.])), rgbe8(0, 0, 0, 0));
+    assert_eq!(to_rgbe8(Rgb([::std::f32::INFINITY, 1., 0.])), rgbe8(255, 255, 255, 255));
+    assert_eq!(to_rgbe8(Rgb([::std::f32::MAX, 0., 0.])), rgbe8(255, 255, 255, 255));
+}
+
+#[test]
+fn roundtrip_test() {
+    use hdr::HDRDecoder;
+    use std::io::Cursor;
+
+    // Flat scanlines, run length encoded ones and ones too wide for the scanline marker
+    for &(width, height) in &[(5, 3), (200, 4), (32768, 1)] {
+        let data: Vec<_> = (0..width * height).map(|i| {
+            let v = if i % 20 < 10 { 1.5 } else { (i % 7) as f32 * 300. };
+            Rgb([v, 0.25, i as f32 / 1000.])
+        }).collect();
+        let mut encoded = Vec::new();
+        HDREncoder::new(&mut encoded).encode(&data, width, height).unwrap();
+
+        let decoder = HDRDecoder::new(Cursor::new(encoded)).unwrap();
+        let meta = decoder.metadata();
+        assert_eq!((meta.width as usize, meta.height as usize), (width, height));
+        let decoded = decoder.read_image_native().unwrap();
+        assert!(decoded.iter().zip(&data).all(|(&a, &b)| a == to_rgbe8(b)));
+    }
+
+    assert!(HDREncoder::new(Vec::new()).encode(&[Rgb([0., 0., 0.])], 2, 1).is_err());
+}
+
 #[test]
 fn runiterator_test() {
     let data = [];
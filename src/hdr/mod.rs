@@ -1,6 +1,6 @@
-//!  Decoding of Radiance HDR Images
+//!  Decoding and Encoding of Radiance HDR Images
 //!
-//!  A decoder for Radiance HDR images
+//!  A decoder and encoder for Radiance HDR images
 //!
 //!  # Related Links
 //!
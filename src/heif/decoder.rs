@@ -0,0 +1,195 @@
+use std::io::Read;
+use byteorder::{BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+use metadata::exif::Exif;
+
+use super::libheif::Context;
+
+/// The representation of a HEIF decoder
+///
+/// The whole file is read when the decoder is created, and the image is decoded on the first
+/// request for its samples.
+pub struct HEIFDecoder {
+    context: Context,
+    eight_bit: bool,
+    samples: Option<Vec<u16>>,
+    /// The next row to be returned
+    row: u32,
+}
+
+impl HEIFDecoder {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new<R: Read>(mut r: R) -> ImageResult<HEIFDecoder> {
+        let mut data = Vec::new();
+        try!(r.read_to_end(&mut data));
+        Ok(HEIFDecoder {
+            context: try!(Context::new(&data)),
+            eight_bit: false,
+            samples: None,
+            row: 0,
+        })
+    }
+
+    /// Sets whether images with more than 8 bits per channel are decoded to 8-bit samples
+    ///
+    /// By default they are scaled to 16 bits.
+    pub fn set_8bit_output(&mut self, eight_bit: bool) {
+        self.eight_bit = eight_bit;
+    }
+
+    /// Returns the EXIF data of the image, starting with its TIFF header
+    pub fn exif(&self) -> ImageResult<Option<Vec<u8>>> {
+        let exif = match try!(self.context.exif()) {
+            Some(exif) => exif,
+            None => return Ok(None),
+        };
+        // The offset of the TIFF header precedes the data
+        let start = if exif.len() >= 4 { BigEndian::read_u32(&exif) as usize + 4 } else { exif.len() };
+        if start > exif.len() {
+            return Err(ImageError::malformed(ImageFormat::HEIF, "Invalid HEIF EXIF offset".to_string()))
+        }
+        Ok(Some(exif[start..].to_vec()))
+    }
+
+    /// Returns the orientation stored in the EXIF data of the image.
+    ///
+    /// The values are those of the EXIF orientation tag. As HEIF files store the same
+    /// transformation in their container, the decoded image is already upright and the
+    /// orientation must not be applied again. Images without EXIF orientation return `None`.
+    pub fn orientation(&self) -> ImageResult<Option<u16>> {
+        Ok(try!(self.exif()).and_then(|exif| Exif::parse(&exif).ok()).and_then(|exif| exif.orientation()))
+    }
+
+    fn bits(&self) -> u8 {
+        if self.context.bit_depth() > 8 && !self.eight_bit { 16 } else { 8 }
+    }
+
+    fn decode(&mut self) -> ImageResult<&[u16]> {
+        if self.samples.is_none() {
+            let wide = self.bits() == 16;
+            let mut samples = try!(self.context.decode(wide));
+            if wide {
+                let max = (1u32 << self.context.bit_depth()) - 1;
+                for sample in &mut samples {
+                    *sample = ((*sample as u32 * 65535 + max / 2) / max) as u16;
+                }
+            }
+            self.samples = Some(samples);
+        }
+        Ok(self.samples.as_ref().unwrap())
+    }
+}
+
+impl ImageDecoder for HEIFDecoder {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok(self.context.dimensions())
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        let bits = self.bits();
+        Ok(if self.context.has_alpha() { ColorType::RGBA(bits) } else { ColorType::RGB(bits) })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let (width, _) = try!(self.dimensions());
+        let bits = ::color::bits_per_pixel(try!(self.colortype()));
+        Ok(width as usize * bits / 8)
+    }
+
+    /// Reads the next row into ```buf```, with 16-bit samples in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let (_, height) = try!(self.dimensions());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let row = self.row as usize;
+        let bytes = self.bits() as usize / 8;
+        let row_samples = len / bytes;
+        let samples = &try!(self.decode())[row * row_samples..(row + 1) * row_samples];
+        if bytes == 1 {
+            for (out, &v) in buf.iter_mut().zip(samples) {
+                *out = v as u8;
+            }
+        } else {
+            for (out, &v) in buf.chunks_mut(2).zip(samples) {
+                BigEndian::write_u16(out, v);
+            }
+        }
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let (_, height) = try!(self.dimensions());
+        let bits = self.bits();
+        let start = self.row as usize * try!(self.row_len()) / (bits as usize / 8);
+        let result = {
+            let samples = &try!(self.decode())[start..];
+            if bits == 8 {
+                DecodingResult::U8(samples.iter().map(|&v| v as u8).collect())
+            } else {
+                DecodingResult::U16(samples.to_vec())
+            }
+        };
+        self.row = height;
+        Ok(result)
+    }
+
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        self.context.icc_profile()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::HEIFDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn open(name: &str) -> HEIFDecoder {
+        HEIFDecoder::new(File::open(format!("tests/images/heif/{}.heic", name)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rgb() {
+        let mut decoder = open("rgb8");
+        assert_eq!(decoder.dimensions().unwrap(), (20, 14));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(decoder.orientation().unwrap(), Some(6));
+        assert!(decoder.exif().unwrap().unwrap().starts_with(b"MM\0*"));
+        let mut row = [0; 60];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(&row[..6], &[0, 2, 121, 3, 5, 124]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data.len(), 13 * 60),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn alpha() {
+        let mut decoder = open("rgba8");
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        assert_eq!(decoder.orientation().unwrap(), None);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => for (i, pixel) in data.chunks(4).enumerate() {
+                assert!((pixel[3] as i32 - if i % 20 < 10 { 255 } else { 60 }).abs() <= 2);
+            },
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(HEIFDecoder::new(&b"\0\0\0\x10ftypheic\0\0\0\0"[..]).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Bindings to the system's libheif library
+
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+/// The colorspace and the interleaved chromas that images are decoded to
+const COLORSPACE_RGB: c_int = 1;
+const CHROMA_RGB: c_int = 10;
+const CHROMA_RGBA: c_int = 11;
+const CHROMA_RRGGBB_BE: c_int = 12;
+const CHROMA_RRGGBBAA_BE: c_int = 13;
+const CHANNEL_INTERLEAVED: c_int = 10;
+
+#[repr(C)]
+struct Error {
+    code: c_int,
+    subcode: c_int,
+    message: *const c_char,
+}
+
+#[link(name = "heif")]
+extern "C" {
+    fn heif_context_alloc() -> *mut c_void;
+    fn heif_context_free(context: *mut c_void);
+    fn heif_context_read_from_memory(context: *mut c_void, mem: *const c_void, size: usize,
+                                     options: *const c_void) -> Error;
+    fn heif_context_get_primary_image_handle(context: *mut c_void, handle: *mut *mut c_void) -> Error;
+    fn heif_image_handle_release(handle: *mut c_void);
+    fn heif_image_handle_get_width(handle: *const c_void) -> c_int;
+    fn heif_image_handle_get_height(handle: *const c_void) -> c_int;
+    fn heif_image_handle_has_alpha_channel(handle: *const c_void) -> c_int;
+    fn heif_image_handle_get_luma_bits_per_pixel(handle: *const c_void) -> c_int;
+    fn heif_image_handle_get_number_of_metadata_blocks(handle: *const c_void, type_filter: *const c_char) -> c_int;
+    fn heif_image_handle_get_list_of_metadata_block_IDs(handle: *const c_void, type_filter: *const c_char,
+                                                        ids: *mut u32, count: c_int) -> c_int;
+    fn heif_image_handle_get_metadata_size(handle: *const c_void, id: u32) -> usize;
+    fn heif_image_handle_get_metadata(handle: *const c_void, id: u32, out: *mut c_void) -> Error;
+    fn heif_image_handle_get_raw_color_profile_size(handle: *const c_void) -> usize;
+    fn heif_image_handle_get_raw_color_profile(handle: *const c_void, out: *mut c_void) -> Error;
+    fn heif_decode_image(handle: *const c_void, image: *mut *mut c_void, colorspace: c_int, chroma: c_int,
+                         options: *const c_void) -> Error;
+    fn heif_image_release(image: *mut c_void);
+    fn heif_image_get_plane_readonly(image: *const c_void, channel: c_int, stride: *mut c_int) -> *const u8;
+}
+
+fn check(error: Error) -> ImageResult<()> {
+    if error.code == 0 {
+        return Ok(())
+    }
+    let message = if error.message.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(error.message) }.to_string_lossy().into_owned()
+    };
+    Err(ImageError::malformed(ImageFormat::HEIF, format!("HEIF decoding failed: {}", message)))
+}
+
+/// A file and its primary image
+pub struct Context {
+    context: *mut c_void,
+    handle: *mut c_void,
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.handle.is_null() {
+                heif_image_handle_release(self.handle);
+            }
+            heif_context_free(self.context);
+        }
+    }
+}
+
+impl Context {
+    /// Parses the file ```data```
+    pub fn new(data: &[u8]) -> ImageResult<Context> {
+        unsafe {
+            let context = heif_context_alloc();
+            if context.is_null() {
+                return Err(ImageError::malformed(ImageFormat::HEIF,
+                    "Could not create a HEIF context".to_string()))
+            }
+            let mut result = Context { context: context, handle: ptr::null_mut() };
+            try!(check(heif_context_read_from_memory(context, data.as_ptr() as *const c_void, data.len(),
+                                                     ptr::null())));
+            try!(check(heif_context_get_primary_image_handle(context, &mut result.handle)));
+            Ok(result)
+        }
+    }
+
+    /// Returns the dimensions of the primary image, after its transformations
+    pub fn dimensions(&self) -> (u32, u32) {
+        unsafe {
+            (heif_image_handle_get_width(self.handle) as u32, heif_image_handle_get_height(self.handle) as u32)
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        unsafe { heif_image_handle_has_alpha_channel(self.handle) != 0 }
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        unsafe { heif_image_handle_get_luma_bits_per_pixel(self.handle) as u8 }
+    }
+
+    /// Returns the first EXIF block of the primary image
+    pub fn exif(&self) -> ImageResult<Option<Vec<u8>>> {
+        unsafe {
+            let filter = b"Exif\0".as_ptr() as *const c_char;
+            if heif_image_handle_get_number_of_metadata_blocks(self.handle, filter) < 1 {
+                return Ok(None)
+            }
+            let mut id = 0;
+            heif_image_handle_get_list_of_metadata_block_IDs(self.handle, filter, &mut id, 1);
+            let mut data = vec![0u8; heif_image_handle_get_metadata_size(self.handle, id)];
+            try!(check(heif_image_handle_get_metadata(self.handle, id, data.as_mut_ptr() as *mut c_void)));
+            Ok(Some(data))
+        }
+    }
+
+    pub fn icc_profile(&self) -> ImageResult<Option<Vec<u8>>> {
+        unsafe {
+            let size = heif_image_handle_get_raw_color_profile_size(self.handle);
+            if size == 0 {
+                return Ok(None)
+            }
+            let mut data = vec![0u8; size];
+            try!(check(heif_image_handle_get_raw_color_profile(self.handle, data.as_mut_ptr() as *mut c_void)));
+            Ok(Some(data))
+        }
+    }
+
+    /// Decodes the primary image to interleaved rgb or rgba samples, which have 16 bits if
+    /// ```wide``` is true, and 8 bits otherwise
+    ///
+    /// The samples of wide images keep the bit depth of the image.
+    pub fn decode(&self, wide: bool) -> ImageResult<Vec<u16>> {
+        let alpha = self.has_alpha();
+        let chroma = match (wide, alpha) {
+            (false, false) => CHROMA_RGB,
+            (false, true) => CHROMA_RGBA,
+            (true, false) => CHROMA_RRGGBB_BE,
+            (true, true) => CHROMA_RRGGBBAA_BE,
+        };
+        let (width, height) = self.dimensions();
+        let row_samples = width as usize * if alpha { 4 } else { 3 };
+
+        unsafe {
+            let mut image = ptr::null_mut();
+            try!(check(heif_decode_image(self.handle, &mut image, COLORSPACE_RGB, chroma, ptr::null())));
+            let mut stride = 0;
+            let plane = heif_image_get_plane_readonly(image, CHANNEL_INTERLEAVED, &mut stride);
+            if plane.is_null() {
+                heif_image_release(image);
+                return Err(ImageError::malformed(ImageFormat::HEIF, "HEIF image without samples".to_string()))
+            }
+
+            let mut samples = Vec::with_capacity(row_samples * height as usize);
+            for y in 0..height as usize {
+                let row = plane.offset(y as isize * stride as isize);
+                if wide {
+                    let row = slice::from_raw_parts(row, row_samples * 2);
+                    samples.extend(row.chunks(2).map(|s| (s[0] as u16) << 8 | s[1] as u16));
+                } else {
+                    samples.extend(slice::from_raw_parts(row, row_samples).iter().map(|&s| s as u16));
+                }
+            }
+            heif_image_release(image);
+            Ok(samples)
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Decoding of HEIF Images
+//!
+//! HEIF stores HEVC coded images in an ISO base media file, which is the format of the HEIC
+//! photos taken by phones. The images are decoded by the system's libheif library, which has
+//! to be installed to use the ```heif``` feature.
+//!
+//! The primary image of a file is decoded, including its alpha plane and grids of tiles.
+//! Its rotation and mirroring are applied, which is how HEIF files store the orientation of
+//! photos.
+//!
+//! # Related Links
+//! * <https://github.com/strukturag/libheif> - The libheif library
+//! * <https://nokiatech.github.io/heif/technical.html> - An overview of the HEIF format
+//!
+
+pub use self::decoder::HEIFDecoder;
+
+mod decoder;
+mod libheif;
@@ -1,8 +1,9 @@
 use byteorder::{ReadBytesExt, LittleEndian};
+use std::cmp::Reverse;
 use std::io::{Read, Seek, SeekFrom};
 
 use color::ColorType;
-use image::{DecodingResult, ImageResult, ImageDecoder, ImageError};
+use image::{DecodingResult, ImageResult, ImageDecoder, ImageError, ImageFormat, ParameterErrorKind};
 
 use bmp::BMPDecoder;
 use png::PNGDecoder;
@@ -12,10 +13,27 @@ use self::InnerDecoder::*;
 // The first eight bytes of a PNG file always contain the following (decimal) values:
 const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-/// An ico decoder
+// Enum value indicating a CUR image (as opposed to an ICO image):
+const CUR_IMAGE_TYPE: u16 = 2;
+
+/// An ico decoder, which also decodes cursors stored in CUR files
 pub struct ICODecoder<R: Read> {
+    entries: Vec<DirEntry>,
+    selected_index: usize,
     selected_entry: DirEntry,
     inner_decoder: InnerDecoder<R>,
+    is_cursor: bool,
+}
+
+/// The properties of one of the images stored in an ICO file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ICOEntry {
+    /// The width of the image
+    pub width: u32,
+    /// The height of the image
+    pub height: u32,
+    /// The number of bits per pixel, which is 0 if unspecified and for cursors
+    pub bits_per_pixel: u16,
 }
 
 enum InnerDecoder<R: Read> {
@@ -39,24 +57,86 @@ struct DirEntry {
 }
 
 impl<R: Read + Seek> ICODecoder<R> {
-    /// Create a new decoder that decodes from the stream ```r```
-    pub fn new(mut r: R) -> ImageResult<ICODecoder<R>> {
-        let entries = try!(read_entries(&mut r));
-        let entry = try!(best_entry(entries));
+    /// Create a new decoder that decodes from the stream ```r```, selecting the image with the
+    /// highest color depth and size
+    pub fn new(r: R) -> ImageResult<ICODecoder<R>> {
+        ICODecoder::new_with_selection(r, best_entry)
+    }
+
+    /// Create a new decoder that decodes the image at ```index``` in the directory of the
+    /// stream ```r```, as listed by ```entries```
+    pub fn new_with_entry(r: R, index: usize) -> ImageResult<ICODecoder<R>> {
+        ICODecoder::new_with_selection(r, |entries, _| {
+            if index < entries.len() {
+                Ok(index)
+            } else {
+                Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+            }
+        })
+    }
+
+    /// Create a new decoder that decodes the image of the stream ```r``` closest to the
+    /// dimensions ```width``` and ```height```
+    ///
+    /// The smallest image at least as large as requested is selected, or the largest image if
+    /// they are all smaller, preferring the highest color depth among images of the same size.
+    pub fn new_with_size(r: R, width: u32, height: u32) -> ImageResult<ICODecoder<R>> {
+        ICODecoder::new_with_selection(r, |entries, is_cursor| {
+            closest_entry(entries, is_cursor, width, height)
+        })
+    }
+
+    fn new_with_selection<F>(mut r: R, select: F) -> ImageResult<ICODecoder<R>>
+        where F: FnOnce(&[DirEntry], bool) -> ImageResult<usize> {
+        // The offsets of the images are relative to the start of the stream, which is where
+        // the directory is as well.
+        try!(r.seek(SeekFrom::Start(0)));
+        let (is_cursor, entries) = try!(read_entries(&mut r));
+        let index = try!(select(&entries, is_cursor));
+        let entry = entries[index];
         let decoder = try!(entry.decoder(r));
 
         Ok(ICODecoder {
+            entries: entries,
+            selected_index: index,
             selected_entry: entry,
             inner_decoder: decoder,
+            is_cursor: is_cursor,
         })
     }
+
+    /// Returns the images stored in the file, in the order of its directory
+    pub fn entries(&self) -> Vec<ICOEntry> {
+        self.entries.iter().map(|entry| ICOEntry {
+            width: entry.real_width().into(),
+            height: entry.real_height().into(),
+            bits_per_pixel: entry.depth(self.is_cursor),
+        }).collect()
+    }
+
+    /// Returns the index of the image that is decoded
+    pub fn selected_entry(&self) -> usize {
+        self.selected_index
+    }
+
+    /// Returns the hotspot of a cursor, the coordinates of the pixel marking the position the
+    /// cursor points at, or ```None``` if the file is an icon
+    pub fn hotspot(&self) -> Option<(u16, u16)> {
+        if self.is_cursor {
+            Some((self.selected_entry.num_color_planes, self.selected_entry.bits_per_pixel))
+        } else {
+            None
+        }
+    }
 }
 
-fn read_entries<R: Read>(r: &mut R) -> ImageResult<Vec<DirEntry>> {
+/// Read the directory, returning whether the file is a cursor and its entries
+fn read_entries<R: Read>(r: &mut R) -> ImageResult<(bool, Vec<DirEntry>)> {
     let _reserved = try!(r.read_u16::<LittleEndian>());
-    let _type = try!(r.read_u16::<LittleEndian>());
+    let image_type = try!(r.read_u16::<LittleEndian>());
     let count = try!(r.read_u16::<LittleEndian>());
-    (0..count).map(|_| read_entry(r)).collect()
+    let entries = try!((0..count).map(|_| read_entry(r)).collect());
+    Ok((image_type == CUR_IMAGE_TYPE, entries))
 }
 
 fn read_entry<R: Read>(r: &mut R) -> ImageResult<DirEntry> {
@@ -72,7 +152,7 @@ fn read_entry<R: Read>(r: &mut R) -> ImageResult<DirEntry> {
     // of the hotspot for CUR files.
     entry.num_color_planes = try!(r.read_u16::<LittleEndian>());
     if entry.num_color_planes > 256 {
-        return Err(ImageError::FormatError(
+        return Err(ImageError::malformed(ImageFormat::ICO,
             "ICO image entry has a too large color planes/hotspot value".to_string()
         ));
     }
@@ -81,7 +161,7 @@ fn read_entry<R: Read>(r: &mut R) -> ImageResult<DirEntry> {
     // or the vertical coordinate of the hotspot for CUR files.
     entry.bits_per_pixel = try!(r.read_u16::<LittleEndian>());
     if entry.bits_per_pixel > 256 {
-        return Err(ImageError::FormatError(
+        return Err(ImageError::malformed(ImageFormat::ICO,
             "ICO image entry has a too large bits per pixel/hotspot value".to_string()
         ));
     }
@@ -93,22 +173,36 @@ fn read_entry<R: Read>(r: &mut R) -> ImageResult<DirEntry> {
 }
 
 /// Find the entry with the highest (color depth, size).
-fn best_entry(mut entries: Vec<DirEntry>) -> ImageResult<DirEntry> {
-    let mut best = try!(entries.pop().ok_or(ImageError::ImageEnd));
-    let mut best_score = (best.bits_per_pixel, best.real_width() as u32 * best.real_height() as u32);
-
-    for entry in entries {
-        let score = (entry.bits_per_pixel, entry.real_width() as u32 * entry.real_height() as u32);
-        if score > best_score {
-            best = entry;
-            best_score = score;
-        }
-    }
-    Ok(best)
+fn best_entry(entries: &[DirEntry], is_cursor: bool) -> ImageResult<usize> {
+    entries.iter().enumerate()
+        .max_by_key(|&(_, entry)| (entry.depth(is_cursor), entry.area()))
+        .map(|(i, _)| i)
+        .ok_or(ImageError::Parameter(ParameterErrorKind::NoMoreData))
 }
 
+/// Find the smallest entry covering ```width``` and ```height```, or the largest if none does.
+fn closest_entry(entries: &[DirEntry], is_cursor: bool, width: u32, height: u32) -> ImageResult<usize> {
+    entries.iter().enumerate()
+        .min_by_key(|&(_, entry)| {
+            let covers = u32::from(entry.real_width()) >= width &&
+                u32::from(entry.real_height()) >= height;
+            let area = if covers { entry.area() } else { u32::max_value() - entry.area() };
+            (!covers, area, Reverse(entry.depth(is_cursor)))
+        })
+        .map(|(i, _)| i)
+        .ok_or(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+}
 
 impl DirEntry {
+    /// The color depth, which the entries of cursors hold the hotspot in place of
+    fn depth(&self, is_cursor: bool) -> u16 {
+        if is_cursor { 0 } else { self.bits_per_pixel }
+    }
+
+    fn area(&self) -> u32 {
+        u32::from(self.real_width()) * u32::from(self.real_height())
+    }
+
     fn real_width(&self) -> u16 {
         match self.width {
             0 => 256,
@@ -190,7 +284,7 @@ impl<R: Read + Seek> ImageDecoder for ICODecoder<R> {
         match self.inner_decoder {
             PNG(ref mut decoder) => {
                 if self.selected_entry.image_length < PNG_SIGNATURE.len() as u32 {
-                    return Err(ImageError::FormatError(
+                    return Err(ImageError::malformed(ImageFormat::ICO,
                         "Entry specified a length that is shorter than PNG header!".to_string()
                     ));
                 }
@@ -198,7 +292,7 @@ impl<R: Read + Seek> ImageDecoder for ICODecoder<R> {
                 // Check if the image dimensions match the ones in the image data.
                 let (width, height) = try!(decoder.dimensions());
                 if !self.selected_entry.matches_dimensions(width, height) {
-                    return Err(ImageError::FormatError(
+                    return Err(ImageError::malformed(ImageFormat::ICO,
                         "Entry and PNG dimensions do not match!".to_string())
                     );
 
@@ -208,7 +302,7 @@ impl<R: Read + Seek> ImageDecoder for ICODecoder<R> {
                 // https://blogs.msdn.microsoft.com/oldnewthing/20101022-00/?p=12473/
                 let color_type = try!(decoder.colortype());
                 if let ColorType::RGBA(8) = color_type {} else {
-                    return Err(ImageError::FormatError(
+                    return Err(ImageError::malformed(ImageFormat::ICO,
                         "The PNG is not in RGBA format!".to_string()
                     ));
                 }
@@ -218,14 +312,15 @@ impl<R: Read + Seek> ImageDecoder for ICODecoder<R> {
             BMP(ref mut decoder) => {
                 let (width, height) = try!(decoder.dimensions());
                 if !self.selected_entry.matches_dimensions(width, height) {
-                    return Err(ImageError::FormatError(
+                    return Err(ImageError::malformed(ImageFormat::ICO,
                         "Entry({:?}) and BMP({:?}) dimensions do not match!".to_string()
                     ));
                 }
 
                 // The ICO decoder needs an alpha channel to apply the AND mask.
                 if try!(decoder.colortype()) != ColorType::RGBA(8) {
-                    return Err(ImageError::UnsupportedError("Unsupported color type".to_string()))
+                    return Err(ImageError::unsupported(ImageFormat::ICO,
+                        "Unsupported color type".to_string()))
                 }
 
                 let mut pixel_data = match try!(decoder.read_image()) {
@@ -244,7 +339,7 @@ impl<R: Read + Seek> ImageDecoder for ICODecoder<R> {
                     let mask_row_bytes = ((width + 31) / 32) * 4;
                     let expected_length = (mask_row_bytes * height) as u64;
                     if mask_length < expected_length {
-                        return Err(ImageError::ImageEnd)
+                        return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
                     }
 
                     for y in 0..height {
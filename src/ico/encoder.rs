@@ -2,29 +2,43 @@ use byteorder::{WriteBytesExt, LittleEndian};
 use std::io::{self, Write};
 
 use color::{ColorType, bits_per_pixel};
+use dynimage::DynamicImage;
+use image::{ImageEncoder, ImageResult};
 
 use png::PNGEncoder;
 
 // Enum value indicating an ICO image (as opposed to a CUR image):
 const ICO_IMAGE_TYPE: u16 = 1;
+// Enum value indicating a CUR image:
+const CUR_IMAGE_TYPE: u16 = 2;
 // The length of an ICO file ICONDIR structure, in bytes:
 const ICO_ICONDIR_SIZE: u32 = 6;
 // The length of an ICO file DIRENTRY structure, in bytes:
 const ICO_DIRENTRY_SIZE: u32 = 16;
+// The length of the BITMAPINFOHEADER of bitmap entries, in bytes:
+const BMP_INFOHEADER_SIZE: u32 = 40;
 
-/// ICO encoder
+/// ICO encoder, which also encodes cursors as CUR files
 pub struct ICOEncoder<W: Write> {
-    w: W
+    w: W,
+    hotspot: Option<(u16, u16)>,
 }
 
 impl<W: Write> ICOEncoder<W> {
     /// Create a new encoder that writes its output to ```w```.
     pub fn new(w: W) -> ICOEncoder<W> {
         ICOEncoder {
-            w: w
+            w: w,
+            hotspot: None,
         }
     }
 
+    /// Write a cursor instead of an icon, whose hotspot, the pixel marking the position the
+    /// cursor points at, is at ```x``` and ```y```. All images share the same hotspot.
+    pub fn set_hotspot(&mut self, x: u16, y: u16) {
+        self.hotspot = Some((x, y));
+    }
+
     /// Encodes the image ```image``` that has dimensions ```width``` and
     /// ```height``` and ```ColorType``` ```c```.  The dimensions of the image
     /// must be between 1 and 256 (inclusive) or an error will be returned.
@@ -34,28 +48,123 @@ impl<W: Write> ICOEncoder<W> {
         try!(PNGEncoder::new(&mut image_data).encode(
             data, width, height, color));
 
-        try!(write_icondir(&mut self.w, 1));
-        try!(write_direntry(&mut self.w, width, height, color,
+        try!(write_icondir(&mut self.w, 1, self.hotspot));
+        try!(write_direntry(&mut self.w, width, height, color, self.hotspot,
                             ICO_ICONDIR_SIZE + ICO_DIRENTRY_SIZE,
                             image_data.len() as u32));
         try!(self.w.write_all(&image_data));
         Ok(())
     }
+
+    /// Encodes the ```images``` as the entries of a single icon, the dimensions of each image
+    /// must be between 1 and 256 (inclusive) or an error will be returned.
+    ///
+    /// Images that are 256 pixels wide or high are stored as PNG, smaller ones as bitmaps which
+    /// older programs are able to read.
+    pub fn encode_images(mut self, images: &[DynamicImage]) -> io::Result<()> {
+        if images.is_empty() || images.len() > u16::max_value() as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "An ICO file holds between 1 and 65535 images"));
+        }
+
+        let mut entries = Vec::with_capacity(images.len());
+        for image in images {
+            let rgba = image.to_rgba();
+            let (width, height) = rgba.dimensions();
+            if width < 1 || width > 256 || height < 1 || height > 256 {
+                return Err(invalid_dimensions());
+            }
+
+            let mut image_data: Vec<u8> = Vec::new();
+            if width == 256 || height == 256 {
+                try!(PNGEncoder::new(&mut image_data).encode(
+                    &rgba, width, height, ColorType::RGBA(8)));
+            } else {
+                try!(write_bmp_image(&mut image_data, &rgba, width, height));
+            }
+            entries.push((width, height, image_data));
+        }
+
+        try!(write_icondir(&mut self.w, entries.len() as u16, self.hotspot));
+        let mut data_start = ICO_ICONDIR_SIZE + ICO_DIRENTRY_SIZE * entries.len() as u32;
+        for &(width, height, ref image_data) in &entries {
+            try!(write_direntry(&mut self.w, width, height, ColorType::RGBA(8), self.hotspot,
+                                data_start, image_data.len() as u32));
+            data_start += image_data.len() as u32;
+        }
+        for &(_, _, ref image_data) in &entries {
+            try!(self.w.write_all(image_data));
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for ICOEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Write the rgba ```data``` as a 32-bit bitmap without file header, followed by the AND mask
+/// marking its transparent pixels for programs that ignore the alpha channel.
+fn write_bmp_image<W: Write>(w: &mut W, data: &[u8], width: u32, height: u32) -> io::Result<()> {
+    // A mask row contains 1 bit per pixel, padded to 4 bytes.
+    let mask_row_bytes = (width + 31) / 32 * 4;
+    let row_bytes = (width * 4) as usize;
+
+    // BITMAPINFOHEADER, whose height covers both the image and the mask:
+    try!(w.write_u32::<LittleEndian>(BMP_INFOHEADER_SIZE));
+    try!(w.write_i32::<LittleEndian>(width as i32));
+    try!(w.write_i32::<LittleEndian>(2 * height as i32));
+    // Color planes:
+    try!(w.write_u16::<LittleEndian>(1));
+    // Bits per pixel:
+    try!(w.write_u16::<LittleEndian>(32));
+    // Compression method, uncompressed:
+    try!(w.write_u32::<LittleEndian>(0));
+    // Image data size, in bytes:
+    try!(w.write_u32::<LittleEndian>((width * 4 + mask_row_bytes) * height));
+    // Resolution and palette, all unused:
+    for _ in 0..4 {
+        try!(w.write_u32::<LittleEndian>(0));
+    }
+
+    // The rows are stored from the bottom up, with the pixels in BGRA order.
+    for row in data.chunks(row_bytes).rev() {
+        for pixel in row.chunks(4) {
+            try!(w.write_all(&[pixel[2], pixel[1], pixel[0], pixel[3]]));
+        }
+    }
+
+    let mut mask = vec![0; mask_row_bytes as usize];
+    for row in data.chunks(row_bytes).rev() {
+        for b in mask.iter_mut() {
+            *b = 0;
+        }
+        for (x, pixel) in row.chunks(4).enumerate() {
+            if pixel[3] == 0 {
+                mask[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        try!(w.write_all(&mask));
+    }
+    Ok(())
 }
 
-fn write_icondir<W: Write>(w: &mut W, num_images: u16) -> io::Result<()> {
+fn write_icondir<W: Write>(w: &mut W, num_images: u16,
+                           hotspot: Option<(u16, u16)>) -> io::Result<()> {
     // Reserved field (must be zero):
     try!(w.write_u16::<LittleEndian>(0));
     // Image type (ICO or CUR):
-    try!(w.write_u16::<LittleEndian>(ICO_IMAGE_TYPE));
+    try!(w.write_u16::<LittleEndian>(if hotspot.is_some() { CUR_IMAGE_TYPE } else { ICO_IMAGE_TYPE }));
     // Number of images in the file:
     try!(w.write_u16::<LittleEndian>(num_images));
     Ok(())
 }
 
 fn write_direntry<W: Write>(w: &mut W, width: u32, height: u32,
-                            color: ColorType, data_start: u32,
-                            data_size: u32) -> io::Result<()> {
+                            color: ColorType, hotspot: Option<(u16, u16)>,
+                            data_start: u32, data_size: u32) -> io::Result<()> {
     // Image dimensions:
     try!(write_width_or_height(w, width));
     try!(write_width_or_height(w, height));
@@ -63,10 +172,19 @@ fn write_direntry<W: Write>(w: &mut W, width: u32, height: u32,
     try!(w.write_u8(0));
     // Reserved field (must be zero):
     try!(w.write_u8(0));
-    // Color planes:
-    try!(w.write_u16::<LittleEndian>(0));
-    // Bits per pixel:
-    try!(w.write_u16::<LittleEndian>(bits_per_pixel(color) as u16));
+    match hotspot {
+        Some((x, y)) => {
+            // Hotspot coordinates of cursors:
+            try!(w.write_u16::<LittleEndian>(x));
+            try!(w.write_u16::<LittleEndian>(y));
+        }
+        None => {
+            // Color planes:
+            try!(w.write_u16::<LittleEndian>(0));
+            // Bits per pixel:
+            try!(w.write_u16::<LittleEndian>(bits_per_pixel(color) as u16));
+        }
+    }
     // Image data size, in bytes:
     try!(w.write_u32::<LittleEndian>(data_size));
     // Image data offset, in bytes:
@@ -77,9 +195,129 @@ fn write_direntry<W: Write>(w: &mut W, width: u32, height: u32,
 /// Encode a width/height value as a single byte, where 0 means 256.
 fn write_width_or_height<W: Write>(w: &mut W, value: u32) -> io::Result<()> {
     if value < 1 || value > 256 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData,
-                                  "Invalid ICO dimensions (width and \
-                                   height must be between 1 and 256)"));
+        return Err(invalid_dimensions());
     }
     w.write_u8(if value < 256 { value as u8 } else { 0 })
 }
+
+fn invalid_dimensions() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   "Invalid ICO dimensions (width and \
+                    height must be between 1 and 256)")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::ICOEncoder;
+    use super::super::{ICODecoder, ICOEntry};
+    use buffer::{ImageBuffer, RgbaImage};
+    use color::{ColorType, Rgba};
+    use dynimage::DynamicImage;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn gradient(size: u32) -> RgbaImage {
+        ImageBuffer::from_fn(size, size, |x, y| {
+            // The first column is transparent to exercise the AND mask of bitmaps
+            let alpha = if x == 0 { 0 } else { (x * 7 + y) as u8 };
+            Rgba([(x * 3) as u8, (y * 5) as u8, (x + y) as u8, alpha])
+        })
+    }
+
+    fn decode(data: Vec<u8>) -> (u32, u32, Vec<u8>) {
+        let mut decoder = ICODecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (width, height, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn multiple_sizes() {
+        let images: Vec<_> = [16, 32, 256].iter()
+            .map(|&size| DynamicImage::ImageRgba8(gradient(size)))
+            .collect();
+        let mut encoded = Vec::new();
+        ICOEncoder::new(&mut encoded).encode_images(&images).unwrap();
+
+        // The directory lists each image, of which only the largest is a PNG
+        assert_eq!(&encoded[..6], &[0, 0, 1, 0, 3, 0]);
+        let mut offsets = Vec::new();
+        for (entry, &size) in encoded[6..54].chunks(16).zip(&[16, 32, 0]) {
+            assert_eq!(&entry[..2], &[size, size]);
+            let offset = entry[12] as usize | (entry[13] as usize) << 8;
+            offsets.push(offset);
+        }
+        assert_eq!(offsets[0], 54);
+        assert_eq!(&encoded[offsets[0]..offsets[0] + 4], &[40, 0, 0, 0]);
+        assert_eq!(&encoded[offsets[2] + 1..offsets[2] + 4], b"PNG");
+
+        // The largest entry is the one being decoded
+        assert_eq!(decode(encoded), (256, 256, gradient(256).into_raw()));
+    }
+
+    #[test]
+    fn cursor_hotspot() {
+        let images: Vec<_> = [32, 48].iter()
+            .map(|&size| DynamicImage::ImageRgba8(gradient(size)))
+            .collect();
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = ICOEncoder::new(&mut encoded);
+            encoder.set_hotspot(5, 12);
+            encoder.encode_images(&images).unwrap();
+        }
+        assert_eq!(&encoded[..4], &[0, 0, 2, 0]);
+
+        let decoder = ICODecoder::new(Cursor::new(encoded.clone())).unwrap();
+        assert_eq!(decoder.hotspot(), Some((5, 12)));
+        assert_eq!(decode(encoded), (48, 48, gradient(48).into_raw()));
+
+        let mut encoded = Vec::new();
+        ICOEncoder::new(&mut encoded).encode_images(&images).unwrap();
+        assert_eq!(ICODecoder::new(Cursor::new(encoded)).unwrap().hotspot(), None);
+    }
+
+    #[test]
+    fn entry_selection() {
+        let images: Vec<_> = [48, 16, 32].iter()
+            .map(|&size| DynamicImage::ImageRgba8(gradient(size)))
+            .collect();
+        let mut encoded = Vec::new();
+        ICOEncoder::new(&mut encoded).encode_images(&images).unwrap();
+
+        let decoder = ICODecoder::new(Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoder.selected_entry(), 0);
+        assert_eq!(decoder.entries(), vec![
+            ICOEntry { width: 48, height: 48, bits_per_pixel: 32 },
+            ICOEntry { width: 16, height: 16, bits_per_pixel: 32 },
+            ICOEntry { width: 32, height: 32, bits_per_pixel: 32 },
+        ]);
+
+        for &(size, index) in &[(1, 1), (16, 1), (17, 2), (40, 0), (64, 0)] {
+            let decoder = ICODecoder::new_with_size(Cursor::new(&encoded), size, size).unwrap();
+            assert_eq!(decoder.selected_entry(), index);
+        }
+
+        let mut decoder = ICODecoder::new_with_entry(Cursor::new(&encoded), 2).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (32, 32));
+        assert!(ICODecoder::new_with_entry(Cursor::new(&encoded), 3).is_err());
+    }
+
+    #[test]
+    fn bitmap_entries() {
+        for &size in &[1, 20, 33] {
+            let image = DynamicImage::ImageRgba8(gradient(size));
+            let mut encoded = Vec::new();
+            ICOEncoder::new(&mut encoded).encode_images(&[image]).unwrap();
+            assert_eq!(decode(encoded), (size, size, gradient(size).into_raw()));
+        }
+
+        let image = DynamicImage::ImageRgba8(gradient(257));
+        assert!(ICOEncoder::new(Vec::new()).encode_images(&[image]).is_err());
+        assert!(ICOEncoder::new(Vec::new()).encode_images(&[]).is_err());
+    }
+}
@@ -2,54 +2,199 @@ use std::fmt;
 use std::mem;
 use std::io;
 use std::error::Error;
+use std::ffi::OsStr;
+use std::ops::{Deref, DerefMut};
+
+use byteorder::{BigEndian, ByteOrder};
 
 use color;
 use color::ColorType;
 use buffer::{ImageBuffer, Pixel};
 
 use animation::{Frame, Frames};
+use monitor::Monitor;
 use dynimage::decoder_to_image;
 
 /// An enumeration of Image errors
+///
+/// The variants tell corrupt images apart from valid images using unsupported features, images
+/// exceeding the `Limits`, invalid arguments and I/O errors, each with structured details.
 #[derive(Debug)]
 pub enum ImageError {
-    /// The Image is not formatted properly
-    FormatError(String),
+    /// The image is malformed, so it could not be decoded
+    Decoding {
+        /// The format of the image
+        format: ImageFormatHint,
+        /// What is wrong with the image
+        kind: DecodingErrorKind,
+    },
+
+    /// The image uses a format or a feature that is not supported
+    Unsupported {
+        /// The format of the image
+        format: ImageFormatHint,
+        /// What is not supported
+        feature: UnsupportedFeature,
+    },
+
+    /// The image exceeds the limits set for decoding it
+    Limits(LimitErrorKind),
+
+    /// An argument passed to the operation is invalid
+    Parameter(ParameterErrorKind),
+
+    /// An I/O Error occurred while decoding or encoding the image
+    IoError(io::Error),
+
+    /// The operation was cancelled through a `CancelToken`
+    Cancelled
+}
+
+/// The format an error occurred in, as far as it is known
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormatHint {
+    /// A format supported by this crate
+    Exact(ImageFormat),
+    /// A format this crate does not support, by its name or the extension of its files
+    Name(String),
+    /// The format is not known
+    Unknown,
+}
 
-    /// The Image's dimensions are either too small or too large
-    DimensionError,
+/// What is wrong with a malformed image
+#[derive(Debug)]
+pub enum DecodingErrorKind {
+    /// The data does not follow the format, as described by the message
+    Malformed(String),
+    /// The data ends before the image is complete
+    Truncated,
+    /// The decoder of another crate failed, the error is its `source`
+    External(Box<Error + Send + Sync>),
+}
 
-    /// The Decoder does not support this image format
-    UnsupportedError(String),
+/// What is not supported in an image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnsupportedFeature {
+    /// Images of the format are not supported at all, or no codec for it is enabled
+    Format,
+    /// Images of the color type are not supported by the format
+    Color(ColorType),
+    /// Another feature of the format, as described by the message
+    Other(String),
+}
 
-    /// The Decoder does not support this color type
-    UnsupportedColor(ColorType),
+/// Which of the `Limits` an image exceeds
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitErrorKind {
+    /// The width or the height of the image or of a frame
+    Dimensions,
+    /// The number of bytes of the decoded samples
+    Memory,
+    /// The number of frames of an animation
+    Frames,
+}
 
-    /// Not enough data was provided to the Decoder
-    /// to decode the image
-    NotEnoughData,
+/// What is wrong with an argument passed to an operation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParameterErrorKind {
+    /// The dimensions do not match the buffer, or they are zero or too large
+    DimensionMismatch,
+    /// The buffer passed to the decoder is too short to hold the requested data
+    BufferTooShort,
+    /// All rows or frames of the image have already been read
+    NoMoreData,
+    /// Another invalid argument, as described by the message
+    Generic(String),
+}
 
-    /// An I/O Error occurred while decoding the image
-    IoError(io::Error),
+impl ImageError {
+    /// The error of an image of format ```format``` that does not follow the format, as
+    /// described by ```message```
+    pub fn malformed(format: ImageFormat, message: String) -> ImageError {
+        ImageError::Decoding {
+            format: ImageFormatHint::Exact(format),
+            kind: DecodingErrorKind::Malformed(message),
+        }
+    }
+
+    /// The error of an image of format ```format``` whose data ends before it is complete
+    pub fn truncated(format: ImageFormat) -> ImageError {
+        ImageError::Decoding {
+            format: ImageFormatHint::Exact(format),
+            kind: DecodingErrorKind::Truncated,
+        }
+    }
+
+    /// The error of an image of format ```format``` using a feature described by ```message```
+    /// that is not supported
+    pub fn unsupported(format: ImageFormat, message: String) -> ImageError {
+        ImageError::Unsupported {
+            format: ImageFormatHint::Exact(format),
+            feature: UnsupportedFeature::Other(message),
+        }
+    }
+
+    /// The error of an image of format ```format``` and color type ```color```, which is not
+    /// supported
+    pub fn unsupported_color(format: ImageFormat, color: ColorType) -> ImageError {
+        ImageError::Unsupported {
+            format: ImageFormatHint::Exact(format),
+            feature: UnsupportedFeature::Color(color),
+        }
+    }
+
+    /// The error of a format ```format``` that is not supported, or whose codec is not enabled
+    pub fn unsupported_format(format: ImageFormatHint) -> ImageError {
+        ImageError::Unsupported {
+            format: format,
+            feature: UnsupportedFeature::Format,
+        }
+    }
+}
 
-    /// The end of the image has been reached
-    ImageEnd
+impl fmt::Display for ImageFormatHint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            ImageFormatHint::Exact(format) => write!(fmt, "{:?}", format),
+            ImageFormatHint::Name(ref name) => write!(fmt, "{}", name),
+            ImageFormatHint::Unknown => write!(fmt, "an unknown format"),
+        }
+    }
 }
 
 impl fmt::Display for ImageError {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match *self {
-            ImageError::FormatError(ref e) => write!(fmt, "Format error: {}", e),
-            ImageError::DimensionError => write!(fmt, "The Image's dimensions are either too \
-                                                        small or too large"),
-            ImageError::UnsupportedError(ref f) => write!(fmt, "The Decoder does not support the \
-                                                                 image format `{}`", f),
-            ImageError::UnsupportedColor(ref c) => write!(fmt, "The decoder does not support \
-                                                                 the color type `{:?}`", c),
-            ImageError::NotEnoughData => write!(fmt, "Not enough data was provided to the \
-                                                       Decoder to decode the image"),
+            ImageError::Decoding { ref format, ref kind } => match *kind {
+                DecodingErrorKind::Malformed(ref message) => write!(fmt, "Format error decoding {}: {}",
+                                                                    format, message),
+                DecodingErrorKind::Truncated => write!(fmt, "The {} image ends before it is complete",
+                                                       format),
+                DecodingErrorKind::External(ref err) => write!(fmt, "Format error decoding {}: {}",
+                                                               format, err),
+            },
+            ImageError::Unsupported { ref format, ref feature } => match *feature {
+                UnsupportedFeature::Format => write!(fmt, "The image format {} is not supported", format),
+                UnsupportedFeature::Color(color) => write!(fmt, "The {} codec does not support the \
+                                                                 color type `{:?}`", format, color),
+                UnsupportedFeature::Other(ref message) => write!(fmt, "The {} codec does not support \
+                                                                       {}", format, message),
+            },
+            ImageError::Limits(kind) => write!(fmt, "The image exceeds the limits set for decoding \
+                                                     it: {}", match kind {
+                LimitErrorKind::Dimensions => "dimensions",
+                LimitErrorKind::Memory => "memory",
+                LimitErrorKind::Frames => "frames",
+            }),
+            ImageError::Parameter(ref kind) => match *kind {
+                ParameterErrorKind::DimensionMismatch => write!(fmt, "The Image's dimensions are \
+                                                                      either too small or too large"),
+                ParameterErrorKind::BufferTooShort => write!(fmt, "The buffer is too short"),
+                ParameterErrorKind::NoMoreData => write!(fmt, "The end of the image has been reached"),
+                ParameterErrorKind::Generic(ref message) => write!(fmt, "Invalid argument: {}", message),
+            },
             ImageError::IoError(ref e) => e.fmt(fmt),
-            ImageError::ImageEnd => write!(fmt, "The end of the image has been reached")
+            ImageError::Cancelled => write!(fmt, "The operation was cancelled")
         }
     }
 }
@@ -57,19 +202,19 @@ impl fmt::Display for ImageError {
 impl Error for ImageError {
     fn description (&self) -> &str {
         match *self {
-            ImageError::FormatError(..) => "Format error",
-            ImageError::DimensionError => "Dimension error",
-            ImageError::UnsupportedError(..) => "Unsupported error",
-            ImageError::UnsupportedColor(..) => "Unsupported color",
-            ImageError::NotEnoughData => "Not enough data",
+            ImageError::Decoding { .. } => "Decoding error",
+            ImageError::Unsupported { .. } => "Unsupported error",
+            ImageError::Limits(..) => "Limits exceeded",
+            ImageError::Parameter(..) => "Parameter error",
             ImageError::IoError(..) => "IO error",
-            ImageError::ImageEnd => "Image end"
+            ImageError::Cancelled => "Cancelled"
         }
     }
 
-    fn cause (&self) -> Option<&Error> {
+    fn source (&self) -> Option<&(Error + 'static)> {
         match *self {
             ImageError::IoError(ref e) => Some(e),
+            ImageError::Decoding { kind: DecodingErrorKind::External(ref e), .. } => Some(&**e),
             _ => None
         }
     }
@@ -85,13 +230,159 @@ impl From<io::Error> for ImageError {
 /// Result of an image decoding/encoding process
 pub type ImageResult<T> = Result<T, ImageError>;
 
+/// Limits on the size of the images a decoder accepts
+///
+/// Images are checked against the limits before their pixels are decoded, so that a small file
+/// declaring huge dimensions is rejected with `ImageError::Limits` instead of exhausting
+/// the memory. A limit of `None` is not enforced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest width of an image or of a frame, in pixels
+    pub max_width: Option<u32>,
+    /// The largest height of an image or of a frame, in pixels
+    pub max_height: Option<u32>,
+    /// The largest number of bytes of the decoded samples, summed over all frames of an
+    /// animation
+    pub max_bytes: Option<u64>,
+    /// The largest number of frames of an animation
+    pub max_frames: Option<usize>,
+}
+
+impl Default for Limits {
+    /// Limits the decoded samples to 512 MiB, without limiting the dimensions or frames
+    fn default() -> Limits {
+        Limits {
+            max_width: None,
+            max_height: None,
+            max_bytes: Some(512 * 1024 * 1024),
+            max_frames: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Limits that accept any image
+    pub fn no_limits() -> Limits {
+        Limits {
+            max_width: None,
+            max_height: None,
+            max_bytes: None,
+            max_frames: None,
+        }
+    }
+
+    /// Checks an image or frame of ```width``` by ```height``` pixels of color type ```color```
+    ///
+    /// Images whose number of bytes does not even fit in a ```u64``` always exceed the memory.
+    pub fn check_image(&self, width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        let bits = (width as u64).checked_mul(height as u64)
+            .and_then(|pixels| pixels.checked_mul(color::bits_per_pixel(color) as u64))
+            .and_then(|bits| bits.checked_add(7));
+        let bytes = match bits {
+            Some(bits) => bits / 8,
+            None => return Err(ImageError::Limits(LimitErrorKind::Memory)),
+        };
+        if self.max_width.map_or(false, |max| width > max) ||
+           self.max_height.map_or(false, |max| height > max) {
+            return Err(ImageError::Limits(LimitErrorKind::Dimensions))
+        }
+        if self.max_bytes.map_or(false, |max| bytes > max) {
+            return Err(ImageError::Limits(LimitErrorKind::Memory))
+        }
+        Ok(())
+    }
+
+    /// Checks an animation of ```frames``` frames holding ```bytes``` bytes of samples
+    pub fn check_animation(&self, frames: usize, bytes: u64) -> ImageResult<()> {
+        if self.max_frames.map_or(false, |max| frames > max) {
+            return Err(ImageError::Limits(LimitErrorKind::Frames))
+        }
+        if self.max_bytes.map_or(false, |max| bytes > max) {
+            return Err(ImageError::Limits(LimitErrorKind::Memory))
+        }
+        Ok(())
+    }
+}
+
+/// The basic properties of an image, as returned by `ImageDecoder::metadata`
+///
+/// They are read from the header of the image, so properties that a format does not store or
+/// that are only known once the image is decoded are `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metadata {
+    /// The width and height of the image in pixels
+    pub dimensions: (u32, u32),
+    /// The number of bits of each sample, e.g. 8 for RGB(8)
+    pub bit_depth: u8,
+    /// The horizontal and vertical resolution in pixels per inch
+    pub dpi: Option<(f32, f32)>,
+    /// The exponent the samples are encoded with, about 0.45455 for images meant for typical
+    /// displays
+    pub gamma: Option<f32>,
+    /// Whether the image embeds an ICC color profile, which `ImageDecoder::icc_profile` returns
+    pub has_icc_profile: bool,
+    /// The EXIF orientation that still has to be applied to show the decoded image upright, as
+    /// described for `JPEGDecoder::orientation`
+    pub orientation: Option<u16>,
+    /// The number of frames, which is one for still images
+    pub frame_count: Option<u32>,
+}
+
+impl Metadata {
+    /// Returns the width and height of the image in inches, if its resolution is known
+    pub fn physical_size(&self) -> Option<(f32, f32)> {
+        self.dpi.and_then(|(x, y)| if x > 0.0 && y > 0.0 {
+            Some((self.dimensions.0 as f32 / x, self.dimensions.1 as f32 / y))
+        } else {
+            None
+        })
+    }
+}
+
+/// Reads the metadata every decoder gives, which decoders complete with what their format stores
+pub fn decoder_metadata<D: ImageDecoder>(decoder: &mut D) -> ImageResult<Metadata> {
+    let dimensions = try!(decoder.dimensions());
+    let color = try!(decoder.colortype());
+    let animated = try!(decoder.is_animated());
+    Ok(Metadata {
+        dimensions: dimensions,
+        bit_depth: (color::bits_per_pixel(color) / color::num_components(color)) as u8,
+        dpi: None,
+        gamma: None,
+        has_icc_profile: try!(decoder.icc_profile()).is_some(),
+        orientation: None,
+        frame_count: if animated { None } else { Some(1) },
+    })
+}
+
+/// Reads the rows of an image into ```buf``` one by one with `read_scanline`, which is how
+/// decoders of formats stored row by row implement `ImageDecoder::read_image_into`
+pub fn read_scanlines_into<D: ImageDecoder>(decoder: &mut D, buf: &mut [u8]) -> ImageResult<usize> {
+    let (_, height) = try!(decoder.dimensions());
+    let row_len = try!(decoder.row_len());
+    let len = match row_len.checked_mul(height as usize) {
+        Some(len) if len <= buf.len() => len,
+        _ => return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)),
+    };
+    if len > 0 {
+        for row in buf[..len].chunks_mut(row_len) {
+            try!(decoder.read_scanline(row));
+        }
+    }
+    Ok(len)
+}
+
 /// Result of a decoding process
 #[derive(Debug)]
 pub enum DecodingResult {
     /// A vector of unsigned bytes
     U8(Vec<u8>),
     /// A vector of unsigned words
-    U16(Vec<u16>)
+    U16(Vec<u16>),
+    /// A vector of unsigned double words
+    U32(Vec<u32>),
+    /// A vector of single precision floating point numbers
+    F32(Vec<f32>)
 }
 
 // A buffer for image decoding
@@ -99,7 +390,11 @@ pub enum DecodingBuffer<'a> {
     /// A slice of unsigned bytes
     U8(&'a mut [u8]),
     /// A slice of unsigned words
-    U16(&'a mut [u16])
+    U16(&'a mut [u16]),
+    /// A slice of unsigned double words
+    U32(&'a mut [u32]),
+    /// A slice of single precision floating point numbers
+    F32(&'a mut [f32])
 }
 
 /// An enumeration of supported image formats.
@@ -118,7 +413,11 @@ pub enum ImageFormat {
     /// An Image in WEBP Format
     WEBP,
 
-    /// An Image in PPM Format
+    /// An Image in general PNM Format
+    PNM,
+
+    /// An Image in PPM Format, read and written as any PNM format
+    #[deprecated(note = "use PNM instead")]
     PPM,
 
     /// An Image in TIFF Format
@@ -135,9 +434,139 @@ pub enum ImageFormat {
 
     /// An Image in Radiance HDR Format
     HDR,
+
+    /// An Image in OpenEXR Format
+    EXR,
+
+    /// An Image in QOI Format
+    QOI,
+
+    /// An Image in farbfeld Format
+    Farbfeld,
+
+    /// An Image in DDS Format
+    DDS,
+
+    /// An Image in AVIF Format
+    AVIF,
+
+    /// An Image in HEIF Format
+    HEIF,
+
+    /// An Image in SGI Format
+    SGI,
+
+    /// An Image in XBM Format
+    XBM,
+
+    /// An Image in XPM Format
+    XPM,
+
+    /// An Image in DNG Format
+    DNG,
+}
+
+impl ImageFormat {
+    /// Returns the format of files with the extension ```ext```, ignoring its case
+    pub fn from_extension<S: AsRef<OsStr>>(ext: S) -> Option<ImageFormat> {
+        let ext = match ext.as_ref().to_str() {
+            Some(ext) => ext.to_ascii_lowercase(),
+            None => return None,
+        };
+        Some(match &ext[..] {
+            "jpg" | "jpeg" => ImageFormat::JPEG,
+            "png" => ImageFormat::PNG,
+            "gif" => ImageFormat::GIF,
+            "webp" => ImageFormat::WEBP,
+            "tif" | "tiff" => ImageFormat::TIFF,
+            "tga" => ImageFormat::TGA,
+            "bmp" => ImageFormat::BMP,
+            "ico" | "cur" => ImageFormat::ICO,
+            "hdr" => ImageFormat::HDR,
+            "exr" => ImageFormat::EXR,
+            "qoi" => ImageFormat::QOI,
+            "ff" | "farbfeld" => ImageFormat::Farbfeld,
+            "dds" => ImageFormat::DDS,
+            "avif" => ImageFormat::AVIF,
+            "heic" | "heif" => ImageFormat::HEIF,
+            "sgi" | "rgb" | "rgba" | "bw" => ImageFormat::SGI,
+            "xbm" => ImageFormat::XBM,
+            "xpm" => ImageFormat::XPM,
+            "dng" => ImageFormat::DNG,
+            "pbm" | "pgm" | "ppm" | "pam" => ImageFormat::PNM,
+            _ => return None,
+        })
+    }
+
+    /// Returns the format of the MIME type ```mime_type```, ignoring its case and parameters
+    ///
+    /// Besides the types returned by `to_mime_type`, common unofficial types such as
+    /// ```image/x-bmp``` are recognized.
+    pub fn from_mime_type(mime_type: &str) -> Option<ImageFormat> {
+        let mime_type = mime_type.split(';').next().unwrap().trim().to_ascii_lowercase();
+        Some(match &mime_type[..] {
+            "image/png" | "image/apng" => ImageFormat::PNG,
+            "image/jpeg" | "image/jpg" | "image/pjpeg" => ImageFormat::JPEG,
+            "image/gif" => ImageFormat::GIF,
+            "image/webp" => ImageFormat::WEBP,
+            "image/x-portable-anymap" |
+            "image/x-portable-bitmap" |
+            "image/x-portable-graymap" |
+            "image/x-portable-pixmap" |
+            "image/x-portable-arbitrarymap" => ImageFormat::PNM,
+            "image/tiff" | "image/tiff-fx" => ImageFormat::TIFF,
+            "image/x-tga" | "image/x-targa" => ImageFormat::TGA,
+            "image/bmp" | "image/x-bmp" | "image/x-ms-bmp" => ImageFormat::BMP,
+            "image/x-icon" | "image/vnd.microsoft.icon" => ImageFormat::ICO,
+            "image/vnd.radiance" => ImageFormat::HDR,
+            "image/x-exr" | "image/aces" => ImageFormat::EXR,
+            "image/qoi" | "image/x-qoi" => ImageFormat::QOI,
+            "image/x-farbfeld" => ImageFormat::Farbfeld,
+            "image/vnd-ms.dds" | "image/x-dds" => ImageFormat::DDS,
+            "image/avif" => ImageFormat::AVIF,
+            "image/heif" | "image/heic" => ImageFormat::HEIF,
+            "image/sgi" | "image/x-sgi" | "image/x-rgb" => ImageFormat::SGI,
+            "image/x-xbitmap" | "image/x-xbm" => ImageFormat::XBM,
+            "image/x-xpixmap" | "image/x-xpm" => ImageFormat::XPM,
+            "image/x-adobe-dng" | "image/dng" => ImageFormat::DNG,
+            _ => return None,
+        })
+    }
+
+    /// Returns the MIME type of the format, using the registered type where there is one
+    pub fn to_mime_type(&self) -> &'static str {
+        match *self {
+            ImageFormat::PNG => "image/png",
+            ImageFormat::JPEG => "image/jpeg",
+            ImageFormat::GIF => "image/gif",
+            ImageFormat::WEBP => "image/webp",
+            #[allow(deprecated)]
+            ImageFormat::PNM | ImageFormat::PPM => "image/x-portable-anymap",
+            ImageFormat::TIFF => "image/tiff",
+            ImageFormat::TGA => "image/x-tga",
+            ImageFormat::BMP => "image/bmp",
+            ImageFormat::ICO => "image/vnd.microsoft.icon",
+            ImageFormat::HDR => "image/vnd.radiance",
+            ImageFormat::EXR => "image/x-exr",
+            ImageFormat::QOI => "image/qoi",
+            ImageFormat::Farbfeld => "image/x-farbfeld",
+            ImageFormat::DDS => "image/vnd-ms.dds",
+            ImageFormat::AVIF => "image/avif",
+            ImageFormat::HEIF => "image/heif",
+            ImageFormat::SGI => "image/sgi",
+            ImageFormat::XBM => "image/x-xbitmap",
+            ImageFormat::XPM => "image/x-xpixmap",
+            ImageFormat::DNG => "image/x-adobe-dng",
+        }
+    }
 }
 
 /// The trait that all decoders implement
+///
+/// Creating a decoder and calling `dimensions`, `colortype` and `row_len` only reads the header
+/// of the image, its data is not decoded until rows or the whole image are read. Decoders of
+/// containers and text formats that have to be read into memory, like AVIF, DNG and XPM, read
+/// the file when they are created, but do not decode it either.
 pub trait ImageDecoder: Sized {
     /// Returns a tuple containing the width and height of the image
     fn dimensions(&mut self) -> ImageResult<(u32, u32)>;
@@ -148,12 +577,61 @@ pub trait ImageDecoder: Sized {
     /// Returns the length in bytes of one decoded row of the image
     fn row_len(&mut self) -> ImageResult<usize>;
 
-    /// Reads one row from the image into ```buf``` and returns the row index
+    /// Reads the next row of the image into ```buf``` and returns its index, starting from 0
+    /// for the top row.
+    ///
+    /// The rows hold the same samples as the image returned by `read_image`, with samples of
+    /// more than 8 bits in big endian order. Returns `ParameterErrorKind::BufferTooShort` if
+    /// ```buf``` is shorter than `row_len`, and `ParameterErrorKind::NoMoreData` once every row
+    /// has been read.
     fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32>;
 
     /// Decodes the entire image and return it as a Vector
     fn read_image(&mut self) -> ImageResult<DecodingResult>;
 
+    /// Decodes the entire image into ```buf```, returning the number of bytes written
+    ///
+    /// The bytes are the rows of the image one after another, as returned by `read_scanline`,
+    /// with samples of more than 8 bits in big endian order. Returns
+    /// `ParameterErrorKind::BufferTooShort` if ```buf``` is too short.
+    ///
+    /// Only the PNG, GIF and PNM decoders write their rows directly into ```buf```. The default
+    /// implementation used by all other decoders copies the image returned by `read_image`, so
+    /// the image is allocated once more while it is decoded.
+    fn read_image_into(&mut self, buf: &mut [u8]) -> ImageResult<usize> {
+        let image = try!(self.read_image());
+        let len = match image {
+            DecodingResult::U8(ref data) => data.len(),
+            DecodingResult::U16(ref data) => data.len() * 2,
+            DecodingResult::U32(ref data) => data.len() * 4,
+            DecodingResult::F32(ref data) => data.len() * 4,
+        };
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+        match image {
+            DecodingResult::U8(data) => buf[..len].copy_from_slice(&data),
+            DecodingResult::U16(data) => BigEndian::write_u16_into(&data, &mut buf[..len]),
+            DecodingResult::U32(data) => BigEndian::write_u32_into(&data, &mut buf[..len]),
+            DecodingResult::F32(data) => BigEndian::write_f32_into(&data, &mut buf[..len]),
+        }
+        Ok(len)
+    }
+
+    /// Decodes the entire image like `read_image`, reporting the rows decoded to ```monitor```
+    ///
+    /// The default implementation only reports the image once it has been decoded. The PNG, PNM,
+    /// TGA and BMP decoders report each row and the TIFF decoder each row of strips or tiles,
+    /// which can be cancelled in between. Run length encoded BMP images and JPEG images, which
+    /// are decoded in a single call, are only reported once they have been decoded.
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        let (_, height) = try!(self.dimensions());
+        try!(monitor.update(0, height as u64));
+        let image = try!(self.read_image());
+        try!(monitor.update(height as u64, height as u64));
+        Ok(image)
+    }
+
     /// Returns true if the image is animated
     fn is_animated(&mut self) -> ImageResult<bool> {
         // since most image formats do not support animation
@@ -161,22 +639,55 @@ pub trait ImageDecoder: Sized {
         Ok(false)
     }
 
+    /// Returns the ICC color profile embedded in the image, if it has one
+    ///
+    /// Images without a profile are usually meant to be interpreted as sRGB.
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Returns the metadata of the image, only reading its header
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        decoder_metadata(self)
+    }
+
     /// Returns the frames of the image
     ///
-    /// If the image is not animated it returns a single frame
+    /// The GIF, APNG and WebP decoders return each frame with its offset, `Delay`, disposal and
+    /// blending, so that animations of any format are played the same way. If the image is not
+    /// animated it returns a single frame
     fn into_frames(self) -> ImageResult<Frames> {
         Ok(Frames::new(vec![
             Frame::new(try!(decoder_to_image(self)).to_rgba())
         ]))
     }
 
+    /// Checks the dimensions of the image against ```limits```, before decoding it
+    fn check_limits(&mut self, limits: &Limits) -> ImageResult<()> {
+        let (width, height) = try!(self.dimensions());
+        let color = try!(self.colortype());
+        limits.check_image(width, height, color)
+    }
+
+    /// Returns the frames of the image, failing if they exceed ```limits```
+    ///
+    /// Decoders of animations check each frame before decoding it. Otherwise the dimensions of
+    /// the image are checked before decoding it, and the frames once they have been decoded.
+    fn into_frames_with_limits(mut self, limits: &Limits) -> ImageResult<Frames> {
+        try!(self.check_limits(limits));
+        let frames: Vec<Frame> = try!(self.into_frames()).collect();
+        let bytes = frames.iter().map(|frame| frame.buffer().len() as u64).sum();
+        try!(limits.check_animation(frames.len(), bytes));
+        Ok(Frames::new(frames))
+    }
+
     /// Decodes a specific region of the image, represented by the rectangle
     /// starting from ```x``` and ```y``` and having ```length``` and ```width```
     fn load_rect(&mut self, x: u32, y: u32, length: u32, width: u32) -> ImageResult<Vec<u8>> {
         let (w, h) = try!(self.dimensions());
 
-        if length > h || width > w || x > w || y > h {
-            return Err(ImageError::DimensionError)
+        if x as u64 + width as u64 > w as u64 || y as u64 + length as u64 > h as u64 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
         }
 
         let c = try!(self.colortype());
@@ -185,34 +696,43 @@ pub trait ImageDecoder: Sized {
 
         let rowlen  = try!(self.row_len());
 
-        let mut buf = vec![0u8; length as usize * width as usize * bpp];
+        let mut buf = Vec::with_capacity(length as usize * width as usize * bpp);
         let mut tmp = vec![0u8; rowlen];
 
-        loop {
+        for _ in 0..y + length {
             let row = try!(self.read_scanline(&mut tmp));
 
-            if row - 1 == y {
-                break
+            if row >= y {
+                buf.extend_from_slice(&tmp[x as usize * bpp..(x + width) as usize * bpp]);
             }
         }
 
-        for i in 0..length as usize {
-            {
-                let from = &tmp[x as usize * bpp..width as usize * bpp];
+        Ok(buf)
+    }
+}
 
-                let to   = &mut buf[i * width as usize * bpp..width as usize * bpp];
 
-                ::copy_memory(from, to);
-            }
-
-            let _ = try!(self.read_scanline(&mut tmp));
-        }
+/// The trait that all encoders implement
+///
+/// Samples of more than 8 bits are stored in big endian order in ```buf```, as in the rows
+/// returned by decoders.
+pub trait ImageEncoder: Sized {
+    /// Encodes the image ```buf``` of ```width``` by ```height``` pixels of color type ```color```
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()>;
 
-        Ok(buf)
+    /// Encodes the image like `write_image`, reporting the rows encoded to ```monitor```
+    ///
+    /// The default implementation only reports the image once it has been written, which all
+    /// encoders but the PNG encoder use. The PNG encoder reports each compressed row and can be
+    /// cancelled in between.
+    fn write_image_with_progress(self, buf: &[u8], width: u32, height: u32, color: ColorType,
+                                 monitor: &mut Monitor) -> ImageResult<()> {
+        try!(monitor.update(0, height as u64));
+        try!(self.write_image(buf, width, height, color));
+        monitor.update(height as u64, height as u64)
     }
 }
 
-
 /// Immutable pixel iterator
 pub struct Pixels<'a, I: 'a> {
     image:  &'a I,
@@ -222,7 +742,7 @@ pub struct Pixels<'a, I: 'a> {
     height: u32
 }
 
-impl<'a, I: GenericImage> Iterator for Pixels<'a, I> {
+impl<'a, I: GenericImageView> Iterator for Pixels<'a, I> {
     type Item = (u32, u32, I::Pixel);
 
     fn next(&mut self) -> Option<(u32, u32, I::Pixel)> {
@@ -287,8 +807,11 @@ impl<'a, I: GenericImage + 'a> Iterator for MutPixels<'a, I>
     }
 }
 
-/// A trait for manipulating images.
-pub trait GenericImage: Sized {
+/// A trait for reading the pixels of images.
+///
+/// Views only read the pixels, they can borrow images immutably, like the views returned by
+/// `view` or `imageops::crop_imm`.
+pub trait GenericImageView: Sized {
     /// The type of pixel.
     type Pixel: Pixel;
 
@@ -331,20 +854,59 @@ pub trait GenericImage: Sized {
     /// TODO: change this signature to &P
     fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel;
 
-    /// Puts a pixel at location (x, y)
+    /// Returns the pixel located at (x, y), or None if it is out of bounds
+    fn get_pixel_checked(&self, x: u32, y: u32) -> Option<Self::Pixel> {
+        let (width, height) = self.dimensions();
+        if x < width && y < height {
+            Some(self.get_pixel(x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the pixel located at (x, y), without checking that it is in bounds
     ///
-    /// # Panics
+    /// Implementations may skip the bounds check of `get_pixel`, which is what the default
+    /// implementation calls, to speed up loops over many pixels.
     ///
-    /// Panics if `(x, y)` is out of bounds.
-    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut Self::Pixel;
-
-    /// Returns the pixel located at (x, y)
+    /// # Safety
     ///
-    /// This function can be implemented in a way that ignores bounds checking.
+    /// `(x, y)` must be within the dimensions of the image. Reading out of bounds is undefined
+    /// behaviour for images that skip the bounds check.
     unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
         self.get_pixel(x, y)
     }
 
+    /// Returns an Iterator over the pixels of this image.
+    /// The iterator yields the coordinates of each pixel
+    /// along with their value
+    fn pixels(&self) -> Pixels<Self> {
+        let (width, height) = self.dimensions();
+
+        Pixels {
+            image:  self,
+            x:      0,
+            y:      0,
+            width:  width,
+            height: height,
+        }
+    }
+
+    /// Returns a subimage that is an immutable view into this image.
+    fn view(&self, x: u32, y: u32, width: u32, height: u32) -> SubImage<&Self> {
+        SubImage::new(self, x, y, width, height)
+    }
+}
+
+/// A trait for manipulating images.
+pub trait GenericImage: GenericImageView {
+    /// Puts a pixel at location (x, y)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `(x, y)` is out of bounds.
+    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut Self::Pixel;
+
     /// Put a pixel at location (x, y)
     ///
     /// # Panics
@@ -352,9 +914,15 @@ pub trait GenericImage: Sized {
     /// Panics if `(x, y)` is out of bounds.
     fn put_pixel(&mut self, x: u32, y: u32, pixel: Self::Pixel);
 
-    /// Puts a pixel at location (x, y)
+    /// Puts a pixel at location (x, y), without checking that it is in bounds
+    ///
+    /// Implementations may skip the bounds check of `put_pixel`, which is what the default
+    /// implementation calls, to speed up loops over many pixels.
     ///
-    /// This function can be implemented in a way that ignores bounds checking.
+    /// # Safety
+    ///
+    /// `(x, y)` must be within the dimensions of the image. Writing out of bounds is undefined
+    /// behaviour for images that skip the bounds check.
     unsafe fn unsafe_put_pixel(&mut self, x: u32, y: u32, pixel: Self::Pixel) {
         self.put_pixel(x, y, pixel);
     }
@@ -364,21 +932,6 @@ pub trait GenericImage: Sized {
     /// DEPRECATED: This method will be removed. Blend the pixel directly instead.
     fn blend_pixel(&mut self, x: u32, y: u32, pixel: Self::Pixel);
 
-    /// Returns an Iterator over the pixels of this image.
-    /// The iterator yields the coordinates of each pixel
-    /// along with their value
-    fn pixels(&self) -> Pixels<Self> {
-        let (width, height) = self.dimensions();
-
-        Pixels {
-            image:  self,
-            x:      0,
-            y:      0,
-            width:  width,
-            height: height,
-        }
-    }
-
     /// Returns an Iterator over mutable pixels of this image.
     /// The iterator yields the coordinates of each pixel
     /// along with a mutable reference to them.
@@ -401,13 +954,13 @@ pub trait GenericImage: Sized {
     /// The other image is copied with the top-left corner of the
     /// other image placed at (x, y).
     ///
-    /// In order to copy only a piece of the other image, use `sub_image`.
+    /// In order to copy only a piece of the other image, use `view`.
     ///
     /// # Returns
     /// `true` if the copy was successful, `false` if the image could not
     /// be copied due to size constraints.
     fn copy_from<O>(&mut self, other: &O, x: u32, y:u32) -> bool
-    where O: GenericImage<Pixel=Self::Pixel> {
+    where O: GenericImageView<Pixel=Self::Pixel> {
         // Do bounds checking here so we can use the non-bounds-checking
         // functions to copy pixels.
         if self.width() < other.width() + x {
@@ -427,31 +980,27 @@ pub trait GenericImage: Sized {
         true
     }
 
-    /// Returns a subimage that is a view into this image.
-    fn sub_image(&mut self, x: u32, y: u32, width: u32, height: u32)
-    -> SubImage<Self>
-    where Self: 'static, <Self::Pixel as Pixel>::Subpixel: 'static,
-    Self::Pixel: 'static {
+    /// Returns a subimage that is a mutable view into this image.
+    fn sub_image(&mut self, x: u32, y: u32, width: u32, height: u32) -> SubImage<&mut Self> {
         SubImage::new(self, x, y, width, height)
     }
 }
 
 /// A View into another image
-pub struct SubImage <'a, I: 'a> {
-    image:   &'a mut I,
+///
+/// The image is borrowed through ```I```, a reference to it: views borrowing it immutably
+/// implement `GenericImageView`, views borrowing it mutably also implement `GenericImage`.
+pub struct SubImage<I> {
+    image:   I,
     xoffset: u32,
     yoffset: u32,
     xstride: u32,
     ystride: u32,
 }
 
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
-impl<'a, I: GenericImage + 'static> SubImage<'a, I>
-    where I::Pixel: 'static,
-          <I::Pixel as Pixel>::Subpixel: 'static {
-
+impl<I> SubImage<I> {
     /// Construct a new subimage
-    pub fn new(image: &mut I, x: u32, y: u32, width: u32, height: u32) -> SubImage<I> {
+    pub fn new(image: I, x: u32, y: u32, width: u32, height: u32) -> SubImage<I> {
         SubImage {
             image:   image,
             xoffset: x,
@@ -461,11 +1010,6 @@ impl<'a, I: GenericImage + 'static> SubImage<'a, I>
         }
     }
 
-    /// Returns a mutable reference to the wrapped image.
-    pub fn inner_mut(&mut self) -> &mut I {
-        &mut (*self.image)
-    }
-
     /// Change the coordinates of this subimage.
     pub fn change_bounds(&mut self, x: u32, y: u32, width: u32, height: u32) {
         self.xoffset = x;
@@ -473,9 +1017,18 @@ impl<'a, I: GenericImage + 'static> SubImage<'a, I>
         self.xstride = width;
         self.ystride = height;
     }
+}
+
+impl<I> SubImage<I> where I: Deref, I::Target: GenericImageView {
+    /// Returns a reference to the wrapped image.
+    pub fn inner(&self) -> &I::Target {
+        &*self.image
+    }
 
     /// Convert this subimage to an ImageBuffer
-    pub fn to_image(&self) -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>> {
+    pub fn to_image(&self)
+                    -> ImageBuffer<ViewPixel<I>, Vec<<ViewPixel<I> as Pixel>::Subpixel>>
+    where ViewPixel<I>: 'static, <ViewPixel<I> as Pixel>::Subpixel: 'static {
         let mut out = ImageBuffer::new(self.xstride, self.ystride);
 
         for y in 0..self.ystride {
@@ -489,13 +1042,18 @@ impl<'a, I: GenericImage + 'static> SubImage<'a, I>
     }
 }
 
-#[allow(deprecated)]
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-impl<'a, I: GenericImage + 'static> GenericImage for SubImage<'a, I>
-    where I::Pixel: 'static,
-          <I::Pixel as Pixel>::Subpixel: 'static {
+impl<I> SubImage<I> where I: DerefMut, I::Target: GenericImage {
+    /// Returns a mutable reference to the wrapped image.
+    pub fn inner_mut(&mut self) -> &mut I::Target {
+        &mut *self.image
+    }
+}
 
-    type Pixel = I::Pixel;
+/// The pixels of the image a `SubImage` borrows through ```I```
+type ViewPixel<I> = <<I as Deref>::Target as GenericImageView>::Pixel;
+
+impl<I> GenericImageView for SubImage<I> where I: Deref, I::Target: GenericImageView {
+    type Pixel = ViewPixel<I>;
 
     fn dimensions(&self) -> (u32, u32) {
         (self.xstride, self.ystride)
@@ -505,20 +1063,31 @@ impl<'a, I: GenericImage + 'static> GenericImage for SubImage<'a, I>
         (self.xoffset, self.yoffset, self.xstride, self.ystride)
     }
 
-    fn get_pixel(&self, x: u32, y: u32) -> I::Pixel {
+    fn get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
         self.image.get_pixel(x + self.xoffset, y + self.yoffset)
     }
 
-    fn put_pixel(&mut self, x: u32, y: u32, pixel: I::Pixel) {
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        self.image.unsafe_get_pixel(x + self.xoffset, y + self.yoffset)
+    }
+}
+
+#[allow(deprecated)]
+impl<I> GenericImage for SubImage<I> where I: DerefMut, I::Target: GenericImage {
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: ViewPixel<I>) {
         self.image.put_pixel(x + self.xoffset, y + self.yoffset, pixel)
     }
 
+    unsafe fn unsafe_put_pixel(&mut self, x: u32, y: u32, pixel: ViewPixel<I>) {
+        self.image.unsafe_put_pixel(x + self.xoffset, y + self.yoffset, pixel)
+    }
+
     /// DEPRECATED: This method will be removed. Blend the pixel directly instead.
-    fn blend_pixel(&mut self, x: u32, y: u32, pixel: I::Pixel) {
+    fn blend_pixel(&mut self, x: u32, y: u32, pixel: ViewPixel<I>) {
         self.image.blend_pixel(x + self.xoffset, y + self.yoffset, pixel)
     }
 
-    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut I::Pixel {
+    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut ViewPixel<I> {
         self.image.get_pixel_mut(x + self.xoffset, y + self.yoffset)
     }
 }
@@ -526,9 +1095,13 @@ impl<'a, I: GenericImage + 'static> GenericImage for SubImage<'a, I>
 #[cfg(test)]
 mod tests {
 
-    use super::GenericImage;
+    use std::error::Error;
+    use std::io;
+
+    use super::{DecodingErrorKind, GenericImage, GenericImageView, ImageDecoder, ImageError, ImageFormat,
+                ImageFormatHint, LimitErrorKind, Limits, Metadata, ParameterErrorKind};
     use buffer::ImageBuffer;
-    use color::{Rgba};
+    use color::{ColorType, Rgba};
 
     #[test]
     /// Test that alpha blending works as expected
@@ -549,6 +1122,60 @@ mod tests {
         assert!(*target.get_pixel(0, 0) == Rgba([169, 85, 0, 190]));
     }
 
+    #[test]
+    fn test_error_kinds() {
+        let limits = Limits { max_width: Some(10), max_bytes: Some(100), ..Limits::no_limits() };
+        match limits.check_image(11, 1, ColorType::Gray(8)) {
+            Err(ImageError::Limits(LimitErrorKind::Dimensions)) => (),
+            r => panic!("Expected the dimensions to exceed the limits, got {:?}", r),
+        }
+        match limits.check_image(10, 11, ColorType::Gray(8)) {
+            Err(ImageError::Limits(LimitErrorKind::Memory)) => (),
+            r => panic!("Expected the memory to exceed the limits, got {:?}", r),
+        }
+        // The number of bits overflows a u64 instead of wrapping to a small value
+        for &(size, color) in &[(0x8000_0000, ColorType::RGBA(16)), (0x4000_0000, ColorType::RGBA(16)),
+                                (u32::max_value(), ColorType::RGBAF(32))] {
+            match Limits::default().check_image(size, size, color) {
+                Err(ImageError::Limits(LimitErrorKind::Memory)) => (),
+                r => panic!("Expected {}x{} pixels to exceed the memory, got {:?}", size, size, r),
+            }
+            match Limits::no_limits().check_image(size, size, color) {
+                Err(ImageError::Limits(LimitErrorKind::Memory)) => (),
+                r => panic!("Expected {}x{} pixels to exceed any memory, got {:?}", size, size, r),
+            }
+        }
+
+        let err = ImageError::malformed(ImageFormat::PNG, "invalid signature".to_string());
+        assert_eq!(err.to_string(), "Format error decoding PNG: invalid signature");
+        assert!(err.source().is_none());
+        let err = ImageError::unsupported_format(ImageFormatHint::Name("xyz".to_string()));
+        assert_eq!(err.to_string(), "The image format xyz is not supported");
+
+        let err = ImageError::from(io::Error::new(io::ErrorKind::Other, "disconnected"));
+        assert_eq!(err.source().unwrap().to_string(), "disconnected");
+        let err = ImageError::Decoding {
+            format: ImageFormatHint::Unknown,
+            kind: DecodingErrorKind::External(Box::new(io::Error::new(io::ErrorKind::Other, "inner"))),
+        };
+        assert_eq!(err.source().unwrap().to_string(), "inner");
+    }
+
+    #[test]
+    fn test_physical_size() {
+        let metadata = Metadata {
+            dimensions: (300, 150),
+            bit_depth: 8,
+            dpi: Some((150.0, 75.0)),
+            gamma: None,
+            has_icc_profile: false,
+            orientation: None,
+            frame_count: Some(1),
+        };
+        assert_eq!(metadata.physical_size(), Some((2.0, 2.0)));
+        assert_eq!(Metadata { dpi: None, ..metadata }.physical_size(), None);
+    }
+
     #[test]
     fn test_in_bounds() {
         let mut target = ImageBuffer::new(2, 2);
@@ -563,4 +1190,55 @@ mod tests {
         assert!(!target.in_bounds(0,2));
         assert!(!target.in_bounds(2,2));
     }
+
+    #[test]
+    fn test_unsafe_pixel_access() {
+        use dynimage::DynamicImage;
+        let mut target = ImageBuffer::new(3, 3);
+        {
+            let mut sub = target.sub_image(1, 1, 2, 2);
+            unsafe {
+                sub.unsafe_put_pixel(1, 0, Rgba([255u8, 0, 0, 255]));
+                assert_eq!(sub.unsafe_get_pixel(1, 0), Rgba([255, 0, 0, 255]));
+            }
+        }
+        assert_eq!(target.get_pixel(2, 1), &Rgba([255, 0, 0, 255]));
+
+        let mut image = DynamicImage::ImageRgb8(ImageBuffer::new(2, 2));
+        unsafe {
+            image.unsafe_put_pixel(1, 1, Rgba([1, 2, 3, 4]));
+            assert_eq!(image.unsafe_get_pixel(1, 1), Rgba([1, 2, 3, 255]));
+        }
+    }
+
+    #[test]
+    fn test_get_pixel_checked() {
+        use dynimage::DynamicImage;
+        let mut target = ImageBuffer::new(3, 3);
+        target.put_pixel(2, 1, Rgba([255u8, 0, 0, 255]));
+
+        let sub = target.sub_image(1, 1, 2, 2);
+        assert_eq!(sub.get_pixel_checked(1, 0), Some(Rgba([255, 0, 0, 255])));
+        assert_eq!(sub.get_pixel_checked(2, 0), None);
+
+        let image = DynamicImage::ImageRgba8(target);
+        assert_eq!(image.get_pixel_checked(2, 1), Some(Rgba([255, 0, 0, 255])));
+        assert_eq!(image.get_pixel_checked(0, 3), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_read_image_into() {
+        use pnm::PNMDecoder;
+
+        // 16-bit samples are written in big endian order, as they are stored in the file
+        let pgm = b"P5 2 1 65535\n\x12\x34\xab\xcd";
+        let mut buf = [0; 5];
+        assert_eq!(PNMDecoder::new(&pgm[..]).unwrap().read_image_into(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], &pgm[13..]);
+        match PNMDecoder::new(&pgm[..]).unwrap().read_image_into(&mut buf[..3]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => (),
+            r => panic!("Expected the buffer to be too short, got {:?}", r),
+        }
+    }
 }
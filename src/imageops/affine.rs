@@ -1,11 +1,10 @@
 //! Functions for performing affine transformations.
 
 use buffer::{ImageBuffer, Pixel};
-use image::GenericImage;
+use image::GenericImageView;
 
 /// Rotate an image 90 degrees clockwise.
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-pub fn rotate90<I: GenericImage + 'static>(image:  &I)
+pub fn rotate90<I: GenericImageView>(image:  &I)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
@@ -23,8 +22,7 @@ pub fn rotate90<I: GenericImage + 'static>(image:  &I)
 }
 
 /// Rotate an image 180 degrees clockwise.
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-pub fn rotate180<I: GenericImage + 'static>(image:  &I)
+pub fn rotate180<I: GenericImageView>(image:  &I)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
@@ -42,8 +40,7 @@ pub fn rotate180<I: GenericImage + 'static>(image:  &I)
 }
 
 /// Rotate an image 270 degrees clockwise.
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-pub fn rotate270<I: GenericImage + 'static>(image:  &I)
+pub fn rotate270<I: GenericImageView>(image:  &I)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
@@ -61,8 +58,7 @@ pub fn rotate270<I: GenericImage + 'static>(image:  &I)
 }
 
 /// Flip an image horizontally
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-pub fn flip_horizontal<I: GenericImage + 'static>(image:  &I)
+pub fn flip_horizontal<I: GenericImageView>(image:  &I)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
@@ -80,8 +76,7 @@ pub fn flip_horizontal<I: GenericImage + 'static>(image:  &I)
 }
 
 /// Flip an image vertically
-// TODO: Is the 'static bound on `I` really required? Can we avoid it?
-pub fn flip_vertical<I: GenericImage + 'static>(image:  &I)
+pub fn flip_vertical<I: GenericImageView>(image:  &I)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
@@ -102,7 +97,7 @@ pub fn flip_vertical<I: GenericImage + 'static>(image:  &I)
 mod test {
     use super::{rotate90,rotate180,rotate270,flip_horizontal,flip_vertical};
     use buffer::{ImageBuffer,Pixel,GrayImage};
-    use image::{GenericImage};
+    use image::{GenericImageView};
 
     macro_rules! assert_pixels_eq {
         ($actual:expr, $expected:expr) => ({
@@ -200,8 +195,8 @@ mod test {
     }
 
     fn pixel_diffs<I, J, P>(left: &I, right: &J) -> Vec<((u32, u32, P), (u32, u32, P))>
-        where I: GenericImage<Pixel=P>,
-              J: GenericImage<Pixel=P>,
+        where I: GenericImageView<Pixel=P>,
+              J: GenericImageView<Pixel=P>,
               P: Pixel + Eq {
         left.pixels()
             .zip(right.pixels())
@@ -3,14 +3,15 @@
 use color::{Luma, Rgba};
 use buffer::{ImageBuffer, Pixel};
 use traits::Primitive;
-use image::GenericImage;
+use image::{GenericImage, GenericImageView};
 use math::utils::clamp;
 use math::nq;
+use math::quantize;
 use std::f64::consts::PI;
 use num_traits::{Num, NumCast};
 
 /// Convert the supplied image to grayscale
-pub fn grayscale<I: GenericImage>(image: &I)
+pub fn grayscale<I: GenericImageView>(image: &I)
     -> ImageBuffer<Luma<<I::Pixel as Pixel>::Subpixel>, Vec<<I::Pixel as Pixel>::Subpixel>>
     where <I::Pixel as Pixel>::Subpixel: 'static,
           <<I::Pixel as Pixel>::Subpixel as Num>::FromStrRadixErr: 'static {
@@ -47,14 +48,14 @@ pub fn invert<I: GenericImage>(image: &mut I) {
 /// Negative values decrease the contrast and positive values increase the contrast.
 pub fn contrast<I, P, S>(image: &I, contrast: f32)
     -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P>,
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
     let (width, height) = image.dimensions();
     let mut out = ImageBuffer::new(width, height);
 
-    let max = S::max_value();
+    let max = S::default_max_value();
     let max: f32 = NumCast::from(max).unwrap();
 
     let percent = ((100.0 + contrast) / 100.0).powi(2);
@@ -82,7 +83,7 @@ pub fn contrast<I, P, S>(image: &I, contrast: f32)
 /// Negative values decrease the brightness and positive values increase it.
 pub fn brighten<I, P, S>(image: &I, value: i32)
     -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P>,
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
@@ -114,7 +115,7 @@ pub fn brighten<I, P, S>(image: &I, value: i32)
 /// just like the css webkit filter hue-rotate(180)
 pub fn huerotate<I, P, S>(image: &I, value: i32)
     -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P>,
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
@@ -219,6 +220,20 @@ impl ColorMap for nq::NeuQuant {
     }
 }
 
+impl ColorMap for quantize::Palette {
+    type Color = Rgba<u8>;
+
+    #[inline(always)]
+    fn index_of(&self, color: &Rgba<u8>) -> usize {
+        self.index_of(color.channels())
+    }
+
+    #[inline(always)]
+    fn map_color(&self, color: &mut Rgba<u8>) {
+        self.map_pixel(color.channels_mut())
+    }
+}
+
 /// Floyd-Steinberg error diffusion
 fn diffuse_err<P: Pixel<Subpixel=u8>>(pixel: &mut P, error: [i16; 3], factor: i16) {
     for (e, c) in error.iter().zip(pixel.channels_mut().iter_mut()) {
@@ -0,0 +1,240 @@
+//! Compositing images with Porter-Duff operators and blend modes
+
+use num_traits::NumCast;
+
+use buffer::Pixel;
+use image::{GenericImage, GenericImageView};
+use math::utils::clamp;
+use traits::Primitive;
+use super::has_alpha;
+
+/// A Porter-Duff operator, deciding which of the top and bottom image are kept where they overlap
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositeOperator {
+    /// Neither image is kept, the result is transparent
+    Clear,
+    /// Only the top image is kept
+    Source,
+    /// Only the bottom image is kept
+    Destination,
+    /// The top image is drawn over the bottom image, the usual way of layering images
+    SourceOver,
+    /// The bottom image is drawn over the top image
+    DestinationOver,
+    /// The top image is kept where the bottom image is opaque
+    SourceIn,
+    /// The bottom image is kept where the top image is opaque
+    DestinationIn,
+    /// The top image is kept where the bottom image is transparent
+    SourceOut,
+    /// The bottom image is kept where the top image is transparent
+    DestinationOut,
+    /// The top image is drawn over the bottom image, only where the bottom image is opaque
+    SourceAtop,
+    /// The bottom image is drawn over the top image, only where the top image is opaque
+    DestinationAtop,
+    /// Each image is kept where the other one is transparent
+    Xor,
+}
+
+impl CompositeOperator {
+    /// The fractions of the top and of the bottom image that are kept, given their alphas
+    fn fractions(&self, top: f32, bottom: f32) -> (f32, f32) {
+        use self::CompositeOperator::*;
+        match *self {
+            Clear => (0.0, 0.0),
+            Source => (1.0, 0.0),
+            Destination => (0.0, 1.0),
+            SourceOver => (1.0, 1.0 - top),
+            DestinationOver => (1.0 - bottom, 1.0),
+            SourceIn => (bottom, 0.0),
+            DestinationIn => (0.0, top),
+            SourceOut => (1.0 - bottom, 0.0),
+            DestinationOut => (0.0, 1.0 - top),
+            SourceAtop => (bottom, 1.0 - top),
+            DestinationAtop => (1.0 - bottom, top),
+            Xor => (1.0 - bottom, 1.0 - top),
+        }
+    }
+}
+
+/// How the colors of the top image are mixed with the colors of the bottom image below them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The color of the top image replaces the color below it
+    Normal,
+    /// The colors are multiplied, which darkens the bottom image
+    Multiply,
+    /// The inverted colors are multiplied, which lightens the bottom image
+    Screen,
+    /// Dark colors of the bottom image are multiplied, light ones screened
+    Overlay,
+    /// The darker of both colors is kept
+    Darken,
+    /// The lighter of both colors is kept
+    Lighten,
+    /// The colors are added, up to white
+    Add,
+}
+
+impl BlendMode {
+    /// Mixes the ```top``` color into the ```bottom``` color, both in the range 0 to 1
+    fn apply(&self, bottom: f32, top: f32) -> f32 {
+        match *self {
+            BlendMode::Normal => top,
+            BlendMode::Multiply => bottom * top,
+            BlendMode::Screen => bottom + top - bottom * top,
+            BlendMode::Overlay => if bottom <= 0.5 {
+                2.0 * bottom * top
+            } else {
+                1.0 - 2.0 * (1.0 - bottom) * (1.0 - top)
+            },
+            BlendMode::Darken => bottom.min(top),
+            BlendMode::Lighten => bottom.max(top),
+            BlendMode::Add => (bottom + top).min(1.0),
+        }
+    }
+}
+
+/// Composites ```top``` onto ```bottom``` with its top left corner at (x, y)
+///
+/// The colors of ```top``` are first mixed into the colors below them with ```mode```, where
+/// ```bottom``` is opaque. The result is then combined with ```bottom``` by ```operator```, after the
+/// alpha of ```top``` has been scaled by ```opacity```, in the range 0 to 1. Only the pixels of
+/// ```bottom``` covered by ```top``` change. Images without an alpha channel are opaque, pixels
+/// that become transparent are composited onto black.
+///
+/// `overlay` composites like `SourceOver` with `Normal` and an opacity of 1.
+pub fn composite<I, J, S>(bottom: &mut I, top: &J, x: u32, y: u32,
+                          operator: CompositeOperator, mode: BlendMode, opacity: f32)
+    where I: GenericImage,
+          J: GenericImageView<Pixel=I::Pixel>,
+          I::Pixel: Pixel<Subpixel=S>,
+          S: Primitive + 'static {
+
+    let (top_width, top_height) = top.dimensions();
+    let (bottom_width, bottom_height) = bottom.dimensions();
+    if x >= bottom_width || y >= bottom_height {
+        return
+    }
+    let range_width = top_width.min(bottom_width - x);
+    let range_height = top_height.min(bottom_height - y);
+
+    let alpha = has_alpha::<I::Pixel>();
+    let opacity = clamp(opacity, 0.0, 1.0);
+    let max: f32 = NumCast::from(S::default_max_value()).unwrap();
+    let limit: f32 = NumCast::from(S::max_value()).unwrap();
+    let normalize = |sample: S| -> f32 { NumCast::from(sample).map_or(0.0, |s: f32| s / max) };
+    // Integer samples are rounded, floating point samples above 1 are kept
+    let integer = max == limit;
+    let denormalize = |value: f32| -> S {
+        let value = clamp(value * max, 0.0, limit);
+        NumCast::from(if integer { value.round() } else { value }).unwrap()
+    };
+
+    for top_y in 0..range_height {
+        for top_x in 0..range_width {
+            let source = top.get_pixel(top_x, top_y);
+            let mut pixel = bottom.get_pixel(x + top_x, y + top_y);
+            {
+                let source = source.channels();
+                let channels = pixel.channels_mut();
+                let colors = if alpha { channels.len() - 1 } else { channels.len() };
+                let (source_alpha, bottom_alpha) = if alpha {
+                    (normalize(source[colors]) * opacity, normalize(channels[colors]))
+                } else {
+                    (opacity, 1.0)
+                };
+
+                let (fa, fb) = operator.fractions(source_alpha, bottom_alpha);
+                let result_alpha = source_alpha * fa + bottom_alpha * fb;
+                for (sample, &top_sample) in channels[..colors].iter_mut().zip(source) {
+                    let (cb, cs) = (normalize(*sample), normalize(top_sample));
+                    // The blend mode only applies where the bottom image is opaque
+                    let cs = (1.0 - bottom_alpha) * cs + bottom_alpha * mode.apply(cb, cs);
+                    let premultiplied = source_alpha * fa * cs + bottom_alpha * fb * cb;
+                    let color = if !alpha {
+                        premultiplied
+                    } else if result_alpha > 0.0 {
+                        premultiplied / result_alpha
+                    } else {
+                        0.0
+                    };
+                    *sample = denormalize(color);
+                }
+                if alpha {
+                    channels[colors] = denormalize(result_alpha);
+                }
+            }
+            bottom.put_pixel(x + top_x, y + top_y, pixel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{composite, BlendMode, CompositeOperator};
+    use buffer::{ImageBuffer, RgbImage, RgbaImage};
+    use color::{Rgb, Rgba};
+
+    fn composite_pixel(bottom: Rgba<u8>, top: Rgba<u8>, operator: CompositeOperator, mode: BlendMode,
+                       opacity: f32) -> Rgba<u8> {
+        let mut image: RgbaImage = ImageBuffer::from_pixel(1, 1, bottom);
+        composite(&mut image, &ImageBuffer::from_pixel(1, 1, top), 0, 0, operator, mode, opacity);
+        *image.get_pixel(0, 0)
+    }
+
+    #[test]
+    fn operators() {
+        use super::CompositeOperator::*;
+        let red = Rgba([255, 0, 0, 255]);
+        let half_blue = Rgba([0, 0, 255, 128]);
+        let normal = BlendMode::Normal;
+        assert_eq!(composite_pixel(red, half_blue, SourceOver, normal, 1.0), Rgba([127, 0, 128, 255]));
+        assert_eq!(composite_pixel(red, half_blue, DestinationOver, normal, 1.0), red);
+        assert_eq!(composite_pixel(red, half_blue, Source, normal, 1.0), half_blue);
+        assert_eq!(composite_pixel(red, half_blue, Destination, normal, 1.0), red);
+        assert_eq!(composite_pixel(red, half_blue, Clear, normal, 1.0), Rgba([0, 0, 0, 0]));
+        assert_eq!(composite_pixel(red, half_blue, SourceIn, normal, 1.0), half_blue);
+        assert_eq!(composite_pixel(red, half_blue, SourceOut, normal, 1.0), Rgba([0, 0, 0, 0]));
+        assert_eq!(composite_pixel(red, half_blue, DestinationIn, normal, 1.0), Rgba([255, 0, 0, 128]));
+        assert_eq!(composite_pixel(red, half_blue, DestinationOut, normal, 1.0), Rgba([255, 0, 0, 127]));
+        assert_eq!(composite_pixel(red, half_blue, SourceAtop, normal, 1.0), Rgba([127, 0, 128, 255]));
+        assert_eq!(composite_pixel(red, half_blue, Xor, normal, 1.0), Rgba([255, 0, 0, 127]));
+
+        // Opacity scales the alpha of the top image
+        let blue = Rgba([0, 0, 255, 255]);
+        assert_eq!(composite_pixel(red, blue, SourceOver, normal, 0.5), Rgba([128, 0, 128, 255]));
+        assert_eq!(composite_pixel(red, blue, SourceOver, normal, 0.0), red);
+    }
+
+    #[test]
+    fn blend_modes() {
+        use super::BlendMode::*;
+        let over = CompositeOperator::SourceOver;
+        let gray = Rgba([128, 64, 200, 255]);
+        let top = Rgba([64, 255, 100, 255]);
+        assert_eq!(composite_pixel(gray, top, over, Normal, 1.0), top);
+        assert_eq!(composite_pixel(gray, top, over, Multiply, 1.0), Rgba([32, 64, 78, 255]));
+        assert_eq!(composite_pixel(gray, top, over, Screen, 1.0), Rgba([160, 255, 222, 255]));
+        assert_eq!(composite_pixel(gray, top, over, Overlay, 1.0), Rgba([65, 128, 188, 255]));
+        assert_eq!(composite_pixel(gray, top, over, Darken, 1.0), Rgba([64, 64, 100, 255]));
+        assert_eq!(composite_pixel(gray, top, over, Lighten, 1.0), Rgba([128, 255, 200, 255]));
+        assert_eq!(composite_pixel(gray, top, over, Add, 1.0), Rgba([192, 255, 255, 255]));
+
+        // Blend modes only apply where the bottom image is opaque
+        let transparent = Rgba([128, 64, 200, 0]);
+        assert_eq!(composite_pixel(transparent, top, over, Multiply, 1.0), top);
+    }
+
+    #[test]
+    fn without_alpha() {
+        let mut bottom: RgbImage = ImageBuffer::from_pixel(3, 2, Rgb([200, 100, 0]));
+        let top: RgbImage = ImageBuffer::from_pixel(2, 2, Rgb([0, 100, 200]));
+        composite(&mut bottom, &top, 2, 1, CompositeOperator::SourceOver, BlendMode::Normal, 0.5);
+        assert_eq!(bottom.get_pixel(1, 1), &Rgb([200, 100, 0]));
+        assert_eq!(bottom.get_pixel(2, 1), &Rgb([100, 100, 100]));
+        assert_eq!(bottom.get_pixel(2, 0), &Rgb([200, 100, 0]));
+        composite(&mut bottom, &top, 3, 0, CompositeOperator::Clear, BlendMode::Normal, 1.0);
+    }
+}
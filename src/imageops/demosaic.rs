@@ -0,0 +1,237 @@
+//! Functions for reconstructing color images from the mosaics of Bayer color filter arrays
+
+use num_traits::NumCast;
+
+use buffer::ImageBuffer;
+use color::{Luma, Rgb};
+use image::GenericImageView;
+use math::utils::clamp;
+use traits::Primitive;
+
+/// The arrangement of the colors in the 2x2 tile of a Bayer filter that starts at the top left
+/// pixel, named by the colors of its top and bottom row
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BayerPattern {
+    /// Red and green in even rows, green and blue in odd rows
+    RGGB,
+    /// Blue and green in even rows, green and red in odd rows
+    BGGR,
+    /// Green and red in even rows, blue and green in odd rows
+    GRBG,
+    /// Green and blue in even rows, red and green in odd rows
+    GBRG,
+}
+
+impl BayerPattern {
+    /// Returns the channel of the filter at ```x```, ```y```, with 0 for red, 1 for green and 2
+    /// for blue
+    pub fn color(&self, x: u32, y: u32) -> usize {
+        let tile = match *self {
+            BayerPattern::RGGB => [0, 1, 1, 2],
+            BayerPattern::BGGR => [2, 1, 1, 0],
+            BayerPattern::GRBG => [1, 0, 2, 1],
+            BayerPattern::GBRG => [1, 2, 0, 1],
+        };
+        tile[(y as usize % 2) * 2 + x as usize % 2]
+    }
+}
+
+/// An algorithm interpolating the missing colors of a mosaic
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DemosaicAlgorithm {
+    /// Averages the closest samples of each color, which is fast but blurs and fringes edges
+    Bilinear,
+    /// The gradient corrected linear interpolation of Malvar, He and Cutler, which uses the
+    /// sample of each pixel to sharpen the interpolation of the other colors
+    MalvarHeCutler,
+}
+
+/// The offsets and weights of the samples that are interpolated
+type Kernel = &'static [(i32, i32, f32)];
+
+/// The kernels of an algorithm, for each kind of missing color
+struct Kernels {
+    /// Green at red and blue pixels
+    green: Kernel,
+    /// The color of the left and right neighbours at green pixels
+    row: Kernel,
+    /// The color of the top and bottom neighbours at green pixels
+    column: Kernel,
+    /// Red at blue pixels and blue at red pixels
+    diagonal: Kernel,
+}
+
+const BILINEAR: Kernels = Kernels {
+    green: &[(0, -1, 0.25), (-1, 0, 0.25), (1, 0, 0.25), (0, 1, 0.25)],
+    row: &[(-1, 0, 0.5), (1, 0, 0.5)],
+    column: &[(0, -1, 0.5), (0, 1, 0.5)],
+    diagonal: &[(-1, -1, 0.25), (1, -1, 0.25), (-1, 1, 0.25), (1, 1, 0.25)],
+};
+
+const MALVAR_HE_CUTLER: Kernels = Kernels {
+    green: &[
+        (0, -2, -0.125),
+        (0, -1, 0.25),
+        (-2, 0, -0.125), (-1, 0, 0.25), (0, 0, 0.5), (1, 0, 0.25), (2, 0, -0.125),
+        (0, 1, 0.25),
+        (0, 2, -0.125),
+    ],
+    row: &[
+        (0, -2, 0.0625),
+        (-1, -1, -0.125), (1, -1, -0.125),
+        (-2, 0, -0.125), (-1, 0, 0.5), (0, 0, 0.625), (1, 0, 0.5), (2, 0, -0.125),
+        (-1, 1, -0.125), (1, 1, -0.125),
+        (0, 2, 0.0625),
+    ],
+    column: &[
+        (0, -2, -0.125),
+        (-1, -1, -0.125), (0, -1, 0.5), (1, -1, -0.125),
+        (-2, 0, 0.0625), (0, 0, 0.625), (2, 0, 0.0625),
+        (-1, 1, -0.125), (0, 1, 0.5), (1, 1, -0.125),
+        (0, 2, -0.125),
+    ],
+    diagonal: &[
+        (0, -2, -0.1875),
+        (-1, -1, 0.25), (1, -1, 0.25),
+        (-2, 0, -0.1875), (0, 0, 0.75), (2, 0, -0.1875),
+        (-1, 1, 0.25), (1, 1, 0.25),
+        (0, 2, -0.1875),
+    ],
+};
+
+/// Mirrors ```i``` at the edges of ```0..len```, which keeps the color of the filter
+fn reflect(i: i64, len: u32) -> u32 {
+    let last = len as i64 - 1;
+    let i = if i < 0 { -i } else if i > last { 2 * last - i } else { i };
+    // Images smaller than the kernels are clamped instead
+    clamp(i, 0, last) as u32
+}
+
+/// Reconstructs an rgb image from the Bayer mosaic ```image```, whose filters are arranged
+/// as ```pattern```, using ```algorithm```
+///
+/// Each pixel keeps its own sample and the other two colors are interpolated. The samples
+/// beyond the edges are mirrored.
+pub fn demosaic<I, S>(image: &I, pattern: BayerPattern, algorithm: DemosaicAlgorithm)
+    -> ImageBuffer<Rgb<S>, Vec<S>>
+    where I: GenericImageView<Pixel=Luma<S>>,
+          S: Primitive + 'static {
+
+    let kernels = match algorithm {
+        DemosaicAlgorithm::Bilinear => &BILINEAR,
+        DemosaicAlgorithm::MalvarHeCutler => &MALVAR_HE_CUTLER,
+    };
+    let (width, height) = image.dimensions();
+    let max: f32 = NumCast::from(S::max_value()).unwrap();
+    // Integer samples are rounded, floating point samples are kept as they are
+    let round = <S as NumCast>::from(0.5).unwrap() == S::zero();
+
+    let apply = |kernel: Kernel, x: u32, y: u32| -> S {
+        let mut sum = 0.0;
+        for &(dx, dy, weight) in kernel {
+            let p = image.get_pixel(reflect(x as i64 + dx as i64, width), reflect(y as i64 + dy as i64, height));
+            let v: f32 = NumCast::from(p[0]).unwrap();
+            sum += v * weight;
+        }
+        let sum = if round { sum.round() } else { sum };
+        NumCast::from(clamp(sum, 0.0, max)).unwrap()
+    };
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let mut out = [S::zero(); 3];
+        let color = pattern.color(x, y);
+        out[color] = image.get_pixel(x, y)[0];
+        if color == 1 {
+            let row = pattern.color(x ^ 1, y);
+            out[row] = apply(kernels.row, x, y);
+            out[2 - row] = apply(kernels.column, x, y);
+        } else {
+            out[1] = apply(kernels.green, x, y);
+            out[2 - color] = apply(kernels.diagonal, x, y);
+        }
+        Rgb(out)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{demosaic, BayerPattern, DemosaicAlgorithm};
+    use buffer::{GrayImage, ImageBuffer};
+    use color::{Luma, Rgb};
+
+    const PATTERNS: [BayerPattern; 4] = [BayerPattern::RGGB, BayerPattern::BGGR, BayerPattern::GRBG, BayerPattern::GBRG];
+    const ALGORITHMS: [DemosaicAlgorithm; 2] = [DemosaicAlgorithm::Bilinear, DemosaicAlgorithm::MalvarHeCutler];
+
+    /// Samples ```color``` through the filters of ```pattern```
+    fn mosaic<F: Fn(u32, u32) -> [f32; 3]>(width: u32, height: u32, pattern: BayerPattern, color: F)
+                                           -> ImageBuffer<Luma<f32>, Vec<f32>> {
+        ImageBuffer::from_fn(width, height, |x, y| Luma([color(x, y)[pattern.color(x, y)]]))
+    }
+
+    #[test]
+    fn test_patterns() {
+        assert_eq!(BayerPattern::RGGB.color(0, 0), 0);
+        assert_eq!(BayerPattern::RGGB.color(3, 3), 2);
+        assert_eq!(BayerPattern::GRBG.color(1, 0), 0);
+        assert_eq!(BayerPattern::GBRG.color(2, 1), 0);
+        assert_eq!(BayerPattern::BGGR.color(1, 2), 1);
+    }
+
+    #[test]
+    fn test_flat_color() {
+        // A flat color is reconstructed everywhere, including the mirrored edges
+        for &pattern in &PATTERNS {
+            let image: GrayImage = ImageBuffer::from_fn(7, 5, |x, y| Luma([[200, 100, 50][pattern.color(x, y)]]));
+            for &algorithm in &ALGORITHMS {
+                let rgb = demosaic(&image, pattern, algorithm);
+                assert!(rgb.pixels().all(|p| *p == Rgb([200, 100, 50])), "{:?} {:?}", pattern, algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gradient() {
+        // Both algorithms are exact for linear gradients away from the edges
+        let color = |x: u32, y: u32| [x as f32 * 10.0, 100.0 + y as f32 * 5.0, 200.0 - (x + y) as f32 * 4.0];
+        for &pattern in &PATTERNS {
+            let image = mosaic(8, 8, pattern, color);
+            for &algorithm in &ALGORITHMS {
+                let rgb = demosaic(&image, pattern, algorithm);
+                for y in 2..6 {
+                    for x in 2..6 {
+                        let expected = color(x, y);
+                        for c in 0..3 {
+                            assert!((rgb.get_pixel(x, y)[c] - expected[c]).abs() < 1e-3,
+                                    "{:?} {:?} at {}, {}", pattern, algorithm, x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_correction() {
+        // A sharp vertical edge in a gray image, where the gradient correction keeps the green
+        // channel closer to the red and blue samples than bilinear averaging
+        let color = |x: u32, _: u32| if x < 4 { [0.0; 3] } else { [1000.0; 3] };
+        let image = mosaic(8, 8, BayerPattern::RGGB, color);
+        let error = |algorithm| {
+            let rgb = demosaic(&image, BayerPattern::RGGB, algorithm);
+            rgb.pixels().map(|p| (p[0] - p[1]).abs() + (p[2] - p[1]).abs()).sum::<f32>()
+        };
+        assert!(error(DemosaicAlgorithm::MalvarHeCutler) < error(DemosaicAlgorithm::Bilinear));
+
+        // Integer samples are rounded and clamped where the correction overshoots
+        let floats = demosaic(&mosaic(8, 8, BayerPattern::RGGB, |x, _| [if x < 4 { 0.0 } else { 255.0 }; 3]),
+                              BayerPattern::RGGB, DemosaicAlgorithm::MalvarHeCutler);
+        let image: GrayImage = ImageBuffer::from_fn(8, 8, |x, _| Luma([if x < 4 { 0 } else { 255 }]));
+        let rgb = demosaic(&image, BayerPattern::RGGB, DemosaicAlgorithm::MalvarHeCutler);
+        assert!(floats.pixels().any(|p| p[0] < 0.0 || p[1] > 255.0));
+        for (p, f) in rgb.pixels().zip(floats.pixels()) {
+            for c in 0..3 {
+                assert_eq!(p[c], f[c].round().max(0.0).min(255.0) as u8);
+            }
+        }
+    }
+}
@@ -4,9 +4,11 @@ use std::cmp;
 use image:: {
     SubImage,
     GenericImage,
+    GenericImageView,
 };
 
 use buffer::Pixel;
+use color::ColorType;
 
 pub use self::sample::FilterType;
 
@@ -31,7 +33,9 @@ pub use self::affine:: {
 pub use self::sample:: {
     filter3x3,
     resize,
+    resize_with_progress,
     blur,
+    blur_with_progress,
     unsharpen,
 };
 
@@ -48,20 +52,58 @@ pub use self::colorops:: {
     index_colors,
 };
 
+/// Demosaicing
+pub use self::demosaic:: {
+    demosaic,
+    BayerPattern,
+    DemosaicAlgorithm,
+};
+
+/// Tone mapping
+pub use self::tonemap:: {
+    tonemap,
+    ToneMapOperator,
+};
+
+/// Compositing
+pub use self::composite:: {
+    composite,
+    BlendMode,
+    CompositeOperator,
+};
+
+/// Views flipping, rotating and cropping images without copying them
+pub use self::view:: {
+    Flipped,
+    Rotated90,
+    Cropped,
+};
+
 mod affine;
 // Public only because of Rust bug:
 // https://github.com/rust-lang/rust/issues/18241
 pub mod colorops;
+mod composite;
+mod demosaic;
 mod sample;
+mod tonemap;
+mod view;
 
 /// Return a mutable view into an image
-// TODO: Is a 'static bound on `I` really required? Acn we avoid it?
-pub fn crop<I: GenericImage + 'static>(image: &mut I, x: u32, y: u32,
-                                       width: u32, height: u32)
-                                       -> SubImage<I>
-    where I::Pixel: 'static,
-          <I::Pixel as Pixel>::Subpixel: 'static {
+pub fn crop<I: GenericImage>(image: &mut I, x: u32, y: u32, width: u32, height: u32) -> SubImage<&mut I> {
+    let (x, y, width, height) = crop_dimensions(image, x, y, width, height);
+    SubImage::new(image, x, y, width, height)
+}
 
+/// Return an immutable view into an image
+pub fn crop_imm<I: GenericImageView>(image: &I, x: u32, y: u32, width: u32, height: u32) -> SubImage<&I> {
+    let (x, y, width, height) = crop_dimensions(image, x, y, width, height);
+    SubImage::new(image, x, y, width, height)
+}
+
+/// Cuts the rectangle at (x, y) of ```width``` by ```height``` pixels to the bounds of ```image```
+fn crop_dimensions<I: GenericImageView>(image: &I, x: u32, y: u32, width: u32, height: u32)
+                                        -> (u32, u32, u32, u32) {
     let (iwidth, iheight) = image.dimensions();
 
     let x = cmp::min(x, iwidth);
@@ -70,11 +112,15 @@ pub fn crop<I: GenericImage + 'static>(image: &mut I, x: u32, y: u32,
     let height = cmp::min(height, iheight - y);
     let width  = cmp::min(width, iwidth - x);
 
-    SubImage::new(image, x, y, width, height)
+    (x, y, width, height)
 }
 
 /// Overlay an image at a given coordinate (x, y)
-pub fn overlay<I: GenericImage>(bottom: &mut I, top: &I, x: u32, y:u32) {
+///
+/// `composite` combines images with other operators, blend modes and opacities.
+pub fn overlay<I, J>(bottom: &mut I, top: &J, x: u32, y:u32)
+    where I: GenericImage,
+          J: GenericImageView<Pixel=I::Pixel> {
     let (top_width, top_height) = top.dimensions();
     let (bottom_width, bottom_height) = bottom.dimensions();
 
@@ -103,7 +149,9 @@ pub fn overlay<I: GenericImage>(bottom: &mut I, top: &I, x: u32, y:u32) {
 }
 
 /// Replace the contents of an image at a given coordinate (x, y)
-pub fn replace<I: GenericImage>(bottom: &mut I, top: &I, x: u32, y:u32) {
+pub fn replace<I, J>(bottom: &mut I, top: &J, x: u32, y:u32)
+    where I: GenericImage,
+          J: GenericImageView<Pixel=I::Pixel> {
     let (top_width, top_height) = top.dimensions();
     let (bottom_width, bottom_height) = bottom.dimensions();
 
@@ -128,12 +176,22 @@ pub fn replace<I: GenericImage>(bottom: &mut I, top: &I, x: u32, y:u32) {
     }
 }
 
+/// Returns whether the last channel of pixels of type ```P``` is an alpha channel
+fn has_alpha<P: Pixel>() -> bool {
+    match P::color_type() {
+        ColorType::GrayA(_) | ColorType::RGBA(_) | ColorType::BGRA(_) |
+        ColorType::GrayAF(_) | ColorType::RGBAF(_) => true,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use buffer::ImageBuffer;
-    use color::{Rgb};
-    use super::overlay;
+    use color::{Luma, Rgb};
+    use image::GenericImageView;
+    use super::{crop_imm, flip_horizontal, overlay, replace};
 
     #[test]
     /// Test that images written into other images works
@@ -159,4 +217,19 @@ mod tests {
         assert!(*target.get_pixel(31, 31) == Rgb([255u8, 0, 0]));
     }
 
+    #[test]
+    /// Test that immutable views of images can be processed and copied
+    fn test_immutable_view() {
+        let source = ImageBuffer::from_fn(4, 4, |x, y| Luma([(4 * y + x) as u8]));
+        let view = crop_imm(&source, 2, 1, 8, 2);
+        assert_eq!(view.dimensions(), (2, 2));
+        assert_eq!(flip_horizontal(&view).into_raw(), vec![7, 6, 11, 10]);
+
+        // The samples of a borrowed slice can only be viewed
+        let samples = source.clone().into_raw();
+        let borrowed: ImageBuffer<Luma<u8>, &[u8]> = ImageBuffer::from_raw(4, 4, &samples[..]).unwrap();
+        let mut target = ImageBuffer::new(3, 3);
+        replace(&mut target, &borrowed.view(1, 1, 2, 2), 1, 0);
+        assert_eq!(target.into_raw(), vec![0, 5, 6, 0, 9, 10, 0, 0, 0]);
+    }
 }
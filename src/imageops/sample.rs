@@ -9,8 +9,10 @@ use num_traits::NumCast;
 
 use buffer::{ImageBuffer, Pixel};
 use traits::Primitive;
-use image::GenericImage;
+use image::{GenericImage, GenericImageView, ImageResult};
 use math::utils::clamp;
+use super::has_alpha;
+use monitor::Monitor;
 
 /// Available Sampling Filters
 #[derive(Clone, Copy)]
@@ -126,20 +128,75 @@ pub fn box_kernel(x: f32) -> f32 {
     }
 }
 
+// The index of the alpha channel of pixels of type ```P```, if they have one.
+fn alpha_index<P: Pixel>() -> Option<usize> {
+    if has_alpha::<P>() {
+        Some(P::channel_count() as usize - 1)
+    } else {
+        None
+    }
+}
+
+// Adds the channels of ```pixel``` weighted by ```w``` to the sums in ```t```.
+// With an alpha channel at ```alpha``` the colors are premultiplied by the alpha first, so that
+// transparent pixels do not bleed their color into their neighbours.
+fn accumulate<P: Pixel>(t: &mut [f32; 4], pixel: &P, w: f32, alpha: Option<usize>) {
+    let (k1, k2, k3, k4) = pixel.channels4();
+    let mut vec: [f32; 4] = [
+        NumCast::from(k1).unwrap(),
+        NumCast::from(k2).unwrap(),
+        NumCast::from(k3).unwrap(),
+        NumCast::from(k4).unwrap()
+    ];
+    if let Some(a) = alpha {
+        let alpha = vec[a];
+        for (i, c) in vec.iter_mut().enumerate() {
+            if i != a {
+                *c *= alpha;
+            }
+        }
+    }
+    for (sum, c) in t.iter_mut().zip(vec.iter()) {
+        *sum += c * w;
+    }
+}
+
+// Turns the sums of ```accumulate``` with a total weight of ```sum``` back into a pixel,
+// dividing the premultiplied colors by their alpha.
+fn weighted_average<P: Pixel>(t: [f32; 4], sum: f32, alpha: Option<usize>, max: f32) -> P {
+    let mut t = [t[0] / sum, t[1] / sum, t[2] / sum, t[3] / sum];
+    if let Some(a) = alpha {
+        let alpha = t[a];
+        for (i, c) in t.iter_mut().enumerate() {
+            if i != a {
+                *c = if alpha > 0.0 { *c / alpha } else { 0.0 };
+            }
+        }
+    }
+    Pixel::from_channels(
+        NumCast::from(clamp(t[0], 0.0, max)).unwrap(),
+        NumCast::from(clamp(t[1], 0.0, max)).unwrap(),
+        NumCast::from(clamp(t[2], 0.0, max)).unwrap(),
+        NumCast::from(clamp(t[3], 0.0, max)).unwrap()
+    )
+}
+
 // Sample the rows of the supplied image using the provided filter.
 // The height of the image remains unchanged.
 // ```new_width``` is the desired width of the new image
 // ```filter``` is the filter to use for sampling.
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
+// Each row is reported to ```monitor``` as one more of ```total``` units after ```done```.
 fn horizontal_sample<I, P, S>(image: &I, new_width: u32,
-                              filter: &mut Filter)
-    -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P> + 'static,
+                              filter: &mut Filter,
+                              monitor: &mut Monitor, done: u64, total: u64)
+    -> ImageResult<ImageBuffer<P, Vec<S>>>
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
     let (width, height) = image.dimensions();
     let mut out = ImageBuffer::new(new_width, height);
+    let alpha = alpha_index::<P>();
 
     for y in 0..height {
         let max = S::max_value();
@@ -196,7 +253,7 @@ fn horizontal_sample<I, P, S>(image: &I, new_width: u32,
 
             let mut sum = 0.;
 
-            let mut t = (0., 0., 0., 0.);
+            let mut t = [0.; 4];
 
             for i in left..right + 1 {
                 let w = (filter.kernel)(i as f32 - inputx);
@@ -205,48 +262,35 @@ fn horizontal_sample<I, P, S>(image: &I, new_width: u32,
                 let x0  = clamp(i, 0, width - 1);
                 let p = image.get_pixel(x0, y);
 
-                let (k1, k2, k3, k4) = p.channels4();
-                let vec: (f32, f32, f32, f32) = (
-                    NumCast::from(k1).unwrap(),
-                    NumCast::from(k2).unwrap(),
-                    NumCast::from(k3).unwrap(),
-                    NumCast::from(k4).unwrap()
-                );
-
-                t.0 += vec.0 * w; t.1 += vec.1 * w;
-                t.2 += vec.2 * w; t.3 += vec.3 * w;
+                accumulate(&mut t, &p, w, alpha);
             }
 
-            let (t1, t2, t3, t4) = (t.0 / sum, t.1 / sum, t.2 / sum, t.3 / sum);
-            let t = Pixel::from_channels(
-                NumCast::from(clamp(t1, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t2, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t3, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t4, 0.0, max)).unwrap()
-            );
+            let t = weighted_average(t, sum, alpha, max);
 
             out.put_pixel(outx, y, t);
         }
+        try!(monitor.update(done + y as u64 + 1, total));
     }
 
-    out
+    Ok(out)
 }
 
 // Sample the columns of the supplied image using the provided filter.
 // The width of the image remains unchanged.
 // ```new_height``` is the desired height of the new image
 // ```filter``` is the filter to use for sampling.
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
+// Each column is reported to ```monitor``` as one more of ```total``` units after ```done```.
 fn vertical_sample<I, P, S>(image: &I, new_height: u32,
-                            filter: &mut Filter)
-    -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P> + 'static,
+                            filter: &mut Filter,
+                            monitor: &mut Monitor, done: u64, total: u64)
+    -> ImageResult<ImageBuffer<P, Vec<S>>>
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
     let (width, height) = image.dimensions();
     let mut out = ImageBuffer::new(width, new_height);
-
+    let alpha = alpha_index::<P>();
 
     for x in 0..width {
         let max = S::max_value();
@@ -277,7 +321,7 @@ fn vertical_sample<I, P, S>(image: &I, new_height: u32,
 
             let mut sum = 0.;
 
-            let mut t = (0., 0., 0., 0.);
+            let mut t = [0.; 4];
 
             for i in left..right + 1 {
                 let w = (filter.kernel)(i as f32 - inputy);
@@ -286,39 +330,24 @@ fn vertical_sample<I, P, S>(image: &I, new_height: u32,
                 let y0  = clamp(i, 0, height - 1);
                 let p = image.get_pixel(x, y0);
 
-                let (k1, k2, k3, k4) = p.channels4();
-                let vec: (f32, f32, f32, f32) = (
-                    NumCast::from(k1).unwrap(),
-                    NumCast::from(k2).unwrap(),
-                    NumCast::from(k3).unwrap(),
-                    NumCast::from(k4).unwrap()
-                );
-
-                t.0 += vec.0 * w; t.1 += vec.1 * w;
-                t.2 += vec.2 * w; t.3 += vec.3 * w;
+                accumulate(&mut t, &p, w, alpha);
             }
 
-            let (t1, t2, t3, t4) = (t.0 / sum, t.1 / sum, t.2 / sum, t.3 / sum);
-            let t = Pixel::from_channels(
-                NumCast::from(clamp(t1, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t2, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t3, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t4, 0.0, max)).unwrap()
-            );
+            let t = weighted_average(t, sum, alpha, max);
 
             out.put_pixel(x, outy, t);
         }
+        try!(monitor.update(done + x as u64 + 1, total));
     }
 
-    out
+    Ok(out)
 }
 
 /// Perform a 3x3 box filter on the supplied image.
 /// ```kernel``` is an array of the filter weights of length 9.
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
 pub fn filter3x3<I, P, S>(image: &I, kernel: &[f32])
     -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P> + 'static,
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
@@ -355,7 +384,8 @@ pub fn filter3x3<I, P, S>(image: &I, kernel: &[f32])
                 let x0 = x as isize + a;
                 let y0 = y as isize + b;
 
-                let p = image.get_pixel(x0 as u32, y0 as u32);
+                // The neighbours of pixels off the border are inside the image
+                let p = unsafe { image.unsafe_get_pixel(x0 as u32, y0 as u32) };
 
                 let (k1, k2, k3, k4) = p.channels4();
 
@@ -379,7 +409,7 @@ pub fn filter3x3<I, P, S>(image: &I, kernel: &[f32])
                 NumCast::from(clamp(t4, 0.0, max)).unwrap()
             );
 
-            out.put_pixel(x, y, t);
+            unsafe { out.unsafe_put_pixel(x, y, t) };
         }
     }
 
@@ -389,10 +419,23 @@ pub fn filter3x3<I, P, S>(image: &I, kernel: &[f32])
 /// Resize the supplied image to the specified dimensions.
 /// ```nwidth``` and ```nheight``` are the new dimensions.
 /// ```filter``` is the sampling filter to use.
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
-pub fn resize<I: GenericImage + 'static>(image: &I, nwidth: u32, nheight: u32,
+/// Images with an alpha channel are filtered with premultiplied alpha, the color of transparent
+/// pixels does not bleed into their neighbours.
+pub fn resize<I: GenericImageView>(image: &I, nwidth: u32, nheight: u32,
                                          filter: FilterType)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+    where I::Pixel: 'static,
+          <I::Pixel as Pixel>::Subpixel: 'static {
+    // Without a `CancelToken` the monitor never fails
+    resize_with_progress(image, nwidth, nheight, filter, &mut Monitor::new()).unwrap()
+}
+
+/// Resize the supplied image like `resize`, reporting the progress to ```monitor```.
+/// The columns of the supplied image are sampled first, then the rows of the new image, each
+/// is one unit of the progress.
+pub fn resize_with_progress<I: GenericImageView>(image: &I, nwidth: u32, nheight: u32,
+                                                       filter: FilterType, monitor: &mut Monitor)
+    -> ImageResult<ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
 
@@ -419,15 +462,28 @@ pub fn resize<I: GenericImage + 'static>(image: &I, nwidth: u32, nheight: u32,
         },
 };
 
-    let tmp = vertical_sample(image, nheight, &mut method);
-    horizontal_sample(&tmp, nwidth, &mut method)
+    let columns = image.width() as u64;
+    let total = columns + nheight as u64;
+    let tmp = try!(vertical_sample(image, nheight, &mut method, monitor, 0, total));
+    horizontal_sample(&tmp, nwidth, &mut method, monitor, columns, total)
 }
 
 /// Performs a Gaussian blur on the supplied image.
 /// ```sigma``` is a measure of how much to blur by.
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
-pub fn blur<I: GenericImage + 'static>(image: &I, sigma: f32)
+/// Images with an alpha channel are blurred with premultiplied alpha, like `resize`.
+pub fn blur<I: GenericImageView>(image: &I, sigma: f32)
     -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+    where I::Pixel: 'static,
+          <I::Pixel as Pixel>::Subpixel: 'static {
+    // Without a `CancelToken` the monitor never fails
+    blur_with_progress(image, sigma, &mut Monitor::new()).unwrap()
+}
+
+/// Performs a Gaussian blur like `blur`, reporting the progress to ```monitor```.
+/// The columns of the supplied image are blurred first, then its rows, each is one unit of
+/// the progress.
+pub fn blur_with_progress<I: GenericImageView>(image: &I, sigma: f32, monitor: &mut Monitor)
+    -> ImageResult<ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>>
     where I::Pixel: 'static,
           <I::Pixel as Pixel>::Subpixel: 'static {
 
@@ -446,8 +502,9 @@ pub fn blur<I: GenericImage + 'static>(image: &I, sigma: f32)
 
     // Keep width and height the same for horizontal and
     // vertical sampling.
-    let tmp = vertical_sample(image, height, &mut method);
-    horizontal_sample(&tmp, width, &mut method)
+    let total = width as u64 + height as u64;
+    let tmp = try!(vertical_sample(image, height, &mut method, monitor, 0, total));
+    horizontal_sample(&tmp, width, &mut method, monitor, width as u64, total)
 }
 
 /// Performs an unsharpen mask on the supplied image.
@@ -455,10 +512,9 @@ pub fn blur<I: GenericImage + 'static>(image: &I, sigma: f32)
 /// ```threshold``` is the threshold for the difference between
 ///
 /// See <https://en.wikipedia.org/wiki/Unsharp_masking#Digital_unsharp_masking>
-// TODO: Do we really need the 'static bound on `I`? Can we avoid it?
 pub fn unsharpen<I, P, S>(image: &I, sigma: f32, threshold: i32)
     -> ImageBuffer<P, Vec<S>>
-    where I: GenericImage<Pixel=P> + 'static,
+    where I: GenericImageView<Pixel=P>,
           P: Pixel<Subpixel=S> + 'static,
           S: Primitive + 'static {
 
@@ -499,8 +555,11 @@ pub fn unsharpen<I, P, S>(image: &I, sigma: f32, threshold: i32)
 mod tests {
     #[cfg(feature = "benchmarks")]
     use test;
-    use buffer::{ImageBuffer, RgbImage};
-    use super::{resize, FilterType};
+    use buffer::{ImageBuffer, RgbImage, RgbaImage};
+    use color::Rgba;
+    use image::ImageError;
+    use monitor::{CancelToken, Monitor};
+    use super::{blur, resize, resize_with_progress, FilterType};
 
     #[bench]
     #[cfg(all(feature = "benchmarks", feature = "png_codec"))]
@@ -519,4 +578,39 @@ mod tests {
         let _ = resize(&img, 50, 50, FilterType::Lanczos3);
     }
 
+    #[test]
+    fn transparent_pixels_do_not_bleed() {
+        let img: RgbaImage = ImageBuffer::from_fn(4, 4, |x, _| if x < 2 {
+            Rgba([255, 0, 0, 0])
+        } else {
+            Rgba([0, 0, 255, 255])
+        });
+        let resized = resize(&img, 1, 1, FilterType::Triangle);
+        assert_eq!(resized.get_pixel(0, 0), &Rgba([0, 0, 255, 127]));
+
+        let blurred = blur(&img, 1.0);
+        assert!(blurred.pixels().all(|pixel| pixel[0] == 0));
+        assert!(blurred.get_pixel(1, 0)[2] > 250);
+    }
+
+    #[test]
+    fn resize_progress() {
+        let img: RgbImage = ImageBuffer::new(40, 30);
+        let mut last = (0, 0);
+        {
+            let mut monitor = Monitor::new().with_callback(|done, total| last = (done, total));
+            let resized = resize_with_progress(&img, 20, 10, FilterType::Triangle, &mut monitor).unwrap();
+            assert_eq!(resized.dimensions(), (20, 10));
+        }
+        // The 40 columns of the image, then the 10 rows of the resized one
+        assert_eq!(last, (50, 50));
+
+        let token = CancelToken::new();
+        token.cancel();
+        let mut monitor = Monitor::new().with_cancel_token(token);
+        match resize_with_progress(&img, 20, 10, FilterType::Triangle, &mut monitor) {
+            Err(ImageError::Cancelled) => (),
+            r => panic!("Expected resizing to be cancelled, got {:?}", r.is_ok()),
+        }
+    }
 }
@@ -0,0 +1,129 @@
+//! Functions for mapping high dynamic range images to 8-bit images
+
+use buffer::{ImageBuffer, Pixel};
+use color::ColorType;
+use color::transfer::linear_to_srgb;
+use image::GenericImageView;
+use math::utils::unit_to_sample;
+
+/// Parameters of the Uncharted 2 filmic curve
+const HABLE_A: f32 = 0.15;
+const HABLE_B: f32 = 0.50;
+const HABLE_C: f32 = 0.10;
+const HABLE_D: f32 = 0.20;
+const HABLE_E: f32 = 0.02;
+const HABLE_F: f32 = 0.30;
+/// The linear value that the filmic curve maps to white
+const HABLE_WHITE: f32 = 11.2;
+/// The exposure the filmic curve is designed for
+const HABLE_EXPOSURE_BIAS: f32 = 2.0;
+
+/// An operator compressing linear values of any brightness into the range 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// ```x / (1 + x)```, which keeps dark values and never quite reaches white
+    Reinhard,
+    /// The filmic curve by John Hable, with a toe in the shadows and white at 11.2
+    Filmic,
+    /// Krzysztof Narkowicz' fit of the ACES filmic reference rendering transform
+    Aces,
+}
+
+impl ToneMapOperator {
+    /// Maps a non-negative linear value to the range 0 to 1
+    fn apply(&self, x: f32) -> f32 {
+        match *self {
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::Filmic => hable(x * HABLE_EXPOSURE_BIAS) / hable(HABLE_WHITE),
+            ToneMapOperator::Aces => {
+                (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+            }
+        }
+    }
+}
+
+fn hable(x: f32) -> f32 {
+    ((x * (HABLE_A * x + HABLE_C * HABLE_B) + HABLE_D * HABLE_E)
+        / (x * (HABLE_A * x + HABLE_B) + HABLE_D * HABLE_F)) - HABLE_E / HABLE_F
+}
+
+/// Tone maps the linear ```f32``` image ```image``` to an 8-bit sRGB image of the same
+/// color model using ```operator```
+///
+/// The colors are scaled by 2<sup>```exposure```</sup> first, so that every step of
+/// ```exposure``` doubles or halves the brightness. Alpha channels are clamped to the range
+/// 0 to 1 but are not tone mapped.
+///
+/// # Panics
+///
+/// Panics if the output pixel type has a different number of channels than the input.
+pub fn tonemap<I, P>(image: &I, operator: ToneMapOperator, exposure: f32) -> ImageBuffer<P, Vec<u8>>
+    where I: GenericImageView,
+          I::Pixel: Pixel<Subpixel=f32>,
+          P: Pixel<Subpixel=u8> + 'static {
+
+    assert_eq!(I::Pixel::channel_count(), P::channel_count());
+    let alpha = match I::Pixel::color_type() {
+        ColorType::GrayAF(_) => Some(1),
+        ColorType::RGBAF(_) => Some(3),
+        _ => None,
+    };
+    let scale = exposure.exp2();
+
+    let (width, height) = image.dimensions();
+    let mut out: ImageBuffer<P, Vec<u8>> = ImageBuffer::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        let p = image.get_pixel(x, y);
+        for (i, (&c, o)) in p.channels().iter().zip(pixel.channels_mut()).enumerate() {
+            *o = if Some(i) == alpha {
+                unit_to_sample(c)
+            } else if c * scale > 0.0 {
+                // All operators are at white long before the limit, which keeps infinity finite
+                unit_to_sample(linear_to_srgb(operator.apply((c * scale).min(1e6))))
+            } else {
+                0
+            };
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tonemap, ToneMapOperator};
+    use buffer::{ImageBuffer, GrayAlphaImage, RgbImage};
+    use color::{LumaA, Rgb};
+
+    #[test]
+    fn operators() {
+        let values = [0.0, 0.05, 0.18, 1.0, 4.0, 16.0, 1000.0, ::std::f32::INFINITY];
+        for &operator in &[ToneMapOperator::Reinhard, ToneMapOperator::Filmic, ToneMapOperator::Aces] {
+            let mapped: Vec<f32> = values[..7].iter().map(|&v| operator.apply(v)).collect();
+            assert!(mapped[0].abs() < 1e-3, "{:?} maps black to {}", operator, mapped[0]);
+            assert!(mapped.windows(2).all(|w| w[0] < w[1]), "{:?} is not increasing: {:?}", operator, mapped);
+        }
+        assert_eq!(ToneMapOperator::Reinhard.apply(1.0), 0.5);
+        assert!((ToneMapOperator::Filmic.apply(super::HABLE_WHITE / super::HABLE_EXPOSURE_BIAS) - 1.0).abs() < 1e-6);
+        assert!((ToneMapOperator::Aces.apply(0.18) - 0.267).abs() < 1e-3);
+
+        let hdr = ImageBuffer::from_fn(values.len() as u32, 1, |x, _| Rgb([values[x as usize], -1.0, ::std::f32::NAN]));
+        let ldr: RgbImage = tonemap(&hdr, ToneMapOperator::Aces, 0.0);
+        let red: Vec<u8> = ldr.pixels().map(|p| p[0]).collect();
+        assert_eq!(red, vec![0, 59, 141, 232, 252, 255, 255, 255]);
+        assert!(ldr.pixels().all(|p| p[1] == 0 && p[2] == 0));
+    }
+
+    #[test]
+    fn exposure_and_alpha() {
+        let hdr = ImageBuffer::from_fn(3, 1, |x, _| LumaA([0.25, [0.0, 0.5, 2.0][x as usize]]));
+        let dark: GrayAlphaImage = tonemap(&hdr, ToneMapOperator::Reinhard, 0.0);
+        let bright: GrayAlphaImage = tonemap(&hdr, ToneMapOperator::Reinhard, 2.0);
+
+        // 0.25 / 1.25 and 1 / 2 in sRGB
+        assert!(dark.pixels().all(|p| p[0] == 124));
+        assert!(bright.pixels().all(|p| p[0] == 188));
+        let alpha: Vec<u8> = bright.pixels().map(|p| p[1]).collect();
+        assert_eq!(alpha, vec![0, 128, 255]);
+    }
+}
@@ -0,0 +1,271 @@
+//! Views flipping, rotating and cropping images without copying their pixels.
+//!
+//! The views borrow the image through ```I```, a reference to it, and compute the coordinates of
+//! the pixels they read on the fly. Views can be stacked, and copied into a new buffer once at the
+//! end with `to_image`.
+
+use std::cmp;
+use std::ops::Deref;
+
+use buffer::{ImageBuffer, Pixel};
+use image::GenericImageView;
+
+/// The pixels of the image a view borrows through ```I```
+type ViewPixel<I> = <<I as Deref>::Target as GenericImageView>::Pixel;
+
+/// Copies the pixels of ```view``` into a new buffer
+fn to_image<V: GenericImageView>(view: &V) -> ImageBuffer<V::Pixel, Vec<<V::Pixel as Pixel>::Subpixel>>
+    where V::Pixel: 'static,
+          <V::Pixel as Pixel>::Subpixel: 'static {
+    let (width, height) = view.dimensions();
+    let mut out = ImageBuffer::new(width, height);
+    for (x, y, pixel) in out.enumerate_pixels_mut() {
+        *pixel = view.get_pixel(x, y);
+    }
+    out
+}
+
+/// A view of an image mirrored horizontally, vertically or both
+pub struct Flipped<I> {
+    image: I,
+    horizontally: bool,
+    vertically: bool,
+}
+
+impl<I> Flipped<I> where I: Deref, I::Target: GenericImageView {
+    /// Views ```image``` mirrored left to right if ```horizontally``` is set and top to bottom if
+    /// ```vertically``` is set
+    ///
+    /// Flipping both ways rotates the image by 180 degrees.
+    pub fn new(image: I, horizontally: bool, vertically: bool) -> Flipped<I> {
+        Flipped {
+            image: image,
+            horizontally: horizontally,
+            vertically: vertically,
+        }
+    }
+
+    /// Returns a reference to the wrapped image.
+    pub fn inner(&self) -> &I::Target {
+        &*self.image
+    }
+
+    /// Copies the pixels of this view into a new buffer
+    pub fn to_image(&self) -> ImageBuffer<ViewPixel<I>, Vec<<ViewPixel<I> as Pixel>::Subpixel>>
+        where ViewPixel<I>: 'static,
+              <ViewPixel<I> as Pixel>::Subpixel: 'static {
+        to_image(self)
+    }
+
+    fn source(&self, x: u32, y: u32) -> (u32, u32) {
+        let (width, height) = self.image.dimensions();
+        let x = if self.horizontally { width - 1 - x } else { x };
+        let y = if self.vertically { height - 1 - y } else { y };
+        (x, y)
+    }
+}
+
+impl<I> GenericImageView for Flipped<I> where I: Deref, I::Target: GenericImageView {
+    type Pixel = ViewPixel<I>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        let (width, height) = self.dimensions();
+        (0, 0, width, height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        let (width, height) = self.dimensions();
+        assert!(x < width && y < height, "Pixel ({}, {}) is out of bounds", x, y);
+        let (x, y) = self.source(x, y);
+        self.image.get_pixel(x, y)
+    }
+
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        let (x, y) = self.source(x, y);
+        self.image.unsafe_get_pixel(x, y)
+    }
+}
+
+/// A view of an image rotated 90 degrees clockwise
+///
+/// Views rotated by 270 degrees are rotated by 90 degrees on top of a `Flipped` view flipping both
+/// ways.
+pub struct Rotated90<I> {
+    image: I,
+}
+
+impl<I> Rotated90<I> where I: Deref, I::Target: GenericImageView {
+    /// Views ```image``` rotated 90 degrees clockwise
+    pub fn new(image: I) -> Rotated90<I> {
+        Rotated90 {
+            image: image,
+        }
+    }
+
+    /// Returns a reference to the wrapped image.
+    pub fn inner(&self) -> &I::Target {
+        &*self.image
+    }
+
+    /// Copies the pixels of this view into a new buffer
+    pub fn to_image(&self) -> ImageBuffer<ViewPixel<I>, Vec<<ViewPixel<I> as Pixel>::Subpixel>>
+        where ViewPixel<I>: 'static,
+              <ViewPixel<I> as Pixel>::Subpixel: 'static {
+        to_image(self)
+    }
+
+    fn source(&self, x: u32, y: u32) -> (u32, u32) {
+        // The left column of the view is the bottom row of the image
+        let (_, height) = self.image.dimensions();
+        (y, height - 1 - x)
+    }
+}
+
+impl<I> GenericImageView for Rotated90<I> where I: Deref, I::Target: GenericImageView {
+    type Pixel = ViewPixel<I>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.image.dimensions();
+        (height, width)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        let (width, height) = self.dimensions();
+        (0, 0, width, height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        let (width, height) = self.dimensions();
+        assert!(x < width && y < height, "Pixel ({}, {}) is out of bounds", x, y);
+        let (x, y) = self.source(x, y);
+        self.image.get_pixel(x, y)
+    }
+
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        let (x, y) = self.source(x, y);
+        self.image.unsafe_get_pixel(x, y)
+    }
+}
+
+/// A view of a rectangle of an image
+///
+/// Unlike a `SubImage`, the rectangle is cut to the bounds of the image and the view only reads
+/// its pixels.
+pub struct Cropped<I> {
+    image: I,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl<I> Cropped<I> where I: Deref, I::Target: GenericImageView {
+    /// Views the rectangle of ```width``` by ```height``` pixels at (x, y) of ```image```, cut to
+    /// the bounds of ```image```
+    pub fn new(image: I, x: u32, y: u32, width: u32, height: u32) -> Cropped<I> {
+        let (iwidth, iheight) = image.dimensions();
+        let x = cmp::min(x, iwidth);
+        let y = cmp::min(y, iheight);
+        Cropped {
+            image: image,
+            x: x,
+            y: y,
+            width: cmp::min(width, iwidth - x),
+            height: cmp::min(height, iheight - y),
+        }
+    }
+
+    /// Returns a reference to the wrapped image.
+    pub fn inner(&self) -> &I::Target {
+        &*self.image
+    }
+
+    /// Copies the pixels of this view into a new buffer
+    pub fn to_image(&self) -> ImageBuffer<ViewPixel<I>, Vec<<ViewPixel<I> as Pixel>::Subpixel>>
+        where ViewPixel<I>: 'static,
+              <ViewPixel<I> as Pixel>::Subpixel: 'static {
+        to_image(self)
+    }
+}
+
+impl<I> GenericImageView for Cropped<I> where I: Deref, I::Target: GenericImageView {
+    type Pixel = ViewPixel<I>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, self.width, self.height)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds", x, y);
+        self.image.get_pixel(x + self.x, y + self.y)
+    }
+
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> ViewPixel<I> {
+        self.image.unsafe_get_pixel(x + self.x, y + self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cropped, Flipped, Rotated90};
+    use buffer::{GrayImage, ImageBuffer};
+    use color::Luma;
+    use image::GenericImageView;
+    use imageops::{flip_horizontal, flip_vertical, rotate180, rotate270, rotate90};
+
+    fn image() -> GrayImage {
+        ImageBuffer::from_fn(3, 2, |x, y| Luma([(10 * y + x) as u8]))
+    }
+
+    #[test]
+    fn flipped() {
+        let image = image();
+        let horizontal = Flipped::new(&image, true, false).to_image();
+        assert_eq!(horizontal.into_raw(), flip_horizontal(&image).into_raw());
+        let vertical = Flipped::new(&image, false, true).to_image();
+        assert_eq!(vertical.into_raw(), flip_vertical(&image).into_raw());
+        let both = Flipped::new(&image, true, true).to_image();
+        assert_eq!(both.into_raw(), rotate180(&image).into_raw());
+        let neither = Flipped::new(&image, false, false).to_image();
+        assert_eq!(neither.into_raw(), image.into_raw());
+    }
+
+    #[test]
+    fn rotated() {
+        let image = image();
+        let rotated = Rotated90::new(&image);
+        assert_eq!(rotated.dimensions(), (2, 3));
+        assert_eq!(rotated.to_image().into_raw(), rotate90(&image).into_raw());
+        let flipped = Flipped::new(&image, true, true);
+        assert_eq!(Rotated90::new(&flipped).to_image().into_raw(), rotate270(&image).into_raw());
+    }
+
+    #[test]
+    fn cropped() {
+        let image = image();
+        let cropped = Cropped::new(&image, 1, 1, 5, 5);
+        assert_eq!(cropped.dimensions(), (2, 1));
+        assert_eq!(cropped.to_image().into_raw(), vec![11, 12]);
+        assert_eq!(Cropped::new(&image, 4, 0, 1, 1).dimensions(), (0, 1));
+
+        // Views stack without copying until the end
+        let rotated = Rotated90::new(&cropped);
+        let flipped = Flipped::new(&rotated, false, true);
+        assert_eq!(flipped.to_image().into_raw(), vec![12, 11]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_bounds() {
+        let image = image();
+        Rotated90::new(&image).get_pixel(2, 0);
+    }
+}
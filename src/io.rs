@@ -0,0 +1,264 @@
+//! Reading images from files and streams
+//!
+//! `Reader` is the entry point for decoding an image whose format is known from its path, given
+//! by the caller or guessed from the first bytes of the image. Images of formats this crate does
+//! not support are decoded by the codecs of the `registry`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use color::icc::Profile;
+use dynimage::{self, DynamicImage};
+use image::{ImageError, ImageFormatHint, ImageFormat, ImageResult, Limits};
+use registry::{self, Codec};
+
+/// The number of bytes read to guess the format of an image
+const GUESS_LEN: u64 = 1024;
+
+/// A reader of an image in any of the supported formats
+///
+/// ```no_run
+/// # fn main() { run().unwrap() }
+/// # fn run() -> image::ImageResult<()> {
+/// use std::io::Cursor;
+/// use image::io::Reader;
+///
+/// let data = std::fs::read("image.bin").unwrap();
+/// let reader = try!(Reader::new(Cursor::new(data)).with_guessed_format());
+/// let image = try!(reader.decode());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Reader<R> {
+    inner: R,
+    format: Option<ImageFormat>,
+    /// The registered codec of the image, if its format is not one of `ImageFormat`
+    codec: Option<Arc<Codec>>,
+    limits: Limits,
+    apply_icc: bool,
+    /// The profile the pixels are converted into if `apply_icc` is set
+    target: Profile,
+}
+
+impl<R: BufRead + Seek> Reader<R> {
+    /// Creates a reader of the image ```inner```, whose format is not known yet
+    ///
+    /// The image is checked against the default `Limits` when it is decoded.
+    pub fn new(inner: R) -> Reader<R> {
+        Reader {
+            inner: inner,
+            format: None,
+            codec: None,
+            limits: Limits::default(),
+            apply_icc: false,
+            target: Profile::srgb(),
+        }
+    }
+
+    /// Creates a reader of the image ```inner``` of format ```format```
+    pub fn with_format(inner: R, format: ImageFormat) -> Reader<R> {
+        Reader {
+            format: Some(format),
+            ..Reader::new(inner)
+        }
+    }
+
+    /// Returns the format of the image, if it is known and supported by this crate
+    pub fn format(&self) -> Option<ImageFormat> {
+        self.format
+    }
+
+    /// Sets the format of the image to ```format```
+    pub fn set_format(&mut self, format: ImageFormat) {
+        self.format = Some(format);
+        self.codec = None;
+    }
+
+    /// Forgets the format of the image, so that it has to be guessed or set again
+    pub fn clear_format(&mut self) {
+        self.format = None;
+        self.codec = None;
+    }
+
+    /// Sets the limits the image is checked against when it is decoded
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Sets whether the pixels are converted from the ICC profile embedded in the image into sRGB,
+    /// or the profile set with `set_target_profile`, when it is decoded
+    ///
+    /// Images without a profile, or whose profile is not a matrix/TRC profile, are left unchanged,
+    /// as are images decoded by the codecs of the `registry`.
+    pub fn apply_icc(&mut self, apply: bool) {
+        self.apply_icc = apply;
+    }
+
+    /// Sets the profile the pixels are converted into if `apply_icc` is set
+    pub fn set_target_profile(&mut self, profile: Profile) {
+        self.target = profile;
+    }
+
+    /// Guesses the format of the image from its first bytes with `guess_format`, or else with
+    /// the codecs of the `registry`
+    ///
+    /// The stream is returned to its position afterwards. The format is left unchanged if it
+    /// can not be guessed, only errors of the stream are returned.
+    pub fn with_guessed_format(mut self) -> io::Result<Reader<R>> {
+        let data = try!(self.read_start());
+        if let Ok(format) = dynimage::guess_format(&data) {
+            self.format = Some(format);
+            self.codec = None;
+        } else if let Some(codec) = registry::find_by_content(&data) {
+            self.format = None;
+            self.codec = Some(codec);
+        }
+        Ok(self)
+    }
+
+    /// Returns the stream the image is read from
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads the dimensions of the image without decoding it
+    pub fn into_dimensions(mut self) -> ImageResult<(u32, u32)> {
+        match self.format {
+            Some(format) => dynimage::load_dimensions(self.inner, format),
+            None => try!(self.require_codec()).dimensions(&mut self.inner),
+        }
+    }
+
+    /// Decodes the image, failing with `ImageError::Limits` if it exceeds the limits
+    pub fn decode(mut self) -> ImageResult<DynamicImage> {
+        match self.format {
+            Some(format) if self.apply_icc => {
+                dynimage::load_with_profile(self.inner, format, &self.limits, &self.target)
+            }
+            Some(format) => dynimage::load_with_limits(self.inner, format, &self.limits),
+            None => try!(self.require_codec()).decode(&mut self.inner, &self.limits),
+        }
+    }
+
+    /// Reads the first bytes of the image and returns the stream to its position
+    fn read_start(&mut self) -> io::Result<Vec<u8>> {
+        let start = try!(self.inner.seek(SeekFrom::Current(0)));
+        let mut data = Vec::new();
+        let result = (&mut self.inner).take(GUESS_LEN).read_to_end(&mut data);
+        try!(self.inner.seek(SeekFrom::Start(start)));
+        try!(result);
+        Ok(data)
+    }
+
+    fn require_codec(&self) -> ImageResult<Arc<Codec>> {
+        self.codec.clone().ok_or_else(|| ImageError::unsupported_format(ImageFormatHint::Unknown))
+    }
+}
+
+impl Reader<BufReader<File>> {
+    /// Opens the image file at ```path```, whose format is determined from its extension
+    ///
+    /// Extensions of formats this crate does not support are looked up in the `registry`. Files
+    /// with an unknown extension are opened without a format, which can then be guessed with
+    /// `with_guessed_format`.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Reader<BufReader<File>>> {
+        let path = path.as_ref();
+        let mut reader = Reader::new(BufReader::new(try!(File::open(path))));
+        reader.format = path.extension().and_then(ImageFormat::from_extension);
+        if reader.format.is_none() {
+            reader.codec = path.extension().and_then(registry::find_by_extension);
+        }
+        Ok(reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom};
+
+    use super::Reader;
+    use color::icc::Profile;
+    use image::{GenericImageView, ImageError, ImageFormat, Limits};
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_guessed_format() {
+        let data = b"P5 3 2 255 \x00\x01\x02\x03\x04\x05";
+        assert!(Reader::new(Cursor::new(&data[..])).decode().is_err());
+
+        let reader = Reader::new(Cursor::new(&data[..])).with_guessed_format().unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::PNM));
+        assert_eq!(reader.decode().unwrap().raw_pixels(), vec![0, 1, 2, 3, 4, 5]);
+
+        // The stream is returned to its position, and unknown formats are left unset
+        let mut cursor = Cursor::new(&b"xxxP5 1 1 255 \x07"[..]);
+        cursor.seek(SeekFrom::Start(3)).unwrap();
+        let reader = Reader::new(cursor).with_guessed_format().unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::PNM));
+        let mut rest = Vec::new();
+        reader.into_inner().read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"P5 1 1 255 \x07");
+        assert_eq!(Reader::new(Cursor::new(b"unknown")).with_guessed_format().unwrap().format(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_dimensions_and_limits() {
+        let data = &b"P5 3 2 255 \x00\x01\x02\x03\x04\x05"[..];
+        assert_eq!(Reader::with_format(Cursor::new(data), ImageFormat::PNM).into_dimensions().unwrap(), (3, 2));
+        // The dimensions are read from the header of images too large to decode
+        let huge = &b"P5 100000 100000 255 "[..];
+        assert_eq!(Reader::with_format(Cursor::new(huge), ImageFormat::PNM).into_dimensions().unwrap(),
+                   (100000, 100000));
+        match Reader::with_format(Cursor::new(huge), ImageFormat::PNM).decode() {
+            Err(ImageError::Limits(_)) => {},
+            r => panic!("Expected the default limits to be exceeded, got {:?}", r.is_ok()),
+        }
+
+        let mut reader = Reader::new(Cursor::new(data));
+        reader.set_format(ImageFormat::PNM);
+        reader.set_limits(Limits { max_width: Some(2), ..Limits::no_limits() });
+        match reader.decode() {
+            Err(ImageError::Limits(_)) => {},
+            r => panic!("Expected the limits to be exceeded, got {:?}", r.is_ok()),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn test_open() {
+        let reader = Reader::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::PNG));
+        let (width, height) = Reader::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap()
+            .into_dimensions().unwrap();
+        assert_eq!(reader.decode().unwrap().dimensions(), (width, height));
+
+        let mut reader = Reader::open("tests/images/tga/testsuite/LICENSE").unwrap();
+        assert_eq!(reader.format(), None);
+        reader.set_format(ImageFormat::TGA);
+        reader.clear_format();
+        assert!(reader.decode().is_err());
+        assert!(Reader::open("tests/images/missing.png").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn test_apply_icc() {
+        let path = "tests/images/jpg/progressive/cat.jpg";
+        let plain = Reader::open(path).unwrap().decode().unwrap();
+        // The profile of the image is sRGB, so converting into sRGB barely changes it
+        let mut reader = Reader::open(path).unwrap();
+        reader.apply_icc(true);
+        let converted = reader.decode().unwrap();
+        assert_eq!(converted.dimensions(), plain.dimensions());
+        assert!(converted.raw_pixels().iter().zip(plain.raw_pixels())
+                    .all(|(&a, b)| (a as i32 - b as i32).abs() <= 2));
+
+        let mut reader = Reader::open(path).unwrap();
+        reader.apply_icc(true);
+        reader.set_target_profile(Profile::display_p3());
+        assert!(reader.decode().unwrap().raw_pixels() != plain.raw_pixels());
+    }
+}
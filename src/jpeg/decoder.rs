@@ -1,26 +1,259 @@
 extern crate jpeg_decoder;
 
-use std::io::Read;
+use std::cell::RefCell;
+use std::io::{self, Cursor, Read};
+use std::rc::Rc;
 
 use color::{self, ColorType};
-use image::{DecodingResult, ImageDecoder, ImageError, ImageResult};
+use image::{decoder_metadata, DecodingErrorKind, DecodingResult, ImageDecoder, ImageError, ImageFormat,
+            ImageFormatHint, ImageResult, Metadata, ParameterErrorKind};
+use metadata::exif::Exif;
+
+use super::transform;
 
 /// JPEG decoder
 pub struct JPEGDecoder<R> {
-    decoder: jpeg_decoder::Decoder<R>,
+    decoder: jpeg_decoder::Decoder<Recorder<R>>,
     metadata: Option<jpeg_decoder::ImageInfo>,
+    /// The EXIF data, read along with the metadata
+    exif: Option<Vec<u8>>,
+    /// The EXIF orientation, read along with the metadata
+    orientation: Option<u16>,
+    /// The resolution in pixels per inch given by the JFIF segment, read along with the metadata
+    density: Option<(f32, f32)>,
+    /// Whether the image has four components, stored either as CMYK or YCCK
+    cmyk: bool,
+    /// Whether the image was decoded as CMYK without the inversion Adobe applies
+    plain_cmyk: bool,
+    /// The dimensions of the image before scaling
+    full_size: (u16, u16),
+    /// The size requested from the decoder to scale the image, if any
+    scale: Option<(u16, u16)>,
+    /// The bytes read by the decoder, until the image data is decoded by `read_image`
+    recorded: Rc<RefCell<Option<Vec<u8>>>>,
+    /// The stream read by the decoder, to read the rest of a damaged image
+    r: Rc<RefCell<R>>,
+    /// The decoded image, while its rows are read by `read_scanline`
+    rows: Option<Vec<u8>>,
+    /// The number of rows read by `read_scanline`
+    row: u32,
+}
+
+/// Keeps a copy of the bytes read from a stream while recording is enabled
+struct Recorder<R> {
+    r: Rc<RefCell<R>>,
+    recorded: Rc<RefCell<Option<Vec<u8>>>>,
+}
+
+impl<R: Read> Read for Recorder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = try!(self.r.borrow_mut().read(buf));
+        if let Some(ref mut recorded) = *self.recorded.borrow_mut() {
+            recorded.extend_from_slice(&buf[..len]);
+        }
+        Ok(len)
+    }
 }
 
 impl<R: Read> JPEGDecoder<R> {
     /// Create a new decoder that decodes from the stream ```r```
     pub fn new(r: R) -> JPEGDecoder<R> {
+        let recorded = Rc::new(RefCell::new(Some(Vec::new())));
+        let r = Rc::new(RefCell::new(r));
         JPEGDecoder {
-            decoder: jpeg_decoder::Decoder::new(r),
+            decoder: jpeg_decoder::Decoder::new(Recorder {
+                r: r.clone(),
+                recorded: recorded.clone(),
+            }),
             metadata: None,
+            exif: None,
+            orientation: None,
+            density: None,
+            cmyk: false,
+            plain_cmyk: false,
+            full_size: (0, 0),
+            scale: None,
+            recorded: recorded,
+            r: r,
+            rows: None,
+            row: 0,
         }
     }
 
-    fn metadata(&mut self) -> ImageResult<jpeg_decoder::ImageInfo> {
+    /// Returns the orientation of the image stored in its EXIF data.
+    ///
+    /// The values from 1 to 8 describe how the decoded image has to be transformed to be shown
+    /// upright: 1 needs no change, 2 a horizontal flip, 3 a rotation by 180 degrees, 4 a vertical
+    /// flip, 5 a transposition, 6 a clockwise rotation by 90 degrees, 7 a transverse flip and 8
+    /// a rotation by 270 degrees. Images without EXIF orientation return `None`.
+    pub fn orientation(&mut self) -> ImageResult<Option<u16>> {
+        try!(self.info());
+        Ok(self.orientation)
+    }
+
+    /// Returns the EXIF data stored in the APP1 segment of the image, if it has one
+    pub fn exif(&mut self) -> ImageResult<Option<Exif>> {
+        try!(self.info());
+        match self.exif {
+            Some(ref exif) => Exif::parse(exif).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the image like `read_image`, reporting the intermediate image after each scan.
+    ///
+    /// ```on_scan``` is called with the number of each scan, counted from 1, and the image
+    /// decoded from it and the scans before. The coefficients not transmitted yet are treated as
+    /// zero, so the first scans of a progressive jpeg give blurry, coarse images. Images which
+    /// are not progressive are reported once, when they are complete.
+    ///
+    /// jpeg-decoder keeps no state between scans, so each intermediate image is decoded again
+    /// from the start of the image: a jpeg with ```n``` scans is decoded ```n``` times, and reads
+    /// about ```n * n / 2``` scans in total rather than ```n```.
+    pub fn read_image_progressively<F>(&mut self, mut on_scan: F) -> ImageResult<DecodingResult>
+    where F: FnMut(usize, &[u8]) {
+        try!(self.info());
+        let pixel_format = self.decoder.info().unwrap().pixel_format;
+        // The bytes read so far with the header, followed by the rest of the image
+        let mut stream = match self.recorded.borrow_mut().take() {
+            Some(stream) => stream,
+            None => return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)),
+        };
+        try!(self.r.borrow_mut().read_to_end(&mut stream));
+        // The decoder refuses four components without an Adobe segment, see `read_samples`
+        self.plain_cmyk = self.cmyk && find_segment(&stream, 0xEE, b"Adobe").is_none();
+
+        let scans = try!(scan_ends(&stream));
+        for (scan, &end) in scans.iter().enumerate().take(scans.len().saturating_sub(1)) {
+            // The decoder renders the coefficients read so far once the image ends
+            let mut partial = stream[..end].to_vec();
+            partial.extend_from_slice(&[0xFF, 0xD9]);
+            let image = try!(decode_stream(&partial, self.plain_cmyk, self.scale));
+            on_scan(scan + 1, &convert(image, pixel_format));
+        }
+        let data = convert(try!(decode_stream(&stream, self.plain_cmyk, self.scale)), pixel_format);
+        on_scan(scans.len().max(1), &data);
+        Ok(DecodingResult::U8(data))
+    }
+
+    /// Decodes the image like `read_image`, recovering what is left of truncated or corrupt images.
+    ///
+    /// Instead of failing, the part of a damaged image that could be decoded is returned along
+    /// with the error describing the damage, the rest of the image is gray. Progressive images
+    /// keep the scans before the damage, so their remaining area is coarse rather than gray.
+    /// Intact images return no error. Images whose header is damaged still fail.
+    pub fn read_image_with_recovery(&mut self) -> ImageResult<(DecodingResult, Option<ImageError>)> {
+        try!(self.info());
+        let err = match self.read_samples(true) {
+            Ok(data) => {
+                let pixel_format = self.decoder.info().unwrap().pixel_format;
+                return Ok((DecodingResult::U8(convert(data, pixel_format)), None))
+            }
+            Err(err) => err,
+        };
+
+        let mut stream = match self.recorded.borrow_mut().take() {
+            Some(stream) => stream,
+            None => return Err(err),
+        };
+        // The decoder stops reading at the damage, the image may go on after it
+        try!(self.r.borrow_mut().read_to_end(&mut stream));
+
+        let mut repaired = Vec::new();
+        let damage = try!(transform::repair(&stream[..], &mut repaired));
+        let data = try!(decode_stream(&repaired, self.plain_cmyk, self.scale));
+
+        let data = if self.cmyk { cmyk_to_rgb(&data) } else { data };
+        Ok((DecodingResult::U8(data), Some(damage.unwrap_or(err))))
+    }
+
+    /// Returns whether the image has four components, stored as CMYK or YCCK.
+    ///
+    /// These images are converted to RGB by `read_image`, `read_cmyk_image` returns their
+    /// samples as they are.
+    pub fn is_cmyk(&mut self) -> ImageResult<bool> {
+        try!(self.info());
+        Ok(self.cmyk)
+    }
+
+    /// Decodes a CMYK or YCCK image to CMYK samples without converting them to RGB.
+    ///
+    /// Each pixel is made up of four bytes for cyan, magenta, yellow and black, where 0 means
+    /// no ink and 255 full ink. The inverted samples written by Adobe applications are corrected
+    /// accordingly. Images with other color types return an `ImageError::Unsupported` error.
+    pub fn read_cmyk_image(&mut self) -> ImageResult<Vec<u8>> {
+        if !try!(self.is_cmyk()) {
+            let color = try!(self.colortype());
+            return Err(ImageError::unsupported_color(ImageFormat::JPEG, color))
+        }
+        self.read_samples(false)
+    }
+
+    /// Decodes the samples of the image, CMYK images are not converted to RGB
+    fn read_samples(&mut self, keep_stream: bool) -> ImageResult<Vec<u8>> {
+        try!(self.info());
+
+        // Only the recovery of damaged images and CMYK images, which may have to be decoded
+        // again, need a copy of the image data, the header is parsed before
+        if !keep_stream && !self.cmyk {
+            *self.recorded.borrow_mut() = None;
+        }
+
+        let err = match self.decoder.decode() {
+            Ok(data) => return Ok(data),
+            Err(err) => err,
+        };
+
+        // The decoder refuses four components without an Adobe segment, these images usually
+        // hold CMYK samples which are not inverted
+        let stream = match *self.recorded.borrow() {
+            Some(ref stream) if self.cmyk && find_segment(stream, 0xEE, b"Adobe").is_none() => stream.clone(),
+            _ => return Err(err.into()),
+        };
+        self.plain_cmyk = true;
+        decode_stream(&stream, true, self.scale)
+    }
+
+    /// Decode the image at a fraction of its size, at least ```num / denom```.
+    ///
+    /// The image is scaled while its DCT coefficients are transformed, like libjpeg does, which
+    /// is much faster than decoding it at full resolution and resizing it afterwards. The
+    /// supported factors are 1/8, 1/4, 1/2 and 1, other ratios are rounded up to the next one
+    /// of these, so the image is never smaller than requested.
+    ///
+    /// Returns the dimensions of the scaled image, which are reported by `dimensions` as well
+    /// from then on. The scale has to be set before the image is decoded.
+    pub fn scale(&mut self, num: u8, denom: u8) -> ImageResult<(u32, u32)> {
+        if num == 0 || denom == 0 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+        try!(self.info());
+
+        // The smallest number of eighths which is at least num / denom
+        let eighths = [1u32, 2, 4, 8].iter().cloned()
+            .find(|&eighths| eighths * denom as u32 >= 8 * num as u32)
+            .unwrap_or(8);
+
+        // The decoder chooses the smallest scale reaching the requested size along either axis,
+        // only the longer one is requested to keep short axes of small images from matching early
+        let (width, height) = self.full_size;
+        let scaled = |length: u16| ((length as u32 * eighths + 7) / 8) as u16;
+        let request = if width >= height {
+            (scaled(width), u16::max_value())
+        } else {
+            (u16::max_value(), scaled(height))
+        };
+
+        let (width, height) = try!(self.decoder.scale(request.0, request.1));
+        self.scale = Some(request);
+        if let Some(ref mut metadata) = self.metadata {
+            metadata.width = width;
+            metadata.height = height;
+        }
+        Ok((width as u32, height as u32))
+    }
+
+    fn info(&mut self) -> ImageResult<jpeg_decoder::ImageInfo> {
         match self.metadata {
             Some(metadata) => Ok(metadata),
             None => {
@@ -30,8 +263,16 @@ impl<R: Read> JPEGDecoder<R> {
                 // We convert CMYK data to RGB before returning it to the user.
                 if metadata.pixel_format == jpeg_decoder::PixelFormat::CMYK32 {
                     metadata.pixel_format = jpeg_decoder::PixelFormat::RGB24;
+                    self.cmyk = true;
                 }
+                self.full_size = (metadata.width, metadata.height);
 
+                if let Some(ref header) = *self.recorded.borrow() {
+                    self.exif = find_segment(header, 0xE1, b"Exif\0\0").map(|segment| segment[6..].to_vec());
+                    self.orientation = self.exif.as_ref().and_then(|exif| Exif::parse(exif).ok())
+                        .and_then(|exif| exif.orientation());
+                    self.density = jfif_density(header);
+                }
                 self.metadata = Some(metadata);
                 Ok(metadata)
             },
@@ -41,32 +282,179 @@ impl<R: Read> JPEGDecoder<R> {
 
 impl<R: Read> ImageDecoder for JPEGDecoder<R> {
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
-        let metadata = try!(self.metadata());
+        let metadata = try!(self.info());
         Ok((metadata.width as u32, metadata.height as u32))
     }
 
     fn colortype(&mut self) -> ImageResult<ColorType> {
-        let metadata = try!(self.metadata());
+        let metadata = try!(self.info());
         Ok(metadata.pixel_format.into())
     }
 
     fn row_len(&mut self) -> ImageResult<usize> {
-        let metadata = try!(self.metadata());
+        let metadata = try!(self.info());
         Ok(metadata.width as usize * color::num_components(metadata.pixel_format.into()))
     }
 
-    fn read_scanline(&mut self, _buf: &mut [u8]) -> ImageResult<u32> {
-        unimplemented!();
+    /// Reads the next row of the image into ```buf```.
+    ///
+    /// The rows of a JPEG image are interleaved in blocks and only become available once the
+    /// whole image has been decoded, which happens when the first row is read.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let (_, height) = try!(self.dimensions());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        if self.rows.is_none() {
+            self.rows = match try!(self.read_image()) {
+                DecodingResult::U8(data) => Some(data),
+                _ => unreachable!(),
+            };
+        }
+        let start = self.row as usize * len;
+        buf[..len].copy_from_slice(&self.rows.as_ref().unwrap()[start..start + len]);
+        self.row += 1;
+        Ok(self.row - 1)
     }
 
     fn read_image(&mut self) -> ImageResult<DecodingResult> {
-        let mut data = try!(self.decoder.decode());
-        data = match self.decoder.info().unwrap().pixel_format {
-            jpeg_decoder::PixelFormat::CMYK32 => cmyk_to_rgb(&data),
-            _ => data,
-        };
+        let data = try!(self.read_samples(false));
+        Ok(DecodingResult::U8(convert(data, self.decoder.info().unwrap().pixel_format)))
+    }
 
-        Ok(DecodingResult::U8(data))
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        try!(self.info());
+        Ok(self.decoder.icc_profile())
+    }
+
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.dpi = self.density;
+        metadata.orientation = self.orientation;
+        Ok(metadata)
+    }
+}
+
+/// Finds the end of each scan of a progressive jpeg held in memory
+///
+/// Returns the offset of the marker following the data of each scan, images which are not
+/// progressive have no scans to report.
+fn scan_ends(data: &[u8]) -> ImageResult<Vec<usize>> {
+    let truncated = || ImageError::malformed(ImageFormat::JPEG, "JPEG segment is truncated".into());
+    let mut ends = Vec::new();
+    let mut progressive = false;
+    let mut pos = 2;
+    loop {
+        // Markers may be preceded by any number of fill bytes
+        while pos + 1 < data.len() && data[pos] == 0xFF && data[pos + 1] == 0xFF {
+            pos += 1;
+        }
+        if pos + 1 >= data.len() || data[pos] != 0xFF {
+            return Err(truncated())
+        }
+        let marker = data[pos + 1];
+        match marker {
+            // End of image
+            0xD9 => break,
+            // Markers without a segment
+            0x01 | 0xD0...0xD7 => {
+                pos += 2;
+                continue
+            },
+            _ => {},
+        }
+        if pos + 4 > data.len() {
+            return Err(truncated())
+        }
+        let len = (data[pos + 2] as usize) << 8 | data[pos + 3] as usize;
+        pos += 2 + len;
+        if pos > data.len() {
+            return Err(truncated())
+        }
+        match marker {
+            // Progressive frame headers
+            0xC2 | 0xC6 | 0xCA | 0xCE => progressive = true,
+            // The entropy coded data of a scan ends at the first marker other than a restart
+            0xDA => {
+                while pos + 1 < data.len() && !(data[pos] == 0xFF && data[pos + 1] != 0
+                                                 && !(data[pos + 1] >= 0xD0 && data[pos + 1] <= 0xD7)) {
+                    pos += 1;
+                }
+                ends.push(pos);
+            },
+            _ => {},
+        }
+    }
+
+    Ok(if progressive { ends } else { Vec::new() })
+}
+
+/// Reads the resolution in pixels per inch from the JFIF segment, if it gives one in inches or
+/// centimeters rather than only the aspect ratio
+fn jfif_density(data: &[u8]) -> Option<(f32, f32)> {
+    let segment = match find_segment(data, 0xE0, b"JFIF\0") {
+        Some(segment) if segment.len() >= 12 => segment,
+        _ => return None,
+    };
+    let x = ((segment[8] as u16) << 8 | segment[9] as u16) as f32;
+    let y = ((segment[10] as u16) << 8 | segment[11] as u16) as f32;
+    match segment[7] {
+        1 => Some((x, y)),
+        2 => Some((x * 2.54, y * 2.54)),
+        _ => None,
+    }
+}
+
+/// Finds the first segment with the marker ```marker``` starting with ```prefix``` before the
+/// first scan of the jpeg ```data```
+fn find_segment<'a>(data: &'a [u8], marker: u8, prefix: &[u8]) -> Option<&'a [u8]> {
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF && data[pos + 1] != 0xDA {
+        let len = (data[pos + 2] as usize) << 8 | data[pos + 3] as usize;
+        let segment = &data[pos + 4..(pos + 2 + len).min(data.len()).max(pos + 4)];
+        if data[pos + 1] == marker && segment.starts_with(prefix) {
+            return Some(segment)
+        }
+        pos += 2 + len;
+    }
+    None
+}
+
+/// Decodes a complete jpeg held in memory, scaled to the requested size ```scale```.
+///
+/// ```plain_cmyk``` images are decoded as Adobe CMYK, whose inversion is undone afterwards.
+fn decode_stream(stream: &[u8], plain_cmyk: bool, scale: Option<(u16, u16)>) -> ImageResult<Vec<u8>> {
+    let decode = |stream: &[u8]| -> ImageResult<Vec<u8>> {
+        let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(stream));
+        if let Some((width, height)) = scale {
+            try!(decoder.scale(width, height));
+        }
+        Ok(try!(decoder.decode()))
+    };
+
+    if !plain_cmyk {
+        return decode(stream)
+    }
+
+    // An Adobe segment with the transform 0, marking CMYK samples
+    let mut patched = stream[..2].to_vec();
+    patched.extend_from_slice(&[0xFF, 0xEE, 0x00, 0x0E]);
+    patched.extend_from_slice(b"Adobe\x00\x64\x00\x00\x00\x00\x00");
+    patched.extend_from_slice(&stream[2..]);
+
+    let data = try!(decode(&patched));
+    Ok(data.into_iter().map(|sample| 255 - sample).collect())
+}
+
+fn convert(data: Vec<u8>, pixel_format: jpeg_decoder::PixelFormat) -> Vec<u8> {
+    match pixel_format {
+        jpeg_decoder::PixelFormat::CMYK32 => cmyk_to_rgb(&data),
+        _ => data,
     }
 }
 
@@ -75,24 +463,11 @@ fn cmyk_to_rgb(input: &[u8]) -> Vec<u8> {
     let mut output = Vec::with_capacity(size);
 
     for pixel in input.chunks(4) {
-        let c = pixel[0] as f32 / 255.0;
-        let m = pixel[1] as f32 / 255.0;
-        let y = pixel[2] as f32 / 255.0;
-        let k = pixel[3] as f32 / 255.0;
-
-        // CMYK -> CMY
-        let c = c * (1.0 - k) + k;
-        let m = m * (1.0 - k) + k;
-        let y = y * (1.0 - k) + k;
-
-        // CMY -> RGB
-        let r = (1.0 - c) * 255.0;
-        let g = (1.0 - m) * 255.0;
-        let b = (1.0 - y) * 255.0;
-
-        output.push(r as u8);
-        output.push(g as u8);
-        output.push(b as u8);
+        // The light not absorbed by either the colored ink or the black one
+        let white = 255 - pixel[3] as u32;
+        for &ink in &pixel[..3] {
+            output.push((((255 - ink as u32) * white + 127) / 255) as u8);
+        }
     }
 
     output
@@ -113,10 +488,301 @@ impl From<jpeg_decoder::Error> for ImageError {
     fn from(err: jpeg_decoder::Error) -> ImageError {
         use self::jpeg_decoder::Error::*;
         match err {
-            Format(desc)      => ImageError::FormatError(desc),
-            Unsupported(desc) => ImageError::UnsupportedError(format!("{:?}", desc)),
+            Format(desc)      => ImageError::malformed(ImageFormat::JPEG, desc),
+            Unsupported(desc) => ImageError::unsupported(ImageFormat::JPEG, format!("{:?}", desc)),
             Io(err)           => ImageError::IoError(err),
-            Internal(err)     => ImageError::FormatError(err.description().to_owned()),
+            Internal(err)     => ImageError::Decoding {
+                format: ImageFormatHint::Exact(ImageFormat::JPEG),
+                kind: DecodingErrorKind::External(err),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{BufReader, Cursor, Read};
+
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+    use super::JPEGDecoder;
+    use super::super::encoder::{write_frame, BitWriter, BlockSource, Component, JPEGEncoder};
+
+    #[test]
+    fn icc_profile() {
+        let file = File::open("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let mut decoder = JPEGDecoder::new(BufReader::new(file));
+        let profile = decoder.icc_profile().unwrap().unwrap();
+        // An ICC profile starts with its own length, the signature follows at offset 36
+        assert_eq!(&profile[..4], &[(profile.len() >> 24) as u8, (profile.len() >> 16) as u8,
+                                     (profile.len() >> 8) as u8, profile.len() as u8]);
+        assert_eq!(&profile[36..40], b"acsp");
+    }
+
+    #[test]
+    fn metadata() {
+        let file = File::open("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let metadata = JPEGDecoder::new(BufReader::new(file)).metadata().unwrap();
+        assert_eq!((metadata.dpi, metadata.bit_depth), (Some((72.0, 72.0)), 8));
+        assert!(metadata.has_icc_profile);
+
+        // A density without units only gives the aspect ratio
+        let file = File::open("tests/images/jpg/progressive/3.jpg").unwrap();
+        assert_eq!(JPEGDecoder::new(BufReader::new(file)).metadata().unwrap().dpi, None);
+    }
+
+    #[test]
+    fn progressive_scans() {
+        let path = "tests/images/jpg/progressive/3.jpg";
+        let mut decoder = JPEGDecoder::new(BufReader::new(File::open(path).unwrap()));
+        let (width, height) = decoder.dimensions().unwrap();
+        let mut scans = Vec::new();
+        let result = decoder.read_image_progressively(|scan, image| scans.push((scan, image.to_vec())));
+        let final_image = match result.unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+
+        assert!(scans.len() > 1);
+        assert_eq!(scans.iter().map(|&(scan, _)| scan).collect::<Vec<_>>(), (1..scans.len() + 1).collect::<Vec<_>>());
+        assert!(scans.iter().all(|&(_, ref image)| image.len() == width as usize * height as usize * 3));
+        assert_eq!(scans.last().unwrap().1, final_image);
+        assert!(scans[0].1 != final_image);
+
+        match JPEGDecoder::new(BufReader::new(File::open(path).unwrap())).read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, final_image),
+            _ => panic!("Expected 8-bit samples"),
+        }
+    }
+
+    /// An APP1 segment holding an EXIF orientation, with a preceding unrelated tag
+    fn exif_segment(orientation: u16, big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| if big_endian {
+            vec![(value >> 8) as u8, value as u8]
+        } else {
+            vec![value as u8, (value >> 8) as u8]
+        };
+        let mut tiff = if big_endian { b"MM\0*".to_vec() } else { b"II*\0".to_vec() };
+        tiff.extend(if big_endian { vec![0, 0, 0, 8] } else { vec![8, 0, 0, 0] });
+        tiff.extend(u16_bytes(2));
+        for &(tag, value) in &[(0x010F, 0), (0x0112, orientation)] {
+            tiff.extend(u16_bytes(tag));
+            tiff.extend(u16_bytes(3));
+            tiff.extend(if big_endian { vec![0, 0, 0, 1] } else { vec![1, 0, 0, 0] });
+            tiff.extend(u16_bytes(value));
+            tiff.extend(vec![0, 0]);
+        }
+        tiff.extend(vec![0; 4]);
+
+        let len = tiff.len() + 8;
+        let mut segment = vec![0xFF, 0xE1, (len >> 8) as u8, len as u8];
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend(tiff);
+        segment
+    }
+
+    #[test]
+    fn orientation() {
+        let mut jpeg = Vec::new();
+        File::open("tests/images/jpg/progressive/3.jpg").unwrap().read_to_end(&mut jpeg).unwrap();
+        assert_eq!(JPEGDecoder::new(&jpeg[..]).orientation().unwrap(), None);
+
+        for &big_endian in &[true, false] {
+            let mut tagged = jpeg[..2].to_vec();
+            tagged.extend(exif_segment(6, big_endian));
+            tagged.extend_from_slice(&jpeg[2..]);
+            let mut decoder = JPEGDecoder::new(&tagged[..]);
+            assert_eq!(decoder.orientation().unwrap(), Some(6));
+            assert_eq!(decoder.exif().unwrap().unwrap().fields().len(), 2);
+            assert!(decoder.read_image().is_ok());
+        }
+    }
+
+    /// A uniformly colored image of four components, with quantization tables of ones
+    struct Uniform {
+        samples: [u8; 4],
+        components: Vec<Component>,
+    }
+
+    impl BlockSource for Uniform {
+        fn dimensions(&self) -> (usize, usize) {
+            (16, 8)
+        }
+
+        fn components(&self) -> &[Component] {
+            &self.components
+        }
+
+        fn block(&self, c: usize, _: usize, _: usize) -> [i32; 64] {
+            let mut block = [0; 64];
+            block[0] = (self.samples[c] as i32 - 128) * 8;
+            block
+        }
+    }
+
+    /// Encodes a four component image with an optional Adobe segment with the transform
+    /// ```transform```
+    fn four_components(samples: [u8; 4], transform: Option<u8>) -> Vec<u8> {
+        let components = (0..4).map(|i| Component {
+            id: i + 1, h: 1, v: 1, tq: 0, dc_table: 0, ac_table: 0
+        }).collect();
+        let image = Uniform { samples: samples, components: components };
+
+        let mut data = Vec::new();
+        {
+            let mut writer = BitWriter::new(&mut data);
+            writer.write_segment(0xD8, None).unwrap();
+            if let Some(transform) = transform {
+                writer.write_segment(0xEE, Some(&[b'A', b'd', b'o', b'b', b'e', 0, 100, 0, 0, 0, 0, transform])).unwrap();
+            }
+            write_frame(&mut writer, &image, &[1; 64], false, false).unwrap();
+            writer.write_segment(0xD9, None).unwrap();
+        }
+        data
+    }
+
+    fn decode_rgb(data: &[u8]) -> Vec<u8> {
+        let mut decoder = JPEGDecoder::new(Cursor::new(data));
+        assert!(decoder.is_cmyk().unwrap());
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data[..3].to_vec(),
+            _ => panic!("Expected 8-bit samples"),
+        }
+    }
+
+    #[test]
+    fn cmyk() {
+        // Without an Adobe segment the samples are the amount of ink
+        let plain = four_components([0, 255, 255, 51], None);
+        assert_eq!(decode_rgb(&plain), vec![204, 0, 0]);
+        let cmyk = JPEGDecoder::new(Cursor::new(&plain)).read_cmyk_image().unwrap();
+        assert_eq!(&cmyk[..4], &[0, 255, 255, 51]);
+
+        // Adobe applications write inverted samples
+        let adobe = four_components([255, 0, 0, 204], Some(0));
+        assert_eq!(decode_rgb(&adobe), vec![204, 0, 0]);
+        let cmyk = JPEGDecoder::new(Cursor::new(&adobe)).read_cmyk_image().unwrap();
+        assert_eq!(&cmyk[..4], &[0, 255, 255, 51]);
+    }
+
+    #[test]
+    fn progressive_scans_of_baseline() {
+        // A baseline CMYK image without an Adobe segment is reported once, when it is complete
+        let plain = four_components([0, 255, 255, 51], None);
+        let mut scans = Vec::new();
+        let mut decoder = JPEGDecoder::new(Cursor::new(&plain));
+        match decoder.read_image_progressively(|scan, _| scans.push(scan)).unwrap() {
+            DecodingResult::U8(data) => assert_eq!(&data[..3], &[204, 0, 0]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+        assert_eq!(scans, vec![1]);
+    }
+
+    #[test]
+    fn ycck() {
+        // Black luma and neutral chroma mean no colored ink, leaving the black ink
+        let ycck = four_components([0, 128, 128, 155], Some(2));
+        assert_eq!(decode_rgb(&ycck), vec![155, 155, 155]);
+        let cmyk = JPEGDecoder::new(Cursor::new(&ycck)).read_cmyk_image().unwrap();
+        assert_eq!(&cmyk[..4], &[0, 0, 0, 100]);
+    }
+
+    #[test]
+    fn read_cmyk_of_rgb() {
+        let file = File::open("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let mut decoder = JPEGDecoder::new(BufReader::new(file));
+        assert!(!decoder.is_cmyk().unwrap());
+        assert!(decoder.read_cmyk_image().is_err());
+    }
+
+    #[test]
+    fn scale() {
+        let path = "tests/images/jpg/progressive/cat.jpg";
+        let mut decoder = JPEGDecoder::new(BufReader::new(File::open(path).unwrap()));
+        let (width, height) = decoder.dimensions().unwrap();
+        let full = match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+
+        for &(num, denom, eighths) in &[(1, 2, 4), (1, 3, 4), (1, 8, 1), (3, 4, 8), (2, 1, 8)] {
+            let mut decoder = JPEGDecoder::new(BufReader::new(File::open(path).unwrap()));
+            let expected = ((width * eighths + 7) / 8, (height * eighths + 7) / 8);
+            assert_eq!(decoder.scale(num, denom).unwrap(), expected);
+            assert_eq!(decoder.dimensions().unwrap(), expected);
+
+            let scaled = match decoder.read_image().unwrap() {
+                DecodingResult::U8(data) => data,
+                _ => panic!("Expected 8-bit samples"),
+            };
+            assert_eq!(scaled.len(), (expected.0 * expected.1 * 3) as usize);
+
+            // The scaled pixels resemble the corresponding ones of the full image
+            let step = 8 / eighths;
+            let mut error = 0;
+            for y in 0..expected.1 - 1 {
+                for x in 0..expected.0 - 1 {
+                    let small = ((y * expected.0 + x) * 3) as usize;
+                    let large = ((y * step * width + x * step) * 3) as usize;
+                    error += (scaled[small] as i32 - full[large] as i32).abs();
+                }
+            }
+            assert!(error / ((expected.0 - 1) * (expected.1 - 1)) as i32 <= 16);
+        }
+    }
+
+    #[test]
+    fn recovery() {
+        let img = vec![255u8; 64 * 64];
+        let mut encoded = Vec::new();
+        JPEGEncoder::new(&mut encoded).encode(&img, 64, 64, ColorType::Gray(8)).unwrap();
+
+        let (data, damage) = JPEGDecoder::new(Cursor::new(&encoded)).read_image_with_recovery().unwrap();
+        assert!(damage.is_none());
+        match data {
+            DecodingResult::U8(data) => assert_eq!(data.len(), img.len()),
+            _ => panic!("Expected 8-bit samples"),
+        }
+
+        // Cut the scan data in half, the lower part of the image is lost
+        let sos = encoded.windows(2).position(|w| w == [0xFF, 0xDA]).unwrap();
+        let truncated = &encoded[..(sos + encoded.len()) / 2];
+        assert!(JPEGDecoder::new(Cursor::new(truncated)).read_image().is_err());
+
+        let (data, damage) = JPEGDecoder::new(Cursor::new(truncated)).read_image_with_recovery().unwrap();
+        assert!(damage.is_some());
+        let data = match data {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+        assert_eq!(data.len(), img.len());
+        assert!(data[..64 * 8].iter().all(|&p| p > 240));
+        assert!(data[64 * 56..].iter().all(|&p| p > 120 && p < 136));
+
+        // Images without frame header cannot be recovered
+        assert!(JPEGDecoder::new(Cursor::new(&encoded[..20])).read_image_with_recovery().is_err());
+    }
+
+    #[test]
+    fn scanlines() {
+        let mut data = Vec::new();
+        File::open("tests/images/jpg/progressive/cat.jpg").unwrap().read_to_end(&mut data).unwrap();
+        let image = match JPEGDecoder::new(Cursor::new(&data)).read_image().unwrap() {
+            DecodingResult::U8(image) => image,
+            _ => panic!("Expected 8-bit samples"),
+        };
+
+        let mut decoder = JPEGDecoder::new(Cursor::new(&data));
+        let (_, height) = decoder.dimensions().unwrap();
+        let mut row = vec![0; decoder.row_len().unwrap()];
+        assert!(decoder.read_scanline(&mut row[1..]).is_err());
+        for (y, expected) in image.chunks(row.len()).enumerate() {
+            assert_eq!(decoder.read_scanline(&mut row).unwrap(), y as u32);
+            assert_eq!(&row[..], expected);
         }
+        assert_eq!(image.len(), row.len() * height as usize);
+        assert!(decoder.read_scanline(&mut row).is_err());
     }
 }
@@ -1,16 +1,19 @@
+use std::cmp;
 use std::io::{self, Write};
 use byteorder::{WriteBytesExt, BigEndian};
-use num_iter::range_step;
 use math::utils::clamp;
 
 use color;
+use image::{ImageEncoder, ImageResult};
 
-use super::transform;
+use super::dct;
 use super::entropy::build_huff_lut;
 
 // Markers
 // Baseline DCT
 static SOF0: u8 = 0xC0;
+// Progressive DCT
+static SOF2: u8 = 0xC2;
 // Huffman Tables
 static DHT: u8 = 0xC4;
 // Start of Image (standalone)
@@ -23,6 +26,17 @@ static SOS: u8 = 0xDA;
 static DQT: u8 = 0xDB;
 // Application segments start and end
 static APP0: u8 = 0xE0;
+// Application segment holding EXIF data
+static APP1: u8 = 0xE1;
+// Application segment holding ICC profiles
+static APP2: u8 = 0xE2;
+
+// The identifiers preceding EXIF data and chunks of ICC profiles in their segments
+static EXIF_PREFIX: &'static [u8] = b"Exif\0\0";
+static ICC_PREFIX: &'static [u8] = b"ICC_PROFILE\0";
+
+// The largest payload of a segment
+static MAX_SEGMENT_LEN: usize = 65533;
 
 // section K.1
 // table K.1
@@ -122,7 +136,7 @@ static CHROMABLUEID: u8 = 2;
 static CHROMAREDID: u8 = 3;
 
 /// The permutation of dct coefficients.
-static UNZIGZAG: [u8; 64] = [
+pub static UNZIGZAG: [u8; 64] = [
     0,  1,  8, 16,  9,  2,  3, 10,
     17, 24, 32, 25, 18, 11,  4,  5,
     12, 19, 26, 33, 40, 48, 41, 34,
@@ -133,29 +147,319 @@ static UNZIGZAG: [u8; 64] = [
     53, 60, 61, 54, 47, 55, 62, 63,
 ];
 
+/// The resolution at which the chroma of color images is stored
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChromaSubsampling {
+    /// 4:4:4, the chroma has the same resolution as the luma
+    Yuv444,
+
+    /// 4:2:2, the chroma has half the horizontal resolution of the luma
+    Yuv422,
+
+    /// 4:2:0, the chroma has half the horizontal and half the vertical resolution of the luma
+    Yuv420,
+}
+
+/// Settings controlling the output of a `JPEGEncoder`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct JPEGEncoderOptions {
+    /// The quality in the range 1-100 where 1 is the worst and 100 is the best.
+    pub quality: u8,
+
+    /// The subsampling of the chroma, gray images are not affected by it.
+    pub subsampling: ChromaSubsampling,
+
+    /// Whether to write a progressive instead of a baseline image.
+    ///
+    /// Progressive images first store the dc coefficients of all components and then the ac
+    /// coefficients of each component in two bands, so that viewers can show a coarse image early.
+    pub progressive: bool,
+
+    /// Whether to use huffman tables computed for the image instead of the standard ones.
+    ///
+    /// This usually makes the image a few percent smaller, at the cost of a second pass over it.
+    pub optimize_huffman: bool,
+}
+
+impl Default for JPEGEncoderOptions {
+    fn default() -> JPEGEncoderOptions {
+        JPEGEncoderOptions {
+            quality: 75,
+            subsampling: ChromaSubsampling::Yuv444,
+            progressive: false,
+            optimize_huffman: false,
+        }
+    }
+}
+
 /// A representation of a JPEG component
 #[derive(Copy, Clone)]
-struct Component {
+pub struct Component {
     /// The Component's identifier
-    id: u8,
+    pub id: u8,
 
     /// Horizontal sampling factor
-    h: u8,
+    pub h: u8,
 
     /// Vertical sampling factor
-    v: u8,
+    pub v: u8,
 
     /// The quantization table selector
-    tq: u8,
+    pub tq: u8,
 
     /// Index to the Huffman DC Table
-    dc_table: u8,
+    pub dc_table: u8,
 
     /// Index to the AC Huffman Table
-    ac_table: u8,
+    pub ac_table: u8,
+}
+
+/// The components and the band of coefficients stored by a scan
+struct Scan {
+    components: Vec<usize>,
+
+    /// Spectral selection start
+    ss: u8,
+
+    /// Spectral selection end
+    se: u8,
+}
+
+/// A huffman table, as written to the image and as used for encoding
+#[derive(Clone)]
+struct HuffmanTable {
+    /// The number of codes of each length
+    bits: [u8; 16],
+
+    /// The symbols ordered by code length
+    values: Vec<u8>,
+
+    /// The size and code of each symbol
+    lut: Vec<(u8, u16)>,
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: &[u8]) -> HuffmanTable {
+        HuffmanTable {
+            bits: *bits,
+            values: values.to_vec(),
+            lut: build_huff_lut(bits, values),
+        }
+    }
+
+    /// Build the optimal table for the symbol frequencies ```freq```, section K.2
+    fn optimal(freq: &[u32; 256]) -> HuffmanTable {
+        // A reserved symbol with the lowest frequency ensures no code consists of only ones
+        let mut freq: Vec<u64> = freq.iter().map(|&f| f as u64).collect();
+        freq.push(1);
+
+        // Figure K.1
+        let mut codesize = vec![0usize; 257];
+        let mut others: Vec<Option<usize>> = vec![None; 257];
+
+        loop {
+            // The two least frequent symbols, ties are resolved in favour of the larger symbol
+            let mut v1 = None;
+            for i in 0..257 {
+                if freq[i] > 0 && v1.map_or(true, |v| freq[i] <= freq[v]) {
+                    v1 = Some(i);
+                }
+            }
+
+            let mut v2 = None;
+            for i in 0..257 {
+                if freq[i] > 0 && Some(i) != v1 && v2.map_or(true, |v| freq[i] <= freq[v]) {
+                    v2 = Some(i);
+                }
+            }
+
+            let (mut v1, mut v2) = match (v1, v2) {
+                (Some(v1), Some(v2)) => (v1, v2),
+                _ => break,
+            };
+
+            freq[v1] += freq[v2];
+            freq[v2] = 0;
+
+            codesize[v1] += 1;
+            while let Some(next) = others[v1] {
+                v1 = next;
+                codesize[v1] += 1;
+            }
+
+            others[v1] = Some(v2);
+
+            codesize[v2] += 1;
+            while let Some(next) = others[v2] {
+                v2 = next;
+                codesize[v2] += 1;
+            }
+        }
+
+        // Figure K.2
+        let mut counts = vec![0usize; 258];
+        for &size in &codesize {
+            if size > 0 {
+                counts[size] += 1;
+            }
+        }
+
+        // Figure K.3, limit the code lengths to 16 bits
+        for i in (17..counts.len()).rev() {
+            while counts[i] > 0 {
+                let mut j = i - 2;
+                while counts[j] == 0 {
+                    j -= 1;
+                }
+
+                counts[i] -= 2;
+                counts[i - 1] += 1;
+                counts[j + 1] += 2;
+                counts[j] -= 1;
+            }
+        }
+
+        // Remove the code of the reserved symbol, which is one of the longest
+        let mut i = 16;
+        while counts[i] == 0 {
+            i -= 1;
+        }
+        counts[i] -= 1;
+
+        // Figure K.4
+        let mut values = Vec::new();
+        for size in 1..codesize.len() {
+            for symbol in 0..256 {
+                if codesize[symbol] == size {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+
+        let mut bits = [0u8; 16];
+        for (i, b) in bits.iter_mut().enumerate() {
+            *b = counts[i + 1] as u8;
+        }
+
+        HuffmanTable::new(&bits, &values)
+    }
+}
 
-    /// The dc prediction of the component
-    _dc_pred: i32
+/// A source of the quantized dct coefficients of the blocks of an image
+pub trait BlockSource {
+    /// The width and height of the image in pixels
+    fn dimensions(&self) -> (usize, usize);
+
+    /// The components of the image
+    fn components(&self) -> &[Component];
+
+    /// The quantized coefficients in natural order of the block at ```bx```, ```by``` of the
+    /// component ```c```
+    fn block(&self, c: usize, bx: usize, by: usize) -> [i32; 64];
+
+    /// The largest horizontal and vertical sampling factors
+    fn max_sampling(&self) -> (usize, usize) {
+        let components = self.components();
+        let hmax = components.iter().map(|c| c.h as usize).max().unwrap_or(1);
+        let vmax = components.iter().map(|c| c.v as usize).max().unwrap_or(1);
+        (hmax, vmax)
+    }
+
+    /// The number of MCUs of an interleaved scan
+    fn mcus(&self) -> (usize, usize) {
+        let (width, height) = self.dimensions();
+        let (hmax, vmax) = self.max_sampling();
+        (ceil_div(width, 8 * hmax), ceil_div(height, 8 * vmax))
+    }
+
+    /// The number of blocks of the component ```c``` in a non-interleaved scan
+    fn blocks(&self, c: usize) -> (usize, usize) {
+        let (width, height) = self.dimensions();
+        let (hmax, vmax) = self.max_sampling();
+        let comp = self.components()[c];
+        let width = ceil_div(width * comp.h as usize, hmax);
+        let height = ceil_div(height * comp.v as usize, vmax);
+
+        (ceil_div(width, 8), ceil_div(height, 8))
+    }
+}
+
+/// The quantized dct coefficients of the blocks of an image
+struct Blocks<'b> {
+    /// The samples of each component at full resolution
+    planes: Vec<Vec<u8>>,
+    width: usize,
+    height: usize,
+
+    components: &'b [Component],
+    tables: &'b [u8],
+}
+
+impl<'b> Blocks<'b> {
+    fn new(planes: Vec<Vec<u8>>,
+           width: usize,
+           height: usize,
+           components: &'b [Component],
+           tables: &'b [u8]) -> Blocks<'b> {
+
+        Blocks {
+            planes: planes,
+            width: width,
+            height: height,
+            components: components,
+            tables: tables,
+        }
+    }
+}
+
+impl<'b> BlockSource for Blocks<'b> {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn components(&self) -> &[Component] {
+        self.components
+    }
+
+    fn block(&self, c: usize, bx: usize, by: usize) -> [i32; 64] {
+        let comp = self.components[c];
+        let plane = &self.planes[c];
+
+        // The number of pixels averaged into one sample
+        let (hmax, vmax) = self.max_sampling();
+        let sx = hmax / comp.h as usize;
+        let sy = vmax / comp.v as usize;
+
+        // Blocks extending past the image repeat its last row and column
+        let mut samples = [0u8; 64];
+        for y in 0usize..8 {
+            for x in 0usize..8 {
+                let mut sum = 0;
+                for j in 0..sy {
+                    let py = cmp::min((by * 8 + y) * sy + j, self.height - 1);
+                    for i in 0..sx {
+                        let px = cmp::min((bx * 8 + x) * sx + i, self.width - 1);
+                        sum += plane[py * self.width + px] as usize;
+                    }
+                }
+
+                samples[y * 8 + x] = ((sum + sx * sy / 2) / (sx * sy)) as u8;
+            }
+        }
+
+        // Level shift and fdct
+        // Coeffs are scaled by 8
+        let mut coefficients = [0i32; 64];
+        dct::fdct(&samples, &mut coefficients);
+
+        // Quantization
+        let table = &self.tables[comp.tq as usize * 64..][..64];
+        for i in 0usize..64 {
+            coefficients[i] = ((coefficients[i] / 8) as f32 / table[i] as f32).round() as i32;
+        }
+
+        coefficients
+    }
 }
 
 pub struct BitWriter<'a, W: 'a> {
@@ -165,7 +469,7 @@ pub struct BitWriter<'a, W: 'a> {
 }
 
 impl<'a, W: Write + 'a> BitWriter<'a, W> {
-    fn new(w: &'a mut W) -> Self {
+    pub fn new(w: &'a mut W) -> Self {
         BitWriter {
             w: w,
             accumulator: 0,
@@ -197,7 +501,12 @@ impl<'a, W: Write + 'a> BitWriter<'a, W> {
     }
 
     fn pad_byte(&mut self) -> io::Result<()> {
-        self.write_bits(0x7F, 7)
+        try!(self.write_bits(0x7F, 7));
+
+        // The bits left over are padding as well, the next scan starts on a fresh byte
+        self.accumulator = 0;
+        self.nbits = 0;
+        Ok(())
     }
 
     fn huffman_encode(&mut self, val: u8, table: &[(u8, u16)]) -> io::Result<()> {
@@ -210,59 +519,7 @@ impl<'a, W: Write + 'a> BitWriter<'a, W> {
         self.write_bits(code, size)
     }
 
-    fn write_block(
-        &mut self,
-        block: &[i32],
-        prevdc: i32,
-        dctable: &[(u8, u16)],
-        actable: &[(u8, u16)]) -> io::Result<i32> {
-
-        // Differential DC encoding
-        let dcval = block[0];
-        let diff  = dcval - prevdc;
-        let (size, value) = encode_coefficient(diff);
-
-        try!(self.huffman_encode(size, dctable));
-        try!(self.write_bits(value, size));
-
-        // Figure F.2
-        let mut zero_run = 0;
-        let mut k = 0usize;
-
-        loop {
-            k += 1;
-
-            if block[UNZIGZAG[k] as usize] == 0 {
-                if k == 63 {
-                    try!(self.huffman_encode(0x00, actable));
-                    break
-                }
-
-                zero_run += 1;
-            } else {
-                while zero_run > 15 {
-                    try!(self.huffman_encode(0xF0, actable));
-                    zero_run -= 16;
-                }
-
-                let (size, value) = encode_coefficient(block[UNZIGZAG[k] as usize]);
-                let symbol = (zero_run << 4) | size;
-
-                try!(self.huffman_encode(symbol, actable));
-                try!(self.write_bits(value, size));
-
-                zero_run = 0;
-
-                if k == 63 {
-                    break
-                }
-            }
-        }
-
-        Ok(dcval)
-    }
-
-    fn write_segment(&mut self, marker: u8, data: Option<&[u8]>) -> io::Result<()> {
+    pub fn write_segment(&mut self, marker: u8, data: Option<&[u8]>) -> io::Result<()> {
         try!(self.w.write_all(&[0xFF]));
         try!(self.w.write_all(&[marker]));
 
@@ -281,10 +538,10 @@ pub struct JPEGEncoder<'a, W: 'a> {
     components: Vec<Component>,
     tables: Vec<u8>,
 
-    luma_dctable: Vec<(u8, u16)>,
-    luma_actable: Vec<(u8, u16)>,
-    chroma_dctable: Vec<(u8, u16)>,
-    chroma_actable: Vec<(u8, u16)>,
+    options: JPEGEncoderOptions,
+
+    exif: Option<Vec<u8>>,
+    icc_profile: Option<Vec<u8>>,
 }
 
 impl<'a, W: Write> JPEGEncoder<'a, W> {
@@ -297,20 +554,28 @@ impl<'a, W: Write> JPEGEncoder<'a, W> {
     /// the quality parameter ```quality``` with a value in the range 1-100
     /// where 1 is the worst and 100 is the best.
     pub fn new_with_quality(w: &mut W, quality: u8) -> JPEGEncoder<W> {
-        let ld = build_huff_lut(&STD_LUMA_DC_CODE_LENGTHS, &STD_LUMA_DC_VALUES);
-        let la = build_huff_lut(&STD_LUMA_AC_CODE_LENGTHS, &STD_LUMA_AC_VALUES);
+        JPEGEncoder::new_with_options(w, JPEGEncoderOptions {
+            quality: quality,
+            ..JPEGEncoderOptions::default()
+        })
+    }
 
-        let cd = build_huff_lut(&STD_CHROMA_DC_CODE_LENGTHS, &STD_CHROMA_DC_VALUES);
-        let ca = build_huff_lut(&STD_CHROMA_AC_CODE_LENGTHS, &STD_CHROMA_AC_VALUES);
+    /// Create a new encoder that writes its output to ```w``` using the settings ```options```.
+    pub fn new_with_options(w: &mut W, options: JPEGEncoderOptions) -> JPEGEncoder<W> {
+        let (h, v) = match options.subsampling {
+            ChromaSubsampling::Yuv444 => (1, 1),
+            ChromaSubsampling::Yuv422 => (2, 1),
+            ChromaSubsampling::Yuv420 => (2, 2),
+        };
 
         let components = vec![
-            Component {id: LUMAID, h: 1, v: 1, tq: LUMADESTINATION, dc_table: LUMADESTINATION, ac_table: LUMADESTINATION, _dc_pred: 0},
-            Component {id: CHROMABLUEID, h: 1, v: 1, tq: CHROMADESTINATION, dc_table: CHROMADESTINATION, ac_table: CHROMADESTINATION, _dc_pred: 0},
-            Component {id: CHROMAREDID, h: 1, v: 1, tq: CHROMADESTINATION, dc_table: CHROMADESTINATION, ac_table: CHROMADESTINATION, _dc_pred: 0}
+            Component {id: LUMAID, h: h, v: v, tq: LUMADESTINATION, dc_table: LUMADESTINATION, ac_table: LUMADESTINATION},
+            Component {id: CHROMABLUEID, h: 1, v: 1, tq: CHROMADESTINATION, dc_table: CHROMADESTINATION, ac_table: CHROMADESTINATION},
+            Component {id: CHROMAREDID, h: 1, v: 1, tq: CHROMADESTINATION, dc_table: CHROMADESTINATION, ac_table: CHROMADESTINATION}
         ];
 
         // Derive our quantization table scaling value using the libjpeg algorithm
-        let scale: u32 = clamp(quality, 1, 100) as u32;
+        let scale: u32 = clamp(options.quality, 1, 100) as u32;
         let scale = if scale < 50 {
             5000 / scale
         } else {
@@ -334,28 +599,99 @@ impl<'a, W: Write> JPEGEncoder<'a, W> {
             components: components,
             tables: tables,
 
-            luma_dctable: ld,
-            luma_actable: la,
-            chroma_dctable: cd,
-            chroma_actable: ca,
+            options: options,
+
+            exif: None,
+            icc_profile: None,
         }
     }
 
+    /// Embed the EXIF data ```exif``` in an APP1 segment of the image.
+    ///
+    /// ```exif``` is the TIFF structure holding the tags, as returned by `Exif::to_bytes`,
+    /// optionally preceded by the ```Exif\0\0``` identifier. It has to fit into a single segment
+    /// of 64 KiB.
+    pub fn set_exif(&mut self, exif: Vec<u8>) {
+        self.exif = Some(exif);
+    }
+
+    /// Embed the ICC profile ```profile``` in the image.
+    ///
+    /// Profiles are split into as many APP2 segments as needed, up to 255 of them.
+    pub fn set_icc_profile(&mut self, profile: Vec<u8>) {
+        self.icc_profile = Some(profile);
+    }
+
     /// Encodes the image ```image```
     /// that has dimensions ```width``` and ```height```
     /// and ```ColorType``` ```c```
     ///
-    /// The Image in encoded with subsampling ratio 4:2:2
+    /// The chroma of color images is subsampled as chosen by the encoder options, alpha channels
+    /// are dropped.
     pub fn encode(&mut self,
                   image: &[u8],
                   width: u32,
                   height: u32,
                   c: color::ColorType) -> io::Result<()> {
 
-        let n = color::num_components(c);
-        let num_components = if n == 1 || n == 2 {1}
+        let bpp = match c {
+            color::ColorType::RGB(8) => 3,
+            color::ColorType::RGBA(8) => 4,
+            color::ColorType::Gray(8) => 1,
+            color::ColorType::GrayA(8) => 2,
+            _  => return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                &format!("Unsupported color type {:?}. Use 8 bit per channel RGB(A) or Gray(A) instead.", c)[..],
+            ))
+        };
+
+        if width == 0 || height == 0 || width > u16::max_value() as u32 || height > u16::max_value() as u32 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                &format!("Invalid image dimensions {}x{}, JPEG supports 1 to 65535 pixels", width, height)[..],
+            ))
+        }
+
+        let (width, height) = (width as usize, height as usize);
+        if image.len() < width * height * bpp {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                &format!("Image buffer of {} bytes is too small for a {}x{} {:?} image", image.len(), width, height, c)[..],
+            ))
+        }
+
+        let exif_len = self.exif.as_ref().map_or(0, |exif| {
+            if exif.starts_with(EXIF_PREFIX) { exif.len() } else { exif.len() + EXIF_PREFIX.len() }
+        });
+        if exif_len > MAX_SEGMENT_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                &format!("EXIF data of {} bytes does not fit into a segment", exif_len)[..],
+            ))
+        }
+
+        let icc_chunks = self.icc_profile.as_ref()
+            .map_or(0, |profile| ceil_div(profile.len(), MAX_SEGMENT_LEN - ICC_PREFIX.len() - 2));
+        if icc_chunks > 255 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ICC profile is too large to be split into 255 segments",
+            ))
+        }
+
+        let num_components = if bpp < 3 {1}
                              else {3};
 
+        // The sampling factors of single component images have no effect
+        let mut components = self.components[..num_components].to_vec();
+        if num_components == 1 {
+            components[0].h = 1;
+            components[0].v = 1;
+        }
+
+        let planes = build_planes(image, width, height, bpp);
+        let blocks = Blocks::new(planes, width, height, &components, &self.tables);
+
         try!(self.writer.write_segment(SOI, None));
 
         let mut buf = Vec::new();
@@ -363,128 +699,227 @@ impl<'a, W: Write> JPEGEncoder<'a, W> {
         build_jfif_header(&mut buf);
         try!(self.writer.write_segment(APP0, Some(&buf)));
 
-        build_frame_header(&mut buf, 8, width as u16, height as u16, &self.components[..num_components]);
-        try!(self.writer.write_segment(SOF0, Some(&buf)));
+        if let Some(ref exif) = self.exif {
+            let mut buf = Vec::new();
+            if !exif.starts_with(EXIF_PREFIX) {
+                buf.extend_from_slice(EXIF_PREFIX);
+            }
+            buf.extend_from_slice(exif);
+            try!(self.writer.write_segment(APP1, Some(&buf)));
+        }
+
+        if let Some(ref profile) = self.icc_profile {
+            // Each chunk is numbered starting from 1, followed by the number of chunks
+            let chunks = profile.chunks(MAX_SEGMENT_LEN - ICC_PREFIX.len() - 2);
+            let count = chunks.len();
+            for (i, chunk) in chunks.enumerate() {
+                let mut buf = ICC_PREFIX.to_vec();
+                buf.push(i as u8 + 1);
+                buf.push(count as u8);
+                buf.extend_from_slice(chunk);
+                try!(self.writer.write_segment(APP2, Some(&buf)));
+            }
+        }
+
+        try!(write_frame(&mut self.writer, &blocks, &self.tables,
+                         self.options.progressive, self.options.optimize_huffman));
 
-        assert_eq!(self.tables.len() / 64, 2);
-        let numtables = if num_components == 1 {1}
-                        else {2};
+        try!(self.writer.write_segment(EOI, None));
+        Ok(())
+    }
+}
 
-        for (i, table) in self.tables.chunks(64).enumerate().take(numtables) {
-            build_quantization_segment(&mut buf, 8, i as u8, table);
-            try!(self.writer.write_segment(DQT, Some(&buf)));
+impl<'a, W: Write> ImageEncoder for JPEGEncoder<'a, W> {
+    fn write_image(mut self, buf: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Write the frame header, the tables and the scans of the image ```blocks```.
+///
+/// ```tables``` contains the quantization tables referenced by the components in natural order.
+pub fn write_frame<W: Write, B: BlockSource>(writer: &mut BitWriter<W>,
+                                             blocks: &B,
+                                             tables: &[u8],
+                                             progressive: bool,
+                                             optimize_huffman: bool) -> io::Result<()> {
+
+    let components = blocks.components();
+    let (width, height) = blocks.dimensions();
+    let scans = build_scans(components.len(), progressive);
+
+    // Indexed by class and destination
+    let mut huffman = vec![
+        HuffmanTable::new(&STD_LUMA_DC_CODE_LENGTHS, &STD_LUMA_DC_VALUES),
+        HuffmanTable::new(&STD_CHROMA_DC_CODE_LENGTHS, &STD_CHROMA_DC_VALUES),
+        HuffmanTable::new(&STD_LUMA_AC_CODE_LENGTHS, &STD_LUMA_AC_VALUES),
+        HuffmanTable::new(&STD_CHROMA_AC_CODE_LENGTHS, &STD_CHROMA_AC_VALUES),
+    ];
+
+    if optimize_huffman {
+        let mut freqs = vec![[0u32; 256]; 4];
+        for scan in &scans {
+            try!(encode_scan(blocks, scan, |class, table, symbol, _, _| {
+                freqs[(class * 2 + table) as usize][symbol as usize] += 1;
+                Ok(())
+            }));
         }
 
-        build_huffman_segment(&mut buf, DCCLASS, LUMADESTINATION,
-                              &STD_LUMA_DC_CODE_LENGTHS, &STD_LUMA_DC_VALUES);
-        try!(self.writer.write_segment(DHT, Some(&buf)));
+        for (table, freq) in huffman.iter_mut().zip(freqs.iter()) {
+            if freq.iter().any(|&f| f > 0) {
+                *table = HuffmanTable::optimal(freq);
+            }
+        }
+    }
 
-        build_huffman_segment(&mut buf, ACCLASS, LUMADESTINATION,
-                              &STD_LUMA_AC_CODE_LENGTHS, &STD_LUMA_AC_VALUES);
-        try!(self.writer.write_segment(DHT, Some(&buf)));
+    let mut buf = Vec::new();
 
-        if num_components == 3 {
-            build_huffman_segment(&mut buf, DCCLASS, CHROMADESTINATION,
-                                  &STD_CHROMA_DC_CODE_LENGTHS, &STD_CHROMA_DC_VALUES);
-            try!(self.writer.write_segment(DHT, Some(&buf)));
+    build_frame_header(&mut buf, 8, width as u16, height as u16, components);
+    let sof = if progressive {SOF2}
+              else {SOF0};
+    try!(writer.write_segment(sof, Some(&buf)));
 
-            build_huffman_segment(&mut buf, ACCLASS, CHROMADESTINATION,
-                                  &STD_CHROMA_AC_CODE_LENGTHS, &STD_CHROMA_AC_VALUES);
-            try!(self.writer.write_segment(DHT, Some(&buf)));
+    let mut written: Vec<u8> = Vec::new();
+    for comp in components {
+        if !written.contains(&comp.tq) {
+            build_quantization_segment(&mut buf, 8, comp.tq, &tables[comp.tq as usize * 64..][..64]);
+            try!(writer.write_segment(DQT, Some(&buf)));
+            written.push(comp.tq);
         }
+    }
 
-        build_scan_header(&mut buf, &self.components[..num_components]);
-        try!(self.writer.write_segment(SOS, Some(&buf)));
+    let numtables = components.iter().map(|c| cmp::max(c.dc_table, c.ac_table)).max().unwrap_or(0) + 1;
+    for destination in 0..numtables {
+        for &class in &[DCCLASS, ACCLASS] {
+            let table = &huffman[(class * 2 + destination) as usize];
+            build_huffman_segment(&mut buf, class, destination, &table.bits, &table.values);
+            try!(writer.write_segment(DHT, Some(&buf)));
+        }
+    }
 
-        match c {
-            color::ColorType::RGB(8)   => try!(self.encode_rgb(image, width as usize, height as usize, 3)),
-            color::ColorType::RGBA(8)  => try!(self.encode_rgb(image, width as usize, height as usize, 4)),
-            color::ColorType::Gray(8)  => try!(self.encode_gray(image, width as usize, height as usize, 1)),
-            color::ColorType::GrayA(8) => try!(self.encode_gray(image, width as usize, height as usize, 2)),
-            _  => return Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                &format!("Unsupported color type {:?}. Use 8 bit per channel RGB(A) or Gray(A) instead.", c)[..],
-            ))
-        };
+    for scan in &scans {
+        let scan_components: Vec<Component> = scan.components.iter().map(|&i| components[i]).collect();
+        build_scan_header(&mut buf, &scan_components, scan.ss, scan.se);
+        try!(writer.write_segment(SOS, Some(&buf)));
 
-        try!(self.writer.pad_byte());
-        try!(self.writer.write_segment(EOI, None));
-        Ok(())
+        {
+            let writer = &mut *writer;
+            try!(encode_scan(blocks, scan, |class, table, symbol, value, size| {
+                try!(writer.huffman_encode(symbol, &huffman[(class * 2 + table) as usize].lut));
+                writer.write_bits(value, size)
+            }));
+        }
+
+        try!(writer.pad_byte());
     }
 
-    fn encode_gray(&mut self, image: &[u8], width: usize, height: usize, bpp: usize) -> io::Result<()> {
-        let mut yblock     = [0u8; 64];
-        let mut y_dcprev   = 0;
-        let mut dct_yblock = [0i32; 64];
+    Ok(())
+}
 
-        for y in range_step(0, height, 8) {
-            for x in range_step(0, width, 8) {
-                // RGB -> YCbCr
-                copy_blocks_gray(image, x, y, width, bpp, &mut yblock);
+/// The scans storing all coefficients of an image with ```num_components``` components
+fn build_scans(num_components: usize, progressive: bool) -> Vec<Scan> {
+    let all = (0..num_components).collect();
 
-                // Level shift and fdct
-                // Coeffs are scaled by 8
-                transform::fdct(&yblock, &mut dct_yblock);
+    if !progressive {
+        return vec![Scan { components: all, ss: 0, se: 63 }]
+    }
 
-                // Quantization
-                for i in 0usize..64 {
-                    dct_yblock[i]   = ((dct_yblock[i] / 8)   as f32 / self.tables[i] as f32).round() as i32;
-                }
+    // The dc coefficients first, followed by the low and high frequencies of each component
+    let mut scans = vec![Scan { components: all, ss: 0, se: 0 }];
+    for c in 0..num_components {
+        scans.push(Scan { components: vec![c], ss: 1, se: 5 });
+        scans.push(Scan { components: vec![c], ss: 6, se: 63 });
+    }
 
-                let la = &*self.luma_actable;
-                let ld = &*self.luma_dctable;
+    scans
+}
 
-                y_dcprev  = try!(self.writer.write_block(&dct_yblock, y_dcprev, ld, la));
+/// Pass the huffman symbols of ```scan``` to ```emit``` along with the class and destination of
+/// the table they are coded with and the additional bits following them.
+fn encode_scan<B, F>(blocks: &B, scan: &Scan, mut emit: F) -> io::Result<()>
+    where B: BlockSource, F: FnMut(u8, u8, u8, u16, u8) -> io::Result<()> {
+
+    let (ss, se) = (scan.ss as usize, scan.se as usize);
+    let mut prevdc = vec![0; blocks.components().len()];
+
+    if scan.components.len() == 1 {
+        // Non-interleaved scans only contain the blocks covering the component
+        let c = scan.components[0];
+        let comp = blocks.components()[c];
+        let (width, height) = blocks.blocks(c);
+
+        for by in 0..height {
+            for bx in 0..width {
+                let block = blocks.block(c, bx, by);
+                prevdc[c] = try!(encode_block(&block, prevdc[c], ss, se, &mut |class, symbol, value, size| {
+                    let table = if class == DCCLASS {comp.dc_table} else {comp.ac_table};
+                    emit(class, table, symbol, value, size)
+                }));
             }
         }
 
-        Ok(())
+        return Ok(())
     }
 
-    fn encode_rgb(&mut self, image: &[u8], width: usize, height: usize, bpp: usize) -> io::Result<()> {
-        let mut y_dcprev = 0;
-        let mut cb_dcprev = 0;
-        let mut cr_dcprev = 0;
-
-        let mut dct_yblock   = [0i32; 64];
-        let mut dct_cb_block = [0i32; 64];
-        let mut dct_cr_block = [0i32; 64];
+    let (mcux, mcuy) = blocks.mcus();
+    for my in 0..mcuy {
+        for mx in 0..mcux {
+            for &c in &scan.components {
+                let comp = blocks.components()[c];
+                let (h, v) = (comp.h as usize, comp.v as usize);
+
+                for y in 0..v {
+                    for x in 0..h {
+                        let block = blocks.block(c, mx * h + x, my * v + y);
+                        prevdc[c] = try!(encode_block(&block, prevdc[c], ss, se, &mut |class, symbol, value, size| {
+                            let table = if class == DCCLASS {comp.dc_table} else {comp.ac_table};
+                            emit(class, table, symbol, value, size)
+                        }));
+                    }
+                }
+            }
+        }
+    }
 
-        let mut yblock   = [0u8; 64];
-        let mut cb_block = [0u8; 64];
-        let mut cr_block = [0u8; 64];
+    Ok(())
+}
 
-        for y in range_step(0, height, 8) {
-            for x in range_step(0, width, 8) {
-                // RGB -> YCbCr
-                copy_blocks_ycbcr(image, x, y, width, bpp, &mut yblock, &mut cb_block, &mut cr_block);
+/// Pass the huffman symbols and additional bits of the coefficients ```ss``` to ```se``` of
+/// ```block``` to ```emit```, returns the dc coefficient for predicting the next block.
+fn encode_block<F>(block: &[i32; 64], prevdc: i32, ss: usize, se: usize, emit: &mut F) -> io::Result<i32>
+    where F: FnMut(u8, u8, u16, u8) -> io::Result<()> {
 
-                // Level shift and fdct
-                // Coeffs are scaled by 8
-                transform::fdct(&yblock, &mut dct_yblock);
-                transform::fdct(&cb_block, &mut dct_cb_block);
-                transform::fdct(&cr_block, &mut dct_cr_block);
+    if ss == 0 {
+        // Differential DC encoding
+        let (size, value) = encode_coefficient(block[0] - prevdc);
+        try!(emit(DCCLASS, size, value, size));
+    }
 
-                // Quantization
-                for i in 0usize..64 {
-                    dct_yblock[i]   = ((dct_yblock[i] / 8)   as f32 / self.tables[i] as f32).round() as i32;
-                    dct_cb_block[i] = ((dct_cb_block[i] / 8) as f32 / self.tables[64..][i] as f32).round() as i32;
-                    dct_cr_block[i] = ((dct_cr_block[i] / 8) as f32 / self.tables[64..][i] as f32).round() as i32;
-                }
+    // Figure F.2
+    let mut zero_run = 0;
+    for k in cmp::max(ss, 1)..se + 1 {
+        let coefficient = block[UNZIGZAG[k] as usize];
 
-                let la = &*self.luma_actable;
-                let ld = &*self.luma_dctable;
-                let cd = &*self.chroma_dctable;
-                let ca = &*self.chroma_actable;
+        if coefficient == 0 {
+            zero_run += 1;
+            continue
+        }
 
-                y_dcprev  = try!(self.writer.write_block(&dct_yblock, y_dcprev, ld, la));
-                cb_dcprev = try!(self.writer.write_block(&dct_cb_block, cb_dcprev, cd, ca));
-                cr_dcprev = try!(self.writer.write_block(&dct_cr_block, cr_dcprev, cd, ca));
-            }
+        while zero_run > 15 {
+            try!(emit(ACCLASS, 0xF0, 0, 0));
+            zero_run -= 16;
         }
 
-        Ok(())
+        let (size, value) = encode_coefficient(coefficient);
+        try!(emit(ACCLASS, (zero_run << 4) | size, value, size));
+        zero_run = 0;
+    }
+
+    if zero_run > 0 {
+        try!(emit(ACCLASS, 0x00, 0, 0));
     }
+
+    Ok(block[0])
 }
 
 fn build_jfif_header(m: &mut Vec<u8>) {
@@ -521,7 +956,7 @@ fn build_frame_header(m: &mut Vec<u8>,
     }
 }
 
-fn build_scan_header(m: &mut Vec<u8>, components: &[Component]) {
+fn build_scan_header(m: &mut Vec<u8>, components: &[Component], ss: u8, se: u8) {
     m.clear();
 
     let _ = m.write_all(&[components.len() as u8]);
@@ -533,8 +968,8 @@ fn build_scan_header(m: &mut Vec<u8>, components: &[Component]) {
     }
 
     // spectral start and end, approx. high and low
-    let _ = m.write_all(&[0]);
-    let _ = m.write_all(&[63]);
+    let _ = m.write_all(&[ss]);
+    let _ = m.write_all(&[se]);
     let _ = m.write_all(&[0]);
 }
 
@@ -615,67 +1050,64 @@ fn rgb_to_ycbcr(r: u8, g: u8, b: u8) -> (u8, u8, u8) {
     (y as u8, cb as u8, cr as u8)
 }
 
-fn value_at(s: &[u8], index: usize) -> u8 {
-    if index < s.len() {
-        s[index]
-    } else {
-        s[s.len() - 1]
-    }
-}
-
-fn copy_blocks_ycbcr(source: &[u8],
-                     x0: usize,
-                     y0: usize,
-                     width: usize,
-                     bpp: usize,
-                     yb: &mut [u8; 64],
-                     cbb: &mut [u8; 64],
-                     crb: &mut [u8; 64]) {
-
-    for y in 0usize..8 {
-        let ystride = (y0 + y) * bpp * width;
-
-        for x in 0usize..8 {
-            let xstride = x0 * bpp + x * bpp;
-
-            let r = value_at(source, ystride + xstride + 0);
-            let g = value_at(source, ystride + xstride + 1);
-            let b = value_at(source, ystride + xstride + 2);
+/// Split the pixels into full resolution planes of either gray or YCbCr samples
+fn build_planes(image: &[u8], width: usize, height: usize, bpp: usize) -> Vec<Vec<u8>> {
+    let pixels = image[..width * height * bpp].chunks(bpp);
 
-            let (yc, cb, cr) = rgb_to_ycbcr(r, g, b);
-
-            yb[y * 8 + x]  = yc;
-            cbb[y * 8 + x] = cb;
-            crb[y * 8 + x] = cr;
-        }
+    if bpp < 3 {
+        return vec![pixels.map(|p| p[0]).collect()]
     }
-}
 
-fn copy_blocks_gray(source: &[u8],
-                    x0: usize,
-                    y0: usize,
-                    width: usize,
-                    bpp: usize,
-                    gb: &mut [u8; 64]) {
+    let mut planes = vec![Vec::with_capacity(width * height); 3];
+    for p in pixels {
+        let (y, cb, cr) = rgb_to_ycbcr(p[0], p[1], p[2]);
+        planes[0].push(y);
+        planes[1].push(cb);
+        planes[2].push(cr);
+    }
 
-    for y in 0usize..8 {
-        let ystride = (y0 + y) * bpp * width;
+    planes
+}
 
-        for x in 0usize..8 {
-            let xstride = x0 * bpp + x * bpp;
-            gb[y * 8 + x] = value_at(source, ystride + xstride + 1);
-        }
-    }
+fn ceil_div(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
 }
 
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
-    use super::JPEGEncoder;
+    use super::{ChromaSubsampling, HuffmanTable, JPEGEncoder, JPEGEncoderOptions};
     use super::super::JPEGDecoder;
     use color::ColorType;
     use image::{ImageDecoder, DecodingResult};
 
+    fn encode(img: &[u8], width: u32, height: u32, color: ColorType, options: JPEGEncoderOptions) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        JPEGEncoder::new_with_options(&mut encoded, options)
+            .encode(img, width, height, color)
+            .expect("Could not encode image");
+        encoded
+    }
+
+    fn decode(encoded: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut decoder = JPEGDecoder::new(Cursor::new(encoded));
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().expect("Could not decode image") {
+            DecodingResult::U8(decoded) => (width, height, decoded),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut img = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                img.extend_from_slice(&[(x * 3) as u8, (y * 5) as u8, (255 - x * 2 - y * 2) as u8]);
+            }
+        }
+        img
+    }
+
     #[test]
     fn roundtrip_sanity_check() {
         // create a 1x1 8-bit image buffer containing a single red pixel
@@ -708,4 +1140,108 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn roundtrip_options() {
+        // Dimensions which are not a multiple of the MCU size
+        let img = gradient(37, 29);
+
+        for &subsampling in &[ChromaSubsampling::Yuv444, ChromaSubsampling::Yuv422, ChromaSubsampling::Yuv420] {
+            for &progressive in &[false, true] {
+                for &optimize_huffman in &[false, true] {
+                    let options = JPEGEncoderOptions {
+                        quality: 90,
+                        subsampling: subsampling,
+                        progressive: progressive,
+                        optimize_huffman: optimize_huffman,
+                    };
+                    let (width, height, decoded) = decode(&encode(&img, 37, 29, ColorType::RGB(8), options));
+                    assert_eq!((width, height), (37, 29));
+                    assert_eq!(decoded.len(), img.len());
+
+                    let error: u32 = img.iter().zip(&decoded).map(|(&a, &b)| (a as i32 - b as i32).abs() as u32).sum();
+                    assert!(error / (img.len() as u32) < 4, "{:?}: mean error {}", options, error / img.len() as u32);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn progressive_gray() {
+        let img: Vec<u8> = (0..40 * 20).map(|i| (i % 40 * 5 + i / 40) as u8).collect();
+        let options = JPEGEncoderOptions { progressive: true, ..JPEGEncoderOptions::default() };
+        let encoded = encode(&img, 40, 20, ColorType::Gray(8), options);
+
+        // Progressive images start with SOF2 instead of SOF0
+        assert!(encoded.windows(2).any(|w| w == [0xFF, 0xC2]));
+        assert!(!encoded.windows(2).any(|w| w == [0xFF, 0xC0]));
+
+        let (width, height, decoded) = decode(&encoded);
+        assert_eq!((width, height), (40, 20));
+        let error: u32 = img.iter().zip(&decoded).map(|(&a, &b)| (a as i32 - b as i32).abs() as u32).sum();
+        assert!(error / (img.len() as u32) < 4);
+    }
+
+    #[test]
+    fn optimized_huffman_is_smaller() {
+        let img = gradient(64, 48);
+        let standard = encode(&img, 64, 48, ColorType::RGB(8), JPEGEncoderOptions::default());
+        let optimized = encode(&img, 64, 48, ColorType::RGB(8), JPEGEncoderOptions {
+            optimize_huffman: true,
+            ..JPEGEncoderOptions::default()
+        });
+        assert!(optimized.len() < standard.len());
+    }
+
+    #[test]
+    fn optimal_table_limits_code_lengths() {
+        // Fibonacci frequencies produce codes longer than 16 bits before adjustment
+        let mut freq = [0u32; 256];
+        let (mut a, mut b) = (1, 1);
+        for f in freq.iter_mut().take(30) {
+            *f = a;
+            let next = a + b;
+            a = b;
+            b = next;
+        }
+
+        let table = HuffmanTable::optimal(&freq);
+        assert_eq!(table.values.len(), 30);
+        assert_eq!(table.bits.iter().map(|&b| b as usize).sum::<usize>(), 30);
+
+        // The codes leave room for at least one more, so none consists of only ones
+        let kraft: u32 = table.bits.iter().enumerate().map(|(i, &b)| (b as u32) << (15 - i)).sum();
+        assert!(kraft < 1 << 16);
+
+        // Frequent symbols get shorter codes
+        assert!(table.lut[29].0 <= table.lut[10].0);
+        assert!(table.lut[10].0 <= table.lut[0].0);
+        assert!(table.lut[0].0 <= 16);
+    }
+
+    #[test]
+    fn metadata_segments() {
+        // A TIFF structure holding the orientation 6
+        let exif = b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0".to_vec();
+        // Large enough to be split into three segments
+        let profile: Vec<u8> = (0..150000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = JPEGEncoder::new(&mut encoded);
+            encoder.set_exif(exif);
+            encoder.set_icc_profile(profile.clone());
+            encoder.encode(&gradient(16, 16), 16, 16, ColorType::RGB(8)).unwrap();
+        }
+        assert_eq!(encoded.windows(12).filter(|w| w == b"ICC_PROFILE\0").count(), 3);
+
+        let mut decoder = JPEGDecoder::new(Cursor::new(&encoded));
+        assert_eq!(decoder.orientation().unwrap(), Some(6));
+        assert_eq!(decoder.icc_profile().unwrap(), Some(profile));
+        assert_eq!(decode(&encoded).0, 16);
+
+        let mut encoder = JPEGEncoder::new(&mut encoded);
+        encoder.set_exif(vec![0; 70000]);
+        assert!(encoder.encode(&gradient(16, 16), 16, 16, ColorType::RGB(8)).is_err());
+    }
 }
@@ -2,15 +2,17 @@
 //!
 //! JPEG (Joint Photographic Experts Group) is an image format that supports lossy compression.
 //! This module implements the Baseline JPEG standard.
+//! The `transform` module rotates, mirrors and crops JPEG images without recompressing them.
 //!
 //! # Related Links
 //! * <http://www.w3.org/Graphics/JPEG/itu-t81.pdf> - The JPEG specification
 //!
 
 pub use self::decoder::JPEGDecoder;
-pub use self::encoder::JPEGEncoder;
+pub use self::encoder::{JPEGEncoder, JPEGEncoderOptions, ChromaSubsampling};
 
 mod encoder;
 mod decoder;
 mod entropy;
-mod transform;
+mod dct;
+pub mod transform;
@@ -1,196 +1,1148 @@
-/*
-fdct is a Rust translation of jfdctint.c from the
-Independent JPEG Group's libjpeg version 9a
-obtained from http://www.ijg.org/files/jpegsr9a.zip
-It comes with the following conditions of distribution and use:
-
-	In plain English:
-
-	1. We don't promise that this software works.  (But if you find any bugs,
-		please let us know!)
-	2. You can use this software for whatever you want.  You don't have to pay us.
-	3. You may not pretend that you wrote this software.  If you use it in a
-	   program, you must acknowledge somewhere in your documentation that
-	   you've used the IJG code.
-
-	In legalese:
-
-	The authors make NO WARRANTY or representation, either express or implied,
-	with respect to this software, its quality, accuracy, merchantability, or
-	fitness for a particular purpose.  This software is provided "AS IS", and you,
-	its user, assume the entire risk as to its quality and accuracy.
-
-	This software is copyright (C) 1991-2014, Thomas G. Lane, Guido Vollbeding.
-	All Rights Reserved except as specified below.
-
-	Permission is hereby granted to use, copy, modify, and distribute this
-	software (or portions thereof) for any purpose, without fee, subject to these
-	conditions:
-	(1) If any part of the source code for this software is distributed, then this
-	README file must be included, with this copyright and no-warranty notice
-	unaltered; and any additions, deletions, or changes to the original files
-	must be clearly indicated in accompanying documentation.
-	(2) If only executable code is distributed, then the accompanying
-	documentation must state that "this software is based in part on the work of
-	the Independent JPEG Group".
-	(3) Permission for use of this software is granted only if the user accepts
-	full responsibility for any undesirable consequences; the authors accept
-	NO LIABILITY for damages of any kind.
-
-	These conditions apply to any software derived from or based on the IJG code,
-	not just to the unmodified library.  If you use our work, you ought to
-	acknowledge us.
-
-	Permission is NOT granted for the use of any IJG author's name or company name
-	in advertising or publicity relating to this software or products derived from
-	it.  This software may be referred to only as "the Independent JPEG Group's
-	software".
-
-	We specifically permit and encourage the use of this software as the basis of
-	commercial products, provided that all warranty or liability claims are
-	assumed by the product vendor.
-*/
-
-static CONST_BITS: i32 = 13;
-static PASS1_BITS: i32 = 2;
-
-static FIX_0_298631336: i32 = 2446;
-static FIX_0_390180644: i32 = 3196;
-static FIX_0_541196100: i32 = 4433;
-static FIX_0_765366865: i32 = 6270;
-static FIX_0_899976223: i32 = 7373;
-static FIX_1_175875602: i32 = 9633;
-static FIX_1_501321110: i32 = 12299;
-static FIX_1_847759065: i32 = 15137;
-static FIX_1_961570560: i32 = 16069;
-static FIX_2_053119869: i32 = 16819;
-static FIX_2_562915447: i32 = 20995;
-static FIX_3_072711026: i32 = 25172;
-
-pub fn fdct(samples: &[u8], coeffs: &mut [i32]) {
-    // Pass 1: process rows.
-    // Results are scaled by sqrt(8) compared to a true DCT
-    // furthermore we scale the results by 2**PASS1_BITS
-    for y in 0usize..8 {
-        let y0 = y * 8;
-
-        // Even part
-        let t0 = samples[y0 + 0] as i32 + samples[y0 + 7] as i32;
-        let t1 = samples[y0 + 1] as i32 + samples[y0 + 6] as i32;
-        let t2 = samples[y0 + 2] as i32 + samples[y0 + 5] as i32;
-        let t3 = samples[y0 + 3] as i32 + samples[y0 + 4] as i32;
-
-        let t10 = t0 + t3;
-        let t12 = t0 - t3;
-        let t11 = t1 + t2;
-        let t13 = t1 - t2;
-
-        let t0 = samples[y0 + 0] as i32 - samples[y0 + 7] as i32;
-        let t1 = samples[y0 + 1] as i32 - samples[y0 + 6] as i32;
-        let t2 = samples[y0 + 2] as i32 - samples[y0 + 5] as i32;
-        let t3 = samples[y0 + 3] as i32 - samples[y0 + 4] as i32;
-
-        // Apply unsigned -> signed conversion
-        coeffs[y0 + 0] = (t10 + t11 - 8 * 128) << PASS1_BITS as usize;
-        coeffs[y0 + 4] = (t10 - t11) << PASS1_BITS as usize;
-
-        let mut z1 = (t12 + t13) * FIX_0_541196100;
-        // Add fudge factor here for final descale
-        z1 += 1 << (CONST_BITS - PASS1_BITS - 1) as usize;
-
-        coeffs[y0 + 2] = (z1 + t12 * FIX_0_765366865) >> (CONST_BITS - PASS1_BITS) as usize;
-        coeffs[y0 + 6] = (z1 - t13 * FIX_1_847759065) >> (CONST_BITS - PASS1_BITS) as usize;
-
-        // Odd part
-        let t12 = t0 + t2;
-        let t13 = t1 + t3;
-
-        let mut z1 = (t12 + t13) * FIX_1_175875602;
-        // Add fudge factor here for final descale
-        z1 += 1 << (CONST_BITS - PASS1_BITS - 1) as usize;
-
-        let mut t12 = t12 * (-FIX_0_390180644);
-        let mut t13 = t13 * (-FIX_1_961570560);
-        t12 += z1;
-        t13 += z1;
-
-        let z1 = (t0 + t3) * (-FIX_0_899976223);
-        let mut t0 = t0 * FIX_1_501321110;
-        let mut t3 = t3 * FIX_0_298631336;
-        t0 += z1 + t12;
-        t3 += z1 + t13;
-
-        let z1 = (t1 + t2) * (-FIX_2_562915447);
-        let mut t1 = t1 * FIX_3_072711026;
-        let mut t2 = t2 * FIX_2_053119869;
-        t1 += z1 + t13;
-        t2 += z1 + t12;
-
-        coeffs[y0 + 1] = t0 >> (CONST_BITS - PASS1_BITS) as usize;
-        coeffs[y0 + 3] = t1 >> (CONST_BITS - PASS1_BITS) as usize;
-        coeffs[y0 + 5] = t2 >> (CONST_BITS - PASS1_BITS) as usize;
-        coeffs[y0 + 7] = t3 >> (CONST_BITS - PASS1_BITS) as usize;
-    }
-
-    // Pass 2: process columns
-    // We remove the PASS1_BITS scaling but leave the results scaled up an
-    // overall factor of 8
-    for x in (0usize..8).rev() {
-        // Even part
-        let t0 = coeffs[x + 8 * 0] + coeffs[x + 8 * 7];
-        let t1 = coeffs[x + 8 * 1] + coeffs[x + 8 * 6];
-        let t2 = coeffs[x + 8 * 2] + coeffs[x + 8 * 5];
-        let t3 = coeffs[x + 8 * 3] + coeffs[x + 8 * 4];
-
-        // Add fudge factor here for final descale
-        let t10 = t0 + t3 + (1 << (PASS1_BITS - 1) as usize);
-        let t12 = t0 - t3;
-        let t11 = t1 + t2;
-        let t13 = t1 - t2;
-
-        let t0 = coeffs[x + 8 * 0] - coeffs[x + 8 * 7];
-        let t1 = coeffs[x + 8 * 1] - coeffs[x + 8 * 6];
-        let t2 = coeffs[x + 8 * 2] - coeffs[x + 8 * 5];
-        let t3 = coeffs[x + 8 * 3] - coeffs[x + 8 * 4];
-
-        coeffs[x + 8 * 0] = (t10 + t11) >> PASS1_BITS as usize;
-        coeffs[x + 8 * 4] = (t10 - t11) >> PASS1_BITS as usize;
-
-        let mut z1 = (t12 + t13) * FIX_0_541196100;
-        // Add fudge factor here for final descale
-        z1 += 1 << (CONST_BITS + PASS1_BITS - 1) as usize;
-
-        coeffs[x + 8 * 2] = (z1 + t12 * FIX_0_765366865) >> (CONST_BITS + PASS1_BITS) as usize;
-        coeffs[x + 8 * 6] = (z1 - t13 * FIX_1_847759065) >> (CONST_BITS + PASS1_BITS) as usize;
-
-        // Odd part
-        let t12 = t0 + t2;
-        let t13 = t1 + t3;
-
-        let mut z1 = (t12 + t13) * FIX_1_175875602;
-        // Add fudge factor here for final descale
-        z1 += 1 << (CONST_BITS - PASS1_BITS - 1) as usize;
-
-        let mut t12 = t12 * (-FIX_0_390180644);
-        let mut t13 = t13 * (-FIX_1_961570560);
-        t12 += z1;
-        t13 += z1;
-
-        let z1 = (t0 + t3) * (-FIX_0_899976223);
-        let mut t0 = t0 * FIX_1_501321110;
-        let mut t3 = t3 * FIX_0_298631336;
-        t0 += z1 + t12;
-        t3 += z1 + t13;
-
-        let z1 = (t1 + t2) * (-FIX_2_562915447);
-        let mut t1 = t1 * FIX_3_072711026;
-        let mut t2 = t2 * FIX_2_053119869;
-        t1 += z1 + t13;
-        t2 += z1 + t12;
-
-        coeffs[x + 8 * 1] = t0 >> (CONST_BITS + PASS1_BITS) as usize;
-        coeffs[x + 8 * 3] = t1 >> (CONST_BITS + PASS1_BITS) as usize;
-        coeffs[x + 8 * 5] = t2 >> (CONST_BITS + PASS1_BITS) as usize;
-        coeffs[x + 8 * 7] = t3 >> (CONST_BITS + PASS1_BITS) as usize;
+//! Lossless transformations of JPEG images
+//!
+//! The transformations rearrange the quantized dct coefficients of an image instead of decoding
+//! and re-encoding its pixels, so they do not degrade it any further. Baseline and progressive
+//! images are supported, the result is written in the same mode with optimized huffman tables.
+//! Application segments and comments are copied unchanged.
+//!
+//! Only whole MCUs (blocks of 8x8 up to 32x32 pixels, depending on the chroma subsampling) can be
+//! moved. Like `jpegtran -trim`, partial MCUs at an edge which would end up on the opposite
+//! side are dropped, so the result can be a few pixels smaller than the original.
+//!
+//! `repair` rewrites truncated or corrupt images the same way, keeping the part which could be
+//! decoded.
+
+use std::cmp;
+use std::io::{Read, Write};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use image::{ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+use super::encoder::{self, BitWriter, BlockSource, Component, UNZIGZAG};
+
+// Markers
+static SOF0: u8 = 0xC0;
+static SOF1: u8 = 0xC1;
+static SOF2: u8 = 0xC2;
+static DHT: u8 = 0xC4;
+static SOI: u8 = 0xD8;
+static EOI: u8 = 0xD9;
+static SOS: u8 = 0xDA;
+static DQT: u8 = 0xDB;
+static DRI: u8 = 0xDD;
+static COM: u8 = 0xFE;
+
+/// Rotate the image read from ```r``` by 90 degrees clockwise and write it to ```w```.
+pub fn rotate90<R: Read, W: Write>(r: R, w: &mut W) -> ImageResult<()> {
+    apply(r, w, |frame| frame.rearrange(true, true, false))
+}
+
+/// Rotate the image read from ```r``` by 180 degrees and write it to ```w```.
+pub fn rotate180<R: Read, W: Write>(r: R, w: &mut W) -> ImageResult<()> {
+    apply(r, w, |frame| frame.rearrange(false, true, true))
+}
+
+/// Rotate the image read from ```r``` by 270 degrees clockwise and write it to ```w```.
+pub fn rotate270<R: Read, W: Write>(r: R, w: &mut W) -> ImageResult<()> {
+    apply(r, w, |frame| frame.rearrange(true, false, true))
+}
+
+/// Mirror the image read from ```r``` horizontally and write it to ```w```.
+pub fn flip_horizontal<R: Read, W: Write>(r: R, w: &mut W) -> ImageResult<()> {
+    apply(r, w, |frame| frame.rearrange(false, true, false))
+}
+
+/// Mirror the image read from ```r``` vertically and write it to ```w```.
+pub fn flip_vertical<R: Read, W: Write>(r: R, w: &mut W) -> ImageResult<()> {
+    apply(r, w, |frame| frame.rearrange(false, false, true))
+}
+
+/// Crop the image read from ```r``` to the rectangle with the top left corner at ```x```, ```y```
+/// and write it to ```w```.
+///
+/// ```x``` and ```y``` have to be multiples of the MCU size of the image. The rectangle is
+/// clipped to the bounds of the image.
+pub fn crop<R: Read, W: Write>(r: R, w: &mut W, x: u32, y: u32, width: u32, height: u32) -> ImageResult<()> {
+    apply(r, w, |frame| frame.crop(x as usize, y as usize, width as usize, height as usize))
+}
+
+/// Rewrite the damaged image read from ```r``` to a valid image written to ```w```.
+///
+/// The image is decoded up to the first truncated or corrupt scan, the blocks which could not be
+/// decoded are left without coefficients and turn out gray. Returns the error describing the
+/// damage, or `None` if the image was intact and has just been copied. Images without a frame
+/// header cannot be repaired.
+pub fn repair<R: Read, W: Write>(mut r: R, w: &mut W) -> ImageResult<Option<ImageError>> {
+    let mut data = Vec::new();
+    try!(r.read_to_end(&mut data));
+
+    let (frame, damage) = try!(Frame::parse(&data, true));
+    try!(frame.write(w));
+    Ok(damage)
+}
+
+fn apply<R, W, F>(mut r: R, w: &mut W, transform: F) -> ImageResult<()>
+    where R: Read, W: Write, F: FnOnce(&Frame) -> ImageResult<Frame> {
+
+    let mut data = Vec::new();
+    try!(r.read_to_end(&mut data));
+
+    let frame = try!(Frame::read(&data));
+    let frame = try!(transform(&frame));
+    try!(frame.write(w));
+    Ok(())
+}
+
+/// The quantized dct coefficients of an image along with everything needed to write them
+struct Frame {
+    width: usize,
+    height: usize,
+    progressive: bool,
+
+    components: Vec<Component>,
+
+    /// The coefficients of all blocks of the MCUs of each component, in natural order
+    coefficients: Vec<Vec<i32>>,
+
+    /// Four quantization tables in natural order
+    tables: Vec<u8>,
+
+    /// Application and comment segments
+    segments: Vec<(u8, Vec<u8>)>,
+}
+
+impl BlockSource for Frame {
+    fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn components(&self) -> &[Component] {
+        &self.components
+    }
+
+    fn block(&self, c: usize, bx: usize, by: usize) -> [i32; 64] {
+        let mut block = [0i32; 64];
+        let stride = self.stride(c);
+        let start = (by * stride + bx) * 64;
+        if bx < stride && start < self.coefficients[c].len() {
+            block.copy_from_slice(&self.coefficients[c][start..start + 64]);
+        }
+        block
+    }
+}
+
+impl Frame {
+    /// An empty frame, all coefficients are zero
+    fn new(width: usize, height: usize, progressive: bool, components: Vec<Component>,
+           tables: Vec<u8>, segments: Vec<(u8, Vec<u8>)>) -> Frame {
+
+        let mut frame = Frame {
+            width: width,
+            height: height,
+            progressive: progressive,
+            components: components,
+            coefficients: Vec::new(),
+            tables: tables,
+            segments: segments,
+        };
+
+        let (mcux, mcuy) = frame.mcus();
+        frame.coefficients = frame.components.iter()
+            .map(|c| vec![0; mcux * c.h as usize * mcuy * c.v as usize * 64])
+            .collect();
+        frame
+    }
+
+    /// The number of blocks in a row of the component ```c```, including those padding the MCUs
+    fn stride(&self, c: usize) -> usize {
+        self.mcus().0 * self.components[c].h as usize
+    }
+
+    fn block_mut(&mut self, c: usize, bx: usize, by: usize) -> &mut [i32] {
+        let start = (by * self.stride(c) + bx) * 64;
+        &mut self.coefficients[c][start..start + 64]
+    }
+
+    fn read(data: &[u8]) -> ImageResult<Frame> {
+        Frame::parse(data, false).map(|(frame, _)| frame)
+    }
+
+    /// Read the frame of a complete image.
+    ///
+    /// If ```recover``` is set, parsing stops at the first damaged scan or segment instead of
+    /// failing, returning the frame decoded so far along with the error.
+    fn parse(data: &[u8], recover: bool) -> ImageResult<(Frame, Option<ImageError>)> {
+        if data.len() < 2 || data[0] != 0xFF || data[1] != SOI {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Missing SOI marker".to_string()))
+        }
+
+        let mut parser = Parser {
+            frame: None,
+            tables: vec![0u8; 4 * 64],
+            dc_tables: vec![None, None, None, None],
+            ac_tables: vec![None, None, None, None],
+            restart_interval: 0,
+            segments: Vec::new(),
+        };
+
+        let mut damage = None;
+        let mut pos = 2;
+        loop {
+            match parser.segment(data, &mut pos) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(err) => {
+                    if !recover || parser.frame.is_none() {
+                        return Err(err)
+                    }
+                    damage = Some(err);
+                    break
+                }
+            }
+        }
+
+        match parser.frame {
+            Some(mut frame) => {
+                frame.tables = parser.tables;
+                frame.segments = parser.segments;
+                Ok((frame, damage))
+            }
+            None => Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Missing frame header".to_string())),
+        }
+    }
+
+    fn decode_scan(&mut self,
+                   scan: &ScanHeader,
+                   dc_tables: &[Option<HuffmanDecoder>],
+                   ac_tables: &[Option<HuffmanDecoder>],
+                   restart_interval: usize,
+                   reader: &mut BitReader) -> ImageResult<()> {
+
+        let mut decoders = Vec::new();
+        for &(c, td, ta) in &scan.components {
+            let dc = if scan.ss == 0 && scan.ah == 0 {
+                match dc_tables[td] {
+                    Some(ref table) => Some(table),
+                    None => return Err(ImageError::malformed(ImageFormat::JPEG,
+                        "JPEG: Missing huffman table".to_string())),
+                }
+            } else {
+                None
+            };
+            let ac = if scan.se > 0 {
+                match ac_tables[ta] {
+                    Some(ref table) => Some(table),
+                    None => return Err(ImageError::malformed(ImageFormat::JPEG,
+                        "JPEG: Missing huffman table".to_string())),
+                }
+            } else {
+                None
+            };
+            decoders.push((c, dc, ac));
+        }
+
+        // The positions of the blocks of each MCU, non-interleaved scans have MCUs of one block
+        let mut positions = Vec::new();
+        if scan.components.len() == 1 {
+            let c = scan.components[0].0;
+            let (width, height) = self.blocks(c);
+            for by in 0..height {
+                for bx in 0..width {
+                    positions.push(vec![(0, bx, by)]);
+                }
+            }
+        } else {
+            let (mcux, mcuy) = self.mcus();
+            for my in 0..mcuy {
+                for mx in 0..mcux {
+                    let mut mcu = Vec::new();
+                    for (i, &(c, _, _)) in scan.components.iter().enumerate() {
+                        let (h, v) = (self.components[c].h as usize, self.components[c].v as usize);
+                        for y in 0..v {
+                            for x in 0..h {
+                                mcu.push((i, mx * h + x, my * v + y));
+                            }
+                        }
+                    }
+                    positions.push(mcu);
+                }
+            }
+        }
+
+        let mut state = ScanState {
+            ss: scan.ss,
+            se: scan.se,
+            ah: scan.ah,
+            al: scan.al,
+            progressive: self.progressive,
+            prevdc: vec![0; scan.components.len()],
+            eobrun: 0,
+        };
+
+        for (n, mcu) in positions.iter().enumerate() {
+            if restart_interval > 0 && n > 0 && n % restart_interval == 0 {
+                reader.restart();
+                state.reset();
+            }
+
+            let mut result = Ok(());
+            for &(i, bx, by) in mcu {
+                let (c, dc, ac) = decoders[i];
+                let block = self.block_mut(c, bx, by);
+                result = state.decode_block(block, i, dc, ac, reader);
+                if result.is_ok() && reader.exhausted {
+                    result = Err(ImageError::truncated(ImageFormat::JPEG));
+                }
+                if result.is_err() {
+                    break
+                }
+            }
+
+            if let Err(err) = result {
+                // The band of the blocks left, including the damaged MCU, stays empty, refinements
+                // of earlier scans are kept as they are
+                if scan.ah == 0 {
+                    for mcu in &positions[n..] {
+                        for &(i, bx, by) in mcu {
+                            let block = self.block_mut(scan.components[i].0, bx, by);
+                            for k in scan.ss as usize..scan.se as usize + 1 {
+                                block[UNZIGZAG[k] as usize] = 0;
+                            }
+                        }
+                    }
+                }
+                return Err(err)
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Transpose the image if ```transpose``` is set, then mirror it horizontally and vertically
+    /// as requested. Partial MCUs that would end up on the opposite edge are dropped.
+    fn rearrange(&self, transpose: bool, flip_x: bool, flip_y: bool) -> ImageResult<Frame> {
+        let (hmax, vmax) = self.max_sampling();
+
+        // The dimensions along the axes of the result before flipping
+        let (mut width, mut height) = (self.width, self.height);
+        let (mut mcu_width, mut mcu_height) = (8 * hmax, 8 * vmax);
+        if transpose {
+            ::std::mem::swap(&mut width, &mut height);
+            ::std::mem::swap(&mut mcu_width, &mut mcu_height);
+        }
+
+        if flip_x {
+            width -= width % mcu_width;
+        }
+        if flip_y {
+            height -= height % mcu_height;
+        }
+        if width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+
+        let mut components = self.components.clone();
+        let mut tables = self.tables.clone();
+        if transpose {
+            for comp in &mut components {
+                ::std::mem::swap(&mut comp.h, &mut comp.v);
+            }
+            for table in tables.chunks_mut(64) {
+                let mut transposed = [0u8; 64];
+                for i in 0..64 {
+                    transposed[i] = table[(i % 8) * 8 + i / 8];
+                }
+                table.copy_from_slice(&transposed);
+            }
+        }
+
+        let mut result = Frame::new(width, height, self.progressive, components, tables, self.segments.clone());
+        let (mcux, mcuy) = result.mcus();
+
+        for c in 0..result.components.len() {
+            let (h, v) = (result.components[c].h as usize, result.components[c].v as usize);
+            let (columns, rows) = result.blocks(c);
+
+            for by in 0..mcuy * v {
+                for bx in 0..mcux * h {
+                    // Blocks padding the MCUs are left empty
+                    if bx >= columns || by >= rows {
+                        continue
+                    }
+
+                    let x = if flip_x { columns - 1 - bx } else { bx };
+                    let y = if flip_y { rows - 1 - by } else { by };
+                    let (x, y) = if transpose { (y, x) } else { (x, y) };
+
+                    let source = self.block(c, x, y);
+                    let target = result.block_mut(c, bx, by);
+                    for (i, coefficient) in target.iter_mut().enumerate() {
+                        let (u, v) = (i % 8, i / 8);
+                        let value = if transpose { source[u * 8 + v] } else { source[i] };
+                        let negate = (flip_x && u % 2 == 1) != (flip_y && v % 2 == 1);
+                        *coefficient = if negate { -value } else { value };
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> ImageResult<Frame> {
+        let (hmax, vmax) = self.max_sampling();
+        if x % (8 * hmax) != 0 || y % (8 * vmax) != 0 {
+            return Err(ImageError::malformed(ImageFormat::JPEG, format!(
+                "JPEG: The crop offset has to be a multiple of the MCU size {}x{}", 8 * hmax, 8 * vmax)))
+        }
+        if x >= self.width || y >= self.height || width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+
+        let width = cmp::min(width, self.width - x);
+        let height = cmp::min(height, self.height - y);
+
+        let mut result = Frame::new(width, height, self.progressive, self.components.clone(),
+                                    self.tables.clone(), self.segments.clone());
+        let (mcux, mcuy) = result.mcus();
+
+        for c in 0..result.components.len() {
+            let (h, v) = (result.components[c].h as usize, result.components[c].v as usize);
+            let (ox, oy) = (x / (8 * hmax) * h, y / (8 * vmax) * v);
+
+            for by in 0..mcuy * v {
+                for bx in 0..mcux * h {
+                    let source = self.block(c, ox + bx, oy + by);
+                    result.block_mut(c, bx, by).copy_from_slice(&source);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> ImageResult<()> {
+        for comp in &self.components {
+            if self.tables[comp.tq as usize * 64..][..64].iter().any(|&q| q == 0) {
+                return Err(ImageError::malformed(ImageFormat::JPEG,
+                    "JPEG: Missing quantization table".to_string()))
+            }
+        }
+
+        let mut writer = BitWriter::new(w);
+        try!(writer.write_segment(SOI, None));
+        for &(marker, ref data) in &self.segments {
+            try!(writer.write_segment(marker, Some(data)));
+        }
+
+        try!(encoder::write_frame(&mut writer, self, &self.tables, self.progressive, true));
+
+        try!(writer.write_segment(EOI, None));
+        Ok(())
+    }
+}
+
+/// The tables and segments collected while reading an image
+struct Parser {
+    frame: Option<Frame>,
+    tables: Vec<u8>,
+    dc_tables: Vec<Option<HuffmanDecoder>>,
+    ac_tables: Vec<Option<HuffmanDecoder>>,
+    restart_interval: usize,
+    segments: Vec<(u8, Vec<u8>)>,
+}
+
+impl Parser {
+    /// Read the segment at ```pos``` along with the entropy coded data following it.
+    ///
+    /// Returns false once the end of the image is reached.
+    fn segment(&mut self, data: &[u8], pos: &mut usize) -> ImageResult<bool> {
+        // Markers may be preceded by any number of fill bytes
+        while *pos < data.len() && data[*pos] == 0xFF {
+            *pos += 1;
+        }
+        if *pos >= data.len() {
+            return Err(ImageError::truncated(ImageFormat::JPEG))
+        }
+        if data[*pos - 1] != 0xFF {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Expected a marker".to_string()))
+        }
+
+        let marker = data[*pos];
+        *pos += 1;
+
+        if marker == EOI {
+            return Ok(false)
+        }
+
+        if *pos + 2 > data.len() {
+            return Err(ImageError::truncated(ImageFormat::JPEG))
+        }
+        let length = BigEndian::read_u16(&data[*pos..]) as usize;
+        if length < 2 || *pos + length > data.len() {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid segment length".to_string()))
+        }
+        let segment = &data[*pos + 2..*pos + length];
+        *pos += length;
+
+        match marker {
+            m if m == SOF0 || m == SOF1 || m == SOF2 => {
+                if self.frame.is_some() {
+                    return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Multiple frames".to_string()))
+                }
+                self.frame = Some(try!(read_frame_header(segment, m == SOF2)));
+            }
+            0xC3 | 0xC5 ... 0xC7 | 0xC9 ... 0xCB | 0xCD ... 0xCF => {
+                return Err(ImageError::unsupported(ImageFormat::JPEG,
+                    "JPEG: Only baseline and progressive huffman coded images can be transformed".to_string()))
+            }
+            m if m == DQT => try!(read_quantization_tables(segment, &mut self.tables)),
+            m if m == DHT => try!(read_huffman_tables(segment, &mut self.dc_tables, &mut self.ac_tables)),
+            m if m == DRI => {
+                if segment.len() < 2 {
+                    return Err(ImageError::malformed(ImageFormat::JPEG,
+                        "JPEG: Invalid DRI segment".to_string()))
+                }
+                self.restart_interval = BigEndian::read_u16(segment) as usize;
+            }
+            m if m == SOS => {
+                let frame = match self.frame {
+                    Some(ref mut frame) => frame,
+                    None => return Err(ImageError::malformed(ImageFormat::JPEG,
+                        "JPEG: Scan before frame header".to_string())),
+                };
+                let scan = try!(read_scan_header(segment, &frame.components));
+                let mut reader = BitReader::new(&data[*pos..]);
+                try!(frame.decode_scan(&scan, &self.dc_tables, &self.ac_tables, self.restart_interval, &mut reader));
+                *pos += reader.end();
+            }
+            0xE0 ... 0xEF => self.segments.push((marker, segment.to_vec())),
+            m if m == COM => self.segments.push((marker, segment.to_vec())),
+            _ => (),
+        }
+
+        Ok(true)
+    }
+}
+
+fn read_frame_header(segment: &[u8], progressive: bool) -> ImageResult<Frame> {
+    if segment.len() < 6 {
+        return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid frame header".to_string()))
+    }
+    if segment[0] != 8 {
+        return Err(ImageError::unsupported(ImageFormat::JPEG, format!(
+            "JPEG: Images with {} bit samples can not be transformed", segment[0])))
+    }
+
+    let height = BigEndian::read_u16(&segment[1..]) as usize;
+    let width = BigEndian::read_u16(&segment[3..]) as usize;
+    if width == 0 || height == 0 {
+        return Err(ImageError::malformed(ImageFormat::JPEG, "Invalid image dimensions".to_string()))
+    }
+
+    let count = segment[5] as usize;
+    if count == 0 || count > 4 || segment.len() < 6 + 3 * count {
+        return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid frame header".to_string()))
+    }
+
+    let mut components = Vec::new();
+    for (i, spec) in segment[6..6 + 3 * count].chunks(3).enumerate() {
+        let (h, v, tq) = (spec[1] >> 4, spec[1] & 0x0F, spec[2]);
+        if h == 0 || h > 4 || v == 0 || v > 4 || tq > 3 {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid frame header".to_string()))
+        }
+
+        // The scans name their own huffman tables, these are the ones the result is written with
+        let destination = if i == 0 {0} else {1};
+        components.push(Component {
+            id: spec[0],
+            h: h,
+            v: v,
+            tq: tq,
+            dc_table: destination,
+            ac_table: destination,
+        });
+    }
+
+    Ok(Frame::new(width, height, progressive, components, Vec::new(), Vec::new()))
+}
+
+fn read_quantization_tables(mut segment: &[u8], tables: &mut [u8]) -> ImageResult<()> {
+    while !segment.is_empty() {
+        let (pq, tq) = ((segment[0] >> 4) as usize, (segment[0] & 0x0F) as usize);
+        if pq != 0 {
+            return Err(ImageError::unsupported(ImageFormat::JPEG,
+                "JPEG: Images with 16 bit quantization tables can not be transformed".to_string()))
+        }
+        if tq > 3 || segment.len() < 65 {
+            return Err(ImageError::malformed(ImageFormat::JPEG,
+                "JPEG: Invalid quantization table".to_string()))
+        }
+
+        for i in 0..64 {
+            tables[tq * 64 + UNZIGZAG[i] as usize] = segment[1 + i];
+        }
+        segment = &segment[65..];
+    }
+
+    Ok(())
+}
+
+fn read_huffman_tables(mut segment: &[u8],
+                       dc_tables: &mut [Option<HuffmanDecoder>],
+                       ac_tables: &mut [Option<HuffmanDecoder>]) -> ImageResult<()> {
+
+    while !segment.is_empty() {
+        let (tc, th) = (segment[0] >> 4, (segment[0] & 0x0F) as usize);
+        if tc > 1 || th > 3 || segment.len() < 17 {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid huffman table".to_string()))
+        }
+
+        let count = segment[1..17].iter().map(|&n| n as usize).sum::<usize>();
+        if count > 256 || segment.len() < 17 + count {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid huffman table".to_string()))
+        }
+
+        let table = HuffmanDecoder::new(&segment[1..17], &segment[17..17 + count]);
+        if tc == 0 {
+            dc_tables[th] = Some(table);
+        } else {
+            ac_tables[th] = Some(table);
+        }
+        segment = &segment[17 + count..];
+    }
+
+    Ok(())
+}
+
+/// The parameters of a scan
+struct ScanHeader {
+    /// The index of each component in the frame and its dc and ac huffman tables
+    components: Vec<(usize, usize, usize)>,
+
+    /// Spectral selection start and end
+    ss: u8,
+    se: u8,
+
+    /// Successive approximation bit positions high and low
+    ah: u8,
+    al: u8,
+}
+
+fn read_scan_header(segment: &[u8], frame_components: &[Component]) -> ImageResult<ScanHeader> {
+    let invalid = || ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid scan header".to_string());
+
+    if segment.is_empty() {
+        return Err(invalid())
+    }
+    let count = segment[0] as usize;
+    if count == 0 || count > 4 || segment.len() < 4 + 2 * count {
+        return Err(invalid())
+    }
+
+    let mut components = Vec::new();
+    for spec in segment[1..1 + 2 * count].chunks(2) {
+        let c = match frame_components.iter().position(|c| c.id == spec[0]) {
+            Some(c) => c,
+            None => return Err(invalid()),
+        };
+        let (td, ta) = ((spec[1] >> 4) as usize, (spec[1] & 0x0F) as usize);
+        if td > 3 || ta > 3 {
+            return Err(invalid())
+        }
+        components.push((c, td, ta));
+    }
+
+    let rest = &segment[1 + 2 * count..];
+    let (ss, se, ah, al) = (rest[0], rest[1], rest[2] >> 4, rest[2] & 0x0F);
+    if ss > se || se > 63 || al > 13 {
+        return Err(invalid())
+    }
+
+    Ok(ScanHeader {
+        components: components,
+        ss: ss,
+        se: se,
+        ah: ah,
+        al: al,
+    })
+}
+
+/// Decodes huffman codes as described in section F.2.2.3
+#[derive(Clone)]
+struct HuffmanDecoder {
+    /// The largest code of each length, -1 if there are none
+    maxcode: [i32; 17],
+
+    /// The smallest code of each length
+    mincode: [i32; 17],
+
+    /// The index of the first value of each code length
+    valptr: [usize; 17],
+
+    values: Vec<u8>,
+}
+
+impl HuffmanDecoder {
+    fn new(bits: &[u8], values: &[u8]) -> HuffmanDecoder {
+        let mut decoder = HuffmanDecoder {
+            maxcode: [-1; 17],
+            mincode: [0; 17],
+            valptr: [0; 17],
+            values: values.to_vec(),
+        };
+
+        // Figure F.15
+        let mut code = 0i32;
+        let mut k = 0;
+        for length in 1..17 {
+            let count = bits[length - 1] as usize;
+            decoder.valptr[length] = k;
+            decoder.mincode[length] = code;
+            code += count as i32;
+            k += count;
+            if count > 0 {
+                decoder.maxcode[length] = code - 1;
+            }
+            code <<= 1;
+        }
+
+        decoder
+    }
+
+    /// Figure F.16
+    fn decode(&self, reader: &mut BitReader) -> ImageResult<u8> {
+        let mut code = 0i32;
+        for length in 1..17 {
+            code = (code << 1) | reader.read_bits(1) as i32;
+            if code <= self.maxcode[length] {
+                let index = self.valptr[length] + (code - self.mincode[length]) as usize;
+                return match self.values.get(index) {
+                    Some(&value) => Ok(value),
+                    None => Err(ImageError::malformed(ImageFormat::JPEG,
+                        "JPEG: Invalid huffman code".to_string())),
+                }
+            }
+        }
+
+        Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid huffman code".to_string()))
+    }
+}
+
+/// Reads the bits of entropy coded data, removing the stuffed zero bytes
+struct BitReader<'d> {
+    data: &'d [u8],
+    pos: usize,
+    accumulator: u32,
+    nbits: u8,
+
+    /// Whether bits beyond the entropy coded data were read
+    exhausted: bool,
+}
+
+impl<'d> BitReader<'d> {
+    fn new(data: &'d [u8]) -> BitReader<'d> {
+        BitReader {
+            data: data,
+            pos: 0,
+            accumulator: 0,
+            nbits: 0,
+            exhausted: false,
+        }
+    }
+
+    /// The next byte of data, zero once a marker is reached
+    fn next_byte(&mut self) -> u32 {
+        match self.data.get(self.pos) {
+            Some(&0xFF) => {
+                if self.data.get(self.pos + 1) == Some(&0x00) {
+                    self.pos += 2;
+                    0xFF
+                } else {
+                    self.exhausted = true;
+                    0
+                }
+            }
+            Some(&byte) => {
+                self.pos += 1;
+                byte as u32
+            }
+            None => {
+                self.exhausted = true;
+                0
+            }
+        }
+    }
+
+    fn read_bits(&mut self, count: u8) -> u32 {
+        if count == 0 {
+            return 0
+        }
+
+        while self.nbits < count {
+            self.accumulator = (self.accumulator << 8) | self.next_byte();
+            self.nbits += 8;
+        }
+
+        self.nbits -= count;
+        (self.accumulator >> self.nbits) & ((1 << count) - 1)
+    }
+
+    /// Read a coefficient of ```size``` bits, section F.2.2.1
+    fn receive_extend(&mut self, size: u8) -> ImageResult<i32> {
+        if size > 15 {
+            return Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Invalid coefficient size".to_string()))
+        }
+
+        let value = self.read_bits(size) as i32;
+        if size > 0 && value < 1 << (size - 1) {
+            Ok(value - (1 << size) + 1)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Skip the remaining bits of the current byte and the restart marker following them
+    fn restart(&mut self) {
+        self.accumulator = 0;
+        self.nbits = 0;
+
+        if self.data.get(self.pos) == Some(&0xFF) {
+            if let Some(&(0xD0 ... 0xD7)) = self.data.get(self.pos + 1) {
+                self.pos += 2;
+            }
+        }
+    }
+
+    /// The position of the marker ending the entropy coded data
+    fn end(&self) -> usize {
+        let mut pos = self.pos;
+        while pos + 1 < self.data.len() {
+            match (self.data[pos], self.data[pos + 1]) {
+                (0xFF, 0x00) | (0xFF, 0xD0 ... 0xD7) => pos += 2,
+                (0xFF, _) => return pos,
+                _ => pos += 1,
+            }
+        }
+        self.data.len()
+    }
+}
+
+/// The state kept while decoding the blocks of a scan
+struct ScanState {
+    ss: u8,
+    se: u8,
+    ah: u8,
+    al: u8,
+    progressive: bool,
+
+    /// The dc prediction of each component of the scan
+    prevdc: Vec<i32>,
+
+    /// The number of remaining blocks without coefficients in the band
+    eobrun: usize,
+}
+
+impl ScanState {
+    /// Reset the predictions after a restart marker
+    fn reset(&mut self) {
+        for dc in &mut self.prevdc {
+            *dc = 0;
+        }
+        self.eobrun = 0;
+    }
+
+    /// Decode the coefficients of the block of the ```i```th component of the scan
+    fn decode_block(&mut self,
+                    block: &mut [i32],
+                    i: usize,
+                    dc: Option<&HuffmanDecoder>,
+                    ac: Option<&HuffmanDecoder>,
+                    reader: &mut BitReader) -> ImageResult<()> {
+
+        match (dc, ac) {
+            (Some(dc), Some(ac)) if !self.progressive => {
+                try!(self.decode_dc_first(block, i, dc, reader));
+                self.decode_ac_first(block, ac, reader)
+            }
+            (Some(dc), _) if self.ss == 0 => self.decode_dc_first(block, i, dc, reader),
+            (None, _) if self.ss == 0 => {
+                if reader.read_bits(1) == 1 {
+                    block[0] |= 1 << self.al;
+                }
+                Ok(())
+            }
+            (_, Some(ac)) if self.ah == 0 => self.decode_ac_first(block, ac, reader),
+            (_, Some(ac)) => self.decode_ac_refine(block, ac, reader),
+            _ => Err(ImageError::malformed(ImageFormat::JPEG, "JPEG: Missing huffman table".to_string())),
+        }
+    }
+
+    fn decode_dc_first(&mut self, block: &mut [i32], i: usize, table: &HuffmanDecoder, reader: &mut BitReader)
+        -> ImageResult<()> {
+
+        let size = try!(table.decode(reader));
+        let diff = try!(reader.receive_extend(size));
+        self.prevdc[i] += diff;
+        block[0] = self.prevdc[i] * (1 << self.al);
+        Ok(())
+    }
+
+    /// Figure F.13, extended by the end of band runs of section G.1.2.2
+    fn decode_ac_first(&mut self, block: &mut [i32], table: &HuffmanDecoder, reader: &mut BitReader)
+        -> ImageResult<()> {
+
+        if self.eobrun > 0 {
+            self.eobrun -= 1;
+            return Ok(())
+        }
+
+        let mut k = cmp::max(self.ss, 1) as usize;
+        while k <= self.se as usize {
+            let rs = try!(table.decode(reader));
+            let (run, size) = ((rs >> 4) as usize, rs & 0x0F);
+
+            if size == 0 {
+                if run < 15 {
+                    self.eobrun = (1 << run) - 1 + reader.read_bits(run as u8) as usize;
+                    break
+                }
+                k += 16;
+                continue
+            }
+
+            k += run;
+            if k > 63 {
+                return Err(ImageError::malformed(ImageFormat::JPEG,
+                    "JPEG: Too many coefficients".to_string()))
+            }
+            block[UNZIGZAG[k] as usize] = try!(reader.receive_extend(size)) * (1 << self.al);
+            k += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Section G.1.2.3
+    fn decode_ac_refine(&mut self, block: &mut [i32], table: &HuffmanDecoder, reader: &mut BitReader)
+        -> ImageResult<()> {
+
+        let se = self.se as usize;
+        let positive = 1 << self.al;
+        let negative = -1 << self.al;
+
+        let mut k = self.ss as usize;
+        if self.eobrun == 0 {
+            while k <= se {
+                let rs = try!(table.decode(reader));
+                let (mut run, size) = ((rs >> 4) as i32, rs & 0x0F);
+
+                let mut value = 0;
+                if size != 0 {
+                    value = if reader.read_bits(1) == 1 { positive } else { negative };
+                } else if run != 15 {
+                    self.eobrun = (1 << run) + reader.read_bits(run as u8) as usize;
+                    break
+                }
+
+                // Skip ```run``` zero coefficients, refining the nonzero ones passed on the way
+                while k <= se {
+                    let coefficient = &mut block[UNZIGZAG[k] as usize];
+                    if *coefficient != 0 {
+                        refine(coefficient, positive, negative, reader);
+                    } else {
+                        run -= 1;
+                        if run < 0 {
+                            break
+                        }
+                    }
+                    k += 1;
+                }
+
+                if value != 0 {
+                    if k > 63 {
+                        return Err(ImageError::malformed(ImageFormat::JPEG,
+                            "JPEG: Too many coefficients".to_string()))
+                    }
+                    block[UNZIGZAG[k] as usize] = value;
+                }
+                k += 1;
+            }
+        }
+
+        if self.eobrun > 0 {
+            // The rest of the band only refines the nonzero coefficients
+            while k <= se {
+                let coefficient = &mut block[UNZIGZAG[k] as usize];
+                if *coefficient != 0 {
+                    refine(coefficient, positive, negative, reader);
+                }
+                k += 1;
+            }
+            self.eobrun -= 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Add the next correction bit to a nonzero coefficient
+fn refine(coefficient: &mut i32, positive: i32, negative: i32, reader: &mut BitReader) {
+    if reader.read_bits(1) == 1 && *coefficient & positive == 0 {
+        *coefficient += if *coefficient >= 0 { positive } else { negative };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError};
+    use super::super::{ChromaSubsampling, JPEGDecoder, JPEGEncoder, JPEGEncoderOptions};
+
+    fn decode(data: &[u8]) -> (u32, u32, Vec<u8>) {
+        let mut decoder = JPEGDecoder::new(Cursor::new(data));
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(pixels) => (width, height, pixels),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    fn encoded(width: u32, height: u32, subsampling: ChromaSubsampling) -> Vec<u8> {
+        let mut img = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                img.extend_from_slice(&[(x * 5) as u8, (y * 7) as u8, ((x * y) % 256) as u8]);
+            }
+        }
+
+        let mut data = Vec::new();
+        let options = JPEGEncoderOptions { subsampling: subsampling, ..JPEGEncoderOptions::default() };
+        JPEGEncoder::new_with_options(&mut data, options).encode(&img, width, height, ColorType::RGB(8)).unwrap();
+        data
+    }
+
+    fn read(path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        File::open(path).unwrap().read_to_end(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn identities() {
+        let data = encoded(48, 32, ChromaSubsampling::Yuv420);
+
+        let mut flipped = Vec::new();
+        super::flip_horizontal(Cursor::new(&data), &mut flipped).unwrap();
+        let mut restored = Vec::new();
+        super::flip_horizontal(Cursor::new(&flipped), &mut restored).unwrap();
+        assert_eq!(decode(&restored), decode(&data));
+
+        // Rotating swaps the sampling factors of 4:2:2 images
+        let data = encoded(48, 32, ChromaSubsampling::Yuv422);
+        let mut rotated = data.clone();
+        for _ in 0..4 {
+            let mut next = Vec::new();
+            super::rotate90(Cursor::new(&rotated), &mut next).unwrap();
+            rotated = next;
+        }
+        assert_eq!(decode(&rotated), decode(&data));
+    }
+
+    #[test]
+    fn rotate() {
+        let data = encoded(40, 24, ChromaSubsampling::Yuv444);
+        let (_, _, original) = decode(&data);
+        let pixel = |x: usize, y: usize| &original[(y * 40 + x) * 3..][..3];
+
+        let mut rotated = Vec::new();
+        super::rotate90(Cursor::new(&data), &mut rotated).unwrap();
+        let (width, height, pixels) = decode(&rotated);
+        assert_eq!((width, height), (24, 40));
+
+        for y in 0..40 {
+            for x in 0..24 {
+                let expected = pixel(y, 23 - x);
+                let actual = &pixels[(y * 24 + x) * 3..][..3];
+                for (&a, &b) in expected.iter().zip(actual) {
+                    assert!((a as i32 - b as i32).abs() <= 2, "{:?} {:?} at {} {}", expected, actual, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn trim_partial_mcus() {
+        // 4:2:0 images consist of 16x16 MCUs
+        let data = encoded(40, 30, ChromaSubsampling::Yuv420);
+
+        let mut rotated = Vec::new();
+        super::rotate180(Cursor::new(&data), &mut rotated).unwrap();
+        assert_eq!(decode(&rotated).0, 32);
+        assert_eq!(decode(&rotated).1, 16);
+
+        let mut rotated = Vec::new();
+        super::rotate270(Cursor::new(&data), &mut rotated).unwrap();
+        assert_eq!((decode(&rotated).0, decode(&rotated).1), (30, 32));
+    }
+
+    #[test]
+    fn crop() {
+        let data = encoded(48, 48, ChromaSubsampling::Yuv420);
+        let (_, _, original) = decode(&data);
+
+        let mut cropped = Vec::new();
+        super::crop(Cursor::new(&data), &mut cropped, 16, 32, 20, 100).unwrap();
+        let (width, height, pixels) = decode(&cropped);
+        assert_eq!((width, height), (20, 16));
+
+        // Away from the edges the upsampling of the chroma sees the same neighbours
+        for y in 1..15 {
+            for x in 1..15 {
+                let expected = &original[((y + 32) * 48 + x + 16) * 3..][..3];
+                assert_eq!(&pixels[(y * 20 + x) * 3..][..3], expected);
+            }
+        }
+
+        match super::crop(Cursor::new(&data), &mut Vec::new(), 8, 0, 8, 8) {
+            Err(ImageError::Decoding { .. }) => (),
+            _ => panic!("Unaligned crop offset was accepted"),
+        }
+    }
+
+    #[test]
+    fn progressive() {
+        for name in &["3.jpg", "cat.jpg", "test.jpg"] {
+            let data = read(&format!("tests/images/jpg/progressive/{}", name));
+            let (width, height, original) = decode(&data);
+
+            // Cropping to the whole image keeps every coefficient
+            let mut copy = Vec::new();
+            super::crop(Cursor::new(&data), &mut copy, 0, 0, width, height).unwrap();
+            assert!(copy.windows(2).any(|w| w == [0xFF, 0xC2]));
+            assert_eq!(decode(&copy), (width, height, original));
+        }
+    }
+
+    #[test]
+    fn repair() {
+        let data = read("tests/images/jpg/progressive/cat.jpg");
+        let (width, height, _) = decode(&data);
+
+        let mut copy = Vec::new();
+        assert!(super::repair(Cursor::new(&data), &mut copy).unwrap().is_none());
+
+        // The scans after the truncation are missing, the ones before are kept
+        let mut repaired = Vec::new();
+        let damage = super::repair(Cursor::new(&data[..data.len() * 2 / 3]), &mut repaired).unwrap();
+        assert!(damage.is_some());
+        let (w, h, _) = decode(&repaired);
+        assert_eq!((w, h), (width, height));
+
+        assert!(super::repair(Cursor::new(&data[..100]), &mut Vec::new()).is_err());
     }
 }
@@ -24,23 +24,41 @@ pub use color::ColorType::{
     RGB,
     Palette,
     GrayA,
-    RGBA
+    RGBA,
+    GrayF,
+    GrayAF,
+    RGBF,
+    RGBAF,
+    BGR,
+    BGRA
 };
 
 pub use color::{
     Luma,
     LumaA,
     Rgb,
-    Rgba
+    Rgba,
+    Bgr,
+    Bgra,
+    Premultiply
 };
 
 pub use image::{
     ImageDecoder,
+    ImageEncoder,
     ImageError,
+    ImageFormatHint,
+    DecodingErrorKind,
+    UnsupportedFeature,
+    LimitErrorKind,
+    ParameterErrorKind,
     ImageResult,
     DecodingResult,
+    Limits,
+    Metadata,
     SubImage,
     GenericImage,
+    GenericImageView,
     // Iterators
     Pixels,
     MutPixels
@@ -61,11 +79,14 @@ pub use image::ImageFormat::{
     JPEG,
     GIF,
     WEBP,
-    PPM,
+    PNM,
     BMP,
     ICO
 };
 
+#[allow(deprecated)]
+pub use image::ImageFormat::PPM;
+
 pub use buffer::{
     Pixel,
     ConvertBuffer,
@@ -74,7 +95,13 @@ pub use buffer::{
     RgbImage,
     RgbaImage,
     GrayImage,
-    GrayAlphaImage
+    GrayAlphaImage,
+    Rgb16Image,
+    Rgba16Image,
+    Gray16Image,
+    GrayAlpha16Image,
+    Rgb32FImage,
+    Rgba32FImage
 };
 
 // Traits
@@ -83,26 +110,76 @@ pub use traits::Primitive;
 // Opening and loading images
 pub use dynimage::{
     open,
+    open_with_orientation,
+    open_with_limits,
+    image_dimensions,
     load,
+    load_with_limits,
+    load_with_progress,
+    load_with_profile,
     load_from_memory,
     load_from_memory_with_format,
+    load_animation,
+    save_animation,
+    transcode_animation,
     guess_format,
     save_buffer
 };
 
+pub use dynimage::EncoderOptions;
+
 pub use dynimage::DynamicImage::{
     self,
     ImageRgb8,
     ImageRgba8,
     ImageLuma8,
-    ImageLumaA8
+    ImageLumaA8,
+    ImageRgb16,
+    ImageRgba16,
+    ImageLuma16,
+    ImageLumaA16,
+    ImageRgb32F,
+    ImageRgba32F
+};
+
+pub use push::{
+    PushDecoder,
+    Progress
+};
+
+pub use monitor::{
+    CancelToken,
+    Monitor
 };
 
 pub use animation::{
+    Blend,
+    Delay,
+    Disposal,
     Frame,
-    Frames
+    Frames,
+    LoopCount
 };
 
+// Reading images from files and streams
+pub mod io;
+
+// Codecs of formats implemented by other crates
+pub mod registry;
+
+// Metadata shared by the codecs
+pub mod metadata;
+
+// Color types and color management
+pub mod color;
+
+// Describing the layout of samples in flat buffers
+pub mod flat;
+
+// Reading images from asynchronous streams
+#[cfg(feature = "async")]
+pub mod async_io;
+
 // Math utils
 pub mod math;
 
@@ -112,8 +189,16 @@ pub mod imageops;
 // Image codecs
 #[cfg(feature = "webp")]
 pub mod webp;
-#[cfg(feature = "ppm")]
-pub mod ppm;
+#[cfg(feature = "pnm")]
+pub mod pnm;
+/// The portable pixmap decoder and encoder under their former names, `pnm` reads and writes all
+/// netpbm formats
+#[cfg(feature = "pnm")]
+#[deprecated(note = "use the pnm module instead")]
+pub mod ppm {
+    pub use pnm::PNMDecoder as PPMDecoder;
+    pub use pnm::PNMEncoder as PPMEncoder;
+}
 #[cfg(feature = "png_codec")]
 pub mod png;
 #[cfg(feature = "ico")]
@@ -130,14 +215,39 @@ pub mod tga;
 pub mod bmp;
 #[cfg(feature = "hdr")]
 pub mod hdr;
+#[cfg(feature = "exr")]
+pub mod exr;
+#[cfg(feature = "qoi")]
+pub mod qoi;
+#[cfg(feature = "farbfeld")]
+pub mod farbfeld;
+#[cfg(feature = "dxt")]
+pub mod dxt;
+#[cfg(feature = "dds")]
+pub mod dds;
+#[cfg(feature = "avif")]
+pub mod avif;
+#[cfg(feature = "heif")]
+pub mod heif;
+#[cfg(feature = "sgi")]
+pub mod sgi;
+#[cfg(feature = "xbm")]
+pub mod xbm;
+#[cfg(feature = "xpm")]
+pub mod xpm;
+#[cfg(feature = "dng")]
+pub mod dng;
 
 mod image;
 mod utils;
 mod dynimage;
-mod color;
 mod buffer;
+#[cfg(feature = "rayon")]
+mod buffer_par;
 mod traits;
 mod animation;
+mod push;
+mod monitor;
 
 // Copies data from `src` to `dst`
 //
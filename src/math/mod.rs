@@ -1,3 +1,4 @@
 //! Mathematical helper functions and types.
 pub mod utils;
 pub mod nq;
+pub mod quantize;
@@ -137,6 +137,16 @@ impl NeuQuant {
         }
     }
 
+    /// Returns the colors of the color map as consecutive rgba samples, in the order of their
+    /// indices
+    pub fn color_map_rgba(&self) -> Vec<u8> {
+        let mut map = Vec::with_capacity(self.netsize * 4);
+        for entry in &self.colormap {
+            map.extend_from_slice(&[entry.r as u8, entry.g as u8, entry.b as u8, entry.a as u8]);
+        }
+        map
+    }
+
     /// Move neuron i towards biased (a,b,g,r) by factor alpha
     fn alter_single(&mut self, alpha: f64, i: i32, quad: Quad<f64>) {
         let n = &mut self.network[i as usize];
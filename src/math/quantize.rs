@@ -0,0 +1,303 @@
+//! Color quantization by the median cut and octree algorithms, and palettes mapping colors to
+//! their nearest entry.
+//!
+//! The algorithms take rgba pixels and only consider their color, the alpha channel is ignored.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Counts the occurences of each color of the rgba ```pixels```, in the order of their first
+/// appearance
+fn histogram(pixels: &[u8]) -> Vec<([u8; 3], u64)> {
+    let mut colors = Vec::new();
+    let mut indices = HashMap::new();
+    for pixel in pixels.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let next = colors.len();
+        let index = *indices.entry(color).or_insert(next);
+        if index == next {
+            colors.push((color, 0));
+        }
+        colors[index].1 += 1;
+    }
+    colors
+}
+
+/// The average of ```colors``` weighted by their counts
+fn average<'a, I: Iterator<Item = &'a ([u8; 3], u64)>>(colors: I) -> [u8; 3] {
+    let mut sum = [0u64; 3];
+    let mut count = 0;
+    for &(color, n) in colors {
+        for c in 0..3 {
+            sum[c] += color[c] as u64 * n;
+        }
+        count += n;
+    }
+    let count = count.max(1);
+    [((sum[0] + count / 2) / count) as u8,
+     ((sum[1] + count / 2) / count) as u8,
+     ((sum[2] + count / 2) / count) as u8]
+}
+
+/// Reduces the colors of the rgba ```pixels``` to at most ```colors``` by the median cut algorithm
+///
+/// The set of colors is repeatedly split in two at the median of the channel with the widest
+/// range, starting with the box spanning the widest range. Each box becomes the average of its
+/// colors.
+pub fn median_cut(pixels: &[u8], colors: usize) -> Vec<[u8; 3]> {
+    let histogram = histogram(pixels);
+    if histogram.len() <= colors {
+        return histogram.into_iter().map(|(color, _)| color).collect()
+    }
+
+    // The channel with the widest range of each box and its extent
+    let widest = |colors: &[([u8; 3], u64)]| -> (usize, u8) {
+        (0..3).map(|c| {
+            let min = colors.iter().map(|e| e.0[c]).min().unwrap();
+            let max = colors.iter().map(|e| e.0[c]).max().unwrap();
+            (c, max - min)
+        }).max_by_key(|&(_, range)| range).unwrap()
+    };
+
+    let mut boxes = vec![histogram];
+    while boxes.len() < colors {
+        let next = boxes.iter().enumerate()
+            .filter(|&(_, b)| b.len() > 1)
+            .max_by_key(|&(_, b)| widest(b).1)
+            .map(|(i, _)| i);
+        let mut split = match next {
+            Some(i) => boxes.swap_remove(i),
+            None => break,
+        };
+
+        let (channel, _) = widest(&split);
+        split.sort_by_key(|e| e.0[channel]);
+
+        // Split at the color reaching half of the pixels, keeping both halves non-empty
+        let total: u64 = split.iter().map(|e| e.1).sum();
+        let mut count = 0;
+        let mut median = 1;
+        for (i, e) in split.iter().enumerate() {
+            count += e.1;
+            if count * 2 >= total {
+                median = i + 1;
+                break
+            }
+        }
+        let median = median.max(1).min(split.len() - 1);
+        let upper = split.split_off(median);
+        boxes.push(split);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|b| average(b.iter())).collect()
+}
+
+/// A node of an octree, either a leaf holding the sum of its colors or an inner node
+struct Node {
+    children: [Option<usize>; 8],
+    leaf: bool,
+    sum: [u64; 3],
+    count: u64,
+}
+
+impl Node {
+    fn new(leaf: bool) -> Node {
+        Node {
+            children: [None; 8],
+            leaf: leaf,
+            sum: [0; 3],
+            count: 0,
+        }
+    }
+}
+
+/// Reduces the colors of the rgba ```pixels``` to at most ```colors``` by octree quantization
+///
+/// Colors are sorted into a tree whose levels split each channel by one more bit. The deepest
+/// nodes holding the fewest pixels are merged into their parents until few enough leaves are left.
+pub fn octree(pixels: &[u8], colors: usize) -> Vec<[u8; 3]> {
+    let histogram = histogram(pixels);
+    if histogram.len() <= colors {
+        return histogram.into_iter().map(|(color, _)| color).collect()
+    }
+
+    let mut nodes = vec![Node::new(false)];
+    // The inner nodes of each level, which can be merged into leaves
+    let mut levels: Vec<Vec<usize>> = vec![Vec::new(); 8];
+    levels[0].push(0);
+    let mut leaves = 0;
+
+    for &(color, count) in &histogram {
+        let mut node = 0;
+        for level in 0..8 {
+            let shift = 7 - level;
+            let child = (((color[0] >> shift) & 1) << 2 | ((color[1] >> shift) & 1) << 1 | ((color[2] >> shift) & 1)) as usize;
+            node = match nodes[node].children[child] {
+                Some(next) => next,
+                None => {
+                    let next = nodes.len();
+                    let leaf = level == 7;
+                    nodes.push(Node::new(leaf));
+                    nodes[node].children[child] = Some(next);
+                    if leaf {
+                        leaves += 1;
+                    } else {
+                        levels[level + 1].push(next);
+                    }
+                    next
+                }
+            };
+        }
+        for c in 0..3 {
+            nodes[node].sum[c] += color[c] as u64 * count;
+        }
+        nodes[node].count += count;
+    }
+
+    // The pixels below each node decide which ones are merged first
+    fn count(nodes: &mut [Node], node: usize) -> u64 {
+        if nodes[node].leaf {
+            return nodes[node].count
+        }
+        let children = nodes[node].children;
+        let total = children.iter().filter_map(|&c| c).map(|c| count(nodes, c)).sum();
+        nodes[node].count = total;
+        total
+    }
+    count(&mut nodes, 0);
+    for level in &mut levels {
+        level.sort_by_key(|&node| Reverse(nodes[node].count));
+    }
+
+    while leaves > colors {
+        let node = match levels.iter_mut().rev().filter_map(|level| level.pop()).next() {
+            Some(node) => node,
+            None => break,
+        };
+
+        let children = nodes[node].children;
+        let mut merged = 0;
+        for child in children.iter().filter_map(|&c| c) {
+            for c in 0..3 {
+                nodes[node].sum[c] += nodes[child].sum[c];
+            }
+            merged += 1;
+        }
+        nodes[node].children = [None; 8];
+        nodes[node].leaf = true;
+        leaves = leaves + 1 - merged;
+    }
+
+    let mut palette = Vec::new();
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let node = &nodes[node];
+        if node.leaf {
+            let count = node.count.max(1);
+            palette.push([((node.sum[0] + count / 2) / count) as u8,
+                          ((node.sum[1] + count / 2) / count) as u8,
+                          ((node.sum[2] + count / 2) / count) as u8]);
+        } else {
+            stack.extend(node.children.iter().filter_map(|&c| c));
+        }
+    }
+    palette
+}
+
+/// A palette of colors, mapping each color to its nearest entry
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    /// Creates a palette of the colors ```colors```
+    pub fn new(colors: Vec<[u8; 3]>) -> Palette {
+        Palette {
+            colors: colors,
+        }
+    }
+
+    /// Returns the colors of the palette
+    pub fn colors(&self) -> &[[u8; 3]] {
+        &self.colors
+    }
+
+    /// Finds the index of the entry nearest to the color of ```pixel```, by euclidean distance
+    pub fn index_of(&self, pixel: &[u8]) -> usize {
+        let distance = |color: &[u8; 3]| -> i32 {
+            (0..3).map(|c| {
+                let d = color[c] as i32 - pixel[c] as i32;
+                d * d
+            }).sum()
+        };
+        self.colors.iter().enumerate()
+            .min_by_key(|&(_, color)| distance(color))
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Replaces the color of ```pixel``` by its nearest entry, keeping any further channels
+    pub fn map_pixel(&self, pixel: &mut [u8]) {
+        if let Some(color) = self.colors.get(self.index_of(pixel)) {
+            pixel[..3].copy_from_slice(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{median_cut, octree, Palette};
+
+    fn gradient() -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for y in 0..32u32 {
+            for x in 0..32u32 {
+                pixels.extend_from_slice(&[(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8, 255]);
+            }
+        }
+        pixels
+    }
+
+    /// The mean distance of the pixels to their nearest palette entry per channel
+    fn error(pixels: &[u8], palette: &Palette) -> u32 {
+        let sum: u32 = pixels.chunks(4).map(|p| {
+            let color = palette.colors()[palette.index_of(p)];
+            (0..3).map(|c| (color[c] as i32 - p[c] as i32).abs() as u32).sum::<u32>()
+        }).sum();
+        sum / (pixels.len() as u32 / 4 * 3)
+    }
+
+    #[test]
+    fn few_colors_are_exact() {
+        let pixels = [1, 2, 3, 255, 4, 5, 6, 0, 1, 2, 3, 255];
+        for colors in &[median_cut(&pixels, 16), octree(&pixels, 16)] {
+            let mut colors = colors.clone();
+            colors.sort();
+            assert_eq!(colors, vec![[1, 2, 3], [4, 5, 6]]);
+        }
+    }
+
+    #[test]
+    fn reduce_colors() {
+        let pixels = gradient();
+        for &n in &[2, 16, 64] {
+            let cut = Palette::new(median_cut(&pixels, n));
+            let tree = Palette::new(octree(&pixels, n));
+            assert!(cut.colors().len() <= n && cut.colors().len() > n / 2);
+            assert!(tree.colors().len() <= n && !tree.colors().is_empty());
+            if n == 64 {
+                assert!(error(&pixels, &cut) < 12);
+                assert!(error(&pixels, &tree) < 16);
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_entry() {
+        let palette = Palette::new(vec![[0, 0, 0], [255, 255, 255], [255, 0, 0]]);
+        assert_eq!(palette.index_of(&[200, 30, 20]), 2);
+        let mut pixel = [100, 100, 100, 7];
+        palette.map_pixel(&mut pixel);
+        assert_eq!(pixel, [0, 0, 0, 7]);
+    }
+}
@@ -1,5 +1,9 @@
 //! Shared mathematical utility functions.
 
+use num_traits::NumCast;
+
+use traits::Primitive;
+
 /// Cut value to be inside given range
 ///
 /// ```
@@ -16,3 +20,36 @@ where N: PartialOrd {
     if a > max { return max }
     a
 }
+
+/// Scales the ```value``` in the range 0 to 1 to the nearest sample of the integer type ```T```,
+/// clamping values outside of the range
+///
+/// NaN compares false to every value and maps to zero.
+#[inline]
+pub(crate) fn unit_to_sample<T: Primitive>(value: f32) -> T {
+    let max = T::max_value().to_f32().unwrap();
+    let scaled = if value > 0.0 { value.min(1.0) * max + 0.5 } else { 0.0 };
+    NumCast::from(scaled.floor()).unwrap()
+}
+
+/// Scales the integer ```sample``` of the type ```T``` to the range 0 to 1
+#[inline]
+pub(crate) fn sample_to_unit<T: Primitive>(sample: T) -> f32 {
+    sample.to_f32().unwrap() / T::max_value().to_f32().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sample_to_unit, unit_to_sample};
+
+    #[test]
+    fn unit_samples() {
+        let values = [-1.0, 0.0, 0.001, 0.5, 0.999, 1.0, 2.0, ::std::f32::NAN];
+        let bytes: Vec<u8> = values.iter().map(|&v| unit_to_sample(v)).collect();
+        assert_eq!(bytes, [0, 0, 0, 128, 255, 255, 255, 0]);
+        let words: Vec<u16> = values.iter().map(|&v| unit_to_sample(v)).collect();
+        assert_eq!(words, [0, 0, 66, 32768, 65469, 65535, 65535, 0]);
+        assert_eq!(sample_to_unit(51u8), 0.2);
+        assert_eq!(sample_to_unit(65535u16), 1.0);
+    }
+}
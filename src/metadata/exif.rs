@@ -0,0 +1,589 @@
+//! Reading and writing EXIF data
+//!
+//! EXIF data is a TIFF structure of tagged fields. JPEG stores it in an APP1 segment, PNG in an
+//! eXIf chunk, WebP in an EXIF chunk and TIFF in the directory of the image itself. `Exif` holds
+//! the fields of the primary image and of the EXIF, GPS and interoperability directories below
+//! it, and serializes them again for encoders to embed, for example with `PNGEncoder::set_exif`.
+//! The thumbnail some cameras store in a second directory is not kept.
+
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use image::{DecodingErrorKind, ImageError, ImageFormatHint, ImageResult};
+
+/// The orientation of the image, a `Value::Short` from 1 to 8 in `Ifd::Primary`
+pub const ORIENTATION: u16 = 0x0112;
+/// The manufacturer of the camera, a `Value::Ascii` in `Ifd::Primary`
+pub const MAKE: u16 = 0x010F;
+/// The model of the camera, a `Value::Ascii` in `Ifd::Primary`
+pub const MODEL: u16 = 0x0110;
+/// The program that created the image, a `Value::Ascii` in `Ifd::Primary`
+pub const SOFTWARE: u16 = 0x0131;
+/// When the image was last changed, a `Value::Ascii` in `Ifd::Primary`
+pub const DATE_TIME: u16 = 0x0132;
+/// When the photo was taken, a `Value::Ascii` in `Ifd::Exif`
+pub const DATE_TIME_ORIGINAL: u16 = 0x9003;
+/// When the photo was stored digitally, a `Value::Ascii` in `Ifd::Exif`
+pub const DATE_TIME_DIGITIZED: u16 = 0x9004;
+/// Whether `GPS_LATITUDE` is north or south of the equator, `N` or `S` in `Ifd::Gps`
+pub const GPS_LATITUDE_REF: u16 = 0x0001;
+/// The degrees, minutes and seconds of latitude, a `Value::Rational` in `Ifd::Gps`
+pub const GPS_LATITUDE: u16 = 0x0002;
+/// Whether `GPS_LONGITUDE` is east or west of the prime meridian, `E` or `W` in `Ifd::Gps`
+pub const GPS_LONGITUDE_REF: u16 = 0x0003;
+/// The degrees, minutes and seconds of longitude, a `Value::Rational` in `Ifd::Gps`
+pub const GPS_LONGITUDE: u16 = 0x0004;
+/// Whether `GPS_ALTITUDE` is above sea level, 0, or below it, 1, a `Value::Byte` in `Ifd::Gps`
+pub const GPS_ALTITUDE_REF: u16 = 0x0005;
+/// The altitude in meters, a `Value::Rational` in `Ifd::Gps`
+pub const GPS_ALTITUDE: u16 = 0x0006;
+
+/// The fields pointing to the directories below `Ifd::Primary` and `Ifd::Exif`
+const EXIF_POINTER: u16 = 0x8769;
+const GPS_POINTER: u16 = 0x8825;
+const INTEROPERABILITY_POINTER: u16 = 0xA005;
+
+/// The identifier preceding EXIF data in JPEG segments and in some WebP chunks
+const PREFIX: &'static [u8] = b"Exif\0\0";
+
+/// A directory of EXIF fields
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ifd {
+    /// The fields of the image, like its orientation, resolution and the camera that took it
+    Primary,
+    /// The fields of the photo, like its exposure and when it was taken
+    Exif,
+    /// The position the photo was taken at
+    Gps,
+    /// How the fields are to be interpreted, below `Ifd::Exif`
+    Interoperability,
+}
+
+/// The directories in the order they are written, each after the one it is below
+const IFDS: [Ifd; 4] = [Ifd::Primary, Ifd::Exif, Ifd::Gps, Ifd::Interoperability];
+
+impl Ifd {
+    /// Returns the directory this one is below and the tag of the field pointing to it
+    fn parent(self) -> Option<(Ifd, u16)> {
+        match self {
+            Ifd::Primary => None,
+            Ifd::Exif => Some((Ifd::Primary, EXIF_POINTER)),
+            Ifd::Gps => Some((Ifd::Primary, GPS_POINTER)),
+            Ifd::Interoperability => Some((Ifd::Exif, INTEROPERABILITY_POINTER)),
+        }
+    }
+}
+
+/// The value of a field, holding the elements of one of the TIFF types
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Unsigned 8-bit integers
+    Byte(Vec<u8>),
+    /// Text, without its terminating NUL
+    Ascii(String),
+    /// Unsigned 16-bit integers
+    Short(Vec<u16>),
+    /// Unsigned 32-bit integers
+    Long(Vec<u32>),
+    /// Fractions of an unsigned numerator and denominator
+    Rational(Vec<(u32, u32)>),
+    /// Signed 8-bit integers
+    SByte(Vec<i8>),
+    /// Bytes whose meaning depends on the field
+    Undefined(Vec<u8>),
+    /// Signed 16-bit integers
+    SShort(Vec<i16>),
+    /// Signed 32-bit integers
+    SLong(Vec<i32>),
+    /// Fractions of a signed numerator and denominator
+    SRational(Vec<(i32, i32)>),
+    /// Single precision floating point numbers
+    Float(Vec<f32>),
+    /// Double precision floating point numbers
+    Double(Vec<f64>),
+}
+
+/// Returns the size of the elements of the TIFF type ```type_```, if it is known
+fn type_size(type_: u16) -> Option<usize> {
+    match type_ {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+impl Value {
+    /// Decodes the elements of the known TIFF type ```type_``` stored in ```data```
+    fn decode<B: ByteOrder>(type_: u16, data: &[u8]) -> Value {
+        match type_ {
+            1 => Value::Byte(data.to_vec()),
+            2 => Value::Ascii(String::from_utf8_lossy(data.split(|&b| b == 0).next().unwrap_or(data))
+                                  .into_owned()),
+            3 => Value::Short(data.chunks(2).map(B::read_u16).collect()),
+            4 => Value::Long(data.chunks(4).map(B::read_u32).collect()),
+            5 => Value::Rational(data.chunks(8).map(|n| (B::read_u32(n), B::read_u32(&n[4..]))).collect()),
+            6 => Value::SByte(data.iter().map(|&n| n as i8).collect()),
+            8 => Value::SShort(data.chunks(2).map(B::read_i16).collect()),
+            9 => Value::SLong(data.chunks(4).map(B::read_i32).collect()),
+            10 => Value::SRational(data.chunks(8).map(|n| (B::read_i32(n), B::read_i32(&n[4..]))).collect()),
+            11 => Value::Float(data.chunks(4).map(B::read_f32).collect()),
+            12 => Value::Double(data.chunks(8).map(B::read_f64).collect()),
+            _ => Value::Undefined(data.to_vec()),
+        }
+    }
+
+    /// Returns the TIFF type of the value and the number of its elements
+    fn type_and_count(&self) -> (u16, usize) {
+        match *self {
+            Value::Byte(ref v) => (1, v.len()),
+            Value::Ascii(ref s) => (2, s.len() + 1),
+            Value::Short(ref v) => (3, v.len()),
+            Value::Long(ref v) => (4, v.len()),
+            Value::Rational(ref v) => (5, v.len()),
+            Value::SByte(ref v) => (6, v.len()),
+            Value::Undefined(ref v) => (7, v.len()),
+            Value::SShort(ref v) => (8, v.len()),
+            Value::SLong(ref v) => (9, v.len()),
+            Value::SRational(ref v) => (10, v.len()),
+            Value::Float(ref v) => (11, v.len()),
+            Value::Double(ref v) => (12, v.len()),
+        }
+    }
+
+    /// Encodes the elements in big endian order
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        match *self {
+            Value::Byte(ref v) | Value::Undefined(ref v) => data.extend_from_slice(v),
+            Value::Ascii(ref s) => {
+                data.extend_from_slice(s.as_bytes());
+                data.push(0);
+            },
+            Value::Short(ref v) => for n in v { data.extend_from_slice(&n.to_be_bytes()) },
+            Value::Long(ref v) => for n in v { data.extend_from_slice(&n.to_be_bytes()) },
+            Value::Rational(ref v) => for &(n, d) in v {
+                data.extend_from_slice(&n.to_be_bytes());
+                data.extend_from_slice(&d.to_be_bytes());
+            },
+            Value::SByte(ref v) => data.extend(v.iter().map(|&n| n as u8)),
+            Value::SShort(ref v) => for n in v { data.extend_from_slice(&n.to_be_bytes()) },
+            Value::SLong(ref v) => for n in v { data.extend_from_slice(&n.to_be_bytes()) },
+            Value::SRational(ref v) => for &(n, d) in v {
+                data.extend_from_slice(&n.to_be_bytes());
+                data.extend_from_slice(&d.to_be_bytes());
+            },
+            Value::Float(ref v) => for n in v { data.extend_from_slice(&n.to_bits().to_be_bytes()) },
+            Value::Double(ref v) => for n in v { data.extend_from_slice(&n.to_bits().to_be_bytes()) },
+        }
+        data
+    }
+}
+
+/// A field of EXIF data
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    /// The directory holding the field
+    pub ifd: Ifd,
+    /// The tag identifying the field within its directory
+    pub tag: u16,
+    /// The value of the field
+    pub value: Value,
+}
+
+/// The fields of EXIF data
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Exif {
+    fields: Vec<Field>,
+}
+
+impl Exif {
+    /// Creates EXIF data without fields
+    pub fn new() -> Exif {
+        Exif::default()
+    }
+
+    /// Parses the TIFF structure ```data```, which may be preceded by the ```Exif\0\0```
+    /// identifier of JPEG segments
+    pub fn parse(data: &[u8]) -> ImageResult<Exif> {
+        let data = if data.starts_with(PREFIX) { &data[PREFIX.len()..] } else { data };
+        Exif::read(&mut Cursor::new(data))
+    }
+
+    /// Reads the TIFF structure starting at the beginning of ```r```, like the header of a
+    /// TIFF file, whose offsets are relative to it
+    ///
+    /// Only the fields of the first image are read.
+    pub fn read<R: Read + Seek>(r: &mut R) -> ImageResult<Exif> {
+        let len = try!(r.seek(SeekFrom::End(0)));
+        let mut reader = Reader {
+            r: r,
+            big_endian: false,
+            len: len,
+            read: Vec::new(),
+        };
+        let header = try!(reader.bytes(0, 8));
+        reader.big_endian = match &header[..4] {
+            b"MM\0*" => true,
+            b"II*\0" => false,
+            _ => return Err(malformed("Invalid EXIF header")),
+        };
+        let offset = reader.u32(&header[4..]);
+        let mut fields = Vec::new();
+        try!(reader.read_ifd(Ifd::Primary, offset as u64, &mut fields));
+        Ok(Exif { fields: fields })
+    }
+
+    /// Returns the fields, in the order they were read or added
+    ///
+    /// The fields pointing to the directories below the primary one are not included, they are
+    /// recreated by `to_bytes`.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// Returns the value of the field ```tag``` of the directory ```ifd```
+    pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&Value> {
+        self.fields.iter().find(|field| field.ifd == ifd && field.tag == tag).map(|field| &field.value)
+    }
+
+    /// Sets the field ```tag``` of the directory ```ifd``` to ```value```, replacing its
+    /// previous value
+    pub fn set(&mut self, ifd: Ifd, tag: u16, value: Value) {
+        match self.fields.iter_mut().find(|field| field.ifd == ifd && field.tag == tag) {
+            Some(field) => field.value = value,
+            None => self.fields.push(Field { ifd: ifd, tag: tag, value: value }),
+        }
+    }
+
+    /// Removes the field ```tag``` of the directory ```ifd```, returning its value
+    pub fn remove(&mut self, ifd: Ifd, tag: u16) -> Option<Value> {
+        self.fields.iter().position(|field| field.ifd == ifd && field.tag == tag)
+            .map(|index| self.fields.remove(index).value)
+    }
+
+    /// Keeps only the fields for which ```keep``` returns true
+    pub fn retain<F: FnMut(&Field) -> bool>(&mut self, keep: F) {
+        self.fields.retain(keep)
+    }
+
+    /// Returns the orientation of the image
+    ///
+    /// The values from 1 to 8 describe how the image has to be transformed to be shown upright,
+    /// as returned by `JPEGDecoder::orientation`.
+    pub fn orientation(&self) -> Option<u16> {
+        match self.get(Ifd::Primary, ORIENTATION) {
+            Some(&Value::Short(ref v)) => v.first().cloned().filter(|&n| n >= 1 && n <= 8),
+            _ => None,
+        }
+    }
+
+    /// Sets the orientation of the image, a value from 1 to 8
+    pub fn set_orientation(&mut self, orientation: u16) {
+        self.set(Ifd::Primary, ORIENTATION, Value::Short(vec![orientation]))
+    }
+
+    /// Returns when the image was last changed, formatted as ```YYYY:MM:DD HH:MM:SS```
+    pub fn date_time(&self) -> Option<&str> {
+        self.ascii(Ifd::Primary, DATE_TIME)
+    }
+
+    /// Returns when the photo was taken, formatted as ```YYYY:MM:DD HH:MM:SS```
+    pub fn date_time_original(&self) -> Option<&str> {
+        self.ascii(Ifd::Exif, DATE_TIME_ORIGINAL)
+    }
+
+    /// Returns the latitude and longitude the photo was taken at, in degrees north and east
+    pub fn gps_position(&self) -> Option<(f64, f64)> {
+        let latitude = match (self.degrees(GPS_LATITUDE), self.ascii(Ifd::Gps, GPS_LATITUDE_REF)) {
+            (Some(degrees), Some("N")) => degrees,
+            (Some(degrees), Some("S")) => -degrees,
+            _ => return None,
+        };
+        let longitude = match (self.degrees(GPS_LONGITUDE), self.ascii(Ifd::Gps, GPS_LONGITUDE_REF)) {
+            (Some(degrees), Some("E")) => degrees,
+            (Some(degrees), Some("W")) => -degrees,
+            _ => return None,
+        };
+        Some((latitude, longitude))
+    }
+
+    /// Returns the altitude the photo was taken at, in meters above sea level
+    pub fn gps_altitude(&self) -> Option<f64> {
+        let altitude = match self.get(Ifd::Gps, GPS_ALTITUDE) {
+            Some(&Value::Rational(ref v)) if v.len() == 1 && v[0].1 != 0 => v[0].0 as f64 / v[0].1 as f64,
+            _ => return None,
+        };
+        match self.get(Ifd::Gps, GPS_ALTITUDE_REF) {
+            Some(&Value::Byte(ref v)) if v.first() == Some(&1) => Some(-altitude),
+            _ => Some(altitude),
+        }
+    }
+
+    /// Returns the text of the field ```tag``` of the directory ```ifd```
+    fn ascii(&self, ifd: Ifd, tag: u16) -> Option<&str> {
+        match self.get(ifd, tag) {
+            Some(&Value::Ascii(ref s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the angle given in degrees, minutes and seconds by the GPS field ```tag```
+    fn degrees(&self, tag: u16) -> Option<f64> {
+        match self.get(Ifd::Gps, tag) {
+            Some(&Value::Rational(ref v)) if v.len() == 3 && v.iter().all(|&(_, d)| d != 0) => {
+                let part = |i: usize| v[i].0 as f64 / v[i].1 as f64;
+                Some(part(0) + part(1) / 60.0 + part(2) / 3600.0)
+            },
+            _ => None,
+        }
+    }
+
+    /// Serializes the fields into a big endian TIFF structure, without the ```Exif\0\0```
+    /// identifier
+    ///
+    /// The fields of each directory are sorted by their tag. Directories without fields are left
+    /// out, unless a directory below them has fields.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Whether each directory is written, as it has fields or a directory below it has
+        let mut present = [true, false, false, false];
+        for (i, &ifd) in IFDS.iter().enumerate().rev() {
+            present[i] |= self.fields.iter().any(|field| field.ifd == ifd && !is_pointer(field));
+            if let Some((parent, _)) = ifd.parent() {
+                let parent = IFDS.iter().position(|&p| p == parent).unwrap();
+                present[parent] |= present[i];
+            }
+        }
+        let mut dirs: Vec<(Ifd, Vec<(u16, Value)>)> = IFDS.iter().zip(present.iter())
+            .filter(|&(_, &present)| present)
+            .map(|(&ifd, _)| {
+                let mut entries: Vec<_> = self.fields.iter()
+                    .filter(|field| field.ifd == ifd && !is_pointer(field))
+                    .map(|field| (field.tag, field.value.clone()))
+                    .collect();
+                // The offsets of the directories below are filled in once they are known
+                for (&child, _) in IFDS.iter().zip(present.iter()).filter(|&(_, &present)| present) {
+                    match child.parent() {
+                        Some((parent, tag)) if parent == ifd => entries.push((tag, Value::Long(vec![0]))),
+                        _ => {},
+                    }
+                }
+                entries.sort_by_key(|&(tag, _)| tag);
+                (ifd, entries)
+            }).collect();
+
+        // Each directory is followed by the values that do not fit into its entries
+        let mut offsets = Vec::new();
+        let mut offset = 8;
+        for &(_, ref entries) in &dirs {
+            offsets.push(offset);
+            offset += 6 + 12 * entries.len();
+            for &(_, ref value) in entries {
+                let len = value.encode().len();
+                if len > 4 {
+                    offset += len + len % 2;
+                }
+            }
+        }
+        let order: Vec<Ifd> = dirs.iter().map(|&(ifd, _)| ifd).collect();
+        for &mut (ifd, ref mut entries) in dirs.iter_mut() {
+            for &mut (tag, ref mut value) in entries.iter_mut() {
+                if let Some(child) = order.iter().position(|child| child.parent() == Some((ifd, tag))) {
+                    *value = Value::Long(vec![offsets[child] as u32]);
+                }
+            }
+        }
+
+        let mut data = b"MM\0*\0\0\0\x08".to_vec();
+        for (&(_, ref entries), &offset) in dirs.iter().zip(offsets.iter()) {
+            let mut values_offset = offset + 6 + 12 * entries.len();
+            let mut values = Vec::new();
+            data.extend_from_slice(&(entries.len() as u16).to_be_bytes());
+            for &(tag, ref value) in entries {
+                let (type_, count) = value.type_and_count();
+                let mut encoded = value.encode();
+                data.extend_from_slice(&tag.to_be_bytes());
+                data.extend_from_slice(&type_.to_be_bytes());
+                data.extend_from_slice(&(count as u32).to_be_bytes());
+                if encoded.len() <= 4 {
+                    encoded.resize(4, 0);
+                    data.extend_from_slice(&encoded);
+                } else {
+                    data.extend_from_slice(&(values_offset as u32).to_be_bytes());
+                    if encoded.len() % 2 != 0 {
+                        encoded.push(0);
+                    }
+                    values_offset += encoded.len();
+                    values.extend_from_slice(&encoded);
+                }
+            }
+            // There is no directory of a thumbnail following the primary one
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&values);
+        }
+        data
+    }
+}
+
+/// Returns whether ```field``` points to a directory, which `Exif::to_bytes` recreates
+fn is_pointer(field: &Field) -> bool {
+    IFDS.iter().any(|child| child.parent() == Some((field.ifd, field.tag)))
+}
+
+/// Reads the directories of a TIFF structure
+struct Reader<'a, R: 'a> {
+    r: &'a mut R,
+    big_endian: bool,
+    /// The length of the stream, which the offsets are checked against
+    len: u64,
+    /// The directories read so far, which are not read again
+    read: Vec<Ifd>,
+}
+
+impl<'a, R: Read + Seek> Reader<'a, R> {
+    /// Reads ```len``` bytes at ```offset```
+    fn bytes(&mut self, offset: u64, len: u64) -> ImageResult<Vec<u8>> {
+        if offset.checked_add(len).map_or(true, |end| end > self.len) {
+            return Err(truncated())
+        }
+        try!(self.r.seek(SeekFrom::Start(offset)));
+        let mut data = vec![0; len as usize];
+        try!(self.r.read_exact(&mut data));
+        Ok(data)
+    }
+
+    fn u16(&self, data: &[u8]) -> u16 {
+        if self.big_endian { BigEndian::read_u16(data) } else { LittleEndian::read_u16(data) }
+    }
+
+    fn u32(&self, data: &[u8]) -> u32 {
+        if self.big_endian { BigEndian::read_u32(data) } else { LittleEndian::read_u32(data) }
+    }
+
+    /// Reads the directory ```ifd``` at ```offset``` into ```fields```, followed by the
+    /// directories below it at the position of the fields pointing to them
+    fn read_ifd(&mut self, ifd: Ifd, offset: u64, fields: &mut Vec<Field>) -> ImageResult<()> {
+        self.read.push(ifd);
+        let count = try!(self.bytes(offset, 2));
+        let count = self.u16(&count) as u64;
+        let entries = try!(self.bytes(offset + 2, 12 * count));
+        for entry in entries.chunks(12) {
+            let tag = self.u16(&entry[..2]);
+            let type_ = self.u16(&entry[2..4]);
+            let len = match type_size(type_) {
+                Some(size) => size as u64 * self.u32(&entry[4..8]) as u64,
+                // Fields of unknown types are skipped, as their size is not known
+                None => continue,
+            };
+            let data = if len <= 4 {
+                entry[8..8 + len as usize].to_vec()
+            } else {
+                let offset = self.u32(&entry[8..]);
+                try!(self.bytes(offset as u64, len))
+            };
+            let value = if self.big_endian {
+                Value::decode::<BigEndian>(type_, &data)
+            } else {
+                Value::decode::<LittleEndian>(type_, &data)
+            };
+
+            match IFDS.iter().find(|child| child.parent() == Some((ifd, tag))) {
+                Some(&child) => match value {
+                    _ if self.read.contains(&child) => {},
+                    Value::Long(ref offsets) if offsets.len() == 1 =>
+                        try!(self.read_ifd(child, offsets[0] as u64, fields)),
+                    _ => return Err(malformed("Invalid EXIF directory offset")),
+                },
+                None => fields.push(Field { ifd: ifd, tag: tag, value: value }),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn malformed(message: &str) -> ImageError {
+    ImageError::Decoding {
+        format: ImageFormatHint::Name("EXIF".to_string()),
+        kind: DecodingErrorKind::Malformed(message.to_string()),
+    }
+}
+
+fn truncated() -> ImageError {
+    ImageError::Decoding {
+        format: ImageFormatHint::Name("EXIF".to_string()),
+        kind: DecodingErrorKind::Truncated,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photo() -> Exif {
+        let mut exif = Exif::new();
+        exif.set_orientation(6);
+        exif.set(Ifd::Primary, MAKE, Value::Ascii("Camera maker".to_string()));
+        exif.set(Ifd::Primary, DATE_TIME, Value::Ascii("2020:01:02 03:04:05".to_string()));
+        exif.set(Ifd::Exif, DATE_TIME_ORIGINAL, Value::Ascii("2020:01:02 03:04:00".to_string()));
+        exif.set(Ifd::Gps, GPS_LATITUDE_REF, Value::Ascii("N".to_string()));
+        exif.set(Ifd::Gps, GPS_LATITUDE, Value::Rational(vec![(52, 1), (30, 1), (3600, 100)]));
+        exif.set(Ifd::Gps, GPS_LONGITUDE_REF, Value::Ascii("W".to_string()));
+        exif.set(Ifd::Gps, GPS_LONGITUDE, Value::Rational(vec![(13, 1), (15, 1), (0, 1)]));
+        exif.set(Ifd::Gps, GPS_ALTITUDE_REF, Value::Byte(vec![1]));
+        exif.set(Ifd::Gps, GPS_ALTITUDE, Value::Rational(vec![(25, 2)]));
+        exif.set(Ifd::Interoperability, 0x0001, Value::Ascii("R98".to_string()));
+        exif
+    }
+
+    #[test]
+    fn accessors() {
+        let exif = photo();
+        assert_eq!(exif.orientation(), Some(6));
+        assert_eq!(exif.date_time(), Some("2020:01:02 03:04:05"));
+        assert_eq!(exif.date_time_original(), Some("2020:01:02 03:04:00"));
+        let (latitude, longitude) = exif.gps_position().unwrap();
+        assert!((latitude - 52.51).abs() < 1e-9 && (longitude + 13.25).abs() < 1e-9);
+        assert_eq!(exif.gps_altitude(), Some(-12.5));
+        assert_eq!(Exif::new().gps_position(), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let exif = photo();
+        let data = exif.to_bytes();
+        assert!(data.starts_with(b"MM\0*\0\0\0\x08"));
+        let parsed = Exif::parse(&data).unwrap();
+        for field in exif.fields() {
+            assert_eq!(parsed.get(field.ifd, field.tag), Some(&field.value));
+        }
+        assert_eq!(parsed.fields().len(), exif.fields().len());
+        assert_eq!(parsed.to_bytes(), data);
+
+        let mut prefixed = b"Exif\0\0".to_vec();
+        prefixed.extend_from_slice(&data);
+        assert_eq!(Exif::parse(&prefixed).unwrap(), parsed);
+    }
+
+    #[test]
+    fn little_endian() {
+        // An orientation of 3 and an EXIF directory with an exposure time of 1/200 s
+        let data = b"II*\0\x08\0\0\0\x02\0\x12\x01\x03\0\x01\0\0\0\x03\0\0\0\x69\x87\x04\0\x01\0\0\0\
+                     \x26\0\0\0\0\0\0\0\x01\0\x9a\x82\x05\0\x01\0\0\0\x38\0\0\0\0\0\0\0\x01\0\0\0\xc8\0\0\0";
+        let exif = Exif::parse(data).unwrap();
+        assert_eq!(exif.orientation(), Some(3));
+        assert_eq!(exif.get(Ifd::Exif, 0x829a), Some(&Value::Rational(vec![(1, 200)])));
+        assert_eq!(exif.fields().len(), 2);
+    }
+
+    #[test]
+    fn malformed_data() {
+        let data = photo().to_bytes();
+        match Exif::parse(&data[..40]) {
+            Err(ImageError::Decoding { kind: DecodingErrorKind::Truncated, .. }) => (),
+            r => panic!("Expected the data to be truncated, got {:?}", r),
+        }
+        assert!(Exif::parse(b"MM\0+\0\0\0\x08").is_err());
+    }
+}
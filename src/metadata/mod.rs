@@ -0,0 +1,6 @@
+//! Metadata stored alongside the pixels of images
+//!
+//! The structures here are shared by the codecs, which read them from and embed them into the
+//! containers of their formats.
+
+pub mod exif;
@@ -0,0 +1,107 @@
+//! Reporting the progress of long operations and cancelling them
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use image::{ImageError, ImageResult};
+
+/// A flag cancelling the operations it was passed to
+///
+/// Clones share the flag, so that a clone kept by another thread can `cancel` an operation.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a token that has not been cancelled
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels the operations the token or one of its clones was passed to
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst)
+    }
+
+    /// Returns whether the token has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Receives the progress of decoding, encoding or processing an image, and cancels it
+///
+/// Operations count their progress in rows, or in rows and columns for those passing over the
+/// image more than once. After each batch the callback is called with the number of completed
+/// and total units, and the operation fails with `ImageError::Cancelled` if the `CancelToken`
+/// has been cancelled.
+pub struct Monitor<'a> {
+    callback: Option<Box<FnMut(u64, u64) + 'a>>,
+    token: Option<CancelToken>,
+}
+
+impl<'a> Monitor<'a> {
+    /// Creates a monitor that neither reports the progress nor cancels the operation
+    pub fn new() -> Monitor<'a> {
+        Monitor {
+            callback: None,
+            token: None,
+        }
+    }
+
+    /// Calls ```callback``` with the number of completed and total units after each batch
+    pub fn with_callback<F: FnMut(u64, u64) + 'a>(mut self, callback: F) -> Monitor<'a> {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Cancels the operation once ```token``` is cancelled
+    pub fn with_cancel_token(mut self, token: CancelToken) -> Monitor<'a> {
+        self.token = Some(token);
+        self
+    }
+
+    /// Reports that ```completed``` of ```total``` units are done, failing with
+    /// `ImageError::Cancelled` if the operation has been cancelled
+    pub fn update(&mut self, completed: u64, total: u64) -> ImageResult<()> {
+        if let Some(ref mut callback) = self.callback {
+            callback(completed, total);
+        }
+        match self.token {
+            Some(ref token) if token.is_cancelled() => Err(ImageError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'a> Default for Monitor<'a> {
+    fn default() -> Monitor<'a> {
+        Monitor::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CancelToken, Monitor};
+    use image::ImageError;
+
+    #[test]
+    fn cancel() {
+        let mut updates = Vec::new();
+        let token = CancelToken::new();
+        {
+            let mut monitor = Monitor::new()
+                .with_callback(|completed, total| updates.push((completed, total)))
+                .with_cancel_token(token.clone());
+            assert!(monitor.update(1, 4).is_ok());
+            token.clone().cancel();
+            match monitor.update(2, 4) {
+                Err(ImageError::Cancelled) => (),
+                r => panic!("Expected the operation to be cancelled, got {:?}", r),
+            }
+        }
+        assert!(token.is_cancelled());
+        assert_eq!(updates, vec![(1, 4), (2, 4)]);
+    }
+}
@@ -7,38 +7,343 @@
 //! * <http://www.w3.org/TR/PNG/> - The PNG Specification
 //!
 
+extern crate deflate;
+extern crate inflate;
 extern crate png;
 
 use self::png::HasParameters;
+use self::png::chunk::{self, ChunkType};
 
-use std::io::{self, Read, Write};
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Write};
 
-use image::{ImageError, ImageResult, DecodingResult, ImageDecoder};
-use color::ColorType;
+use byteorder::{BigEndian, ByteOrder};
+use num_rational::Ratio;
 
-enum Either<T, U> {
-    Left(T),
-    Right(U)
-}
+use animation::{Blend, Delay, Disposal, Frame, Frames, LoopCount};
+use buffer::RgbaImage;
+use dynimage::decoder_to_image;
+use image::{ImageError, ImageFormat, ImageResult, DecodingResult, ImageDecoder, ImageEncoder,
+            Limits, Metadata, ParameterErrorKind};
+use monitor::Monitor;
+use color::{self, ColorType};
+use imageops;
+use math::nq;
+use metadata::exif::Exif;
+use utils::Either;
+
+/// The eight bytes every png starts with
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+/// The type of the chunk holding an embedded ICC profile
+const ICCP: ChunkType = *b"iCCP";
+
+/// The types of the chunks describing the color space
+const GAMA: ChunkType = *b"gAMA";
+const CHRM: ChunkType = *b"cHRM";
+const SRGB: ChunkType = *b"sRGB";
+
+/// The type of the chunk holding the physical size of the pixels
+const PHYS: ChunkType = *b"pHYs";
+
+/// The type of the chunk holding EXIF data
+const EXIF: ChunkType = *b"eXIf";
+
+/// The types of the chunks holding text
+const TEXT: ChunkType = *b"tEXt";
+const ZTXT: ChunkType = *b"zTXt";
+const ITXT: ChunkType = *b"iTXt";
+
+/// The gamma of sRGB as stored in the gAMA chunk
+const SRGB_GAMMA: u32 = 45455;
+
+/// The color type of indexed images in the IHDR chunk
+const INDEXED: u8 = 3;
+
+/// The largest chunk length allowed by the specification
+const MAX_CHUNK_LEN: u32 = (1 << 31) - 1;
+
+/// The first column and row of each Adam7 pass, and the distances between its pixels
+const ADAM7_PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The area each pixel of an Adam7 pass covers until later passes are decoded
+const ADAM7_BLOCKS: [(u32, u32); 7] = [(8, 8), (4, 8), (4, 4), (2, 4), (2, 2), (1, 2), (1, 1)];
+
+/// The input of a decoder, preceded by the bytes already read while scanning the header
+type Source<R> = io::Chain<Cursor<Vec<u8>>, R>;
 
 /// PNG decoder
+///
+/// Animated PNGs (APNG) are decoded as their default image by `read_image`, their frames are
+/// available through `into_frames`.
 pub struct PNGDecoder<R: Read> {
-    inner: Option<Either<png::Decoder<R>, png::Reader<R>>>
+    inner: Option<Either<Source<R>, png::Reader<Source<R>>>>,
+    header: Option<Header>,
+    options: PNGDecoderOptions,
+    /// The deinterlaced image, while the rows of an interlaced image are read by `read_scanline`
+    deinterlaced: Option<Vec<u8>>,
+    /// The number of rows read by `read_scanline`
+    row: u32,
+}
+
+/// Settings controlling how a `PNGDecoder` transforms the decoded samples
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PNGDecoderOptions {
+    /// Whether indexed images are expanded to the colors of their palette.
+    ///
+    /// Defaults to true. Otherwise indexed images are decoded as `Palette` images holding the
+    /// packed indices, their colors are available through `palette` and `transparency`.
+    pub expand_palette: bool,
+
+    /// Whether the color samples of images declaring a gamma other than that of sRGB are
+    /// converted to sRGB gamma.
+    ///
+    /// Disabled by default, the gamma of the image is available through `gamma`. Only 8-bit
+    /// samples are corrected, the alpha channel and palette indices are left unchanged.
+    pub correct_gamma: bool,
+}
+
+impl Default for PNGDecoderOptions {
+    fn default() -> PNGDecoderOptions {
+        PNGDecoderOptions {
+            expand_palette: true,
+            correct_gamma: false,
+        }
+    }
+}
+
+/// The chromaticities of the primaries and white point of an image in the CIE 1931 xy space
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Chromaticities {
+    /// The x and y coordinates of the white point
+    pub white: (f32, f32),
+    /// The x and y coordinates of the red primary
+    pub red: (f32, f32),
+    /// The x and y coordinates of the green primary
+    pub green: (f32, f32),
+    /// The x and y coordinates of the blue primary
+    pub blue: (f32, f32),
+}
+
+/// How colors outside of the gamut of a display are mapped, as declared by the sRGB chunk
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RenderingIntent {
+    /// For images like photographs, the colors may change as long as they look natural
+    Perceptual,
+    /// For images needing exact colors like logos, colors are adapted to the white point
+    RelativeColorimetric,
+    /// For images like charts, saturation is preserved at the expense of hue and lightness
+    Saturation,
+    /// Colors are reproduced exactly, even the white point
+    AbsoluteColorimetric,
+}
+
+/// The properties of a png stored before its image data
+struct Header {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    animation: Option<png::AnimationControl>,
+    /// The content of the iCCP chunk: the profile name, compression method and compressed profile
+    icc_profile: Option<Vec<u8>>,
+    /// The type and content of each text chunk
+    text: Vec<(ChunkType, Vec<u8>)>,
+    /// The content of the PLTE chunk
+    palette: Option<Vec<u8>>,
+    /// The content of the tRNS chunk
+    transparency: Option<Vec<u8>>,
+    /// The content of the gAMA chunk, the gamma times 100000
+    gamma: Option<u32>,
+    /// The content of the cHRM chunk, the coordinates times 100000
+    chromaticities: Option<[u32; 8]>,
+    /// The content of the sRGB chunk
+    srgb: Option<u8>,
+    /// The content of the pHYs chunk: the pixels per unit along both axes and the unit
+    physical: Option<(u32, u32, u8)>,
+    /// The content of the eXIf chunk
+    exif: Option<Vec<u8>>,
+}
+
+/// A textual chunk of a png, holding a keyword and the text it describes
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TextChunk {
+    /// Identifies the kind of information, such as `Title`, `Author` or `Source`.
+    ///
+    /// Keywords are 1 to 79 characters of Latin-1 without leading, trailing or double spaces.
+    pub keyword: String,
+    /// The text itself
+    pub text: String,
+    /// How the chunk is stored
+    pub kind: TextKind,
+}
+
+/// How a `TextChunk` is stored in a png
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TextKind {
+    /// A `tEXt` chunk, holding uncompressed Latin-1 text
+    Plain,
+    /// A `zTXt` chunk, holding compressed Latin-1 text
+    Compressed,
+    /// An `iTXt` chunk, holding UTF-8 text
+    International {
+        /// Whether the text is compressed
+        compressed: bool,
+        /// The language of the text as a RFC 3066 tag, such as `en` or `de-ch`, may be empty
+        language: String,
+        /// The keyword translated to the language of the text, may be empty
+        translated_keyword: String,
+    },
+}
+
+impl TextChunk {
+    /// Create an uncompressed `tEXt` chunk
+    pub fn new(keyword: &str, text: &str) -> TextChunk {
+        TextChunk {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            kind: TextKind::Plain,
+        }
+    }
+
+    fn decode(kind: ChunkType, data: &[u8]) -> ImageResult<TextChunk> {
+        let malformed = || ImageError::malformed(ImageFormat::PNG, "text chunk is malformed".into());
+        let (keyword, rest) = try!(split_null(data).ok_or_else(malformed));
+        let keyword = latin1_to_string(keyword);
+        match kind {
+            TEXT => Ok(TextChunk {
+                keyword: keyword,
+                text: latin1_to_string(rest),
+                kind: TextKind::Plain,
+            }),
+            ZTXT => match rest.split_first() {
+                Some((&0, compressed)) => Ok(TextChunk {
+                    keyword: keyword,
+                    text: latin1_to_string(&try!(inflate_text(compressed))),
+                    kind: TextKind::Compressed,
+                }),
+                _ => Err(malformed()),
+            },
+            _ => {
+                if rest.len() < 2 || rest[1] != 0 {
+                    return Err(malformed())
+                }
+                let compressed = match rest[0] {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(malformed()),
+                };
+                let (language, rest) = try!(split_null(&rest[2..]).ok_or_else(malformed));
+                let (translated_keyword, text) = try!(split_null(rest).ok_or_else(malformed));
+                let text = if compressed { try!(inflate_text(text)) } else { text.to_vec() };
+                let utf8 = |bytes: Vec<u8>| String::from_utf8(bytes).map_err(|_| malformed());
+                Ok(TextChunk {
+                    keyword: keyword,
+                    text: try!(utf8(text)),
+                    kind: TextKind::International {
+                        compressed: compressed,
+                        language: try!(utf8(language.to_vec())),
+                        translated_keyword: try!(utf8(translated_keyword.to_vec())),
+                    },
+                })
+            },
+        }
+    }
+
+    fn encode(&self) -> io::Result<(ChunkType, Vec<u8>)> {
+        let keyword = try!(string_to_latin1(&self.keyword));
+        if keyword.is_empty() || keyword.len() > 79 || keyword.contains(&0)
+            || keyword[0] == b' ' || keyword[keyword.len() - 1] == b' ' || self.keyword.contains("  ") {
+            return Err(invalid_input("Invalid png text keyword"))
+        }
+
+        let mut data = keyword;
+        data.push(0);
+        let kind = match self.kind {
+            TextKind::Plain => {
+                data.extend(try!(string_to_latin1(&self.text)));
+                TEXT
+            },
+            TextKind::Compressed => {
+                data.push(0);
+                data.extend(deflate::deflate_bytes_zlib(&try!(string_to_latin1(&self.text))));
+                ZTXT
+            },
+            TextKind::International { compressed, ref language, ref translated_keyword } => {
+                data.extend_from_slice(&[compressed as u8, 0]);
+                data.extend_from_slice(language.as_bytes());
+                data.push(0);
+                data.extend_from_slice(translated_keyword.as_bytes());
+                data.push(0);
+                if compressed {
+                    data.extend(deflate::deflate_bytes_zlib(self.text.as_bytes()));
+                } else {
+                    data.extend_from_slice(self.text.as_bytes());
+                }
+                ITXT
+            },
+        };
+        Ok((kind, data))
+    }
+}
+
+/// Splits ```data``` at its first null byte, which is dropped
+fn split_null(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    data.iter().position(|&b| b == 0).map(|end| (&data[..end], &data[end + 1..]))
+}
+
+fn inflate_text(data: &[u8]) -> ImageResult<Vec<u8>> {
+    inflate::inflate_bytes_zlib(data).map_err(|err| ImageError::malformed(ImageFormat::PNG, err))
+}
+
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| b as char).collect()
+}
+
+fn string_to_latin1(text: &str) -> io::Result<Vec<u8>> {
+    text.chars().map(|c| if (c as u32) < 256 {
+        Ok(c as u8)
+    } else {
+        Err(invalid_input("Text of tEXt and zTXt chunks must be Latin-1"))
+    }).collect()
 }
 
 impl<R: Read> PNGDecoder<R> {
     /// Creates a new decoder that decodes from the stream ```r```
     pub fn new(r: R) -> PNGDecoder<R> {
+        PNGDecoder::new_with_options(r, PNGDecoderOptions::default())
+    }
+
+    /// Creates a new decoder that decodes from the stream ```r``` using the settings ```options```
+    pub fn new_with_options(r: R, options: PNGDecoderOptions) -> PNGDecoder<R> {
         PNGDecoder {
-            inner: Some(Either::Left(png::Decoder::new(r)))
+            inner: Some(Either::Left(Cursor::new(Vec::new()).chain(r))),
+            header: None,
+            options: options,
+            deinterlaced: None,
+            row: 0,
         }
     }
 
     // Converts the inner decoder to a reader
-    fn get_reader(&mut self) -> Result<&mut png::Reader<R>, png::DecodingError> {
+    fn get_reader(&mut self) -> ImageResult<&mut png::Reader<Source<R>>> {
+        // The reader skips chunks it does not know, so they are read beforehand
+        let indexed = try!(self.get_header()).color_type == INDEXED;
         let inner = self.inner.take().unwrap();
         self.inner = Some(match inner {
-            Either::Left(decoder) => {
+            Either::Left(source) => {
+                let mut decoder = png::Decoder::new(source);
+                if indexed && !self.options.expand_palette {
+                    decoder.set(png::Transformations::IDENTITY);
+                }
                 let (_, reader) = try!(decoder.read_info());
                 Either::Right(reader)
             },
@@ -49,12 +354,247 @@ impl<R: Read> PNGDecoder<R> {
             _ => unreachable!()
         }
     }
+
+    // Reads the chunks before the image data without consuming them for the reader
+    fn get_header(&mut self) -> ImageResult<&Header> {
+        if self.header.is_none() {
+            let mut source = match self.inner.take().unwrap() {
+                Either::Left(source) => source,
+                Either::Right(_) => unreachable!(),
+            };
+            let mut recorded = Vec::new();
+            let header = scan_header(&mut source, &mut recorded);
+            let (prefix, rest) = source.into_inner();
+            let position = prefix.position() as usize;
+            recorded.extend_from_slice(&prefix.get_ref()[position..]);
+            self.inner = Some(Either::Left(Cursor::new(recorded).chain(rest)));
+            self.header = Some(try!(header));
+        }
+        Ok(self.header.as_ref().unwrap())
+    }
+
+    /// Returns true if the rows of the image are interlaced, so that they are only available
+    /// from `read_scanline` once the whole image has been decoded.
+    pub fn is_interlaced(&mut self) -> ImageResult<bool> {
+        Ok(try!(self.get_reader()).info().interlaced)
+    }
+
+    /// Returns how often the animation is played, which is once for still images
+    pub fn loop_count(&mut self) -> ImageResult<LoopCount> {
+        Ok(match try!(self.get_header()).animation {
+            Some(ref animation) if animation.num_plays == 0 => LoopCount::Infinite,
+            Some(ref animation) => LoopCount::Finite(animation.num_plays),
+            None => LoopCount::Finite(1),
+        })
+    }
+
+    /// Returns the colors of the palette, if the image has one.
+    ///
+    /// Indexed images always have a palette, other images may suggest one for displays with a
+    /// limited number of colors.
+    pub fn palette(&mut self) -> ImageResult<Option<Vec<(u8, u8, u8)>>> {
+        let palette = match try!(self.get_header()).palette {
+            Some(ref palette) => palette,
+            None => return Ok(None),
+        };
+        Ok(Some(palette.chunks(3).filter(|entry| entry.len() == 3)
+                .map(|entry| (entry[0], entry[1], entry[2])).collect()))
+    }
+
+    /// Returns the content of the tRNS chunk, if the image has one.
+    ///
+    /// For indexed images these are the alpha values of the first entries of the palette, the
+    /// remaining entries are opaque. Otherwise they hold the gray or rgb value of the single
+    /// transparent color as 16-bit big endian samples.
+    pub fn transparency(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        Ok(try!(self.get_header()).transparency.clone())
+    }
+
+    /// Returns the gamma the samples of the image are encoded with, if it is known.
+    ///
+    /// The gamma is the exponent converting linear intensities to samples, about 0.45455 for
+    /// images meant for typical displays. For sRGB images it is that of sRGB, as the gAMA chunk
+    /// is to be ignored then.
+    pub fn gamma(&mut self) -> ImageResult<Option<f32>> {
+        let header = try!(self.get_header());
+        Ok(match (header.srgb, header.gamma) {
+            (Some(_), _) => Some(SRGB_GAMMA as f32 / 100000.0),
+            (None, Some(gamma)) => Some(gamma as f32 / 100000.0),
+            (None, None) => None,
+        })
+    }
+
+    /// Returns the chromaticities stored in the cHRM chunk, if the image has one.
+    pub fn chromaticities(&mut self) -> ImageResult<Option<Chromaticities>> {
+        Ok(try!(self.get_header()).chromaticities.map(|c| {
+            let point = |i: usize| (c[i] as f32 / 100000.0, c[i + 1] as f32 / 100000.0);
+            Chromaticities {
+                white: point(0),
+                red: point(2),
+                green: point(4),
+                blue: point(6),
+            }
+        }))
+    }
+
+    /// Returns the rendering intent of the sRGB chunk, if the image declares to be sRGB.
+    pub fn srgb(&mut self) -> ImageResult<Option<RenderingIntent>> {
+        match try!(self.get_header()).srgb {
+            None => Ok(None),
+            Some(0) => Ok(Some(RenderingIntent::Perceptual)),
+            Some(1) => Ok(Some(RenderingIntent::RelativeColorimetric)),
+            Some(2) => Ok(Some(RenderingIntent::Saturation)),
+            Some(3) => Ok(Some(RenderingIntent::AbsoluteColorimetric)),
+            Some(_) => Err(ImageError::malformed(ImageFormat::PNG, "sRGB chunk is malformed".into())),
+        }
+    }
+
+    /// Decodes the image into ```data```, which holds exactly its rows, reporting each row to
+    /// ```monitor```
+    fn read_into(&mut self, data: &mut [u8], monitor: &mut Monitor) -> ImageResult<()> {
+        let correction = try!(self.gamma_correction());
+        let reader = try!(self.get_reader());
+        let height = reader.info().height as u64;
+        try!(monitor.update(0, height));
+        if reader.info().interlaced {
+            try!(reader.next_frame(data));
+            try!(monitor.update(height, height));
+        } else {
+            // The reader does not stop at the end of the image data if APNG frames follow it
+            let line_size = reader.output_line_size(reader.info().width);
+            for (y, out) in data.chunks_mut(line_size.max(1)).enumerate() {
+                match try!(reader.next_row()) {
+                    Some(line) => ::copy_memory(line, out),
+                    None => return Err(ImageError::truncated(ImageFormat::PNG)),
+                }
+                try!(monitor.update(y as u64 + 1, height));
+            }
+        }
+        if let Some(correction) = correction {
+            correction.apply(data);
+        }
+        Ok(())
+    }
+
+    /// The conversion of samples to sRGB gamma, if it is enabled and needed
+    fn gamma_correction(&mut self) -> ImageResult<Option<GammaCorrection>> {
+        if !self.options.correct_gamma {
+            return Ok(None)
+        }
+        let gamma = match *try!(self.get_header()) {
+            Header { srgb: None, gamma: Some(gamma), .. } if gamma != SRGB_GAMMA && gamma != 0 => gamma,
+            _ => return Ok(None),
+        };
+        let channels = match try!(self.get_reader()).output_color_type() {
+            (_, png::BitDepth::Eight) => try!(self.colortype()),
+            _ => return Ok(None),
+        };
+        let (channels, alpha) = match channels {
+            ColorType::Gray(_) => (1, false),
+            ColorType::GrayA(_) => (2, true),
+            ColorType::RGB(_) => (3, false),
+            ColorType::RGBA(_) => (4, true),
+            _ => return Ok(None),
+        };
+
+        // Samples are decoded to linear intensities and encoded again with the gamma of sRGB
+        let exponent = SRGB_GAMMA as f32 / gamma as f32;
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            *entry = ((i as f32 / 255.0).powf(exponent) * 255.0).round() as u8;
+        }
+        Ok(Some(GammaCorrection {
+            table: table,
+            channels: channels,
+            alpha: alpha,
+        }))
+    }
+
+    /// Returns the text chunks of the image, in the order they are stored.
+    ///
+    /// Only the chunks before the image data are read, text after it is not available.
+    pub fn text_chunks(&mut self) -> ImageResult<Vec<TextChunk>> {
+        try!(self.get_header()).text.iter().map(|&(kind, ref data)| TextChunk::decode(kind, data)).collect()
+    }
+
+    /// Returns the EXIF data of the eXIf chunk, if the image has one before its image data
+    pub fn exif(&mut self) -> ImageResult<Option<Exif>> {
+        match try!(self.get_header()).exif {
+            Some(ref exif) => Exif::parse(exif).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Decodes the image like `read_image`, reporting the intermediate image after each pass.
+    ///
+    /// ```on_pass``` is called with the number of each completed Adam7 pass, from 1 to 7, and the
+    /// image decoded so far. Pixels of later passes are filled in from the closest pixel already
+    /// decoded, so every intermediate image is a complete, coarser preview. Images which are not
+    /// interlaced are reported once, as pass 7. If the stream ends early the passes completed
+    /// before are still reported.
+    pub fn read_image_progressively<F>(&mut self, mut on_pass: F) -> ImageResult<DecodingResult>
+    where F: FnMut(u8, &[u8]) {
+        let (width, height) = try!(self.dimensions());
+        if !try!(self.get_reader()).info().interlaced {
+            let image = try!(self.read_image());
+            if let DecodingResult::U8(ref data) = image {
+                on_pass(7, data);
+            }
+            return Ok(image)
+        }
+
+        let correction = try!(self.gamma_correction());
+        let reader = try!(self.get_reader());
+        let line_size = reader.output_line_size(width);
+        let bits = bits_per_pixel(reader.output_color_type().into());
+        let mut data = vec![0; reader.output_buffer_size()];
+        let mut current = None;
+        let mut corrected = Vec::new();
+        while let Some((row, adam7)) = try!(reader.next_interlaced_row()) {
+            let row = match correction {
+                Some(ref correction) => {
+                    corrected.clear();
+                    corrected.extend_from_slice(row);
+                    correction.apply(&mut corrected);
+                    &corrected[..]
+                },
+                None => row,
+            };
+            let (pass, line, _) = adam7.unwrap();
+            if current != Some(pass) {
+                if let Some(previous) = current {
+                    on_pass(previous, &data);
+                }
+                current = Some(pass);
+            }
+
+            let (x_start, y_start, x_step, y_step) = ADAM7_PASSES[pass as usize - 1];
+            let (block_width, block_height) = ADAM7_BLOCKS[pass as usize - 1];
+            let y = y_start + line * y_step;
+            let pixels = if width > x_start { (width - x_start + x_step - 1) / x_step } else { 0 };
+            for i in 0..pixels as usize {
+                let x = x_start + i as u32 * x_step;
+                for block_y in y..(y + block_height).min(height) {
+                    let start = block_y as usize * line_size;
+                    let out = &mut data[start..start + line_size];
+                    for block_x in x..(x + block_width).min(width) {
+                        copy_pixel(row, i, out, block_x as usize, bits);
+                    }
+                }
+            }
+        }
+        if let Some(last) = current {
+            on_pass(last, &data);
+        }
+        Ok(DecodingResult::U8(data))
+    }
+
 }
 
 impl<R: Read> ImageDecoder for PNGDecoder<R> {
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
-        let reader = try!(self.get_reader());
-        Ok(reader.info().size())
+        let header = try!(self.get_header());
+        Ok((header.width, header.height))
     }
 
     fn colortype(&mut self) -> ImageResult<ColorType> {
@@ -68,53 +608,1209 @@ impl<R: Read> ImageDecoder for PNGDecoder<R> {
         Ok(reader.output_line_size(width))
     }
 
+    /// Reads the next row of the image into ```buf```.
+    ///
+    /// The rows of an interlaced image are spread over the passes of Adam7, so the whole image
+    /// is decoded when the first row is read.
     fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
-        match try!(try!(self.get_reader()).next_row()) {
-            Some(line) => {
-                ::copy_memory(line, &mut buf[..line.len()]);
-                Ok(line.len() as u32)
-            },
-            None => Err(ImageError::ImageEnd)
+        // The rows are counted, as the reader does not stop at the end of the image data if APNG
+        // frames follow it
+        let (_, height) = try!(self.dimensions());
+        if self.row == height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
         }
+
+        if try!(self.is_interlaced()) {
+            if self.deinterlaced.is_none() {
+                self.deinterlaced = match try!(self.read_image()) {
+                    DecodingResult::U8(data) => Some(data),
+                    _ => unreachable!(),
+                };
+            }
+            let start = self.row as usize * len;
+            buf[..len].copy_from_slice(&self.deinterlaced.as_ref().unwrap()[start..start + len]);
+        } else {
+            let correction = try!(self.gamma_correction());
+            match try!(try!(self.get_reader()).next_row()) {
+                Some(line) => ::copy_memory(line, &mut buf[..len]),
+                None => return Err(ImageError::truncated(ImageFormat::PNG)),
+            }
+            if let Some(correction) = correction {
+                correction.apply(&mut buf[..len]);
+            }
+        }
+        self.row += 1;
+        Ok(self.row - 1)
     }
 
     fn read_image(&mut self) -> ImageResult<DecodingResult> {
-        let reader = try!(self.get_reader());
-        let mut data = vec![0; reader.output_buffer_size()];
-        try!(reader.next_frame(&mut data));
+        self.read_image_with_progress(&mut Monitor::new())
+    }
+
+    /// Decodes the entire image into ```buf```, without allocating it
+    fn read_image_into(&mut self, buf: &mut [u8]) -> ImageResult<usize> {
+        let len = try!(self.get_reader()).output_buffer_size();
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+        try!(self.read_into(&mut buf[..len], &mut Monitor::new()));
+        Ok(len)
+    }
+
+    /// Decodes the entire image, reporting each row to ```monitor```
+    ///
+    /// The rows of an interlaced image are only complete once all passes of Adam7 have been
+    /// decoded, so it is reported as a whole.
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        let mut data = vec![0; try!(self.get_reader()).output_buffer_size()];
+        try!(self.read_into(&mut data, monitor));
         Ok(DecodingResult::U8(data))
     }
+
+    fn is_animated(&mut self) -> ImageResult<bool> {
+        Ok(try!(self.get_header()).animation.is_some())
+    }
+
+    /// Returns the metadata read from the chunks before the image data.
+    ///
+    /// Unlike `colortype`, this does not prevent the frames of an APNG from being read.
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let gamma = try!(self.gamma());
+        let expand = self.options.expand_palette;
+        let header = try!(self.get_header());
+        // Samples of fewer than 8 bits are expanded, unless palette indices are kept
+        let bit_depth = if header.color_type == INDEXED && !expand {
+            header.bit_depth
+        } else {
+            header.bit_depth.max(8)
+        };
+        Ok(Metadata {
+            dimensions: (header.width, header.height),
+            bit_depth: bit_depth,
+            // Pixels per meter, other units only give the aspect ratio
+            dpi: match header.physical {
+                Some((x, y, 1)) => Some((x as f32 * 0.0254, y as f32 * 0.0254)),
+                _ => None,
+            },
+            gamma: gamma,
+            has_icc_profile: header.icc_profile.is_some(),
+            orientation: header.exif.as_ref().and_then(|exif| Exif::parse(exif).ok())
+                .and_then(|exif| exif.orientation()),
+            frame_count: Some(header.animation.map_or(1, |animation| animation.num_frames)),
+        })
+    }
+
+    fn icc_profile(&mut self) -> ImageResult<Option<Vec<u8>>> {
+        let chunk = match try!(self.get_header()).icc_profile {
+            Some(ref chunk) => chunk,
+            None => return Ok(None),
+        };
+        // The profile name is followed by a null separator and the compression method
+        let name_end = match chunk.iter().position(|&b| b == 0) {
+            Some(end) if end + 1 < chunk.len() => end,
+            _ => return Err(ImageError::malformed(ImageFormat::PNG, "iCCP chunk is malformed".into())),
+        };
+        if chunk[name_end + 1] != 0 {
+            return Err(ImageError::malformed(ImageFormat::PNG,
+                "iCCP chunk has an unknown compression method".into()))
+        }
+        inflate::inflate_bytes_zlib(&chunk[name_end + 2..])
+            .map(Some)
+            .map_err(|err| ImageError::malformed(ImageFormat::PNG, err))
+    }
+
+    /// Returns the frames of an APNG, or the image as a single frame if it is not animated.
+    ///
+    /// The frames of an APNG can not be read once its default image has been decoded, as the
+    /// frames following it are stored after its data.
+    fn into_frames(self) -> ImageResult<Frames> {
+        self.into_frames_with_limits(&Limits::no_limits())
+    }
+
+    fn into_frames_with_limits(mut self, limits: &Limits) -> ImageResult<Frames> {
+        if !try!(self.is_animated()) {
+            self.options.expand_palette = true;
+            try!(self.check_limits(limits));
+            return Ok(Frames::new(vec![
+                Frame::new(try!(decoder_to_image(self)).to_rgba())
+            ]))
+        }
+
+        let mut data = Vec::new();
+        match self.inner.take().unwrap() {
+            Either::Left(mut source) => try!(source.read_to_end(&mut data)),
+            Either::Right(_) => return Err(ImageError::unsupported(ImageFormat::PNG,
+                "The frames of an APNG can not be read after decoding its default image".into())),
+        };
+        Ok(Frames::new(try!(read_frames(&data, limits))))
+    }
+}
+
+/// A lookup table converting the color samples of an image to another gamma
+struct GammaCorrection {
+    table: [u8; 256],
+    channels: usize,
+    alpha: bool,
+}
+
+impl GammaCorrection {
+    fn apply(&self, data: &mut [u8]) {
+        for pixel in data.chunks_mut(self.channels) {
+            let colors = if self.alpha { self.channels - 1 } else { self.channels };
+            for sample in pixel.iter_mut().take(colors) {
+                *sample = self.table[*sample as usize];
+            }
+        }
+    }
+}
+
+/// Copies the pixel ```from``` of the row ```src``` to the pixel ```to``` of the row ```dst```
+fn copy_pixel(src: &[u8], from: usize, dst: &mut [u8], to: usize, bits: usize) {
+    if bits >= 8 {
+        let bpp = bits / 8;
+        ::copy_memory(&src[from * bpp..(from + 1) * bpp], &mut dst[to * bpp..(to + 1) * bpp]);
+    } else {
+        // Packed pixels are stored from the most significant bits on
+        let mask = (1u8 << bits) - 1;
+        let value = (src[from * bits / 8] >> (8 - bits - from * bits % 8)) & mask;
+        let shift = 8 - bits - to * bits % 8;
+        let byte = &mut dst[to * bits / 8];
+        *byte = *byte & !(mask << shift) | value << shift;
+    }
+}
+
+/// A chunk of a png held in memory
+struct Chunk<'a> {
+    kind: ChunkType,
+    data: &'a [u8],
+    /// The complete chunk including its length, type and crc
+    raw: &'a [u8],
+}
+
+/// Reads the signature and all chunks up to the first `IDAT`, appending the bytes to ```recorded```
+fn scan_header<R: Read>(r: &mut R, recorded: &mut Vec<u8>) -> ImageResult<Header> {
+    let mut signature = [0; 8];
+    try!(r.read_exact(&mut signature));
+    recorded.extend_from_slice(&signature);
+    if signature != SIGNATURE {
+        return Err(ImageError::malformed(ImageFormat::PNG, "invalid signature".into()))
+    }
+
+    let mut header = None;
+    let mut animation = None;
+    let mut icc_profile = None;
+    let mut text = Vec::new();
+    let mut palette = None;
+    let mut transparency = None;
+    let mut gamma = None;
+    let mut chromaticities = None;
+    let mut srgb = None;
+    let mut physical = None;
+    let mut exif = None;
+    loop {
+        let mut start = [0; 8];
+        try!(r.read_exact(&mut start));
+        recorded.extend_from_slice(&start);
+        let length = BigEndian::read_u32(&start[..4]);
+        let kind = [start[4], start[5], start[6], start[7]];
+        if kind == chunk::IDAT || kind == chunk::IEND {
+            break
+        }
+        if length > MAX_CHUNK_LEN {
+            return Err(ImageError::malformed(ImageFormat::PNG, "chunk is too long".into()))
+        }
+
+        // The length is not trusted to allocate the chunk up front
+        let offset = recorded.len();
+        let total = length as u64 + 4;
+        if try!(r.by_ref().take(total).read_to_end(recorded)) as u64 != total {
+            return Err(ImageError::truncated(ImageFormat::PNG))
+        }
+        let data = &recorded[offset..offset + length as usize];
+        match kind {
+            chunk::IHDR if data.len() >= 10 => header = Some((
+                BigEndian::read_u32(&data[..4]), BigEndian::read_u32(&data[4..8]), data[8], data[9])),
+            chunk::PLTE => palette = Some(data.to_vec()),
+            chunk::tRNS => transparency = Some(data.to_vec()),
+            GAMA if data.len() == 4 => gamma = Some(BigEndian::read_u32(data)),
+            CHRM if data.len() == 32 => {
+                let mut points = [0; 8];
+                for (point, value) in points.iter_mut().zip(data.chunks(4)) {
+                    *point = BigEndian::read_u32(value);
+                }
+                chromaticities = Some(points)
+            },
+            SRGB if data.len() == 1 => srgb = Some(data[0]),
+            PHYS if data.len() == 9 => physical = Some((
+                BigEndian::read_u32(&data[..4]), BigEndian::read_u32(&data[4..8]), data[8])),
+            EXIF => exif = Some(data.to_vec()),
+            chunk::acTL if data.len() >= 8 => animation = Some(png::AnimationControl {
+                num_frames: BigEndian::read_u32(&data[..4]),
+                num_plays: BigEndian::read_u32(&data[4..8]),
+            }),
+            ICCP => icc_profile = Some(data.to_vec()),
+            TEXT | ZTXT | ITXT => text.push((kind, data.to_vec())),
+            _ => {},
+        }
+    }
+
+    match header {
+        Some((width, height, bit_depth, color_type)) => Ok(Header {
+            width: width,
+            height: height,
+            bit_depth: bit_depth,
+            color_type: color_type,
+            animation: animation,
+            icc_profile: icc_profile,
+            text: text,
+            palette: palette,
+            transparency: transparency,
+            gamma: gamma,
+            chromaticities: chromaticities,
+            srgb: srgb,
+            physical: physical,
+            exif: exif,
+        }),
+        None => Err(ImageError::malformed(ImageFormat::PNG, "IHDR chunk missing".into())),
+    }
+}
+
+/// Splits a png held in memory into its chunks, up to and including `IEND`
+fn read_chunks(data: &[u8]) -> ImageResult<Vec<Chunk>> {
+    if data.len() < 8 || data[..8] != SIGNATURE {
+        return Err(ImageError::malformed(ImageFormat::PNG, "invalid signature".into()))
+    }
+
+    let mut chunks = Vec::new();
+    let mut rest = &data[8..];
+    loop {
+        if rest.len() < 12 {
+            return Err(ImageError::truncated(ImageFormat::PNG))
+        }
+        let length = BigEndian::read_u32(&rest[..4]) as usize;
+        if rest.len() - 12 < length {
+            return Err(ImageError::truncated(ImageFormat::PNG))
+        }
+        let (raw, next) = rest.split_at(length + 12);
+        if crc32(&raw[4..length + 8]) != BigEndian::read_u32(&raw[length + 8..]) {
+            return Err(ImageError::malformed(ImageFormat::PNG, "CRC error".into()))
+        }
+        let chunk = Chunk {
+            kind: [raw[4], raw[5], raw[6], raw[7]],
+            data: &raw[8..length + 8],
+            raw: raw,
+        };
+        let end = chunk.kind == chunk::IEND;
+        chunks.push(chunk);
+        if end {
+            return Ok(chunks)
+        }
+        rest = next;
+    }
+}
+
+/// Decodes every frame of an APNG held in memory
+fn read_frames(data: &[u8], limits: &Limits) -> ImageResult<Vec<Frame>> {
+    let mut ihdr = None;
+    // Chunks such as the palette apply to every frame
+    let mut shared = Vec::new();
+    let mut control = None;
+    let mut frame_data = Vec::new();
+    let mut have_idat = false;
+    let mut frames = Vec::new();
+
+    for chunk in try!(read_chunks(data)) {
+        match chunk.kind {
+            chunk::IHDR => ihdr = Some(chunk.data),
+            chunk::acTL | chunk::IEND => {},
+            chunk::fcTL => {
+                if let Some(control) = control.take() {
+                    try!(check_frame(limits, &frames, &control));
+                    frames.push(try!(decode_frame(ihdr, &shared, control, &frame_data)));
+                    frame_data.clear();
+                }
+                control = Some(try!(read_frame_control(chunk.data)));
+            },
+            chunk::IDAT => {
+                // The default image is only part of the animation if a fcTL precedes it
+                if control.is_some() && frames.is_empty() {
+                    frame_data.extend_from_slice(chunk.data);
+                }
+                have_idat = true;
+            },
+            chunk::fdAT => {
+                if control.is_none() || chunk.data.len() < 4 {
+                    return Err(ImageError::malformed(ImageFormat::PNG,
+                        "fdAT chunk without frame control".into()))
+                }
+                frame_data.extend_from_slice(&chunk.data[4..]);
+            },
+            _ if !have_idat => shared.push(chunk.raw),
+            _ => {},
+        }
+    }
+
+    if let Some(control) = control {
+        try!(check_frame(limits, &frames, &control));
+        frames.push(try!(decode_frame(ihdr, &shared, control, &frame_data)));
+    }
+    Ok(frames)
+}
+
+/// Checks the animation against ```limits``` before the frame of ```control``` is added to
+/// ```frames```
+fn check_frame(limits: &Limits, frames: &[Frame], control: &FrameControl) -> ImageResult<()> {
+    try!(limits.check_image(control.width, control.height, ColorType::RGBA(8)));
+    let bytes = control.width as u64 * control.height as u64 * 4;
+    let decoded: u64 = frames.iter().map(|frame| frame.buffer().len() as u64).sum();
+    limits.check_animation(frames.len() + 1, decoded + bytes)
+}
+
+/// The contents of a fcTL chunk
+struct FrameControl {
+    width: u32,
+    height: u32,
+    left: u32,
+    top: u32,
+    delay: Delay,
+    disposal: Disposal,
+    blend: Blend,
+}
+
+fn read_frame_control(data: &[u8]) -> ImageResult<FrameControl> {
+    if data.len() != 26 {
+        return Err(ImageError::malformed(ImageFormat::PNG, "fcTL chunk has an invalid length".into()))
+    }
+    let numerator = BigEndian::read_u16(&data[20..22]);
+    // A denominator of 0 stands for hundredths of a second
+    let denominator = match BigEndian::read_u16(&data[22..24]) {
+        0 => 100,
+        denominator => denominator,
+    };
+    Ok(FrameControl {
+        width: BigEndian::read_u32(&data[4..8]),
+        height: BigEndian::read_u32(&data[8..12]),
+        left: BigEndian::read_u32(&data[12..16]),
+        top: BigEndian::read_u32(&data[16..20]),
+        delay: Delay::from_numer_denom_ms(numerator as u32 * 1000, denominator as u32),
+        disposal: match data[24] {
+            0 => Disposal::None,
+            1 => Disposal::Background,
+            2 => Disposal::Previous,
+            op => return Err(ImageError::malformed(ImageFormat::PNG, format!("invalid dispose op {}", op))),
+        },
+        blend: match data[25] {
+            0 => Blend::Source,
+            1 => Blend::Over,
+            op => return Err(ImageError::malformed(ImageFormat::PNG, format!("invalid blend op {}", op))),
+        },
+    })
+}
+
+/// Decodes the image data of a frame by wrapping it into a png of its own
+fn decode_frame(ihdr: Option<&[u8]>, shared: &[&[u8]], control: FrameControl, data: &[u8]) -> ImageResult<Frame> {
+    let ihdr = match ihdr {
+        Some(ihdr) if ihdr.len() == 13 => ihdr,
+        _ => return Err(ImageError::malformed(ImageFormat::PNG, "IHDR chunk missing".into())),
+    };
+    let (width, height) = (BigEndian::read_u32(&ihdr[..4]), BigEndian::read_u32(&ihdr[4..8]));
+    let fits = |offset: u32, size: u32, max: u32| size > 0 && offset.checked_add(size).map_or(false, |end| end <= max);
+    if !fits(control.left, control.width, width) || !fits(control.top, control.height, height) {
+        return Err(ImageError::malformed(ImageFormat::PNG, "frame is outside of the image".into()))
+    }
+
+    let mut frame_ihdr = ihdr.to_vec();
+    BigEndian::write_u32(&mut frame_ihdr[..4], control.width);
+    BigEndian::write_u32(&mut frame_ihdr[4..8], control.height);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, chunk::IHDR, &frame_ihdr);
+    for raw in shared {
+        png.extend_from_slice(raw);
+    }
+    write_chunk(&mut png, chunk::IDAT, data);
+    write_chunk(&mut png, chunk::IEND, &[]);
+
+    let buffer = try!(decoder_to_image(PNGDecoder::new(&png[..]))).to_rgba();
+    let mut frame = Frame::from_parts(buffer, control.left, control.top, control.delay);
+    frame.set_disposal(control.disposal);
+    frame.set_blend(control.blend);
+    Ok(frame)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: ChunkType, data: &[u8]) {
+    let start = out.len();
+    out.extend_from_slice(&[0; 4]);
+    BigEndian::write_u32(&mut out[start..], data.len() as u32);
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start + 4..]);
+    out.extend_from_slice(&[0; 4]);
+    let end = out.len();
+    BigEndian::write_u32(&mut out[end - 4..], crc);
+}
+
+/// The CRC-32 checksum used by png chunks
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
 }
 
 /// PNG encoder
 pub struct PNGEncoder<W: Write> {
-    w: W
+    w: W,
+    options: PNGEncoderOptions,
+    text: Vec<TextChunk>,
+    exif: Option<Vec<u8>>,
+}
+
+/// Encodes a png row by row, created by `PNGEncoder::into_stream_writer`
+///
+/// Only a few rows are kept in memory. The image is complete once `finish` is called, dropping the
+/// writer before leaves a truncated png.
+pub struct PNGStreamWriter<W: Write> {
+    compressor: Compressor<IdatWriter<W>>,
+    filter: RowFilter,
+    row_len: usize,
+    rows_left: u32,
+}
+
+impl<W: Write> PNGStreamWriter<W> {
+    /// Encodes the next row of the image
+    ///
+    /// ```row``` holds the samples of the row in the color type of the image, packed pixels are
+    /// padded to full bytes and 16-bit samples are stored in big endian order.
+    pub fn write_scanline(&mut self, row: &[u8]) -> io::Result<()> {
+        if self.rows_left == 0 {
+            return Err(invalid_input("All rows of the png have already been written"))
+        }
+        if row.len() != self.row_len {
+            return Err(invalid_input(&format!(
+                "A row of the png has {} bytes, {} were provided", self.row_len, row.len())))
+        }
+        self.rows_left -= 1;
+        self.compressor.write_all(self.filter.filter(row))
+    }
+
+    /// Completes the image once all rows have been written
+    pub fn finish(self) -> io::Result<()> {
+        if self.rows_left > 0 {
+            return Err(invalid_input(&format!("{} rows of the png are missing", self.rows_left)))
+        }
+        let mut idat = try!(self.compressor.finish());
+        // The png writer ends the image when it is dropped
+        idat.write_chunk()
+    }
+}
+
+/// Splits the image data into IDAT chunks of a bounded size
+struct IdatWriter<W: Write> {
+    writer: png::Writer<W>,
+    buffer: Vec<u8>,
+}
+
+/// The size of the IDAT chunks written by a `PNGStreamWriter`
+const IDAT_SIZE: usize = 1 << 15;
+
+impl<W: Write> IdatWriter<W> {
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            try!(self.writer.write_chunk(chunk::IDAT, &self.buffer));
+            self.buffer.clear();
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for IdatWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= IDAT_SIZE {
+            try!(self.write_chunk());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Settings trading the speed of a `PNGEncoder` against the size of its output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PNGEncoderOptions {
+    /// The zlib compression level from 0, storing the data uncompressed, to 9 for the best
+    /// compression. Defaults to 1.
+    pub compression: u8,
+
+    /// How rows are filtered before they are compressed. Defaults to `PNGFilter::Sub`.
+    pub filter: PNGFilter,
+
+    /// Search for the smallest output, ignoring the other settings.
+    ///
+    /// The image is compressed at level 9 with every filter strategy and the smallest result is
+    /// kept, which makes encoding several times slower. Disabled by default.
+    pub optimize: bool,
+}
+
+impl Default for PNGEncoderOptions {
+    fn default() -> PNGEncoderOptions {
+        PNGEncoderOptions {
+            compression: 1,
+            filter: PNGFilter::Sub,
+            optimize: false,
+        }
+    }
+}
+
+/// The filter applied to the rows of a png, making them easier to compress
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PNGFilter {
+    /// Rows are stored unchanged
+    NoFilter,
+    /// Each byte is stored as the difference to the corresponding byte of the pixel to its left
+    Sub,
+    /// Each byte is stored as the difference to the byte above it
+    Up,
+    /// Each byte is stored as the difference to the average of its left and upper neighbours
+    Average,
+    /// Each byte is stored as the difference to the closest of its left, upper and upper left
+    /// neighbours
+    Paeth,
+    /// The filter is chosen per row, picking the one with the smallest sum of absolute differences
+    Adaptive,
 }
 
 impl<W: Write> PNGEncoder<W> {
     /// Create a new encoder that writes its output to ```w```
     pub fn new(w: W) -> PNGEncoder<W> {
+        PNGEncoder::new_with_options(w, PNGEncoderOptions::default())
+    }
+
+    /// Create a new encoder that writes its output to ```w``` using the settings ```options```
+    pub fn new_with_options(w: W, options: PNGEncoderOptions) -> PNGEncoder<W> {
         PNGEncoder {
-            w: w
+            w: w,
+            options: options,
+            text: Vec::new(),
+            exif: None,
         }
     }
 
+    /// Add a text chunk to the encoded image, it is written before the image data.
+    pub fn add_text(&mut self, text: TextChunk) {
+        self.text.push(text)
+    }
+
+    /// Embed the EXIF data ```exif``` in an eXIf chunk, written before the image data.
+    ///
+    /// ```exif``` is the TIFF structure holding the tags, as returned by `Exif::to_bytes`. The
+    /// ```Exif\0\0``` identifier of JPEG segments is removed if it precedes it.
+    pub fn set_exif(&mut self, exif: Vec<u8>) {
+        self.exif = Some(if exif.starts_with(b"Exif\0\0") { exif[6..].to_vec() } else { exif });
+    }
+
     /// Encodes the image ```image```
     /// that has dimensions ```width``` and ```height```
     /// and ```ColorType``` ```c```
+    ///
+    /// 16-bit samples are expected to be stored in big endian order, as they are in the file.
     pub fn encode(self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        try!(check_color(color));
+        let image_data = try!(image_data(data, width, height, color, &self.options));
+        let mut writer = try!(self.write_header(width, height, color));
+        writer.write_chunk(chunk::IDAT, &image_data).map_err(|e| e.into())
+    }
+
+    /// Encodes the image ```data``` as an indexed png of at most 256 colors
+    ///
+    /// Images with few enough colors are stored exactly, others are quantized with the NeuQuant
+    /// algorithm, optionally applying Floyd-Steinberg ```dither```ing. Transparent colors are
+    /// kept in a tRNS chunk. Only 8-bit images are supported.
+    pub fn encode_quantized(self, data: &[u8], width: u32, height: u32, color: ColorType, dither: bool)
+        -> io::Result<()> {
+        let channels = match color {
+            ColorType::Gray(8) | ColorType::GrayA(8) | ColorType::RGB(8) | ColorType::RGBA(8) =>
+                color::num_components(color),
+            _ => return Err(invalid_input(&format!("PNG: Can not quantize images of color type {:?}", color))),
+        };
+        let pixels = width as usize * height as usize;
+        if pixels == 0 || pixels.checked_mul(channels).map_or(true, |len| data.len() < len) {
+            return Err(invalid_input("not enough image data provided"))
+        }
+        let rgba: Vec<u8> = data[..pixels * channels].chunks(channels).flat_map(|pixel| match channels {
+            1 => vec![pixel[0], pixel[0], pixel[0], 255],
+            2 => vec![pixel[0], pixel[0], pixel[0], pixel[1]],
+            3 => vec![pixel[0], pixel[1], pixel[2], 255],
+            _ => pixel.to_vec(),
+        }).collect();
+
+        let (palette, indices) = quantize(rgba, width, height, dither);
+        let bits = match palette.len() {
+            0...2 => 1,
+            3...4 => 2,
+            5...16 => 4,
+            _ => 8,
+        };
+        let row_len = (width as usize * bits + 7) / 8;
+        let mut packed = vec![0; row_len * height as usize];
+        for (row, out) in indices.chunks(width as usize).zip(packed.chunks_mut(row_len)) {
+            for (x, &index) in row.iter().enumerate() {
+                out[x * bits / 8] |= index << (8 - bits - x * bits % 8);
+            }
+        }
+
+        let color = ColorType::Palette(bits as u8);
+        let image_data = try!(image_data(&packed, width, height, color, &self.options));
+        let mut writer = try!(self.write_header(width, height, color));
+        let plte: Vec<u8> = palette.iter().flat_map(|entry| entry[..3].to_vec()).collect();
+        try!(writer.write_chunk(chunk::PLTE, &plte));
+        // Transparent colors come first, the opaque ones can be left out
+        let trns: Vec<u8> = palette.iter().map(|entry| entry[3]).take_while(|&alpha| alpha < 255).collect();
+        if !trns.is_empty() {
+            try!(writer.write_chunk(chunk::tRNS, &trns));
+        }
+        writer.write_chunk(chunk::IDAT, &image_data).map_err(|e| e.into())
+    }
+
+    /// Writes the IHDR chunk followed by the text chunks
+    fn write_header(self, width: u32, height: u32, color: ColorType) -> io::Result<png::Writer<W>> {
         let (ct, bits) = color.into();
         let mut encoder = png::Encoder::new(self.w, width, height);
         encoder.set(ct).set(bits);
         let mut writer = try!(encoder.write_header());
-        writer.write_image_data(data).map_err(|e| e.into())
+        for text in &self.text {
+            let (kind, data) = try!(text.encode());
+            try!(writer.write_chunk(kind, &data));
+        }
+        if let Some(ref exif) = self.exif {
+            try!(writer.write_chunk(EXIF, exif));
+        }
+        Ok(writer)
     }
-}
 
-impl From<(png::ColorType, png::BitDepth)> for ColorType {
-    fn from((ct, bits): (png::ColorType, png::BitDepth)) -> ColorType {
-        use self::png::ColorType::*;
-        let bits = bits as u8;
+    /// Starts encoding an image row by row, for images too large to be held in memory at once
+    ///
+    /// The header and text chunks are written immediately, the rows are passed to
+    /// `write_scanline` of the returned writer. As the rows are compressed as they arrive the
+    /// `optimize` setting can not be applied, adaptive filtering at level 9 is used instead.
+    pub fn into_stream_writer(self, width: u32, height: u32, color: ColorType)
+        -> io::Result<PNGStreamWriter<W>> {
+        try!(check_color(color));
+        if self.options.compression > 9 && !self.options.optimize {
+            return Err(invalid_input("The png compression level must be between 0 and 9"))
+        }
+        let bits = bits_per_pixel(color);
+        let row_len = (width as u64 * bits as u64 + 7) / 8;
+        if row_len == 0 || height == 0 || row_len > usize::max_value() as u64 - 1 {
+            return Err(invalid_input("Invalid png image dimensions"))
+        }
+
+        let (filter, level) = if self.options.optimize {
+            (PNGFilter::Adaptive, 9)
+        } else {
+            (self.options.filter, self.options.compression)
+        };
+        let writer = try!(self.write_header(width, height, color));
+        let idat = IdatWriter {
+            writer: writer,
+            buffer: Vec::new(),
+        };
+        Ok(PNGStreamWriter {
+            compressor: Compressor::new(idat, level),
+            filter: RowFilter::new(filter, row_len as usize, (bits + 7) / 8),
+            row_len: row_len as usize,
+            rows_left: height,
+        })
+    }
+
+    /// Encodes ```frames``` as an animated PNG, played ```loop_count``` times
+    ///
+    /// The size of the animation is that of the first frame, which must not have an offset. All
+    /// later frames have to fit within it. The first frame is also the default image shown by
+    /// decoders without support for animations.
+    pub fn encode_frames<I>(mut self, frames: I, loop_count: LoopCount) -> io::Result<()>
+    where I: IntoIterator<Item = Frame> {
+        let frames: Vec<Frame> = frames.into_iter().collect();
+        let (width, height) = match frames.first() {
+            Some(first) if first.left() == 0 && first.top() == 0 => first.buffer().dimensions(),
+            Some(_) => return Err(invalid_input("The first APNG frame must not have an offset")),
+            None => return Err(invalid_input("An APNG needs at least one frame")),
+        };
+        let num_plays = match loop_count {
+            LoopCount::Infinite => 0,
+            LoopCount::Finite(0) => return Err(invalid_input("An APNG must be played at least once")),
+            LoopCount::Finite(n) => n,
+        };
+
+        let mut ihdr = vec![0; 13];
+        BigEndian::write_u32(&mut ihdr[..4], width);
+        BigEndian::write_u32(&mut ihdr[4..8], height);
+        ihdr[8] = 8; // Bit depth
+        ihdr[9] = png::ColorType::RGBA as u8;
+
+        let mut actl = vec![0; 8];
+        BigEndian::write_u32(&mut actl[..4], frames.len() as u32);
+        BigEndian::write_u32(&mut actl[4..], num_plays);
+
+        let mut out = SIGNATURE.to_vec();
+        write_chunk(&mut out, chunk::IHDR, &ihdr);
+        write_chunk(&mut out, chunk::acTL, &actl);
+        for text in &self.text {
+            let (kind, data) = try!(text.encode());
+            write_chunk(&mut out, kind, &data);
+        }
+        if let Some(ref exif) = self.exif {
+            write_chunk(&mut out, EXIF, exif);
+        }
+
+        // fcTL and fdAT chunks share their sequence numbers
+        let mut sequence = 0;
+        for (i, frame) in frames.iter().enumerate() {
+            let (frame_width, frame_height) = frame.buffer().dimensions();
+            let fits = |offset: u32, size: u32, max: u32| offset.checked_add(size).map_or(false, |end| end <= max);
+            if !fits(frame.left(), frame_width, width) || !fits(frame.top(), frame_height, height) {
+                return Err(invalid_input("APNG frame is outside of the first frame"))
+            }
+
+            let mut fctl = vec![0; 26];
+            for (field, &value) in [sequence, frame_width, frame_height, frame.left(), frame.top()].iter().enumerate() {
+                BigEndian::write_u32(&mut fctl[field * 4..], value);
+            }
+            let (numerator, denominator) = apng_delay(frame.delay());
+            BigEndian::write_u16(&mut fctl[20..], numerator);
+            BigEndian::write_u16(&mut fctl[22..], denominator);
+            fctl[24] = match frame.disposal() {
+                Disposal::None => 0,
+                Disposal::Background => 1,
+                Disposal::Previous => 2,
+            };
+            fctl[25] = match frame.blend() {
+                Blend::Source => 0,
+                Blend::Over => 1,
+            };
+            write_chunk(&mut out, chunk::fcTL, &fctl);
+            sequence += 1;
+
+            let data = try!(image_data(frame.buffer(), frame_width, frame_height, ColorType::RGBA(8), &self.options));
+            if i == 0 {
+                write_chunk(&mut out, chunk::IDAT, &data);
+            } else {
+                let mut fdat = vec![0; 4];
+                BigEndian::write_u32(&mut fdat, sequence);
+                fdat.extend(data);
+                write_chunk(&mut out, chunk::fdAT, &fdat);
+                sequence += 1;
+            }
+        }
+        write_chunk(&mut out, chunk::IEND, &[]);
+
+        self.w.write_all(&out)
+    }
+}
+
+impl<W: Write> ImageEncoder for PNGEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+
+    /// Encodes the image, reporting each compressed row to ```monitor```
+    ///
+    /// With the `optimize` setting the image is compressed once per filter, each pass counts
+    /// as many units as the image has rows.
+    fn write_image_with_progress(self, buf: &[u8], width: u32, height: u32, color: ColorType,
+                                 monitor: &mut Monitor) -> ImageResult<()> {
+        try!(check_color(color));
+        let image_data = try!(image_data_with_progress(buf, width, height, color, &self.options, monitor));
+        let mut writer = try!(self.write_header(width, height, color));
+        writer.write_chunk(chunk::IDAT, &image_data).map_err(|e| ImageError::IoError(e.into()))
+    }
+}
+
+/// Converts ```delay``` to the 16-bit numerator and denominator of seconds of a fcTL chunk
+///
+/// Delays that can not be stored exactly are rounded to milliseconds, or to seconds if they are
+/// longer than a minute.
+fn apng_delay(delay: Delay) -> (u16, u16) {
+    let (numer, denom) = delay.numer_denom_ms();
+    let seconds = Ratio::new(numer as u64, denom as u64 * 1000);
+    let max = u16::max_value() as u64;
+    if *seconds.numer() <= max && *seconds.denom() <= max {
+        return (*seconds.numer() as u16, *seconds.denom() as u16)
+    }
+    let millis = (numer as u64 * 2 + denom as u64) / (denom as u64 * 2);
+    if millis <= max {
+        (millis as u16, 1000)
+    } else {
+        (((millis + 500) / 1000).min(max) as u16, 1)
+    }
+}
+
+/// The number of bits of a pixel as stored in a png, where indexed pixels are a single sample
+fn bits_per_pixel(color: ColorType) -> usize {
+    match color {
+        ColorType::Palette(bits) => bits as usize,
+        color => color::bits_per_pixel(color),
+    }
+}
+
+/// Reduces the rgba pixels ```rgba``` to at most 256 colors
+///
+/// Returns the palette, with the transparent colors first, and the index of each pixel.
+fn quantize(rgba: Vec<u8>, width: u32, height: u32, dither: bool) -> (Vec<[u8; 4]>, Vec<u8>) {
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    {
+        let mut known = HashMap::new();
+        for pixel in rgba.chunks(4) {
+            let color = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            let next = known.len();
+            let index = *known.entry(color).or_insert(next);
+            if index == next {
+                palette.push(color);
+                if palette.len() > 256 {
+                    break
+                }
+            }
+            indices.push(index as u8);
+        }
+    }
+
+    if palette.len() > 256 {
+        let opaque = rgba.chunks(4).all(|pixel| pixel[3] == 255);
+        let quantizer = nq::NeuQuant::new(10, 256, &rgba);
+        let mut image = RgbaImage::from_raw(width, height, rgba).unwrap();
+        // Dithering spreads the error of a pixel to its right and lower neighbours
+        if dither && width > 1 {
+            imageops::dither(&mut image, &quantizer);
+        }
+        indices = image.pixels().map(|pixel| quantizer.index_of(&pixel.data) as u8).collect();
+        // Entries of the map the image did not train may still be transparent
+        palette = quantizer.color_map_rgba().chunks(4)
+            .map(|c| [c[0], c[1], c[2], if opaque { 255 } else { c[3] }]).collect();
+    }
+
+    let mut order: Vec<usize> = (0..palette.len()).collect();
+    order.sort_by_key(|&i| palette[i][3] == 255);
+    let mut new_index = vec![0; palette.len()];
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new as u8;
+    }
+    let palette = order.iter().map(|&i| palette[i]).collect();
+    let indices = indices.iter().map(|&i| new_index[i as usize]).collect();
+    (palette, indices)
+}
+
+/// Checks that ```color``` is one of the combinations of channels and bit depth png supports
+fn check_color(color: ColorType) -> io::Result<()> {
+    match color {
+        ColorType::Gray(1) | ColorType::Gray(2) | ColorType::Gray(4) | ColorType::Gray(8) | ColorType::Gray(16) |
+        ColorType::Palette(1) | ColorType::Palette(2) | ColorType::Palette(4) | ColorType::Palette(8) |
+        ColorType::GrayA(8) | ColorType::GrayA(16) |
+        ColorType::RGB(8) | ColorType::RGB(16) |
+        ColorType::RGBA(8) | ColorType::RGBA(16) => Ok(()),
+        _ => Err(invalid_input(&format!("PNG: Can not encode images of color type {:?}", color))),
+    }
+}
+
+/// Filters and compresses ```data```, giving the content of the IDAT chunk of the image
+fn image_data(data: &[u8], width: u32, height: u32, color: ColorType, options: &PNGEncoderOptions)
+    -> io::Result<Vec<u8>> {
+    image_data_with_progress(data, width, height, color, options, &mut Monitor::new()).map_err(|err| match err {
+        ImageError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::Other, err),
+    })
+}
+
+/// Filters and compresses the image ```data```, reporting each compressed row to ```monitor```
+fn image_data_with_progress(data: &[u8], width: u32, height: u32, color: ColorType,
+                            options: &PNGEncoderOptions, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
+    let bits = bits_per_pixel(color);
+    let row_len = ((width as u64 * bits as u64 + 7) / 8) as usize;
+    match row_len.checked_mul(height as usize) {
+        Some(len) if len > 0 && data.len() >= len => {},
+        _ => return Err(invalid_input("not enough image data provided").into()),
+    }
+    // Filters work on whole bytes, bit packed pixels are compared with the previous byte
+    let bpp = ((bits + 7) / 8) as usize;
+    let rows = data[..row_len * height as usize].chunks(row_len);
+    let height = height as u64;
+
+    if options.optimize {
+        let filters = [PNGFilter::NoFilter, PNGFilter::Sub, PNGFilter::Up, PNGFilter::Average,
+                       PNGFilter::Paeth, PNGFilter::Adaptive];
+        let total = filters.len() as u64 * height;
+        let mut best: Option<Vec<u8>> = None;
+        for (pass, &filter) in filters.iter().enumerate() {
+            let result = compress(&filter_rows(rows.clone(), row_len, bpp, filter), 9);
+            if best.as_ref().map_or(true, |best| result.len() < best.len()) {
+                best = Some(result);
+            }
+            try!(monitor.update((pass as u64 + 1) * height, total));
+        }
+        return Ok(best.unwrap())
+    }
+    if options.compression > 9 {
+        return Err(invalid_input("The png compression level must be between 0 and 9").into())
+    }
+    let mut filter = RowFilter::new(options.filter, row_len, bpp);
+    let mut compressor = Compressor::new(Vec::new(), options.compression);
+    try!(monitor.update(0, height));
+    for (y, row) in rows.enumerate() {
+        // Writing to a vector can not fail
+        compressor.write_all(filter.filter(row)).unwrap();
+        try!(monitor.update(y as u64 + 1, height));
+    }
+    Ok(compressor.finish().unwrap())
+}
+
+/// Prefixes each row with the type of its filter and applies it
+fn filter_rows<'a, I>(rows: I, row_len: usize, bpp: usize, filter: PNGFilter) -> Vec<u8>
+where I: Iterator<Item = &'a [u8]> {
+    let mut filter = RowFilter::new(filter, row_len, bpp);
+    let mut out = Vec::new();
+    for row in rows {
+        out.extend_from_slice(filter.filter(row));
+    }
+    out
+}
+
+/// Filters the rows of an image one after another
+struct RowFilter {
+    candidates: &'static [u8],
+    bpp: usize,
+    previous: Vec<u8>,
+    filtered: Vec<u8>,
+    /// The filter type followed by the row filtered with it
+    best: Vec<u8>,
+}
+
+impl RowFilter {
+    fn new(filter: PNGFilter, row_len: usize, bpp: usize) -> RowFilter {
+        RowFilter {
+            candidates: match filter {
+                PNGFilter::NoFilter => &[0],
+                PNGFilter::Sub => &[1],
+                PNGFilter::Up => &[2],
+                PNGFilter::Average => &[3],
+                PNGFilter::Paeth => &[4],
+                PNGFilter::Adaptive => &[0, 1, 2, 3, 4],
+            },
+            bpp: bpp,
+            previous: vec![0; row_len],
+            filtered: vec![0; row_len],
+            best: vec![0; row_len + 1],
+        }
+    }
+
+    /// Filters the row following the previous one, returning it prefixed with its filter type
+    fn filter(&mut self, row: &[u8]) -> &[u8] {
+        let mut best_cost = u64::max_value();
+        for &filter_type in self.candidates {
+            filter_row(filter_type, self.bpp, &self.previous, row, &mut self.filtered);
+            // The filtered bytes are interpreted as signed, small values compress best
+            let cost = if self.candidates.len() > 1 {
+                self.filtered.iter().map(|&b| (b as i8 as i64).abs() as u64).sum()
+            } else {
+                0
+            };
+            if cost < best_cost {
+                best_cost = cost;
+                self.best[0] = filter_type;
+                self.best[1..].copy_from_slice(&self.filtered);
+            }
+        }
+        self.previous.copy_from_slice(row);
+        &self.best
+    }
+}
+
+fn filter_row(filter_type: u8, bpp: usize, previous: &[u8], row: &[u8], out: &mut [u8]) {
+    for i in 0..row.len() {
+        let left = if i >= bpp { row[i - bpp] } else { 0 };
+        let up = previous[i];
+        let up_left = if i >= bpp { previous[i - bpp] } else { 0 };
+        let prediction = match filter_type {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            _ => paeth(left, up, up_left),
+        };
+        out[i] = row[i].wrapping_sub(prediction);
+    }
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let (to_left, to_up, to_up_left) = ((estimate - left as i16).abs(), (estimate - up as i16).abs(),
+                                        (estimate - up_left as i16).abs());
+    if to_left <= to_up && to_left <= to_up_left {
+        left
+    } else if to_up <= to_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Compresses ```data``` into a zlib stream at the given level
+fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let mut compressor = Compressor::new(Vec::new(), level);
+    // Writing to a vector can not fail
+    compressor.write_all(data).unwrap();
+    compressor.finish().unwrap()
+}
+
+/// Wraps ```data``` into a zlib stream of uncompressed blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    compress(data, 0)
+}
+
+/// Writes a zlib stream compressed at one of the levels from 0 to 9
+enum Compressor<W: Write> {
+    Stored(StoredEncoder<W>),
+    Deflate(deflate::write::ZlibEncoder<W>),
+}
+
+impl<W: Write> Compressor<W> {
+    fn new(w: W, level: u8) -> Compressor<W> {
+        use self::deflate::{CompressionOptions, MatchingType};
+
+        let (max_hash_checks, lazy_if_less_than, matching_type) = match level {
+            0 => return Compressor::Stored(StoredEncoder::new(w)),
+            1 => (1, 0, MatchingType::Greedy),
+            2 => (4, 0, MatchingType::Greedy),
+            3 => (8, 0, MatchingType::Greedy),
+            4 => (16, 16, MatchingType::Lazy),
+            5 => (32, 32, MatchingType::Lazy),
+            6 => (128, 32, MatchingType::Lazy),
+            7 => (256, 64, MatchingType::Lazy),
+            8 => (1024, 128, MatchingType::Lazy),
+            _ => (1768, 128, MatchingType::Lazy),
+        };
+        let options = CompressionOptions {
+            max_hash_checks: max_hash_checks,
+            lazy_if_less_than: lazy_if_less_than,
+            matching_type: matching_type,
+            ..CompressionOptions::default()
+        };
+        Compressor::Deflate(deflate::write::ZlibEncoder::new(w, options))
+    }
+
+    /// Ends the stream, returning the writer
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Compressor::Stored(encoder) => encoder.finish(),
+            Compressor::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Compressor::Stored(ref mut encoder) => encoder.write(buf),
+            Compressor::Deflate(ref mut encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Compressor::Stored(ref mut encoder) => encoder.flush(),
+            Compressor::Deflate(ref mut encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Writes a zlib stream of uncompressed blocks
+struct StoredEncoder<W: Write> {
+    w: W,
+    started: bool,
+    /// The data of the block not written yet
+    block: Vec<u8>,
+    /// The two sums of the adler32 checksum
+    adler: (u32, u32),
+}
+
+/// The largest amount of data a stored block can hold
+const MAX_STORED_BLOCK: usize = 0xffff;
+
+impl<W: Write> StoredEncoder<W> {
+    fn new(w: W) -> StoredEncoder<W> {
+        StoredEncoder {
+            w: w,
+            started: false,
+            block: Vec::new(),
+            adler: (1, 0),
+        }
+    }
+
+    fn write_block(&mut self, len: usize, last: bool) -> io::Result<()> {
+        if !self.started {
+            try!(self.w.write_all(&[0x78, 0x01]));
+            self.started = true;
+        }
+        let size = len as u16;
+        try!(self.w.write_all(&[last as u8, size as u8, (size >> 8) as u8, !size as u8, !(size >> 8) as u8]));
+        try!(self.w.write_all(&self.block[..len]));
+        self.block.drain(..len);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<W> {
+        while self.block.len() > MAX_STORED_BLOCK {
+            try!(self.write_block(MAX_STORED_BLOCK, false));
+        }
+        let len = self.block.len();
+        try!(self.write_block(len, true));
+        let (a, b) = self.adler;
+        let mut adler = [0; 4];
+        BigEndian::write_u32(&mut adler, (b << 16) | a);
+        try!(self.w.write_all(&adler));
+        Ok(self.w)
+    }
+}
+
+impl<W: Write> Write for StoredEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (mut a, mut b) = self.adler;
+        for &byte in buf {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        self.adler = (a, b);
+        self.block.extend_from_slice(buf);
+        // A full block is kept back, as the last block has to be marked
+        while self.block.len() > MAX_STORED_BLOCK {
+            try!(self.write_block(MAX_STORED_BLOCK, false));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl From<(png::ColorType, png::BitDepth)> for ColorType {
+    fn from((ct, bits): (png::ColorType, png::BitDepth)) -> ColorType {
+        use self::png::ColorType::*;
+        let bits = bits as u8;
         match ct {
             Grayscale => ColorType::Gray(bits),
             RGB => ColorType::RGB(bits),
@@ -134,6 +1830,9 @@ impl From<ColorType> for (png::ColorType, png::BitDepth) {
             ColorType::Palette(bits) => (Indexed, bits),
             ColorType::GrayA(bits) => (GrayscaleAlpha, bits),
             ColorType::RGBA(bits) => (RGBA, bits),
+            ColorType::GrayF(_) | ColorType::GrayAF(_) | ColorType::RGBF(_) | ColorType::RGBAF(_) =>
+                panic!("PNG has no floating point color types"),
+            ColorType::BGR(_) | ColorType::BGRA(_) => panic!("PNG stores colors in RGB order"),
         };
         (ct, png::BitDepth::from_u8(bits).unwrap())
     }
@@ -144,11 +1843,706 @@ impl From<png::DecodingError> for ImageError {
         use self::png::DecodingError::*;
         match err {
             IoError(err) => ImageError::IoError(err),
-            Format(desc) => ImageError::FormatError(desc.into_owned()),
-            InvalidSignature => ImageError::FormatError("invalid signature".into()),
-            CrcMismatch { .. } => ImageError::FormatError("CRC error".into()),
-            Other(desc) => ImageError::FormatError(desc.into_owned()),
-            CorruptFlateStream => ImageError::FormatError("compressed data stream corrupted".into())
+            Format(desc) => ImageError::malformed(ImageFormat::PNG, desc.into_owned()),
+            InvalidSignature => ImageError::malformed(ImageFormat::PNG, "invalid signature".into()),
+            CrcMismatch { .. } => ImageError::malformed(ImageFormat::PNG, "CRC error".into()),
+            Other(desc) => ImageError::malformed(ImageFormat::PNG, desc.into_owned()),
+            CorruptFlateStream => ImageError::malformed(ImageFormat::PNG,
+                "compressed data stream corrupted".into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use color::Rgba;
+    use monitor::CancelToken;
+
+    fn frame_control(sequence: u32, width: u32, height: u32, left: u32, top: u32,
+                     delay: (u16, u16), ops: (u8, u8)) -> Vec<u8> {
+        let mut data = vec![0; 26];
+        for (i, &v) in [sequence, width, height, left, top].iter().enumerate() {
+            BigEndian::write_u32(&mut data[i * 4..], v);
+        }
+        BigEndian::write_u16(&mut data[20..], delay.0);
+        BigEndian::write_u16(&mut data[22..], delay.1);
+        data[24] = ops.0;
+        data[25] = ops.1;
+        data
+    }
+
+    /// A 2x2 APNG, optionally with its red default image as the first frame, followed by a
+    /// single blue pixel at (1, 1)
+    fn animation(default_is_frame: bool) -> Vec<u8> {
+        let red = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(1, 1, Rgba([0, 0, 255, 128]));
+
+        let mut ihdr = vec![0; 13];
+        BigEndian::write_u32(&mut ihdr[..4], 2);
+        BigEndian::write_u32(&mut ihdr[4..8], 2);
+        ihdr[8] = 8;
+        ihdr[9] = 6;
+
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, chunk::IHDR, &ihdr);
+        write_chunk(&mut png, chunk::acTL, &[0, 0, 0, if default_is_frame { 2 } else { 1 }, 0, 0, 0, 0]);
+        let mut sequence = 0;
+        if default_is_frame {
+            write_chunk(&mut png, chunk::fcTL, &frame_control(0, 2, 2, 0, 0, (1, 0), (0, 0)));
+            sequence += 1;
+        }
+        write_chunk(&mut png, chunk::IDAT, &image_data(&red, 2, 2, ColorType::RGBA(8), &PNGEncoderOptions::default()).unwrap());
+        write_chunk(&mut png, chunk::fcTL, &frame_control(sequence, 1, 1, 1, 1, (1, 10), (1, 1)));
+        let mut fdat = vec![0, 0, 0, sequence as u8 + 1];
+        fdat.extend(image_data(&blue, 1, 1, ColorType::RGBA(8), &PNGEncoderOptions::default()).unwrap());
+        write_chunk(&mut png, chunk::fdAT, &fdat);
+        write_chunk(&mut png, chunk::IEND, &[]);
+        png
+    }
+
+    #[test]
+    fn apng_frames() {
+        let mut decoder = PNGDecoder::new(Cursor::new(animation(true)));
+        assert_eq!(decoder.dimensions().unwrap(), (2, 2));
+        assert!(decoder.is_animated().unwrap());
+
+        let frames: Vec<Frame> = decoder.into_frames().unwrap().collect();
+        assert_eq!(frames.len(), 2);
+
+        assert_eq!(frames[0].buffer().dimensions(), (2, 2));
+        assert_eq!(frames[0].buffer().get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(10, 1));
+        assert_eq!((frames[0].disposal(), frames[0].blend()), (Disposal::None, Blend::Source));
+
+        assert_eq!(frames[1].buffer().dimensions(), (1, 1));
+        assert_eq!(frames[1].buffer().get_pixel(0, 0), &Rgba([0, 0, 255, 128]));
+        assert_eq!((frames[1].left(), frames[1].top()), (1, 1));
+        assert_eq!(frames[1].delay(), Delay::from_numer_denom_ms(100, 1));
+        assert_eq!((frames[1].disposal(), frames[1].blend()), (Disposal::Background, Blend::Over));
+    }
+
+    #[test]
+    fn apng_limits() {
+        let frames = |limits: Limits| PNGDecoder::new(Cursor::new(animation(true))).into_frames_with_limits(&limits);
+        // The frames hold 16 and 4 bytes
+        assert_eq!(frames(Limits { max_frames: Some(2), max_bytes: Some(20), ..Limits::no_limits() }).unwrap().count(), 2);
+        for &limits in &[Limits { max_frames: Some(1), ..Limits::no_limits() },
+                         Limits { max_bytes: Some(19), ..Limits::no_limits() },
+                         Limits { max_width: Some(1), ..Limits::no_limits() }] {
+            match frames(limits) {
+                Err(ImageError::Limits(_)) => {},
+                _ => panic!("Expected {:?} to be exceeded", limits),
+            }
+        }
+    }
+
+    #[test]
+    fn apng_default_image() {
+        // The default image is not part of the animation without a fcTL before it
+        let frames: Vec<Frame> = PNGDecoder::new(Cursor::new(animation(false))).into_frames().unwrap().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].buffer().get_pixel(0, 0), &Rgba([0, 0, 255, 128]));
+
+        // It is what read_image returns either way
+        let mut decoder = PNGDecoder::new(Cursor::new(animation(false)));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(&data[..4], &[255, 0, 0, 255]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+        assert!(decoder.is_animated().unwrap());
+        assert!(decoder.into_frames().is_err());
+    }
+
+    #[test]
+    fn metadata() {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode(&[7, 9], 1, 1, ColorType::GrayA(8)).unwrap();
+        // pHYs at 3780 pixels per meter and an EXIF orientation of 6, following IHDR
+        let mut chunks = Vec::new();
+        write_chunk(&mut chunks, PHYS, &[0, 0, 14, 196, 0, 0, 14, 196, 1]);
+        write_chunk(&mut chunks, EXIF, b"MM\0*\0\0\0\x08\0\x01\x01\x12\0\x03\0\0\0\x01\0\x06\0\0\0\0\0\0");
+        let rest = png.split_off(8 + 25);
+        png.extend(chunks);
+        png.extend(rest);
+
+        let metadata = PNGDecoder::new(Cursor::new(&png[..])).metadata().unwrap();
+        assert_eq!(metadata.dimensions, (1, 1));
+        assert_eq!(metadata.bit_depth, 8);
+        let (x, y) = metadata.dpi.unwrap();
+        assert!((x - 96.012).abs() < 1e-3 && x == y);
+        assert_eq!((metadata.orientation, metadata.frame_count), (Some(6), Some(1)));
+        assert!(!metadata.has_icc_profile);
+
+        // Reading the metadata of an animation does not prevent decoding its frames
+        let mut decoder = PNGDecoder::new(Cursor::new(animation(true)));
+        let metadata = decoder.metadata().unwrap();
+        assert_eq!((metadata.dpi, metadata.orientation, metadata.frame_count), (None, None, Some(2)));
+        assert_eq!(decoder.into_frames().unwrap().count(), 2);
+    }
+
+    #[test]
+    fn exif() {
+        let mut exif = Exif::new();
+        exif.set_orientation(8);
+        let mut png = Vec::new();
+        {
+            let mut encoder = PNGEncoder::new(&mut png);
+            encoder.set_exif(exif.to_bytes());
+            encoder.encode(&[7, 9], 1, 1, ColorType::GrayA(8)).unwrap();
+        }
+        let mut decoder = PNGDecoder::new(Cursor::new(&png[..]));
+        assert_eq!(decoder.exif().unwrap(), Some(exif));
+        assert_eq!(decoder.metadata().unwrap().orientation, Some(8));
+        assert_eq!(PNGDecoder::new(Cursor::new(animation(true))).exif().unwrap(), None);
+    }
+
+    #[test]
+    fn apng_delays() {
+        assert_eq!(apng_delay(Delay::from_numer_denom_ms(40, 1)), (1, 25));
+        assert_eq!(apng_delay(Delay::from_numer_denom_ms(1000, 3)), (1, 3));
+        // Rounded to milliseconds, or to seconds when those do not fit
+        assert_eq!(apng_delay(Delay::from_numer_denom_ms(1, 70001)), (0, 1000));
+        assert_eq!(apng_delay(Delay::from_numer_denom_ms(200001, 3)), (67, 1));
+        assert_eq!(apng_delay(Delay::from_numer_denom_ms(u32::max_value(), 1)), (65535, 1));
+    }
+
+    #[test]
+    fn still_image_frames() {
+        let image = RgbaImage::from_pixel(3, 1, Rgba([1, 2, 3, 4]));
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode(&image, 3, 1, ColorType::RGBA(8)).unwrap();
+
+        let mut decoder = PNGDecoder::new(Cursor::new(png));
+        assert!(!decoder.is_animated().unwrap());
+        let frames: Vec<Frame> = decoder.into_frames().unwrap().collect();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].buffer().clone().into_raw(), image.into_raw());
+    }
+
+    #[test]
+    fn apng_roundtrip() {
+        let mut second = Frame::from_parts(RgbaImage::from_pixel(1, 2, Rgba([0, 255, 0, 255])), 2, 0, Delay::from_numer_denom_ms(40, 1));
+        second.set_disposal(Disposal::Previous);
+        second.set_blend(Blend::Over);
+        let frames = vec![
+            Frame::from_parts(RgbaImage::from_pixel(3, 2, Rgba([9, 8, 7, 6])), 0, 0, Delay::from_numer_denom_ms(500, 1)),
+            second,
+        ];
+
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode_frames(frames.clone(), LoopCount::Finite(3)).unwrap();
+
+        let mut decoder = PNGDecoder::new(Cursor::new(&png[..]));
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        assert_eq!(decoder.get_header().unwrap().animation.map(|a| (a.num_frames, a.num_plays)), Some((2, 3)));
+        assert_eq!(decoder.loop_count().unwrap(), LoopCount::Finite(3));
+        let decoded: Vec<Frame> = decoder.into_frames().unwrap().collect();
+        assert_eq!(decoded.len(), 2);
+        for (original, decoded) in frames.into_iter().zip(decoded) {
+            assert_eq!((original.left(), original.top()), (decoded.left(), decoded.top()));
+            assert_eq!(original.delay(), decoded.delay());
+            assert_eq!((original.disposal(), original.blend()), (decoded.disposal(), decoded.blend()));
+            assert_eq!(original.into_buffer().into_raw(), decoded.into_buffer().into_raw());
+        }
+
+        // The first frame is the default image
+        match PNGDecoder::new(Cursor::new(&png[..])).read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(&data[..4], &[9, 8, 7, 6]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+    }
+
+    #[test]
+    fn apng_invalid_input() {
+        let frame = |left: u32, size: u32| Frame::from_parts(
+            RgbaImage::new(size, size), left, 0, Delay::from_numer_denom_ms(0, 1));
+        let encode = |frames: Vec<Frame>, loop_count: LoopCount| PNGEncoder::new(Vec::new()).encode_frames(frames, loop_count);
+
+        assert!(encode(vec![frame(0, 2), frame(1, 1)], LoopCount::Infinite).is_ok());
+        assert!(encode(vec![], LoopCount::Infinite).is_err());
+        assert!(encode(vec![frame(1, 2)], LoopCount::Infinite).is_err());
+        assert!(encode(vec![frame(0, 2), frame(1, 2)], LoopCount::Infinite).is_err());
+        assert!(encode(vec![frame(0, 2)], LoopCount::Finite(0)).is_err());
+    }
+
+    #[test]
+    fn progressive_passes() {
+        use std::fs::File;
+        let path = "tests/images/png/interlaced/lenna_fragment_interlaced.png";
+
+        let mut decoder = PNGDecoder::new(File::open(path).unwrap());
+        let (width, height) = decoder.dimensions().unwrap();
+        let bpp = decoder.row_len().unwrap() / width as usize;
+        let mut passes = Vec::new();
+        let result = decoder.read_image_progressively(|pass, image| passes.push((pass, image.to_vec())));
+        let final_image = match result.unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+        assert_eq!(passes.iter().map(|&(pass, _)| pass).collect::<Vec<_>>(), vec![1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(passes[6].1, final_image);
+
+        match PNGDecoder::new(File::open(path).unwrap()).read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, final_image),
+            _ => panic!("Expected 8-bit samples"),
+        }
+
+        // After the first pass every 8x8 block holds the color of its top left pixel
+        let first = &passes[0].1;
+        let pixel = |x: u32, y: u32| {
+            let start = (y * width + x) as usize * bpp;
+            &first[start..start + bpp]
+        };
+        assert!(width >= 8 && height >= 8);
+        assert_eq!(pixel(7, 7), pixel(0, 0));
+        assert_eq!(pixel(3, 5), pixel(0, 0));
+    }
+
+    #[test]
+    fn progressive_truncated() {
+        use std::fs::File;
+        let mut data = Vec::new();
+        File::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap().read_to_end(&mut data).unwrap();
+        let len = data.len();
+
+        // Cut in the middle of the second of its two IDAT chunks
+        let mut passes = Vec::new();
+        let mut decoder = PNGDecoder::new(&data[..len - 700]);
+        assert!(decoder.read_image_progressively(|pass, _| passes.push(pass)).is_err());
+        assert!(!passes.is_empty() && passes.len() < 7);
+    }
+
+    #[test]
+    fn icc_profile() {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode(&[1, 2, 3], 1, 1, ColorType::RGB(8)).unwrap();
+        let mut decoder = PNGDecoder::new(Cursor::new(png.clone()));
+        assert_eq!(decoder.icc_profile().unwrap(), None);
+
+        // Inserted after the IHDR chunk
+        let mut iccp = b"test profile\0\0".to_vec();
+        iccp.extend(zlib_stored(b"not really a profile"));
+        let mut with_profile = png[..33].to_vec();
+        write_chunk(&mut with_profile, ICCP, &iccp);
+        with_profile.extend_from_slice(&png[33..]);
+
+        let mut decoder = PNGDecoder::new(Cursor::new(with_profile));
+        assert_eq!(decoder.icc_profile().unwrap(), Some(b"not really a profile".to_vec()));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![1, 2, 3]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+        // The profile stays available after decoding
+        assert!(decoder.icc_profile().unwrap().is_some());
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        let chunks = vec![
+            TextChunk::new("Title", "Caf\u{e9}"),
+            TextChunk {
+                keyword: "Source".into(),
+                text: "scanner 3".repeat(20),
+                kind: TextKind::Compressed,
+            },
+            TextChunk {
+                keyword: "Description".into(),
+                text: "\u{65e5}\u{672c}".into(),
+                kind: TextKind::International {
+                    compressed: false,
+                    language: "ja".into(),
+                    translated_keyword: "\u{8aac}\u{660e}".into(),
+                },
+            },
+            TextChunk {
+                keyword: "Comment".into(),
+                text: "\u{263a}".repeat(30),
+                kind: TextKind::International {
+                    compressed: true,
+                    language: "".into(),
+                    translated_keyword: "".into(),
+                },
+            },
+        ];
+
+        let mut png = Vec::new();
+        {
+            let mut encoder = PNGEncoder::new(&mut png);
+            for chunk in &chunks {
+                encoder.add_text(chunk.clone());
+            }
+            encoder.encode(&[0], 1, 1, ColorType::Gray(8)).unwrap();
+        }
+        let kinds: Vec<ChunkType> = read_chunks(&png).unwrap().iter().map(|chunk| chunk.kind).collect();
+        assert_eq!(kinds, vec![chunk::IHDR, TEXT, ZTXT, ITXT, ITXT, chunk::IDAT, chunk::IEND]);
+
+        let mut decoder = PNGDecoder::new(Cursor::new(png));
+        assert_eq!(decoder.text_chunks().unwrap(), chunks);
+        assert!(decoder.read_image().is_ok());
+    }
+
+    #[test]
+    fn invalid_text() {
+        let encode = |chunk: TextChunk| {
+            let mut encoder = PNGEncoder::new(Vec::new());
+            encoder.add_text(chunk);
+            encoder.encode(&[0], 1, 1, ColorType::Gray(8))
+        };
+        assert!(encode(TextChunk::new("", "empty keyword")).is_err());
+        assert!(encode(TextChunk::new(&"k".repeat(80), "long keyword")).is_err());
+        assert!(encode(TextChunk::new(" Title", "leading space")).is_err());
+        assert!(encode(TextChunk::new("Two  spaces", "double space")).is_err());
+        assert!(encode(TextChunk::new("Title", "\u{263a} is not Latin-1")).is_err());
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut png = animation(true);
+        let len = png.len();
+        png[len - 20] ^= 1; // Corrupts the data of the fdAT
+        assert!(PNGDecoder::new(Cursor::new(png)).into_frames().is_err());
+    }
+
+    /// A gradient with some noise, which the different filters compress differently
+    fn test_image() -> Vec<u8> {
+        (0..32u32 * 24 * 3).map(|i| ((i % 96) * 2 + (i / 96) * 3 + (i * 7919) % 5) as u8).collect()
+    }
+
+    fn decode(png: Vec<u8>) -> Vec<u8> {
+        match PNGDecoder::new(Cursor::new(png)).read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("expected 8 bit samples"),
+        }
+    }
+
+    fn encode_with(options: PNGEncoderOptions, data: &[u8], color: ColorType) -> Vec<u8> {
+        let mut png = Vec::new();
+        PNGEncoder::new_with_options(&mut png, options).encode(data, 32, 24, color).unwrap();
+        png
+    }
+
+    #[test]
+    fn compression_options() {
+        let data = test_image();
+        let filters = [PNGFilter::NoFilter, PNGFilter::Sub, PNGFilter::Up, PNGFilter::Average,
+                       PNGFilter::Paeth, PNGFilter::Adaptive];
+        for &filter in &filters {
+            for compression in 0..10 {
+                let options = PNGEncoderOptions { compression: compression, filter: filter, optimize: false };
+                let png = encode_with(options, &data, ColorType::RGB(8));
+                assert_eq!(decode(png), data.clone());
+            }
+        }
+
+        // Bit packed rows compare whole bytes
+        let gray: Vec<u8> = (0..4 * 24).map(|i| (i * 37) as u8).collect();
+        let options = PNGEncoderOptions { filter: PNGFilter::Paeth, ..PNGEncoderOptions::default() };
+        let png = encode_with(options, &gray, ColorType::Gray(1));
+        let reference = encode_with(PNGEncoderOptions::default(), &gray, ColorType::Gray(1));
+        assert_eq!(decode(png), decode(reference));
+    }
+
+    #[test]
+    fn compression_sizes() {
+        let data = test_image();
+        let size = |compression, filter| {
+            encode_with(PNGEncoderOptions { compression: compression, filter: filter, optimize: false },
+                        &data, ColorType::RGB(8)).len()
+        };
+        assert!(size(0, PNGFilter::NoFilter) > data.len());
+        assert!(size(9, PNGFilter::Sub) < size(0, PNGFilter::Sub));
+
+        let optimized = encode_with(PNGEncoderOptions { optimize: true, ..PNGEncoderOptions::default() },
+                                    &data, ColorType::RGB(8));
+        for &filter in &[PNGFilter::NoFilter, PNGFilter::Sub, PNGFilter::Paeth, PNGFilter::Adaptive] {
+            assert!(optimized.len() <= size(9, filter));
+        }
+        assert_eq!(decode(optimized), data);
+    }
+
+    #[test]
+    fn invalid_compression() {
+        let options = PNGEncoderOptions { compression: 10, ..PNGEncoderOptions::default() };
+        let result = PNGEncoder::new_with_options(Vec::new(), options).encode(&[0], 1, 1, ColorType::Gray(8));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let result = PNGEncoder::new(Vec::new()).encode(&[0; 3], 2, 2, ColorType::Gray(8));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn sixteen_bit_roundtrip() {
+        for &color in &[ColorType::Gray(16), ColorType::GrayA(16), ColorType::RGB(16), ColorType::RGBA(16)] {
+            let len = 32 * 24 * color::num_components(color) * 2;
+            // Covers both bytes of the samples so a loss of precision changes the result
+            let data: Vec<u8> = (0..len).map(|i| (i * 131 + i / 7) as u8).collect();
+            let png = encode_with(PNGEncoderOptions::default(), &data, color);
+            // The png decoder strips the samples to 8 bits by default
+            let mut decoder = png::Decoder::new(Cursor::new(png));
+            decoder.set(png::Transformations::IDENTITY);
+            let (info, mut reader) = decoder.read_info().unwrap();
+            assert_eq!((info.color_type, info.bit_depth), color.into());
+            let mut decoded = vec![0; info.buffer_size()];
+            reader.next_frame(&mut decoded).unwrap();
+            assert_eq!(decoded, data);
+        }
+    }
+
+    #[test]
+    fn invalid_color() {
+        for &color in &[ColorType::RGB(4), ColorType::GrayA(2), ColorType::Palette(16), ColorType::Gray(32)] {
+            let result = PNGEncoder::new(Vec::new()).encode(&[0; 64], 1, 1, color);
+            assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn palette() {
+        use std::fs::File;
+        let path = "tests/images/png/transparency/tm3n3p02.png";
+        let expanded = match PNGDecoder::new(File::open(path).unwrap()).read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+
+        let options = PNGDecoderOptions { expand_palette: false, ..PNGDecoderOptions::default() };
+        let mut decoder = PNGDecoder::new_with_options(File::open(path).unwrap(), options);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Palette(2));
+        let palette = decoder.palette().unwrap().unwrap();
+        let alpha = decoder.transparency().unwrap().unwrap();
+        let indices = match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Expected 8-bit samples"),
+        };
+        assert_eq!(indices.len(), 32 * 32 / 4);
+
+        let colors: Vec<u8> = indices.iter().flat_map(|&byte| vec![byte >> 6, byte >> 4 & 3, byte >> 2 & 3, byte & 3])
+            .flat_map(|index| {
+                let (r, g, b) = palette[index as usize];
+                vec![r, g, b, *alpha.get(index as usize).unwrap_or(&255)]
+            }).collect();
+        assert_eq!(colors, expanded);
+
+        let mut decoder = PNGDecoder::new(File::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap());
+        assert_eq!(decoder.palette().unwrap(), None);
+        assert_eq!(decoder.transparency().unwrap(), None);
+    }
+
+    #[test]
+    fn palette_progressive() {
+        // A 2x2 interlaced bitmap, only passes 1, 6 and 7 hold pixels
+        let mut png = SIGNATURE.to_vec();
+        write_chunk(&mut png, chunk::IHDR, &[0, 0, 0, 2, 0, 0, 0, 2, 1, 3, 0, 0, 1]);
+        write_chunk(&mut png, chunk::PLTE, &[0, 0, 0, 255, 255, 255]);
+        write_chunk(&mut png, chunk::IDAT, &zlib_stored(&[0, 0x80, 0, 0x00, 0, 0x40]));
+        write_chunk(&mut png, chunk::IEND, &[]);
+
+        let options = PNGDecoderOptions { expand_palette: false, ..PNGDecoderOptions::default() };
+        let mut decoder = PNGDecoder::new_with_options(Cursor::new(png), options);
+        assert_eq!(decoder.palette().unwrap(), Some(vec![(0, 0, 0), (255, 255, 255)]));
+        let mut passes = Vec::new();
+        decoder.read_image_progressively(|pass, image| passes.push((pass, image.to_vec()))).unwrap();
+        assert_eq!(passes, vec![(1, vec![0xc0, 0xc0]), (6, vec![0x80, 0x80]), (7, vec![0x80, 0x40])]);
+    }
+
+    /// Inserts a chunk after the IHDR chunk of ```png```
+    fn insert_chunk(png: &[u8], kind: ChunkType, data: &[u8]) -> Vec<u8> {
+        let mut out = png[..33].to_vec();
+        write_chunk(&mut out, kind, data);
+        out.extend_from_slice(&png[33..]);
+        out
+    }
+
+    #[test]
+    fn color_space() {
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode(&[0, 128, 255, 128], 1, 1, ColorType::RGBA(8)).unwrap();
+        let linear = insert_chunk(&png, GAMA, &[0, 1, 0x86, 0xa0]);
+        let corrected = PNGDecoderOptions { correct_gamma: true, ..PNGDecoderOptions::default() };
+
+        let mut decoder = PNGDecoder::new(Cursor::new(linear.clone()));
+        assert_eq!(decoder.gamma().unwrap(), Some(1.0));
+        assert_eq!(decoder.srgb().unwrap(), None);
+        assert_eq!(decode(linear.clone()), vec![0, 128, 255, 128]);
+        let mut decoder = PNGDecoder::new_with_options(Cursor::new(linear.clone()), corrected);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![0, 186, 255, 128]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+
+        // The gamma of sRGB images is known, the gAMA chunk is ignored
+        let srgb = insert_chunk(&linear, SRGB, &[1]);
+        let mut decoder = PNGDecoder::new_with_options(Cursor::new(srgb), corrected);
+        assert_eq!(decoder.gamma().unwrap(), Some(0.45455));
+        assert_eq!(decoder.srgb().unwrap(), Some(RenderingIntent::RelativeColorimetric));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![0, 128, 255, 128]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+
+        let mut chrm = Vec::new();
+        for &value in &[31270u32, 32900, 64000, 33000, 30000, 60000, 15000, 6000] {
+            chrm.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]);
+        }
+        let mut decoder = PNGDecoder::new(Cursor::new(insert_chunk(&png, CHRM, &chrm)));
+        assert_eq!(decoder.gamma().unwrap(), None);
+        assert_eq!(decoder.chromaticities().unwrap(), Some(Chromaticities {
+            white: (0.3127, 0.329),
+            red: (0.64, 0.33),
+            green: (0.3, 0.6),
+            blue: (0.15, 0.06),
+        }));
+    }
+
+    #[test]
+    fn stream_writer() {
+        let data = test_image();
+        let mut png = Vec::new();
+        {
+            let mut writer = PNGEncoder::new(&mut png).into_stream_writer(32, 24, ColorType::RGB(8)).unwrap();
+            for row in data.chunks(32 * 3) {
+                writer.write_scanline(row).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert_eq!(decode(png), data);
+
+        // Large images are split into several IDAT chunks
+        let data: Vec<u8> = (0..256 * 256 * 3).map(|i| (i * 7 / 3) as u8).collect();
+        let options = PNGEncoderOptions { compression: 0, ..PNGEncoderOptions::default() };
+        let mut png = Vec::new();
+        {
+            let mut writer = PNGEncoder::new_with_options(&mut png, options)
+                .into_stream_writer(256, 256, ColorType::RGB(8)).unwrap();
+            for row in data.chunks(256 * 3) {
+                writer.write_scanline(row).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let idats = read_chunks(&png).unwrap().iter().filter(|chunk| chunk.kind == chunk::IDAT).count();
+        assert!(idats > 1);
+        assert_eq!(decode(png), data);
+    }
+
+    #[test]
+    fn stream_writer_invalid_rows() {
+        let mut writer = PNGEncoder::new(Vec::new()).into_stream_writer(2, 2, ColorType::Gray(8)).unwrap();
+        assert!(writer.write_scanline(&[0, 0, 0]).is_err());
+        writer.write_scanline(&[0, 0]).unwrap();
+        writer.write_scanline(&[0, 0]).unwrap();
+        assert!(writer.write_scanline(&[0, 0]).is_err());
+
+        let mut writer = PNGEncoder::new(Vec::new()).into_stream_writer(2, 2, ColorType::Gray(8)).unwrap();
+        writer.write_scanline(&[0, 0]).unwrap();
+        assert!(writer.finish().is_err());
+        assert!(PNGEncoder::new(Vec::new()).into_stream_writer(0, 2, ColorType::Gray(8)).is_err());
+    }
+
+    #[test]
+    fn quantized() {
+        // Few colors are stored exactly, the transparent one first
+        let data = [255, 0, 0, 255, 0, 0, 255, 255, 0, 0, 0, 0, 255, 0, 0, 255];
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).encode_quantized(&data, 2, 2, ColorType::RGBA(8), false).unwrap();
+        let options = PNGDecoderOptions { expand_palette: false, ..PNGDecoderOptions::default() };
+        let mut decoder = PNGDecoder::new_with_options(Cursor::new(png.clone()), options);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Palette(2));
+        assert_eq!(decoder.palette().unwrap(), Some(vec![(0, 0, 0), (255, 0, 0), (0, 0, 255)]));
+        assert_eq!(decoder.transparency().unwrap(), Some(vec![0]));
+        assert_eq!(decode(png), data.to_vec());
+
+        // Many colors are approximated, dithered or not
+        let data = test_image();
+        for &dither in &[false, true] {
+            let mut png = Vec::new();
+            PNGEncoder::new(&mut png).encode_quantized(&data, 32, 24, ColorType::RGB(8), dither).unwrap();
+            let mut decoder = PNGDecoder::new_with_options(Cursor::new(png.clone()), options);
+            assert_eq!(decoder.colortype().unwrap(), ColorType::Palette(8));
+            assert_eq!(decoder.transparency().unwrap(), None);
+            let decoded = decode(png);
+            assert_eq!(decoded.len(), data.len());
+            // Most pixels are close to their original color
+            let error: i32 = decoded.iter().zip(data.iter()).map(|(&a, &b)| (a as i32 - b as i32).abs()).sum();
+            assert!(error / (data.len() as i32) < 8);
+        }
+
+        let result = PNGEncoder::new(Vec::new()).encode_quantized(&[0; 12], 2, 2, ColorType::RGBA(16), false);
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    /// Reads all rows of ```png``` with `read_scanline` and checks them against `read_image`
+    fn check_scanlines(png: &[u8]) {
+        let image = decode(png.to_vec());
+        let mut decoder = PNGDecoder::new(Cursor::new(png));
+        let (_, height) = decoder.dimensions().unwrap();
+        let mut row = vec![0; decoder.row_len().unwrap()];
+        assert_eq!(image.len(), row.len() * height as usize);
+        match decoder.read_scanline(&mut row[1..]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => {},
+            r => panic!("Expected a short buffer to be rejected, got {:?}", r),
+        }
+        for (y, expected) in image.chunks(row.len()).enumerate() {
+            assert_eq!(decoder.read_scanline(&mut row).unwrap(), y as u32);
+            assert_eq!(&row[..], expected);
+        }
+        match decoder.read_scanline(&mut row) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => {},
+            r => panic!("Expected the end of the image, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn scanlines() {
+        check_scanlines(&encode_with(PNGEncoderOptions::default(), &test_image(), ColorType::RGB(8)));
+
+        let mut png = Vec::new();
+        File::open("tests/images/png/interlaced/lenna_fragment_interlaced.png").unwrap()
+            .read_to_end(&mut png).unwrap();
+        assert!(PNGDecoder::new(Cursor::new(&png)).is_interlaced().unwrap());
+        check_scanlines(&png);
+
+        // Rows stop at the end of the default image of an APNG
+        check_scanlines(&animation(true));
+    }
+
+    #[test]
+    fn progress() {
+        let data = test_image();
+        let mut updates = Vec::new();
+        let mut png = Vec::new();
+        PNGEncoder::new(&mut png).write_image_with_progress(&data, 32, 24, ColorType::RGB(8),
+            &mut Monitor::new().with_callback(|done, total| updates.push((done, total)))).unwrap();
+        assert_eq!(updates.len(), 25);
+        assert_eq!(updates.last(), Some(&(24, 24)));
+        assert_eq!(decode(png.clone()), data);
+
+        // Decoding stops at the first row after the token has been cancelled
+        let token = CancelToken::new();
+        let mut rows = 0;
+        let result = {
+            let mut monitor = Monitor::new()
+                .with_callback(|done, _| if done == 10 { token.cancel() } else { rows = done })
+                .with_cancel_token(token.clone());
+            PNGDecoder::new(Cursor::new(png)).read_image_with_progress(&mut monitor)
+        };
+        match result {
+            Err(ImageError::Cancelled) => (),
+            r => panic!("Expected decoding to be cancelled, got {:?}", r.is_ok()),
+        }
+        assert_eq!(rows, 9);
+    }
+
+    #[test]
+    fn read_image_into() {
+        let data = test_image();
+        let png = encode_with(PNGEncoderOptions::default(), &data, ColorType::RGB(8));
+        let mut buf = vec![0; data.len() + 1];
+        match PNGDecoder::new(Cursor::new(&png)).read_image_into(&mut buf[..data.len() - 1]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => (),
+            r => panic!("Expected the buffer to be too short, got {:?}", r),
         }
+        assert_eq!(PNGDecoder::new(Cursor::new(&png)).read_image_into(&mut buf).unwrap(), data.len());
+        assert_eq!(&buf[..data.len()], &data[..]);
     }
 }
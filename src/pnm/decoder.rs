@@ -0,0 +1,1129 @@
+use std::io;
+use std::io::{Read, BufRead};
+use std::io::BufReader;
+
+use color::{ColorType};
+use image::{read_scanlines_into, DecodingResult, ImageDecoder, ImageResult, ImageError, ImageFormat, Limits,
+            ParameterErrorKind};
+use monitor::Monitor;
+extern crate byteorder;
+use self::byteorder::{BigEndian, ByteOrder};
+
+use super::{ArbitraryTuplType, PNMHeader, PNMSubtype, SampleEncoding};
+
+/// The longest header token accepted, enough for any u32 with plenty of leading zeros
+const MAX_TOKEN_LEN: usize = 64;
+
+/// The longest line accepted in the header of a pam, including comments
+const MAX_LINE_LEN: usize = 4096;
+
+/// PNM decoder, reading bitmaps (`P1`, `P4`), graymaps (`P2`, `P5`), pixmaps (`P3`, `P6`) and
+/// arbitrary maps (`P7`)
+pub struct PNMDecoder<R> {
+    reader: BufReader<R>,
+    header: PNMHeader,
+    /// The text of the comments in the header, in the order they appeared
+    comments: Vec<String>,
+    options: PNMDecoderOptions,
+    /// The number of rows read so far
+    row: u32,
+}
+
+/// Settings controlling how a `PNMDecoder` interprets an image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PNMDecoderOptions {
+    /// Rescale samples so that maxval maps to the largest value of the decoded sample type.
+    ///
+    /// With a maxval of 1000 a sample of 500 is decoded as 128 in an 8-bit image. Disabling this
+    /// returns the samples exactly as stored, relative to `PNMHeader::maxval`. Bitmaps are always
+    /// expanded to 0 and 255. Enabled by default.
+    pub scale_samples: bool,
+
+    /// Reject images violating the specification which are commonly accepted otherwise.
+    ///
+    /// Strict decoding requires whitespace after the magic number, so the minimal form `P61 1 255`
+    /// is an error, and raw samples larger than maxval are errors instead of being clamped.
+    /// Disabled by default.
+    pub strict: bool,
+
+    /// The limits the samples are checked against before they are allocated.
+    ///
+    /// `Limits::default()` by default.
+    pub limits: Limits,
+}
+
+impl Default for PNMDecoderOptions {
+    fn default() -> PNMDecoderOptions {
+        PNMDecoderOptions {
+            scale_samples: true,
+            strict: false,
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl<R: Read> PNMDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(read: R) -> ImageResult<PNMDecoder<R>> {
+        PNMDecoder::new_with_options(read, PNMDecoderOptions::default())
+    }
+
+    /// Create a new decoder that decodes from the stream ```r``` using the settings ```options```
+    pub fn new_with_options(read: R, options: PNMDecoderOptions) -> ImageResult<PNMDecoder<R>> {
+        PNMDecoder::from_buffered(BufReader::new(read), options)
+    }
+
+    /// Reads the header of the next image from an already buffered stream
+    fn from_buffered(mut buf: BufReader<R>, options: PNMDecoderOptions) -> ImageResult<PNMDecoder<R>> {
+        let mut magic: [u8; 2] = [0, 0];
+        try!(buf.read_exact(&mut magic[..])); // Skip magic constant
+        let subtype = match (magic[0], PNMSubtype::from_magic_byte(magic[1])) {
+            (b'P', Some(subtype)) => subtype,
+            _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                "Expected magic constant for pnm, P1 through P7".to_string())),
+        };
+
+        if options.strict {
+            match try!(buf.fill_buf()).first() {
+                Some(&byte) if is_whitespace(byte) => {},
+                _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                    "Expected whitespace after the magic constant".to_string())),
+            }
+        }
+
+        let mut comments = Vec::new();
+        let header = match subtype {
+            PNMSubtype::ArbitraryMap => try!(PNMDecoder::read_arbitrary_header(&mut buf, &mut comments)),
+            _ => {
+                let width = try!(PNMDecoder::read_next_u32(&mut buf, &mut comments));
+                let height = try!(PNMDecoder::read_next_u32(&mut buf, &mut comments));
+                let maxval = match subtype {
+                    PNMSubtype::Bitmap(_) => 1,
+                    _ => try!(PNMDecoder::read_next_u32(&mut buf, &mut comments)),
+                };
+                PNMHeader::new(subtype, width, height, maxval)
+            }
+        };
+
+        try!(PNMDecoder::<R>::validate_header(&header));
+
+        Ok(PNMDecoder {
+            reader: buf,
+            header: header,
+            comments: comments,
+            options: options,
+            row: 0,
+        })
+    }
+
+    /// Get the pnm header of the image, as read from the stream.
+    pub fn header(&self) -> &PNMHeader {
+        &self.header
+    }
+
+    /// Get the comments of the header, without their leading `#` and surrounding whitespace.
+    ///
+    /// Comments in the raster of plain images are not kept.
+    pub fn comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Extract the reader and header after an image has been read.
+    pub fn into_inner(self) -> (BufReader<R>, PNMHeader) {
+        (self.reader, self.header)
+    }
+
+    /// Get a decoder for the image following this one in the same stream.
+    ///
+    /// Netpbm streams may contain any number of images, one directly after the other. Rows of
+    /// this image which have not been read yet are skipped, as is whitespace after its raster.
+    /// The new decoder uses the same options. Returns `None` if the stream ends after this image.
+    pub fn next_image(mut self) -> ImageResult<Option<PNMDecoder<R>>> {
+        try!(self.skip_remaining_rows());
+
+        loop {
+            let (whitespace, eof) = {
+                let buf = try!(self.reader.fill_buf());
+                let whitespace = buf.iter().take_while(|&&b| is_whitespace(b)).count();
+                (whitespace, buf.is_empty())
+            };
+            if eof {
+                return Ok(None)
+            }
+            if whitespace == 0 {
+                break
+            }
+            self.reader.consume(whitespace);
+        }
+
+        PNMDecoder::from_buffered(self.reader, self.options).map(Some)
+    }
+
+    /// Consumes the rows of the raster which have not been read
+    fn skip_remaining_rows(&mut self) -> ImageResult<()> {
+        let rows = self.header.height() - self.row;
+        if rows == 0 {
+            return Ok(())
+        }
+
+        let row_bytes = match self.header.subtype() {
+            PNMSubtype::Bitmap(SampleEncoding::Binary) => Some((self.header.width() as u64 + 7) / 8),
+            subtype if subtype.sample_encoding() == SampleEncoding::Binary => Some(
+                self.header.width() as u64 * self.header.depth() as u64 * self.bytewidth() as u64),
+            _ => None,
+        };
+        match row_bytes {
+            // Binary rows are discarded without interpreting their samples
+            Some(row_bytes) => {
+                let len = try!(row_bytes.checked_mul(rows as u64).ok_or(ImageError::malformed(ImageFormat::PNM,
+                    "Invalid image dimensions".to_string())));
+                let skipped = try!(io::copy(&mut self.reader.by_ref().take(len), &mut io::sink()));
+                if skipped < len {
+                    return Err(ImageError::truncated(ImageFormat::PNM))
+                }
+            },
+            None => { try!(self.read_rows(rows)); },
+        }
+
+        self.row = self.header.height();
+        Ok(())
+    }
+
+    /// Checks that the values of a header describe an image which can be decoded
+    fn validate_header(header: &PNMHeader) -> ImageResult<()> {
+        if header.maxval() == 0 {
+            return Err(ImageError::malformed(ImageFormat::PNM, "Image maxval must not be 0".to_string()))
+        }
+        if !(header.maxval() <= u16::max_value() as u32) {
+            return Err(ImageError::malformed(ImageFormat::PNM,
+                "Image maxval is not less or equal to 65535".to_string()))
+        }
+        if header.width() == 0 || header.height() == 0 {
+            return Err(ImageError::malformed(ImageFormat::PNM, format!(
+                "Image dimensions {}x{} must not be zero", header.width(), header.height())))
+        }
+        if header.depth() == 0 {
+            return Err(ImageError::malformed(ImageFormat::PNM, "Image depth must not be 0".to_string()))
+        }
+        Ok(())
+    }
+
+    /// Reads a string as well as a single whitespace after it. Comments are skipped, their text
+    /// is appended to ```comments```.
+    fn read_next_string(reader: &mut BufReader<R>, comments: &mut Vec<String>) -> ImageResult<String> {
+        let mut bytes = Vec::new();
+        let mut comment: Option<Vec<u8>> = None;
+
+        for read in reader.bytes() {
+            let byte = try!(read);
+
+            // A comment extends to the end of its line, the line break is not a separator
+            if let Some(mut text) = comment.take() {
+                if byte == b'\r' || byte == b'\n' {
+                    comments.push(comment_text(&text));
+                } else {
+                    if text.len() < MAX_LINE_LEN {
+                        text.push(byte);
+                    }
+                    comment = Some(text);
+                }
+                continue
+            }
+
+            match byte {
+                b'#' => comment = Some(Vec::new()),
+                byte if is_whitespace(byte) => {
+                    if !bytes.is_empty() {
+                        break // We're done as we already have some content
+                    }
+                },
+                _ if bytes.len() == MAX_TOKEN_LEN => {
+                    return Err(ImageError::malformed(ImageFormat::PNM, format!(
+                        "Header token is longer than {} bytes", MAX_TOKEN_LEN)))
+                },
+                byte => bytes.push(byte),
+            }
+        }
+
+        if let Some(text) = comment {
+            comments.push(comment_text(&text));
+        }
+
+        if bytes.is_empty() {
+            return Err(ImageError::malformed(ImageFormat::PNM, "Unexpected eof".to_string()))
+        }
+
+        if !bytes.as_slice().is_ascii() {
+            return Err(ImageError::malformed(ImageFormat::PNM, "Non ascii character in preamble".to_string()))
+        }
+
+        String::from_utf8(bytes).map_err(|_| ImageError::malformed(ImageFormat::PNM,
+            "Couldn't read preamble".to_string()))
+    }
+
+    fn read_next_u32(reader: &mut BufReader<R>, comments: &mut Vec<String>) -> ImageResult<u32> {
+        let s = try!(PNMDecoder::read_next_string(reader, comments));
+        s.parse::<u32>().map_err(|_| ImageError::malformed(ImageFormat::PNM,
+            "Invalid number in preamble".to_string()))
+    }
+
+    /// Reads the line based header of a pam, up to and including the `ENDHDR` line
+    fn read_arbitrary_header(reader: &mut BufReader<R>, comments: &mut Vec<String>) -> ImageResult<PNMHeader> {
+        fn parse_field(value: &str, field: &mut Option<u32>, name: &str) -> ImageResult<()> {
+            if field.is_some() {
+                return Err(ImageError::malformed(ImageFormat::PNM,
+                    format!("Duplicate {} line in pam header", name)))
+            }
+            match value.parse::<u32>() {
+                Ok(v) => { *field = Some(v); Ok(()) },
+                Err(_) => Err(ImageError::malformed(ImageFormat::PNM,
+                    format!("Invalid {} value in pam header", name))),
+            }
+        }
+
+        let mut width = None;
+        let mut height = None;
+        let mut depth = None;
+        let mut maxval = None;
+        let mut tupltype: Option<String> = None;
+
+        loop {
+            let mut line = Vec::new();
+            try!(reader.by_ref().take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line));
+            if line.is_empty() {
+                return Err(ImageError::malformed(ImageFormat::PNM,
+                    "Unexpected eof in pam header".to_string()))
+            }
+            if line.len() == MAX_LINE_LEN && line[MAX_LINE_LEN - 1] != b'\n' {
+                return Err(ImageError::malformed(ImageFormat::PNM, format!(
+                    "Pam header line is longer than {} bytes", MAX_LINE_LEN)))
+            }
+            if !line.as_slice().is_ascii() {
+                return Err(ImageError::malformed(ImageFormat::PNM,
+                    "Non ascii character in preamble".to_string()))
+            }
+            let line = String::from_utf8(line)
+                .map_err(|_| ImageError::malformed(ImageFormat::PNM, "Couldn't read preamble".to_string()));
+            let line = try!(line);
+            let line = line.trim();
+            if line.starts_with('#') {
+                comments.push(line[1..].trim().to_string());
+                continue
+            }
+            if line.is_empty() {
+                continue
+            }
+
+            let (key, value) = match line.find(|c: char| c.is_whitespace()) {
+                Some(split) => (&line[..split], line[split..].trim()),
+                None => (line, ""),
+            };
+            match key {
+                "ENDHDR" => break,
+                "WIDTH" => try!(parse_field(value, &mut width, key)),
+                "HEIGHT" => try!(parse_field(value, &mut height, key)),
+                "DEPTH" => try!(parse_field(value, &mut depth, key)),
+                "MAXVAL" => try!(parse_field(value, &mut maxval, key)),
+                // Multiple tuple type lines are concatenated, separated by a single space
+                "TUPLTYPE" => tupltype = Some(match tupltype {
+                    None => value.to_string(),
+                    Some(previous) => previous + " " + value,
+                }),
+                _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                    format!("Unknown pam header line {}", key))),
+            }
+        }
+
+        match (width, height, depth, maxval) {
+            (Some(width), Some(height), Some(depth), Some(maxval)) => Ok(PNMHeader::new_arbitrary(
+                width, height, depth, maxval,
+                tupltype.map(|name| ArbitraryTuplType::from_name(&name)))),
+            _ => Err(ImageError::malformed(ImageFormat::PNM,
+                "Pam header is missing one of WIDTH, HEIGHT, DEPTH or MAXVAL".to_string())),
+        }
+    }
+}
+
+impl<R: Read> ImageDecoder for PNMDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.header.width(), self.header.height()))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        let bits = match self.bytewidth() {
+            1 => 8,
+            2 => 16,
+            _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                "Don't know how to decode PNM with more than 16 bits".to_string())),
+        };
+        match self.header.subtype() {
+            PNMSubtype::Bitmap(_) | PNMSubtype::Graymap(_) => Ok(ColorType::Gray(bits)),
+            PNMSubtype::Pixmap(_) => Ok(ColorType::RGB(bits)),
+            PNMSubtype::ArbitraryMap => self.arbitrary_colortype(bits),
+        }
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let bytewidth = self.bytewidth();
+        match self.header.width().checked_mul(self.header.depth()).and_then(|v| v.checked_mul(bytewidth)) {
+            Some(len) => Ok(len as usize),
+            None => Err(ImageError::malformed(ImageFormat::PNM, "Invalid image dimensions".to_string())),
+        }
+    }
+
+    /// Reads the next row into ```buf```, 16-bit samples are stored in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row >= self.header.height() {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        match try!(self.read_rows(1)) {
+            DecodingResult::U8(row) => ::copy_memory(&row, &mut buf[..len]),
+            DecodingResult::U16(row) => for (o, &i) in buf.chunks_mut(2).zip(row.iter()) {
+                BigEndian::write_u16(o, i);
+            },
+            // Samples are at most 16 bits
+            _ => unreachable!(),
+        }
+
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let height = self.header.height();
+        let image = try!(self.read_rows(height));
+        self.row = height;
+        Ok(image)
+    }
+
+    fn read_image_into(&mut self, buf: &mut [u8]) -> ImageResult<usize> {
+        read_scanlines_into(self, buf)
+    }
+
+    /// Decodes the entire image row by row, reporting each row to ```monitor```
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        let (width, height) = (self.header.width(), self.header.height());
+        let color = try!(self.colortype());
+        try!(self.options.limits.check_image(width, height, color));
+        try!(monitor.update(0, height as u64));
+        let mut image = try!(self.read_rows(0));
+        for row in 0..height {
+            match (&mut image, try!(self.read_rows(1))) {
+                (&mut DecodingResult::U8(ref mut image), DecodingResult::U8(row)) => image.extend(row),
+                (&mut DecodingResult::U16(ref mut image), DecodingResult::U16(row)) => image.extend(row),
+                // Samples are at most 16 bits
+                _ => unreachable!(),
+            }
+            self.row = row + 1;
+            try!(monitor.update(row as u64 + 1, height as u64));
+        }
+        Ok(image)
+    }
+}
+
+impl<R: Read> PNMDecoder<R> {
+    /// Reads the samples of the next ```rows``` rows
+    fn read_rows(&mut self, rows: u32) -> ImageResult<DecodingResult> {
+        // Unsupported tuple types must not be read as any of the known ones
+        let color = try!(self.colortype());
+        try!(self.options.limits.check_image(self.header.width(), rows, color));
+
+        let opt_samples = self.header.width().checked_mul(rows)
+            .map_or(None, |v| v.checked_mul(self.header.depth()));
+
+        let samples = match opt_samples {
+            Some(v) if v.checked_mul(self.bytewidth()).is_some() => v as usize,
+            _ => return Err(ImageError::malformed(ImageFormat::PNM, "Invalid image dimensions".to_string())),
+        };
+
+        let data = match self.header.subtype() {
+            PNMSubtype::Bitmap(SampleEncoding::Binary) => return self.read_packed_bits(rows as usize),
+            PNMSubtype::Bitmap(SampleEncoding::Ascii) => return self.read_ascii_bits(samples),
+            PNMSubtype::ArbitraryMap => try!(self.read_arbitrary_samples(samples)),
+            subtype => match subtype.sample_encoding() {
+                SampleEncoding::Binary => try!(self.read_binary_samples(samples)),
+                SampleEncoding::Ascii => try!(self.read_ascii_samples(samples)),
+            },
+        };
+        if self.options.strict {
+            try!(self.check_maxval(&data));
+        }
+        Ok(self.scale_samples(data))
+    }
+
+    fn bytewidth(&self) -> u32 {
+        if self.header.maxval() < 256 { 1 } else { 2 }
+    }
+
+    /// Maps the tuple type and depth of a pam to a color type
+    fn arbitrary_colortype(&self, bits: u8) -> ImageResult<ColorType> {
+        match (self.header.tupltype(), self.header.depth()) {
+            (Some(&ArbitraryTuplType::Custom(ref name)), _) => Err(ImageError::unsupported(ImageFormat::PNM,
+                format!("Unsupported pam tuple type {}", name))),
+            (Some(tupltype), depth) if tupltype.depth() != Some(depth) => Err(ImageError::malformed(ImageFormat::PNM,
+                format!("Pam tuple type {} requires a depth of {}", tupltype.name(), tupltype.depth().unwrap()))),
+            // Without a tuple type the depth alone decides how the samples are interpreted
+            (_, 1) => Ok(ColorType::Gray(bits)),
+            (_, 2) => Ok(ColorType::GrayA(bits)),
+            (_, 3) => Ok(ColorType::RGB(bits)),
+            (_, 4) => Ok(ColorType::RGBA(bits)),
+            (None, depth) => Err(ImageError::unsupported(ImageFormat::PNM,
+                format!("Unsupported pam depth {} without a tuple type", depth))),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Reads the samples of a pam, expanding black and white images to the full range of a byte
+    fn read_arbitrary_samples(&mut self, samples: usize) -> ImageResult<DecodingResult> {
+        let data = try!(self.read_binary_samples(samples));
+        match self.header.tupltype() {
+            Some(&ArbitraryTuplType::BlackAndWhite) | Some(&ArbitraryTuplType::BlackAndWhiteAlpha) => {
+                match data {
+                    DecodingResult::U8(ref data) if self.header.maxval() == 1 => {
+                        if data.iter().any(|&v| v > 1) {
+                            return Err(ImageError::malformed(ImageFormat::PNM,
+                                "Sample value is larger than maxval".to_string()))
+                        }
+                    },
+                    _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                        "Black and white pam images require a maxval of 1".to_string())),
+                }
+            },
+            _ => {},
+        }
+        Ok(data)
+    }
+
+    /// Fails if any sample is larger than maxval
+    fn check_maxval(&self, data: &DecodingResult) -> ImageResult<()> {
+        let maxval = self.header.maxval();
+        let valid = match *data {
+            DecodingResult::U8(ref data) => data.iter().all(|&v| v as u32 <= maxval),
+            DecodingResult::U16(ref data) => data.iter().all(|&v| v as u32 <= maxval),
+            _ => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(ImageError::malformed(ImageFormat::PNM, "Sample value is larger than maxval".to_string()))
+        }
+    }
+
+    /// Maps samples relative to maxval onto the full range of their type, if enabled
+    fn scale_samples(&self, data: DecodingResult) -> DecodingResult {
+        let maxval = self.header.maxval();
+        if !self.options.scale_samples {
+            return data
+        }
+
+        // Rounds to the nearest value, samples above maxval are clamped
+        let scale = |value: u32, max: u32| ((value * max + maxval / 2) / maxval).min(max);
+        match data {
+            DecodingResult::U8(data) => if maxval == 255 {
+                DecodingResult::U8(data)
+            } else {
+                DecodingResult::U8(data.into_iter().map(|v| scale(v as u32, 255) as u8).collect())
+            },
+            DecodingResult::U16(data) => if maxval == 65535 {
+                DecodingResult::U16(data)
+            } else {
+                DecodingResult::U16(data.into_iter().map(|v| scale(v as u32, 65535) as u16).collect())
+            },
+            data => data,
+        }
+    }
+
+    fn read_binary_samples(&mut self, samples: usize) -> ImageResult<DecodingResult> {
+        let mut data = vec![0 as u8; samples * self.bytewidth() as usize];
+
+        match self.reader.read_exact(&mut data) {
+            Ok(_) => {},
+            Err(e) => return Err(ImageError::IoError(e)),
+        };
+
+        if self.bytewidth() == 1 {
+            Ok(DecodingResult::U8(data))
+        } else {
+            let mut out = vec![0 as u16; samples];
+            for (o, i) in out.chunks_mut(1).zip(data.chunks(2)) {
+                o[0] = BigEndian::read_u16(i);
+            }
+            Ok(DecodingResult::U16(out))
+        }
+    }
+
+    /// Reads `samples` whitespace separated decimal values of a plain pnm
+    fn read_ascii_samples(&mut self, samples: usize) -> ImageResult<DecodingResult> {
+        let mut data = Vec::with_capacity(samples);
+
+        for _ in 0..samples {
+            let value = try!(PNMDecoder::read_next_u32(&mut self.reader, &mut Vec::new()));
+            if value > self.header.maxval() {
+                return Err(ImageError::malformed(ImageFormat::PNM,
+                    "Sample value is larger than maxval".to_string()))
+            }
+            data.push(value as u16);
+        }
+
+        if self.bytewidth() == 1 {
+            Ok(DecodingResult::U8(data.into_iter().map(|v| v as u8).collect()))
+        } else {
+            Ok(DecodingResult::U16(data))
+        }
+    }
+
+    /// Reads the rows of a raw bitmap, each packed into bytes with the most significant bit
+    /// first and padded to a full byte at its end.
+    fn read_packed_bits(&mut self, rows: usize) -> ImageResult<DecodingResult> {
+        let width = self.header.width() as usize;
+        let row_bytes = (width + 7) / 8;
+        let mut data = vec![0 as u8; row_bytes * rows];
+
+        match self.reader.read_exact(&mut data) {
+            Ok(_) => {},
+            Err(e) => return Err(ImageError::IoError(e)),
+        };
+
+        let mut out = Vec::with_capacity(width * rows);
+        for row in data.chunks(row_bytes) {
+            for x in 0..width {
+                let bit = (row[x / 8] >> (7 - x % 8)) & 1;
+                out.push(bit_to_gray(bit));
+            }
+        }
+
+        Ok(DecodingResult::U8(out))
+    }
+
+    /// Reads the digits of a plain bitmap. These need not be separated by whitespace.
+    fn read_ascii_bits(&mut self, samples: usize) -> ImageResult<DecodingResult> {
+        let mut out = Vec::with_capacity(samples);
+        let mut comment = false;
+
+        // Stop right after the last digit, the stream may continue with another image
+        let mut bytes = (&mut self.reader).bytes();
+        while out.len() < samples {
+            let byte = match bytes.next() {
+                Some(byte) => try!(byte),
+                None => break,
+            };
+            match byte {
+                b'\n' | b'\r' if comment => comment = false,
+                _ if comment => {},
+                b'#' => comment = true,
+                b'0' => out.push(bit_to_gray(0)),
+                b'1' => out.push(bit_to_gray(1)),
+                b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r' | b' ' => {},
+                _ => return Err(ImageError::malformed(ImageFormat::PNM,
+                    "Invalid character in bitmap raster".to_string())),
+            }
+        }
+
+        if out.len() < samples {
+            return Err(ImageError::truncated(ImageFormat::PNM))
+        }
+
+        Ok(DecodingResult::U8(out))
+    }
+}
+
+/// The text of a comment, after its `#`
+fn comment_text(text: &[u8]) -> String {
+    String::from_utf8_lossy(text).trim().to_string()
+}
+
+/// Whether ```byte``` is one of the whitespace characters separating tokens of a pnm
+fn is_whitespace(byte: u8) -> bool {
+    match byte {
+        b'\t' | b'\n' | b'\x0b' | b'\x0c' | b'\r' | b' ' => true,
+        _ => false,
+    }
+}
+
+/// In bitmaps a set bit is black, unlike all other formats where 0 is the darkest value
+fn bit_to_gray(bit: u8) -> u8 {
+    if bit == 0 { 255 } else { 0 }
+}
+
+/// Tests parsing binary buffers were written based on and validated against `pamfile` from
+/// netpbm (http://netpbm.sourceforge.net/).
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DecodingErrorKind, LimitErrorKind};
+
+    #[test]
+    fn minimal_form() {
+        // Violates current specification (October 2016 ) but accepted by both netpbm and ImageMagick
+        decode_minimal_image(&b"P61 1 255 123"[..]);
+        decode_minimal_image(&b"P6 1 1 255 123"[..]);
+        decode_minimal_image(&b"P6 1 1 255 123\xFF"[..]); // Too long should not be an issue
+    }
+
+    #[test]
+    fn comment_in_token() {
+        decode_minimal_image(&b"P6 1 1 2#comment\n55 123"[..]); // Terminating LF
+        decode_minimal_image(&b"P6 1 1 2#comment\r55 123"[..]); // Terminating CR
+        decode_minimal_image(&b"P6 1 1#comment\n 255 123"[..]); // Comment after token
+        decode_minimal_image(&b"P6 1 1 #comment\n255 123"[..]); // Comment before token
+        decode_minimal_image(&b"P6#comment\n 1 1 255 123"[..]); // Begin of header
+        decode_minimal_image(&b"P6 1 1 255#comment\n 123"[..]); // End of header
+    }
+
+    #[test]
+    fn comments() {
+        let decoder = PNMDecoder::new(&b"P6 # first\n1 1#second\r\n#  \n255 123"[..]).unwrap();
+        assert_eq!(decoder.comments(), &["first".to_string(), "second".to_string(), "".to_string()][..]);
+
+        let encoded = b"P7\n# exposure 1/60\nWIDTH 1\nHEIGHT 1\n#lens 50mm\nDEPTH 3\nMAXVAL 255\nENDHDR\n123";
+        let decoder = PNMDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.comments(), &["exposure 1/60".to_string(), "lens 50mm".to_string()][..]);
+
+        // Comments in the raster are not part of the header
+        let mut decoder = PNMDecoder::new(&b"P3 1 1 255 49 #comment\n50 51"[..]).unwrap();
+        decoder.read_image().unwrap();
+        assert!(decoder.comments().is_empty());
+    }
+
+    #[test]
+    fn whitespace() {
+        decode_minimal_image(&b"P6\x091\x091\x09255\x09123"[..]); // TAB
+        decode_minimal_image(&b"P6\x0a1\x0a1\x0a255\x0a123"[..]); // LF
+        decode_minimal_image(&b"P6\x0b1\x0b1\x0b255\x0b123"[..]); // VT
+        decode_minimal_image(&b"P6\x0c1\x0c1\x0c255\x0c123"[..]); // FF
+        decode_minimal_image(&b"P6\x0d1\x0d1\x0d255\x0d123"[..]); // CR
+        // Spaces tested before
+        decode_minimal_image(&b"P61\x09\x0a\x0b\x0c\x0d1 255 123"[..]); // All whitespace, combined
+    }
+
+    /// Tests for decoding error, assuming `encoded` is ppm encoding for the very simplistic image
+    /// containing a single pixel with one byte values (1, 2, 3).
+    fn decode_minimal_image(encoded: &[u8]) {
+        let content = vec![49 as u8, 50, 51];
+        let mut decoder = PNMDecoder::new(encoded).unwrap();
+
+        assert_eq!(decoder.dimensions().unwrap(), (1, 1));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(decoder.row_len().unwrap(), 3);
+        assert_eq!(decoder.bytewidth(), 1);
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, content),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn plain_form() {
+        decode_minimal_image(&b"P3 1 1 255 49 50 51"[..]);
+        decode_minimal_image(&b"P3\n1 1\n255\n49\n50\n51\n"[..]);
+        decode_minimal_image(&b"P3 1 1 255 49 #comment\n50\t51"[..]); // Comment in raster
+        decode_minimal_image(&b"P3 1 1 255 49 50#comment\n 51"[..]); // Comment after sample
+    }
+
+    #[test]
+    fn plain_form_16bit() {
+        let mut decoder = PNMDecoder::new(&b"P3 2 1 65535 0 1 2 1000 65535 300"[..]).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(16));
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(image) => assert_eq!(image, vec![0, 1, 2, 1000, 65535, 300]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn plain_form_invalid_raster() {
+        // Sample above maxval
+        assert!(PNMDecoder::new(&b"P3 1 1 255 49 50 256"[..]).unwrap().read_image().is_err());
+        // Not enough samples
+        assert!(PNMDecoder::new(&b"P3 1 1 255 49 50"[..]).unwrap().read_image().is_err());
+        // Garbage in raster
+        assert!(PNMDecoder::new(&b"P3 1 1 255 49 50 x"[..]).unwrap().read_image().is_err());
+    }
+
+    #[test]
+    fn graymap() {
+        let mut decoder = PNMDecoder::new(&b"P5 3 1 255 \x00\x7f\xff"[..]).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+        assert_eq!(decoder.row_len().unwrap(), 3);
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 127, 255]),
+            _ => assert!(false),
+        }
+
+        let mut decoder = PNMDecoder::new(&b"P5 2 1 65535 \x00\x01\xff\xfe"[..]).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(16));
+        assert_eq!(decoder.row_len().unwrap(), 4);
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(image) => assert_eq!(image, vec![1, 65534]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn plain_graymap() {
+        let mut decoder = PNMDecoder::new(&b"P2\n# created by hand\n3 2\n15\n0 1 2\n13 14 15\n"[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 17, 34, 221, 238, 255]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn bitmap() {
+        // Rows are padded to whole bytes, the padding bits are ignored
+        let mut decoder = PNMDecoder::new(&b"P4 10 2 \xa5\xc0\x0f\x3f"[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (10, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+        assert_eq!(decoder.row_len().unwrap(), 10);
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![
+                0, 255, 0, 255, 255, 0, 255, 0, 0, 0,
+                255, 255, 255, 255, 0, 0, 0, 0, 255, 255,
+            ]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn plain_bitmap() {
+        let expected = vec![0, 255, 255, 0, 255, 0];
+        for encoded in &[&b"P1 3 2 1 0 0 1 0 1"[..], &b"P1\n3 2\n100\n101\n"[..],
+                         &b"P1 3 2 10#comment\n0101"[..]] {
+            let mut decoder = PNMDecoder::new(*encoded).unwrap();
+            assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+
+            match decoder.read_image().unwrap() {
+                DecodingResult::U8(image) => assert_eq!(image, expected),
+                _ => assert!(false),
+            }
+        }
+
+        assert!(PNMDecoder::new(&b"P1 3 2 1 0 0 1 0"[..]).unwrap().read_image().is_err());
+        assert!(PNMDecoder::new(&b"P1 3 2 1 0 0 1 0 2"[..]).unwrap().read_image().is_err());
+    }
+
+    #[test]
+    fn arbitrary_map() {
+        let encoded = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n\
+                        \x01\x02\x03\x04\xfb\xfc\xfd\xfe";
+        let mut decoder = PNMDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (2, 1));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        assert_eq!(decoder.row_len().unwrap(), 8);
+        assert_eq!(decoder.header().tupltype(), Some(&ArbitraryTuplType::RGBAlpha));
+
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![1, 2, 3, 4, 251, 252, 253, 254]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn arbitrary_map_tupltypes() {
+        let decode = |tupltype: &str, depth: u32, maxval: u32| {
+            let encoded = format!("P7\n# comment\nWIDTH 1\nHEIGHT 1\nDEPTH {}\nMAXVAL {}\n{}ENDHDR\n\x01\x01\x01\x01",
+                                  depth, maxval, tupltype);
+            let mut decoder = PNMDecoder::new(encoded.as_bytes()).unwrap();
+            decoder.colortype().map(|color| (color, decoder.read_image().unwrap()))
+        };
+
+        match decode("TUPLTYPE BLACKANDWHITE\n", 1, 1) {
+            Ok((ColorType::Gray(8), DecodingResult::U8(image))) => assert_eq!(image, vec![255]),
+            _ => assert!(false),
+        }
+        match decode("TUPLTYPE BLACKANDWHITE_ALPHA\n", 2, 1) {
+            Ok((ColorType::GrayA(8), DecodingResult::U8(image))) => assert_eq!(image, vec![255, 255]),
+            _ => assert!(false),
+        }
+        match decode("TUPLTYPE GRAYSCALE\n", 1, 255) {
+            Ok((ColorType::Gray(8), DecodingResult::U8(image))) => assert_eq!(image, vec![1]),
+            _ => assert!(false),
+        }
+        match decode("TUPLTYPE GRAYSCALE_ALPHA\n", 2, 65535) {
+            Ok((ColorType::GrayA(16), DecodingResult::U16(image))) => assert_eq!(image, vec![257, 257]),
+            _ => assert!(false),
+        }
+        match decode("TUPLTYPE RGB\n", 3, 255) {
+            Ok((ColorType::RGB(8), DecodingResult::U8(image))) => assert_eq!(image, vec![1, 1, 1]),
+            _ => assert!(false),
+        }
+        // The depth decides without a tuple type
+        match decode("", 2, 255) {
+            Ok((ColorType::GrayA(8), DecodingResult::U8(image))) => assert_eq!(image, vec![1, 1]),
+            _ => assert!(false),
+        }
+
+        assert!(decode("TUPLTYPE RGB\n", 4, 255).is_err()); // Depth mismatch
+        assert!(decode("TUPLTYPE CMYK\n", 4, 255).is_err()); // Custom tuple type
+        assert!(decode("", 5, 255).is_err()); // Unknown depth
+    }
+
+    #[test]
+    fn invalid_arbitrary_header() {
+        assert!(PNMDecoder::new(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nENDHDR\n\x01"[..]).is_err());
+        assert!(PNMDecoder::new(&b"P7\nWIDTH 1\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nENDHDR\n\x01"[..]).is_err());
+        assert!(PNMDecoder::new(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nCOLOR red\nENDHDR\n\x01"[..]).is_err());
+        assert!(PNMDecoder::new(&b"P7\nWIDTH x\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nENDHDR\n\x01"[..]).is_err());
+        assert!(PNMDecoder::new(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\n"[..]).is_err());
+    }
+
+    #[test]
+    fn degenerate_headers() {
+        let message = |encoded: &[u8]| match PNMDecoder::new(encoded) {
+            Err(ImageError::Decoding { kind: DecodingErrorKind::Malformed(message), .. }) => message,
+            Err(err) => panic!("Expected a format error, got {}", err),
+            Ok(_) => panic!("Expected a format error"),
+        };
+
+        assert_eq!(message(&b"P6 1 1 0 1"[..]), "Image maxval must not be 0");
+        assert_eq!(message(&b"P2 1 1 0 1"[..]), "Image maxval must not be 0");
+        assert_eq!(message(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 0\nENDHDR\n\x00"[..]),
+                   "Image maxval must not be 0");
+        assert_eq!(message(&b"P6 1 1 70000 1"[..]), "Image maxval is not less or equal to 65535");
+        assert_eq!(message(&b"P6 0 1 255 "[..]), "Image dimensions 0x1 must not be zero");
+        assert_eq!(message(&b"P4 1 0 "[..]), "Image dimensions 1x0 must not be zero");
+        assert_eq!(message(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 0\nMAXVAL 255\nENDHDR\n"[..]),
+                   "Image depth must not be 0");
+    }
+
+    #[test]
+    fn long_header_tokens() {
+        let long = format!("P6 {} 1 255 1", "1".repeat(1000));
+        match PNMDecoder::new(long.as_bytes()) {
+            Err(ImageError::Decoding { kind: DecodingErrorKind::Malformed(message), .. }) =>
+                assert_eq!(message, "Header token is longer than 64 bytes"),
+            _ => assert!(false),
+        }
+        // Leading zeros are fine as long as the token stays short
+        assert!(PNMDecoder::new(&b"P6 0000000001 1 255 123"[..]).is_ok());
+
+        let long = format!("P7\n#{}\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 255\nENDHDR\n\x00", "x".repeat(5000));
+        match PNMDecoder::new(long.as_bytes()) {
+            Err(ImageError::Decoding { kind: DecodingErrorKind::Malformed(message), .. }) =>
+                assert_eq!(message, "Pam header line is longer than 4096 bytes"),
+            _ => assert!(false),
+        }
+    }
+
+    /// Reads all rows of ```encoded``` with ```read_scanline```
+    fn read_scanlines(encoded: &[u8]) -> Vec<Vec<u8>> {
+        let mut decoder = PNMDecoder::new(encoded).unwrap();
+        let mut rows = Vec::new();
+        let mut buf = vec![0; decoder.row_len().unwrap()];
+        for row in 0..decoder.dimensions().unwrap().1 {
+            assert_eq!(decoder.read_scanline(&mut buf).unwrap(), row);
+            rows.push(buf.clone());
+        }
+        match decoder.read_scanline(&mut buf) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => {},
+            r => panic!("Expected the end of the image, got {:?}", r),
+        }
+        rows
+    }
+
+    #[test]
+    fn scanlines() {
+        assert_eq!(read_scanlines(&b"P6 1 2 255 123456"[..]), vec![b"123".to_vec(), b"456".to_vec()]);
+        assert_eq!(read_scanlines(&b"P3 1 2 255 1 2 3 4 5 6"[..]), vec![vec![1, 2, 3], vec![4, 5, 6]]);
+        assert_eq!(read_scanlines(&b"P4 9 2 \x80\x00\x01\x00"[..]), vec![
+            vec![0, 255, 255, 255, 255, 255, 255, 255, 255],
+            vec![255, 255, 255, 255, 255, 255, 255, 0, 255],
+        ]);
+        assert_eq!(read_scanlines(&b"P1 2 2 1 0 0 1"[..]), vec![vec![0, 255], vec![255, 0]]);
+    }
+
+    #[test]
+    fn load_rect() {
+        let encoded = &b"P5 3 3 255 \x01\x02\x03\x04\x05\x06\x07\x08\x09"[..];
+        assert_eq!(PNMDecoder::new(encoded).unwrap().load_rect(1, 1, 2, 2).unwrap(), vec![5, 6, 8, 9]);
+        assert_eq!(PNMDecoder::new(encoded).unwrap().load_rect(0, 0, 1, 3).unwrap(), vec![1, 2, 3]);
+        assert!(PNMDecoder::new(encoded).unwrap().load_rect(2, 0, 1, 2).is_err());
+    }
+
+    #[test]
+    fn scanlines_16bit() {
+        assert_eq!(read_scanlines(&b"P5 2 1 65535 \x01\x02\x03\x04"[..]), vec![vec![1, 2, 3, 4]]);
+        assert_eq!(read_scanlines(&b"P2 2 2 65535 1 2 258 772"[..]), vec![vec![0, 1, 0, 2], vec![1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn scaled_samples() {
+        let mut decoder = PNMDecoder::new(&b"P5 4 1 1000 \x00\x00\x01\xf4\x03\xe8\x00\x02"[..]).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(16));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(image) => assert_eq!(image, vec![0, 32768, 65535, 131]),
+            _ => assert!(false),
+        }
+
+        let mut decoder = PNMDecoder::new(&b"P2 4 1 15 0 7 8 15"[..]).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 119, 136, 255]),
+            _ => assert!(false),
+        }
+
+        let mut decoder = PNMDecoder::new(&b"P6 1 1 3 \x00\x01\x04"[..]).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 85, 255]), // Clamped
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn unscaled_samples() {
+        let options = PNMDecoderOptions { scale_samples: false, ..PNMDecoderOptions::default() };
+        let mut decoder = PNMDecoder::new_with_options(&b"P2 4 1 15 0 7 8 15"[..], options).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 7, 8, 15]),
+            _ => assert!(false),
+        }
+
+        let encoded = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 1\nMAXVAL 1\nTUPLTYPE BLACKANDWHITE\nENDHDR\n\x00\x01";
+        let mut decoder = PNMDecoder::new_with_options(&encoded[..], options).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0, 1]),
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn multiple_images() {
+        let encoded = b"P6 1 1 255 123P5 2 1 255 \x01\x02\nP1 2 1 01\n\nP3 1 1 255 4 5 6\n";
+        let mut decoder = PNMDecoder::new(&encoded[..]).unwrap();
+        let mut images = Vec::new();
+        loop {
+            images.push(decoder.read_image().unwrap());
+            decoder = match decoder.next_image().unwrap() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        assert_eq!(images.len(), 4);
+        match (&images[0], &images[1], &images[2], &images[3]) {
+            (&DecodingResult::U8(ref a), &DecodingResult::U8(ref b),
+             &DecodingResult::U8(ref c), &DecodingResult::U8(ref d)) => {
+                assert_eq!(a, &b"123".to_vec());
+                assert_eq!(b, &vec![1, 2]);
+                assert_eq!(c, &vec![255, 0]);
+                assert_eq!(d, &vec![4, 5, 6]);
+            },
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn next_image_skips_unread_rows() {
+        let encoded = b"P5 1 3 255 \x01\x02\x03P2 1 3 255 1 2 3 P5 1 1 255 \x07";
+        let mut decoder = PNMDecoder::new(&encoded[..]).unwrap();
+        let mut buf = [0; 1];
+        decoder.read_scanline(&mut buf).unwrap();
+
+        let decoder = decoder.next_image().unwrap().unwrap();
+        assert_eq!(decoder.header().subtype(), PNMSubtype::Graymap(SampleEncoding::Ascii));
+
+        let mut decoder = decoder.next_image().unwrap().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![7]),
+            _ => assert!(false),
+        }
+        assert!(decoder.next_image().unwrap().is_none());
+
+        // Truncated rasters and trailing garbage are errors
+        assert!(PNMDecoder::new(&b"P5 1 3 255 \x01"[..]).unwrap().next_image().is_err());
+        assert!(PNMDecoder::new(&b"P5 1 1 255 \x01 x"[..]).unwrap().next_image().is_err());
+    }
+
+    #[test]
+    fn huge_dimensions() {
+        let encoded = b"P7\nWIDTH 4294967295\nHEIGHT 1\nDEPTH 4\nMAXVAL 65535\nENDHDR\n";
+        let mut decoder = PNMDecoder::new(&encoded[..]).unwrap();
+        assert!(decoder.row_len().is_err());
+        match decoder.read_image() {
+            Err(ImageError::Limits(LimitErrorKind::Memory)) => {},
+            r => panic!("Expected a memory limit error, got {:?}", r.map(|_| ())),
+        }
+
+        // The samples are checked against the limits of the options before they are allocated
+        let limits = Limits { max_bytes: Some(3), ..Limits::default() };
+        let options = PNMDecoderOptions { limits: limits, ..PNMDecoderOptions::default() };
+        let mut decoder = PNMDecoder::new_with_options(&b"P5 2 2 255 \x01\x02\x03\x04"[..], options).unwrap();
+        match decoder.read_image() {
+            Err(ImageError::Limits(LimitErrorKind::Memory)) => {},
+            r => panic!("Expected a memory limit error, got {:?}", r.map(|_| ())),
+        }
+        let mut row = [0; 2];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, [1, 2]);
+    }
+
+    #[test]
+    fn read_image_into() {
+        let encoded = &b"P5 2 2 65535 \x01\x02\x03\x04\x05\x06\x07\x08"[..];
+        let mut buf = [0; 9];
+        match PNMDecoder::new(encoded).unwrap().read_image_into(&mut buf[..7]) {
+            Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort)) => {},
+            r => panic!("Expected the buffer to be too short, got {:?}", r),
+        }
+        assert_eq!(PNMDecoder::new(encoded).unwrap().read_image_into(&mut buf).unwrap(), 8);
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6, 7, 8, 0]);
+    }
+
+    #[test]
+    fn strict_parsing() {
+        let strict = PNMDecoderOptions { strict: true, ..PNMDecoderOptions::default() };
+        let decode = |encoded: &[u8]| PNMDecoder::new_with_options(encoded, strict)
+            .and_then(|mut decoder| decoder.read_image());
+
+        assert!(decode(&b"P6 1 1 255 123"[..]).is_ok());
+        assert!(decode(&b"P6#comment\n1 1 255 123"[..]).is_err());
+        match decode(&b"P61 1 255 123"[..]) {
+            Err(ImageError::Decoding { kind: DecodingErrorKind::Malformed(message), .. }) =>
+                assert_eq!(message, "Expected whitespace after the magic constant"),
+            _ => assert!(false),
+        }
+
+        // Raw samples above maxval
+        assert!(decode(&b"P5 3 1 3 \x00\x03\x02"[..]).is_ok());
+        assert!(decode(&b"P5 3 1 3 \x00\x04\x02"[..]).is_err());
+        assert!(decode(&b"P5 1 1 1000 \x03\xe9"[..]).is_err());
+        assert!(decode(&b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 1\nMAXVAL 7\nENDHDR\n\x08"[..]).is_err());
+
+        // Lenient decoding stays the default
+        assert!(PNMDecoder::new(&b"P61 1 255 123"[..]).unwrap().read_image().is_ok());
+        assert!(PNMDecoder::new(&b"P5 1 1 3 \x04"[..]).unwrap().read_image().is_ok());
+    }
+
+    #[test]
+    fn wrong_tag() {
+        assert!(PNMDecoder::new(&b"P8 1 1 255 1"[..]).is_err());
+        assert!(PNMDecoder::new(&b"Q6 1 1 255 1"[..]).is_err());
+    }
+
+    #[test]
+    fn invalid_characters() {
+        assert!(PNMDecoder::new(&b"P6 1chars1 255 1"[..]).is_err()); // No text outside of comments
+        assert!(PNMDecoder::new(&b"P6 1\xFF1 255 1"[..]).is_err()); // No invalid ascii chars
+        assert!(PNMDecoder::new(&b"P6 0x01 1 255 1"[..]).is_err()); // Numbers only as decimal
+    }
+
+    /// These violate the narrow specification of ppm but are commonly supported in other programs.
+    /// Fail fast and concise is important here as these might be received as input files.
+    #[test]
+    fn unsupported_extensions() {
+        assert!(PNMDecoder::new(&b"P6 1 1 65536 1"[..]).is_err()); // No bitwidth above 16
+    }
+}
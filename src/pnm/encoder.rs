@@ -0,0 +1,269 @@
+//! Encoding of PNM Images
+
+use std::io;
+use std::io::Write;
+
+use color;
+use color::ColorType:: {
+    Gray,
+    Palette,
+    GrayA,
+    RGB,
+    RGBA,
+    GrayF,
+    GrayAF,
+    RGBF,
+    RGBAF,
+    BGR,
+    BGRA,
+};
+use image::{ImageEncoder, ImageResult};
+
+extern crate byteorder;
+use self::byteorder::{BigEndian, ByteOrder};
+
+use super::{PNMHeader, PNMSubtype, SampleEncoding};
+
+/// Encodes images to any of the `pbm`, `pgm` or `ppm` formats.
+///
+/// The format is chosen from the color type of the image: bit packed `Gray(1)` images are written
+/// as bitmaps, other gray images as graymaps and color images as pixmaps. Alpha channels are
+/// dropped.
+pub struct PNMEncoder<'a, W: 'a> {
+    w: &'a mut W,
+    options: PNMEncoderOptions,
+    comments: Vec<String>,
+}
+
+/// Settings controlling the output of a `PNMEncoder`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PNMEncoderOptions {
+    /// Whether to write raw binary samples or plain decimal ones (`P1`, `P2` and `P3`).
+    pub encoding: SampleEncoding,
+
+    /// The length after which lines of plain samples are wrapped.
+    ///
+    /// Defaults to 70, the longest line netpbm allows. A sample is never split, so lines
+    /// containing a single sample may still be longer.
+    pub line_width: usize,
+}
+
+impl Default for PNMEncoderOptions {
+    fn default() -> PNMEncoderOptions {
+        PNMEncoderOptions {
+            encoding: SampleEncoding::Binary,
+            line_width: 70,
+        }
+    }
+}
+
+impl<'a, W: Write> PNMEncoder<'a, W> {
+    /// Create a new PNMEncoder from the Writer ```w```.
+    pub fn new(w: &mut W) -> PNMEncoder<W> {
+        PNMEncoder::new_with_options(w, PNMEncoderOptions::default())
+    }
+
+    /// Create a new PNMEncoder from the Writer ```w``` using the settings ```options```.
+    pub fn new_with_options(w: &mut W, options: PNMEncoderOptions) -> PNMEncoder<W> {
+        PNMEncoder {
+            w: w,
+            options: options,
+            comments: Vec::new(),
+        }
+    }
+
+    /// Add a comment to the header of the encoded images, following any added before.
+    ///
+    /// Each line of ```comment``` is written as a separate comment line, empty lines are skipped.
+    pub fn add_comment(&mut self, comment: &str) {
+        let lines = comment.split(|c| c == '\n' || c == '\r').filter(|line| !line.is_empty());
+        self.comments.extend(lines.map(|line| line.to_string()));
+    }
+
+    /// Encode the buffer ```im``` as a PNM image.
+    /// ```width``` and ```height``` are the dimensions of the buffer.
+    /// ```color``` is the buffers ColorType.
+    ///
+    /// 16-bit samples are expected to be stored in big endian order, as they are in the file.
+    pub fn encode(&mut self, im: &[u8], width: u32, height: u32, color: color::ColorType) -> io::Result<()> {
+        let header = try!(header_for(width, height, color, self.options.encoding));
+        let expected = try!(buffer_len(width, height, color));
+        if im.len() < expected {
+            return Err(invalid_input(format!(
+                "Image buffer of {} bytes is too small for a {}x{} {:?} image", im.len(), width, height, color)))
+        }
+
+        try!(self.write_header(&header));
+        self.write_image(&im[..expected], color, &header)
+    }
+
+    fn write_header(&mut self, header: &PNMHeader) -> io::Result<()> {
+        try!(self.w.write_all(header.subtype().magic_constant()));
+        try!(self.w.write_all(b"\n"));
+        for comment in &self.comments {
+            try!(write!(self.w, "# {}\n", comment));
+        }
+        match header.subtype() {
+            PNMSubtype::Bitmap(_) => write!(self.w, "{0} {1}\n", header.width(), header.height()),
+            _ => write!(self.w, "{0} {1}\n{2}\n", header.width(), header.height(), header.maxval()),
+        }
+    }
+
+    fn write_image(&mut self, buf: &[u8], color: color::ColorType, header: &PNMHeader) -> io::Result<()> {
+        match self.options.encoding {
+            SampleEncoding::Binary => self.write_binary_image(buf, color, header),
+            SampleEncoding::Ascii => self.write_ascii_image(buf, color, header),
+        }
+    }
+
+    fn write_binary_image(&mut self, buf: &[u8], color: color::ColorType, header: &PNMHeader) -> io::Result<()> {
+        match color {
+            // Bitmaps use 1 for black while gray images use it for white
+            Gray(1) => {
+                let unused = (8 - header.width() % 8) % 8;
+                let row_bytes = ((header.width() + 7) / 8) as usize;
+                for row in buf.chunks(row_bytes) {
+                    let mut inverted: Vec<u8> = row.iter().map(|&byte| !byte).collect();
+                    if let Some(last) = inverted.last_mut() {
+                        // The padding bits are kept clear
+                        *last &= 0xffu8 << unused;
+                    }
+                    try!(self.w.write_all(&inverted));
+                }
+            }
+            Gray(8) | Gray(16) | RGB(8) | RGB(16) => try!(self.w.write_all(buf)),
+            GrayA(n) | RGBA(n) => {
+                let bytes = n as usize / 8;
+                let pixel = (color::num_components(color)) * bytes;
+                for x in buf.chunks(pixel) {
+                    try!(self.w.write_all(&x[..pixel - bytes]));
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    fn write_ascii_image(&mut self, buf: &[u8], color: color::ColorType, header: &PNMHeader) -> io::Result<()> {
+        let mut out = LineWrapper::new(&mut *self.w, self.options.line_width);
+        match color {
+            Gray(1) => {
+                let width = header.width() as usize;
+                for row in buf.chunks((width + 7) / 8) {
+                    for x in 0..width {
+                        let bit = (row[x / 8] >> (7 - x % 8)) & 1;
+                        try!(out.write_token(if bit == 0 { b"1" } else { b"0" }));
+                    }
+                }
+            }
+            _ => {
+                let bytes = match color {
+                    Gray(n) | GrayA(n) | RGB(n) | RGBA(n) | Palette(n) |
+                    GrayF(n) | GrayAF(n) | RGBF(n) | RGBAF(n) | BGR(n) | BGRA(n) => n as usize / 8,
+                };
+                let channels = color::num_components(color);
+                let kept = match color {
+                    GrayA(_) | RGBA(_) => channels - 1,
+                    _ => channels,
+                };
+                for pixel in buf.chunks(channels * bytes) {
+                    for sample in pixel[..kept * bytes].chunks(bytes) {
+                        let value = if bytes == 1 { sample[0] as u16 } else { BigEndian::read_u16(sample) };
+                        try!(out.write_token(value.to_string().as_bytes()));
+                    }
+                }
+            }
+        }
+
+        out.finish()
+    }
+}
+
+impl<'a, W: Write> ImageEncoder for PNMEncoder<'a, W> {
+    fn write_image(mut self, buf: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Writes whitespace separated tokens, breaking lines before they exceed a maximum width
+struct LineWrapper<'a, W: 'a> {
+    w: &'a mut W,
+    line_width: usize,
+    column: usize,
+}
+
+impl<'a, W: Write> LineWrapper<'a, W> {
+    fn new(w: &'a mut W, line_width: usize) -> LineWrapper<'a, W> {
+        LineWrapper {
+            w: w,
+            line_width: line_width,
+            column: 0,
+        }
+    }
+
+    fn write_token(&mut self, token: &[u8]) -> io::Result<()> {
+        if self.column > 0 {
+            if self.column + 1 + token.len() > self.line_width {
+                try!(self.w.write_all(b"\n"));
+                self.column = 0;
+            } else {
+                try!(self.w.write_all(b" "));
+                self.column += 1;
+            }
+        }
+        self.column += token.len();
+        self.w.write_all(token)
+    }
+
+    /// Terminates the last line
+    fn finish(self) -> io::Result<()> {
+        if self.column > 0 {
+            self.w.write_all(b"\n")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Choose the header matching the color type of an image
+fn header_for(width: u32, height: u32, color: color::ColorType, encoding: SampleEncoding)
+    -> io::Result<PNMHeader> {
+    let subtype = match color {
+        Gray(1) => PNMSubtype::Bitmap(encoding),
+        Gray(8) | Gray(16) | GrayA(8) | GrayA(16) => PNMSubtype::Graymap(encoding),
+        RGB(8) | RGB(16) | RGBA(8) | RGBA(16) => PNMSubtype::Pixmap(encoding),
+        Gray(_) | GrayA(_) | RGB(_) | RGBA(_) | Palette(_) |
+        GrayF(_) | GrayAF(_) | RGBF(_) | RGBAF(_) | BGR(_) | BGRA(_) => return Err(invalid_input(
+            format!("PNM: Can not encode images of color type {:?}", color))),
+    };
+
+    Ok(PNMHeader::new(subtype, width, height, max_pixel_value(color) as u32))
+}
+
+/// The number of bytes of an image buffer, rows of bit packed images are padded to full bytes
+fn buffer_len(width: u32, height: u32, color: color::ColorType) -> io::Result<usize> {
+    let row_bits = (width as u64) * color::bits_per_pixel(color) as u64;
+    let len = ((row_bits + 7) / 8).checked_mul(height as u64);
+    match len {
+        Some(len) if len <= usize::max_value() as u64 => Ok(len as usize),
+        _ => Err(invalid_input(format!("PNM: Image dimensions {}x{} are too large", width, height))),
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn max_pixel_value(pixel_type: color::ColorType) -> u16 {
+    let max = match pixel_type {
+        Gray(n) | RGB(n) | Palette(n) | GrayA(n) | RGBA(n) |
+        GrayF(n) | RGBF(n) | GrayAF(n) | RGBAF(n) | BGR(n) | BGRA(n) => 2u32.pow(n as u32) - 1,
+    };
+
+    if max > 65535 {
+      panic!("PNM: Trying to encode image with more than 16bit per pixel");
+    } else {
+      max as u16
+    }
+}
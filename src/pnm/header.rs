@@ -0,0 +1,205 @@
+/// The kind of encoding used to store sample values
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleEncoding {
+    /// Samples are unsigned binary integers in big endian
+    Binary,
+
+    /// Samples are encoded as decimal ascii strings separated by whitespace
+    Ascii,
+}
+
+/// Denotes the category of the magic number
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PNMSubtype {
+    /// Magic numbers P1 and P4
+    Bitmap(SampleEncoding),
+
+    /// Magic numbers P2 and P5
+    Graymap(SampleEncoding),
+
+    /// Magic numbers P3 and P6
+    Pixmap(SampleEncoding),
+
+    /// Magic number P7
+    ArbitraryMap,
+}
+
+/// Standardized tuple type specifiers in the header of a `pam`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ArbitraryTuplType {
+    /// Pixels are either black (0) or white (1)
+    BlackAndWhite,
+
+    /// Pixels are either black (0) or white (1) and a second alpha channel
+    BlackAndWhiteAlpha,
+
+    /// Pixels represent the amount of white
+    Grayscale,
+
+    /// Grayscale with an additional alpha channel
+    GrayscaleAlpha,
+
+    /// Three channels: Red, Green, Blue
+    RGB,
+
+    /// Four channels: Red, Green, Blue, Alpha
+    RGBAlpha,
+
+    /// An image format which is not standardized
+    Custom(String),
+}
+
+/// The header of a pnm image, as it was read from the stream
+#[derive(Clone, Debug)]
+pub struct PNMHeader {
+    subtype: PNMSubtype,
+    width: u32,
+    height: u32,
+    depth: u32,
+    maxval: u32,
+    tupltype: Option<ArbitraryTuplType>,
+}
+
+impl PNMSubtype {
+    /// Get the two magic constant bytes corresponding to this format subtype.
+    pub fn magic_constant(self) -> &'static [u8; 2] {
+        match self {
+            PNMSubtype::Bitmap(SampleEncoding::Ascii) => b"P1",
+            PNMSubtype::Graymap(SampleEncoding::Ascii) => b"P2",
+            PNMSubtype::Pixmap(SampleEncoding::Ascii) => b"P3",
+            PNMSubtype::Bitmap(SampleEncoding::Binary) => b"P4",
+            PNMSubtype::Graymap(SampleEncoding::Binary) => b"P5",
+            PNMSubtype::Pixmap(SampleEncoding::Binary) => b"P6",
+            PNMSubtype::ArbitraryMap => b"P7",
+        }
+    }
+
+    /// Identify the subtype from the second byte of its magic constant.
+    pub fn from_magic_byte(byte: u8) -> Option<PNMSubtype> {
+        match byte {
+            b'1' => Some(PNMSubtype::Bitmap(SampleEncoding::Ascii)),
+            b'2' => Some(PNMSubtype::Graymap(SampleEncoding::Ascii)),
+            b'3' => Some(PNMSubtype::Pixmap(SampleEncoding::Ascii)),
+            b'4' => Some(PNMSubtype::Bitmap(SampleEncoding::Binary)),
+            b'5' => Some(PNMSubtype::Graymap(SampleEncoding::Binary)),
+            b'6' => Some(PNMSubtype::Pixmap(SampleEncoding::Binary)),
+            b'7' => Some(PNMSubtype::ArbitraryMap),
+            _ => None,
+        }
+    }
+
+    /// Whether samples are stored as binary or as decimal ascii
+    pub fn sample_encoding(self) -> SampleEncoding {
+        match self {
+            PNMSubtype::Bitmap(e) | PNMSubtype::Graymap(e) | PNMSubtype::Pixmap(e) => e,
+            PNMSubtype::ArbitraryMap => SampleEncoding::Binary,
+        }
+    }
+}
+
+impl ArbitraryTuplType {
+    /// Parse the value of a `TUPLTYPE` header line.
+    pub fn from_name(name: &str) -> ArbitraryTuplType {
+        match name {
+            "BLACKANDWHITE" => ArbitraryTuplType::BlackAndWhite,
+            "BLACKANDWHITE_ALPHA" => ArbitraryTuplType::BlackAndWhiteAlpha,
+            "GRAYSCALE" => ArbitraryTuplType::Grayscale,
+            "GRAYSCALE_ALPHA" => ArbitraryTuplType::GrayscaleAlpha,
+            "RGB" => ArbitraryTuplType::RGB,
+            "RGB_ALPHA" => ArbitraryTuplType::RGBAlpha,
+            custom => ArbitraryTuplType::Custom(custom.to_string()),
+        }
+    }
+
+    /// The name of this tuple type as written in a `TUPLTYPE` header line.
+    pub fn name(&self) -> &str {
+        match *self {
+            ArbitraryTuplType::BlackAndWhite => "BLACKANDWHITE",
+            ArbitraryTuplType::BlackAndWhiteAlpha => "BLACKANDWHITE_ALPHA",
+            ArbitraryTuplType::Grayscale => "GRAYSCALE",
+            ArbitraryTuplType::GrayscaleAlpha => "GRAYSCALE_ALPHA",
+            ArbitraryTuplType::RGB => "RGB",
+            ArbitraryTuplType::RGBAlpha => "RGB_ALPHA",
+            ArbitraryTuplType::Custom(ref custom) => custom,
+        }
+    }
+
+    /// The depth required by the tuple type, `None` for custom types.
+    pub fn depth(&self) -> Option<u32> {
+        match *self {
+            ArbitraryTuplType::BlackAndWhite | ArbitraryTuplType::Grayscale => Some(1),
+            ArbitraryTuplType::BlackAndWhiteAlpha | ArbitraryTuplType::GrayscaleAlpha => Some(2),
+            ArbitraryTuplType::RGB => Some(3),
+            ArbitraryTuplType::RGBAlpha => Some(4),
+            ArbitraryTuplType::Custom(_) => None,
+        }
+    }
+}
+
+impl PNMHeader {
+    /// Construct a header for one of the formats with a fixed number of channels.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `subtype` is `ArbitraryMap`, use `new_arbitrary` for those instead.
+    pub fn new(subtype: PNMSubtype, width: u32, height: u32, maxval: u32) -> PNMHeader {
+        let depth = match subtype {
+            PNMSubtype::Bitmap(_) | PNMSubtype::Graymap(_) => 1,
+            PNMSubtype::Pixmap(_) => 3,
+            PNMSubtype::ArbitraryMap => panic!("Arbitrary maps need an explicit depth"),
+        };
+        PNMHeader {
+            subtype: subtype,
+            width: width,
+            height: height,
+            depth: depth,
+            maxval: maxval,
+            tupltype: None,
+        }
+    }
+
+    /// Construct the header of a `pam` image.
+    pub fn new_arbitrary(width: u32, height: u32, depth: u32, maxval: u32,
+                         tupltype: Option<ArbitraryTuplType>) -> PNMHeader {
+        PNMHeader {
+            subtype: PNMSubtype::ArbitraryMap,
+            width: width,
+            height: height,
+            depth: depth,
+            maxval: maxval,
+            tupltype: tupltype,
+        }
+    }
+
+    /// Retrieve the format subtype from which the header was created.
+    pub fn subtype(&self) -> PNMSubtype {
+        self.subtype
+    }
+
+    /// The width of the image this header is for.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image this header is for.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of samples making up each pixel.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The tuple type of a `pam` image, if its header declared one.
+    pub fn tupltype(&self) -> Option<&ArbitraryTuplType> {
+        self.tupltype.as_ref()
+    }
+
+    /// The biggest value a sample can have, the sample values are scaled relative to it.
+    ///
+    /// Bitmaps do not store a maxval, their samples are single bits so it is always 1.
+    pub fn maxval(&self) -> u32 {
+        self.maxval
+    }
+}
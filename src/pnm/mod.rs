@@ -0,0 +1,254 @@
+//! Decoding and Encoding of netpbm images
+//!
+//! The decoder reads bitmaps (pbm, `P1` and `P4`), graymaps (pgm, `P2` and `P5`) and pixmaps
+//! (ppm, `P3` and `P6`) in both their plain and raw variants. Bitmaps are expanded to 8-bit
+//! grayscale. Arbitrary maps (pam, `P7`) with the tuple types `BLACKANDWHITE`, `GRAYSCALE`, `RGB`
+//! and their `_ALPHA` variants are supported as well. The encoder writes bitmaps, graymaps and
+//! pixmaps, either raw or plain as selected by its `PNMEncoderOptions`.
+//!
+//! Samples are scaled to the full range of 8 or 16 bits when decoding, unless disabled in the
+//! `PNMDecoderOptions`. Streams of concatenated images are read one after the other with
+//! `PNMDecoder::next_image`.
+
+pub use self::encoder::{PNMEncoder, PNMEncoderOptions};
+pub use self::decoder::{PNMDecoder, PNMDecoderOptions};
+pub use self::header::{ArbitraryTuplType, PNMHeader, PNMSubtype, SampleEncoding};
+
+mod encoder;
+mod decoder;
+mod header;
+
+#[cfg(test)]
+mod test {
+    use color::ColorType;
+    use image::{ImageDecoder, DecodingResult};
+
+    #[test]
+    fn test_roundtrip_ppm() {
+        // 3x3 image that tries all the 0/255 RGB combinations
+        let buf: [u8; 27] = [
+              0,   0,   0,
+              0,   0, 255,
+              0, 255,   0,
+              0, 255, 255,
+            255,   0,   0,
+            255,   0, 255,
+            255, 255,   0,
+            255, 255, 255,
+            255, 255, 255,
+        ];
+
+        let mut stream = Vec::<u8>::new();
+        {
+            let mut encoder = super::PNMEncoder::new(&mut stream);
+            match encoder.encode(&buf, 3, 3, ColorType::RGB(8)) {
+                Ok(_) => {},
+                Err(_) => panic!("PNM encoder failed"),
+            };
+        }
+
+        let mut decoder = match super::PNMDecoder::new(&stream[..]) {
+            Ok(img) => img,
+            Err(e) => panic!("PPM decoder failed with {}", e),
+        };
+        match decoder.read_image() {
+            Ok(DecodingResult::U8(vec)) => {
+                assert_eq!(&buf[..], &vec[..]);
+            },
+            r => {
+                panic!("PPM: Got a strange image result {:?}", r);
+            }
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_ppm_aliases() {
+        let mut encoded = Vec::new();
+        ::ppm::PPMEncoder::new(&mut encoded).encode(&[1, 2, 3], 1, 1, ColorType::RGB(8)).unwrap();
+        assert_eq!(&encoded[..2], b"P6");
+        let mut decoder = ::ppm::PPMDecoder::new(&encoded[..]).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![1, 2, 3]),
+            r => panic!("PPM: Got a strange image result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_ppm_16bit() {
+        // 3x3 image that tries all the 0/65535 RGB combinations plus a few more values
+        // that check for big-endian conversion correctness
+        let buf: [u16; 27] = [
+                0,     0,     0,
+                0,     0, 65535,
+                0, 65535,     0,
+                0, 65535, 65535,
+            65535,     0,     0,
+            65535,     0, 65535,
+            65535, 65535,     0,
+            65535, 65535, 65535,
+             1000,  2000,  3000,
+        ];
+        let mut bytebuf = [0 as u8; 54];
+        for (o, i) in bytebuf.chunks_mut(2).zip(buf.iter()) {
+          o[0] = (i >> 8) as u8;
+          o[1] = (i & 0xff) as u8;
+        }
+
+        let mut stream = Vec::<u8>::new();
+        {
+            let mut encoder = super::PNMEncoder::new(&mut stream);
+            match encoder.encode(&bytebuf, 3, 3, ColorType::RGB(16)) {
+                Ok(_) => {},
+                Err(_) => panic!("PNM encoder failed"),
+            };
+        }
+
+        let mut decoder = match super::PNMDecoder::new(&stream[..]) {
+            Ok(img) => img,
+            Err(e) => panic!("PPM decoder failed with {}", e),
+        };
+        match decoder.read_image() {
+            Ok(DecodingResult::U16(vec)) => {
+                assert_eq!(&buf[..], &vec[..]);
+            },
+            r => {
+                panic!("PPM: Got a strange image result {:?}", r);
+            }
+        }
+    }
+
+    #[test]
+    fn test_16bit_output() {
+        let mut stream = Vec::<u8>::new();
+        super::PNMEncoder::new(&mut stream).encode(&[0x12, 0x34, 0x00, 0xff, 0xff, 0x00], 1, 1, ColorType::RGB(16)).unwrap();
+        assert_eq!(&stream[..], &b"P6\n1 1\n65535\n\x12\x34\x00\xff\xff\x00"[..]);
+
+        let mut stream = Vec::<u8>::new();
+        super::PNMEncoder::new(&mut stream).encode(&[0xab, 0xcd, 0x00, 0x01], 2, 1, ColorType::Gray(16)).unwrap();
+        assert_eq!(&stream[..], &b"P5\n2 1\n65535\n\xab\xcd\x00\x01"[..]);
+    }
+
+    fn roundtrip(buf: &[u8], width: u32, height: u32, color: ColorType) -> (ColorType, DecodingResult) {
+        let mut stream = Vec::<u8>::new();
+        super::PNMEncoder::new(&mut stream).encode(buf, width, height, color).unwrap();
+
+        let mut decoder = super::PNMDecoder::new(&stream[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (width, height));
+        (decoder.colortype().unwrap(), decoder.read_image().unwrap())
+    }
+
+    #[test]
+    fn test_roundtrip_pgm() {
+        let buf: [u8; 6] = [0, 1, 127, 128, 254, 255];
+        match roundtrip(&buf, 3, 2, ColorType::Gray(8)) {
+            (ColorType::Gray(8), DecodingResult::U8(vec)) => assert_eq!(&buf[..], &vec[..]),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+
+        let mut stream = Vec::<u8>::new();
+        super::PNMEncoder::new(&mut stream).encode(&buf, 3, 2, ColorType::Gray(8)).unwrap();
+        assert_eq!(&stream[..11], b"P5\n3 2\n255\n");
+    }
+
+    #[test]
+    fn test_roundtrip_pgm_16bit() {
+        let buf: [u8; 4] = [0x12, 0x34, 0xff, 0x00];
+        match roundtrip(&buf, 1, 2, ColorType::Gray(16)) {
+            (ColorType::Gray(16), DecodingResult::U16(vec)) => assert_eq!(vec, vec![0x1234, 0xff00]),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_pbm() {
+        // 10 pixels per row, packed into two bytes of which only the high bits of the second are used
+        let buf: [u8; 4] = [0b1010_0101, 0b1100_0000, 0b0000_1111, 0b0011_1111];
+        match roundtrip(&buf, 10, 2, ColorType::Gray(1)) {
+            (ColorType::Gray(8), DecodingResult::U8(vec)) => assert_eq!(vec, vec![
+                255, 0, 255, 0, 0, 255, 0, 255, 255, 255,
+                0, 0, 0, 0, 255, 255, 255, 255, 0, 0,
+            ]),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_alpha_is_dropped() {
+        let buf: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+        match roundtrip(&buf, 2, 1, ColorType::RGBA(8)) {
+            (ColorType::RGB(8), DecodingResult::U8(vec)) => assert_eq!(vec, vec![1, 2, 3, 5, 6, 7]),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+        match roundtrip(&buf, 2, 2, ColorType::GrayA(8)) {
+            (ColorType::Gray(8), DecodingResult::U8(vec)) => assert_eq!(vec, vec![1, 3, 5, 7]),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_comments() {
+        let mut stream = Vec::<u8>::new();
+        {
+            let mut encoder = super::PNMEncoder::new(&mut stream);
+            encoder.add_comment("camera 1");
+            encoder.add_comment("exposure 1/60\r\ngain 2");
+            encoder.encode(&[1, 2], 2, 1, ColorType::Gray(8)).unwrap();
+        }
+        assert_eq!(&stream[..], &b"P5\n# camera 1\n# exposure 1/60\n# gain 2\n2 1\n255\n\x01\x02"[..]);
+
+        let decoder = super::PNMDecoder::new(&stream[..]).unwrap();
+        assert_eq!(decoder.comments(), &["camera 1".to_string(), "exposure 1/60".to_string(),
+                                         "gain 2".to_string()][..]);
+    }
+
+    #[test]
+    fn test_unsupported_input() {
+        let mut stream = Vec::<u8>::new();
+        let mut encoder = super::PNMEncoder::new(&mut stream);
+        assert!(encoder.encode(&[0; 3], 3, 1, ColorType::Palette(8)).is_err());
+        assert!(encoder.encode(&[0; 3], 3, 1, ColorType::Gray(4)).is_err());
+        assert!(encoder.encode(&[0; 3], 3, 1, ColorType::RGB(8)).is_err()); // Buffer too small
+    }
+
+    fn encode_plain(buf: &[u8], width: u32, height: u32, color: ColorType, line_width: usize) -> Vec<u8> {
+        let options = super::PNMEncoderOptions {
+            encoding: super::SampleEncoding::Ascii,
+            line_width: line_width,
+        };
+        let mut stream = Vec::<u8>::new();
+        super::PNMEncoder::new_with_options(&mut stream, options)
+            .encode(buf, width, height, color).unwrap();
+        stream
+    }
+
+    #[test]
+    fn test_plain_output() {
+        assert_eq!(encode_plain(&[0b1010_0000], 3, 1, ColorType::Gray(1), 70), b"P1\n3 1\n0 1 0\n".to_vec());
+        assert_eq!(encode_plain(&[0, 17, 255], 3, 1, ColorType::Gray(8), 70), b"P2\n3 1\n255\n0 17 255\n".to_vec());
+        assert_eq!(encode_plain(&[0x01, 0x00, 0xff, 0xff], 2, 1, ColorType::Gray(16), 70),
+                   b"P2\n2 1\n65535\n256 65535\n".to_vec());
+        assert_eq!(encode_plain(&[1, 2, 3, 4], 1, 1, ColorType::RGBA(8), 70), b"P3\n1 1\n255\n1 2 3\n".to_vec());
+    }
+
+    #[test]
+    fn test_plain_line_wrapping() {
+        let buf = [100, 200, 50, 5, 255, 0];
+        assert_eq!(encode_plain(&buf, 2, 1, ColorType::RGB(8), 8), b"P3\n2 1\n255\n100 200\n50 5 255\n0\n".to_vec());
+        // Tokens are never split, even if they do not fit a line
+        assert_eq!(encode_plain(&buf, 2, 1, ColorType::RGB(8), 2), b"P3\n2 1\n255\n100\n200\n50\n5\n255\n0\n".to_vec());
+    }
+
+    #[test]
+    fn test_roundtrip_plain() {
+        let buf: Vec<u8> = (0..60).map(|v| (v * 4) as u8).collect();
+        let stream = encode_plain(&buf, 4, 5, ColorType::RGB(8), 70);
+        assert!(stream.split(|&b| b == b'\n').all(|line| line.len() <= 70));
+
+        let mut decoder = super::PNMDecoder::new(&stream[..]).unwrap();
+        match decoder.read_image() {
+            Ok(DecodingResult::U8(vec)) => assert_eq!(buf, vec),
+            r => panic!("PNM: Got a strange image result {:?}", r),
+        }
+    }
+}
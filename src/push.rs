@@ -0,0 +1,443 @@
+//! Decoding of images from data that arrives in chunks
+//!
+//! A `PushDecoder` is fed the bytes of an image as they arrive, for example from a network
+//! connection, instead of reading them from a blocking `Read`. The header and the rows of the
+//! image are made available as soon as the data they depend on has been fed.
+
+use std::cmp;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormatHint, ImageFormat, ImageResult};
+
+#[cfg(feature = "png_codec")]
+use png;
+#[cfg(feature = "gif_codec")]
+use gif;
+#[cfg(feature = "jpeg")]
+use jpeg;
+#[cfg(feature = "webp")]
+use webp;
+#[cfg(feature = "tiff")]
+use tiff;
+#[cfg(feature = "tga")]
+use tga;
+#[cfg(feature = "bmp")]
+use bmp;
+#[cfg(feature = "ico")]
+use ico;
+#[cfg(feature = "hdr")]
+use hdr;
+#[cfg(feature = "exr")]
+use exr;
+#[cfg(feature = "pnm")]
+use pnm;
+#[cfg(feature = "qoi")]
+use qoi;
+#[cfg(feature = "farbfeld")]
+use farbfeld;
+#[cfg(feature = "dds")]
+use dds;
+#[cfg(feature = "avif")]
+use avif;
+#[cfg(feature = "heif")]
+use heif;
+#[cfg(feature = "sgi")]
+use sgi;
+#[cfg(feature = "xbm")]
+use xbm;
+#[cfg(feature = "xpm")]
+use xpm;
+#[cfg(feature = "dng")]
+use dng;
+
+/// What a `PushDecoder` could decode after it was fed more data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// Nothing new could be decoded, more data is needed
+    NeedMoreData,
+    /// The header has been decoded, so the dimensions, the color type and the color profile
+    /// are known
+    Header,
+    /// New rows have been decoded, the number is that of all rows decoded so far
+    Rows(u32),
+    /// The whole image has been decoded
+    Done,
+}
+
+/// The data fed so far, which runs out with a ```WouldBlock``` error until the end of the
+/// stream is known
+struct Partial<'a> {
+    data: &'a [u8],
+    pos: usize,
+    complete: bool,
+}
+
+fn pending() -> io::Error {
+    io::Error::new(io::ErrorKind::WouldBlock, "More image data is needed")
+}
+
+impl<'a> Read for Partial<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let available = try!(self.fill_buf());
+            let len = cmp::min(available.len(), buf.len());
+            buf[..len].copy_from_slice(&available[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<'a> BufRead for Partial<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos >= self.data.len() && !self.complete {
+            return Err(pending())
+        }
+        Ok(&self.data[cmp::min(self.pos, self.data.len())..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt;
+    }
+}
+
+impl<'a> Seek for Partial<'a> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos as i64,
+            SeekFrom::Current(offset) => self.pos as i64 + offset,
+            // The length is not known before the end of the stream
+            SeekFrom::End(_) if !self.complete => return Err(pending()),
+            SeekFrom::End(offset) => self.data.len() as i64 + offset,
+        };
+        if pos < 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the image"))
+        }
+        self.pos = pos as usize;
+        Ok(pos as u64)
+    }
+}
+
+/// The header of the image and the rows that have not been taken yet
+#[derive(Default)]
+struct State {
+    header: Option<(u32, u32, ColorType)>,
+    icc_profile: Option<Vec<u8>>,
+    row_len: usize,
+    decoded_rows: u32,
+    rows: Vec<u8>,
+}
+
+impl State {
+    /// Decodes the image with ```decoder```, keeping the rows which were not decoded before
+    ///
+    /// Images are decoded row by row if ```by_rows``` is true, and as a whole otherwise.
+    fn decode<D: ImageDecoder>(&mut self, decoder: ImageResult<D>, by_rows: bool) -> ImageResult<()> {
+        let mut decoder = try!(decoder);
+        if self.header.is_none() {
+            let (width, height) = try!(decoder.dimensions());
+            let color = try!(decoder.colortype());
+            self.icc_profile = try!(decoder.icc_profile());
+            self.header = Some((width, height, color));
+        }
+        let (_, height, _) = self.header.unwrap();
+
+        if !by_rows {
+            return decoder.read_image().map(|image| self.set_image(image))
+        }
+        self.row_len = try!(decoder.row_len());
+        let mut row = vec![0; self.row_len];
+        for y in 0..height {
+            try!(decoder.read_scanline(&mut row));
+            if y == self.decoded_rows {
+                self.rows.extend_from_slice(&row);
+                self.decoded_rows += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps all rows of the decoded ```image```, with samples of more than 8 bits in big
+    /// endian order
+    fn set_image(&mut self, image: DecodingResult) {
+        let (_, height, _) = self.header.unwrap();
+        self.rows = match image {
+            DecodingResult::U8(data) => data,
+            DecodingResult::U16(data) => {
+                let mut bytes = vec![0; data.len() * 2];
+                BigEndian::write_u16_into(&data, &mut bytes);
+                bytes
+            }
+            DecodingResult::U32(data) => {
+                let mut bytes = vec![0; data.len() * 4];
+                BigEndian::write_u32_into(&data, &mut bytes);
+                bytes
+            }
+            DecodingResult::F32(data) => {
+                let mut bytes = vec![0; data.len() * 4];
+                BigEndian::write_f32_into(&data, &mut bytes);
+                bytes
+            }
+        };
+        self.row_len = if height == 0 { 0 } else { self.rows.len() / height as usize };
+        self.decoded_rows = height;
+    }
+}
+
+/// A decoder that is fed the data of an image in chunks
+///
+/// All data fed so far is kept, and the image is decoded again from its start whenever more
+/// data arrives, skipping the rows that have been decoded before. Formats whose decoders can
+/// not decode single rows yet, like JPEG, TIFF and interlaced PNG, return all rows at once.
+pub struct PushDecoder {
+    format: ImageFormat,
+    data: Vec<u8>,
+    state: State,
+    done: bool,
+}
+
+impl PushDecoder {
+    /// Creates a decoder for an image of format ```format```
+    pub fn new(format: ImageFormat) -> PushDecoder {
+        PushDecoder {
+            format: format,
+            data: Vec::new(),
+            state: State::default(),
+            done: false,
+        }
+    }
+
+    /// Adds the next chunk ```data``` of the image and decodes as much as possible
+    ///
+    /// Errors other than running out of data are returned as soon as they are found.
+    pub fn feed(&mut self, data: &[u8]) -> ImageResult<Progress> {
+        if self.done {
+            return Ok(Progress::Done)
+        }
+        self.data.extend_from_slice(data);
+        self.update(false)
+    }
+
+    /// Signals the end of the data, decoding the rest of the image
+    ///
+    /// Images that are still incomplete are decoded like truncated files, which is an error
+    /// for most formats.
+    pub fn finish(&mut self) -> ImageResult<Progress> {
+        if self.done {
+            return Ok(Progress::Done)
+        }
+        self.update(true)
+    }
+
+    /// Returns the dimensions of the image once its header has been decoded
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.state.header.map(|(width, height, _)| (width, height))
+    }
+
+    /// Returns the color type of the image once its header has been decoded
+    pub fn colortype(&self) -> Option<ColorType> {
+        self.state.header.map(|(_, _, color)| color)
+    }
+
+    /// Returns the ICC color profile embedded in the image, once the header has been decoded
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.state.icc_profile.as_ref().map(|profile| &profile[..])
+    }
+
+    /// Returns the length in bytes of a row, once rows have been decoded
+    pub fn row_len(&self) -> usize {
+        self.state.row_len
+    }
+
+    /// Returns the rows decoded since the last call, with samples of more than 8 bits in big
+    /// endian order
+    pub fn take_rows(&mut self) -> Vec<u8> {
+        let rows = Vec::new();
+        ::std::mem::replace(&mut self.state.rows, rows)
+    }
+
+    fn update(&mut self, complete: bool) -> ImageResult<Progress> {
+        let (had_header, rows) = (self.state.header.is_some(), self.state.decoded_rows);
+        match self.decode(complete) {
+            Ok(()) => {
+                self.done = true;
+                return Ok(Progress::Done)
+            }
+            Err(ImageError::IoError(ref err)) if err.kind() == io::ErrorKind::WouldBlock => (),
+            Err(err) => return Err(err),
+        }
+
+        Ok(if self.state.decoded_rows > rows {
+            Progress::Rows(self.state.decoded_rows)
+        } else if !had_header && self.state.header.is_some() {
+            Progress::Header
+        } else {
+            Progress::NeedMoreData
+        })
+    }
+
+    fn decode(&mut self, complete: bool) -> ImageResult<()> {
+        let r = Partial { data: &self.data, pos: 0, complete: complete };
+        let state = &mut self.state;
+        match self.format {
+            #[cfg(feature = "png_codec")]
+            ImageFormat::PNG => state.decode(Ok(png::PNGDecoder::new(r)), true),
+            #[cfg(feature = "gif_codec")]
+            ImageFormat::GIF => state.decode(Ok(gif::Decoder::new(r)), false),
+            #[cfg(feature = "jpeg")]
+            ImageFormat::JPEG => state.decode(Ok(jpeg::JPEGDecoder::new(r)), false),
+            #[cfg(feature = "webp")]
+            ImageFormat::WEBP => state.decode(Ok(webp::WebpDecoder::new(r)), true),
+            #[cfg(feature = "tiff")]
+            ImageFormat::TIFF => state.decode(tiff::TIFFDecoder::new(r), false),
+            #[cfg(feature = "tga")]
+            ImageFormat::TGA => state.decode(Ok(tga::TGADecoder::new(r)), false),
+            #[cfg(feature = "bmp")]
+            ImageFormat::BMP => state.decode(Ok(bmp::BMPDecoder::new(r)), false),
+            #[cfg(feature = "ico")]
+            ImageFormat::ICO => state.decode(ico::ICODecoder::new(r), false),
+            #[cfg(feature = "hdr")]
+            ImageFormat::HDR => {
+                let decoder = try!(hdr::HDRDecoder::new(r));
+                let meta = decoder.metadata();
+                state.header = Some((meta.width, meta.height, ColorType::RGBF(32)));
+                let pixels = try!(decoder.read_image_hdr());
+                let samples = pixels.iter().flat_map(|p| p.data.iter().cloned()).collect();
+                state.set_image(DecodingResult::F32(samples));
+                Ok(())
+            }
+            #[cfg(feature = "exr")]
+            ImageFormat::EXR => state.decode(Ok(exr::EXRDecoder::new(r)), false),
+            #[cfg(feature = "pnm")]
+            #[allow(deprecated)]
+            ImageFormat::PNM | ImageFormat::PPM => state.decode(pnm::PNMDecoder::new(r), true),
+            #[cfg(feature = "qoi")]
+            ImageFormat::QOI => state.decode(Ok(qoi::QOIDecoder::new(r)), true),
+            #[cfg(feature = "farbfeld")]
+            ImageFormat::Farbfeld => state.decode(Ok(farbfeld::FarbfeldDecoder::new(r)), true),
+            #[cfg(feature = "dds")]
+            ImageFormat::DDS => state.decode(dds::DDSDecoder::new(r), true),
+            #[cfg(feature = "avif")]
+            ImageFormat::AVIF => state.decode(avif::AVIFDecoder::new(r), true),
+            #[cfg(feature = "heif")]
+            ImageFormat::HEIF => state.decode(heif::HEIFDecoder::new(r), true),
+            #[cfg(feature = "sgi")]
+            ImageFormat::SGI => state.decode(sgi::SGIDecoder::new(r), true),
+            #[cfg(feature = "xbm")]
+            ImageFormat::XBM => state.decode(xbm::XBMDecoder::new(r), true),
+            #[cfg(feature = "xpm")]
+            ImageFormat::XPM => state.decode(xpm::XPMDecoder::new(r), true),
+            #[cfg(feature = "dng")]
+            ImageFormat::DNG => state.decode(dng::DNGDecoder::new(r), true),
+            format => Err(ImageError::unsupported_format(ImageFormatHint::Exact(format))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Progress, PushDecoder};
+    use color::ColorType;
+    use image::{ImageError, ImageFormat};
+
+    /// Feeds ```data``` in chunks of ```size``` bytes, returning the progress after each chunk
+    fn feed(decoder: &mut PushDecoder, data: &[u8], size: usize) -> (Vec<Progress>, Vec<u8>) {
+        let mut progress = Vec::new();
+        let mut rows = Vec::new();
+        for chunk in data.chunks(size) {
+            progress.push(decoder.feed(chunk).unwrap());
+            rows.extend(decoder.take_rows());
+        }
+        (progress, rows)
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn test_rows() {
+        let mut data = b"P5 3 4 255\n".to_vec();
+        let pixels: Vec<u8> = (0..12).collect();
+        data.extend_from_slice(&pixels);
+
+        let mut decoder = PushDecoder::new(ImageFormat::PNM);
+        assert_eq!(decoder.feed(&data[..5]).unwrap(), Progress::NeedMoreData);
+        assert_eq!(decoder.dimensions(), None);
+        assert_eq!(decoder.feed(&data[5..11]).unwrap(), Progress::Header);
+        assert_eq!(decoder.dimensions(), Some((3, 4)));
+        assert_eq!(decoder.colortype(), Some(ColorType::Gray(8)));
+        assert_eq!(decoder.feed(&data[11..16]).unwrap(), Progress::Rows(1));
+        assert_eq!(decoder.take_rows(), vec![0, 1, 2]);
+        assert_eq!(decoder.feed(&data[16..17]).unwrap(), Progress::Rows(2));
+        assert_eq!(decoder.feed(&data[17..19]).unwrap(), Progress::NeedMoreData);
+        assert_eq!(decoder.feed(&data[19..]).unwrap(), Progress::Done);
+        assert_eq!(decoder.take_rows(), pixels[3..].to_vec());
+        assert_eq!(decoder.row_len(), 3);
+        assert_eq!(decoder.feed(b"more").unwrap(), Progress::Done);
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn test_png() {
+        use png::PNGEncoder;
+
+        // Noise, which does not compress, so that the image data is split into several chunks
+        let mut state = 1u32;
+        let pixels: Vec<u8> = (0..128 * 128 * 3).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        }).collect();
+        let mut data = Vec::new();
+        PNGEncoder::new(&mut data).encode(&pixels, 128, 128, ColorType::RGB(8)).unwrap();
+
+        let mut decoder = PushDecoder::new(ImageFormat::PNG);
+        let (progress, rows) = feed(&mut decoder, &data, 4096);
+        assert_eq!(progress[0], Progress::Header);
+        assert_eq!(progress.last(), Some(&Progress::Done));
+        assert!(progress.iter().any(|p| match *p { Progress::Rows(n) => n < 128, _ => false }));
+        assert_eq!(rows, pixels);
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn test_whole_image() {
+        use std::fs::File;
+        use std::io::Read;
+        use dynimage::load_from_memory;
+
+        let mut data = Vec::new();
+        File::open("tests/images/jpg/progressive/cat.jpg").unwrap().read_to_end(&mut data).unwrap();
+
+        let mut decoder = PushDecoder::new(ImageFormat::JPEG);
+        let (progress, rows) = feed(&mut decoder, &data, 1000);
+        assert_eq!(progress.last(), Some(&Progress::Done));
+        // All rows are decoded at once, after the header
+        assert_eq!(progress.iter().filter(|&&p| p == Progress::Header).count(), 1);
+        assert!(progress[..progress.len() - 1].iter().all(|&p| p == Progress::NeedMoreData || p == Progress::Header));
+        assert_eq!(rows, load_from_memory(&data).unwrap().raw_pixels());
+    }
+
+    #[test]
+    #[cfg(all(feature = "pnm", feature = "qoi"))]
+    fn test_errors() {
+        // Truncated images fail once the end of the stream is known
+        let mut decoder = PushDecoder::new(ImageFormat::PNM);
+        assert_eq!(decoder.feed(b"P5 2 2 255\n\x00\x01").unwrap(), Progress::Rows(1));
+        assert!(decoder.finish().is_err());
+
+        // Invalid images fail as soon as possible
+        let mut decoder = PushDecoder::new(ImageFormat::QOI);
+        assert!(decoder.feed(b"qoix\0\0\0\x01\0\0\0\x01\x04\0").is_err());
+
+        if !cfg!(feature = "heif") {
+            match PushDecoder::new(ImageFormat::HEIF).feed(b"data") {
+                Err(ImageError::Unsupported { .. }) => (),
+                _ => panic!("Expected an unsupported error"),
+            }
+        }
+    }
+}
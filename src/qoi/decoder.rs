@@ -0,0 +1,249 @@
+use std::io::Read;
+use byteorder::{ReadBytesExt, BigEndian};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+use super::{hash, MAGIC, OP_RGB, OP_RGBA, OP_INDEX, OP_DIFF, OP_LUMA};
+
+/// The largest number of pixels that is decoded, as the header is not protected by a checksum
+const MAX_PIXELS: u64 = 400_000_000;
+
+/// Header used by QOI image files
+#[derive(Clone, Copy, Debug)]
+struct Header {
+    width: u32,
+    height: u32,
+    channels: u8,
+    linear: bool,
+}
+
+/// The representation of a QOI decoder
+///
+/// Rows are decoded one at a time, so the decoder does not need to seek.
+pub struct QOIDecoder<R> {
+    r: R,
+    header: Option<Header>,
+    /// The pixels seen so far, by their hash
+    index: [[u8; 4]; 64],
+    /// The previous pixel, which runs repeat
+    pixel: [u8; 4],
+    /// The number of repetitions of ```pixel``` left from the last run
+    run: u8,
+    /// The next row to be decoded
+    row: u32,
+}
+
+impl<R: Read> QOIDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> QOIDecoder<R> {
+        QOIDecoder {
+            r: r,
+            header: None,
+            index: [[0; 4]; 64],
+            pixel: [0, 0, 0, 255],
+            run: 0,
+            row: 0,
+        }
+    }
+
+    /// Returns true if all channels are linear, or false if the color channels are sRGB encoded
+    ///
+    /// The value is informative only, it does not change the decoded samples.
+    pub fn is_linear(&mut self) -> ImageResult<bool> {
+        Ok(try!(self.read_header()).linear)
+    }
+
+    fn read_header(&mut self) -> ImageResult<Header> {
+        if let Some(header) = self.header {
+            return Ok(header)
+        }
+
+        let mut magic = [0; 4];
+        try!(self.r.read_exact(&mut magic));
+        if magic != MAGIC {
+            return Err(ImageError::malformed(ImageFormat::QOI, "Not a QOI image".to_string()))
+        }
+        let width = try!(self.r.read_u32::<BigEndian>());
+        let height = try!(self.r.read_u32::<BigEndian>());
+        let channels = try!(self.r.read_u8());
+        let colorspace = try!(self.r.read_u8());
+
+        if width == 0 || height == 0 || width as u64 * height as u64 > MAX_PIXELS {
+            return Err(ImageError::malformed(ImageFormat::QOI, "Invalid image dimensions".to_string()))
+        }
+        if channels != 3 && channels != 4 {
+            return Err(ImageError::malformed(ImageFormat::QOI,
+                format!("Invalid number of channels {}", channels)))
+        }
+        if colorspace > 1 {
+            return Err(ImageError::malformed(ImageFormat::QOI, format!("Invalid colorspace {}", colorspace)))
+        }
+
+        let header = Header {
+            width: width,
+            height: height,
+            channels: channels,
+            linear: colorspace == 1,
+        };
+        self.header = Some(header);
+        Ok(header)
+    }
+
+    /// Decodes the next pixel from the chunks of the stream
+    fn next_pixel(&mut self) -> ImageResult<[u8; 4]> {
+        if self.run > 0 {
+            self.run -= 1;
+            return Ok(self.pixel)
+        }
+
+        let tag = try!(self.r.read_u8());
+        let pixel = &mut self.pixel;
+        match tag {
+            OP_RGB => try!(self.r.read_exact(&mut pixel[..3])),
+            OP_RGBA => try!(self.r.read_exact(&mut pixel[..])),
+            _ => match tag & 0xc0 {
+                OP_INDEX => *pixel = self.index[tag as usize],
+                OP_DIFF => {
+                    // Differences of -2 to 1 to the previous pixel
+                    pixel[0] = pixel[0].wrapping_add((tag >> 4) & 3).wrapping_sub(2);
+                    pixel[1] = pixel[1].wrapping_add((tag >> 2) & 3).wrapping_sub(2);
+                    pixel[2] = pixel[2].wrapping_add(tag & 3).wrapping_sub(2);
+                }
+                OP_LUMA => {
+                    // The green difference, and the red and blue differences relative to it
+                    let next = try!(self.r.read_u8());
+                    let dg = (tag & 0x3f).wrapping_sub(32);
+                    pixel[0] = pixel[0].wrapping_add(dg).wrapping_add(next >> 4).wrapping_sub(8);
+                    pixel[1] = pixel[1].wrapping_add(dg);
+                    pixel[2] = pixel[2].wrapping_add(dg).wrapping_add(next & 0xf).wrapping_sub(8);
+                }
+                // The run includes the pixel returned now
+                _ => self.run = tag & 0x3f,
+            },
+        }
+        self.index[hash(*pixel)] = *pixel;
+        Ok(*pixel)
+    }
+}
+
+impl<R: Read> ImageDecoder for QOIDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        let header = try!(self.read_header());
+        Ok((header.width, header.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        let header = try!(self.read_header());
+        Ok(if header.channels == 4 { ColorType::RGBA(8) } else { ColorType::RGB(8) })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        let header = try!(self.read_header());
+        Ok(header.width as usize * header.channels as usize)
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        let header = try!(self.read_header());
+        if self.row == header.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let channels = header.channels as usize;
+        if buf.len() < header.width as usize * channels {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        for target in buf[..header.width as usize * channels].chunks_mut(channels) {
+            let pixel = try!(self.next_pixel());
+            target.copy_from_slice(&pixel[..channels]);
+        }
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let row_len = try!(self.row_len());
+        let (_, height) = try!(self.dimensions());
+        let mut data = vec![0; row_len * (height - self.row) as usize];
+        for row in data.chunks_mut(row_len) {
+            try!(self.read_scanline(row));
+        }
+        Ok(DecodingResult::U8(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::QOIDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError};
+
+    fn qoi(width: u8, height: u8, channels: u8, chunks: &[u8]) -> Vec<u8> {
+        let mut data = b"qoif".to_vec();
+        data.extend_from_slice(&[0, 0, 0, width, 0, 0, 0, height, channels, 0]);
+        data.extend_from_slice(chunks);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        data
+    }
+
+    #[test]
+    fn chunks() {
+        let data = qoi(4, 2, 4, &[
+            0xfe, 10, 20, 30,       // RGB, keeping the alpha of the initial black
+            0x7f,                   // DIFF of +1, +1, +1
+            0xa0, 0x0f,             // LUMA of green 0, red -8 and blue +7
+            0xff, 1, 2, 3, 4,       // RGBA
+            0xc1,                   // RUN of two
+            0x09,                   // INDEX of the pixel 10, 20, 30, 255
+            0x40,                   // DIFF of -2, -2, -2
+        ]);
+        let mut decoder = QOIDecoder::new(Cursor::new(data));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        assert_eq!(decoder.dimensions().unwrap(), (4, 2));
+        assert!(!decoder.is_linear().unwrap());
+
+        let mut row = [0; 16];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, [10, 20, 30, 255, 11, 21, 31, 255, 3, 21, 38, 255, 1, 2, 3, 4]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![
+                1, 2, 3, 4, 1, 2, 3, 4, 10, 20, 30, 255, 8, 18, 28, 255,
+            ]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn runs_across_rows() {
+        let data = qoi(3, 2, 3, &[0xc4, 0xfe, 1, 2, 3]);
+        let mut decoder = QOIDecoder::new(Cursor::new(data));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![
+                0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 1, 2, 3,
+            ]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut data = qoi(1, 1, 3, &[0xc0]);
+        data[12] = 2;
+        assert!(QOIDecoder::new(Cursor::new(data)).read_image().is_err());
+        let data = qoi(0, 1, 3, &[]);
+        match QOIDecoder::new(Cursor::new(data)).dimensions() {
+            Err(ImageError::Decoding { .. }) => (),
+            _ => panic!("Expected a dimension error"),
+        }
+        let mut data = qoi(1, 1, 3, &[0xc0]);
+        data[0] = b'Q';
+        assert!(QOIDecoder::new(Cursor::new(data)).read_image().is_err());
+        // Missing chunks
+        let data = b"qoif\0\0\0\x02\0\0\0\x02\x03\0\xc0".to_vec();
+        assert!(QOIDecoder::new(Cursor::new(data)).read_image().is_err());
+    }
+}
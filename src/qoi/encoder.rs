@@ -0,0 +1,196 @@
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, BigEndian};
+
+use color::ColorType;
+use image::{ImageEncoder, ImageResult};
+
+use super::{hash, MAGIC, END_MARKER, MAX_RUN, OP_RGB, OP_RGBA, OP_DIFF, OP_LUMA, OP_RUN};
+
+/// The representation of a QOI encoder
+///
+/// Images are marked as sRGB encoded with linear alpha.
+pub struct QOIEncoder<W> {
+    w: W,
+}
+
+impl<W: Write> QOIEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```
+    pub fn new(w: W) -> QOIEncoder<W> {
+        QOIEncoder {
+            w: w,
+        }
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// 8-bit rgb and rgba images are supported. Gray images are stored as rgb and gray images
+    /// with alpha as rgba, as QOI has no gray color types.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        let (channels, stored_channels) = match color {
+            ColorType::Gray(8) => (1, 3),
+            ColorType::GrayA(8) => (2, 4),
+            ColorType::RGB(8) => (3, 3),
+            ColorType::RGBA(8) => (4, 4),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Unsupported color type {:?}", color))),
+        };
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid QOI image dimensions"))
+        }
+        let len = width as usize * height as usize * channels;
+        if data.len() < len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+
+        let mut out = Vec::with_capacity(len / 2);
+        try!(out.write_all(MAGIC));
+        try!(out.write_u32::<BigEndian>(width));
+        try!(out.write_u32::<BigEndian>(height));
+        try!(out.write_u8(stored_channels));
+        try!(out.write_u8(0));
+
+        let mut index = [[0; 4]; 64];
+        let mut previous = [0, 0, 0, 255];
+        let mut run = 0;
+        for source in data[..len].chunks(channels) {
+            let pixel = match channels {
+                1 => [source[0], source[0], source[0], 255],
+                2 => [source[0], source[0], source[0], source[1]],
+                3 => [source[0], source[1], source[2], 255],
+                _ => [source[0], source[1], source[2], source[3]],
+            };
+
+            if pixel == previous {
+                run += 1;
+                if run == MAX_RUN {
+                    out.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue
+            }
+            if run > 0 {
+                out.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+
+            let position = hash(pixel);
+            if index[position] == pixel {
+                // The tag of index chunks is zero
+                out.push(position as u8);
+            } else {
+                index[position] = pixel;
+                if pixel[3] == previous[3] {
+                    let dr = pixel[0].wrapping_sub(previous[0]) as i8;
+                    let dg = pixel[1].wrapping_sub(previous[1]) as i8;
+                    let db = pixel[2].wrapping_sub(previous[2]) as i8;
+                    let (dr_dg, db_dg) = (dr.wrapping_sub(dg), db.wrapping_sub(dg));
+                    if [dr, dg, db].iter().all(|&d| d >= -2 && d <= 1) {
+                        out.push(OP_DIFF | ((dr + 2) as u8) << 4 | ((dg + 2) as u8) << 2 | (db + 2) as u8);
+                    } else if dg >= -32 && dg <= 31 && [dr_dg, db_dg].iter().all(|&d| d >= -8 && d <= 7) {
+                        out.push(OP_LUMA | (dg + 32) as u8);
+                        out.push(((dr_dg + 8) as u8) << 4 | (db_dg + 8) as u8);
+                    } else {
+                        out.push(OP_RGB);
+                        out.extend_from_slice(&pixel[..3]);
+                    }
+                } else {
+                    out.push(OP_RGBA);
+                    out.extend_from_slice(&pixel);
+                }
+            }
+            previous = pixel;
+        }
+        if run > 0 {
+            out.push(OP_RUN | (run - 1));
+        }
+        out.extend_from_slice(&END_MARKER);
+
+        self.w.write_all(&out)
+    }
+}
+
+impl<W: Write> ImageEncoder for QOIEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::QOIEncoder;
+    use super::super::QOIDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn encode(data: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        QOIEncoder::new(&mut encoded).encode(data, width, height, color).unwrap();
+        encoded
+    }
+
+    fn decode(data: Vec<u8>) -> (ColorType, u32, u32, Vec<u8>) {
+        let mut decoder = QOIDecoder::new(Cursor::new(data));
+        let color = decoder.colortype().unwrap();
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (color, width, height, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let data = [
+            10, 20, 30, 255,
+            11, 21, 31, 255,
+            3, 21, 38, 255,
+            1, 2, 3, 4,
+            1, 2, 3, 4,
+            1, 2, 3, 4,
+            10, 20, 30, 255,
+            8, 18, 28, 255,
+        ];
+        let encoded = encode(&data, 4, 2, ColorType::RGBA(8));
+        assert_eq!(&encoded[..14], b"qoif\0\0\0\x04\0\0\0\x02\x04\0");
+        assert_eq!(&encoded[14..], &[
+            0xfe, 10, 20, 30, 0x7f, 0xa0, 0x0f, 0xff, 1, 2, 3, 4, 0xc1, 0x09, 0x40,
+            0, 0, 0, 0, 0, 0, 0, 1,
+        ][..]);
+        assert_eq!(decode(encoded), (ColorType::RGBA(8), 4, 2, data.to_vec()));
+    }
+
+    #[test]
+    fn roundtrip() {
+        // Gradients, noise, runs longer than a chunk holds and changing alpha
+        let (width, height) = (150, 7);
+        let mut img = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = match y {
+                    0 => [x as u8, x as u8, x as u8, 255],
+                    1 => [(x * 3) as u8, (x * 7) as u8, (x * 13) as u8, 255],
+                    2 => [(x * x * 31 % 251) as u8, (x * 17 % 256) as u8, 0, 255],
+                    3 => [200, 100, 50, if x < 70 { 255 } else { 0 }],
+                    4 => [(x / 4) as u8, (x / 4 + 40) as u8, (x / 4) as u8, (x % 3 * 100) as u8],
+                    _ => [x as u8 & 0xf0, 255 - x as u8, (x * y) as u8, 128],
+                };
+                img.extend_from_slice(&pixel);
+            }
+        }
+
+        for &(color, channels) in &[(ColorType::RGB(8), 3), (ColorType::RGBA(8), 4)] {
+            let data: Vec<u8> = img.chunks(4).flat_map(|p| p[..channels].to_vec()).collect();
+            let encoded = encode(&data, width, height, color);
+            assert!(encoded.len() < data.len());
+            assert_eq!(decode(encoded), (color, width, height, data));
+        }
+
+        let gray: Vec<u8> = img.chunks(4).flat_map(|p| vec![p[1], p[3]]).collect();
+        let rgba = gray.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]]).collect();
+        assert_eq!(decode(encode(&gray, width, height, ColorType::GrayA(8))),
+                   (ColorType::RGBA(8), width, height, rgba));
+    }
+}
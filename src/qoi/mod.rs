@@ -0,0 +1,38 @@
+//! Decoding and Encoding of QOI Images
+//!
+//! The Quite OK Image format stores 8-bit RGB and RGBA images losslessly, with a compression
+//! close to PNG at a fraction of the cost.
+//!
+//! # Related Links
+//! * <https://qoiformat.org/qoi-specification.pdf> - The QOI specification
+//!
+
+pub use self::decoder::QOIDecoder;
+pub use self::encoder::QOIEncoder;
+
+mod decoder;
+mod encoder;
+
+/// The magic bytes at the start of every QOI image
+const MAGIC: &'static [u8] = b"qoif";
+
+/// The end of the pixel data
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// A full pixel follows the tag
+const OP_RGB: u8 = 0xfe;
+const OP_RGBA: u8 = 0xff;
+
+/// The two bit tags of the other chunks
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xc0;
+
+/// The longest run a single chunk holds, as longer runs would collide with the full pixel tags
+const MAX_RUN: u8 = 62;
+
+/// The position of a previously seen pixel in the index of 64 pixels
+fn hash(pixel: [u8; 4]) -> usize {
+    (pixel[0] as usize * 3 + pixel[1] as usize * 5 + pixel[2] as usize * 7 + pixel[3] as usize * 11) % 64
+}
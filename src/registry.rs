@@ -0,0 +1,172 @@
+//! Codecs of image formats that are not part of this crate
+//!
+//! Other crates can implement `Codec` for their own formats and `register` them, after which
+//! `open`, `image_dimensions`, `load_from_memory`, `save_buffer` and `io::Reader` fall back to
+//! them for images that are not of a format this crate supports.
+
+use std::ffi::OsStr;
+use std::io::{BufRead, Seek, Write};
+use std::sync::{Arc, RwLock};
+
+use color::ColorType;
+use dynimage::DynamicImage;
+use image::{GenericImageView, ImageError, ImageFormatHint, ImageResult, Limits, UnsupportedFeature};
+
+/// A stream a `Codec` decodes images from
+pub trait Source: BufRead + Seek {}
+
+impl<T: BufRead + Seek> Source for T {}
+
+/// An image format implemented outside of this crate
+pub trait Codec: Send + Sync {
+    /// Returns the name of the format, used in error messages
+    fn name(&self) -> &str;
+
+    /// Returns the extensions of files of the format, without the leading dot
+    ///
+    /// Extensions are compared ignoring their case.
+    fn extensions(&self) -> &[&str];
+
+    /// Returns whether an image starting with the bytes ```start``` is of this format
+    ///
+    /// ```start``` holds at least the first kilobyte of the image, unless the image is shorter.
+    fn matches(&self, start: &[u8]) -> bool;
+
+    /// Decodes the image read from ```reader```, failing with `ImageError::Limits` if
+    /// it exceeds ```limits```
+    fn decode(&self, reader: &mut Source, limits: &Limits) -> ImageResult<DynamicImage>;
+
+    /// Returns the dimensions of the image read from ```reader```
+    ///
+    /// The default implementation decodes the whole image, codecs should only read its header.
+    fn dimensions(&self, reader: &mut Source) -> ImageResult<(u32, u32)> {
+        self.decode(reader, &Limits::no_limits()).map(|image| image.dimensions())
+    }
+
+    /// Encodes the image ```buf``` of ```width``` by ```height``` pixels of color type ```color```
+    /// into ```writer```
+    ///
+    /// The default implementation fails, for formats that can only be decoded.
+    fn encode(&self, _writer: &mut Write, _buf: &[u8], _width: u32, _height: u32, _color: ColorType)
+              -> ImageResult<()> {
+        Err(ImageError::Unsupported {
+            format: ImageFormatHint::Name(self.name().to_string()),
+            feature: UnsupportedFeature::Other("encoding".to_string()),
+        })
+    }
+}
+
+/// The registered codecs, in the order they were registered
+static CODECS: RwLock<Vec<Arc<Codec>>> = RwLock::new(Vec::new());
+
+/// Registers ```codec```, so that images of its format are decoded and encoded with it
+///
+/// Formats supported by this crate are always handled by its own codecs. Codecs registered
+/// earlier take precedence over later ones claiming the same extension or bytes.
+pub fn register<C: Codec + 'static>(codec: C) {
+    let mut codecs = match CODECS.write() {
+        Ok(codecs) => codecs,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    codecs.push(Arc::new(codec));
+}
+
+/// Returns the first registered codec for files with the extension ```extension```
+pub fn find_by_extension(extension: &OsStr) -> Option<Arc<Codec>> {
+    let extension = extension.to_string_lossy().to_lowercase();
+    codecs().into_iter().find(|codec| {
+        codec.extensions().iter().any(|e| e.to_lowercase() == extension)
+    })
+}
+
+/// Returns the first registered codec matching an image starting with the bytes ```start```
+pub fn find_by_content(start: &[u8]) -> Option<Arc<Codec>> {
+    codecs().into_iter().find(|codec| codec.matches(start))
+}
+
+/// Returns the registered codecs, so that the lock is not held while they run
+fn codecs() -> Vec<Arc<Codec>> {
+    match CODECS.read() {
+        Ok(codecs) => codecs.clone(),
+        Err(poisoned) => poisoned.into_inner().clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::io::{Cursor, Write};
+
+    use super::{find_by_content, find_by_extension, register, Codec, Source};
+    use buffer::ImageBuffer;
+    use color::ColorType;
+    use dynimage::{load_from_memory, DynamicImage};
+    use image::{GenericImageView, ImageError, ImageResult, Limits, ParameterErrorKind};
+    use io::Reader;
+
+    /// Grayscale images of at most 255 by 255 pixels, stored after a magic and their dimensions
+    struct Toy;
+
+    impl Codec for Toy {
+        fn name(&self) -> &str {
+            "Toy"
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["toy"]
+        }
+
+        fn matches(&self, start: &[u8]) -> bool {
+            start.starts_with(b"TOYI")
+        }
+
+        fn decode(&self, reader: &mut Source, limits: &Limits) -> ImageResult<DynamicImage> {
+            let mut data = Vec::new();
+            try!(reader.read_to_end(&mut data));
+            let (width, height) = try!(self.dimensions(&mut Cursor::new(&data)));
+            try!(limits.check_image(width, height, ColorType::Gray(8)));
+            ImageBuffer::from_raw(width, height, data[6..].to_vec())
+                .map(DynamicImage::ImageLuma8)
+                .ok_or(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+
+        fn dimensions(&self, reader: &mut Source) -> ImageResult<(u32, u32)> {
+            let mut header = [0; 6];
+            try!(reader.read_exact(&mut header));
+            Ok((header[4] as u32, header[5] as u32))
+        }
+
+        fn encode(&self, writer: &mut Write, buf: &[u8], width: u32, height: u32, _: ColorType)
+                  -> ImageResult<()> {
+            try!(writer.write_all(b"TOYI"));
+            try!(writer.write_all(&[width as u8, height as u8]));
+            try!(writer.write_all(buf));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn registered_codec() {
+        register(Toy);
+        let data = b"TOYI\x02\x01\x05\x06";
+        assert_eq!(load_from_memory(data).unwrap().raw_pixels(), vec![5, 6]);
+
+        let reader = Reader::new(Cursor::new(&data[..])).with_guessed_format().unwrap();
+        assert_eq!(reader.format(), None);
+        assert_eq!(reader.into_dimensions().unwrap(), (2, 1));
+        let mut reader = Reader::new(Cursor::new(&data[..])).with_guessed_format().unwrap();
+        reader.set_limits(Limits { max_width: Some(1), ..Limits::no_limits() });
+        match reader.decode() {
+            Err(ImageError::Limits(_)) => {},
+            r => panic!("Expected the limits to be exceeded, got {:?}", r.is_ok()),
+        }
+
+        let codec = find_by_extension(OsStr::new("TOY")).unwrap();
+        let mut encoded = Vec::new();
+        codec.encode(&mut encoded, &[1, 2, 3], 3, 1, ColorType::Gray(8)).unwrap();
+        assert_eq!(codec.decode(&mut Cursor::new(encoded), &Limits::default()).unwrap().dimensions(), (3, 1));
+
+        assert!(find_by_content(b"TOY").is_none());
+        assert!(find_by_extension(OsStr::new("toys")).is_none());
+    }
+}
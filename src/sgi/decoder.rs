@@ -0,0 +1,294 @@
+use std::io::{Read, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, BigEndian, ByteOrder};
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+use super::{MAGIC, HEADER_LEN, VERBATIM, RLE};
+
+/// The representation of an SGI decoder
+///
+/// As the channels are stored one after the other, each row is collected from every channel,
+/// which requires the reader to seek.
+pub struct SGIDecoder<R> {
+    r: R,
+    width: u32,
+    height: u32,
+    channels: u16,
+    /// The bytes of each sample
+    bytes: u8,
+    /// The offsets and lengths of the run length encoded rows, by channel and row
+    rle_rows: Option<Vec<(u32, u32)>>,
+    name: String,
+    /// The next row to be read, from the top
+    row: u32,
+}
+
+impl<R: Read + Seek> SGIDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(mut r: R) -> ImageResult<SGIDecoder<R>> {
+        if try!(r.read_u16::<BigEndian>()) != MAGIC {
+            return Err(ImageError::malformed(ImageFormat::SGI, "Not an SGI image".to_string()))
+        }
+        let storage = try!(r.read_u8());
+        let bytes = try!(r.read_u8());
+        let dimension = try!(r.read_u16::<BigEndian>());
+        let width = try!(r.read_u16::<BigEndian>()) as u32;
+        // Images with fewer dimensions leave the remaining sizes undefined
+        let height = if dimension < 2 { 1 } else { try!(r.read_u16::<BigEndian>()) as u32 };
+        try!(r.seek(SeekFrom::Start(10)));
+        let channels = if dimension < 3 { 1 } else { try!(r.read_u16::<BigEndian>()) };
+        try!(r.seek(SeekFrom::Start(24)));
+        let mut name = [0; 80];
+        try!(r.read_exact(&mut name));
+        let colormap = try!(r.read_u32::<BigEndian>());
+
+        if storage != VERBATIM && storage != RLE {
+            return Err(ImageError::malformed(ImageFormat::SGI,
+                format!("Invalid SGI storage format {}", storage)))
+        }
+        if bytes != 1 && bytes != 2 {
+            return Err(ImageError::malformed(ImageFormat::SGI, format!("Invalid SGI sample size {}", bytes)))
+        }
+        if dimension < 1 || dimension > 3 {
+            return Err(ImageError::malformed(ImageFormat::SGI,
+                format!("Invalid SGI dimension {}", dimension)))
+        }
+        if width == 0 || height == 0 {
+            return Err(ImageError::malformed(ImageFormat::SGI, "Invalid image dimensions".to_string()))
+        }
+        if channels == 0 || channels > 4 {
+            return Err(ImageError::unsupported(ImageFormat::SGI,
+                format!("Unsupported number of SGI channels {}", channels)))
+        }
+        if colormap != 0 {
+            return Err(ImageError::unsupported(ImageFormat::SGI,
+                format!("Unsupported SGI colormap {}", colormap)))
+        }
+
+        let rle_rows = if storage == RLE {
+            // All offsets, followed by all lengths
+            let count = height as usize * channels as usize;
+            try!(r.seek(SeekFrom::Start(HEADER_LEN)));
+            let mut table = Vec::with_capacity(count * 2);
+            for _ in 0..count * 2 {
+                table.push(try!(r.read_u32::<BigEndian>()));
+            }
+            Some(table[..count].iter().cloned().zip(table[count..].iter().cloned()).collect())
+        } else {
+            None
+        };
+
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        Ok(SGIDecoder {
+            r: r,
+            width: width,
+            height: height,
+            channels: channels,
+            bytes: bytes,
+            rle_rows: rle_rows,
+            name: String::from_utf8_lossy(&name[..end]).into_owned(),
+            row: 0,
+        })
+    }
+
+    /// Returns the name stored in the header of the image
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads the samples of the channel ```channel``` in the stored row ```row```
+    fn read_channel_row(&mut self, channel: u16, row: u32, samples: &mut [u8]) -> ImageResult<()> {
+        let index = channel as usize * self.height as usize + row as usize;
+        let (offset, len) = match self.rle_rows {
+            Some(ref rows) => rows[index],
+            None => {
+                let offset = HEADER_LEN + index as u64 * samples.len() as u64;
+                try!(self.r.seek(SeekFrom::Start(offset)));
+                try!(self.r.read_exact(samples));
+                return Ok(())
+            }
+        };
+
+        try!(self.r.seek(SeekFrom::Start(offset as u64)));
+        let mut data = Vec::new();
+        try!(self.r.by_ref().take(len as u64).read_to_end(&mut data));
+        decode_rle(&data, self.bytes as usize, samples)
+    }
+}
+
+/// Decodes the run length encoded samples of ```bytes``` bytes in ```data``` into ```samples```
+///
+/// Each run starts with a count in the low seven bits of a sample. If the highest bit is set, as
+/// many samples follow, otherwise the next sample is repeated. A count of zero ends the row.
+fn decode_rle(data: &[u8], bytes: usize, samples: &mut [u8]) -> ImageResult<()> {
+    let truncated = || ImageError::malformed(ImageFormat::SGI, "Truncated SGI row".to_string());
+    let mut input = data.chunks(bytes).filter(|s| s.len() == bytes);
+    let mut pos = 0;
+    loop {
+        let tag = match input.next() {
+            Some(tag) => tag[bytes - 1],
+            None => break,
+        };
+        let count = (tag & 0x7f) as usize * bytes;
+        if count == 0 {
+            break
+        }
+        if pos + count > samples.len() {
+            return Err(ImageError::malformed(ImageFormat::SGI, "SGI run exceeds the row".to_string()))
+        }
+        if tag & 0x80 != 0 {
+            for out in samples[pos..pos + count].chunks_mut(bytes) {
+                out.copy_from_slice(try!(input.next().ok_or_else(&truncated)));
+            }
+        } else {
+            let value = try!(input.next().ok_or_else(&truncated));
+            for out in samples[pos..pos + count].chunks_mut(bytes) {
+                out.copy_from_slice(value);
+            }
+        }
+        pos += count;
+    }
+    if pos < samples.len() {
+        return Err(truncated())
+    }
+    Ok(())
+}
+
+impl<R: Read + Seek> ImageDecoder for SGIDecoder<R> {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        let bits = self.bytes * 8;
+        Ok(match self.channels {
+            1 => ColorType::Gray(bits),
+            2 => ColorType::GrayA(bits),
+            3 => ColorType::RGB(bits),
+            _ => ColorType::RGBA(bits),
+        })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize * self.channels as usize * self.bytes as usize)
+    }
+
+    /// Reads the next row into ```buf```, with 16-bit samples in big endian order.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let bytes = self.bytes as usize;
+        let channels = self.channels as usize;
+        let stored_row = self.height - 1 - self.row;
+        let mut samples = vec![0; self.width as usize * bytes];
+        for channel in 0..self.channels {
+            try!(self.read_channel_row(channel, stored_row, &mut samples));
+            let c = channel as usize;
+            for (pixel, sample) in buf[..len].chunks_mut(channels * bytes).zip(samples.chunks(bytes)) {
+                pixel[c * bytes..(c + 1) * bytes].copy_from_slice(sample);
+            }
+        }
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let mut row = vec![0; try!(self.row_len())];
+        let mut data = Vec::new();
+        while self.row < self.height {
+            try!(self.read_scanline(&mut row));
+            data.extend_from_slice(&row);
+        }
+        Ok(if self.bytes == 1 {
+            DecodingResult::U8(data)
+        } else {
+            DecodingResult::U16(data.chunks(2).map(BigEndian::read_u16).collect())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::SGIDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError, ParameterErrorKind};
+
+    fn header(storage: u8, bytes: u8, dimension: u16, width: u16, height: u16, channels: u16) -> Vec<u8> {
+        let mut data = vec![0x01, 0xda, storage, bytes, 0, dimension as u8];
+        for &v in &[width, height, channels] {
+            data.extend_from_slice(&[(v >> 8) as u8, v as u8]);
+        }
+        data.resize(24, 0);
+        data.extend_from_slice(b"test");
+        data.resize(512, 0);
+        data
+    }
+
+    #[test]
+    fn verbatim() {
+        let mut data = header(0, 1, 3, 2, 2, 3);
+        // Red, green and blue, each with the bottom row first
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]);
+        let mut decoder = SGIDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (2, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(decoder.name(), "test");
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![3, 7, 11, 4, 8, 12, 1, 5, 9, 2, 6, 10]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+        match decoder.read_scanline(&mut [0; 6]) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => (),
+            _ => panic!("Expected the end of the image"),
+        }
+    }
+
+    #[test]
+    fn rle() {
+        let mut data = header(1, 2, 2, 3, 2, 0);
+        // The offsets and lengths of the two rows
+        data.extend_from_slice(&[0, 0, 2, 16, 0, 0, 2, 26, 0, 0, 0, 10, 0, 0, 0, 8]);
+        // A literal run of two and a repeat of one
+        data.extend_from_slice(&[0, 0x82, 0x12, 0x34, 0x56, 0x78, 0, 1, 0xff, 0xff]);
+        // A repeat of three
+        data.extend_from_slice(&[0, 3, 0, 7, 0, 0]);
+        let mut decoder = SGIDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(16));
+        let mut row = [0; 6];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, [0, 7, 0, 7, 0, 7]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U16(image) => assert_eq!(image, vec![0x1234, 0x5678, 0xffff]),
+            _ => panic!("Image did not decode as 16-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        let mut data = header(0, 3, 2, 1, 1, 1);
+        data.push(0);
+        assert!(SGIDecoder::new(Cursor::new(data)).is_err());
+        let mut data = header(0, 1, 3, 1, 1, 5);
+        data.extend_from_slice(&[0; 5]);
+        assert!(SGIDecoder::new(Cursor::new(data)).is_err());
+        // A run longer than the row
+        let mut data = header(1, 1, 1, 2, 1, 1);
+        data.extend_from_slice(&[0, 0, 2, 8, 0, 0, 0, 3, 3, 1, 0]);
+        let mut decoder = SGIDecoder::new(Cursor::new(data)).unwrap();
+        assert!(decoder.read_image().is_err());
+        // A truncated row
+        let mut data = header(1, 1, 1, 2, 1, 1);
+        data.extend_from_slice(&[0, 0, 2, 8, 0, 0, 0, 2, 0x82, 1]);
+        let mut decoder = SGIDecoder::new(Cursor::new(data)).unwrap();
+        assert!(decoder.read_image().is_err());
+    }
+}
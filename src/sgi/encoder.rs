@@ -0,0 +1,233 @@
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, BigEndian};
+
+use color::ColorType;
+use image::{ImageEncoder, ImageResult};
+
+use super::{MAGIC, HEADER_LEN, VERBATIM, RLE};
+
+/// The most samples a single run holds
+const MAX_RUN: usize = 127;
+
+/// The representation of an SGI encoder
+pub struct SGIEncoder<W> {
+    w: W,
+    rle: bool,
+}
+
+impl<W: Write> SGIEncoder<W> {
+    /// Create a new encoder that writes verbatim images to ```w```
+    pub fn new(w: W) -> SGIEncoder<W> {
+        SGIEncoder {
+            w: w,
+            rle: false,
+        }
+    }
+
+    /// Run length encode the rows if ```rle``` is true, or store them verbatim otherwise
+    pub fn set_rle(&mut self, rle: bool) {
+        self.rle = rle;
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// Gray, gray with alpha, rgb and rgba images are supported with 8 or 16 bits per sample.
+    /// 16-bit samples are expected in big endian order.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        let (channels, bytes) = match color {
+            ColorType::Gray(8) => (1, 1),
+            ColorType::GrayA(8) => (2, 1),
+            ColorType::RGB(8) => (3, 1),
+            ColorType::RGBA(8) => (4, 1),
+            ColorType::Gray(16) => (1, 2),
+            ColorType::GrayA(16) => (2, 2),
+            ColorType::RGB(16) => (3, 2),
+            ColorType::RGBA(16) => (4, 2),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Unsupported color type {:?}", color))),
+        };
+        if width == 0 || height == 0 || width > 0xFFFF || height > 0xFFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid SGI image dimensions"))
+        }
+        let (width, height) = (width as usize, height as usize);
+        let row_len = width * channels * bytes;
+        if data.len() < row_len * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+
+        try!(self.w.write_u16::<BigEndian>(MAGIC));
+        try!(self.w.write_u8(if self.rle { RLE } else { VERBATIM }));
+        try!(self.w.write_u8(bytes as u8));
+        try!(self.w.write_u16::<BigEndian>(if channels == 1 { 2 } else { 3 }));
+        try!(self.w.write_u16::<BigEndian>(width as u16));
+        try!(self.w.write_u16::<BigEndian>(height as u16));
+        try!(self.w.write_u16::<BigEndian>(channels as u16));
+        // The smallest and largest sample values
+        try!(self.w.write_u32::<BigEndian>(0));
+        try!(self.w.write_u32::<BigEndian>(if bytes == 1 { 0xff } else { 0xffff }));
+        // Unused bytes, the image name, a normal colormap and padding
+        try!(self.w.write_all(&[0; 84]));
+        try!(self.w.write_u32::<BigEndian>(0));
+        try!(self.w.write_all(&[0; 404]));
+
+        // The samples of each channel, from the bottom row up
+        let channel_rows = (0..channels).flat_map(|c| (0..height).rev().map(move |y| (c, y)));
+        let mut samples = Vec::with_capacity(width * bytes);
+        let mut rows = Vec::new();
+        for (c, y) in channel_rows {
+            samples.clear();
+            for pixel in data[y * row_len..(y + 1) * row_len].chunks(channels * bytes) {
+                samples.extend_from_slice(&pixel[c * bytes..(c + 1) * bytes]);
+            }
+            if self.rle {
+                let mut row = Vec::new();
+                encode_rle(&samples, bytes, &mut row);
+                rows.push(row);
+            } else {
+                try!(self.w.write_all(&samples));
+            }
+        }
+
+        if self.rle {
+            // The tables of offsets and lengths precede the rows
+            let mut offset = HEADER_LEN + rows.len() as u64 * 8;
+            for row in &rows {
+                try!(self.w.write_u32::<BigEndian>(offset as u32));
+                offset += row.len() as u64;
+            }
+            if offset > 0xFFFF_FFFF {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "SGI image too large"))
+            }
+            for row in &rows {
+                try!(self.w.write_u32::<BigEndian>(row.len() as u32));
+            }
+            for row in &rows {
+                try!(self.w.write_all(row));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for SGIEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Writes a run with the count ```count``` and the flag ```literal``` as a sample of ```bytes```
+/// bytes
+fn write_count(out: &mut Vec<u8>, count: usize, literal: bool, bytes: usize) {
+    if bytes == 2 {
+        out.push(0);
+    }
+    out.push(count as u8 | if literal { 0x80 } else { 0 });
+}
+
+/// Run length encodes the samples of ```bytes``` bytes in ```row``` into ```out```
+///
+/// Samples repeated at least three times are stored as a run, all others literally.
+fn encode_rle(row: &[u8], bytes: usize, out: &mut Vec<u8>) {
+    let samples: Vec<&[u8]> = row.chunks(bytes).collect();
+    let repeats = |i: usize| {
+        let mut run = 1;
+        while i + run < samples.len() && run < MAX_RUN && samples[i + run] == samples[i] {
+            run += 1;
+        }
+        run
+    };
+
+    let mut i = 0;
+    while i < samples.len() {
+        let run = repeats(i);
+        if run >= 3 {
+            write_count(out, run, false, bytes);
+            out.extend_from_slice(samples[i]);
+            i += run;
+        } else {
+            let start = i;
+            while i < samples.len() && i - start < MAX_RUN && repeats(i) < 3 {
+                i += 1;
+            }
+            write_count(out, i - start, true, bytes);
+            for sample in &samples[start..i] {
+                out.extend_from_slice(sample);
+            }
+        }
+    }
+    write_count(out, 0, false, bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::SGIEncoder;
+    use super::super::SGIDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn round_trip(data: &[u8], width: u32, height: u32, color: ColorType, rle: bool) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = SGIEncoder::new(&mut encoded);
+            encoder.set_rle(rle);
+            encoder.encode(data, width, height, color).unwrap();
+        }
+        let mut decoder = SGIDecoder::new(Cursor::new(encoded)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (width, height));
+        assert_eq!(decoder.colortype().unwrap(), color);
+        let mut decoded = Vec::new();
+        let mut row = vec![0; decoder.row_len().unwrap()];
+        for y in 0..height {
+            assert_eq!(decoder.read_scanline(&mut row).unwrap(), y);
+            decoded.extend_from_slice(&row);
+        }
+        decoded
+    }
+
+    #[test]
+    fn color_types() {
+        let colors = [
+            ColorType::Gray(8), ColorType::GrayA(8), ColorType::RGB(8), ColorType::RGBA(8),
+            ColorType::Gray(16), ColorType::GrayA(16), ColorType::RGB(16), ColorType::RGBA(16),
+        ];
+        for &color in &colors {
+            let len = 300 * 3 * ::color::bits_per_pixel(color) / 8;
+            // Long runs, short repeats and literal samples
+            let data: Vec<u8> = (0..len).map(|i| match i % 600 {
+                0...299 => 9,
+                i => (i / 2 % 7) as u8,
+            }).collect();
+            for &rle in &[false, true] {
+                assert_eq!(round_trip(&data, 300, 3, color, rle), data);
+            }
+        }
+    }
+
+    #[test]
+    fn compression() {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = SGIEncoder::new(&mut encoded);
+            encoder.set_rle(true);
+            encoder.encode(&[7; 1000], 1000, 1, ColorType::Gray(8)).unwrap();
+        }
+        // The header, one offset and length, eight runs and the end of the row
+        assert_eq!(encoded.len(), 512 + 8 + 8 * 2 + 1);
+        let mut decoder = SGIDecoder::new(Cursor::new(encoded)).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![7; 1000]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid_input() {
+        let encoder = SGIEncoder::new(Vec::new());
+        assert!(encoder.encode(&[0; 3], 2, 1, ColorType::RGB(8)).is_err());
+        let encoder = SGIEncoder::new(Vec::new());
+        assert!(encoder.encode(&[0; 4], 1, 1, ColorType::RGBAF(32)).is_err());
+    }
+}
@@ -0,0 +1,25 @@
+//! Decoding and Encoding of SGI Images
+//!
+//! The SGI image format, also known as IRIS RGB, stores gray or color images with up to four
+//! channels of 8 or 16 bits, either verbatim or run length encoded. The channels are stored
+//! one after the other, with the bottom row first.
+//!
+//! # Related Links
+//! * <https://paulbourke.net/dataformats/sgirgb/sgiversion.html> - The SGI image file format
+//!
+
+pub use self::decoder::SGIDecoder;
+pub use self::encoder::SGIEncoder;
+
+mod decoder;
+mod encoder;
+
+/// The magic number at the start of every SGI image
+const MAGIC: u16 = 474;
+
+/// The length of the header, which is padded with zeros
+const HEADER_LEN: u64 = 512;
+
+/// The storage formats
+const VERBATIM: u8 = 0;
+const RLE: u8 = 1;
@@ -2,11 +2,12 @@ use std::io;
 use std::io::{Read, Seek};
 use byteorder::{ReadBytesExt, LittleEndian};
 
-use image::ImageError;
+use image::{ImageError, ImageFormat, ParameterErrorKind};
 use image::ImageResult;
 use image::ImageDecoder;
 use image::DecodingResult;
-use color::ColorType;
+use color::{ColorType, bits_per_pixel};
+use monitor::Monitor;
 
 enum ImageType {
     NoImageData = 0,
@@ -150,10 +151,13 @@ impl ColorMap {
             })
         }
 
-    /// Get one entry from the color map
-    pub fn get(&self, index: usize) -> &[u8] {
-        let entry = self.start_offset + self.entry_size * index;
-        &self.bytes[entry..entry + self.entry_size]
+    /// Get one entry from the color map, whose first entry has the index ```start_offset```
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        let entry = match index.checked_sub(self.start_offset) {
+            Some(i) => self.entry_size * i,
+            None => return None,
+        };
+        self.bytes.get(entry..entry + self.entry_size)
     }
 }
 
@@ -171,6 +175,11 @@ pub struct TGADecoder<R> {
 
     header: Header,
     color_map: Option<ColorMap>,
+
+    /// The decoded image, while its rows are read by `read_scanline`
+    rows: Option<Vec<u8>>,
+    /// The number of rows read by `read_scanline`
+    row: usize,
 }
 
 impl<R: Read + Seek> TGADecoder<R> {
@@ -189,6 +198,9 @@ impl<R: Read + Seek> TGADecoder<R> {
 
             header: Header::new(),
             color_map: None,
+
+            rows: None,
+            row: 0,
         }
     }
 
@@ -214,42 +226,43 @@ impl<R: Read + Seek> TGADecoder<R> {
 
     /// Loads the color information for the decoder
     ///
-    /// To keep things simple, we won't handle bit depths that aren't divisible
-    /// by 8 and are less than 32.
+    /// Colors, either of the pixels or of the color map, may be stored in 15, 16, 24 or 32 bits,
+    /// while gray pixels take 8 bits with an optional 8-bit alpha channel.
     fn read_color_information(&mut self) -> ImageResult<()> {
-        if self.header.pixel_depth % 8 != 0 {
-            return Err(ImageError::UnsupportedError("\
+        if self.header.pixel_depth % 8 != 0 && self.header.pixel_depth != 15 {
+            return Err(ImageError::unsupported(ImageFormat::TGA, "\
                 Bit depth must be divisible by 8".to_string()));
         }
         if self.header.pixel_depth > 32 {
-            return Err(ImageError::UnsupportedError("\
+            return Err(ImageError::unsupported(ImageFormat::TGA, "\
                 Bit depth must be less than 32".to_string()));
         }
 
         let num_alpha_bits = self.header.image_desc & 0b1111;
 
-        let other_channel_bits = if self.header.map_type != 0 {
+        let color_bits = if self.image_type.is_color_mapped() {
+            if self.header.map_type != 1 || self.header.pixel_depth > 16 {
+                return Err(ImageError::unsupported(ImageFormat::TGA, format!("\
+                    Color map index size not supported: {}", self.header.pixel_depth)))
+            }
             self.header.map_entry_size
         } else {
-            if num_alpha_bits > self.header.pixel_depth {
-                return Err(ImageError::UnsupportedError(format!("\
-                    Color format not supported. Alpha bits: {}", num_alpha_bits).to_string()))
-            }
-
-            self.header.pixel_depth - num_alpha_bits
+            self.header.pixel_depth
         };
         let color = self.image_type.is_color();
 
-        match (num_alpha_bits, other_channel_bits, color) {
+        match (num_alpha_bits, color_bits, color) {
             // really, the encoding is BGR and BGRA, this is fixed
-            // up with `TGADecoder::reverse_encoding`.
-            (8, 24, true) => self.color_type = ColorType::RGBA(8),
-            (0, 24, true) => self.color_type = ColorType::RGB(8),
-            (8, 8, false) => self.color_type = ColorType::GrayA(8),
+            // up with `TGADecoder::decode_colors`.
+            (0, 15, true) | (0, 16, true) => self.color_type = ColorType::RGB(8),
+            (1, 16, true) => self.color_type = ColorType::RGBA(8),
+            (0, 24, true) | (0, 32, true) => self.color_type = ColorType::RGB(8),
+            (8, 32, true) => self.color_type = ColorType::RGBA(8),
+            (8, 16, false) => self.color_type = ColorType::GrayA(8),
             (0, 8, false) => self.color_type = ColorType::Gray(8),
-            _ => return Err(ImageError::UnsupportedError(format!("\
+            _ => return Err(ImageError::unsupported(ImageFormat::TGA, format!("\
                     Color format not supported. Bit depth: {}, Alpha bits: {}",
-                    other_channel_bits, num_alpha_bits).to_string())),
+                    color_bits, num_alpha_bits).to_string())),
         }
         Ok(())
     }
@@ -275,16 +288,7 @@ impl<R: Read + Seek> TGADecoder<R> {
     }
 
     /// Expands indices into its mapped color
-    fn expand_color_map(&mut self, pixel_data: Vec<u8>) -> Vec<u8> {
-        #[inline]
-        fn bytes_to_index(bytes: &[u8]) -> usize {
-            let mut result = 0usize;
-            for byte in bytes.iter() {
-                result = result << 8 | *byte as usize;
-            }
-            result
-        }
-
+    fn expand_color_map(&mut self, pixel_data: Vec<u8>) -> ImageResult<Vec<u8>> {
         let bytes_per_entry = (self.header.map_entry_size as usize + 7) / 8;
         let mut result = Vec::with_capacity(self.width * self.height *
                                             bytes_per_entry);
@@ -295,42 +299,55 @@ impl<R: Read + Seek> TGADecoder<R> {
         };
 
         for chunk in pixel_data.chunks(self.bytes_per_pixel) {
-            let index = bytes_to_index(chunk);
-            result.extend(color_map.get(index).iter().cloned());
+            // Indices are little endian like the rest of the file
+            let index = chunk.iter().rev().fold(0, |index, &byte| index << 8 | byte as usize);
+            match color_map.get(index) {
+                Some(entry) => result.extend_from_slice(entry),
+                None => return Err(ImageError::malformed(ImageFormat::TGA, format!(
+                    "Color map index {} out of range", index))),
+            }
         }
 
-        result
+        Ok(result)
     }
 
-    fn read_image_data(&mut self) -> ImageResult<Vec<u8>> {
-        // read the pixels from the data region
+    /// Reads the pixels from the data region, reporting each row read to ```monitor```
+    fn read_image_data(&mut self, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
+        let height = self.height as u64;
+        try!(monitor.update(0, height));
         let mut pixel_data = if self.image_type.is_encoded() {
-            try!(self.read_encoded_data())
+            try!(self.read_encoded_data(monitor))
         } else {
-            let num_raw_bytes = self.width * self.height * self.bytes_per_pixel;
-            let mut buf = vec![0; num_raw_bytes];
-            try!(self.r.by_ref().read_exact(&mut buf));
+            let row_bytes = self.width * self.bytes_per_pixel;
+            let mut buf = vec![0; row_bytes * self.height];
+            for (y, row) in buf.chunks_mut(row_bytes.max(1)).enumerate() {
+                try!(self.r.by_ref().read_exact(row));
+                try!(monitor.update(y as u64 + 1, height));
+            }
             buf
         };
 
         // expand the indices using the color map if necessary
         if self.image_type.is_color_mapped() {
-            pixel_data = self.expand_color_map(pixel_data)
+            pixel_data = try!(self.expand_color_map(pixel_data));
         }
 
-        self.reverse_encoding(&mut pixel_data);
+        let mut pixel_data = self.decode_colors(pixel_data);
 
+        self.flip_horizontally(&mut pixel_data);
         self.flip_vertically(&mut pixel_data);
-        
+
         Ok(pixel_data)
     }
 
-    /// Reads a run length encoded packet
-    fn read_encoded_data(&mut self) -> ImageResult<Vec<u8>> {
+    /// Reads the run length encoded packets of the image
+    fn read_encoded_data(&mut self, monitor: &mut Monitor) -> ImageResult<Vec<u8>> {
+        let row_bytes = (self.width * self.bytes_per_pixel).max(1);
         let num_bytes = self.width * self.height * self.bytes_per_pixel;
         let mut pixel_data = Vec::with_capacity(num_bytes);
 
         while pixel_data.len() < num_bytes {
+            let rows = pixel_data.len() / row_bytes;
             let run_packet = try!(self.r.read_u8());
             // If the highest bit in `run_packet` is set, then we repeat pixels
             //
@@ -349,24 +366,73 @@ impl<R: Read + Seek> TGADecoder<R> {
                 let num_raw_bytes = (run_packet + 1) as usize * self.bytes_per_pixel;
                 try!(self.r.by_ref().take(num_raw_bytes as u64).read_to_end(&mut pixel_data));
             }
+            // Packets may span rows
+            let done = (pixel_data.len() / row_bytes).min(self.height);
+            if done > rows {
+                try!(monitor.update(done as u64, self.height as u64));
+            }
         }
 
         Ok(pixel_data)
     }
 
-    /// Reverse from BGR encoding to RGB encoding
+    /// Convert the stored colors to the 8-bit samples of the color type
     ///
-    /// TGA files are stored in the BGRA encoding. This function swaps
-    /// the blue and red bytes in the `pixels` array.
-    fn reverse_encoding(&mut self, pixels: &mut [u8]) {
-        // We only need to reverse the encoding of color images
-        match self.color_type {
-            ColorType::RGB(8) | ColorType::RGBA(8) => {
-                for chunk in pixels.chunks_mut(self.bytes_per_pixel) {
+    /// TGA files store colors in the BGRA encoding, either with 8 bits per channel or with
+    /// 5 bits per color and a single attribute bit packed into a little endian u16. Colors
+    /// stored in 32 bits without alpha bits have their fourth byte dropped.
+    fn decode_colors(&self, data: Vec<u8>) -> Vec<u8> {
+        let color_bits = if self.image_type.is_color_mapped() {
+            self.header.map_entry_size
+        } else {
+            self.header.pixel_depth
+        };
+        let has_alpha = self.color_type == ColorType::RGBA(8);
+
+        match (color_bits, self.color_type) {
+            (15, _) | (16, ColorType::RGB(8)) | (16, ColorType::RGBA(8)) => {
+                let expand = |v: u16| ((v & 0x1f) << 3 | (v & 0x1f) >> 2) as u8;
+                let channels = if has_alpha { 4 } else { 3 };
+                let mut pixels = Vec::with_capacity(data.len() / 2 * channels);
+                for chunk in data.chunks(2) {
+                    let v = chunk[0] as u16 | (chunk[1] as u16) << 8;
+                    pixels.push(expand(v >> 10));
+                    pixels.push(expand(v >> 5));
+                    pixels.push(expand(v));
+                    if has_alpha {
+                        pixels.push(if v & 0x8000 != 0 { 0xff } else { 0 });
+                    }
+                }
+                pixels
+            }
+            (24, _) | (32, ColorType::RGBA(8)) => {
+                let mut pixels = data;
+                for chunk in pixels.chunks_mut(color_bits as usize / 8) {
                     chunk.swap(0, 2);
                 }
+                pixels
+            }
+            (32, _) => {
+                data.chunks(4).flat_map(|chunk| vec![chunk[2], chunk[1], chunk[0]]).collect()
+            }
+            _ => data,
+        }
+    }
+
+    /// Flip the image horizontally depending on the right-to-left bit
+    ///
+    /// The bit in position 4 of the image descriptor byte is set if the pixels of each row
+    /// are stored from right to left.
+    fn flip_horizontally(&self, pixels: &mut [u8]) {
+        if 0b10000 & self.header.image_desc != 0 {
+            let channels = pixels.len() / (self.width * self.height);
+            for row in pixels.chunks_mut(self.width * channels) {
+                for x in 0..self.width / 2 {
+                    for c in 0..channels {
+                        row.swap(x * channels + c, (self.width - 1 - x) * channels + c);
+                    }
+                }
             }
-            _ => { }
         }
     }
 
@@ -418,15 +484,103 @@ impl<R: Read + Seek> ImageDecoder for TGADecoder<R> {
     fn row_len(&mut self) -> ImageResult<usize> {
         try!(self.read_metadata());
 
-        Ok(self.bytes_per_pixel * 8 * self.width)
+        Ok((bits_per_pixel(self.color_type) + 7) / 8 * self.width)
     }
 
-    fn read_scanline(&mut self, _buf: &mut [u8]) -> ImageResult<u32> {
-        unimplemented!();
+    /// Reads the next row of the image into ```buf```.
+    ///
+    /// Images may be stored bottom up or right to left, so the whole image is decoded when the
+    /// first row is read.
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        try!(self.read_metadata());
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        if self.rows.is_none() {
+            self.rows = Some(try!(self.read_image_data(&mut Monitor::new())));
+        }
+        let start = self.row * len;
+        buf[..len].copy_from_slice(&self.rows.as_ref().unwrap()[start..start + len]);
+        self.row += 1;
+        Ok(self.row as u32 - 1)
     }
 
     fn read_image(&mut self) -> ImageResult<DecodingResult> {
         try!(self.read_metadata());
-        self.read_image_data().map(DecodingResult::U8)
+        self.read_image_data(&mut Monitor::new()).map(DecodingResult::U8)
+    }
+
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        try!(self.read_metadata());
+        self.read_image_data(monitor).map(DecodingResult::U8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+
+    use super::TGADecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn decode(data: Vec<u8>) -> (ColorType, Vec<u8>) {
+        let mut decoder = TGADecoder::new(Cursor::new(data));
+        let color = decoder.colortype().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (color, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn packed_colors_and_origin() {
+        // A 2x2 image of 5-5-5-1 pixels stored bottom-up and right-to-left
+        let mut tga = vec![0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 16, 0b10001];
+        for &pixel in &[0x801fu16, 0x03e0, 0xfc00, 0x8000] {
+            tga.extend_from_slice(&[pixel as u8, (pixel >> 8) as u8]);
+        }
+        assert_eq!(decode(tga), (ColorType::RGBA(8), vec![
+            0, 0, 0, 255, 255, 0, 0, 255,
+            0, 255, 0, 0, 0, 0, 255, 255,
+        ]));
+
+        // The same pixels through a color map of 15-bit entries starting at index 4
+        let mut tga = vec![0, 1, 1, 4, 0, 4, 0, 15, 0, 0, 0, 0, 2, 0, 2, 0, 8, 0b10000];
+        for &entry in &[0x801fu16, 0x03e0, 0xfc00, 0x8000] {
+            tga.extend_from_slice(&[entry as u8, (entry >> 8) as u8]);
+        }
+        tga.extend_from_slice(&[4, 5, 6, 7]);
+        assert_eq!(decode(tga), (ColorType::RGB(8), vec![
+            0, 0, 0, 255, 0, 0,
+            0, 255, 0, 0, 0, 255,
+        ]));
+    }
+
+    #[test]
+    fn scanlines() {
+        // Run length encoded and raw images, stored bottom up
+        for name in &["ctc24", "ucm8", "utc32"] {
+            let mut tga = Vec::new();
+            File::open(format!("tests/images/tga/testsuite/{}.tga", name)).unwrap().read_to_end(&mut tga).unwrap();
+            let (_, image) = decode(tga.clone());
+
+            let mut decoder = TGADecoder::new(Cursor::new(tga));
+            let (_, height) = decoder.dimensions().unwrap();
+            let mut row = vec![0; decoder.row_len().unwrap()];
+            assert_eq!(image.len(), row.len() * height as usize);
+            assert!(decoder.read_scanline(&mut row[1..]).is_err());
+            for (y, expected) in image.chunks(row.len()).enumerate() {
+                assert_eq!(decoder.read_scanline(&mut row).unwrap(), y as u32);
+                assert_eq!(&row[..], expected);
+            }
+            assert!(decoder.read_scanline(&mut row).is_err());
+        }
     }
 }
@@ -0,0 +1,194 @@
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, LittleEndian};
+
+use color::ColorType;
+use image::{ImageEncoder, ImageResult};
+
+/// The longest run of pixels a single packet can hold
+const MAX_PACKET_PIXELS: usize = 128;
+
+/// The footer marking the file as TGA 2.0, without extension or developer areas
+const FOOTER: &'static [u8] = b"\0\0\0\0\0\0\0\0TRUEVISION-XFILE.\0";
+
+/// The representation of a TGA encoder
+///
+/// Images are written with their origin at the top left, with run length encoded packets
+/// that do not cross rows if compression is enabled.
+pub struct TGAEncoder<W> {
+    w: W,
+    rle: bool,
+}
+
+impl<W: Write> TGAEncoder<W> {
+    /// Create a new encoder that writes uncompressed images to ```w```
+    pub fn new(w: W) -> TGAEncoder<W> {
+        TGAEncoder {
+            w: w,
+            rle: false,
+        }
+    }
+
+    /// Run length encode the pixels if ```rle``` is true, or store them as they are otherwise
+    pub fn set_rle(&mut self, rle: bool) {
+        self.rle = rle;
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// Only 8-bit gray and rgb images, with or without alpha, are supported.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        // The image type without compression, the bytes per pixel and the bits of alpha
+        let (image_type, bytes_per_pixel, alpha_bits) = match color {
+            ColorType::Gray(8) => (3, 1, 0),
+            ColorType::GrayA(8) => (3, 2, 8),
+            ColorType::RGB(8) => (2, 3, 0),
+            ColorType::RGBA(8) => (2, 4, 8),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Unsupported color type {:?}", color))),
+        };
+        if width == 0 || height == 0 || width > 0xFFFF || height > 0xFFFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid TGA image dimensions"))
+        }
+        let row_len = width as usize * bytes_per_pixel;
+        if data.len() < row_len * height as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+
+        // Header, without image id or color map
+        try!(self.w.write_u8(0));
+        try!(self.w.write_u8(0));
+        try!(self.w.write_u8(if self.rle { image_type + 8 } else { image_type }));
+        try!(self.w.write_all(&[0; 5]));
+        try!(self.w.write_u16::<LittleEndian>(0));
+        try!(self.w.write_u16::<LittleEndian>(0));
+        try!(self.w.write_u16::<LittleEndian>(width as u16));
+        try!(self.w.write_u16::<LittleEndian>(height as u16));
+        try!(self.w.write_u8(bytes_per_pixel as u8 * 8));
+        // The screen origin bit puts the first row at the top
+        try!(self.w.write_u8(0b100000 | alpha_bits));
+
+        let mut row = vec![0; row_len];
+        for source in data[..row_len * height as usize].chunks(row_len) {
+            row.copy_from_slice(source);
+            // Color pixels are stored as BGR(A)
+            if bytes_per_pixel >= 3 {
+                for pixel in row.chunks_mut(bytes_per_pixel) {
+                    pixel.swap(0, 2);
+                }
+            }
+
+            if self.rle {
+                try!(write_rle_row(&mut self.w, &row, bytes_per_pixel));
+            } else {
+                try!(self.w.write_all(&row));
+            }
+        }
+
+        self.w.write_all(FOOTER)
+    }
+}
+
+impl<W: Write> ImageEncoder for TGAEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Write the pixels of ```row``` as packets repeating a single pixel, or packets of raw pixels
+/// that last until the next repeated pixel.
+fn write_rle_row<W: Write>(w: &mut W, row: &[u8], bytes_per_pixel: usize) -> io::Result<()> {
+    let pixels: Vec<&[u8]> = row.chunks(bytes_per_pixel).collect();
+    let mut i = 0;
+    while i < pixels.len() {
+        let mut run = 1;
+        while i + run < pixels.len() && run < MAX_PACKET_PIXELS && pixels[i + run] == pixels[i] {
+            run += 1;
+        }
+
+        if run > 1 {
+            try!(w.write_u8(0x80 | (run - 1) as u8));
+            try!(w.write_all(pixels[i]));
+            i += run;
+        } else {
+            let start = i;
+            while i < pixels.len() && i - start < MAX_PACKET_PIXELS
+                && !(i + 1 < pixels.len() && pixels[i] == pixels[i + 1]) {
+                i += 1;
+            }
+            try!(w.write_u8((i - start - 1) as u8));
+            for pixel in &pixels[start..i] {
+                try!(w.write_all(pixel));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::TGAEncoder;
+    use super::super::TGADecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    fn encode(data: &[u8], width: u32, height: u32, color: ColorType, rle: bool) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = TGAEncoder::new(&mut encoded);
+            encoder.set_rle(rle);
+            encoder.encode(data, width, height, color).unwrap();
+        }
+        encoded
+    }
+
+    fn decode(data: Vec<u8>) -> (ColorType, u32, u32, Vec<u8>) {
+        let mut decoder = TGADecoder::new(Cursor::new(data));
+        let color = decoder.colortype().unwrap();
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (color, width, height, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        // Rows with runs, single pixels and a run longer than a packet holds
+        let (width, height) = (300, 5);
+        let mut img = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let v = if y % 2 == 0 { (x / 7) as u8 } else { (x * y) as u8 };
+                img.extend_from_slice(&[v, y as u8, 255 - v, if x < 150 { 255 } else { v }]);
+            }
+        }
+
+        for &(color, channels) in &[(ColorType::Gray(8), 1), (ColorType::GrayA(8), 2),
+                                    (ColorType::RGB(8), 3), (ColorType::RGBA(8), 4)] {
+            let data: Vec<u8> = img.chunks(4).flat_map(|p| match channels {
+                1 => vec![p[0]],
+                2 => vec![p[0], p[3]],
+                3 => p[..3].to_vec(),
+                _ => p.to_vec(),
+            }).collect();
+
+            let raw = encode(&data, width, height, color, false);
+            let rle = encode(&data, width, height, color, true);
+            assert_eq!(raw[2], if channels < 3 { 3 } else { 2 });
+            assert_eq!(rle[2], raw[2] + 8);
+            assert!(rle.len() < raw.len());
+            assert_eq!(decode(raw), (color, width, height, data.clone()));
+            assert_eq!(decode(rle), (color, width, height, data));
+        }
+    }
+
+    #[test]
+    fn packets() {
+        let data = [1, 1, 1, 2, 3, 4, 4, 5];
+        let encoded = encode(&data, 8, 1, ColorType::Gray(8), true);
+        assert_eq!(&encoded[18..encoded.len() - 26], &[0x82, 1, 1, 2, 3, 0x81, 4, 0, 5]);
+    }
+}
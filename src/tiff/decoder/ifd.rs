@@ -5,7 +5,7 @@ use std::collections::{HashMap};
 
 use super::stream::{ByteOrder, SmartReader, EndianReader};
 
-use self::Value::{Unsigned, List};
+use self::Value::{Unsigned, UnsignedBig, Rational, List};
 
 macro_rules! tags {
     {$(
@@ -57,10 +57,10 @@ tags!{
     MinSampleValue 280; // TODO add support
     Model 272; // TODO add support
     NewSubfileType 254; // TODO add support
-    Orientation 274; // TODO add support
+    Orientation 274;
     PhotometricInterpretation 262;
     PlanarConfiguration 284;
-    ResolutionUnit 296; // TODO add support
+    ResolutionUnit 296;
     RowsPerStrip 278;
     SamplesPerPixel 277;
     Software 305;
@@ -72,6 +72,11 @@ tags!{
     YResolution 283;
     // Advanced tags
     Predictor 317;
+    TileWidth 322;
+    TileLength 323;
+    TileOffsets 324;
+    TileByteCounts 325;
+    SampleFormat 339;
 }
 
 enum_from_primitive! {
@@ -82,6 +87,7 @@ pub enum Type {
     SHORT = 3,
     LONG = 4,
     RATIONAL = 5,
+    LONG8 = 16,
 }
 }
 
@@ -91,6 +97,9 @@ pub enum Type {
 pub enum Value {
     //Signed(i32),
     Unsigned(u32),
+    UnsignedBig(u64),
+    /// A fraction of a numerator and a denominator
+    Rational(u32, u32),
     List(Vec<Value>)
 }
 
@@ -98,11 +107,31 @@ impl Value {
     pub fn as_u32(self) -> ::image::ImageResult<u32> {
         match self {
             Unsigned(val) => Ok(val),
-            val => Err(::image::ImageError::FormatError(format!(
+            UnsignedBig(val) if val <= u32::max_value() as u64 => Ok(val as u32),
+            val => Err(::image::ImageError::malformed(::image::ImageFormat::TIFF, format!(
                 "Expected unsigned integer, {:?} found.", val
             )))
         }
     }
+    pub fn as_u64(self) -> ::image::ImageResult<u64> {
+        match self {
+            Unsigned(val) => Ok(val as u64),
+            UnsignedBig(val) => Ok(val),
+            val => Err(::image::ImageError::malformed(::image::ImageFormat::TIFF, format!(
+                "Expected unsigned integer, {:?} found.", val
+            )))
+        }
+    }
+    pub fn as_f64(self) -> ::image::ImageResult<f64> {
+        match self {
+            Unsigned(val) => Ok(val as f64),
+            UnsignedBig(val) => Ok(val as f64),
+            Rational(num, denom) if denom != 0 => Ok(num as f64 / denom as f64),
+            val => Err(::image::ImageError::malformed(::image::ImageFormat::TIFF, format!(
+                "Expected number, {:?} found.", val
+            )))
+        }
+    }
     pub fn as_u32_vec(self) -> ::image::ImageResult<Vec<u32>> {
         match self {
             List(vec) => {
@@ -112,16 +141,27 @@ impl Value {
                 }
                 Ok(new_vec)
             },
-            Unsigned(val) => Ok(vec![val]),
-            //_ => Err(::image::FormatError("Tag data malformed.".to_string()))
+            val => Ok(vec![try!(val.as_u32())]),
+        }
+    }
+    pub fn as_u64_vec(self) -> ::image::ImageResult<Vec<u64>> {
+        match self {
+            List(vec) => {
+                let mut new_vec = Vec::with_capacity(vec.len());
+                for v in vec {
+                    new_vec.push(try!(v.as_u64()))
+                }
+                Ok(new_vec)
+            },
+            val => Ok(vec![try!(val.as_u64())]),
         }
     }
 }
 
 pub struct Entry {
     type_: Type,
-    count: u32,
-    offset: [u8; 4],
+    count: u64,
+    offset: [u8; 8],
 }
 
 impl ::std::fmt::Debug for Entry {
@@ -135,7 +175,7 @@ impl ::std::fmt::Debug for Entry {
 }
 
 impl Entry {
-    pub fn new(type_: Type, count: u32, offset: [u8; 4]) -> Entry {
+    pub fn new(type_: Type, count: u64, offset: [u8; 8]) -> Entry {
         Entry {
             type_: type_,
             count: count,
@@ -154,36 +194,54 @@ impl Entry {
     pub fn val<R: Read + Seek>(&self, decoder: &mut super::TIFFDecoder<R>)
     -> ::image::ImageResult<Value> {
         let bo = decoder.byte_order();
-        match (self.type_, self.count) {
-            // TODO check if this could give wrong results
-            // at a different endianess of file/computer.
-            (Type::BYTE, 1) => Ok(Unsigned(self.offset[0] as u32)),
-            (Type::SHORT, 1) => Ok(Unsigned(try!(self.r(bo).read_u16()) as u32)),
-            (Type::SHORT, 2) => {
-                let mut r = self.r(bo);
-                Ok(List(vec![
-                    Unsigned(try!(r.read_u16()) as u32),
-                    Unsigned(try!(r.read_u16()) as u32)
-                ]))
-            },
-            (Type::SHORT, n) => {
-                let mut v = Vec::with_capacity(n as usize);
-                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
-                for _ in 0 .. n {
-                    v.push(Unsigned(try!(decoder.read_short()) as u32))
-                }
-                Ok(List(v))
-            },
-            (Type::LONG, 1) => Ok(Unsigned(try!(self.r(bo).read_u32()))),
-            (Type::LONG, n) => {
-                let mut v = Vec::with_capacity(n as usize);
-                try!(decoder.goto_offset(try!(self.r(bo).read_u32())));
-                for _ in 0 .. n {
-                    v.push(Unsigned(try!(decoder.read_long())))
-                }
-                Ok(List(v))
+        let size = match self.type_ {
+            Type::BYTE => 1,
+            Type::SHORT => 2,
+            Type::LONG => 4,
+            Type::RATIONAL | Type::LONG8 => 8,
+            _ => return Err(::image::ImageError::unsupported(::image::ImageFormat::TIFF,
+                "Unsupported data type.".to_string()))
+        };
+        let len = match self.count.checked_mul(size) {
+            Some(len) => len,
+            None => return Err(::image::ImageError::malformed(::image::ImageFormat::TIFF,
+                "Tag data too long.".to_string()))
+        };
+        // Values are stored in the offset field if they fit in there
+        let mut r = if len <= decoder.offset_len() as u64 {
+            self.r(bo)
+        } else {
+            let offset = if decoder.is_bigtiff() {
+                try!(self.r(bo).read_u64())
+            } else {
+                try!(self.r(bo).read_u32()) as u64
+            };
+            try!(decoder.goto_offset(offset));
+            let mut data = Vec::new();
+            try!(decoder.reader.by_ref().take(len).read_to_end(&mut data));
+            if (data.len() as u64) < len {
+                return Err(::image::ImageError::malformed(::image::ImageFormat::TIFF,
+                    "Tag data truncated.".to_string()))
             }
-            _ => Err(::image::ImageError::UnsupportedError("Unsupported data type.".to_string()))
+            SmartReader::wrap(io::Cursor::new(data), bo)
+        };
+        let mut values = Vec::new();
+        for _ in 0..self.count {
+            values.push(match self.type_ {
+                Type::BYTE => {
+                    let mut byte = [0];
+                    try!(r.read_exact(&mut byte));
+                    Unsigned(byte[0] as u32)
+                },
+                Type::SHORT => Unsigned(try!(r.read_u16()) as u32),
+                Type::LONG => Unsigned(try!(r.read_u32())),
+                Type::RATIONAL => Rational(try!(r.read_u32()), try!(r.read_u32())),
+                _ => UnsignedBig(try!(r.read_u64())),
+            })
+        }
+        match values.len() {
+            1 => Ok(values.pop().unwrap()),
+            _ => Ok(List(values))
         }
     }
 }
@@ -1,18 +1,26 @@
 use std::io::{self, Read, Seek};
 use std::mem;
 use num_traits::{FromPrimitive, Num};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use image;
 use image::{
+    decoder_metadata,
     ImageError,
+    ImageFormat,
+    LimitErrorKind,
+    Limits,
+    ParameterErrorKind,
     ImageResult,
     ImageDecoder,
     DecodingResult,
-    DecodingBuffer
+    DecodingBuffer,
+    Metadata
 };
 
 use color::{ColorType};
+use metadata::exif::{Exif, Ifd};
+use monitor::Monitor;
 
 use self::ifd::Directory;
 
@@ -21,6 +29,7 @@ use self::stream::{
     EndianReader,
     SmartReader,
     LZWReader,
+    DeflateReader,
     PackBitsReader
 };
 
@@ -50,7 +59,9 @@ enum CompressionMethod {
     Fax4 = 4,
     LZW = 5,
     JPEG = 6,
-    PackBits = 32773
+    Deflate = 8,
+    PackBits = 32773,
+    OldDeflate = 32946
 }
 }
 
@@ -62,6 +73,16 @@ enum PlanarConfiguration {
 }
 }
 
+enum_from_primitive! {
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SampleFormat {
+    Uint = 1,
+    Int = 2,
+    IEEEFP = 3,
+    Void = 4
+}
+}
+
 enum_from_primitive! {
 #[derive(Clone, Copy, Debug)]
 enum Predictor {
@@ -70,6 +91,13 @@ enum Predictor {
 }
 }
 
+/// The tags of the fields describing how the pixels and their colors are stored, from the
+/// subfile type to the ICC profile
+const LAYOUT_TAGS: &'static [u16] = &[
+    254, 255, 256, 257, 258, 259, 262, 266, 273, 277, 278, 279, 284, 317, 320, 322, 323, 324, 325,
+    330, 338, 339, 347, 529, 530, 532, 34675,
+];
+
 /// The representation of a TIFF decoder
 ///
 /// Currently does not support decoding of interlaced images
@@ -77,14 +105,19 @@ enum Predictor {
 pub struct TIFFDecoder<R> where R: Read + Seek {
     reader: SmartReader<R>,
     byte_order: ByteOrder,
-    next_ifd: Option<u32>,
+    bigtiff: bool,
+    first_ifd: Option<u64>,
+    next_ifd: Option<u64>,
+    read_ifds: HashSet<u64>,
     ifd: Option<Directory>,
     width: u32,
     height: u32,
     bits_per_sample: Vec<u8>,
     samples: u8,
+    sample_format: SampleFormat,
     photometric_interpretation: PhotometricInterpretation,
-    compression_method: CompressionMethod
+    compression_method: CompressionMethod,
+    limits: Limits
 }
 
 trait Wrapping {
@@ -103,6 +136,12 @@ impl Wrapping for u16 {
     }
 }
 
+impl Wrapping for u32 {
+    fn wrapping_add(&self, other: Self) -> Self {
+        u32::wrapping_add(*self, other)
+    }
+}
+
 fn rev_hpredict_nsamp<T>(mut image: Vec<T>,
                          size: (u32, u32),
                          samples: usize)
@@ -122,10 +161,10 @@ fn rev_hpredict_nsamp<T>(mut image: Vec<T>,
 
 fn rev_hpredict(image: DecodingResult, size: (u32, u32), color_type: ColorType) -> ImageResult<DecodingResult> {
     let samples = match color_type {
-        ColorType::Gray(8) | ColorType::Gray(16) => 1,
-        ColorType::RGB(8) | ColorType::RGB(16) => 3,
-        ColorType::RGBA(8) | ColorType::RGBA(16) => 4,
-        _ => return Err(ImageError::UnsupportedError(format!(
+        ColorType::Gray(8) | ColorType::Gray(16) | ColorType::Gray(32) => 1,
+        ColorType::RGB(8) | ColorType::RGB(16) | ColorType::RGB(32) => 3,
+        ColorType::RGBA(8) | ColorType::RGBA(16) | ColorType::RGBA(32) => 4,
+        _ => return Err(ImageError::unsupported(ImageFormat::TIFF, format!(
             "Horizontal predictor for {:?} is unsupported.", color_type
         )))
     };
@@ -136,26 +175,60 @@ fn rev_hpredict(image: DecodingResult, size: (u32, u32), color_type: ColorType)
         DecodingResult::U16(buf) => {
             DecodingResult::U16(rev_hpredict_nsamp(buf, size, samples))
         }
+        DecodingResult::U32(buf) => {
+            DecodingResult::U32(rev_hpredict_nsamp(buf, size, samples))
+        }
+        DecodingResult::F32(_) => return Err(ImageError::unsupported(ImageFormat::TIFF,
+            "Horizontal predictor for floating point samples is unsupported.".to_string()
+        ))
     })
 }
 
+/// Copies ```len``` bits starting at bit ```src``` of ```from``` to bit ```dst``` of ```to```,
+/// counting the bits of each byte from the most significant one
+fn copy_bits(from: &[u8], src: usize, to: &mut [u8], dst: usize, len: usize) {
+    if src % 8 == 0 && dst % 8 == 0 && len % 8 == 0 {
+        to[dst / 8..(dst + len) / 8].copy_from_slice(&from[src / 8..(src + len) / 8]);
+        return
+    }
+    for i in 0..len {
+        let bit = from[(src + i) / 8] >> (7 - (src + i) % 8) & 1;
+        let byte = &mut to[(dst + i) / 8];
+        *byte = *byte & !(0x80 >> ((dst + i) % 8)) | bit << (7 - (dst + i) % 8);
+    }
+}
+
 impl<R: Read + Seek> TIFFDecoder<R> {
     /// Create a new decoder that decodes from the stream ```r```
     pub fn new(r: R) -> ImageResult<TIFFDecoder<R>> {
         TIFFDecoder {
             reader: SmartReader::wrap(r, ByteOrder::LittleEndian),
             byte_order: ByteOrder::LittleEndian,
+            bigtiff: false,
+            first_ifd: None,
             next_ifd: None,
+            read_ifds: HashSet::new(),
             ifd: None,
             width: 0,
             height: 0,
             bits_per_sample: vec![1],
             samples: 1,
+            sample_format: SampleFormat::Uint,
             photometric_interpretation: PhotometricInterpretation::BlackIsZero,
-            compression_method: CompressionMethod::None
+            compression_method: CompressionMethod::None,
+            limits: Limits::default()
         }.init()
     }
 
+    /// Sets the limits the buffers allocated while decoding are checked against, which are
+    /// `Limits::default()` unless set
+    ///
+    /// Strips and tiles are decoded into buffers of their own, so a small image declaring huge
+    /// tiles fails with `ImageError::Limits` before they are allocated.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
     fn read_header(&mut self) -> ImageResult<()> {
         let mut endianess = Vec::with_capacity(2);
         try!(self.reader.by_ref().take(2).read_to_end(&mut endianess));
@@ -166,22 +239,35 @@ impl<R: Read + Seek> TIFFDecoder<R> {
             b"MM" => {
                 self.byte_order = ByteOrder::BigEndian;
                 self.reader.byte_order = ByteOrder::BigEndian;  },
-            _ => return Err(image::ImageError::FormatError(
+            _ => return Err(image::ImageError::malformed(ImageFormat::TIFF,
                 "TIFF signature not found.".to_string()
             ))
         }
-        if try!(self.read_short()) != 42 {
-            return Err(image::ImageError::FormatError("TIFF signature invalid.".to_string()))
+        match try!(self.read_short()) {
+            42 => self.bigtiff = false,
+            43 => {
+                // BigTIFF stores the size of its offsets, which is always 8, followed by a
+                // constant 0
+                if try!(self.read_short()) != 8 || try!(self.read_short()) != 0 {
+                    return Err(image::ImageError::malformed(ImageFormat::TIFF,
+                        "BigTIFF header invalid.".to_string()))
+                }
+                self.bigtiff = true
+            },
+            _ => return Err(image::ImageError::malformed(ImageFormat::TIFF,
+                "TIFF signature invalid.".to_string()))
         }
-        self.next_ifd = match try!(self.read_long()) {
+        self.first_ifd = match try!(self.read_ifd_offset()) {
             0 => None,
             n => Some(n)
         };
+        self.next_ifd = self.first_ifd;
         Ok(())
     }
 
     /// Initializes the decoder.
-    pub fn init(self) -> ImageResult<TIFFDecoder<R>> {
+    pub fn init(mut self) -> ImageResult<TIFFDecoder<R>> {
+        try!(self.read_header());
         self.next_image()
     }
 
@@ -189,15 +275,19 @@ impl<R: Read + Seek> TIFFDecoder<R> {
     /// If there is no further image in the TIFF file a format error is returned.
     /// To determine whether there are more images call `TIFFDecoder::more_images` instead.
     pub fn next_image(mut self) -> ImageResult<TIFFDecoder<R>> {
-        try!(self.read_header());
         self.ifd = Some(try!(self.read_ifd()));
+        // Fields with defaults must not carry over from the previous image
+        self.bits_per_sample = vec![1];
+        self.samples = 1;
+        self.compression_method = CompressionMethod::None;
+        self.sample_format = SampleFormat::Uint;
         self.width = try!(self.get_tag_u32(ifd::Tag::ImageWidth));
         self.height = try!(self.get_tag_u32(ifd::Tag::ImageLength));
         self.photometric_interpretation = match FromPrimitive::from_u32(
             try!(self.get_tag_u32(ifd::Tag::PhotometricInterpretation))
         ) {
             Some(val) => val,
-            None => return Err(image::ImageError::UnsupportedError(
+            None => return Err(image::ImageError::unsupported(ImageFormat::TIFF,
                 "The image is using an unknown photometric interpretation.".to_string()
             ))
         };
@@ -206,7 +296,7 @@ impl<R: Read + Seek> TIFFDecoder<R> {
                 Some(method) =>  {
                     self.compression_method = method
                 },
-                None => return Err(image::ImageError::UnsupportedError(
+                None => return Err(image::ImageError::unsupported(ImageFormat::TIFF,
                     "Unknown compression method.".to_string()
                 ))
             }
@@ -214,21 +304,34 @@ impl<R: Read + Seek> TIFFDecoder<R> {
         if let Some(val) = try!(self.find_tag_u32(ifd::Tag::SamplesPerPixel)) {
             self.samples = val as u8
         }
-        match self.samples {
-            1 => {
-                if let Some(val) = try!(self.find_tag_u32(ifd::Tag::BitsPerSample)) {
-                    self.bits_per_sample = vec![val as u8]
-                }
-            }
-            3 | 4 => {
-                if let Some(val) = try!(self.find_tag_u32_vec(ifd::Tag::BitsPerSample)) {
-                    self.bits_per_sample = val.iter().map(|&v| v as u8).collect()
-                }
-
-            }
-            _ => return Err(image::ImageError::UnsupportedError(
+        let bits_per_sample = match self.samples {
+            1 => try!(self.find_tag_u32(ifd::Tag::BitsPerSample)).map(|val| vec![val]),
+            3 | 4 => try!(self.find_tag_u32_vec(ifd::Tag::BitsPerSample)),
+            _ => return Err(image::ImageError::unsupported(ImageFormat::TIFF,
                 format!("{} samples per pixel is supported.", self.samples)
             ))
+        };
+        if let Some(bits_per_sample) = bits_per_sample {
+            // Rows are measured in samples of these sizes, which must not be empty
+            match bits_per_sample.iter().find(|&&bits| ![1, 2, 4, 8, 16, 32].contains(&bits)) {
+                Some(bits) => return Err(ImageError::malformed(ImageFormat::TIFF,
+                    format!("{} bits per sample are invalid or unsupported.", bits)
+                )),
+                None if bits_per_sample.is_empty() => return Err(ImageError::malformed(ImageFormat::TIFF,
+                    "Bits per sample are missing.".to_string()
+                )),
+                None => self.bits_per_sample = bits_per_sample.iter().map(|&v| v as u8).collect()
+            }
+        }
+        if let Some(val) = try!(self.find_tag_u32_vec(ifd::Tag::SampleFormat)) {
+            self.sample_format = match val.first().map(|&v| FromPrimitive::from_u32(v)) {
+                // The format of the first sample is used for all of them
+                Some(Some(format)) => format,
+                None => SampleFormat::Uint,
+                Some(None) => return Err(ImageError::unsupported(ImageFormat::TIFF,
+                    "Unknown sample format.".to_string()
+                ))
+            }
         }
         Ok(self)
     }
@@ -241,11 +344,69 @@ impl<R: Read + Seek> TIFFDecoder<R> {
         }
     }
 
+    /// Returns the number of images in the file.
+    ///
+    /// Only the links between the image file directories are followed, their
+    /// entries are neither read nor decoded.
+    pub fn page_count(&mut self) -> ImageResult<u32> {
+        let mut visited = HashSet::new();
+        let mut next = self.first_ifd;
+        while let Some(offset) = next {
+            if !visited.insert(offset) {
+                return Err(ImageError::malformed(ImageFormat::TIFF,
+                    "Image file directories form a cycle.".to_string()
+                ))
+            }
+            try!(self.goto_offset(offset));
+            let entries = try!(self.read_entry_count());
+            let entry_len = 4 + 2 * self.offset_len() as u64;
+            let skip = match entries.checked_mul(entry_len) {
+                Some(skip) if skip <= i64::max_value() as u64 => skip as i64,
+                _ => return Err(ImageError::malformed(ImageFormat::TIFF,
+                    "Image file directory too large.".to_string()
+                ))
+            };
+            try!(self.reader.seek(io::SeekFrom::Current(skip)));
+            next = match try!(self.read_ifd_offset()) {
+                0 => None,
+                n => Some(n)
+            };
+        }
+        Ok(visited.len() as u32)
+    }
+
     /// Returns the byte_order
     pub fn byte_order(&self) -> ByteOrder {
         self.byte_order
     }
 
+    /// Returns `true` if the file is a BigTIFF, which uses 64-bit offsets
+    pub fn is_bigtiff(&self) -> bool {
+        self.bigtiff
+    }
+
+    /// Returns the EXIF fields of the first image of the file
+    ///
+    /// The fields describing how the pixels and their colors are stored, like the dimensions and
+    /// the compression, are left out as they do not apply to images stored elsewhere. The
+    /// EXIF data of BigTIFF files is not supported.
+    pub fn exif(&mut self) -> ImageResult<Exif> {
+        if self.bigtiff {
+            return Err(ImageError::unsupported(ImageFormat::TIFF, "EXIF data of BigTIFF files".to_string()))
+        }
+        let position = try!(self.reader.seek(io::SeekFrom::Current(0)));
+        let exif = Exif::read(&mut self.reader);
+        try!(self.goto_offset(position));
+        let mut exif = try!(exif);
+        exif.retain(|field| field.ifd != Ifd::Primary || !LAYOUT_TAGS.contains(&field.tag));
+        Ok(exif)
+    }
+
+    /// The length in bytes of offsets and of the offset/value field of IFD entries
+    fn offset_len(&self) -> usize {
+        if self.bigtiff { 8 } else { 4 }
+    }
+
     /// Reads a TIFF short value
     #[inline]
     pub fn read_short(&mut self) -> Result<u16, io::Error> {
@@ -258,18 +419,45 @@ impl<R: Read + Seek> TIFFDecoder<R> {
         self.reader.read_u32()
     }
 
+    /// Reads a BigTIFF long8 value
+    #[inline]
+    pub fn read_long8(&mut self) -> Result<u64, io::Error> {
+        self.reader.read_u64()
+    }
+
     /// Reads a TIFF IFA offset/value field
+    ///
+    /// It is 8 bytes long in BigTIFF files, the last 4 bytes are zero otherwise.
     #[inline]
-    pub fn read_offset(&mut self) -> Result<[u8; 4], io::Error> {
-        let mut val = [0; 4];
-        try!(self.reader.read_exact(&mut val));
+    pub fn read_offset(&mut self) -> Result<[u8; 8], io::Error> {
+        let mut val = [0; 8];
+        let len = self.offset_len();
+        try!(self.reader.read_exact(&mut val[..len]));
         Ok(val)
     }
 
+    /// Reads the offset of an IFD
+    fn read_ifd_offset(&mut self) -> Result<u64, io::Error> {
+        if self.bigtiff {
+            self.read_long8()
+        } else {
+            self.read_long().map(|n| n as u64)
+        }
+    }
+
+    /// Reads the number of entries of an IFD
+    fn read_entry_count(&mut self) -> Result<u64, io::Error> {
+        if self.bigtiff {
+            self.read_long8()
+        } else {
+            self.read_short().map(|n| n as u64)
+        }
+    }
+
     /// Moves the cursor to the specified offset
     #[inline]
-    pub fn goto_offset(&mut self, offset: u32) -> io::Result<()> {
-        self.reader.seek(io::SeekFrom::Start(offset as u64)).map(|_| ())
+    pub fn goto_offset(&mut self, offset: u64) -> io::Result<()> {
+        self.reader.seek(io::SeekFrom::Start(offset)).map(|_| ())
     }
 
     /// Reads a IFD entry.
@@ -277,23 +465,28 @@ impl<R: Read + Seek> TIFFDecoder<R> {
     //
     // Tag   2 bytes
     // Type  2 bytes
-    // Count 4 bytes
-    // Value 4 bytes either a pointer the value itself
+    // Count 4 bytes (8 bytes in BigTIFF)
+    // Value 4 bytes (8 bytes in BigTIFF) either a pointer the value itself
     fn read_entry(&mut self) -> ImageResult<Option<(ifd::Tag, ifd::Entry)>> {
         let tag = ifd::Tag::from_u16(try!(self.read_short()));
         let type_: ifd::Type = match FromPrimitive::from_u16(try!(self.read_short())) {
             Some(t) => t,
             None => {
                 // Unknown type. Skip this entry according to spec.
-                try!(self.read_long());
-                try!(self.read_long());
+                try!(self.read_ifd_offset());
+                try!(self.read_offset());
                 return Ok(None)
 
             }
         };
+        let count = if self.bigtiff {
+            try!(self.read_long8())
+        } else {
+            try!(self.read_long()) as u64
+        };
         Ok(Some((tag, ifd::Entry::new(
             type_,
-            try!(self.read_long()), // count
+            count,
             try!(self.read_offset())  // offset
         ))))
     }
@@ -302,19 +495,26 @@ impl<R: Read + Seek> TIFFDecoder<R> {
     fn read_ifd(&mut self) -> ImageResult<Directory> {
         let mut dir: Directory = HashMap::new();
         match self.next_ifd {
-            None => return Err(image::ImageError::FormatError(
+            None => return Err(image::ImageError::malformed(ImageFormat::TIFF,
                 "Image file directory not found.".to_string())
             ),
-            Some(offset) => try!(self.goto_offset(offset))
+            Some(offset) => {
+                if !self.read_ifds.insert(offset) {
+                    return Err(image::ImageError::malformed(ImageFormat::TIFF,
+                        "Image file directories form a cycle.".to_string()
+                    ))
+                }
+                try!(self.goto_offset(offset))
+            }
         }
-        for _ in 0..try!(self.read_short()) {
+        for _ in 0..try!(self.read_entry_count()) {
             let (tag, entry) = match try!(self.read_entry()) {
                 Some(val) => val,
                 None => continue // Unknown data type in tag, skip
             };
             dir.insert(tag, entry);
         }
-        self.next_ifd = match try!(self.read_long()) {
+        self.next_ifd = match try!(self.read_ifd_offset()) {
             0 => None,
             n => Some(n)
         };
@@ -353,12 +553,29 @@ impl<R: Read + Seek> TIFFDecoder<R> {
         }
     }
 
+    /// Returns the resolution in pixels per inch, if the image gives it in inches or centimeters
+    fn resolution(&mut self) -> ImageResult<Option<(f32, f32)>> {
+        // The unit defaults to inches
+        let unit = try!(self.find_tag_u32(ifd::Tag::ResolutionUnit)).unwrap_or(2);
+        let x = try!(self.find_tag(ifd::Tag::XResolution));
+        let y = try!(self.find_tag(ifd::Tag::YResolution));
+        let (x, y) = match (x, y) {
+            (Some(x), Some(y)) => (try!(x.as_f64()) as f32, try!(y.as_f64()) as f32),
+            _ => return Ok(None),
+        };
+        Ok(match unit {
+            2 => Some((x, y)),
+            3 => Some((x * 2.54, y * 2.54)),
+            _ => None,
+        })
+    }
+
     /// Tries to retrieve a tag.
     /// Returns an error if the tag is not present
     fn get_tag(&mut self, tag: ifd::Tag) -> ImageResult<ifd::Value> {
         match try!(self.find_tag(tag)) {
             Some(val) => Ok(val),
-            None => Err(::image::ImageError::FormatError(format!(
+            None => Err(::image::ImageError::malformed(::image::ImageFormat::TIFF, format!(
                 "Required tag `{:?}` not found.", tag
             )))
         }
@@ -374,9 +591,185 @@ impl<R: Read + Seek> TIFFDecoder<R> {
         (try!(self.get_tag(tag))).as_u32_vec()
     }
 
+    /// Tries to retrieve a tag and convert it to the desired type.
+    fn get_tag_u64_vec(&mut self, tag: ifd::Tag) -> ImageResult<Vec<u64>> {
+        (try!(self.get_tag(tag))).as_u64_vec()
+    }
+
+    /// Returns the size of the strips or tiles the image is stored in, with their offsets and
+    /// byte counts in row-major order.
+    fn chunk_layout(&mut self) -> ImageResult<(u32, u32, Vec<u64>, Vec<u32>)> {
+        if let Some(tile_width) = try!(self.find_tag_u32(ifd::Tag::TileWidth)) {
+            let tile_length = try!(self.get_tag_u32(ifd::Tag::TileLength));
+            if tile_width == 0 || tile_length == 0 {
+                return Err(ImageError::malformed(ImageFormat::TIFF, "Tiles have zero size.".to_string()))
+            }
+            // Tiles are multiples of 16 covering the image, writers commonly use a fixed size
+            // such as 256 even for smaller images
+            let max_tile = |size: u32| (size.max(256) as u64 + 15) / 16 * 16;
+            if tile_width as u64 > max_tile(self.width) || tile_length as u64 > max_tile(self.height) {
+                return Err(ImageError::malformed(ImageFormat::TIFF,
+                    "Tiles are larger than the image.".to_string()
+                ))
+            }
+            Ok((tile_width, tile_length,
+                try!(self.get_tag_u64_vec(ifd::Tag::TileOffsets)),
+                try!(self.get_tag_u32_vec(ifd::Tag::TileByteCounts))))
+        } else {
+            // Strips span the whole width, their default length is the whole image
+            let rows_per_strip = match try!(self.find_tag_u32(ifd::Tag::RowsPerStrip)) {
+                Some(rows) => rows.min(self.height).max(1),
+                None => self.height.max(1)
+            };
+            Ok((self.width, rows_per_strip,
+                try!(self.get_tag_u64_vec(ifd::Tag::StripOffsets)),
+                try!(self.get_tag_u32_vec(ifd::Tag::StripByteCounts))))
+        }
+    }
+
+    /// Decodes the rectangle of the image with its top left corner at ```x```, ```y``` and the
+    /// dimensions ```width``` and ```height```.
+    ///
+    /// Only the strips or tiles intersecting the rectangle are read, which makes it possible to
+    /// view parts of images too large to be decoded as a whole. The samples are laid out as
+    /// those of `read_image`.
+    pub fn read_region(&mut self, x: u32, y: u32, width: u32, height: u32) -> ImageResult<DecodingResult> {
+        self.decode_region(x, y, width, height, &mut Monitor::new())
+    }
+
+    /// Decodes a region like `read_region`, reporting the rows of the region decoded to
+    /// ```monitor``` after each row of strips or tiles
+    fn decode_region(&mut self, x: u32, y: u32, width: u32, height: u32, monitor: &mut Monitor)
+                     -> ImageResult<DecodingResult> {
+        if x as u64 + width as u64 > self.width as u64 || y as u64 + height as u64 > self.height as u64 {
+            return Err(ImageError::Parameter(ParameterErrorKind::DimensionMismatch))
+        }
+        if let Ok(config) = self.get_tag_u32(ifd::Tag::PlanarConfiguration) {
+            match FromPrimitive::from_u32(config) {
+                Some(PlanarConfiguration::Chunky) => {},
+                config => return Err(ImageError::unsupported(ImageFormat::TIFF,
+                    format!("Unsupported planar configuration “{:?}”.", config)
+                ))
+            }
+        }
+        let predictor = match try!(self.find_tag_u32(ifd::Tag::Predictor)) {
+            None => Predictor::None,
+            Some(predictor) => match FromPrimitive::from_u32(predictor) {
+                Some(predictor) => predictor,
+                None => return Err(ImageError::malformed(ImageFormat::TIFF,
+                    format!("Unknown predictor “{}” encountered", predictor)
+                ))
+            }
+        };
+        let color_type = try!(self.colortype());
+
+        let samples = self.bits_per_sample.len();
+        let bits = self.bits_per_sample.iter().cloned().max().unwrap_or(8) as usize;
+        // Samples of less than 8 bits are packed into bytes, rows start at a byte boundary.
+        // Otherwise everything is counted in samples.
+        let float = match (self.sample_format, bits) {
+            (SampleFormat::Uint, _) | (SampleFormat::Void, _) => false,
+            (SampleFormat::IEEEFP, 32) => true,
+            (format, bits) => return Err(ImageError::unsupported(ImageFormat::TIFF,
+                format!("{} bit samples of format {:?} are unsupported", bits, format)
+            ))
+        };
+        let (pixel_bits, unit_bits) = match bits {
+            n if n <= 8 => (n * samples, 8),
+            n if n <= 16 => (16 * samples, 16),
+            32 => (32 * samples, 32),
+            n => return Err(ImageError::unsupported(ImageFormat::TIFF,
+                format!("{} bits per channel not supported", n)
+            ))
+        };
+        let row_units = |width: u32| width as usize * pixel_bits / unit_bits
+            + (width as usize * pixel_bits % unit_bits != 0) as usize;
+        // The buffers are checked against the limits before they are allocated
+        let new_buffer = |width: u32, height: u32, limits: &Limits| -> ImageResult<DecodingResult> {
+            try!(limits.check_image(width, height, color_type));
+            let len = match row_units(width).checked_mul(height as usize) {
+                Some(len) => len,
+                None => return Err(ImageError::Limits(LimitErrorKind::Memory))
+            };
+            Ok(match unit_bits {
+                8 => DecodingResult::U8(vec![0; len]),
+                16 => DecodingResult::U16(vec![0; len]),
+                _ if float => DecodingResult::F32(vec![0.0; len]),
+                _ => DecodingResult::U32(vec![0; len]),
+            })
+        };
+
+        let mut result = try!(new_buffer(width, height, &self.limits));
+        try!(monitor.update(0, height as u64));
+        if width == 0 || height == 0 {
+            return Ok(result)
+        }
+
+        let (chunk_width, chunk_height, offsets, byte_counts) = try!(self.chunk_layout());
+        // Only the size of a chunk is limited, it may be wider or taller than the image
+        let chunk_limits = Limits { max_width: None, max_height: None, ..self.limits };
+        let chunks_across = (self.width as u64 + chunk_width as u64 - 1) / chunk_width as u64;
+        let chunk_row_units = row_units(chunk_width);
+        for chunk_y in y / chunk_height..(y + height - 1) / chunk_height + 1 {
+            for chunk_x in x / chunk_width..(x + width - 1) / chunk_width + 1 {
+                // Indices past the offsets are those of missing chunks
+                let index = chunk_y as u64 * chunks_across + chunk_x as u64;
+                let index = index.min(offsets.len() as u64) as usize;
+                let (offset, byte_count) = match (offsets.get(index), byte_counts.get(index)) {
+                    (Some(&offset), Some(&byte_count)) => (offset, byte_count),
+                    _ => return Err(ImageError::malformed(ImageFormat::TIFF,
+                        "Image data is missing strips or tiles.".to_string()
+                    ))
+                };
+
+                let mut chunk = try!(new_buffer(chunk_width, chunk_height, &chunk_limits));
+                let units = match chunk {
+                    DecodingResult::U8(ref mut buffer) =>
+                        try!(self.expand_strip(DecodingBuffer::U8(buffer), offset, byte_count)),
+                    DecodingResult::U16(ref mut buffer) =>
+                        try!(self.expand_strip(DecodingBuffer::U16(buffer), offset, byte_count)),
+                    DecodingResult::U32(ref mut buffer) =>
+                        try!(self.expand_strip(DecodingBuffer::U32(buffer), offset, byte_count)),
+                    DecodingResult::F32(ref mut buffer) =>
+                        try!(self.expand_strip(DecodingBuffer::F32(buffer), offset, byte_count)),
+                };
+                // The last strip may be shorter
+                let rows = (units / chunk_row_units) as u32;
+                if let Predictor::Horizontal = predictor {
+                    chunk = try!(rev_hpredict(chunk, (chunk_width, rows), color_type));
+                }
+
+                // The part of the region covered by the chunk
+                let (left, top) = (chunk_x * chunk_width, chunk_y * chunk_height);
+                let columns = x.max(left)..(x + width).min(left + chunk_width);
+                for row in y.max(top)..(y + height).min(top + rows) {
+                    let src = (row - top) as usize * chunk_row_units * unit_bits
+                        + (columns.start - left) as usize * pixel_bits;
+                    let dst = (row - y) as usize * row_units(width) * unit_bits
+                        + (columns.start - x) as usize * pixel_bits;
+                    let len = (columns.end - columns.start) as usize * pixel_bits;
+                    match (&chunk, &mut result) {
+                        (&DecodingResult::U8(ref chunk), &mut DecodingResult::U8(ref mut result)) =>
+                            copy_bits(chunk, src, result, dst, len),
+                        (&DecodingResult::U16(ref chunk), &mut DecodingResult::U16(ref mut result)) =>
+                            result[dst / 16..(dst + len) / 16].copy_from_slice(&chunk[src / 16..(src + len) / 16]),
+                        (&DecodingResult::U32(ref chunk), &mut DecodingResult::U32(ref mut result)) =>
+                            result[dst / 32..(dst + len) / 32].copy_from_slice(&chunk[src / 32..(src + len) / 32]),
+                        (&DecodingResult::F32(ref chunk), &mut DecodingResult::F32(ref mut result)) =>
+                            result[dst / 32..(dst + len) / 32].copy_from_slice(&chunk[src / 32..(src + len) / 32]),
+                        _ => unreachable!()
+                    }
+                }
+            }
+            let done = (chunk_y as u64 + 1) * chunk_height as u64;
+            try!(monitor.update(done.min(y as u64 + height as u64) - y as u64, height as u64));
+        }
+        Ok(result)
+    }
+
     /// Decompresses the strip into the supplied buffer.
     /// Returns the number of bytes read.
-    fn expand_strip<'a>(&mut self, buffer: DecodingBuffer<'a>, offset: u32, length: u32) -> ImageResult<usize> {
+    fn expand_strip<'a>(&mut self, buffer: DecodingBuffer<'a>, offset: u64, length: u32) -> ImageResult<usize> {
         let color_type = try!(self.colortype());
         try!(self.goto_offset(offset));
         let (bytes, mut reader): (usize, Box<EndianReader>) = match self.compression_method {
@@ -385,7 +778,11 @@ impl<R: Read + Seek> TIFFDecoder<R> {
                 (length as usize, Box::new(SmartReader::wrap(&mut self.reader, order)))
             },
             CompressionMethod::LZW => {
-                let (bytes, reader) = try!(LZWReader::new(&mut self.reader));
+                let (bytes, reader) = try!(LZWReader::new(&mut self.reader, length as usize));
+                (bytes, Box::new(reader))
+            },
+            CompressionMethod::Deflate | CompressionMethod::OldDeflate => {
+                let (bytes, reader) = try!(DeflateReader::new(&mut self.reader, length as usize));
                 (bytes, Box::new(reader))
             },
             CompressionMethod::PackBits => {
@@ -393,14 +790,22 @@ impl<R: Read + Seek> TIFFDecoder<R> {
                 let (bytes, reader) = try!(PackBitsReader::new(&mut self.reader, order, length as usize));
                 (bytes, Box::new(reader))
             },
-            method => return Err(::image::ImageError::UnsupportedError(format!(
+            method => return Err(::image::ImageError::unsupported(::image::ImageFormat::TIFF, format!(
                 "Compression method {:?} is unsupported", method
             )))
         };
+        // Corrupt strips may hold more data than fits into the buffer
+        let bytes = bytes.min(match buffer {
+            DecodingBuffer::U8(ref buffer) => buffer.len(),
+            DecodingBuffer::U16(ref buffer) => buffer.len() * 2,
+            DecodingBuffer::U32(ref buffer) => buffer.len() * 4,
+            DecodingBuffer::F32(ref buffer) => buffer.len() * 4,
+        });
         Ok(match (color_type, buffer) {
             (ColorType:: RGB(8), DecodingBuffer::U8(ref mut buffer)) |
             (ColorType::RGBA(8), DecodingBuffer::U8(ref mut buffer)) => {
-                try!(reader.read(&mut buffer[..bytes]))
+                try!(reader.read_exact(&mut buffer[..bytes]));
+                bytes
             }
             (ColorType::RGBA(16), DecodingBuffer::U16(ref mut buffer)) |
             (ColorType:: RGB(16), DecodingBuffer::U16(ref mut buffer)) => {
@@ -418,6 +823,30 @@ impl<R: Read + Seek> TIFFDecoder<R> {
                 }
                 bytes/2
             }
+            (ColorType::RGBA(32), DecodingBuffer::U32(ref mut buffer)) |
+            (ColorType:: RGB(32), DecodingBuffer::U32(ref mut buffer)) => {
+                for datum in buffer[..bytes/4].iter_mut() {
+                    *datum = try!(reader.read_u32())
+                }
+                bytes/4
+            }
+            (ColorType::Gray(32), DecodingBuffer::U32(ref mut buffer)) => {
+                for datum in buffer[..bytes/4].iter_mut() {
+                    *datum = try!(reader.read_u32());
+                    if self.photometric_interpretation == PhotometricInterpretation::WhiteIsZero {
+                        *datum = 0xffff_ffff - *datum
+                    }
+                }
+                bytes/4
+            }
+            (ColorType::RGBAF(32), DecodingBuffer::F32(ref mut buffer)) |
+            (ColorType:: RGBF(32), DecodingBuffer::F32(ref mut buffer)) |
+            (ColorType::GrayF(32), DecodingBuffer::F32(ref mut buffer)) => {
+                for datum in buffer[..bytes/4].iter_mut() {
+                    *datum = f32::from_bits(try!(reader.read_u32()))
+                }
+                bytes/4
+            }
             (ColorType::Gray(n), DecodingBuffer::U8(ref mut buffer)) if n <= 8 => {
                 try!(reader.read_exact(&mut buffer[..bytes]));
                 if self.photometric_interpretation == PhotometricInterpretation::WhiteIsZero {
@@ -427,7 +856,7 @@ impl<R: Read + Seek> TIFFDecoder<R> {
                 }
                 bytes
             }
-            (type_, _) => return Err(::image::ImageError::UnsupportedError(format!(
+            (type_, _) => return Err(::image::ImageError::unsupported(::image::ImageFormat::TIFF, format!(
                 "Color type {:?} is unsupported", type_
             )))
         })
@@ -440,20 +869,36 @@ impl<R: Read + Seek> ImageDecoder for TIFFDecoder<R> {
 
     }
 
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.dpi = try!(self.resolution());
+        metadata.orientation = try!(self.find_tag_u32(ifd::Tag::Orientation))
+            .and_then(|o| if o >= 1 && o <= 8 { Some(o as u16) } else { None });
+        Ok(metadata)
+    }
+
     fn colortype(&mut self) -> ImageResult<ColorType> {
-        match self.photometric_interpretation {
+        let color = match self.photometric_interpretation {
             // TODO: catch also [ 8, 8, 8, _] this does not work due to a bug in rust atm
-            PhotometricInterpretation::RGB if self.bits_per_sample == [8, 8, 8, 8] => Ok(ColorType::RGBA(8)),
-            PhotometricInterpretation::RGB if self.bits_per_sample == [8, 8, 8] => Ok(ColorType::RGB(8)),
-            PhotometricInterpretation::RGB if self.bits_per_sample == [16, 16, 16, 16] => Ok(ColorType::RGBA(16)),
-            PhotometricInterpretation::RGB if self.bits_per_sample == [16, 16, 16] => Ok(ColorType::RGB(16)),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [8, 8, 8, 8] => ColorType::RGBA(8),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [8, 8, 8] => ColorType::RGB(8),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [16, 16, 16, 16] => ColorType::RGBA(16),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [16, 16, 16] => ColorType::RGB(16),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [32, 32, 32, 32] => ColorType::RGBA(32),
+            PhotometricInterpretation::RGB if self.bits_per_sample == [32, 32, 32] => ColorType::RGB(32),
             PhotometricInterpretation::BlackIsZero | PhotometricInterpretation::WhiteIsZero
-                                           if self.bits_per_sample.len() == 1 => Ok(ColorType::Gray(self.bits_per_sample[0])),
+                                           if self.bits_per_sample.len() == 1 => ColorType::Gray(self.bits_per_sample[0]),
 
-            _ => Err(::image::ImageError::UnsupportedError(format!(
+            _ => return Err(::image::ImageError::unsupported(::image::ImageFormat::TIFF, format!(
                 "{:?} with {:?} bits per sample is unsupported", self.bits_per_sample, self.photometric_interpretation
             ))) // TODO: this is bad we should not fail at this point}
-        }
+        };
+        Ok(match (self.sample_format, color) {
+            (SampleFormat::IEEEFP, ColorType::Gray(n)) => ColorType::GrayF(n),
+            (SampleFormat::IEEEFP, ColorType::RGB(n)) => ColorType::RGBF(n),
+            (SampleFormat::IEEEFP, ColorType::RGBA(n)) => ColorType::RGBAF(n),
+            _ => color,
+        })
     }
 
     fn row_len(&mut self) -> ImageResult<usize> {
@@ -465,79 +910,433 @@ impl<R: Read + Seek> ImageDecoder for TIFFDecoder<R> {
     }
 
     fn read_image(&mut self) -> ImageResult<DecodingResult> {
-        let buffer_size =
-            self.width  as usize
-            * self.height as usize
-            * self.bits_per_sample.iter().count();
-        let mut result = match self.bits_per_sample.iter().cloned().max().unwrap_or(8) {
-            n if n <= 8 => DecodingResult::U8(Vec::with_capacity(buffer_size)),
-            n if n <= 16 => DecodingResult::U16(Vec::with_capacity(buffer_size)),
-            n => return Err(
-                ImageError::UnsupportedError(
-                    format!("{} bits per channel not supported", n)
-                )
-            )
+        let (width, height) = (self.width, self.height);
+        self.read_region(0, 0, width, height)
+    }
+
+    /// Decodes the entire image, reporting the rows decoded after each row of strips or tiles
+    fn read_image_with_progress(&mut self, monitor: &mut Monitor) -> ImageResult<DecodingResult> {
+        let (width, height) = (self.width, self.height);
+        self.decode_region(0, 0, width, height, monitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Cursor;
+    use byteorder::{WriteBytesExt, LittleEndian};
+
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError, LimitErrorKind, Limits};
+    use monitor::Monitor;
+    use super::TIFFDecoder;
+
+    /// Builds a little endian TIFF or BigTIFF of 8-bit grayscale images, each given by its size,
+    /// compression method and the data of its only strip
+    fn build_tiff(pages: &[(u32, u32, u16, Vec<u8>)], bigtiff: bool) -> Vec<u8> {
+        let mut data = if bigtiff {
+            vec![b'I', b'I', 43, 0, 8, 0, 0, 0, 16, 0, 0, 0, 0, 0, 0, 0]
+        } else {
+            vec![b'I', b'I', 42, 0, 8, 0, 0, 0]
         };
-        if let Ok(config) = self.get_tag_u32(ifd::Tag::PlanarConfiguration) {
-            match FromPrimitive::from_u32(config) {
-                Some(PlanarConfiguration::Chunky) => {},
-                config => return Err(ImageError::UnsupportedError(
-                    format!("Unsupported planar configuration “{:?}”.", config)
-                ))
+        // The lengths of the entry count and next offset, of each entry and of offsets
+        let (header_len, entry_len, offset_len) = if bigtiff { (16, 20, 8) } else { (6, 12, 4) };
+        for (n, &(width, height, compression, ref strip)) in pages.iter().enumerate() {
+            let entries: [(u16, u16, u32); 8] = [
+                (256, 4, width),
+                (257, 4, height),
+                (258, 3, 8),
+                (259, 3, compression as u32),
+                (262, 3, 1),
+                (273, if bigtiff { 16 } else { 4 }, 0),
+                (278, 4, height),
+                (279, 4, strip.len() as u32),
+            ];
+            let ifd_size = header_len + entries.len() * entry_len;
+            let offset = (data.len() + ifd_size) as u32;
+            let next = if n + 1 < pages.len() { offset + strip.len() as u32 } else { 0 };
+
+            let write_offset = |data: &mut Vec<u8>, value: u32| if bigtiff {
+                data.write_u64::<LittleEndian>(value as u64).unwrap();
+            } else {
+                data.write_u32::<LittleEndian>(value).unwrap();
+            };
+            if bigtiff {
+                data.write_u64::<LittleEndian>(entries.len() as u64).unwrap();
+            } else {
+                data.write_u16::<LittleEndian>(entries.len() as u16).unwrap();
             }
+            for &(tag, type_, value) in &entries {
+                data.write_u16::<LittleEndian>(tag).unwrap();
+                data.write_u16::<LittleEndian>(type_).unwrap();
+                write_offset(&mut data, 1);
+                let value = if tag == 273 { offset } else { value };
+                let mut field = Vec::new();
+                match type_ {
+                    3 => field.write_u16::<LittleEndian>(value as u16).unwrap(),
+                    4 => field.write_u32::<LittleEndian>(value).unwrap(),
+                    _ => field.write_u64::<LittleEndian>(value as u64).unwrap(),
+                }
+                field.resize(offset_len, 0);
+                data.extend(field);
+            }
+            write_offset(&mut data, next);
+            data.extend_from_slice(strip);
         }
-        // Safe since the uninitialized values are never read.
-        match result {
-            DecodingResult::U8(ref mut buffer) =>
-                unsafe { buffer.set_len(buffer_size) },
-            DecodingResult::U16(ref mut buffer) =>
-                unsafe { buffer.set_len(buffer_size) },
-        }
-        let mut units_read = 0;
-        for (&offset, &byte_count) in try!(self.get_tag_u32_vec(ifd::Tag::StripOffsets))
-        .iter().zip(try!(self.get_tag_u32_vec(ifd::Tag::StripByteCounts)).iter()) {
-            units_read += match result {
-                DecodingResult::U8(ref mut buffer) => {
-                    try!(self.expand_strip(
-                        DecodingBuffer::U8(&mut buffer[units_read..]),
-                        offset, byte_count
-                    ))
-                },
-                DecodingResult::U16(ref mut buffer) => {
-                    try!(self.expand_strip(
-                        DecodingBuffer::U16(&mut buffer[units_read..]),
-                        offset, byte_count
-                    ))
-                },
-            };
-            if units_read == buffer_size {
-                break
+        data
+    }
+
+    /// Builds an uncompressed little endian TIFF of an 8-bit gray or rgb image from its
+    /// ```chunks```, which are tiles of the given size if there is one, strips otherwise.
+    /// The entries in ```extra``` are added, replacing those of the same tag.
+    fn build_chunked(width: u32, height: u32, samples: u32, tile: Option<(u32, u32)>, chunks: &[Vec<u8>],
+                     extra: &[(u16, u16, Vec<u32>)]) -> Vec<u8> {
+        let mut data = vec![b'I', b'I', 42, 0, 0, 0, 0, 0];
+        let mut offsets = Vec::new();
+        for chunk in chunks {
+            offsets.push(data.len() as u32);
+            data.extend_from_slice(chunk);
+        }
+        let counts = chunks.iter().map(|c| c.len() as u32).collect();
+
+        let mut entries: Vec<(u16, u16, Vec<u32>)> = vec![
+            (256, 4, vec![width]),
+            (257, 4, vec![height]),
+            (258, 3, vec![8; samples as usize]),
+            (262, 3, vec![if samples == 1 { 1 } else { 2 }]),
+            (277, 3, vec![samples]),
+        ];
+        match tile {
+            Some((tile_width, tile_length)) => {
+                entries.push((322, 4, vec![tile_width]));
+                entries.push((323, 4, vec![tile_length]));
+                entries.push((324, 4, offsets));
+                entries.push((325, 4, counts));
+            }
+            None => {
+                entries.push((273, 4, offsets));
+                entries.push((278, 4, vec![(height + chunks.len() as u32 - 1) / chunks.len() as u32]));
+                entries.push((279, 4, counts));
             }
         }
-        // Shrink length such that the uninitialized memory is not exposed.
-        if units_read < buffer_size {
-            match result {
-                DecodingResult::U8(ref mut buffer) =>
-                    unsafe { buffer.set_len(units_read) },
-                DecodingResult::U16(ref mut buffer) =>
-                    unsafe { buffer.set_len(units_read) },
+        entries.retain(|e| extra.iter().all(|x| x.0 != e.0));
+        entries.extend(extra.iter().cloned());
+        entries.sort_by_key(|e| e.0);
+
+        // Values that do not fit into their entry follow the directory
+        let ifd = data.len() as u32;
+        let mut values = Vec::new();
+        let mut values_offset = ifd + 2 + 12 * entries.len() as u32 + 4;
+        (&mut data[4..8]).write_u32::<LittleEndian>(ifd).unwrap();
+        data.write_u16::<LittleEndian>(entries.len() as u16).unwrap();
+        for &(tag, type_, ref value) in &entries {
+            data.write_u16::<LittleEndian>(tag).unwrap();
+            data.write_u16::<LittleEndian>(type_).unwrap();
+            data.write_u32::<LittleEndian>(value.len() as u32).unwrap();
+            let mut field = Vec::new();
+            for &v in value {
+                if type_ == 3 {
+                    field.write_u16::<LittleEndian>(v as u16).unwrap();
+                } else {
+                    field.write_u32::<LittleEndian>(v).unwrap();
+                }
+            }
+            if field.len() <= 4 {
+                field.resize(4, 0);
+                data.extend(field);
+            } else {
+                data.write_u32::<LittleEndian>(values_offset).unwrap();
+                values_offset += field.len() as u32;
+                values.extend(field);
             }
         }
-        if let Ok(predictor) = self.get_tag_u32(ifd::Tag::Predictor) {
-            result = match FromPrimitive::from_u32(predictor) {
-                Some(Predictor::None) => result,
-                Some(Predictor::Horizontal) => {
-                    try!(rev_hpredict(
-                        result,
-                        try!(self.dimensions()),
-                        try!(self.colortype())
-                    ))
-                },
-                None => return Err(ImageError::FormatError(
-                    format!("Unknown predictor “{}” encountered", predictor)
-                ))
+        data.write_u32::<LittleEndian>(0).unwrap();
+        data.extend(values);
+        data
+    }
+
+    fn build(pages: &[(u32, u32, u16, Vec<u8>)]) -> Vec<u8> {
+        build_tiff(pages, false)
+    }
+
+    /// Builds an uncompressed TIFF holding an image of each size, where every pixel of the
+    /// n-th image has the value n
+    fn multi_page(sizes: &[(u32, u32)]) -> Vec<u8> {
+        let pages: Vec<_> = sizes.iter().enumerate().map(|(n, &(width, height))| {
+            (width, height, 1, vec![n as u8; (width * height) as usize])
+        }).collect();
+        build(&pages)
+    }
+
+    fn decode_gray(data: Vec<u8>) -> Vec<u8> {
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn pages() {
+        let sizes = [(3, 2), (5, 4), (1, 7)];
+        let mut decoder = TIFFDecoder::new(Cursor::new(multi_page(&sizes))).unwrap();
+        assert_eq!(decoder.page_count().unwrap(), 3);
+
+        for (n, &size) in sizes.iter().enumerate() {
+            if n > 0 {
+                assert!(decoder.more_images());
+                decoder = decoder.next_image().unwrap();
+            }
+            assert_eq!(decoder.dimensions().unwrap(), size);
+            assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+            match decoder.read_image().unwrap() {
+                DecodingResult::U8(data) => assert_eq!(data, vec![n as u8; (size.0 * size.1) as usize]),
+                _ => panic!("Image did not decode as 8-bit"),
             }
         }
-        Ok(result)
+        assert!(!decoder.more_images());
+        assert!(decoder.next_image().is_err());
+    }
+
+    #[test]
+    fn cyclic_pages() {
+        let mut data = multi_page(&[(2, 2)]);
+        // Link the last directory back to the first one
+        let next = data.len() - 4 - 4;
+        data[next..next + 4].copy_from_slice(&[8, 0, 0, 0]);
+
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert!(decoder.page_count().is_err());
+        assert!(decoder.more_images());
+        assert!(decoder.next_image().is_err());
+    }
+
+    #[test]
+    fn compression() {
+        let expected = [0, 0, 0, 0, 255, 255, 255, 255,
+                        0, 0, 0, 0, 255, 255, 255, 255,
+                        128, 128, 128, 128, 128, 128, 128, 128];
+        let lzw = vec![0x80, 0x00, 0x20, 0x40, 0x07, 0xfc, 0x15, 0xff, 0x03,
+                       0x82, 0x41, 0x90, 0x10, 0xb8, 0x64, 0x2e, 0x02];
+        // The same stream without the end of information code
+        let lzw_unterminated = vec![0x80, 0x00, 0x20, 0x40, 0x07, 0xfc, 0x15, 0xff,
+                                    0x03, 0x82, 0x41, 0x90, 0x10, 0xb8, 0x64, 0x2c];
+        let deflate = vec![0x78, 0xda, 0x63, 0x60, 0x60, 0x60, 0xf8, 0x0f, 0x04, 0x0c,
+                           0x50, 0xba, 0x01, 0x0a, 0x00, 0x85, 0xa4, 0x0b, 0xf9];
+        let packbits = vec![0xfd, 0x00, 0xfd, 0xff, 0xfd, 0x00, 0xfd, 0xff, 0xf9, 0x80];
+
+        for &(compression, ref strip) in &[(5, lzw), (5, lzw_unterminated), (8, deflate.clone()),
+                                           (32946, deflate), (32773, packbits)] {
+            let data = decode_gray(build(&[(8, 3, compression, strip.clone())]));
+            assert_eq!(data, &expected[..], "compression {}", compression);
+        }
+    }
+
+    #[test]
+    fn truncated_packbits() {
+        let packbits = vec![0xfd, 0x00, 0xfd, 0xff, 0xfd, 0x00, 0xfd, 0xff, 0xf9, 0x80];
+        let mut data = build(&[(8, 3, 32773, packbits)]);
+        // The strip ends between two runs, two bytes before its byte count
+        let len = data.len();
+        data.truncate(len - 2);
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert!(decoder.read_image().is_err());
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let data = build_chunked(1, 1, 1, None, &[vec![0]], &[(258, 3, vec![0])]);
+        match TIFFDecoder::new(Cursor::new(data)) {
+            Err(ImageError::Decoding { .. }) => (),
+            _ => panic!("Expected zero bits per sample to be rejected"),
+        }
+
+        let data = build_chunked(1, 1, 1, Some((0xFFFF_FFF0, 0xFFFF_FFF0)), &[vec![0]], &[]);
+        match TIFFDecoder::new(Cursor::new(data)).unwrap().read_image() {
+            Err(ImageError::Decoding { .. }) => (),
+            _ => panic!("Expected tiles larger than the image to be rejected"),
+        }
+
+        // Tiles of a fixed size may cover small images, within the limits
+        let data = build_chunked(1, 1, 1, Some((256, 256)), &[vec![7; 256 * 256]], &[]);
+        let mut decoder = TIFFDecoder::new(Cursor::new(data.clone())).unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![7]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        decoder.set_limits(Limits { max_bytes: Some(1000), ..Limits::no_limits() });
+        match decoder.read_image() {
+            Err(ImageError::Limits(LimitErrorKind::Memory)) => (),
+            _ => panic!("Expected the tile to exceed the limits"),
+        }
+    }
+
+    #[test]
+    fn bigtiff() {
+        let pages = [(3, 2, 1, vec![1; 6]), (2, 2, 1, vec![2; 4])];
+        let mut decoder = TIFFDecoder::new(Cursor::new(build_tiff(&pages, true))).unwrap();
+        assert!(decoder.is_bigtiff());
+        assert_eq!(decoder.page_count().unwrap(), 2);
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        decoder = decoder.next_image().unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (2, 2));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![2; 4]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    /// An rgb pixel encoding its position
+    fn pixel(x: u32, y: u32) -> [u8; 3] {
+        [x as u8, y as u8, (x * 7 + y * 13) as u8]
+    }
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        for y in y..y + height {
+            for x in x..x + width {
+                data.extend_from_slice(&pixel(x, y));
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn tiles() {
+        // 3 by 2 tiles of 4x4 pixels, padded at the right and bottom edges
+        let (width, height) = (10, 7);
+        let mut tiles = Vec::new();
+        for tile_y in 0..2 {
+            for tile_x in 0..3 {
+                let mut tile = Vec::new();
+                for y in tile_y * 4..tile_y * 4 + 4 {
+                    for x in tile_x * 4..tile_x * 4 + 4 {
+                        tile.extend_from_slice(&if x < width && y < height { pixel(x, y) } else { [0xee; 3] });
+                    }
+                }
+                tiles.push(tile);
+            }
+        }
+        let data = build_chunked(width, height, 3, Some((4, 4)), &tiles, &[]);
+
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        for &(x, y, w, h) in &[(0, 0, 10, 7), (3, 2, 6, 5), (5, 5, 1, 1), (8, 4, 2, 3), (0, 0, 0, 0)] {
+            match decoder.read_region(x, y, w, h).unwrap() {
+                DecodingResult::U8(decoded) => assert!(decoded == region(x, y, w, h), "region {:?}", (x, y, w, h)),
+                _ => panic!("Image did not decode as 8-bit"),
+            }
+        }
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(decoded) => assert!(decoded == region(0, 0, width, height)),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+        assert!(decoder.read_region(8, 0, 3, 1).is_err());
+
+        // The rows are reported after each row of tiles
+        let mut updates = Vec::new();
+        {
+            let mut monitor = Monitor::new().with_callback(|done, total| updates.push((done, total)));
+            assert!(decoder.read_image_with_progress(&mut monitor).is_ok());
+        }
+        assert_eq!(updates, vec![(0, 7), (4, 7), (7, 7)]);
+    }
+
+    #[test]
+    fn strip_regions() {
+        // Strips of 3 rows, the last one is shorter
+        let strips: Vec<_> = (0..3).map(|n| region(0, n * 3, 5, 3.min(8 - n * 3))).collect();
+        let data = build_chunked(5, 8, 3, None, &strips, &[]);
+
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        for &(x, y, w, h) in &[(0, 0, 5, 8), (1, 2, 3, 5), (4, 7, 1, 1)] {
+            match decoder.read_region(x, y, w, h).unwrap() {
+                DecodingResult::U8(decoded) => assert!(decoded == region(x, y, w, h), "region {:?}", (x, y, w, h)),
+                _ => panic!("Image did not decode as 8-bit"),
+            }
+        }
+    }
+
+    #[test]
+    fn packed_regions() {
+        // A bilevel image of 11x2 pixels, rows are padded to whole bytes
+        let data = build_chunked(11, 2, 1, None, &[vec![0b1011_0010, 0b1110_0000, 0b0101_1100, 0b0100_0000]],
+                                 &[(258, 3, vec![1])]);
+
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(1));
+        match decoder.read_region(3, 0, 6, 2).unwrap() {
+            DecodingResult::U8(decoded) => assert_eq!(decoded, vec![0b1001_0100, 0b1110_0000]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn wide_samples() {
+        let floats = [0.5f32, -1.25, 1e10, 3.0, 0.0, -0.0];
+        let mut strip = Vec::new();
+        for &f in &floats {
+            strip.write_u32::<LittleEndian>(f.to_bits()).unwrap();
+        }
+        let data = build_chunked(3, 2, 1, None, &[strip], &[(258, 3, vec![32]), (339, 3, vec![3])]);
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::GrayF(32));
+        match decoder.read_region(1, 0, 2, 2).unwrap() {
+            DecodingResult::F32(decoded) => assert_eq!(decoded, vec![-1.25, 1e10, 0.0, -0.0]),
+            _ => panic!("Image did not decode as floating point"),
+        }
+
+        // Integer samples with the horizontal predictor
+        let mut strip = Vec::new();
+        for &v in &[7u32, 0, 0xffff_ffff, 1, 0x8000_0000, 2] {
+            strip.write_u32::<LittleEndian>(v).unwrap();
+        }
+        let data = build_chunked(2, 1, 3, None, &[strip], &[(258, 3, vec![32; 3]), (317, 3, vec![2])]);
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(32));
+        match decoder.read_image().unwrap() {
+            DecodingResult::U32(decoded) => assert_eq!(decoded, vec![7, 0, 0xffff_ffff, 8, 0x8000_0000, 1]),
+            _ => panic!("Image did not decode as 32-bit"),
+        }
+
+        // Signed samples are not supported
+        let data = build_chunked(1, 1, 1, None, &[vec![0; 4]], &[(258, 3, vec![32]), (339, 3, vec![2])]);
+        assert!(TIFFDecoder::new(Cursor::new(data)).unwrap().read_image().is_err());
+    }
+
+    #[test]
+    fn metadata() {
+        let file = File::open("tests/images/tiff/testsuite/hpredict.tiff").unwrap();
+        let metadata = TIFFDecoder::new(file).unwrap().metadata().unwrap();
+        assert_eq!((metadata.dimensions, metadata.dpi), ((32, 32), Some((72.0, 72.0))));
+        assert_eq!((metadata.orientation, metadata.frame_count), (Some(1), Some(1)));
+        let (width, height) = metadata.physical_size().unwrap();
+        assert!((width - 32.0 / 72.0).abs() < 1e-6 && width == height);
+
+        // Images without resolution tags have no DPI
+        let data = build_tiff(&[(2, 1, 1, vec![0, 0])], false);
+        assert_eq!(TIFFDecoder::new(Cursor::new(data)).unwrap().metadata().unwrap().dpi, None);
+    }
+
+    #[test]
+    fn exif() {
+        let data = build_chunked(2, 1, 1, None, &[vec![1, 2]], &[(274, 3, vec![6])]);
+        let mut decoder = TIFFDecoder::new(Cursor::new(data)).unwrap();
+        let exif = decoder.exif().unwrap();
+        assert_eq!(exif.orientation(), Some(6));
+        assert_eq!(exif.fields().len(), 1);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(data, vec![1, 2]),
+            _ => panic!("Expected 8-bit samples"),
+        }
+
+        let bigtiff = build_tiff(&[(1, 1, 1, vec![0])], true);
+        match TIFFDecoder::new(Cursor::new(bigtiff)).unwrap().exif() {
+            Err(ImageError::Unsupported { .. }) => (),
+            r => panic!("Expected the EXIF data of BigTIFF files to be unsupported, got {:?}", r),
+        }
     }
 }
@@ -1,5 +1,7 @@
 //! All IO functionality needed for TIFF decoding
 
+extern crate inflate;
+
 use std::io;
 use std::io::{Read, Seek};
 use byteorder::{ReadBytesExt, BigEndian, LittleEndian};
@@ -37,6 +39,15 @@ pub trait EndianReader: Read {
             ByteOrder::BigEndian => <Self as ReadBytesExt>::read_u32::<BigEndian>(self)
         }
     }
+
+    /// Reads an u64
+    #[inline(always)]
+    fn read_u64(&mut self) -> Result<u64, io::Error> {
+        match self.byte_order() {
+            ByteOrder::LittleEndian => <Self as ReadBytesExt>::read_u64::<LittleEndian>(self),
+            ByteOrder::BigEndian => <Self as ReadBytesExt>::read_u64::<BigEndian>(self)
+        }
+    }
 }
 
 /// Reader that decompresses LZW streams
@@ -47,10 +58,15 @@ pub struct LZWReader {
 
 impl LZWReader {
     /// Wraps a reader
-    pub fn new<R>(reader: &mut SmartReader<R>) -> io::Result<(usize, LZWReader)> where R: Read + Seek {
+    pub fn new<R>(reader: &mut SmartReader<R>, length: usize) -> io::Result<(usize, LZWReader)> where R: Read + Seek {
         let mut buffer = Vec::new();
         let order = reader.byte_order;
-        try!(lzw::decode_early_change(bitstream::MsbReader::new(reader), &mut buffer, 8));
+        let compressed = bitstream::MsbReader::new(reader.take(length as u64));
+        match lzw::decode_early_change(compressed, &mut buffer, 8) {
+            // Some writers omit the end of information code at the end of a strip
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => (),
+            result => try!(result)
+        }
         let bytes = buffer.len();
         Ok((bytes, LZWReader {
             buffer: io::Cursor::new(buffer),
@@ -73,6 +89,42 @@ impl EndianReader for LZWReader {
     }
 }
 
+/// Reader that decompresses zlib streams
+pub struct DeflateReader {
+    buffer: io::Cursor<Vec<u8>>,
+    byte_order: ByteOrder
+}
+
+impl DeflateReader {
+    /// Wraps a reader
+    pub fn new<R: Read + Seek>(reader: &mut SmartReader<R>, length: usize) -> io::Result<(usize, DeflateReader)> {
+        let mut compressed = Vec::new();
+        let order = reader.byte_order;
+        try!(reader.take(length as u64).read_to_end(&mut compressed));
+        let buffer = try!(inflate::inflate_bytes_zlib(&compressed).map_err(|err| {
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        }));
+        Ok((buffer.len(), DeflateReader {
+            buffer: io::Cursor::new(buffer),
+            byte_order: order
+        }))
+    }
+}
+
+impl Read for DeflateReader {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.buffer.read(buf)
+    }
+}
+
+impl EndianReader for DeflateReader {
+    #[inline(always)]
+    fn byte_order(&self) -> ByteOrder {
+        self.byte_order
+    }
+}
+
 /// Reader that unpacks Apple's PackBits format
 pub struct PackBitsReader {
     buffer: io::Cursor<Vec<u8>>,
@@ -100,7 +152,8 @@ fn read_packbits_run<R: Read + Seek>(reader: &mut R, buffer: &mut Vec<u8>) -> io
     let bytes = try!(reader.read(&mut header));
 
     match bytes {
-        0 => Ok(0),
+        // The strip ends before the length it was given
+        0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated PackBits strip")),
         _ => match header[0] as i8 {
             -128 => Ok(1),
             h if h >= -127 && h <= -1 => {
@@ -0,0 +1,337 @@
+extern crate deflate;
+
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, BigEndian};
+
+use color::ColorType;
+use image::{ImageEncoder, ImageResult};
+use utils::{lzw, bitstream};
+
+/// The compression of the strips of an encoded TIFF image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TIFFCompression {
+    /// The samples are stored as they are
+    None,
+    /// Lempel-Ziv-Welch compression, as written by most TIFF writers
+    LZW,
+    /// Deflate compression in a zlib stream, as in PNG images
+    Deflate,
+}
+
+impl TIFFCompression {
+    /// The value of the compression tag
+    fn tag_value(&self) -> u16 {
+        match *self {
+            TIFFCompression::None => 1,
+            TIFFCompression::LZW => 5,
+            TIFFCompression::Deflate => 8,
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(match *self {
+            TIFFCompression::None => data.to_vec(),
+            TIFFCompression::LZW => {
+                let mut compressed = Vec::new();
+                try!(lzw::encode_early_change(data, bitstream::MsbWriter::new(&mut compressed), 8));
+                compressed
+            }
+            TIFFCompression::Deflate => deflate::deflate_bytes_zlib(data),
+        })
+    }
+}
+
+/// The number of uncompressed bytes aimed for in each strip
+const STRIP_SIZE: usize = 8192;
+
+/// Types of the values of IFD entries
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const LONG8: u16 = 16;
+
+/// The representation of a TIFF encoder
+///
+/// Images are written in big endian byte order as a single image file directory. Files that
+/// would exceed the 4 GiB reachable by 32-bit offsets are written as BigTIFF.
+pub struct TIFFEncoder<W> {
+    w: W,
+    compression: TIFFCompression,
+    bigtiff: bool,
+}
+
+impl<W: Write> TIFFEncoder<W> {
+    /// Create a new encoder that writes uncompressed images to ```w```
+    pub fn new(w: W) -> TIFFEncoder<W> {
+        TIFFEncoder::new_with_compression(w, TIFFCompression::None)
+    }
+
+    /// Create a new encoder that writes its output to ```w``` with the strips compressed by
+    /// ```compression```
+    pub fn new_with_compression(w: W, compression: TIFFCompression) -> TIFFEncoder<W> {
+        TIFFEncoder {
+            w: w,
+            compression: compression,
+            bigtiff: false,
+        }
+    }
+
+    /// Write a BigTIFF file with 64-bit offsets even if the image is small enough for TIFF
+    pub fn set_bigtiff(&mut self, bigtiff: bool) {
+        self.bigtiff = bigtiff
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// 8 and 16-bit as well as 32-bit floating point gray, rgb and rgba images are supported.
+    /// Samples wider than a byte are expected to be stored in big endian order, as they are in
+    /// the file.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: ColorType) -> io::Result<()> {
+        let (samples, bits) = match color {
+            ColorType::Gray(8) => (1, 8),
+            ColorType::Gray(16) => (1, 16),
+            ColorType::RGB(8) => (3, 8),
+            ColorType::RGB(16) => (3, 16),
+            ColorType::RGBA(8) => (4, 8),
+            ColorType::RGBA(16) => (4, 16),
+            ColorType::GrayF(32) => (1, 32),
+            ColorType::RGBF(32) => (3, 32),
+            ColorType::RGBAF(32) => (4, 32),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           format!("Unsupported color type {:?}", color))),
+        };
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid TIFF image dimensions"))
+        }
+        let row_len = width as usize * samples * bits / 8;
+        let image_len = row_len * height as usize;
+        if data.len() < image_len {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+        }
+
+        let rows_per_strip = (STRIP_SIZE / row_len).max(1).min(height as usize);
+        let mut strips = Vec::new();
+        for rows in data[..image_len].chunks(rows_per_strip * row_len) {
+            strips.push(try!(self.compression.compress(rows)));
+        }
+
+        let strips_len: u64 = strips.iter().map(|s| (s.len() as u64 + 1) & !1).sum();
+        // The offsets and byte counts of the strips make up most of the remaining data
+        let bigtiff = self.bigtiff || 8 + strips_len + 16 * strips.len() as u64 + 1024 > u32::max_value() as u64;
+        let offset_len = if bigtiff { 8 } else { 4 };
+
+        // The strips follow the header, then the values too large for their entries, then the
+        // directory itself. Everything is aligned to even offsets.
+        let mut offset: u64 = if bigtiff { 16 } else { 8 };
+        let mut strip_offsets = Vec::with_capacity(strips.len());
+        for strip in &strips {
+            strip_offsets.push(offset);
+            offset += (strip.len() as u64 + 1) & !1;
+        }
+        let strip_byte_counts: Vec<u64> = strips.iter().map(|s| s.len() as u64).collect();
+
+        let mut values = Vec::new();
+        let mut entries = Vec::new();
+        {
+            let mut entry = |tag: u16, type_: u16, data: Vec<u64>| {
+                let size = match type_ { SHORT => 2, LONG8 => 8, _ => 4 };
+                let count = if type_ == RATIONAL { data.len() / 2 } else { data.len() };
+                let mut bytes = Vec::new();
+                for &value in &data {
+                    match type_ {
+                        SHORT => bytes.write_u16::<BigEndian>(value as u16).unwrap(),
+                        LONG8 => bytes.write_u64::<BigEndian>(value).unwrap(),
+                        _ => bytes.write_u32::<BigEndian>(value as u32).unwrap(),
+                    }
+                }
+                if data.len() * size <= offset_len {
+                    // Small values are stored left-justified in the entry
+                    bytes.resize(offset_len, 0);
+                } else {
+                    let position = offset + values.len() as u64;
+                    values.extend(bytes);
+                    if values.len() % 2 == 1 {
+                        values.push(0);
+                    }
+                    bytes = Vec::new();
+                    if bigtiff {
+                        bytes.write_u64::<BigEndian>(position).unwrap();
+                    } else {
+                        bytes.write_u32::<BigEndian>(position as u32).unwrap();
+                    }
+                }
+                entries.push((tag, type_, count as u64, bytes));
+            };
+
+            entry(256, LONG, vec![width as u64]); // ImageWidth
+            entry(257, LONG, vec![height as u64]); // ImageLength
+            entry(258, SHORT, vec![bits as u64; samples]); // BitsPerSample
+            entry(259, SHORT, vec![self.compression.tag_value() as u64]); // Compression
+            entry(262, SHORT, vec![if samples == 1 { 1 } else { 2 }]); // PhotometricInterpretation
+            entry(273, if bigtiff { LONG8 } else { LONG }, strip_offsets); // StripOffsets
+            entry(277, SHORT, vec![samples as u64]); // SamplesPerPixel
+            entry(278, LONG, vec![rows_per_strip as u64]); // RowsPerStrip
+            entry(279, LONG, strip_byte_counts); // StripByteCounts
+            entry(282, RATIONAL, vec![72, 1]); // XResolution
+            entry(283, RATIONAL, vec![72, 1]); // YResolution
+            entry(284, SHORT, vec![1]); // PlanarConfiguration
+            entry(296, SHORT, vec![2]); // ResolutionUnit
+            if samples == 4 {
+                entry(338, SHORT, vec![2]); // ExtraSamples, unassociated alpha
+            }
+            if bits == 32 {
+                entry(339, SHORT, vec![3; samples]); // SampleFormat, floating point
+            }
+        }
+        let ifd_offset = offset + values.len() as u64;
+
+        try!(self.w.write_all(b"MM"));
+        if bigtiff {
+            try!(self.w.write_u16::<BigEndian>(43));
+            // The size of offsets and a constant 0
+            try!(self.w.write_u16::<BigEndian>(8));
+            try!(self.w.write_u16::<BigEndian>(0));
+            try!(self.w.write_u64::<BigEndian>(ifd_offset));
+        } else {
+            try!(self.w.write_u16::<BigEndian>(42));
+            try!(self.w.write_u32::<BigEndian>(ifd_offset as u32));
+        }
+        for strip in &strips {
+            try!(self.w.write_all(strip));
+            if strip.len() % 2 == 1 {
+                try!(self.w.write_all(&[0]));
+            }
+        }
+        try!(self.w.write_all(&values));
+
+        if bigtiff {
+            try!(self.w.write_u64::<BigEndian>(entries.len() as u64));
+        } else {
+            try!(self.w.write_u16::<BigEndian>(entries.len() as u16));
+        }
+        for (tag, type_, count, value) in entries {
+            try!(self.w.write_u16::<BigEndian>(tag));
+            try!(self.w.write_u16::<BigEndian>(type_));
+            if bigtiff {
+                try!(self.w.write_u64::<BigEndian>(count));
+            } else {
+                try!(self.w.write_u32::<BigEndian>(count as u32));
+            }
+            try!(self.w.write_all(&value));
+        }
+        // There is no further image
+        if bigtiff {
+            try!(self.w.write_u64::<BigEndian>(0));
+        } else {
+            try!(self.w.write_u32::<BigEndian>(0));
+        }
+
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for TIFFEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+    use super::{TIFFEncoder, TIFFCompression};
+    use super::super::TIFFDecoder;
+
+    fn roundtrip(data: &[u8], width: u32, height: u32, color: ColorType, compression: TIFFCompression)
+                 -> DecodingResult {
+        roundtrip_as(data, width, height, color, compression, false)
+    }
+
+    fn roundtrip_as(data: &[u8], width: u32, height: u32, color: ColorType, compression: TIFFCompression,
+                    bigtiff: bool) -> DecodingResult {
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = TIFFEncoder::new_with_compression(&mut encoded, compression);
+            encoder.set_bigtiff(bigtiff);
+            encoder.encode(data, width, height, color).unwrap();
+        }
+        assert_eq!(encoded[2..4], if bigtiff { [0, 43] } else { [0, 42] });
+        let mut decoder = TIFFDecoder::new(Cursor::new(encoded)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (width, height));
+        assert_eq!(decoder.colortype().unwrap(), color);
+        decoder.read_image().unwrap()
+    }
+
+    #[test]
+    fn roundtrip_8bit() {
+        // Large enough to be split into several strips
+        let (width, height) = (123, 77);
+        let rgba: Vec<u8> = (0..width * height * 4).map(|i| (i % 251 ^ i / 1000) as u8).collect();
+        let rgb: Vec<u8> = rgba.chunks(4).flat_map(|p| p[..3].to_vec()).collect();
+        let gray: Vec<u8> = rgba.chunks(4).map(|p| p[1]).collect();
+
+        for &compression in &[TIFFCompression::None, TIFFCompression::LZW, TIFFCompression::Deflate] {
+            for &(ref data, color) in &[(&gray, ColorType::Gray(8)), (&rgb, ColorType::RGB(8)),
+                                        (&rgba, ColorType::RGBA(8))] {
+                match roundtrip(data, width, height, color, compression) {
+                    DecodingResult::U8(decoded) => assert!(&decoded == *data, "{:?} {:?}", compression, color),
+                    _ => panic!("Image did not decode as 8-bit"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_16bit() {
+        let samples: Vec<u16> = (0..5 * 3 * 3).map(|i| i * 1409).collect();
+        let data: Vec<u8> = samples.iter().flat_map(|&s| vec![(s >> 8) as u8, s as u8]).collect();
+
+        for &compression in &[TIFFCompression::None, TIFFCompression::LZW, TIFFCompression::Deflate] {
+            match roundtrip(&data, 5, 3, ColorType::RGB(16), compression) {
+                DecodingResult::U16(decoded) => assert_eq!(decoded, samples),
+                _ => panic!("Image did not decode as 16-bit"),
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_float() {
+        let samples: Vec<f32> = (0..4 * 3 * 4).map(|i| i as f32 * 0.375 - 2.0).collect();
+        let data: Vec<u8> = samples.iter().flat_map(|&s| {
+            let bits = s.to_bits();
+            vec![(bits >> 24) as u8, (bits >> 16) as u8, (bits >> 8) as u8, bits as u8]
+        }).collect();
+
+        match roundtrip(&data, 4, 3, ColorType::RGBAF(32), TIFFCompression::LZW) {
+            DecodingResult::F32(decoded) => assert_eq!(decoded, samples),
+            _ => panic!("Image did not decode as floating point"),
+        }
+        match roundtrip(&data, 6, 2, ColorType::GrayF(32), TIFFCompression::None) {
+            DecodingResult::F32(decoded) => assert_eq!(decoded, &samples[..12]),
+            _ => panic!("Image did not decode as floating point"),
+        }
+    }
+
+    #[test]
+    fn lzw_compresses() {
+        let data = vec![7; 256 * 256];
+        let mut encoded = Vec::new();
+        TIFFEncoder::new_with_compression(&mut encoded, TIFFCompression::LZW)
+            .encode(&data, 256, 256, ColorType::Gray(8)).unwrap();
+        assert!(encoded.len() < data.len() / 20);
+    }
+
+    #[test]
+    fn roundtrip_bigtiff() {
+        let (width, height) = (97, 203);
+        let data: Vec<u8> = (0..width * height * 3).map(|i| (i * 13 % 256) as u8).collect();
+        match roundtrip_as(&data, width, height, ColorType::RGB(8), TIFFCompression::LZW, true) {
+            DecodingResult::U8(decoded) => assert!(decoded == data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+}
@@ -8,5 +8,7 @@
 //!
 
 pub use self::decoder::TIFFDecoder;
+pub use self::encoder::{TIFFEncoder, TIFFCompression};
 
 mod decoder;
+mod encoder;
@@ -7,6 +7,12 @@ use num_traits::{ Bounded, Num, NumCast };
 
 /// Primitive trait from old stdlib
 pub trait Primitive: Copy + NumCast + Num + PartialOrd<Self> + Clone + Bounded {
+    /// The value of a channel at full intensity, which is also an opaque alpha value
+    ///
+    /// This is the largest value of integer types and 1.0 for floating point types.
+    fn default_max_value() -> Self {
+        Self::max_value()
+    }
 }
 
 impl Primitive for usize {
@@ -30,6 +36,12 @@ impl Primitive for i32 {
 impl Primitive for i64 {
 }
 impl Primitive for f32 {
+    fn default_max_value() -> f32 {
+        1.0
+    }
 }
 impl Primitive for f64 {
+    fn default_max_value() -> f64 {
+        1.0
+    }
 }
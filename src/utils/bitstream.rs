@@ -157,8 +157,8 @@ impl<'a, W> Write for $name<'a, W> where W: Write + 'a  {
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        let missing = 8 - self.bits;
-        if missing > 0 {
+        if self.bits > 0 {
+            let missing = 8 - self.bits;
             try!(self.write_bits(0, missing));
         }
         self.w.flush()
@@ -266,7 +266,15 @@ impl EncodingDict {
     }
 }
 
-pub fn encode<R, W>(r: R, mut w: W, min_code_size: u8) -> io::Result<()>
+macro_rules! define_encoder_function {
+    {$(
+        $name:ident, $offset:expr, #[$doc:meta];
+    )*} => {
+
+$( // START function definition
+
+#[$doc]
+pub fn $name<R, W>(r: R, mut w: W, min_code_size: u8) -> io::Result<()>
 where R: Read, W: BitWriter {
     let mut dict = EncodingDict::new(min_code_size);
     dict.push_node(Node::new(0)); // clear code
@@ -287,11 +295,11 @@ where R: Read, W: BitWriter {
         }
         // There is a hit: do not write out code but continue
         let next_code = dict.next_code();
-        if next_code > (1 << code_size as usize)
+        if next_code > (1 << code_size as usize) - $offset
            && code_size < MAX_CODESIZE {
             code_size += 1;
         }
-        if next_code > MAX_ENTRIES {
+        if next_code > MAX_ENTRIES - 2 * $offset {
             dict.reset();
             dict.push_node(Node::new(0)); // clear code
             dict.push_node(Node::new(0)); // end code
@@ -302,8 +310,23 @@ where R: Read, W: BitWriter {
     }
     if let Some(code) = i {
         try!(w.write_bits(code, code_size));
+        // The decoder adds an entry for the last code before reading the end code
+        if dict.next_code() + 1 > (1 << code_size as usize) - $offset
+           && code_size < MAX_CODESIZE {
+            code_size += 1;
+        }
     }
     try!(w.write_bits(dict.end_code(), code_size));
     try!(w.flush());
     Ok(())
 }
+
+)* // END function definition
+
+    }
+}
+
+define_encoder_function!{
+    encode, 0, #[doc = "Encodes a stream with lzw compression."];
+    encode_early_change, 1, #[doc = "Encodes a stream with lzw compression using an “early change” algorithm."];
+}
@@ -7,6 +7,12 @@ pub mod lzw;
 use std::iter::repeat;
 use num_iter::range_step;
 
+/// One of two values, used by the decoders that hold either a stream or the reader wrapping it
+#[cfg(any(feature = "gif_codec", feature = "png_codec"))]
+pub enum Either<T, U> {
+    Left(T),
+    Right(U)
+}
 
 #[inline(always)]
 pub fn expand_packed<F>(buf: &mut [u8], channels: usize, bit_depth: u8, mut func: F)
@@ -0,0 +1,82 @@
+//! Decoding of the alpha channel of lossy WebP images
+//!
+//! The alpha values are stored in an ```ALPH``` chunk, either raw or as the green channel of a
+//! lossless image, after subtracting a prediction from the neighbouring values.
+//!
+//! # Related Links
+//! * <https://developers.google.com/speed/webp/docs/riff_container#alpha>
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+use super::lossless;
+
+// Compression methods
+const NO_COMPRESSION: u8 = 0;
+const LOSSLESS_COMPRESSION: u8 = 1;
+
+// Filtering methods
+const NO_FILTER: u8 = 0;
+const HORIZONTAL_FILTER: u8 = 1;
+const VERTICAL_FILTER: u8 = 2;
+const GRADIENT_FILTER: u8 = 3;
+
+/// Decodes the ```ALPH``` chunk ```data``` of an image of ```width``` x ```height```
+pub fn decode(data: &[u8], width: usize, height: usize) -> ImageResult<Vec<u8>> {
+    let header = match data.first() {
+        Some(&header) => header,
+        None => return Err(ImageError::truncated(ImageFormat::WEBP)),
+    };
+    let compression = header & 0x03;
+    let filter = (header >> 2) & 0x03;
+    // The preprocessing bits only tell whether the levels were reduced, which needs no undoing
+
+    let mut alpha = match compression {
+        NO_COMPRESSION => {
+            if data.len() < 1 + width * height {
+                return Err(ImageError::truncated(ImageFormat::WEBP))
+            }
+            data[1..1 + width * height].to_vec()
+        }
+        LOSSLESS_COMPRESSION => {
+            let pixels = try!(lossless::decode_headerless(&data[1..], width, height));
+            pixels.iter().map(|&pixel| (pixel >> 8) as u8).collect()
+        }
+        _ => return Err(ImageError::malformed(ImageFormat::WEBP,
+            "Unknown alpha compression method".to_string())),
+    };
+
+    unfilter(&mut alpha, width, height, filter);
+    Ok(alpha)
+}
+
+/// Adds the prediction of ```filter``` back to each alpha value
+fn unfilter(alpha: &mut [u8], width: usize, height: usize, filter: u8) {
+    if filter == NO_FILTER {
+        return
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let pos = y * width + x;
+            let prediction = if x == 0 && y == 0 {
+                0
+            } else if y == 0 {
+                alpha[pos - 1]
+            } else if x == 0 {
+                alpha[pos - width]
+            } else {
+                match filter {
+                    HORIZONTAL_FILTER => alpha[pos - 1],
+                    VERTICAL_FILTER => alpha[pos - width],
+                    GRADIENT_FILTER => {
+                        let gradient = alpha[pos - 1] as i16 + alpha[pos - width] as i16
+                            - alpha[pos - width - 1] as i16;
+                        if gradient < 0 { 0 } else if gradient > 255 { 255 } else { gradient as u8 }
+                    }
+                    _ => unreachable!(),
+                }
+            };
+            alpha[pos] = alpha[pos].wrapping_add(prediction);
+        }
+    }
+}
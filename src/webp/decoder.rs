@@ -1,24 +1,43 @@
 use std::io;
 use std::io::Read;
-use std::default::Default;
-use byteorder::{ReadBytesExt, LittleEndian};
+use byteorder::{ByteOrder, ReadBytesExt, LittleEndian};
 
 use image;
 use image::ImageResult;
 use image::ImageDecoder;
+use image::{decoder_metadata, Metadata};
 
+use animation::{self, Blend, Delay, Disposal, Frames, LoopCount};
+use buffer::RgbaImage;
 use color;
+use dynimage::decoder_to_image;
+use metadata::exif::Exif;
 
-use super::vp8::Frame;
-use super::vp8::VP8Decoder;
+use super::alpha;
+use super::lossless;
+use super::vp8::{Frame, VP8Decoder};
 
 
 
+/// The flag of the extended format header marking animated images
+static ANIMATION_FLAG: u8 = 0x02;
+
 /// A Representation of a Webp Image format decoder.
 pub struct WebpDecoder<R> {
     r: R,
-    frame: Frame,
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    /// The chunks of the image
+    payload: Vec<u8>,
+    animated: bool,
+    /// The number of frames of an animation
+    frame_count: u32,
+    data: Vec<u8>,
+    frames: Option<Vec<animation::Frame>>,
+    loop_count: LoopCount,
     have_frame: bool,
+    decoded: bool,
     decoded_rows: u32,
 }
 
@@ -26,16 +45,56 @@ impl<R: Read> WebpDecoder<R> {
     /// Create a new WebpDecoder from the Reader ```r```.
     /// This function takes ownership of the Reader.
     pub fn new(r: R) -> WebpDecoder<R> {
-        let f: Frame = Default::default();
-
         WebpDecoder {
             r: r,
+            width: 0,
+            height: 0,
+            color: color::ColorType::Gray(8),
+            payload: Vec::new(),
+            animated: false,
+            frame_count: 1,
+            data: Vec::new(),
+            frames: None,
+            loop_count: LoopCount::Finite(1),
             have_frame: false,
-            frame: f,
+            decoded: false,
             decoded_rows: 0
         }
     }
 
+    /// Returns true if the image is animated
+    pub fn is_animated(&mut self) -> ImageResult<bool> {
+        try!(self.read_metadata());
+
+        Ok(self.animated)
+    }
+
+    /// Returns how often the animation is played, which is once for still images
+    pub fn loop_count(&mut self) -> ImageResult<LoopCount> {
+        try!(self.read_metadata());
+
+        Ok(self.loop_count)
+    }
+
+    /// Returns the EXIF data of the EXIF chunk, if the image has one
+    pub fn exif(&mut self) -> ImageResult<Option<Exif>> {
+        try!(self.read_metadata());
+        let chunks = try!(read_chunks(&self.payload));
+        match chunks.iter().find(|&&(name, _)| &name == b"EXIF") {
+            Some(&(_, exif)) => Exif::parse(exif).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the frames of the image rendered onto full canvases
+    ///
+    /// Unlike the frames returned by `into_frames`, which only cover the area they change, each
+    /// of these shows the whole image with the disposal of the frames before it applied.
+    pub fn into_composited_frames(mut self) -> ImageResult<Frames> {
+        let (width, height) = try!(self.dimensions());
+        Ok(try!(self.into_frames()).composite(width, height))
+    }
+
     fn read_riff_header(&mut self) -> ImageResult<u32> {
         let mut riff = Vec::with_capacity(4);
         try!(self.r.by_ref().take(4).read_to_end(&mut riff));
@@ -44,81 +103,341 @@ impl<R: Read> WebpDecoder<R> {
         try!(self.r.by_ref().take(4).read_to_end(&mut webp));
 
         if &*riff != b"RIFF" {
-            return Err(image::ImageError::FormatError("Invalid RIFF signature.".to_string()))
+            return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                "Invalid RIFF signature.".to_string()))
         }
 
         if &*webp != b"WEBP" {
-            return Err(image::ImageError::FormatError("Invalid WEBP signature.".to_string()))
+            return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                "Invalid WEBP signature.".to_string()))
         }
 
         Ok(size)
     }
 
-    fn read_vp8_header(&mut self) -> ImageResult<()> {
-        let mut vp8 = Vec::with_capacity(4);
-        try!(self.r.by_ref().take(4).read_to_end(&mut vp8));
-
-        if &*vp8 != b"VP8 " {
-            return Err(image::ImageError::FormatError("Invalid VP8 signature.".to_string()))
+    /// Reads the chunks of the image and the headers of its bitstream, without decoding it
+    fn read_metadata(&mut self) -> ImageResult<()> {
+        if self.have_frame {
+            return Ok(())
         }
 
-        let _len = try!(self.r.read_u32::<LittleEndian>());
+        // The size includes the WEBP signature
+        let size = try!(self.read_riff_header());
+        let mut payload = Vec::new();
+        try!(self.r.by_ref().take(size.saturating_sub(4) as u64).read_to_end(&mut payload));
+
+        // The size of the canvas given by the extended format header
+        let mut canvas = None;
+        let mut animated = false;
+        let mut frames = 0;
+        {
+            let chunks = try!(read_chunks(&payload));
+            for &(name, chunk) in &chunks {
+                match &name {
+                    b"VP8X" => {
+                        if chunk.len() < 10 {
+                            return Err(image::ImageError::truncated(image::ImageFormat::WEBP))
+                        }
+                        animated = chunk[0] & ANIMATION_FLAG != 0;
+                        let width = LittleEndian::read_u24(&chunk[4..7]) + 1;
+                        let height = LittleEndian::read_u24(&chunk[7..10]) + 1;
+                        canvas = Some((width, height));
+                    }
+                    b"ANIM" => {
+                        if chunk.len() < 6 {
+                            return Err(image::ImageError::truncated(image::ImageFormat::WEBP))
+                        }
+                        // The background color is only a hint, disposed frames are cleared to transparent
+                        self.loop_count = match LittleEndian::read_u16(&chunk[4..6]) {
+                            0 => LoopCount::Infinite,
+                            n => LoopCount::Finite(n as u32),
+                        };
+                    }
+                    b"ANMF" => frames += 1,
+                    _ => (),
+                }
+            }
+
+            if animated {
+                // Only the extended format header marks images as animated
+                if frames == 0 {
+                    return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                        "Animation has no frames.".to_string()))
+                }
+                let (width, height) = canvas.unwrap();
+                self.width = width;
+                self.height = height;
+                self.color = color::ColorType::RGBA(8);
+                self.frame_count = frames;
+            } else {
+                let (width, height, color) = match try!(image_header(&chunks)) {
+                    Some(header) => header,
+                    None => return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                        "Image data is missing.".to_string())),
+                };
+                match canvas {
+                    Some(canvas) if canvas != (width, height) => {
+                        return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                            "Image size does not match the canvas.".to_string()))
+                    }
+                    _ => (),
+                }
+                self.width = width;
+                self.height = height;
+                self.color = color;
+            }
+        }
 
+        self.animated = animated;
+        self.payload = payload;
+        self.have_frame = true;
         Ok(())
     }
 
-    fn read_frame(&mut self) -> ImageResult<()> {
-        let mut framedata = Vec::new();
-        try!(self.r.read_to_end(&mut framedata));
-        let m = io::Cursor::new(framedata);
+    /// Decodes the image, or the frames of an animation
+    fn read_data(&mut self) -> ImageResult<()> {
+        try!(self.read_metadata());
+        if self.decoded {
+            return Ok(())
+        }
 
-        let mut v = VP8Decoder::new(m);
-        let frame = try!(v.decode_frame());
+        let chunks = try!(read_chunks(&self.payload));
+        if self.animated {
+            let mut frames = Vec::new();
+            for &(name, chunk) in &chunks {
+                if &name == b"ANMF" {
+                    frames.push(try!(read_animation_frame(chunk)));
+                }
+            }
+            // The image itself is the first frame of the animation
+            let first = Frames::new(vec![frames[0].clone()]).composite(self.width, self.height).next().unwrap();
+            self.data = first.into_buffer().into_raw();
+            self.frames = Some(frames);
+        } else {
+            let image = match try!(decode_image(&chunks)) {
+                Some(image) => image,
+                None => return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                    "Image data is missing.".to_string())),
+            };
+            if (image.width, image.height, image.color) != (self.width, self.height, self.color) {
+                return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                    "Image does not match its header.".to_string()))
+            }
+            self.data = image.data;
+        }
+        self.decoded = true;
+        Ok(())
+    }
+}
 
-        self.frame = frame.clone();
+/// Reads the dimensions and color type of the image stored in ```chunks``` from the header of its
+/// bitstream, as they are returned by `decode_image`
+fn image_header(chunks: &[([u8; 4], &[u8])]) -> ImageResult<Option<(u32, u32, color::ColorType)>> {
+    let has_alpha = chunks.iter().any(|&(name, _)| &name == b"ALPH");
+    for &(name, chunk) in chunks {
+        match &name {
+            b"VP8 " => {
+                if chunk.len() < 10 {
+                    return Err(image::ImageError::truncated(image::ImageFormat::WEBP))
+                }
+                // A frame tag for a key frame followed by the start code
+                if chunk[0] & 1 != 0 || chunk[3..6] != [0x9d, 0x01, 0x2a] {
+                    return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+                        "Invalid VP8 frame header.".to_string()))
+                }
+                let width = (LittleEndian::read_u16(&chunk[6..8]) & 0x3FFF) as u32;
+                let height = (LittleEndian::read_u16(&chunk[8..10]) & 0x3FFF) as u32;
+                let color = if has_alpha { color::ColorType::RGBA(8) } else { color::ColorType::RGB(8) };
+                return Ok(Some((width, height, color)))
+            }
+            b"VP8L" => {
+                let (width, height) = try!(lossless::dimensions(chunk));
+                return Ok(Some((width, height, color::ColorType::RGBA(8))))
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
 
-        Ok(())
+/// Splits ```data``` into chunks, returning the name and contents of each
+fn read_chunks(mut data: &[u8]) -> ImageResult<Vec<([u8; 4], &[u8])>> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let name = [data[0], data[1], data[2], data[3]];
+        let len = LittleEndian::read_u32(&data[4..8]) as usize;
+        if data.len() - 8 < len {
+            return Err(image::ImageError::truncated(image::ImageFormat::WEBP))
+        }
+        chunks.push((name, &data[8..8 + len]));
+        // Chunks are padded to an even size
+        data = &data[(8 + len + len % 2).min(data.len())..];
     }
+    Ok(chunks)
+}
 
-    fn read_metadata(&mut self) -> ImageResult<()> {
-        if !self.have_frame {
-            try!(self.read_riff_header());
-            try!(self.read_vp8_header());
-            try!(self.read_frame());
+/// A still image decoded from a bitstream
+struct Image {
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    data: Vec<u8>,
+}
 
-            self.have_frame = true;
+/// Decodes the image stored in ```chunks```, which is lossy with an optional alpha channel or
+/// lossless
+fn decode_image(chunks: &[([u8; 4], &[u8])]) -> ImageResult<Option<Image>> {
+    let mut alpha = None;
+    for &(name, chunk) in chunks {
+        match &name {
+            b"ALPH" => alpha = Some(chunk),
+            b"VP8 " => {
+                let mut v = VP8Decoder::new(io::Cursor::new(chunk));
+                let frame = try!(v.decode_frame());
+                let (width, height) = (frame.width as usize, frame.height as usize);
+
+                let rgb = yuv_to_rgb(frame);
+
+                return Ok(Some(match alpha {
+                    Some(alpha) => {
+                        let alpha = try!(alpha::decode(alpha, width, height));
+                        let mut data = Vec::with_capacity(width * height * 4);
+                        for (p, &a) in rgb.chunks(3).zip(&alpha) {
+                            data.extend_from_slice(&[p[0], p[1], p[2], a]);
+                        }
+                        Image { width: width as u32, height: height as u32, color: color::ColorType::RGBA(8), data: data }
+                    }
+                    None => {
+                        Image { width: width as u32, height: height as u32, color: color::ColorType::RGB(8), data: rgb }
+                    }
+                }))
+            }
+            b"VP8L" => {
+                let (width, height, data) = try!(lossless::decode(chunk));
+                return Ok(Some(Image { width: width, height: height, color: color::ColorType::RGBA(8), data: data }))
+            }
+            _ => (),
         }
+    }
+    Ok(None)
+}
 
-        Ok(())
+/// Converts the limited range YCbCr planes of ```frame``` to rgb pixels the way libwebp does,
+/// interpolating each chroma sample with its nearest neighbours in the ratio 9:3:3:1
+fn yuv_to_rgb(frame: &Frame) -> Vec<u8> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let (cw, ch) = ((width + 1) / 2, (height + 1) / 2);
+    // The chroma sample to the left or above a pixel in an even column or row, else to its right or
+    // below, repeating those at the edges
+    let neighbour = |i: usize, len: usize| {
+        if i % 2 == 0 { (i / 2).max(1) - 1 } else { (i / 2 + 1).min(len - 1) }
+    };
+    let mult_hi = |v: u8, coeff: i32| (v as i32 * coeff) >> 8;
+    let clip = |v: i32| (v >> 6).max(0).min(255) as u8;
+
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        let (near_row, far_row) = (y / 2 * cw, neighbour(y, ch) * cw);
+        for x in 0..width {
+            let (near, far) = (x / 2, neighbour(x, cw));
+            let upsample = |plane: &[u8]| {
+                let (a, b) = (plane[near_row + near] as u32, plane[near_row + far] as u32);
+                let (c, d) = (plane[far_row + near] as u32, plane[far_row + far] as u32);
+                // Pixels in the first and last column are only interpolated vertically, and the
+                // others are rounded in two steps like libwebp
+                if near == far {
+                    ((3 * a + c + 2) >> 2) as u8
+                } else {
+                    ((((a + 3 * b + 3 * c + d + 8) >> 3) + a) >> 1) as u8
+                }
+            };
+            let (u, v) = (upsample(&frame.ubuf), upsample(&frame.vbuf));
+            let luma = mult_hi(frame.ybuf[y * width + x], 19077);
+            rgb.push(clip(luma + mult_hi(v, 26149) - 14234));
+            rgb.push(clip(luma - mult_hi(u, 6419) - mult_hi(v, 13320) + 8708));
+            rgb.push(clip(luma + mult_hi(u, 33050) - 17685));
+        }
     }
+    rgb
 }
 
+/// Reads the ```ANMF``` chunk ```data``` holding a frame of an animation
+fn read_animation_frame(data: &[u8]) -> ImageResult<animation::Frame> {
+    if data.len() < 16 {
+        return Err(image::ImageError::truncated(image::ImageFormat::WEBP))
+    }
+    // Offsets are stored in units of two pixels
+    let left = LittleEndian::read_u24(&data[0..3]) * 2;
+    let top = LittleEndian::read_u24(&data[3..6]) * 2;
+    let width = LittleEndian::read_u24(&data[6..9]) + 1;
+    let height = LittleEndian::read_u24(&data[9..12]) + 1;
+    let duration = LittleEndian::read_u24(&data[12..15]);
+    let flags = data[15];
+
+    let image = match try!(decode_image(&try!(read_chunks(&data[16..])))) {
+        Some(image) => image,
+        None => return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+            "Frame data is missing.".to_string())),
+    };
+    if (image.width, image.height) != (width, height) {
+        return Err(image::ImageError::malformed(image::ImageFormat::WEBP,
+            "Frame size does not match its data.".to_string()))
+    }
+    let data = match image.color {
+        color::ColorType::RGB(8) => image.data.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        _ => image.data,
+    };
+    let buffer = RgbaImage::from_raw(width, height, data).unwrap();
+
+    let mut frame = animation::Frame::from_parts(buffer, left, top, Delay::from_numer_denom_ms(duration, 1));
+    frame.set_blend(if flags & 0x02 != 0 { Blend::Source } else { Blend::Over });
+    frame.set_disposal(if flags & 0x01 != 0 { Disposal::Background } else { Disposal::None });
+    Ok(frame)
+}
+
+
 impl<R: Read> ImageDecoder for WebpDecoder<R> {
     fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
         try!(self.read_metadata());
 
-        Ok((self.frame.width as u32, self.frame.height as u32))
+        Ok((self.width, self.height))
     }
 
     fn colortype(&mut self) -> ImageResult<color::ColorType> {
-        Ok(color::ColorType::Gray(8))
+        try!(self.read_metadata());
+
+        Ok(self.color)
     }
 
     fn row_len(&mut self) -> ImageResult<usize> {
         try!(self.read_metadata());
 
-        Ok(self.frame.width as usize)
+        Ok(self.width as usize * color::num_components(self.color))
+    }
+
+    fn is_animated(&mut self) -> ImageResult<bool> {
+        WebpDecoder::is_animated(self)
+    }
+
+    fn metadata(&mut self) -> ImageResult<Metadata> {
+        let mut metadata = try!(decoder_metadata(self));
+        metadata.frame_count = Some(self.frame_count);
+        Ok(metadata)
     }
 
     fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
-        try!(self.read_metadata());
+        try!(self.read_data());
 
-        if self.decoded_rows > self.frame.height as u32 {
-            return Err(image::ImageError::ImageEnd)
+        if self.decoded_rows >= self.height {
+            return Err(image::ImageError::Parameter(image::ParameterErrorKind::NoMoreData))
         }
 
-        let rlen  = buf.len();
-        let slice = &self.frame.ybuf[
+        let rlen = try!(self.row_len());
+        if buf.len() < rlen {
+            return Err(image::ImageError::Parameter(image::ParameterErrorKind::BufferTooShort))
+        }
+
+        let slice = &self.data[
             self.decoded_rows as usize * rlen..
             self.decoded_rows as usize * rlen + rlen
         ];
@@ -126,12 +445,143 @@ impl<R: Read> ImageDecoder for WebpDecoder<R> {
         ::copy_memory(slice, buf);
         self.decoded_rows += 1;
 
-        Ok(self.decoded_rows)
+        Ok(self.decoded_rows - 1)
     }
 
     fn read_image(&mut self) -> ImageResult<image::DecodingResult> {
-        try!(self.read_metadata());
+        try!(self.read_data());
 
-        Ok(image::DecodingResult::U8(self.frame.ybuf.clone()))
+        Ok(image::DecodingResult::U8(self.data.clone()))
+    }
+
+    /// Returns the frames of an animated image, or the image as a single frame if it is not
+    /// animated.
+    fn into_frames(mut self) -> ImageResult<Frames> {
+        try!(self.read_data());
+
+        match self.frames.take() {
+            Some(frames) => Ok(Frames::new(frames)),
+            None => Ok(Frames::new(vec![
+                animation::Frame::new(try!(decoder_to_image(self)).to_rgba())
+            ])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use super::WebpDecoder;
+    use animation::{Blend, Delay, Disposal, LoopCount};
+    use color::{ColorType, Rgba};
+    use image::{DecodingResult, ImageDecoder};
+
+    fn decode(name: &str) -> (u32, u32, Vec<u8>) {
+        let file = File::open(format!("tests/images/webp/{}", name)).unwrap();
+        let mut decoder = WebpDecoder::new(file);
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (width, height, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    fn check<F: Fn(u32, u32) -> [u8; 4]>(name: &str, width: u32, height: u32, pixel: F) {
+        let (w, h, data) = decode(name);
+        assert_eq!((w, h), (width, height));
+        assert_eq!(data.len(), (width * height * 4) as usize);
+        for (i, decoded) in data.chunks(4).enumerate() {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            assert_eq!(decoded, &pixel(x, y)[..], "{} at ({}, {})", name, x, y);
+        }
+    }
+
+    #[test]
+    fn lossless() {
+        check("lossless/gradient.webp", 37, 23, |x, y| {
+            [(x * 7 % 256) as u8, (y * 11 % 256) as u8, (x * y % 256) as u8, (255 - x * 3) as u8]
+        });
+
+        let palette = [[255, 0, 0, 255], [0, 128, 255, 255], [20, 200, 20, 128], [250, 250, 250, 255]];
+        check("lossless/palette.webp", 40, 20, |x, y| palette[((x / 3 + y / 2) % 4) as usize]);
+
+        check("lossless/noise.webp", 128, 96, |x, y| {
+            let (x, y) = (x as u64, y as u64);
+            [((x * x + y * 37) * 2654435761 >> 13) as u8,
+             (x ^ y) as u8,
+             ((x / 8) * 40 + (y / 8) * 13) as u8,
+             255]
+        });
+    }
+
+    #[test]
+    fn lossy_alpha() {
+        for name in &["compressed.webp", "uncompressed.webp"] {
+            let (width, height, data) = decode(&format!("lossy_alpha/{}", name));
+            assert_eq!((width, height), (48, 32));
+            for (i, pixel) in data.chunks(4).enumerate() {
+                let (x, y) = (i as u32 % width, i as u32 / width);
+                let alpha = if x < 8 { 0 } else { ((x * 5 + y * 3) % 256) as u8 };
+                assert_eq!(pixel[3], alpha, "{} at ({}, {})", name, x, y);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "png_codec")]
+    fn lossy_alpha_colors() {
+        use png::PNGDecoder;
+
+        // Decoded by libwebp, which also applies the loop filter that moves a few pixels by a level
+        let file = File::open("tests/images/webp/lossy_alpha/reference.png").unwrap();
+        let mut reference = PNGDecoder::new(file);
+        let reference = match reference.read_image().unwrap() {
+            DecodingResult::U8(data) => data,
+            _ => panic!("Image did not decode as 8-bit"),
+        };
+        for name in &["compressed.webp", "uncompressed.webp"] {
+            let (_, _, data) = decode(&format!("lossy_alpha/{}", name));
+            for (i, (a, b)) in data.iter().zip(&reference).enumerate() {
+                assert!((*a as i32 - *b as i32).abs() <= 3, "{} at byte {}: {} != {}", name, i, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn animated() {
+        let open = || WebpDecoder::new(File::open("tests/images/webp/animated/frames.webp").unwrap());
+        assert_eq!(open().metadata().unwrap().frame_count, Some(3));
+
+        let mut decoder = open();
+        assert!(decoder.is_animated().unwrap());
+        assert_eq!(decoder.loop_count().unwrap(), LoopCount::Finite(3));
+        assert_eq!(decoder.dimensions().unwrap(), (6, 4));
+
+        let frames: Vec<_> = decoder.into_frames().unwrap().collect();
+        assert_eq!(frames.len(), 3);
+        assert_eq!((frames[1].left(), frames[1].top()), (2, 2));
+        assert_eq!(frames[1].buffer().dimensions(), (2, 2));
+        assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(100, 1));
+        assert_eq!(frames[1].delay(), Delay::from_numer_denom_ms(250, 1));
+        assert_eq!(frames[0].blend(), Blend::Source);
+        assert_eq!(frames[1].blend(), Blend::Over);
+        assert_eq!(frames[1].disposal(), Disposal::Background);
+        assert_eq!(frames[2].disposal(), Disposal::None);
+
+        let frames: Vec<_> = open().into_composited_frames().unwrap().collect();
+        assert_eq!(frames[0].buffer().dimensions(), (6, 4));
+        let blended = *frames[1].buffer().get_pixel(2, 2);
+        assert!(blended[0] > 100 && blended[2] > 100 && blended[3] > 250);
+        // The second frame was cleared before the third one was drawn
+        assert_eq!(*frames[2].buffer().get_pixel(2, 2), Rgba([0, 0, 0, 0]));
+        assert_eq!(*frames[2].buffer().get_pixel(4, 1), Rgba([0, 255, 0, 255]));
+        assert_eq!(*frames[2].buffer().get_pixel(0, 3), Rgba([255, 0, 0, 255]));
+
+        // Decoding the image as a still shows the first frame
+        match open().read_image().unwrap() {
+            DecodingResult::U8(data) => assert_eq!(&data[..4], &[255, 0, 0, 255]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
     }
 }
@@ -0,0 +1,397 @@
+use std::io::{self, Write};
+use byteorder::{WriteBytesExt, LittleEndian};
+
+use animation::{Blend, Disposal, Frame, LoopCount};
+use color;
+use image::{ImageEncoder, ImageResult};
+
+use super::lossless;
+use super::vp8::VP8Encoder;
+
+/// The compression of an encoded WebP image
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WebpQuality {
+    /// The pixels are stored exactly
+    Lossless,
+    /// The image is compressed lossily with a quality in the range 1-100, where 1 is the worst
+    /// and 100 is the best. Alpha channels are always kept exactly.
+    Lossy(u8),
+}
+
+/// The representation of a WebP encoder
+pub struct WebpEncoder<W> {
+    w: W,
+    quality: WebpQuality,
+    exif: Option<Vec<u8>>,
+}
+
+impl<W: Write> WebpEncoder<W> {
+    /// Create a new encoder that writes lossless images to ```w```
+    pub fn new(w: W) -> WebpEncoder<W> {
+        WebpEncoder::new_with_quality(w, WebpQuality::Lossless)
+    }
+
+    /// Create a new encoder that writes its output to ```w``` with the compression ```quality```
+    pub fn new_with_quality(w: W, quality: WebpQuality) -> WebpEncoder<W> {
+        WebpEncoder {
+            w: w,
+            quality: quality,
+            exif: None,
+        }
+    }
+
+    /// Embed the EXIF data ```exif``` in an EXIF chunk of the image.
+    ///
+    /// ```exif``` is the TIFF structure holding the tags, as returned by `Exif::to_bytes`. The
+    /// ```Exif\0\0``` identifier of JPEG segments is removed if it precedes it.
+    pub fn set_exif(&mut self, exif: Vec<u8>) {
+        self.exif = Some(if exif.starts_with(b"Exif\0\0") { exif[6..].to_vec() } else { exif });
+    }
+
+    /// Encodes the image ```data``` that has dimensions ```width``` and ```height```
+    /// and ```ColorType``` ```color```
+    ///
+    /// Only 8-bit gray and rgb images, with or without alpha, are supported.
+    pub fn encode(mut self, data: &[u8], width: u32, height: u32, color: color::ColorType) -> io::Result<()> {
+        let rgba = try!(to_rgba(data, width, height, color));
+        let mut chunks = try!(self.image_chunks(&rgba, width, height));
+        if chunks[0].0 == *b"ALPH" || self.exif.is_some() {
+            // The extended format header announcing the alpha channel of lossy images and the
+            // EXIF data
+            let alpha = if rgba.iter().any(|p| p[3] != 255) { 0x10 } else { 0 };
+            let exif = if self.exif.is_some() { 0x08 } else { 0 };
+            let mut header = vec![alpha | exif, 0, 0, 0];
+            try!(header.write_u24::<LittleEndian>(width - 1));
+            try!(header.write_u24::<LittleEndian>(height - 1));
+            chunks.insert(0, (*b"VP8X", header));
+        }
+        if let Some(exif) = self.exif.take() {
+            chunks.push((*b"EXIF", exif));
+        }
+        write_riff(&mut self.w, &chunks)
+    }
+
+    /// Encodes ```frames``` as an animation, played ```loop_count``` times.
+    ///
+    /// The size of the animation is that of the first frame, the other frames have to lie within
+    /// it at even offsets. Delays are rounded to milliseconds. WebP can not restore the content
+    /// below a frame, so frames with `Disposal::Previous` are rejected.
+    pub fn encode_frames<I>(mut self, frames: I, loop_count: LoopCount) -> io::Result<()>
+    where I: IntoIterator<Item = Frame> {
+        let frames: Vec<_> = frames.into_iter().collect();
+        let (width, height) = match frames.first() {
+            Some(first) => first.buffer().dimensions(),
+            None => return Err(invalid_input("An animation needs at least one frame")),
+        };
+        let loop_count = match loop_count {
+            LoopCount::Infinite => 0,
+            LoopCount::Finite(n) if n > 0 && n <= u16::max_value() as u32 => n as u16,
+            LoopCount::Finite(_) => return Err(invalid_input("A WebP animation is played 1 to 65535 times")),
+        };
+
+        let mut header = vec![0x02, 0, 0, 0];
+        if self.exif.is_some() {
+            header[0] |= 0x08;
+        }
+        try!(header.write_u24::<LittleEndian>(width - 1));
+        try!(header.write_u24::<LittleEndian>(height - 1));
+        // Disposed frames are cleared to transparent black, the background color is only a hint
+        let mut anim = vec![0, 0, 0, 0];
+        try!(anim.write_u16::<LittleEndian>(loop_count));
+        let mut chunks = vec![(*b"VP8X", header), (*b"ANIM", anim)];
+
+        for frame in &frames {
+            let (frame_width, frame_height) = frame.buffer().dimensions();
+            let fits = |offset: u32, size: u32, max: u32| offset.checked_add(size).map_or(false, |end| end <= max);
+            if !fits(frame.left(), frame_width, width) || !fits(frame.top(), frame_height, height) {
+                return Err(invalid_input("WebP frame is outside of the first frame"))
+            }
+            if frame.left() % 2 != 0 || frame.top() % 2 != 0 {
+                return Err(invalid_input("WebP frames have to be at even offsets"))
+            }
+            let rgba: Vec<[u8; 4]> = frame.buffer().pixels().map(|p| p.data).collect();
+            if rgba.iter().any(|p| p[3] != 255) {
+                chunks[0].1[0] |= 0x10;
+            }
+
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let duration = (numer as u64 + denom as u64 / 2) / denom as u64;
+            let mut anmf = Vec::new();
+            try!(anmf.write_u24::<LittleEndian>(frame.left() / 2));
+            try!(anmf.write_u24::<LittleEndian>(frame.top() / 2));
+            try!(anmf.write_u24::<LittleEndian>(frame_width - 1));
+            try!(anmf.write_u24::<LittleEndian>(frame_height - 1));
+            try!(anmf.write_u24::<LittleEndian>(duration.min(0xff_ffff) as u32));
+            anmf.push(match frame.blend() {
+                Blend::Source => 0x02,
+                Blend::Over => 0,
+            } | match frame.disposal() {
+                Disposal::None => 0,
+                Disposal::Background => 0x01,
+                Disposal::Previous => return Err(invalid_input("WebP frames can not be disposed to the previous content")),
+            });
+            for (name, data) in try!(self.image_chunks(&rgba, frame_width, frame_height)) {
+                write_chunk(&mut anmf, &name, &data);
+            }
+            chunks.push((*b"ANMF", anmf));
+        }
+        if let Some(exif) = self.exif.take() {
+            chunks.push((*b"EXIF", exif));
+        }
+
+        write_riff(&mut self.w, &chunks)
+    }
+
+    /// Compresses the ```width``` by ```height``` pixels ```rgba``` into the chunks of a VP8L
+    /// bitstream, or of a VP8 bitstream preceded by its alpha channel
+    fn image_chunks(&self, rgba: &[[u8; 4]], width: u32, height: u32) -> io::Result<Vec<([u8; 4], Vec<u8>)>> {
+        if width == 0 || height == 0 || width > 1 << 14 || height > 1 << 14 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid WebP image dimensions"))
+        }
+        let (w, h) = (width as usize, height as usize);
+        let has_alpha = rgba.iter().any(|p| p[3] != 255);
+
+        Ok(match self.quality {
+            WebpQuality::Lossless => {
+                let argb: Vec<u32> = rgba.iter().map(|p| {
+                    (p[3] as u32) << 24 | (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32
+                }).collect();
+                vec![(*b"VP8L", lossless::encode(&argb, w, h, has_alpha))]
+            }
+            WebpQuality::Lossy(quality) => {
+                let (y, u, v) = rgb_to_yuv420(rgba, w, h);
+                let mut frame = Vec::new();
+                try!(VP8Encoder::new(&mut frame, quality).encode_frame(&y, &u, &v, width as u16, height as u16));
+
+                if has_alpha {
+                    // Losslessly compressed alpha values without filtering
+                    let mut alpha = vec![1];
+                    let green: Vec<u32> = rgba.iter().map(|p| (p[3] as u32) << 8).collect();
+                    alpha.extend(lossless::encode_headerless(&green, w, h));
+                    vec![(*b"ALPH", alpha), (*b"VP8 ", frame)]
+                } else {
+                    vec![(*b"VP8 ", frame)]
+                }
+            }
+        })
+    }
+}
+
+/// Converts the image ```data``` of color type ```color``` to rgba pixels
+fn to_rgba(data: &[u8], width: u32, height: u32, color: color::ColorType) -> io::Result<Vec<[u8; 4]>> {
+    let channels = match color {
+        color::ColorType::Gray(8) => 1,
+        color::ColorType::GrayA(8) => 2,
+        color::ColorType::RGB(8) => 3,
+        color::ColorType::RGBA(8) => 4,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       format!("Unsupported color type {:?}", color))),
+    };
+    let (w, h) = (width as usize, height as usize);
+    if data.len() < w * h * channels {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough image data"))
+    }
+
+    Ok(data[..w * h * channels].chunks(channels).map(|p| match channels {
+        1 => [p[0], p[0], p[0], 255],
+        2 => [p[0], p[0], p[0], p[1]],
+        3 => [p[0], p[1], p[2], 255],
+        _ => [p[0], p[1], p[2], p[3]],
+    }).collect())
+}
+
+/// Appends the chunk ```name``` holding ```data``` to ```out```, padded to an even size
+fn write_chunk(out: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(name);
+    out.write_u32::<LittleEndian>(data.len() as u32).unwrap();
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// Writes a RIFF container of a WebP image holding ```chunks``` to ```w```
+fn write_riff<W: Write>(w: &mut W, chunks: &[([u8; 4], Vec<u8>)]) -> io::Result<()> {
+    let mut data = b"WEBP".to_vec();
+    for &(ref name, ref chunk) in chunks {
+        write_chunk(&mut data, name, chunk);
+    }
+    try!(w.write_all(b"RIFF"));
+    try!(w.write_u32::<LittleEndian>(data.len() as u32));
+    w.write_all(&data)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl<W: Write> ImageEncoder for WebpEncoder<W> {
+    fn write_image(self, buf: &[u8], width: u32, height: u32, color: color::ColorType) -> ImageResult<()> {
+        self.encode(buf, width, height, color).map_err(From::from)
+    }
+}
+
+/// Converts rgba pixels to the limited range YCbCr of VP8, averaging the chroma of each 2x2 block
+fn rgb_to_yuv420(rgba: &[[u8; 4]], width: usize, height: usize) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+    let y = rgba.iter().map(|p| {
+        ((16839 * p[0] as i32 + 33059 * p[1] as i32 + 6420 * p[2] as i32 + (16 << 16) + (1 << 15)) >> 16) as u8
+    }).collect();
+
+    let (cw, ch) = ((width + 1) / 2, (height + 1) / 2);
+    let mut u = Vec::with_capacity(cw * ch);
+    let mut v = Vec::with_capacity(cw * ch);
+    for cy in 0..ch {
+        for cx in 0..cw {
+            let (mut r, mut g, mut b, mut n) = (0, 0, 0, 0);
+            for y in 2 * cy..height.min(2 * cy + 2) {
+                for x in 2 * cx..width.min(2 * cx + 2) {
+                    let p = rgba[y * width + x];
+                    r += p[0] as i32;
+                    g += p[1] as i32;
+                    b += p[2] as i32;
+                    n += 1;
+                }
+            }
+            let (r, g, b) = ((r + n / 2) / n, (g + n / 2) / n, (b + n / 2) / n);
+            u.push(((-9719 * r - 19081 * g + 28800 * b + (128 << 16) + (1 << 15)) >> 16) as u8);
+            v.push(((28800 * r - 24116 * g - 4684 * b + (128 << 16) + (1 << 15)) >> 16) as u8);
+        }
+    }
+
+    (y, u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{WebpEncoder, WebpQuality};
+    use super::super::WebpDecoder;
+    use animation::{Blend, Delay, Disposal, Frame, LoopCount};
+    use buffer::RgbaImage;
+    use color::{ColorType, Rgba};
+    use image::{DecodingResult, ImageDecoder};
+    use metadata::exif::Exif;
+
+    fn encode(data: &[u8], width: u32, height: u32, color: ColorType, quality: WebpQuality) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        WebpEncoder::new_with_quality(&mut encoded, quality).encode(data, width, height, color).unwrap();
+        encoded
+    }
+
+    fn decode(data: &[u8]) -> (ColorType, u32, u32, Vec<u8>) {
+        let mut decoder = WebpDecoder::new(data);
+        let color = decoder.colortype().unwrap();
+        let (width, height) = decoder.dimensions().unwrap();
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(data) => (color, width, height, data),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut img = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                img.extend_from_slice(&[(x * 6) as u8, (y * 8) as u8, (255 - x * 3 - y * 2) as u8, (x * y) as u8]);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn lossless_roundtrip() {
+        let img = gradient(37, 29);
+        let (color, width, height, decoded) = decode(&encode(&img, 37, 29, ColorType::RGBA(8), WebpQuality::Lossless));
+        assert_eq!((color, width, height), (ColorType::RGBA(8), 37, 29));
+        assert!(decoded == img);
+
+        // Repeated content is stored as backward references
+        let stripes: Vec<u8> = (0..64 * 64).map(|i| (i / 64 % 8 * 30) as u8).collect();
+        let encoded = encode(&stripes, 64, 64, ColorType::Gray(8), WebpQuality::Lossless);
+        assert!(encoded.len() < 200);
+        let (_, _, _, decoded) = decode(&encoded);
+        assert!(decoded.chunks(4).map(|p| p[0]).eq(stripes.iter().cloned()));
+    }
+
+    #[test]
+    fn lossless_white_after_subtract_green() {
+        // Subtracting green stores this opaque pixel with its red, green and blue all 0xFF
+        let img = [0xFE, 0xFF, 0xFE, 0xFF].repeat(4);
+        let (_, _, _, decoded) = decode(&encode(&img, 2, 2, ColorType::RGBA(8), WebpQuality::Lossless));
+        assert_eq!(decoded, img);
+    }
+
+    #[test]
+    fn lossy_roundtrip() {
+        let img = gradient(37, 29);
+        let rgb: Vec<u8> = img.chunks(4).flat_map(|p| p[..3].to_vec()).collect();
+
+        for &quality in &[10, 75, 100] {
+            let (color, width, height, decoded) = decode(&encode(&rgb, 37, 29, ColorType::RGB(8), WebpQuality::Lossy(quality)));
+            assert_eq!((color, width, height), (ColorType::RGB(8), 37, 29));
+
+            let error: u32 = rgb.iter().zip(&decoded).map(|(&a, &b)| {
+                (a as i32 - b as i32).abs() as u32
+            }).sum();
+            let limit = if quality == 10 { 12 } else { 3 };
+            assert!(error / (decoded.len() as u32) < limit, "quality {}: mean error {}", quality, error / decoded.len() as u32);
+        }
+
+        // Alpha is kept exactly
+        let (color, _, _, decoded) = decode(&encode(&img, 37, 29, ColorType::RGBA(8), WebpQuality::Lossy(75)));
+        assert_eq!(color, ColorType::RGBA(8));
+        assert!(decoded.chunks(4).zip(img.chunks(4)).all(|(a, b)| a[3] == b[3]));
+    }
+
+    #[test]
+    fn animation_roundtrip() {
+        let mut second = Frame::from_parts(RgbaImage::from_pixel(2, 1, Rgba([0, 0, 255, 128])), 2, 2,
+                                           Delay::from_numer_denom_ms(250, 1));
+        second.set_blend(Blend::Over);
+        second.set_disposal(Disposal::Background);
+        let frames = vec![
+            Frame::from_parts(RgbaImage::from_pixel(6, 4, Rgba([255, 0, 0, 255])), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            second,
+        ];
+
+        let mut encoded = Vec::new();
+        WebpEncoder::new(&mut encoded).encode_frames(frames.clone(), LoopCount::Finite(3)).unwrap();
+        let mut decoder = WebpDecoder::new(&encoded[..]);
+        assert!(decoder.is_animated().unwrap());
+        assert_eq!(decoder.dimensions().unwrap(), (6, 4));
+        assert_eq!(decoder.loop_count().unwrap(), LoopCount::Finite(3));
+        let decoded: Vec<_> = decoder.into_frames().unwrap().collect();
+        assert_eq!(decoded.len(), 2);
+        for (original, decoded) in frames.into_iter().zip(decoded) {
+            assert_eq!((original.left(), original.top()), (decoded.left(), decoded.top()));
+            assert_eq!(original.delay(), decoded.delay());
+            assert_eq!((original.disposal(), original.blend()), (decoded.disposal(), decoded.blend()));
+            assert_eq!(original.into_buffer().into_raw(), decoded.into_buffer().into_raw());
+        }
+
+        let encode = |frame: Frame| WebpEncoder::new(Vec::new()).encode_frames(vec![frame], LoopCount::Infinite);
+        assert!(encode(Frame::from_parts(RgbaImage::new(2, 2), 1, 0, Delay::from_numer_denom_ms(0, 1))).is_err());
+        let mut previous = Frame::new(RgbaImage::new(2, 2));
+        previous.set_disposal(Disposal::Previous);
+        assert!(encode(previous).is_err());
+        assert!(WebpEncoder::new(Vec::new()).encode_frames(vec![], LoopCount::Infinite).is_err());
+    }
+
+    #[test]
+    fn exif() {
+        let mut exif = Exif::new();
+        exif.set_orientation(3);
+        for &quality in &[WebpQuality::Lossless, WebpQuality::Lossy(80)] {
+            let mut encoded = Vec::new();
+            {
+                let mut encoder = WebpEncoder::new_with_quality(&mut encoded, quality);
+                encoder.set_exif(exif.to_bytes());
+                encoder.encode(&gradient(4, 4), 4, 4, ColorType::RGBA(8)).unwrap();
+            }
+            assert_eq!(WebpDecoder::new(&encoded[..]).exif().unwrap(), Some(exif.clone()));
+            let (color, width, height, _) = decode(&encoded);
+            assert_eq!((color, width, height), (ColorType::RGBA(8), 4, 4));
+        }
+        let plain = encode(&[1, 2, 3], 1, 1, ColorType::RGB(8), WebpQuality::Lossless);
+        assert_eq!(WebpDecoder::new(&plain[..]).exif().unwrap(), None);
+    }
+}
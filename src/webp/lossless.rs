@@ -0,0 +1,1081 @@
+//! Decoding and encoding of lossless WebP images
+//!
+//! The pixels of a VP8L stream are compressed with LZ77 backward references, a cache of recently
+//! used colors and prefix codes, after applying up to four reversible transforms.
+//!
+//! # Related Links
+//! * <https://developers.google.com/speed/webp/docs/webp_lossless_bitstream_specification>
+
+use image::{ImageError, ImageFormat, ImageResult};
+
+/// The signature byte starting a VP8L stream
+static SIGNATURE: u8 = 0x2F;
+
+// Transforms
+const PREDICTOR_TRANSFORM: u32 = 0;
+const COLOR_TRANSFORM: u32 = 1;
+const SUBTRACT_GREEN: u32 = 2;
+const COLOR_INDEXING_TRANSFORM: u32 = 3;
+
+/// The number of literal codes for the green channel before the length prefix codes
+const NUM_LITERAL_CODES: usize = 256;
+/// The number of prefix codes for backward reference lengths
+const NUM_LENGTH_CODES: usize = 24;
+/// The number of prefix codes for backward reference distances
+const NUM_DISTANCE_CODES: usize = 40;
+
+/// The order in which the code lengths of the code length code are stored
+static CODE_LENGTH_ORDER: [usize; 19] = [17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+/// The offsets (x, y) of the pixels the first 120 distance codes refer to
+static DISTANCE_MAP: [(i8, i8); 120] = [
+    (0, 1), (1, 0), (1, 1), (-1, 1), (0, 2), (2, 0), (1, 2), (-1, 2), (2, 1), (-2, 1), (2, 2), (-2, 2),
+    (0, 3), (3, 0), (1, 3), (-1, 3), (3, 1), (-3, 1), (2, 3), (-2, 3), (3, 2), (-3, 2), (0, 4), (4, 0),
+    (1, 4), (-1, 4), (4, 1), (-4, 1), (3, 3), (-3, 3), (2, 4), (-2, 4), (4, 2), (-4, 2), (0, 5), (3, 4),
+    (-3, 4), (4, 3), (-4, 3), (5, 0), (1, 5), (-1, 5), (5, 1), (-5, 1), (2, 5), (-2, 5), (5, 2), (-5, 2),
+    (4, 4), (-4, 4), (3, 5), (-3, 5), (5, 3), (-5, 3), (0, 6), (6, 0), (1, 6), (-1, 6), (6, 1), (-6, 1),
+    (2, 6), (-2, 6), (6, 2), (-6, 2), (4, 5), (-4, 5), (5, 4), (-5, 4), (3, 6), (-3, 6), (6, 3), (-6, 3),
+    (0, 7), (7, 0), (1, 7), (-1, 7), (5, 5), (-5, 5), (7, 1), (-7, 1), (4, 6), (-4, 6), (6, 4), (-6, 4),
+    (2, 7), (-2, 7), (7, 2), (-7, 2), (3, 7), (-3, 7), (7, 3), (-7, 3), (5, 6), (-5, 6), (6, 5), (-6, 5),
+    (8, 0), (4, 7), (-4, 7), (7, 4), (-7, 4), (8, 1), (8, 2), (6, 6), (-6, 6), (8, 3), (5, 7), (-5, 7),
+    (7, 5), (-7, 5), (8, 4), (6, 7), (-6, 7), (7, 6), (-7, 6), (8, 5), (7, 7), (-7, 7), (8, 6), (8, 7),
+];
+
+fn format_error(message: &str) -> ImageError {
+    ImageError::malformed(ImageFormat::WEBP, format!("VP8L: {}", message))
+}
+
+/// Decodes the VP8L stream ```data``` to rgba pixels
+///
+/// Returns the width and height of the image along with its pixels.
+pub fn decode(data: &[u8]) -> ImageResult<(u32, u32, Vec<u8>)> {
+    if data.first() != Some(&SIGNATURE) {
+        return Err(format_error("Invalid signature"))
+    }
+
+    let mut reader = BitReader::new(&data[1..]);
+    let width = try!(reader.read_bits(14)) as usize + 1;
+    let height = try!(reader.read_bits(14)) as usize + 1;
+    let _alpha_is_used = try!(reader.read_bits(1));
+    if try!(reader.read_bits(3)) != 0 {
+        return Err(format_error("Unknown version"))
+    }
+
+    let argb = try!(decode_image_stream(&mut reader, width, height));
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for pixel in argb {
+        rgba.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8, (pixel >> 24) as u8]);
+    }
+    Ok((width as u32, height as u32, rgba))
+}
+
+/// Reads the width and height of the VP8L stream ```data``` from its header
+pub fn dimensions(data: &[u8]) -> ImageResult<(u32, u32)> {
+    if data.first() != Some(&SIGNATURE) {
+        return Err(format_error("Invalid signature"))
+    }
+    let mut reader = BitReader::new(&data[1..]);
+    let width = try!(reader.read_bits(14)) + 1;
+    let height = try!(reader.read_bits(14)) + 1;
+    Ok((width, height))
+}
+
+/// Decodes a VP8L image stream of ```width``` x ```height``` without the header, as used for the
+/// alpha channel of lossy images
+///
+/// Returns the pixels in ARGB order.
+pub fn decode_headerless(data: &[u8], width: usize, height: usize) -> ImageResult<Vec<u32>> {
+    decode_image_stream(&mut BitReader::new(data), width, height)
+}
+
+/// A transform applied to the pixels before they were compressed
+enum Transform {
+    /// Each pixel was predicted from its neighbours by the mode of its block
+    Predictor { bits: u8, modes: Vec<u32> },
+    /// The red and blue channels were decorrelated from green and red by the factors of each block
+    Color { bits: u8, factors: Vec<u32> },
+    /// Green was subtracted from red and blue
+    SubtractGreen,
+    /// The pixels were replaced by indices into a palette, bundling several in one pixel
+    ColorIndexing { bits: u8, palette: Vec<u32> },
+}
+
+/// Decodes the main image of size ```width``` x ```height``` along with its transforms
+fn decode_image_stream(reader: &mut BitReader, width: usize, height: usize) -> ImageResult<Vec<u32>> {
+    let mut transforms = Vec::new();
+    let mut seen = [false; 4];
+    // Color indexing reduces the width of the coded image by bundling pixels
+    let mut coded_width = width;
+
+    while try!(reader.read_bits(1)) == 1 {
+        let kind = try!(reader.read_bits(2));
+        if seen[kind as usize] {
+            return Err(format_error("Repeated transform"))
+        }
+        seen[kind as usize] = true;
+
+        let transform = match kind {
+            PREDICTOR_TRANSFORM | COLOR_TRANSFORM => {
+                let bits = try!(reader.read_bits(3)) as u8 + 2;
+                let data = try!(decode_entropy_image(reader, subsample(coded_width, bits), subsample(height, bits)));
+                if kind == PREDICTOR_TRANSFORM {
+                    Transform::Predictor { bits: bits, modes: data }
+                } else {
+                    Transform::Color { bits: bits, factors: data }
+                }
+            }
+            SUBTRACT_GREEN => Transform::SubtractGreen,
+            COLOR_INDEXING_TRANSFORM => {
+                let size = try!(reader.read_bits(8)) as usize + 1;
+                let mut palette = try!(decode_entropy_image(reader, size, 1));
+                // The palette is stored as differences to the previous entry
+                for i in 1..size {
+                    palette[i] = add_pixels(palette[i], palette[i - 1]);
+                }
+                let bits = match size {
+                    0 ... 2 => 3,
+                    3 ... 4 => 2,
+                    5 ... 16 => 1,
+                    _ => 0,
+                };
+                coded_width = subsample(coded_width, bits);
+                Transform::ColorIndexing { bits: bits, palette: palette }
+            }
+            _ => unreachable!(),
+        };
+        transforms.push((transform, coded_width));
+    }
+
+    let mut pixels = try!(decode_spatial_image(reader, coded_width, height));
+
+    // The transforms are undone in reverse order, each on the width it was applied to
+    for i in (0..transforms.len()).rev() {
+        let width_before = if i == 0 { width } else { transforms[i - 1].1 };
+        pixels = match transforms[i].0 {
+            Transform::Predictor { bits, ref modes } => {
+                undo_prediction(&mut pixels, width_before, height, bits, modes);
+                pixels
+            }
+            Transform::Color { bits, ref factors } => {
+                undo_color_transform(&mut pixels, width_before, bits, factors);
+                pixels
+            }
+            Transform::SubtractGreen => {
+                for pixel in &mut pixels {
+                    let green = (*pixel >> 8) & 0xFF;
+                    *pixel = add_pixels(*pixel, green << 16 | green);
+                }
+                pixels
+            }
+            Transform::ColorIndexing { bits, ref palette } => {
+                expand_indices(&pixels, width_before, height, bits, palette)
+            }
+        };
+    }
+
+    Ok(pixels)
+}
+
+/// The size of ```size``` pixels in blocks of ```1 << bits```
+fn subsample(size: usize, bits: u8) -> usize {
+    (size + (1 << bits) - 1) >> bits
+}
+
+/// Adds each channel of two pixels modulo 256
+fn add_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = (a & 0xFF00FF00).wrapping_add(b & 0xFF00FF00) & 0xFF00FF00;
+    let red_blue = (a & 0x00FF00FF).wrapping_add(b & 0x00FF00FF) & 0x00FF00FF;
+    alpha_green | red_blue
+}
+
+/// Decodes an image storing transform data or the entropy image, which has no meta prefix codes
+fn decode_entropy_image(reader: &mut BitReader, width: usize, height: usize) -> ImageResult<Vec<u32>> {
+    let cache_bits = try!(read_color_cache_bits(reader));
+    let groups = vec![try!(read_prefix_code_group(reader, cache_bits))];
+    decode_pixels(reader, width, height, cache_bits, &groups, None)
+}
+
+/// Decodes the main image, which may use several groups of prefix codes
+fn decode_spatial_image(reader: &mut BitReader, width: usize, height: usize) -> ImageResult<Vec<u32>> {
+    let cache_bits = try!(read_color_cache_bits(reader));
+
+    let mut meta = None;
+    let mut num_groups = 1;
+    if try!(reader.read_bits(1)) == 1 {
+        let bits = try!(reader.read_bits(3)) as u8 + 2;
+        let xsize = subsample(width, bits);
+        let entropy = try!(decode_entropy_image(reader, xsize, subsample(height, bits)));
+        // The red and green channels hold the index of the group of each block
+        let entropy: Vec<usize> = entropy.iter().map(|&pixel| (pixel >> 8) as usize & 0xFFFF).collect();
+        num_groups = entropy.iter().cloned().max().unwrap_or(0) + 1;
+        meta = Some(MetaCodes { bits: bits, xsize: xsize, groups: entropy });
+    }
+
+    let mut groups = Vec::with_capacity(num_groups);
+    for _ in 0..num_groups {
+        groups.push(try!(read_prefix_code_group(reader, cache_bits)));
+    }
+    decode_pixels(reader, width, height, cache_bits, &groups, meta.as_ref())
+}
+
+/// The assignment of prefix code groups to the blocks of an image
+struct MetaCodes {
+    bits: u8,
+    xsize: usize,
+    groups: Vec<usize>,
+}
+
+fn read_color_cache_bits(reader: &mut BitReader) -> ImageResult<u8> {
+    if try!(reader.read_bits(1)) == 0 {
+        return Ok(0)
+    }
+    let bits = try!(reader.read_bits(4)) as u8;
+    if bits < 1 || bits > 11 {
+        return Err(format_error("Invalid color cache size"))
+    }
+    Ok(bits)
+}
+
+/// The prefix codes of green and the reference lengths, red, blue, alpha and the distances
+type CodeGroup = [HuffmanTree; 5];
+
+fn read_prefix_code_group(reader: &mut BitReader, cache_bits: u8) -> ImageResult<CodeGroup> {
+    let cache_size = if cache_bits > 0 { 1 << cache_bits } else { 0 };
+    Ok([
+        try!(read_prefix_code(reader, NUM_LITERAL_CODES + NUM_LENGTH_CODES + cache_size)),
+        try!(read_prefix_code(reader, 256)),
+        try!(read_prefix_code(reader, 256)),
+        try!(read_prefix_code(reader, 256)),
+        try!(read_prefix_code(reader, NUM_DISTANCE_CODES)),
+    ])
+}
+
+/// Reads a prefix code for an alphabet of ```alphabet_size``` symbols
+fn read_prefix_code(reader: &mut BitReader, alphabet_size: usize) -> ImageResult<HuffmanTree> {
+    let mut lengths = vec![0u8; alphabet_size];
+
+    if try!(reader.read_bits(1)) == 1 {
+        // A simple code of one or two symbols
+        let num_symbols = try!(reader.read_bits(1)) + 1;
+        let first_bits = if try!(reader.read_bits(1)) == 1 { 8 } else { 1 };
+        let first = try!(reader.read_bits(first_bits)) as usize;
+        if first >= alphabet_size {
+            return Err(format_error("Invalid symbol"))
+        }
+        lengths[first] = 1;
+        if num_symbols == 2 {
+            let second = try!(reader.read_bits(8)) as usize;
+            if second >= alphabet_size {
+                return Err(format_error("Invalid symbol"))
+            }
+            lengths[second] = 1;
+        }
+        return HuffmanTree::new(&lengths)
+    }
+
+    let mut length_lengths = [0u8; 19];
+    let num_lengths = try!(reader.read_bits(4)) as usize + 4;
+    for &i in &CODE_LENGTH_ORDER[..num_lengths] {
+        length_lengths[i] = try!(reader.read_bits(3)) as u8;
+    }
+    let length_code = try!(HuffmanTree::new(&length_lengths));
+
+    let mut max_symbol = if try!(reader.read_bits(1)) == 1 {
+        let bits = 2 + 2 * try!(reader.read_bits(3)) as u8;
+        let max_symbol = 2 + try!(reader.read_bits(bits)) as usize;
+        if max_symbol > alphabet_size {
+            return Err(format_error("Invalid number of code lengths"))
+        }
+        max_symbol
+    } else {
+        alphabet_size
+    };
+
+    let mut symbol = 0;
+    let mut previous = 8;
+    while symbol < alphabet_size {
+        if max_symbol == 0 {
+            break
+        }
+        max_symbol -= 1;
+
+        let code = try!(length_code.read_symbol(reader));
+        let (length, repeat) = match code {
+            0 ... 15 => {
+                lengths[symbol] = code as u8;
+                symbol += 1;
+                if code != 0 {
+                    previous = code as u8;
+                }
+                continue
+            }
+            16 => (previous, 3 + try!(reader.read_bits(2)) as usize),
+            17 => (0, 3 + try!(reader.read_bits(3)) as usize),
+            _ => (0, 11 + try!(reader.read_bits(7)) as usize),
+        };
+        if symbol + repeat > alphabet_size {
+            return Err(format_error("Code lengths exceed the alphabet"))
+        }
+        for length_slot in &mut lengths[symbol..symbol + repeat] {
+            *length_slot = length;
+        }
+        symbol += repeat;
+    }
+
+    HuffmanTree::new(&lengths)
+}
+
+/// Decodes the entropy coded pixels of an image of ```width``` x ```height```
+fn decode_pixels(reader: &mut BitReader,
+                 width: usize,
+                 height: usize,
+                 cache_bits: u8,
+                 groups: &[CodeGroup],
+                 meta: Option<&MetaCodes>) -> ImageResult<Vec<u32>> {
+
+    let total = width * height;
+    let mut pixels = Vec::with_capacity(total);
+    let mut cache = if cache_bits > 0 { vec![0u32; 1 << cache_bits] } else { Vec::new() };
+    // The pixels up to which the color cache is up to date
+    let mut cached = 0;
+
+    while pixels.len() < total {
+        let group = match meta {
+            Some(meta) => {
+                let (x, y) = (pixels.len() % width, pixels.len() / width);
+                &groups[meta.groups[(y >> meta.bits) * meta.xsize + (x >> meta.bits)]]
+            }
+            None => &groups[0],
+        };
+
+        let green = try!(group[0].read_symbol(reader)) as usize;
+        if green < NUM_LITERAL_CODES {
+            let red = try!(group[1].read_symbol(reader)) as u32;
+            let blue = try!(group[2].read_symbol(reader)) as u32;
+            let alpha = try!(group[3].read_symbol(reader)) as u32;
+            pixels.push(alpha << 24 | red << 16 | (green as u32) << 8 | blue);
+        } else if green < NUM_LITERAL_CODES + NUM_LENGTH_CODES {
+            let length = try!(read_lz77_value(reader, (green - NUM_LITERAL_CODES) as u16));
+            let distance_code = try!(group[4].read_symbol(reader));
+            let distance_code = try!(read_lz77_value(reader, distance_code));
+            let distance = plane_distance(width, distance_code);
+
+            if distance > pixels.len() || pixels.len() + length > total {
+                return Err(format_error("Invalid backward reference"))
+            }
+            // The copy may overlap the pixels it produces
+            let start = pixels.len() - distance;
+            for i in 0..length {
+                let pixel = pixels[start + i];
+                pixels.push(pixel);
+            }
+        } else {
+            let index = green - NUM_LITERAL_CODES - NUM_LENGTH_CODES;
+            if index >= cache.len() {
+                return Err(format_error("Invalid color cache index"))
+            }
+            pixels.push(cache[index]);
+        }
+
+        // Every decoded pixel enters the cache, including those of backward references
+        if cache_bits > 0 {
+            while cached < pixels.len() {
+                insert_into_cache(&mut cache, cache_bits, pixels[cached]);
+                cached += 1;
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn insert_into_cache(cache: &mut [u32], bits: u8, pixel: u32) {
+    let index = pixel.wrapping_mul(0x1E35A7BD) >> (32 - bits);
+    cache[index as usize] = pixel;
+}
+
+/// Reads a backward reference length or distance of the prefix code ```prefix```
+fn read_lz77_value(reader: &mut BitReader, prefix: u16) -> ImageResult<usize> {
+    if prefix < 4 {
+        return Ok(prefix as usize + 1)
+    }
+    let extra_bits = (prefix as u8 - 2) >> 1;
+    let offset = (2 + (prefix as usize & 1)) << extra_bits;
+    Ok(offset + try!(reader.read_bits(extra_bits)) as usize + 1)
+}
+
+/// Converts a distance code to the distance in pixels in an image ```width``` pixels wide
+fn plane_distance(width: usize, code: usize) -> usize {
+    if code > DISTANCE_MAP.len() {
+        return code - DISTANCE_MAP.len()
+    }
+    let (x, y) = DISTANCE_MAP[code - 1];
+    let distance = y as isize * width as isize + x as isize;
+    if distance < 1 { 1 } else { distance as usize }
+}
+
+/// Undoes the predictor transform, adding the prediction to the residual of each pixel
+fn undo_prediction(pixels: &mut [u32], width: usize, height: usize, bits: u8, modes: &[u32]) {
+    let xsize = subsample(width, bits);
+    for y in 0..height {
+        for x in 0..width {
+            let mode = (modes[(y >> bits) * xsize + (x >> bits)] >> 8) & 0xF;
+            let pos = y * width + x;
+            pixels[pos] = add_pixels(pixels[pos], prediction(pixels, width, x, y, mode));
+        }
+    }
+}
+
+/// Predicts the pixel at (```x```, ```y```) from the pixels before it by ```mode```
+///
+/// The pixels of the first row and column are always predicted from their left and top neighbour.
+fn prediction(pixels: &[u32], width: usize, x: usize, y: usize, mode: u32) -> u32 {
+    let pos = y * width + x;
+    if y == 0 {
+        if x == 0 { 0xFF000000 } else { pixels[pos - 1] }
+    } else if x == 0 {
+        pixels[pos - width]
+    } else {
+        let left = pixels[pos - 1];
+        let top = pixels[pos - width];
+        let top_left = pixels[pos - width - 1];
+        // The rightmost pixels use the leftmost pixel of the current row as top right
+        let top_right = pixels[pos - width + 1];
+        predict(mode, left, top, top_left, top_right)
+    }
+}
+
+fn predict(mode: u32, left: u32, top: u32, top_left: u32, top_right: u32) -> u32 {
+    match mode {
+        0 => 0xFF000000,
+        1 => left,
+        2 => top,
+        3 => top_right,
+        4 => top_left,
+        5 => average2(average2(left, top_right), top),
+        6 => average2(left, top_left),
+        7 => average2(left, top),
+        8 => average2(top_left, top),
+        9 => average2(top, top_right),
+        10 => average2(average2(left, top_left), average2(top, top_right)),
+        11 => select(left, top, top_left),
+        12 => map_channels3(left, top, top_left, |l, t, tl| clamp(l + t - tl)),
+        13 => map_channels2(average2(left, top), top_left, |a, tl| clamp(a + (a - tl) / 2)),
+        // The remaining modes are not defined, libwebp predicts black for them
+        _ => 0xFF000000,
+    }
+}
+
+fn channel(pixel: u32, c: u32) -> i32 {
+    ((pixel >> (8 * c)) & 0xFF) as i32
+}
+
+fn clamp(value: i32) -> i32 {
+    if value < 0 { 0 } else if value > 255 { 255 } else { value }
+}
+
+fn map_channels2<F: Fn(i32, i32) -> i32>(a: u32, b: u32, f: F) -> u32 {
+    (0..4).fold(0, |pixel, c| pixel | ((f(channel(a, c), channel(b, c)) as u32 & 0xFF) << (8 * c)))
+}
+
+fn map_channels3<F: Fn(i32, i32, i32) -> i32>(a: u32, b: u32, d: u32, f: F) -> u32 {
+    (0..4).fold(0, |pixel, c| pixel | ((f(channel(a, c), channel(b, c), channel(d, c)) as u32 & 0xFF) << (8 * c)))
+}
+
+fn average2(a: u32, b: u32) -> u32 {
+    map_channels2(a, b, |a, b| (a + b) / 2)
+}
+
+/// Chooses the left or top pixel, whichever is closer to the gradient estimate
+fn select(left: u32, top: u32, top_left: u32) -> u32 {
+    let mut distance_left = 0;
+    let mut distance_top = 0;
+    for c in 0..4 {
+        let estimate = channel(left, c) + channel(top, c) - channel(top_left, c);
+        distance_left += (estimate - channel(left, c)).abs();
+        distance_top += (estimate - channel(top, c)).abs();
+    }
+    if distance_left < distance_top { left } else { top }
+}
+
+/// Undoes the color transform, adding back the red and blue parts derived from the other channels
+fn undo_color_transform(pixels: &mut [u32], width: usize, bits: u8, factors: &[u32]) {
+    let xsize = subsample(width, bits);
+    let delta = |factor: u32, value: u32| ((factor as u8 as i8 as i32) * (value as u8 as i8 as i32)) >> 5;
+
+    for (pos, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (pos % width, pos / width);
+        let factor = factors[(y >> bits) * xsize + (x >> bits)];
+        let (green_to_red, green_to_blue, red_to_blue) = (factor, factor >> 8, factor >> 16);
+
+        let green = (*pixel >> 8) & 0xFF;
+        let red = ((((*pixel >> 16) & 0xFF) as i32 + delta(green_to_red, green)) & 0xFF) as u32;
+        let blue = (*pixel & 0xFF) as i32 + delta(green_to_blue, green) + delta(red_to_blue, red);
+        *pixel = (*pixel & 0xFF00FF00) | red << 16 | (blue & 0xFF) as u32;
+    }
+}
+
+/// Replaces the palette indices bundled in the green channel of ```pixels``` by their colors
+fn expand_indices(pixels: &[u32], width: usize, height: usize, bits: u8, palette: &[u32]) -> Vec<u32> {
+    let xsize = subsample(width, bits);
+    let bits_per_index = 8 >> bits;
+    let mask = (1 << bits_per_index) - 1;
+
+    let mut expanded = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let packed = (pixels[y * xsize + (x >> bits)] >> 8) & 0xFF;
+            let index = (packed >> ((x & ((1 << bits) - 1)) * bits_per_index)) & mask;
+            // Indices beyond the palette are transparent black
+            expanded.push(palette.get(index as usize).cloned().unwrap_or(0));
+        }
+    }
+    expanded
+}
+
+/// A canonical prefix code, decoded bit by bit
+struct HuffmanTree {
+    /// The number of codes of each length
+    counts: [u16; 16],
+    /// The symbols ordered by their codes
+    symbols: Vec<u16>,
+    /// The symbol of a code with a single symbol, which takes no bits
+    single: Option<u16>,
+}
+
+impl HuffmanTree {
+    fn new(lengths: &[u8]) -> ImageResult<HuffmanTree> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = Vec::new();
+        for length in 1..16 {
+            for (symbol, &l) in lengths.iter().enumerate() {
+                if l as usize == length {
+                    symbols.push(symbol as u16);
+                }
+            }
+        }
+
+        let single = match symbols.len() {
+            0 => return Err(format_error("Empty prefix code")),
+            1 => Some(symbols[0]),
+            _ => {
+                // The code has to be complete
+                let mut left = 1i32;
+                for length in 1..16 {
+                    left = 2 * left - counts[length] as i32;
+                    if left < 0 {
+                        return Err(format_error("Oversubscribed prefix code"))
+                    }
+                }
+                if left != 0 {
+                    return Err(format_error("Incomplete prefix code"))
+                }
+                None
+            }
+        };
+
+        Ok(HuffmanTree {
+            counts: counts,
+            symbols: symbols,
+            single: single,
+        })
+    }
+
+    fn read_symbol(&self, reader: &mut BitReader) -> ImageResult<u16> {
+        if let Some(symbol) = self.single {
+            return Ok(symbol)
+        }
+
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for length in 1..16 {
+            code |= try!(reader.read_bits(1)) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize])
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(format_error("Invalid prefix code"))
+    }
+}
+
+/// Reads the bits of a VP8L stream, least significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buffer: u64,
+    nbits: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data: data,
+            pos: 0,
+            buffer: 0,
+            nbits: 0,
+        }
+    }
+
+    fn read_bits(&mut self, count: u8) -> ImageResult<u32> {
+        while self.nbits < count {
+            let byte = match self.data.get(self.pos) {
+                Some(&byte) => byte,
+                None => return Err(ImageError::truncated(ImageFormat::WEBP)),
+            };
+            self.buffer |= (byte as u64) << self.nbits;
+            self.nbits += 8;
+            self.pos += 1;
+        }
+        let value = (self.buffer & ((1 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.nbits -= count;
+        Ok(value)
+    }
+}
+
+/// The block size of the predictor transform applied when encoding, as a power of two
+const PREDICTOR_BITS: u8 = 4;
+/// The shortest backward reference worth encoding
+const MIN_LENGTH: usize = 3;
+/// The longest backward reference the length prefix codes can express
+const MAX_LENGTH: usize = 4096;
+/// The farthest backward reference the distance prefix codes can express
+const MAX_DISTANCE: usize = (1 << 20) - 120;
+/// The number of earlier positions compared when looking for backward references
+const MAX_CANDIDATES: usize = 16;
+
+/// Encodes the argb ```pixels``` of an image of ```width``` x ```height``` as a VP8L stream
+///
+/// ```alpha_is_used``` is a hint for decoders that the image is not fully opaque.
+pub fn encode(pixels: &[u32], width: usize, height: usize, alpha_is_used: bool) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    writer.write_bits(SIGNATURE as u32, 8);
+    writer.write_bits(width as u32 - 1, 14);
+    writer.write_bits(height as u32 - 1, 14);
+    writer.write_bits(alpha_is_used as u32, 1);
+    writer.write_bits(0, 3);
+    encode_image_stream(&mut writer, pixels, width, height);
+    writer.finish()
+}
+
+/// Encodes the argb ```pixels``` of an image of ```width``` x ```height``` as a VP8L image stream
+/// without the header
+pub fn encode_headerless(pixels: &[u32], width: usize, height: usize) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+    encode_image_stream(&mut writer, pixels, width, height);
+    writer.finish()
+}
+
+/// Writes the main image after decorrelating its channels and predicting each pixel
+fn encode_image_stream(writer: &mut BitWriter, pixels: &[u32], width: usize, height: usize) {
+    // Subtract green
+    writer.write_bits(1, 1);
+    writer.write_bits(SUBTRACT_GREEN, 2);
+    let pixels: Vec<u32> = pixels.iter().map(|&pixel| {
+        let green = (pixel >> 8) & 0xFF;
+        (pixel & 0xFF00FF00)
+            | (((pixel >> 16).wrapping_sub(green)) & 0xFF) << 16
+            | (pixel.wrapping_sub(green) & 0xFF)
+    }).collect();
+
+    // Predictor
+    writer.write_bits(1, 1);
+    writer.write_bits(PREDICTOR_TRANSFORM, 2);
+    writer.write_bits((PREDICTOR_BITS - 2) as u32, 3);
+    let (modes, residuals) = predict_pixels(&pixels, width, height, PREDICTOR_BITS);
+    write_entropy_coded(writer, &modes, subsample(width, PREDICTOR_BITS), false);
+
+    writer.write_bits(0, 1);
+    write_entropy_coded(writer, &residuals, width, true);
+}
+
+/// Subtracts each channel of ```b``` from ```a``` modulo 256
+fn sub_pixels(a: u32, b: u32) -> u32 {
+    let alpha_green = (a | 0x00FF00FF).wrapping_sub(b & 0xFF00FF00) & 0xFF00FF00;
+    let red_blue = (a | 0xFF00FF00).wrapping_sub(b & 0x00FF00FF) & 0x00FF00FF;
+    alpha_green | red_blue
+}
+
+/// Chooses the prediction mode of each block leaving the smallest residuals
+///
+/// Returns the image of the modes and the residuals of all pixels.
+fn predict_pixels(pixels: &[u32], width: usize, height: usize, bits: u8) -> (Vec<u32>, Vec<u32>) {
+    let xsize = subsample(width, bits);
+    let ysize = subsample(height, bits);
+    let size = 1 << bits;
+
+    // The cost of a residual is its distance from zero in each channel
+    let cost = |residual: u32| -> u32 {
+        (0..4).map(|c| {
+            let value = (residual >> (8 * c)) & 0xFF;
+            if value < 128 { value } else { 256 - value }
+        }).sum()
+    };
+
+    let mut modes = Vec::with_capacity(xsize * ysize);
+    for by in 0..ysize {
+        for bx in 0..xsize {
+            let best = (0..14).min_by_key(|&mode| {
+                let mut total = 0;
+                for y in by * size..height.min((by + 1) * size) {
+                    for x in bx * size..width.min((bx + 1) * size) {
+                        let residual = sub_pixels(pixels[y * width + x], prediction(pixels, width, x, y, mode));
+                        total += cost(residual);
+                    }
+                }
+                total
+            }).unwrap();
+            modes.push(0xFF000000 | best << 8);
+        }
+    }
+
+    let mut residuals = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            let mode = (modes[(y >> bits) * xsize + (x >> bits)] >> 8) & 0xF;
+            residuals.push(sub_pixels(pixels[y * width + x], prediction(pixels, width, x, y, mode)));
+        }
+    }
+    (modes, residuals)
+}
+
+/// A pixel or a backward reference to earlier pixels
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u32),
+    Copy { length: usize, distance_code: usize },
+}
+
+/// Writes ```pixels``` with a single group of prefix codes and no color cache
+///
+/// Only the ```main``` image has the bit telling whether meta prefix codes are used.
+fn write_entropy_coded(writer: &mut BitWriter, pixels: &[u32], width: usize, main: bool) {
+    // No color cache
+    writer.write_bits(0, 1);
+    if main {
+        // No meta prefix codes
+        writer.write_bits(0, 1);
+    }
+
+    let symbols = find_references(pixels, width);
+
+    let mut histograms = [
+        vec![0u32; NUM_LITERAL_CODES + NUM_LENGTH_CODES],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; 256],
+        vec![0u32; NUM_DISTANCE_CODES],
+    ];
+    for symbol in &symbols {
+        match *symbol {
+            Symbol::Literal(pixel) => {
+                histograms[0][((pixel >> 8) & 0xFF) as usize] += 1;
+                histograms[1][((pixel >> 16) & 0xFF) as usize] += 1;
+                histograms[2][(pixel & 0xFF) as usize] += 1;
+                histograms[3][(pixel >> 24) as usize] += 1;
+            }
+            Symbol::Copy { length, distance_code } => {
+                histograms[0][NUM_LITERAL_CODES + prefix_encode(length).0 as usize] += 1;
+                histograms[4][prefix_encode(distance_code).0 as usize] += 1;
+            }
+        }
+    }
+
+    let codes: Vec<PrefixCode> = histograms.iter().map(|histogram| {
+        let code = PrefixCode::new(histogram, 15);
+        code.write(writer);
+        code
+    }).collect();
+
+    for symbol in &symbols {
+        match *symbol {
+            Symbol::Literal(pixel) => {
+                codes[0].write_symbol(writer, ((pixel >> 8) & 0xFF) as usize);
+                codes[1].write_symbol(writer, ((pixel >> 16) & 0xFF) as usize);
+                codes[2].write_symbol(writer, (pixel & 0xFF) as usize);
+                codes[3].write_symbol(writer, (pixel >> 24) as usize);
+            }
+            Symbol::Copy { length, distance_code } => {
+                let (prefix, extra_bits, extra) = prefix_encode(length);
+                codes[0].write_symbol(writer, NUM_LITERAL_CODES + prefix as usize);
+                writer.write_bits(extra, extra_bits);
+                let (prefix, extra_bits, extra) = prefix_encode(distance_code);
+                codes[4].write_symbol(writer, prefix as usize);
+                writer.write_bits(extra, extra_bits);
+            }
+        }
+    }
+}
+
+/// Replaces repeated runs of pixels by backward references, greedily taking the longest match
+/// among the recent positions starting with the same two pixels
+fn find_references(pixels: &[u32], width: usize) -> Vec<Symbol> {
+    use std::collections::HashMap;
+
+    let mut symbols = Vec::new();
+    let mut recent: HashMap<(u32, u32), Vec<usize>> = HashMap::new();
+    let insert = |recent: &mut HashMap<(u32, u32), Vec<usize>>, pos: usize| {
+        if pos + 1 < pixels.len() {
+            let positions = recent.entry((pixels[pos], pixels[pos + 1])).or_insert_with(Vec::new);
+            if positions.len() == MAX_CANDIDATES {
+                positions.remove(0);
+            }
+            positions.push(pos);
+        }
+    };
+
+    let mut pos = 0;
+    while pos < pixels.len() {
+        let mut best = (0, 0);
+        if pos + MIN_LENGTH <= pixels.len() {
+            if let Some(candidates) = recent.get(&(pixels[pos], pixels[pos + 1])) {
+                for &start in candidates.iter().rev().filter(|&&start| pos - start <= MAX_DISTANCE) {
+                    let max = MAX_LENGTH.min(pixels.len() - pos);
+                    let length = (0..max).take_while(|&i| pixels[start + i] == pixels[pos + i]).count();
+                    if length > best.0 {
+                        best = (length, pos - start);
+                    }
+                }
+            }
+        }
+
+        if best.0 >= MIN_LENGTH {
+            symbols.push(Symbol::Copy { length: best.0, distance_code: distance_code(width, best.1) });
+            for i in pos..pos + best.0 {
+                insert(&mut recent, i);
+            }
+            pos += best.0;
+        } else {
+            symbols.push(Symbol::Literal(pixels[pos]));
+            insert(&mut recent, pos);
+            pos += 1;
+        }
+    }
+    symbols
+}
+
+/// Converts a distance in pixels to its distance code, preferring the short codes of nearby pixels
+fn distance_code(width: usize, distance: usize) -> usize {
+    DISTANCE_MAP.iter()
+        .position(|&(x, y)| y as isize * width as isize + x as isize == distance as isize)
+        .map_or(distance + DISTANCE_MAP.len(), |i| i + 1)
+}
+
+/// Splits a backward reference length or distance code into its prefix code and extra bits
+///
+/// Returns the prefix code, the number of extra bits and their value.
+fn prefix_encode(value: usize) -> (u16, u8, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value as u16, 0, 0)
+    }
+    let highest = 63 - (value as u64).leading_zeros() as u8;
+    let second = (value >> (highest - 1)) & 1;
+    let extra_bits = highest - 1;
+    (2 * highest as u16 + second as u16, extra_bits, (value & ((1 << extra_bits) - 1)) as u32)
+}
+
+/// A canonical prefix code for writing symbols
+struct PrefixCode {
+    lengths: Vec<u8>,
+    codes: Vec<u16>,
+    /// Whether only a single symbol is used, which takes no bits
+    single: bool,
+}
+
+impl PrefixCode {
+    /// Builds a code for the symbol counts ```histogram```, with codes of at most ```limit``` bits
+    fn new(histogram: &[u32], limit: u8) -> PrefixCode {
+        let mut lengths = code_lengths(histogram, limit);
+        let used = lengths.iter().filter(|&&length| length > 0).count();
+        if used == 0 {
+            // A code needs at least one symbol even if it is never written
+            lengths[0] = 1;
+        }
+
+        let mut counts = [0u16; 16];
+        for &length in &lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut next = [0u16; 16];
+        for length in 1..16 {
+            next[length] = (next[length - 1] + counts[length - 1]) << 1;
+        }
+        let codes = lengths.iter().map(|&length| {
+            let code = next[length as usize];
+            next[length as usize] += 1;
+            code
+        }).collect();
+
+        PrefixCode {
+            lengths: lengths,
+            codes: codes,
+            single: used <= 1,
+        }
+    }
+
+    fn write_symbol(&self, writer: &mut BitWriter, symbol: usize) {
+        if self.single {
+            return
+        }
+        // Codes are read from their most significant bit on
+        let length = self.lengths[symbol];
+        let code = self.codes[symbol] as u32;
+        let reversed = (0..length).fold(0, |reversed, i| reversed << 1 | (code >> i) & 1);
+        writer.write_bits(reversed, length);
+    }
+
+    /// Writes the code lengths, as a simple code if possible
+    fn write(&self, writer: &mut BitWriter) {
+        let symbols: Vec<usize> = (0..self.lengths.len()).filter(|&i| self.lengths[i] > 0).collect();
+        if symbols.len() <= 2 && symbols.iter().all(|&symbol| symbol < 256) {
+            writer.write_bits(1, 1);
+            writer.write_bits(symbols.len() as u32 - 1, 1);
+            if symbols[0] < 2 {
+                writer.write_bits(0, 1);
+                writer.write_bits(symbols[0] as u32, 1);
+            } else {
+                writer.write_bits(1, 1);
+                writer.write_bits(symbols[0] as u32, 8);
+            }
+            if symbols.len() == 2 {
+                writer.write_bits(symbols[1] as u32, 8);
+            }
+            return
+        }
+
+        // The lengths as codes of the code length code, with runs of zeros as codes 17 and 18
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < self.lengths.len() {
+            let run = self.lengths[i..].iter().take_while(|&&length| length == 0).count();
+            if run >= 11 {
+                let run = run.min(138);
+                tokens.push((18, run as u32 - 11));
+                i += run;
+            } else if run >= 3 {
+                tokens.push((17, run as u32 - 3));
+                i += run;
+            } else {
+                tokens.push((self.lengths[i] as usize, 0));
+                i += 1;
+            }
+        }
+
+        let mut histogram = [0u32; 19];
+        for &(token, _) in &tokens {
+            histogram[token] += 1;
+        }
+        let length_code = PrefixCode::new(&histogram, 7);
+
+        let num_lengths = CODE_LENGTH_ORDER.iter()
+            .rposition(|&i| length_code.lengths[i] > 0)
+            .map_or(4, |last| (last + 1).max(4));
+        writer.write_bits(0, 1);
+        writer.write_bits(num_lengths as u32 - 4, 4);
+        for &i in &CODE_LENGTH_ORDER[..num_lengths] {
+            writer.write_bits(length_code.lengths[i] as u32, 3);
+        }
+
+        // All lengths are written
+        writer.write_bits(0, 1);
+        for &(token, extra) in &tokens {
+            length_code.write_symbol(writer, token);
+            match token {
+                17 => writer.write_bits(extra, 3),
+                18 => writer.write_bits(extra, 7),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Computes the lengths of a Huffman code for the symbol counts ```histogram```, limited to
+/// ```limit``` bits by flattening the counts until the code is short enough
+fn code_lengths(histogram: &[u32], limit: u8) -> Vec<u8> {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    let mut counts = histogram.to_vec();
+    loop {
+        let mut lengths = vec![0u8; counts.len()];
+        // Each node is a set of symbols, merging the two rarest ones deepens all their symbols
+        let mut heap: BinaryHeap<Reverse<(u32, usize)>> = BinaryHeap::new();
+        let mut sets: Vec<Vec<usize>> = Vec::new();
+        for (symbol, &count) in counts.iter().enumerate() {
+            if count > 0 {
+                heap.push(Reverse((count, sets.len())));
+                sets.push(vec![symbol]);
+            }
+        }
+        if sets.len() == 1 {
+            lengths[sets[0][0]] = 1;
+            return lengths
+        }
+
+        while heap.len() > 1 {
+            let Reverse((count_a, a)) = heap.pop().unwrap();
+            let Reverse((count_b, b)) = heap.pop().unwrap();
+            let mut merged = ::std::mem::replace(&mut sets[a], Vec::new());
+            merged.extend(::std::mem::replace(&mut sets[b], Vec::new()));
+            for &symbol in &merged {
+                lengths[symbol] += 1;
+            }
+            heap.push(Reverse((count_a + count_b, sets.len())));
+            sets.push(merged);
+        }
+
+        if lengths.iter().all(|&length| length <= limit) {
+            return lengths
+        }
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = (*count + 1) / 2;
+        }
+    }
+}
+
+/// Writes the bits of a VP8L stream, least significant first
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u64,
+    nbits: u8,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            data: Vec::new(),
+            buffer: 0,
+            nbits: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, count: u8) {
+        self.buffer |= (value as u64 & ((1 << count) - 1)) << self.nbits;
+        self.nbits += count;
+        while self.nbits >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.nbits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.nbits > 0 {
+            self.data.push(self.buffer as u8);
+        }
+        self.data
+    }
+}
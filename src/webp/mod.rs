@@ -1,8 +1,12 @@
-//! Decoding of Webp Images
+//! Decoding and Encoding of Webp Images
 
 pub use self::decoder::WebpDecoder as WebpDecoder;
+pub use self::encoder::{WebpEncoder, WebpQuality};
 
+mod alpha;
 mod decoder;
+mod encoder;
+mod lossless;
 mod transform;
 
 pub mod vp8;
\ No newline at end of file
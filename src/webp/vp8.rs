@@ -3,7 +3,8 @@
 //! This module contains a partial implementation of the
 //! VP8 video format as defined in RFC-6386.
 //!
-//! It decodes Keyframes only sans Loop Filtering.
+//! It decodes Keyframes only sans Loop Filtering,
+//! and encodes single Keyframes without Loop Filtering.
 //! VP8 is the underpinning of the Webp image format
 //!
 //! # Related Links
@@ -14,6 +15,7 @@
 
 use std::io;
 use std::io::Read;
+use std::io::Write;
 use std::default::Default;
 use byteorder::{ReadBytesExt, LittleEndian};
 
@@ -760,6 +762,13 @@ pub struct Frame {
     /// The luma plane of the frame
     pub ybuf: Vec<u8>,
 
+    /// The blue difference chroma plane of the frame, half the width and height of the luma plane
+    /// rounded up
+    pub ubuf: Vec<u8>,
+
+    /// The red difference chroma plane of the frame, the size of ```ubuf```
+    pub vbuf: Vec<u8>,
+
     /// Indicates whether this frame is a keyframe
     pub keyframe: bool,
 
@@ -829,6 +838,9 @@ pub struct VP8Decoder<R> {
 
     top_border: Vec<u8>,
     left_border: Vec<u8>,
+
+    top_border_uv: [Vec<u8>; 2],
+    left_border_uv: [Vec<u8>; 2],
 }
 
 impl<R: Read> VP8Decoder<R> {
@@ -873,6 +885,9 @@ impl<R: Read> VP8Decoder<R> {
 
             top_border: Vec::new(),
             left_border: Vec::new(),
+
+            top_border_uv: [Vec::new(), Vec::new()],
+            left_border_uv: [Vec::new(), Vec::new()],
         }
 }
 
@@ -932,14 +947,15 @@ impl<R: Read> VP8Decoder<R> {
 
         let n = if self.segments_enabled { MAX_SEGMENTS } else { 1 };
         for i in 0usize..n {
-            let base = if !self.segment[i].delta_values { self.segment[i].quantizer_level as i16 }
+            let base = if !self.segments_enabled { yac_abs as i16 }
+                    else if !self.segment[i].delta_values { self.segment[i].quantizer_level as i16 }
                     else { self.segment[i].quantizer_level as i16 + yac_abs as i16} as i32;
 
             self.segment[i].ydc  = DC_QUANT[clamp(base + ydc_delta, 0, 127) as usize];
             self.segment[i].yac  = AC_QUANT[clamp(base, 0, 127) as usize];
 
             self.segment[i].y2dc = DC_QUANT[clamp(base + y2dc_delta, 0, 127) as usize] * 2;
-            self.segment[i].y2ac = AC_QUANT[clamp(base + y2ac_delta, 0, 127) as usize] * 155 / 100;
+            self.segment[i].y2ac = (AC_QUANT[clamp(base + y2ac_delta, 0, 127) as usize] as i32 * 155 / 100) as i16;
 
             self.segment[i].uvdc = DC_QUANT[clamp(base + uvdc_delta, 0, 127) as usize];
             self.segment[i].uvac = AC_QUANT[clamp(base + uvac_delta, 0, 127) as usize];
@@ -1054,8 +1070,17 @@ impl<R: Read> VP8Decoder<R> {
             self.frame.ybuf = vec![0u8; self.frame.width as usize * 
                                         self.frame.height as usize];
 
+            let chroma_size = ((self.frame.width as usize + 1) / 2) * ((self.frame.height as usize + 1) / 2);
+            self.frame.ubuf = vec![0u8; chroma_size];
+            self.frame.vbuf = vec![0u8; chroma_size];
+
             self.top_border = vec![127u8; self.frame.width as usize + 4 + 16];
             self.left_border = vec![129u8; 1 + 16];
+
+            for plane in 0usize..2 {
+                self.top_border_uv[plane] = vec![127u8; self.mbwidth as usize * 8];
+                self.left_border_uv[plane] = vec![129u8; 1 + 8];
+            }
         }
 
         let mut buf = Vec::with_capacity(first_partition_size as usize);
@@ -1225,11 +1250,11 @@ impl<R: Read> VP8Decoder<R> {
 
         let ylength = if mby < self.mbheight as usize - 1 { 16usize }
                       else if self.frame.height % 16 == 0 { 16usize }
-                      else { (16 - (self.frame.height as usize & 15)) % 16 };
+                      else { self.frame.height as usize & 15 };
 
         let xlength = if mbx < self.mbwidth as usize - 1 { 16usize }
                       else if self.frame.width % 16 == 0 { 16usize }
-                      else { (16 - (self.frame.width as usize & 15)) % 16 };
+                      else { self.frame.width as usize & 15 };
 
         for y in 0usize..ylength {
             for x in 0usize..xlength {
@@ -1239,6 +1264,49 @@ impl<R: Read> VP8Decoder<R> {
         }
     }
 
+    fn intra_predict_chroma(&mut self, mbx: usize, mby: usize, mb: &MacroBlock, resdata: &[i32]) {
+        let stride = 1usize + 8;
+        let w = (self.frame.width as usize + 1) / 2;
+        let h = (self.frame.height as usize + 1) / 2;
+
+        // The blocks of the U plane follow the 16 luma blocks, those of the V plane the 4 of U
+        for plane in 0usize..2 {
+            let mut ws = create_chroma_border(
+                mbx, mby, &self.top_border_uv[plane], &self.left_border_uv[plane]);
+
+            match mb.chroma_mode {
+                V_PRED  => predict_vpred(&mut ws, 8, 1, 1, stride),
+                H_PRED  => predict_hpred(&mut ws, 8, 1, 1, stride),
+                TM_PRED => predict_tmpred(&mut ws, 8, 1, 1, stride),
+                DC_PRED => predict_dcpred(&mut ws, 8, stride, mby != 0, mbx != 0),
+                _       => panic!("unknown chroma intra prediction mode")
+            }
+
+            for y in 0usize..2 {
+                for x in 0usize..2 {
+                    let i  = 16 + plane * 4 + x + y * 2;
+                    let rb = &resdata[i * 16..i * 16 + 16];
+
+                    add_residue(&mut ws, rb, 1 + y * 4, 1 + x * 4, stride);
+                }
+            }
+
+            self.left_border_uv[plane][0] = ws[8];
+
+            for i in 0usize..8 {
+                self.top_border_uv[plane][mbx * 8 + i] = ws[8 * stride + 1 + i];
+                self.left_border_uv[plane][i + 1] = ws[(i + 1) * stride + 8];
+            }
+
+            let buf = if plane == 0 { &mut self.frame.ubuf } else { &mut self.frame.vbuf };
+            for y in 0usize..8.min(h - mby * 8) {
+                for x in 0usize..8.min(w - mbx * 8) {
+                    buf[(mby * 8 + y) * w + mbx * 8 + x] = ws[(1 + y) * stride + 1 + x];
+                }
+            }
+        }
+    }
+
     fn read_coefficients(&mut self,
                          block: &mut [i32],
                          p: usize,
@@ -1417,9 +1485,13 @@ impl<R: Read> VP8Decoder<R> {
                 }
 
                 self.intra_predict(mbx, mby, &mb, &blocks);
+                self.intra_predict_chroma(mbx, mby, &mb, &blocks);
             }
 
             self.left_border = vec![129u8; 1 + 16];
+            for plane in 0usize..2 {
+                self.left_border_uv[plane] = vec![129u8; 1 + 8];
+            }
         }
 
         Ok(&self.frame)
@@ -1498,6 +1570,32 @@ fn create_border(mbx: usize, mby: usize, mbw: usize, top: &[u8], left: &[u8]) ->
     ws
 }
 
+fn create_chroma_border(mbx: usize, mby: usize, top: &[u8], left: &[u8]) -> [u8; 81] {
+    let stride = 1usize + 8;
+    let mut ws = [0u8; (1 + 8) * (1 + 8)];
+
+    // A
+    for i in 0usize..8 {
+        ws[1 + i] = if mby == 0 { 127 } else { top[mbx * 8 + i] };
+    }
+
+    // L
+    for i in 0usize..8 {
+        ws[(i + 1) * stride] = if mbx == 0 { 129 } else { left[i + 1] };
+    }
+
+    // P
+    ws[0] = if mby == 0 {
+        127
+    } else if mbx == 0 {
+        129
+    } else {
+        left[0]
+    };
+
+    ws
+}
+
 fn avg3(left: u8, this: u8, right: u8) -> u8 {
     let avg = (left as u16 + 2 * this as u16 + right as u16 + 2) >> 2;
     avg as u8
@@ -1841,3 +1939,566 @@ fn predict_bhupred(a: &mut [u8], x0: usize, y0: usize, stride: usize) {
     a[(y0 + 3) * stride + x0 + 2] = l3;
     a[(y0 + 3) * stride + x0 + 3] = l3;
 }
+
+/// Section 7.3, the boolean entropy encoder
+struct BoolWriter {
+    buf: Vec<u8>,
+
+    range: u32,
+    bottom: u32,
+    bit_count: i32,
+}
+
+impl BoolWriter {
+    fn new() -> BoolWriter {
+        BoolWriter {buf: Vec::new(), range: 255, bottom: 0, bit_count: 24}
+    }
+
+    fn add_one_to_output(&mut self) {
+        let mut i = self.buf.len();
+        while i > 0 && self.buf[i - 1] == 255 {
+            self.buf[i - 1] = 0;
+            i -= 1;
+        }
+
+        if i > 0 {
+            self.buf[i - 1] += 1;
+        }
+    }
+
+    fn write_bool(&mut self, probability: u8, value: bool) {
+        let split = 1 + (((self.range - 1) * probability as u32) >> 8);
+
+        if value {
+            self.bottom = self.bottom.wrapping_add(split);
+            self.range -= split;
+        } else {
+            self.range = split;
+        }
+
+        while self.range < 128 {
+            self.range <<= 1;
+
+            if self.bottom & (1 << 31) != 0 {
+                self.add_one_to_output();
+            }
+
+            self.bottom <<= 1;
+            self.bit_count -= 1;
+
+            if self.bit_count == 0 {
+                self.buf.push((self.bottom >> 24) as u8);
+                self.bottom &= (1 << 24) - 1;
+                self.bit_count = 8;
+            }
+        }
+    }
+
+    fn write_literal(&mut self, value: u8, n: u8) {
+        for i in (0..n).rev() {
+            self.write_bool(128, (value >> i) & 1 == 1);
+        }
+    }
+
+    fn write_flag(&mut self, flag: bool) {
+        self.write_bool(128, flag);
+    }
+
+    fn write_with_tree(&mut self, tree: &[i8], probs: &[Prob], value: i8, start: isize) {
+        let mut path = Vec::new();
+        if !tree_path(tree, -value, start as usize, &mut path) {
+            panic!("value not in tree")
+        }
+
+        for (index, bit) in path {
+            self.write_bool(probs[index >> 1], bit);
+        }
+    }
+
+    fn flush(mut self) -> Vec<u8> {
+        let mut c = self.bit_count;
+        let mut v = self.bottom;
+
+        if v & (1 << (32 - c)) != 0 {
+            self.add_one_to_output();
+        }
+
+        v <<= c & 7;
+        c >>= 3;
+        while c > 0 {
+            v <<= 8;
+            c -= 1;
+        }
+
+        for _ in 0..4 {
+            self.buf.push((v >> 24) as u8);
+            v <<= 8;
+        }
+
+        self.buf
+    }
+}
+
+/// Finds the branches leading from the node ```index``` of ```tree``` to ```leaf```
+fn tree_path(tree: &[i8], leaf: i8, index: usize, path: &mut Vec<(usize, bool)>) -> bool {
+    for bit in 0usize..2 {
+        let next = tree[index + bit];
+        path.push((index, bit == 1));
+
+        if next <= 0 {
+            if next == leaf {
+                return true
+            }
+        } else if tree_path(tree, leaf, next as usize, path) {
+            return true
+        }
+
+        path.pop();
+    }
+
+    false
+}
+
+/// VP8 Encoder
+///
+/// Encodes a single keyframe without loop filtering. Each macroblock is predicted as a whole
+/// by the mode leaving the smallest residual.
+pub struct VP8Encoder<W> {
+    w: W,
+    quality: u8,
+}
+
+impl<W: Write> VP8Encoder<W> {
+    /// Create a new encoder writing a raw vp8 bitstream to ```w```.
+    /// The ```quality``` ranges from 1 to 100, higher values keep more detail.
+    pub fn new(w: W, quality: u8) -> VP8Encoder<W> {
+        VP8Encoder {
+            w: w,
+            quality: clamp(quality, 1, 100),
+        }
+    }
+
+    /// Encodes a frame of ```width``` x ```height``` pixels from its luma plane ```y``` and the
+    /// chroma planes ```u``` and ```v```, which are subsampled by two in both directions
+    pub fn encode_frame(&mut self, y: &[u8], u: &[u8], v: &[u8], width: u16, height: u16) -> io::Result<()> {
+        if width == 0 || height == 0 || width > 0x3FFF || height > 0x3FFF {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid VP8 frame size"))
+        }
+
+        let (w, h) = (width as usize, height as usize);
+        let (cw, ch) = ((w + 1) / 2, (h + 1) / 2);
+        if y.len() < w * h || u.len() < cw * ch || v.len() < cw * ch {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Not enough plane data"))
+        }
+
+        let mbw = (w + 15) / 16;
+        let mbh = (h + 15) / 16;
+        let qindex = ((100 - self.quality as usize) * 127 + 50) / 100;
+        let quant = Segment {
+            ydc: DC_QUANT[qindex],
+            yac: AC_QUANT[qindex],
+            y2dc: DC_QUANT[qindex] * 2,
+            y2ac: ::std::cmp::max((AC_QUANT[qindex] as i32 * 155 / 100) as i16, 8),
+            uvdc: ::std::cmp::min(DC_QUANT[qindex], 132),
+            uvac: AC_QUANT[qindex],
+            ..Default::default()
+        };
+
+        let mut planes = PlaneEncoder {
+            source: [
+                pad_plane(y, w, h, mbw * 16, mbh * 16),
+                pad_plane(u, cw, ch, mbw * 8, mbh * 8),
+                pad_plane(v, cw, ch, mbw * 8, mbh * 8),
+            ],
+            recon: [
+                vec![0u8; mbw * mbh * 256],
+                vec![0u8; mbw * mbh * 64],
+                vec![0u8; mbw * mbh * 64],
+            ],
+            strides: [mbw * 16, mbw * 8, mbw * 8],
+            quant: quant,
+            top: vec![[0u8; 9]; mbw],
+            left: [0u8; 9],
+        };
+
+        let mut header = BoolWriter::new();
+        let mut tokens = BoolWriter::new();
+
+        // Color space and clamping type
+        header.write_literal(0, 1);
+        header.write_literal(0, 1);
+        // No segmentation
+        header.write_flag(false);
+        // Simple filter type, filter level and sharpness, leaving the loop filter off
+        header.write_literal(0, 1);
+        header.write_literal(0, 6);
+        header.write_literal(0, 3);
+        // No loop filter adjustments
+        header.write_flag(false);
+        // A single token partition
+        header.write_literal(0, 2);
+        header.write_literal(qindex as u8, 7);
+        for _ in 0..5 {
+            header.write_flag(false);
+        }
+        // Refresh entropy probs
+        header.write_literal(0, 1);
+        // The default token probabilities are kept
+        for i in 0usize..4 {
+            for j in 0usize..8 {
+                for k in 0usize..3 {
+                    for t in 0usize..NUM_DCT_TOKENS - 1 {
+                        header.write_bool(COEFF_UPDATE_PROBS[i][j][k][t], false);
+                    }
+                }
+            }
+        }
+        // Macroblocks without coefficients are not marked
+        header.write_literal(0, 1);
+
+        for mby in 0..mbh {
+            planes.left = [0u8; 9];
+
+            for mbx in 0..mbw {
+                let (luma_mode, chroma_mode) = planes.encode_macroblock(mbx, mby, &mut tokens);
+
+                header.write_with_tree(&KEYFRAME_YMODE_TREE, &KEYFRAME_YMODE_PROBS, luma_mode, 0);
+                header.write_with_tree(&KEYFRAME_UV_MODE_TREE, &KEYFRAME_UV_MODE_PROBS, chroma_mode, 0);
+            }
+        }
+
+        let first_partition = header.flush();
+        let partition = tokens.flush();
+        if first_partition.len() >= 1 << 19 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Image is too large to encode"))
+        }
+
+        // Section 9.1, a keyframe intended for display
+        let tag = (1u32 << 4) | (first_partition.len() as u32) << 5;
+        try!(self.w.write_all(&[tag as u8, (tag >> 8) as u8, (tag >> 16) as u8]));
+        try!(self.w.write_all(&[0x9d, 0x01, 0x2a]));
+        try!(self.w.write_all(&[width as u8, (width >> 8) as u8, height as u8, (height >> 8) as u8]));
+        try!(self.w.write_all(&first_partition));
+        try!(self.w.write_all(&partition));
+
+        Ok(())
+    }
+}
+
+/// The planes of a frame being encoded along with their reconstruction as decoders will see it
+struct PlaneEncoder {
+    source: [Vec<u8>; 3],
+    recon: [Vec<u8>; 3],
+    strides: [usize; 3],
+    quant: Segment,
+
+    // Whether the blocks at the edges of the neighbouring macroblocks have coefficients, indexed
+    // like the complexity of a macroblock
+    top: Vec<[u8; 9]>,
+    left: [u8; 9],
+}
+
+impl PlaneEncoder {
+    /// Encodes the residual data of a macroblock, returning its luma and chroma modes
+    fn encode_macroblock(&mut self, mbx: usize, mby: usize, tokens: &mut BoolWriter) -> (i8, i8) {
+        let luma_mode = self.best_mode(&[0], 16, mbx, mby);
+        let chroma_mode = self.best_mode(&[1, 2], 8, mbx, mby);
+        let probs = &COEFF_PROBS;
+
+        // Luma, with the DC coefficients of all subblocks transformed into one more block
+        let stride = self.strides[0];
+        let prediction = predict_block(&self.recon[0], stride, mbx * 16, mby * 16, 16, luma_mode);
+        let mut blocks = [[0i32; 16]; 16];
+        let mut dc = [0i32; 16];
+        for (i, block) in blocks.iter_mut().enumerate() {
+            let (x0, y0) = (i % 4 * 4, i / 4 * 4);
+            for y in 0..4 {
+                for x in 0..4 {
+                    let source = self.source[0][(mby * 16 + y0 + y) * stride + mbx * 16 + x0 + x];
+                    block[y * 4 + x] = source as i32 - prediction[(y0 + y) * 16 + x0 + x] as i32;
+                }
+            }
+            forward_dct(block);
+            dc[i] = block[0];
+        }
+
+        forward_wht(&mut dc);
+        let mut y2 = quantize(&dc, self.quant.y2dc, self.quant.y2ac);
+        let complexity = self.top[mbx][0] + self.left[0];
+        let nz = write_coefficients(tokens, &probs[1], &y2, 0, complexity as usize);
+        self.top[mbx][0] = nz as u8;
+        self.left[0] = nz as u8;
+        dequantize(&mut y2, self.quant.y2dc, self.quant.y2ac);
+        transform::iwht4x4(&mut y2);
+
+        let mut residue = [[0i32; 16]; 16];
+        for (i, block) in blocks.iter().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let mut levels = quantize(block, self.quant.ydc, self.quant.yac);
+            levels[0] = 0;
+
+            let complexity = self.top[mbx][1 + x] + self.left[1 + y];
+            let nz = write_coefficients(tokens, &probs[0], &levels, 1, complexity as usize);
+            self.top[mbx][1 + x] = nz as u8;
+            self.left[1 + y] = nz as u8;
+
+            dequantize(&mut levels, self.quant.ydc, self.quant.yac);
+            levels[0] = y2[i];
+            transform::idct4x4(&mut levels);
+            residue[i] = levels;
+        }
+        self.reconstruct(0, 16, mbx, mby, &prediction, &residue);
+
+        // Chroma
+        for (plane, context) in [(1usize, 5usize), (2, 7)].iter().cloned() {
+            let stride = self.strides[plane];
+            let prediction = predict_block(&self.recon[plane], stride, mbx * 8, mby * 8, 8, chroma_mode);
+            let mut residue = [[0i32; 16]; 4];
+
+            for (i, block) in residue.iter_mut().enumerate() {
+                let (x, y) = (i % 2, i / 2);
+                for by in 0..4 {
+                    for bx in 0..4 {
+                        let source = self.source[plane][(mby * 8 + y * 4 + by) * stride + mbx * 8 + x * 4 + bx];
+                        block[by * 4 + bx] = source as i32 - prediction[(y * 4 + by) * 8 + x * 4 + bx] as i32;
+                    }
+                }
+                forward_dct(block);
+                let mut levels = quantize(block, self.quant.uvdc, self.quant.uvac);
+
+                let complexity = self.top[mbx][context + x] + self.left[context + y];
+                let nz = write_coefficients(tokens, &probs[2], &levels, 0, complexity as usize);
+                self.top[mbx][context + x] = nz as u8;
+                self.left[context + y] = nz as u8;
+
+                dequantize(&mut levels, self.quant.uvdc, self.quant.uvac);
+                transform::idct4x4(&mut levels);
+                *block = levels;
+            }
+            self.reconstruct(plane, 8, mbx, mby, &prediction, &residue);
+        }
+
+        (luma_mode, chroma_mode)
+    }
+
+    /// Chooses the prediction of a macroblock of ```size``` pixels closest to the ```planes```
+    fn best_mode(&self, planes: &[usize], size: usize, mbx: usize, mby: usize) -> i8 {
+        let modes = [DC_PRED, V_PRED, H_PRED, TM_PRED];
+        *modes.iter().min_by_key(|&&mode| {
+            planes.iter().map(|&plane| {
+                let stride = self.strides[plane];
+                let prediction = predict_block(&self.recon[plane], stride, mbx * size, mby * size, size, mode);
+                let mut error = 0u32;
+                for y in 0..size {
+                    for x in 0..size {
+                        let source = self.source[plane][(mby * size + y) * stride + mbx * size + x];
+                        error += (source as i32 - prediction[y * size + x] as i32).abs() as u32;
+                    }
+                }
+                error
+            }).sum::<u32>()
+        }).unwrap()
+    }
+
+    /// Adds the ```residue``` of the 4x4 subblocks to the ```prediction``` of a macroblock
+    fn reconstruct(&mut self, plane: usize, size: usize, mbx: usize, mby: usize, prediction: &[u8], residue: &[[i32; 16]]) {
+        let stride = self.strides[plane];
+        for y in 0..size {
+            for x in 0..size {
+                let block = &residue[y / 4 * (size / 4) + x / 4];
+                let value = prediction[y * size + x] as i32 + block[y % 4 * 4 + x % 4];
+                self.recon[plane][(mby * size + y) * stride + mbx * size + x] = clamp(value, 0, 255) as u8;
+            }
+        }
+    }
+}
+
+/// Extends a plane of ```width``` x ```height``` to ```padded_width``` x ```padded_height``` by
+/// repeating its last column and row
+fn pad_plane(plane: &[u8], width: usize, height: usize, padded_width: usize, padded_height: usize) -> Vec<u8> {
+    let mut padded = Vec::with_capacity(padded_width * padded_height);
+    for y in 0..padded_height {
+        let row = &plane[::std::cmp::min(y, height - 1) * width..][..width];
+        padded.extend_from_slice(row);
+        for _ in width..padded_width {
+            padded.push(row[width - 1]);
+        }
+    }
+    padded
+}
+
+/// Predicts the block of ```size``` x ```size``` pixels at (```x0```, ```y0```) of a plane by
+/// ```mode```, using 127 above and 129 left of the frame like decoders do
+fn predict_block(plane: &[u8], stride: usize, x0: usize, y0: usize, size: usize, mode: i8) -> Vec<u8> {
+    let above: Vec<u8> = (0..size).map(|i| if y0 == 0 { 127 } else { plane[(y0 - 1) * stride + x0 + i] }).collect();
+    let left: Vec<u8> = (0..size).map(|i| if x0 == 0 { 129 } else { plane[(y0 + i) * stride + x0 - 1] }).collect();
+    let top_left = if y0 == 0 { 127 } else if x0 == 0 { 129 } else { plane[(y0 - 1) * stride + x0 - 1] };
+
+    let mut block = vec![0u8; size * size];
+    match mode {
+        DC_PRED => {
+            let shift = if size == 16 { 4 } else { 3 };
+            let sum_above: u32 = above.iter().map(|&p| p as u32).sum();
+            let sum_left: u32 = left.iter().map(|&p| p as u32).sum();
+            let dc = match (y0 > 0, x0 > 0) {
+                (true, true) => (sum_above + sum_left + size as u32) >> (shift + 1),
+                (true, false) => (sum_above + size as u32 / 2) >> shift,
+                (false, true) => (sum_left + size as u32 / 2) >> shift,
+                (false, false) => 128,
+            };
+            for p in &mut block {
+                *p = dc as u8;
+            }
+        }
+        _ => for y in 0..size {
+            for x in 0..size {
+                block[y * size + x] = match mode {
+                    V_PRED => above[x],
+                    H_PRED => left[y],
+                    _ => clamp(left[y] as i32 + above[x] as i32 - top_left as i32, 0, 255) as u8,
+                };
+            }
+        },
+    }
+    block
+}
+
+/// Section 14.3, the inverse of the transform decoders apply to the 4x4 subblocks
+fn forward_dct(block: &mut [i32; 16]) {
+    let mut temp = [0i32; 16];
+    for i in 0usize..4 {
+        let row = &block[i * 4..i * 4 + 4];
+        let a1 = (row[0] + row[3]) * 8;
+        let b1 = (row[1] + row[2]) * 8;
+        let c1 = (row[1] - row[2]) * 8;
+        let d1 = (row[0] - row[3]) * 8;
+
+        temp[i * 4] = a1 + b1;
+        temp[i * 4 + 2] = a1 - b1;
+        temp[i * 4 + 1] = (c1 * 2217 + d1 * 5352 + 14500) >> 12;
+        temp[i * 4 + 3] = (d1 * 2217 - c1 * 5352 + 7500) >> 12;
+    }
+
+    for i in 0usize..4 {
+        let a1 = temp[i] + temp[12 + i];
+        let b1 = temp[4 + i] + temp[8 + i];
+        let c1 = temp[4 + i] - temp[8 + i];
+        let d1 = temp[i] - temp[12 + i];
+
+        block[i] = (a1 + b1 + 7) >> 4;
+        block[8 + i] = (a1 - b1 + 7) >> 4;
+        block[4 + i] = ((c1 * 2217 + d1 * 5352 + 12000) >> 16) + if d1 != 0 { 1 } else { 0 };
+        block[12 + i] = (d1 * 2217 - c1 * 5352 + 51000) >> 16;
+    }
+}
+
+/// Section 14.3, the inverse of the Walsh-Hadamard transform of the luma DC coefficients
+fn forward_wht(block: &mut [i32; 16]) {
+    let mut temp = [0i32; 16];
+    for i in 0usize..4 {
+        let row = &block[i * 4..i * 4 + 4];
+        let a1 = (row[0] + row[2]) * 4;
+        let d1 = (row[1] + row[3]) * 4;
+        let c1 = (row[1] - row[3]) * 4;
+        let b1 = (row[0] - row[2]) * 4;
+
+        temp[i * 4] = a1 + d1 + if a1 != 0 { 1 } else { 0 };
+        temp[i * 4 + 1] = b1 + c1;
+        temp[i * 4 + 2] = b1 - c1;
+        temp[i * 4 + 3] = a1 - d1;
+    }
+
+    for i in 0usize..4 {
+        let a1 = temp[i] + temp[8 + i];
+        let d1 = temp[4 + i] + temp[12 + i];
+        let c1 = temp[4 + i] - temp[12 + i];
+        let b1 = temp[i] - temp[8 + i];
+
+        let values = [a1 + d1, b1 + c1, b1 - c1, a1 - d1];
+        for (j, &value) in values.iter().enumerate() {
+            let value = if value < 0 { value + 1 } else { value };
+            block[4 * j + i] = (value + 3) >> 3;
+        }
+    }
+}
+
+/// The largest coefficient magnitude the DCT tokens can express
+const MAX_LEVEL: i32 = 67 + 2047;
+
+fn quantize(block: &[i32; 16], dcq: i16, acq: i16) -> [i32; 16] {
+    let mut levels = [0i32; 16];
+    for (i, (level, &coefficient)) in levels.iter_mut().zip(block.iter()).enumerate() {
+        let q = if i == 0 { dcq } else { acq } as i32;
+        let magnitude = ::std::cmp::min((coefficient.abs() + q / 2) / q, MAX_LEVEL);
+        *level = if coefficient < 0 { -magnitude } else { magnitude };
+    }
+    levels
+}
+
+fn dequantize(levels: &mut [i32; 16], dcq: i16, acq: i16) {
+    for (i, level) in levels.iter_mut().enumerate() {
+        *level *= if i == 0 { dcq } else { acq } as i32;
+    }
+}
+
+/// Section 13, writes the tokens of the quantized coefficients of a block from index
+/// ```first``` on, returning whether any of them is non-zero
+fn write_coefficients(w: &mut BoolWriter,
+                      probs: &[[[Prob; NUM_DCT_TOKENS - 1]; 3]; 8],
+                      levels: &[i32; 16],
+                      first: usize,
+                      complexity: usize) -> bool {
+
+    let last = match (first..16usize).rev().find(|&i| levels[ZIGZAG[i] as usize] != 0) {
+        Some(last) => last,
+        None => {
+            w.write_with_tree(&DCT_TOKEN_TREE, &probs[COEFF_BANDS[first] as usize][complexity], DCT_EOB, 0);
+            return false
+        }
+    };
+
+    let mut complexity = complexity;
+    let mut skip = false;
+
+    for i in first..last + 1 {
+        let table = &probs[COEFF_BANDS[i] as usize][complexity];
+        let value = levels[ZIGZAG[i] as usize];
+        let abs_value = value.abs();
+
+        let token = match abs_value {
+            0 ... 4 => abs_value as i8,
+            5 ... 6 => DCT_CAT1,
+            7 ... 10 => DCT_CAT2,
+            11 ... 18 => DCT_CAT3,
+            19 ... 34 => DCT_CAT4,
+            35 ... 66 => DCT_CAT5,
+            _ => DCT_CAT6,
+        };
+        w.write_with_tree(&DCT_TOKEN_TREE, table, token, if skip { 2 } else { 0 });
+
+        if token >= DCT_CAT1 {
+            let t = PROB_DCT_CAT[(token - DCT_CAT1) as usize];
+            let extra = abs_value - DCT_CAT_BASE[(token - DCT_CAT1) as usize] as i32;
+            let bits = t.iter().take_while(|&&p| p > 0).count();
+
+            for j in 0..bits {
+                w.write_bool(t[j], (extra >> (bits - 1 - j)) & 1 == 1);
+            }
+        }
+
+        if abs_value != 0 {
+            w.write_bool(128, value < 0);
+        }
+
+        skip = abs_value == 0;
+        complexity = if abs_value == 0 { 0 }
+                     else if abs_value == 1 { 1 }
+                     else { 2 };
+    }
+
+    if last < 15 {
+        w.write_with_tree(&DCT_TOKEN_TREE, &probs[COEFF_BANDS[last + 1] as usize][complexity], DCT_EOB, 0);
+    }
+
+    true
+}
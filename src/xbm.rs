@@ -0,0 +1,233 @@
+//! Decoding of XBM Images
+//!
+//! X BitMaps are C source files that define the dimensions of a bitmap and an array with its
+//! bits. Rows are padded to whole array elements, with the leftmost pixel in the lowest bit.
+//! Set bits are decoded as black, and cleared bits as white.
+//!
+//! Both the X11 format with ```char``` elements and the X10 format with ```short``` elements
+//! are supported.
+//!
+//! # Related Links
+//! * <https://www.x.org/releases/X11R7.7/doc/libX11/libX11/libX11.html#Manipulating_Bitmaps> - Manipulating Bitmaps
+//!
+
+use std::io::Read;
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+/// The largest width and height that is accepted
+const MAX_DIMENSION: u32 = 0x8000;
+
+/// The representation of an XBM decoder
+///
+/// The whole file is read when the decoder is created, but its bits are only parsed when the
+/// rows are read.
+pub struct XBMDecoder {
+    width: u32,
+    height: u32,
+    hotspot: Option<(u32, u32)>,
+    /// The contents of the array of bits
+    bits: String,
+    /// The bits per element of the array
+    element_bits: u32,
+    /// The pixels, one byte each
+    pixels: Option<Vec<u8>>,
+    /// The next row to be returned
+    row: u32,
+}
+
+/// Parses a decimal, or a hexadecimal number with a ```0x``` prefix
+fn parse_number(s: &str) -> ImageResult<u32> {
+    let result = if s.starts_with("0x") || s.starts_with("0X") {
+        u32::from_str_radix(&s[2..], 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|_| ImageError::malformed(ImageFormat::XBM, format!("Invalid XBM number {:?}", s)))
+}
+
+impl XBMDecoder {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new<R: Read>(mut r: R) -> ImageResult<XBMDecoder> {
+        let mut text = String::new();
+        try!(r.read_to_string(&mut text));
+
+        let (mut width, mut height, mut x_hot, mut y_hot) = (None, None, None, None);
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("#define") {
+                continue
+            }
+            let (name, value) = match (words.next(), words.next()) {
+                (Some(name), Some(value)) => (name, try!(parse_number(value))),
+                _ => continue,
+            };
+            // The names are prefixed by the name of the bitmap
+            if name.ends_with("width") {
+                width = Some(value);
+            } else if name.ends_with("height") {
+                height = Some(value);
+            } else if name.ends_with("x_hot") {
+                x_hot = Some(value);
+            } else if name.ends_with("y_hot") {
+                y_hot = Some(value);
+            }
+        }
+        let (width, height) = match (width, height) {
+            (Some(width), Some(height)) => (width, height),
+            _ => return Err(ImageError::malformed(ImageFormat::XBM,
+                "XBM image without dimensions".to_string())),
+        };
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(ImageError::malformed(ImageFormat::XBM, "Invalid image dimensions".to_string()))
+        }
+
+        let start = try!(text.find('{').ok_or_else(|| ImageError::malformed(ImageFormat::XBM,
+            "XBM image without bits".to_string())));
+        let end = try!(text[start..].find('}').ok_or_else(|| ImageError::malformed(ImageFormat::XBM,
+            "Unterminated XBM bits".to_string())));
+        let element_bits = if text[..start].contains("short") { 16 } else { 8 };
+
+        Ok(XBMDecoder {
+            width: width,
+            height: height,
+            hotspot: match (x_hot, y_hot) {
+                (Some(x), Some(y)) => Some((x, y)),
+                _ => None,
+            },
+            bits: text[start + 1..start + end].to_string(),
+            element_bits: element_bits,
+            pixels: None,
+            row: 0,
+        })
+    }
+
+    /// Parses the bits of the image into pixels
+    fn decode(&mut self) -> ImageResult<&[u8]> {
+        if self.pixels.is_none() {
+            let (width, height, element_bits) = (self.width, self.height, self.element_bits);
+            let mut elements = Vec::new();
+            for element in self.bits.split(',').map(str::trim) {
+                // A trailing comma is allowed
+                if !element.is_empty() {
+                    elements.push(try!(parse_number(element)));
+                }
+            }
+
+            let row_elements = (width + element_bits - 1) / element_bits;
+            if (elements.len() as u64) < row_elements as u64 * height as u64 {
+                return Err(ImageError::truncated(ImageFormat::XBM))
+            }
+            let mut pixels = Vec::with_capacity(width as usize * height as usize);
+            for row in elements.chunks(row_elements as usize).take(height as usize) {
+                pixels.extend((0..width).map(|x| {
+                    let bit = (row[(x / element_bits) as usize] >> (x % element_bits)) & 1;
+                    if bit == 1 { 0 } else { 255 }
+                }));
+            }
+            self.pixels = Some(pixels);
+        }
+        Ok(self.pixels.as_ref().unwrap())
+    }
+
+    /// Returns the position of the hot spot of a cursor, if the image defines one
+    pub fn hotspot(&self) -> Option<(u32, u32)> {
+        self.hotspot
+    }
+}
+
+impl ImageDecoder for XBMDecoder {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        Ok(ColorType::Gray(8))
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize)
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = self.width as usize;
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let start = self.row as usize * len;
+        buf[..len].copy_from_slice(&try!(self.decode())[start..start + len]);
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let start = self.row as usize * self.width as usize;
+        let pixels = try!(self.decode())[start..].to_vec();
+        self.row = self.height;
+        Ok(DecodingResult::U8(pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::XBMDecoder;
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder, ImageError, ParameterErrorKind};
+
+    #[test]
+    fn x11() {
+        let text = b"#define cross_width 10\n#define cross_height 2\n\
+                     #define cross_x_hot 4\n#define cross_y_hot 1\n\
+                     static unsigned char cross_bits[] = {\n   0x01, 0x02, 0xff, 0x03, };\n";
+        let mut decoder = XBMDecoder::new(&text[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (10, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::Gray(8));
+        assert_eq!(decoder.hotspot(), Some((4, 1)));
+        let mut row = [0; 10];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, [0, 255, 255, 255, 255, 255, 255, 255, 255, 0]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![0; 10]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+        match decoder.read_scanline(&mut row) {
+            Err(ImageError::Parameter(ParameterErrorKind::NoMoreData)) => (),
+            _ => panic!("Expected the end of the image"),
+        }
+    }
+
+    #[test]
+    fn x10() {
+        let text = b"#define old_width 17\n#define old_height 1\n\
+                     static short old_bits[] = { 0x8001, 0x0001 };";
+        let mut decoder = XBMDecoder::new(&text[..]).unwrap();
+        assert_eq!(decoder.hotspot(), None);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => {
+                assert_eq!(image.len(), 17);
+                assert_eq!((image[0], image[1], image[15], image[16]), (0, 255, 0, 0));
+            }
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        // Missing height
+        assert!(XBMDecoder::new(&b"#define a_width 1\nstatic char a_bits[] = { 0 };"[..]).is_err());
+        // Not enough bits, which are only parsed when the image is read
+        let text = b"#define a_width 9\n#define a_height 1\nstatic char a_bits[] = { 0x00 };";
+        assert_eq!(XBMDecoder::new(&text[..]).unwrap().dimensions().unwrap(), (9, 1));
+        assert!(XBMDecoder::new(&text[..]).unwrap().read_image().is_err());
+        // Invalid number
+        let text = b"#define a_width 1\n#define a_height 1\nstatic char a_bits[] = { 0xg };";
+        assert!(XBMDecoder::new(&text[..]).unwrap().read_image().is_err());
+        // Missing bits
+        assert!(XBMDecoder::new(&b"#define a_width 1\n#define a_height 1\n"[..]).is_err());
+    }
+}
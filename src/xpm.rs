@@ -0,0 +1,419 @@
+//! Decoding of XPM Images
+//!
+//! X PixMaps are C source files with an array of strings. The first string holds the
+//! dimensions, the number of colors and the characters per pixel, followed by a string for
+//! each color and one for each row of pixels.
+//!
+//! Colors are given as hexadecimal RGB values, as ```None``` for transparent pixels, or by name.
+//! Only the common X11 color names and the numbered grays are known.
+//!
+//! # Related Links
+//! * <https://www.xfree86.org/current/xpm.pdf> - The XPM manual
+//!
+
+use std::collections::HashMap;
+use std::io::Read;
+
+use color::ColorType;
+use image::{DecodingResult, ImageDecoder, ImageError, ImageFormat, ImageResult, ParameterErrorKind};
+
+/// The largest width and height that is accepted
+const MAX_DIMENSION: u32 = 0x8000;
+
+/// The X11 colors that are known by name, without spaces and in lower case
+const COLOR_NAMES: [(&'static str, [u8; 3]); 60] = [
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("red", [255, 0, 0]),
+    ("green", [0, 255, 0]),
+    ("blue", [0, 0, 255]),
+    ("yellow", [255, 255, 0]),
+    ("cyan", [0, 255, 255]),
+    ("magenta", [255, 0, 255]),
+    ("gray", [190, 190, 190]),
+    ("grey", [190, 190, 190]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgrey", [169, 169, 169]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgrey", [211, 211, 211]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("gainsboro", [220, 220, 220]),
+    ("whitesmoke", [245, 245, 245]),
+    ("snow", [255, 250, 250]),
+    ("ivory", [255, 255, 240]),
+    ("beige", [245, 245, 220]),
+    ("wheat", [245, 222, 179]),
+    ("tan", [210, 180, 140]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("orange", [255, 165, 0]),
+    ("darkorange", [255, 140, 0]),
+    ("gold", [255, 215, 0]),
+    ("brown", [165, 42, 42]),
+    ("chocolate", [210, 105, 30]),
+    ("sienna", [160, 82, 45]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("salmon", [250, 128, 114]),
+    ("coral", [255, 127, 80]),
+    ("tomato", [255, 99, 71]),
+    ("firebrick", [178, 34, 34]),
+    ("indianred", [205, 92, 92]),
+    ("darkred", [139, 0, 0]),
+    ("maroon", [176, 48, 96]),
+    ("purple", [160, 32, 240]),
+    ("violet", [238, 130, 238]),
+    ("orchid", [218, 112, 214]),
+    ("plum", [221, 160, 221]),
+    ("navy", [0, 0, 128]),
+    ("navyblue", [0, 0, 128]),
+    ("darkblue", [0, 0, 139]),
+    ("midnightblue", [25, 25, 112]),
+    ("royalblue", [65, 105, 225]),
+    ("steelblue", [70, 130, 180]),
+    ("skyblue", [135, 206, 235]),
+    ("lightblue", [173, 216, 230]),
+    ("turquoise", [64, 224, 208]),
+    ("darkgreen", [0, 100, 0]),
+    ("forestgreen", [34, 139, 34]),
+    ("seagreen", [46, 139, 87]),
+];
+
+/// The representation of an XPM decoder
+///
+/// The strings of the file and its colors are parsed when the decoder is created, the pixels
+/// when the rows are read.
+pub struct XPMDecoder {
+    width: u32,
+    height: u32,
+    hotspot: Option<(u32, u32)>,
+    /// The characters per pixel
+    chars: usize,
+    /// The colors by their characters, with `None` for transparent pixels
+    palette: HashMap<String, Option<[u8; 3]>>,
+    /// Whether a color is transparent
+    has_alpha: bool,
+    /// The strings of the rows of pixels
+    rows: Vec<String>,
+    /// The pixels, as rgb or rgba
+    pixels: Option<Vec<u8>>,
+    /// The next row to be returned
+    row: u32,
+}
+
+/// Returns the contents of the string literals in ```text```, outside of comments
+fn string_literals(text: &str) -> ImageResult<Vec<String>> {
+    let mut strings = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut last = ' ';
+                loop {
+                    match chars.next() {
+                        Some('/') if last == '*' => break,
+                        Some(c) => last = c,
+                        None => return Err(ImageError::malformed(ImageFormat::XPM,
+                            "Unterminated XPM comment".to_string())),
+                    }
+                }
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => string.push(c),
+                            None => return Err(ImageError::malformed(ImageFormat::XPM,
+                                "Unterminated XPM string".to_string())),
+                        },
+                        Some(c) => string.push(c),
+                        None => return Err(ImageError::malformed(ImageFormat::XPM,
+                            "Unterminated XPM string".to_string())),
+                    }
+                }
+                strings.push(string);
+            }
+            _ => (),
+        }
+    }
+    Ok(strings)
+}
+
+/// Parses a color, returning ```None``` for a transparent one
+fn parse_color(value: &str) -> ImageResult<Option<[u8; 3]>> {
+    let invalid = || ImageError::malformed(ImageFormat::XPM, format!("Invalid XPM color {:?}", value));
+    if value.starts_with('#') {
+        // One to four hexadecimal digits for each channel
+        let digits = &value[1..];
+        if digits.is_empty() || digits.len() % 3 != 0 || digits.len() > 12 {
+            return Err(invalid())
+        }
+        let n = digits.len() / 3;
+        let max = (1u32 << (4 * n)) - 1;
+        let mut rgb = [0; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            let v = try!(u32::from_str_radix(&digits[i * n..(i + 1) * n], 16).map_err(|_| invalid()));
+            *channel = ((v * 255 + max / 2) / max) as u8;
+        }
+        return Ok(Some(rgb))
+    }
+
+    let name: String = value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    if name == "none" {
+        return Ok(None)
+    }
+    if let Some(&(_, rgb)) = COLOR_NAMES.iter().find(|&&(n, _)| n == name) {
+        return Ok(Some(rgb))
+    }
+    // The grays from gray0 to gray100
+    if name.starts_with("gray") || name.starts_with("grey") {
+        if let Ok(percent) = name[4..].parse::<u32>() {
+            if percent <= 100 {
+                let v = ((percent * 255 + 50) / 100) as u8;
+                return Ok(Some([v, v, v]))
+            }
+        }
+    }
+    Err(ImageError::unsupported(ImageFormat::XPM, format!("Unknown XPM color {:?}", value)))
+}
+
+/// Parses the keys and values that follow the characters of a color, returning the color
+/// for color displays, or the one for the deepest other display
+fn parse_color_definition(definition: &str) -> ImageResult<Option<[u8; 3]>> {
+    let mut values: Vec<(&str, String)> = Vec::new();
+    for word in definition.split_whitespace() {
+        match word {
+            "c" | "g" | "g4" | "m" | "s" => values.push((word, String::new())),
+            // Values may consist of several words
+            _ => match values.last_mut() {
+                Some(&mut (_, ref mut value)) => {
+                    if !value.is_empty() {
+                        value.push(' ');
+                    }
+                    value.push_str(word);
+                }
+                None => return Err(ImageError::malformed(ImageFormat::XPM,
+                    format!("Invalid XPM color {:?}", definition))),
+            },
+        }
+    }
+    for key in &["c", "g", "g4", "m"] {
+        if let Some(&(_, ref value)) = values.iter().find(|&&(k, _)| k == *key) {
+            return parse_color(value)
+        }
+    }
+    Err(ImageError::malformed(ImageFormat::XPM, format!("XPM color without value {:?}", definition)))
+}
+
+impl XPMDecoder {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new<R: Read>(mut r: R) -> ImageResult<XPMDecoder> {
+        let mut text = String::new();
+        try!(r.read_to_string(&mut text));
+        let strings = try!(string_literals(&text));
+        let values = match strings.first() {
+            Some(values) => values,
+            None => return Err(ImageError::malformed(ImageFormat::XPM,
+                "XPM image without values".to_string())),
+        };
+
+        let mut numbers = Vec::new();
+        for word in values.split_whitespace().take(6) {
+            // Extensions follow the numbers
+            match word.parse::<u32>() {
+                Ok(n) => numbers.push(n),
+                Err(_) => break,
+            }
+        }
+        if numbers.len() < 4 {
+            return Err(ImageError::malformed(ImageFormat::XPM, format!("Invalid XPM values {:?}", values)))
+        }
+        let (width, height, colors, chars) = (numbers[0], numbers[1], numbers[2] as usize, numbers[3] as usize);
+        if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(ImageError::malformed(ImageFormat::XPM, "Invalid image dimensions".to_string()))
+        }
+        if chars == 0 || chars > 8 {
+            return Err(ImageError::malformed(ImageFormat::XPM,
+                format!("Invalid XPM characters per pixel {}", chars)))
+        }
+        if strings.len() < 1 + colors + height as usize {
+            return Err(ImageError::truncated(ImageFormat::XPM))
+        }
+
+        let mut palette = HashMap::new();
+        for definition in &strings[1..1 + colors] {
+            let chars_len = definition.char_indices().nth(chars).map(|(i, _)| i);
+            let split = try!(chars_len.ok_or_else(|| {
+                ImageError::malformed(ImageFormat::XPM, format!("Invalid XPM color {:?}", definition))
+            }));
+            let color = try!(parse_color_definition(&definition[split..]));
+            palette.insert(definition[..split].to_string(), color);
+        }
+        let has_alpha = palette.values().any(Option::is_none);
+
+        Ok(XPMDecoder {
+            width: width,
+            height: height,
+            hotspot: if numbers.len() >= 6 { Some((numbers[4], numbers[5])) } else { None },
+            chars: chars,
+            palette: palette,
+            has_alpha: has_alpha,
+            rows: strings[1 + colors..1 + colors + height as usize].to_vec(),
+            pixels: None,
+            row: 0,
+        })
+    }
+
+    /// Looks up the colors of the pixels
+    fn decode(&mut self) -> ImageResult<&[u8]> {
+        if self.pixels.is_none() {
+            let (width, height, chars, has_alpha) = (self.width, self.height, self.chars, self.has_alpha);
+            let palette = &self.palette;
+            let channels = if has_alpha { 4 } else { 3 };
+            let mut pixels = Vec::with_capacity(width as usize * height as usize * channels);
+            for row in &self.rows {
+                let row: Vec<char> = row.chars().collect();
+                if row.len() < width as usize * chars {
+                    return Err(ImageError::truncated(ImageFormat::XPM))
+                }
+                for key in row.chunks(chars).take(width as usize) {
+                    let key: String = key.iter().cloned().collect();
+                    let color = try!(palette.get(&key).ok_or_else(|| {
+                        ImageError::malformed(ImageFormat::XPM, format!("Undefined XPM color {:?}", key))
+                    }));
+                    match *color {
+                        Some(rgb) => {
+                            pixels.extend_from_slice(&rgb);
+                            if has_alpha {
+                                pixels.push(255);
+                            }
+                        }
+                        None => pixels.extend_from_slice(&[0, 0, 0, 0]),
+                    }
+                }
+            }
+            self.pixels = Some(pixels);
+        }
+        Ok(self.pixels.as_ref().unwrap())
+    }
+
+    /// Returns the position of the hot spot of a cursor, if the image defines one
+    pub fn hotspot(&self) -> Option<(u32, u32)> {
+        self.hotspot
+    }
+}
+
+impl ImageDecoder for XPMDecoder {
+    fn dimensions(&mut self) -> ImageResult<(u32, u32)> {
+        Ok((self.width, self.height))
+    }
+
+    /// Returns RGBA(8) if a color is transparent, or RGB(8) otherwise
+    fn colortype(&mut self) -> ImageResult<ColorType> {
+        Ok(if self.has_alpha { ColorType::RGBA(8) } else { ColorType::RGB(8) })
+    }
+
+    fn row_len(&mut self) -> ImageResult<usize> {
+        Ok(self.width as usize * if self.has_alpha { 4 } else { 3 })
+    }
+
+    fn read_scanline(&mut self, buf: &mut [u8]) -> ImageResult<u32> {
+        if self.row == self.height {
+            return Err(ImageError::Parameter(ParameterErrorKind::NoMoreData))
+        }
+        let len = try!(self.row_len());
+        if buf.len() < len {
+            return Err(ImageError::Parameter(ParameterErrorKind::BufferTooShort))
+        }
+
+        let start = self.row as usize * len;
+        buf[..len].copy_from_slice(&try!(self.decode())[start..start + len]);
+        self.row += 1;
+        Ok(self.row - 1)
+    }
+
+    fn read_image(&mut self) -> ImageResult<DecodingResult> {
+        let start = self.row as usize * try!(self.row_len());
+        let pixels = try!(self.decode())[start..].to_vec();
+        self.row = self.height;
+        Ok(DecodingResult::U8(pixels))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{XPMDecoder, parse_color};
+    use color::ColorType;
+    use image::{DecodingResult, ImageDecoder};
+
+    #[test]
+    fn decode() {
+        let text = br##"/* XPM */
+static char *icon[] = {
+/* columns rows colors chars-per-pixel hotspot */
+"3 2 3 2 1 0",
+"  c None",
+".. c #ff0000 s red",
+"#x m black c light blue",
+/* pixels */
+"  ..#x",
+"#x#x  "
+};"##;
+        let mut decoder = XPMDecoder::new(&text[..]).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (3, 2));
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGBA(8));
+        assert_eq!(decoder.hotspot(), Some((1, 0)));
+        let mut row = [0; 12];
+        assert_eq!(decoder.read_scanline(&mut row).unwrap(), 0);
+        assert_eq!(row, [0, 0, 0, 0, 255, 0, 0, 255, 173, 216, 230, 255]);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![173, 216, 230, 255, 173, 216, 230, 255, 0, 0, 0, 0]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn opaque() {
+        let text = b"/* XPM */ static char *a[] = { \"2 1 2 1\", \"a c gray50\", \"b g #fff\", \"ba\" };";
+        let mut decoder = XPMDecoder::new(&text[..]).unwrap();
+        assert_eq!(decoder.colortype().unwrap(), ColorType::RGB(8));
+        assert_eq!(decoder.hotspot(), None);
+        match decoder.read_image().unwrap() {
+            DecodingResult::U8(image) => assert_eq!(image, vec![255, 255, 255, 128, 128, 128]),
+            _ => panic!("Image did not decode as 8-bit"),
+        }
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#123").unwrap(), Some([0x11, 0x22, 0x33]));
+        assert_eq!(parse_color("#ffff80800000").unwrap(), Some([255, 128, 0]));
+        assert_eq!(parse_color("Dark Slate Grey").unwrap(), Some([47, 79, 79]));
+        assert_eq!(parse_color("none").unwrap(), None);
+        assert!(parse_color("#12").is_err());
+        assert!(parse_color("grey101").is_err());
+        assert!(parse_color("chartreuse").is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        // Missing rows
+        let text = b"/* XPM */ static char *a[] = { \"1 2 1 1\", \"a c red\", \"a\" };";
+        assert!(XPMDecoder::new(&text[..]).is_err());
+        // Undefined pixel, which is only looked up when the image is read
+        let text = b"/* XPM */ static char *a[] = { \"1 1 1 1\", \"a c red\", \"b\" };";
+        assert_eq!(XPMDecoder::new(&text[..]).unwrap().colortype().unwrap(), ColorType::RGB(8));
+        assert!(XPMDecoder::new(&text[..]).unwrap().read_image().is_err());
+        // Unterminated string
+        let text = b"/* XPM */ static char *a[] = { \"1 1 1 1";
+        assert!(XPMDecoder::new(&text[..]).is_err());
+    }
+}
@@ -41,7 +41,7 @@ fn render_images() {
 			// Do not fail on unsupported error
 			// This might happen because the testsuite contains unsupported images
 			// or because a specific decoder included via a feature.
-			Err(image::ImageError::UnsupportedError(_)) => return,
+			Err(image::ImageError::Unsupported { .. }) => return,
 			Err(err) => panic!(format!("decoding of {:?} failed with: {}", path, err))
 		};
 		let mut crc = Crc32::new();
@@ -74,7 +74,7 @@ fn check_references() {
             // Do not fail on unsupported error
             // This might happen because the testsuite contains unsupported images
             // or because a specific decoder included via a feature.
-            Err(image::ImageError::UnsupportedError(_)) => return,
+            Err(image::ImageError::Unsupported { .. }) => return,
             Err(err) => panic!(format!("{}", err))
         };
 
@@ -103,7 +103,7 @@ fn check_references() {
             // Do not fail on unsupported error
             // This might happen because the testsuite contains unsupported images
             // or because a specific decoder included via a feature.
-            Err(image::ImageError::UnsupportedError(_)) => return,
+            Err(image::ImageError::Unsupported { .. }) => return,
             Err(err) => panic!(format!("decoding of {:?} failed with: {}", path, err))
         };
         let mut test_crc = Crc32::new();